@@ -0,0 +1,95 @@
+//! Parse times of a vocab-heavy header under the string options of `ParseOptions`
+//!
+//! `cargo bench --bench strings` prints the median of a few runs for each, and the bytes of
+//! text the string arrays keep.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use gguf::writer::write_header;
+use gguf::{
+    AssumeUtf8, GGUFFile, GGUFHeader, GGUFMetadata, GGUFMetadataArrayValue, GGUFMetadataValue,
+    GGUfMetadataValueType, ParseOptions,
+};
+
+/// a header with a vocabulary and merges the size of Llama 3's
+fn header() -> Vec<u8> {
+    let tokens: Vec<String> = (0..128_256)
+        .map(|i| format!("Ġtok{}", i % 50_000))
+        .collect();
+    let merges: Vec<String> = (0..280_000).map(|i| format!("Ġt ok{}", i % 1000)).collect();
+    let strings = |values: Vec<String>| {
+        GGUFMetadataValue::Array(GGUFMetadataArrayValue::packed(
+            GGUfMetadataValueType::String,
+            values.into(),
+        ))
+    };
+    let file = GGUFFile {
+        header: GGUFHeader {
+            version: 3,
+            tensor_count: 0,
+            metadata: vec![
+                GGUFMetadata::new("tokenizer.ggml.tokens", strings(tokens)),
+                GGUFMetadata::new("tokenizer.ggml.merges", strings(merges)),
+            ],
+        },
+        tensors: Vec::new(),
+    };
+    let mut buf = Vec::new();
+    write_header(&mut buf, &file).expect("writing to memory does not fail");
+    buf
+}
+
+fn median(mut run: impl FnMut()) -> Duration {
+    run();
+    let mut times: Vec<Duration> = (0..15)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[times.len() / 2]
+}
+
+fn main() {
+    let buf = header();
+    // SAFETY: the header was written from Rust strings just above
+    let unchecked = unsafe { AssumeUtf8::new() };
+    let cases = [
+        ("validated", ParseOptions::strict()),
+        (
+            "interned",
+            ParseOptions {
+                intern_strings: true,
+                ..ParseOptions::strict()
+            },
+        ),
+        (
+            "lazy",
+            ParseOptions {
+                lazy_arrays: Some(1024),
+                ..ParseOptions::strict()
+            },
+        ),
+        (
+            "unchecked",
+            ParseOptions {
+                unchecked_utf8: Some(unchecked),
+                ..ParseOptions::strict()
+            },
+        ),
+    ];
+    println!("header of {} bytes", buf.len());
+    for (name, options) in cases {
+        let time = median(|| {
+            black_box(GGUFFile::read_with(black_box(&buf), &options).unwrap());
+        });
+        let file = GGUFFile::read_with(&buf, &options).unwrap().unwrap();
+        let text: usize = (file.header.metadata.iter())
+            .filter_map(|m| m.value.as_array()?.value.as_strings())
+            .map(|strings| strings.text_len())
+            .sum();
+        println!("{name:>10}  {time:>12.3?}  {text} bytes of text");
+    }
+}
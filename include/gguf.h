@@ -0,0 +1,253 @@
+#ifndef GGUF_H
+#define GGUF_H
+
+/* The C API of the gguf crate, built with
+ * `cargo rustc --lib --release --features capi --crate-type cdylib,staticlib` into
+ * libgguf.so, libgguf.dylib or gguf.dll and libgguf.a. Regenerate with
+ * `cbindgen --config cbindgen.toml --output include/gguf.h`. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The most dimensions a tensor has in ggml
+ */
+#define GGUF_MAX_DIMS 4
+
+/**
+ * An opened file: the parsed header with C copies of its strings
+ */
+typedef struct GgufFile GgufFile;
+
+/**
+ * A validation report with C copies of the strings of its findings
+ */
+typedef struct GgufReport GgufReport;
+
+/**
+ * A metadata value; which fields are set depends on `value_type`
+ */
+typedef struct GgufValue {
+  /**
+   * The GGUF value type, 0 for uint8 to 12 for float64.
+   */
+  uint32_t value_type;
+  /**
+   * The type of the items of an array.
+   */
+  uint32_t item_type;
+  /**
+   * The value of signed integer types.
+   */
+  int64_t int_value;
+  /**
+   * The value of unsigned integer types, and 0 or 1 for booleans.
+   */
+  uint64_t uint_value;
+  /**
+   * The value of float types.
+   */
+  double float_value;
+  /**
+   * The value of strings, NUL-terminated and cut at any NUL within it.
+   */
+  const char *string_value;
+  /**
+   * The length of a string in bytes or the number of items of an array.
+   */
+  uint64_t len;
+} GgufValue;
+
+/**
+ * A tensor info
+ */
+typedef struct GgufTensorInfo {
+  /**
+   * The name, NUL-terminated.
+   */
+  const char *name;
+  /**
+   * The number of dimensions used in `dims`.
+   */
+  uint32_t n_dims;
+  /**
+   * The dimensions, innermost first; unused ones are 1.
+   */
+  uint64_t dims[GGUF_MAX_DIMS];
+  /**
+   * The ggml type of the data.
+   */
+  uint32_t tensor_type;
+  /**
+   * The offset of the data from the start of the tensor data.
+   */
+  uint64_t offset;
+  /**
+   * The size of the data in bytes, 0 if the shape does not fit the type's blocks.
+   */
+  uint64_t size;
+} GgufTensorInfo;
+
+/**
+ * A finding of a validation report
+ */
+typedef struct GgufFinding {
+  /**
+   * 0 for info, 1 for warning and 2 for error.
+   */
+  uint32_t severity;
+  /**
+   * The stable identifier of the check, e.g. `duplicate-key`, NUL-terminated.
+   */
+  const char *code;
+  /**
+   * A description of the problem, NUL-terminated.
+   */
+  const char *message;
+  /**
+   * The byte offset in the file the finding refers to, or -1.
+   */
+  int64_t offset;
+  /**
+   * The metadata key the finding is about, NUL-terminated, or `NULL`.
+   */
+  const char *key;
+  /**
+   * The tensor the finding is about, NUL-terminated, or `NULL`.
+   */
+  const char *tensor;
+} GgufFinding;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * The message of the last failure on this thread, or `NULL`; valid until the next call into
+ * the API on this thread
+ */
+const char *gguf_last_error(void);
+
+/**
+ * Open a file and parse its header and tensor infos, returning `NULL` on failure
+ */
+GgufFile *gguf_open(const char *path);
+
+/**
+ * Look up a metadata key, filling `out` and returning whether the key is there
+ */
+bool gguf_get_metadata(const GgufFile *file, const char *key, GgufValue *out);
+
+/**
+ * Fill `out` with item `index` of the array at `key`, returning whether there is one
+ *
+ * The `string_value` of string items points at `len` bytes of UTF-8 that are not
+ * NUL-terminated, since arrays such as the vocabulary hold too many strings to copy.
+ */
+bool gguf_get_array_item(const GgufFile *file, const char *key, uint64_t index, GgufValue *out);
+
+/**
+ * The number of metadata entries, 0 for `NULL`
+ */
+uint64_t gguf_metadata_count(const GgufFile *file);
+
+/**
+ * The key of metadata entry `index`, NUL-terminated, or `NULL` if there is none
+ */
+const char *gguf_metadata_key(const GgufFile *file, uint64_t index);
+
+/**
+ * The offset in the file of metadata entry `index`, where its key starts, or 0 if there is
+ * none
+ *
+ * After an edit this is the offset in a file `gguf_write` writes from the handle.
+ */
+uint64_t gguf_metadata_offset(const GgufFile *file, uint64_t index);
+
+/**
+ * The offset in the file where the tensor data starts, 0 for `NULL`
+ */
+uint64_t gguf_data_offset(const GgufFile *file);
+
+/**
+ * The number of tensors, 0 for `NULL`
+ */
+uint64_t gguf_tensor_count(const GgufFile *file);
+
+/**
+ * Fill `out` with the tensor info at `index`, returning whether there is one
+ */
+bool gguf_tensor_info(const GgufFile *file, uint64_t index, GgufTensorInfo *out);
+
+/**
+ * Set the metadata key `key` to a number, boolean or string, replacing any value it has and
+ * otherwise adding it last, and return whether it was set
+ *
+ * The fields of `value` are read as `gguf_get_metadata` fills them, strings as the `len`
+ * bytes of UTF-8 at `string_value`. The strings the handle handed out before are invalidated.
+ */
+bool gguf_set_metadata(GgufFile *file, const char *key, const GgufValue *value);
+
+/**
+ * Set the metadata key `key` to an array of the `count` values at `items`, all of
+ * `item_type`, and return whether it was set; see `gguf_set_metadata`
+ */
+bool gguf_set_array(GgufFile *file,
+                    const char *key,
+                    uint32_t item_type,
+                    const GgufValue *items,
+                    uint64_t count);
+
+/**
+ * Remove the metadata key `key`, returning whether it was there
+ *
+ * The strings the handle handed out before are invalidated.
+ */
+bool gguf_remove_metadata(GgufFile *file, const char *key);
+
+/**
+ * Write the metadata and tensor infos of a handle to `path` with the tensor data of the file
+ * it was opened from, returning whether the file was written
+ *
+ * `path` may be the file opened, which is replaced once the new one is complete; the handle
+ * goes on describing the file as it was opened.
+ */
+bool gguf_write(const GgufFile *file, const char *path);
+
+/**
+ * Free a handle from `gguf_open`, invalidating the strings it handed out
+ */
+void gguf_free(GgufFile *file);
+
+/**
+ * Check a file against the spec, returning its report or `NULL` if it cannot be read
+ */
+GgufReport *gguf_validate(const char *path);
+
+/**
+ * Whether a report has no error-level finding, false for `NULL`
+ */
+bool gguf_report_valid(const GgufReport *report);
+
+/**
+ * The number of findings of a report, 0 for `NULL`
+ */
+uint64_t gguf_report_count(const GgufReport *report);
+
+/**
+ * Fill `out` with finding `index` of a report, returning whether there is one
+ */
+bool gguf_report_finding(const GgufReport *report, uint64_t index, GgufFinding *out);
+
+/**
+ * Free a report from `gguf_validate`, invalidating the strings it handed out
+ */
+void gguf_report_free(GgufReport *report);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* GGUF_H */
@@ -0,0 +1,21 @@
+// Kotlin bindings over the C API; Android apps depend on "net.java.dev.jna:jna:5.14.0@aar"
+// instead and ship libgguf.so, built with `cargo rustc --lib --release --features capi
+// --crate-type cdylib --target aarch64-linux-android`, in src/main/jniLibs/arm64-v8a.
+plugins {
+    kotlin("jvm") version "1.9.24"
+}
+
+repositories {
+    mavenCentral()
+}
+
+dependencies {
+    implementation("net.java.dev.jna:jna:5.14.0")
+    testImplementation(kotlin("test"))
+}
+
+// the tests load the library of `cargo rustc --lib --features capi --crate-type cdylib`
+tasks.test {
+    useJUnitPlatform()
+    systemProperty("jna.library.path", file("../target/debug").path)
+}
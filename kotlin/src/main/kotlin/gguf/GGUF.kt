@@ -0,0 +1,201 @@
+// Read and validate GGUF files from Kotlin through the C API of the gguf crate, so apps can
+// check a downloaded model and show its metadata before loading it. The library is loaded with
+// JNA as libgguf.so, which Android apps ship in jniLibs for each ABI.
+package gguf
+
+import com.sun.jna.Library
+import com.sun.jna.Native
+import com.sun.jna.Pointer
+import com.sun.jna.Structure
+
+@Suppress("FunctionName")
+internal interface CApi : Library {
+    fun gguf_last_error(): String?
+    fun gguf_open(path: String): Pointer?
+    // C bools are a byte; JNA maps Boolean to an int
+    fun gguf_get_metadata(file: Pointer, key: String, out: GgufValue): Byte
+    fun gguf_get_array_item(file: Pointer, key: String, index: Long, out: GgufValue): Byte
+    fun gguf_metadata_count(file: Pointer): Long
+    fun gguf_metadata_key(file: Pointer, index: Long): String?
+    fun gguf_data_offset(file: Pointer): Long
+    fun gguf_tensor_count(file: Pointer): Long
+    fun gguf_tensor_info(file: Pointer, index: Long, out: GgufTensorInfo): Byte
+    fun gguf_free(file: Pointer)
+    fun gguf_validate(path: String): Pointer?
+    fun gguf_report_valid(report: Pointer): Byte
+    fun gguf_report_count(report: Pointer): Long
+    fun gguf_report_finding(report: Pointer, index: Long, out: GgufFinding): Byte
+    fun gguf_report_free(report: Pointer)
+
+    companion object {
+        val lib: CApi = Native.load("gguf", CApi::class.java)
+    }
+}
+
+@Suppress("PropertyName")
+@Structure.FieldOrder("value_type", "item_type", "int_value", "uint_value", "float_value", "string_value", "len")
+internal class GgufValue : Structure() {
+    @JvmField var value_type: Int = 0
+    @JvmField var item_type: Int = 0
+    @JvmField var int_value: Long = 0
+    @JvmField var uint_value: Long = 0
+    @JvmField var float_value: Double = 0.0
+    @JvmField var string_value: Pointer? = null
+    @JvmField var len: Long = 0
+}
+
+@Suppress("PropertyName")
+@Structure.FieldOrder("name", "n_dims", "dims", "tensor_type", "offset", "size")
+internal class GgufTensorInfo : Structure() {
+    @JvmField var name: Pointer? = null
+    @JvmField var n_dims: Int = 0
+    @JvmField var dims: LongArray = LongArray(4)
+    @JvmField var tensor_type: Int = 0
+    @JvmField var offset: Long = 0
+    @JvmField var size: Long = 0
+}
+
+@Suppress("PropertyName")
+@Structure.FieldOrder("severity", "code", "message", "offset", "key", "tensor")
+internal class GgufFinding : Structure() {
+    @JvmField var severity: Int = 0
+    @JvmField var code: Pointer? = null
+    @JvmField var message: Pointer? = null
+    @JvmField var offset: Long = 0
+    @JvmField var key: Pointer? = null
+    @JvmField var tensor: Pointer? = null
+}
+
+/** A failure reported by the library */
+class GGUFException(message: String) : Exception(message) {
+    internal companion object {
+        fun last() = GGUFException(CApi.lib.gguf_last_error() ?: "unknown error")
+    }
+}
+
+private fun Byte.isTrue() = this != 0.toByte()
+
+private fun Pointer?.string() = this?.getString(0, "UTF-8")
+
+/** A metadata value; arrays are read item by item with [GGUFFile.item] */
+sealed class MetadataValue {
+    /** An unsigned integer of any width */
+    data class UIntValue(val value: ULong) : MetadataValue()
+    data class IntValue(val value: Long) : MetadataValue()
+    data class FloatValue(val value: Double) : MetadataValue()
+    data class BoolValue(val value: Boolean) : MetadataValue()
+    data class StringValue(val value: String) : MetadataValue()
+    /** An array of [count] items of GGUF value type [itemType] */
+    data class ArrayValue(val itemType: Int, val count: Long) : MetadataValue()
+
+    internal companion object {
+        fun of(value: GgufValue, nulTerminated: Boolean = true): MetadataValue = when (value.value_type) {
+            0, 2, 4, 10 -> UIntValue(value.uint_value.toULong())
+            1, 3, 5, 11 -> IntValue(value.int_value)
+            6, 12 -> FloatValue(value.float_value)
+            7 -> BoolValue(value.uint_value != 0L)
+            8 -> StringValue(
+                if (nulTerminated) value.string_value.string() ?: ""
+                else value.string_value?.getByteArray(0, value.len.toInt())?.decodeToString() ?: ""
+            )
+            else -> ArrayValue(value.item_type, value.len)
+        }
+    }
+}
+
+/** A tensor info; [dimensions] are innermost first, as ggml orders them */
+data class TensorInfo(
+    val name: String,
+    val dimensions: List<Long>,
+    /** The ggml type, 0 for F32 to 39 for MXFP4 */
+    val type: Int,
+    /** The offset of the data from [GGUFFile.dataOffset] */
+    val offset: Long,
+    val size: Long,
+)
+
+/** The header and tensor infos of an opened file, to [close] when done */
+class GGUFFile(path: String) : AutoCloseable {
+    private var handle: Pointer? = CApi.lib.gguf_open(path) ?: throw GGUFException.last()
+
+    private val open: Pointer
+        get() = handle ?: throw IllegalStateException("the file is closed")
+
+    /** The metadata keys, in file order */
+    val keys: List<String>
+        get() = (0 until CApi.lib.gguf_metadata_count(open)).mapNotNull { CApi.lib.gguf_metadata_key(open, it) }
+
+    /** The value at [key], if there is one */
+    operator fun get(key: String): MetadataValue? {
+        val value = GgufValue()
+        return if (CApi.lib.gguf_get_metadata(open, key, value).isTrue()) MetadataValue.of(value) else null
+    }
+
+    /** Item [index] of the array at [key], if there is one */
+    fun item(key: String, index: Long): MetadataValue? {
+        val value = GgufValue()
+        if (!CApi.lib.gguf_get_array_item(open, key, index, value).isTrue()) return null
+        return MetadataValue.of(value, nulTerminated = false)
+    }
+
+    /** The offset in the file where the tensor data starts */
+    val dataOffset: Long
+        get() = CApi.lib.gguf_data_offset(open)
+
+    val tensors: List<TensorInfo>
+        get() = (0 until CApi.lib.gguf_tensor_count(open)).map {
+            val info = GgufTensorInfo()
+            if (!CApi.lib.gguf_tensor_info(open, it, info).isTrue()) throw GGUFException.last()
+            TensorInfo(
+                name = info.name.string() ?: "",
+                dimensions = info.dims.take(info.n_dims),
+                type = info.tensor_type,
+                offset = info.offset,
+                size = info.size,
+            )
+        }
+
+    override fun close() {
+        handle?.let { CApi.lib.gguf_free(it) }
+        handle = null
+    }
+}
+
+enum class Severity { INFO, WARNING, ERROR }
+
+/** A problem found in a file */
+data class Finding(
+    val severity: Severity,
+    /** The stable identifier of the check, e.g. `duplicate-key` */
+    val code: String,
+    val message: String,
+    val offset: Long?,
+    val key: String?,
+    val tensor: String?,
+)
+
+/** The findings of checking a file against the spec */
+data class ValidationReport(val isValid: Boolean, val findings: List<Finding>) {
+    companion object {
+        fun of(path: String): ValidationReport {
+            val report = CApi.lib.gguf_validate(path) ?: throw GGUFException.last()
+            try {
+                val findings = (0 until CApi.lib.gguf_report_count(report)).mapNotNull {
+                    val finding = GgufFinding()
+                    if (!CApi.lib.gguf_report_finding(report, it, finding).isTrue()) return@mapNotNull null
+                    Finding(
+                        severity = Severity.entries.getOrElse(finding.severity) { Severity.ERROR },
+                        code = finding.code.string() ?: "",
+                        message = finding.message.string() ?: "",
+                        offset = finding.offset.takeIf { it >= 0 },
+                        key = finding.key.string(),
+                        tensor = finding.tensor.string(),
+                    )
+                }
+                return ValidationReport(CApi.lib.gguf_report_valid(report).isTrue(), findings)
+            } finally {
+                CApi.lib.gguf_report_free(report)
+            }
+        }
+    }
+}
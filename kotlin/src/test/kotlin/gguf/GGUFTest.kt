@@ -0,0 +1,77 @@
+// Smoke test against a library built with `cargo rustc --lib --features capi --crate-type cdylib`,
+// found through the jna.library.path build.gradle.kts sets
+package gguf
+
+import java.io.ByteArrayOutputStream
+import java.io.File
+import java.nio.ByteBuffer
+import java.nio.ByteOrder
+import kotlin.test.Test
+import kotlin.test.assertEquals
+import kotlin.test.assertFailsWith
+import kotlin.test.assertFalse
+import kotlin.test.assertTrue
+
+class GGUFTest {
+    private class Writer {
+        val out = ByteArrayOutputStream()
+
+        private fun put(size: Int, fill: ByteBuffer.() -> Unit) {
+            out.write(ByteBuffer.allocate(size).order(ByteOrder.LITTLE_ENDIAN).apply(fill).array())
+        }
+
+        fun u32(vararg values: Int) = values.forEach { put(4) { putInt(it) } }
+        fun u64(vararg values: Long) = values.forEach { put(8) { putLong(it) } }
+        fun f32(vararg values: Float) = values.forEach { put(4) { putFloat(it) } }
+
+        fun string(s: String) {
+            val bytes = s.toByteArray()
+            u64(bytes.size.toLong())
+            out.write(bytes)
+        }
+    }
+
+    /** A string, a number and an array of strings, and one F32 tensor of 2x3 */
+    private fun tinyFile(): File {
+        val w = Writer()
+        w.out.write("GGUF".toByteArray())
+        w.u32(3)
+        w.u64(1, 3)
+        w.string("general.architecture"); w.u32(8); w.string("llama")
+        w.string("llama.context_length"); w.u32(4, 4096)
+        w.string("tokenizer.ggml.tokens"); w.u32(9, 8); w.u64(3)
+        listOf("<s>", "</s>", "héllo").forEach(w::string)
+        w.string("output.weight"); w.u32(2); w.u64(3, 2); w.u32(0); w.u64(0)
+        w.out.write(ByteArray(-w.out.size() and 31))
+        w.f32(0f, 1f, 2f, 3f, 4f, 5f)
+        return File.createTempFile("tiny", ".gguf").apply {
+            deleteOnExit()
+            writeBytes(w.out.toByteArray())
+        }
+    }
+
+    @Test
+    fun readsMetadataAndTensors() {
+        val path = tinyFile().path
+        GGUFFile(path).use { file ->
+            assertEquals(listOf("general.architecture", "llama.context_length", "tokenizer.ggml.tokens"), file.keys)
+            assertEquals(MetadataValue.StringValue("llama"), file["general.architecture"])
+            assertEquals(MetadataValue.UIntValue(4096u), file["llama.context_length"])
+            assertEquals(MetadataValue.ArrayValue(itemType = 8, count = 3), file["tokenizer.ggml.tokens"])
+            assertEquals(MetadataValue.StringValue("héllo"), file.item("tokenizer.ggml.tokens", 2))
+            assertEquals(null, file.item("tokenizer.ggml.tokens", 3))
+            val tensor = file.tensors.single()
+            assertEquals(TensorInfo("output.weight", listOf(3L, 2L), type = 0, offset = 0, size = 24), tensor)
+            assertEquals(File(path).length() - 24, file.dataOffset)
+        }
+    }
+
+    @Test
+    fun validates() {
+        val report = ValidationReport.of(tinyFile().path)
+        assertFalse(report.isValid)
+        assertTrue(report.findings.any { it.code == "missing-key" && it.key == "llama.block_count" })
+        assertTrue(report.findings.any { it.code == "vocab-size" && it.tensor == "output.weight" })
+        assertFailsWith<GGUFException> { GGUFFile("missing.gguf") }
+    }
+}
@@ -0,0 +1,67 @@
+// Smoke test of the addon built with the `napi` feature:
+//
+//     cargo rustc --lib --features napi --crate-type cdylib && cp target/debug/libgguf.so gguf.node
+//     GGUF_NODE=./gguf.node node --test node/
+const assert = require("node:assert");
+const fs = require("node:fs");
+const os = require("node:os");
+const path = require("node:path");
+const test = require("node:test");
+
+const gguf = require(path.resolve(process.env.GGUF_NODE || "gguf.node"));
+
+function string(s) {
+  const data = Buffer.from(s);
+  const len = Buffer.alloc(8);
+  len.writeBigUInt64LE(BigInt(data.length));
+  return Buffer.concat([len, data]);
+}
+
+function u32(...values) {
+  const b = Buffer.alloc(4 * values.length);
+  values.forEach((v, i) => b.writeUInt32LE(v, 4 * i));
+  return b;
+}
+
+function u64(...values) {
+  const b = Buffer.alloc(8 * values.length);
+  values.forEach((v, i) => b.writeBigUInt64LE(BigInt(v), 8 * i));
+  return b;
+}
+
+// a string, a number and an array of strings, and one F32 tensor of 2x3
+function file() {
+  const header = Buffer.concat([
+    Buffer.from("GGUF"), u32(3), u64(1, 3),
+    string("general.architecture"), u32(8), string("llama"),
+    string("llama.context_length"), u32(4, 4096),
+    string("tokenizer.ggml.tokens"), u32(9, 8), u64(3), string("<s>"), string("</s>"), string("héllo"),
+    string("output.weight"), u32(2), u64(3, 2), u32(0), u64(0),
+  ]);
+  const data = Buffer.alloc(24);
+  [0, 1, 2, 3, 4, 5].forEach((v, i) => data.writeFloatLE(v, 4 * i));
+  return Buffer.concat([header, Buffer.alloc(-header.length & 31), data]);
+}
+
+test("open, inspect and validate", () => {
+  const bytes = file();
+  const p = path.join(fs.mkdtempSync(path.join(os.tmpdir(), "gguf-")), "tiny.gguf");
+  fs.writeFileSync(p, bytes);
+
+  const { header, tensors, data_offset } = gguf.open(p);
+  assert.strictEqual(header.version, 3);
+  const value = (key) => header.metadata.find((m) => m.key === key).value;
+  assert.strictEqual(value("general.architecture"), "llama");
+  assert.strictEqual(value("llama.context_length"), 4096);
+  assert.deepStrictEqual(value("tokenizer.ggml.tokens").value, ["<s>", "</s>", "héllo"]);
+  assert.strictEqual(tensors.length, 1);
+  assert.strictEqual(tensors[0].name, "output.weight");
+  assert.strictEqual(data_offset, bytes.length - 24);
+
+  assert.strictEqual(gguf.inspect(bytes.subarray(0, 16)), null);
+  assert.deepStrictEqual(gguf.inspect(new Uint8Array(bytes)).header, header);
+  assert.strictEqual(typeof gguf.validate(p).valid, "boolean");
+
+  assert.throws(() => gguf.open(p + ".missing"), Error);
+  fs.rmSync(path.dirname(p), { recursive: true });
+});
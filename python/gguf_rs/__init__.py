@@ -0,0 +1,526 @@
+"""Read and edit GGUF files from Python through the C API of the gguf crate.
+
+`GGUFReader` follows the reader of the official `gguf` package closely enough to stand in for
+it when reading metadata and tensor infos: `fields`, `get_field`, the `name`, `offset`,
+`parts`, `data`, `types` and `contents()` of a field, `tensors` and `get_tensor` behave the
+same, while the header is parsed in Rust rather than in Python, which matters for headers of
+hundreds of thousands of tokens. The parts of a field are read from the file only when asked
+for. Tensor data and parts are memory-mapped, as NumPy arrays when NumPy is installed.
+
+`GGUFEditor` changes the metadata of a file with the `add_*` methods of the official
+`GGUFWriter` and `remove_key`, then writes it with the tensor data of the original.
+
+The library is built with
+`cargo rustc --lib --release --features capi --crate-type cdylib`; it is looked up in
+`GGUF_RS_LIB`, next to this package, then on the system library path.
+"""
+
+import ctypes
+import ctypes.util
+import mmap
+import os
+import struct
+import sys
+from enum import IntEnum
+
+__all__ = [
+    "GGUFEditor",
+    "GGUFReader",
+    "GGUFValueType",
+    "GGMLQuantizationType",
+    "ReaderField",
+    "ReaderTensor",
+]
+
+
+class GGUFValueType(IntEnum):
+    UINT8 = 0
+    INT8 = 1
+    UINT16 = 2
+    INT16 = 3
+    UINT32 = 4
+    INT32 = 5
+    FLOAT32 = 6
+    BOOL = 7
+    STRING = 8
+    ARRAY = 9
+    UINT64 = 10
+    INT64 = 11
+    FLOAT64 = 12
+
+    @staticmethod
+    def get_type(val):
+        """The type `GGUFWriter` stores a Python value as"""
+        if isinstance(val, (str, bytes, bytearray)):
+            return GGUFValueType.STRING
+        if isinstance(val, list):
+            return GGUFValueType.ARRAY
+        if isinstance(val, float):
+            return GGUFValueType.FLOAT32
+        if isinstance(val, bool):
+            return GGUFValueType.BOOL
+        if isinstance(val, int):
+            return GGUFValueType.INT32
+        raise ValueError(f"cannot store a {type(val).__name__} in GGUF")
+
+
+class GGMLQuantizationType(IntEnum):
+    F32 = 0
+    F16 = 1
+    Q4_0 = 2
+    Q4_1 = 3
+    Q5_0 = 6
+    Q5_1 = 7
+    Q8_0 = 8
+    Q8_1 = 9
+    Q2_K = 10
+    Q3_K = 11
+    Q4_K = 12
+    Q5_K = 13
+    Q6_K = 14
+    Q8_K = 15
+    IQ2_XXS = 16
+    IQ2_XS = 17
+    IQ3_XXS = 18
+    IQ1_S = 19
+    IQ4_NL = 20
+    IQ3_S = 21
+    IQ2_S = 22
+    IQ4_XS = 23
+    I8 = 24
+    I16 = 25
+    I32 = 26
+    I64 = 27
+    F64 = 28
+    IQ1_M = 29
+    BF16 = 30
+    TQ1_0 = 34
+    TQ2_0 = 35
+    MXFP4 = 39
+
+
+GGUF_MAX_DIMS = 4
+
+_SIGNED = (GGUFValueType.INT8, GGUFValueType.INT16, GGUFValueType.INT32, GGUFValueType.INT64)
+
+# the struct formats of the scalar types
+_FORMATS = {
+    GGUFValueType.UINT8: "B",
+    GGUFValueType.INT8: "b",
+    GGUFValueType.UINT16: "H",
+    GGUFValueType.INT16: "h",
+    GGUFValueType.UINT32: "I",
+    GGUFValueType.INT32: "i",
+    GGUFValueType.FLOAT32: "f",
+    GGUFValueType.BOOL: "?",
+    GGUFValueType.UINT64: "Q",
+    GGUFValueType.INT64: "q",
+    GGUFValueType.FLOAT64: "d",
+}
+
+
+class _Value(ctypes.Structure):
+    _fields_ = [
+        ("value_type", ctypes.c_uint32),
+        ("item_type", ctypes.c_uint32),
+        ("int_value", ctypes.c_int64),
+        ("uint_value", ctypes.c_uint64),
+        ("float_value", ctypes.c_double),
+        ("string_value", ctypes.c_void_p),
+        ("len", ctypes.c_uint64),
+    ]
+
+
+class _TensorInfo(ctypes.Structure):
+    _fields_ = [
+        ("name", ctypes.c_char_p),
+        ("n_dims", ctypes.c_uint32),
+        ("dims", ctypes.c_uint64 * GGUF_MAX_DIMS),
+        ("tensor_type", ctypes.c_uint32),
+        ("offset", ctypes.c_uint64),
+        ("size", ctypes.c_uint64),
+    ]
+
+
+def _load():
+    names = {"darwin": ["libgguf.dylib"], "win32": ["gguf.dll"]}.get(sys.platform, ["libgguf.so"])
+    candidates = [os.environ.get("GGUF_RS_LIB")]
+    candidates += [os.path.join(os.path.dirname(__file__), name) for name in names]
+    candidates.append(ctypes.util.find_library("gguf"))
+    for path in filter(None, candidates):
+        if os.path.exists(path) or not os.path.dirname(path):
+            lib = ctypes.CDLL(path)
+            break
+    else:
+        raise ImportError(
+            "the gguf library was not found, build it with "
+            "`cargo rustc --lib --features capi --crate-type cdylib`"
+        )
+    handle, value = ctypes.c_void_p, ctypes.POINTER(_Value)
+    signatures = {
+        "gguf_last_error": ([], ctypes.c_char_p),
+        "gguf_open": ([ctypes.c_char_p], handle),
+        "gguf_get_metadata": ([handle, ctypes.c_char_p, value], ctypes.c_bool),
+        "gguf_get_array_item": ([handle, ctypes.c_char_p, ctypes.c_uint64, value], ctypes.c_bool),
+        "gguf_metadata_count": ([handle], ctypes.c_uint64),
+        "gguf_metadata_key": ([handle, ctypes.c_uint64], ctypes.c_char_p),
+        "gguf_metadata_offset": ([handle, ctypes.c_uint64], ctypes.c_uint64),
+        "gguf_data_offset": ([handle], ctypes.c_uint64),
+        "gguf_tensor_count": ([handle], ctypes.c_uint64),
+        "gguf_tensor_info": ([handle, ctypes.c_uint64, ctypes.POINTER(_TensorInfo)], ctypes.c_bool),
+        "gguf_set_metadata": ([handle, ctypes.c_char_p, value], ctypes.c_bool),
+        "gguf_set_array": (
+            [handle, ctypes.c_char_p, ctypes.c_uint32, value, ctypes.c_uint64],
+            ctypes.c_bool,
+        ),
+        "gguf_remove_metadata": ([handle, ctypes.c_char_p], ctypes.c_bool),
+        "gguf_write": ([handle, ctypes.c_char_p], ctypes.c_bool),
+        "gguf_free": ([handle], None),
+    }
+    for name, (argtypes, restype) in signatures.items():
+        function = getattr(lib, name)
+        function.argtypes, function.restype = argtypes, restype
+    return lib
+
+
+_lib = _load()
+
+
+def _scalar(value):
+    kind = GGUFValueType(value.value_type)
+    if kind == GGUFValueType.STRING:
+        return ctypes.string_at(value.string_value, value.len).decode("utf-8", "replace")
+    if kind == GGUFValueType.BOOL:
+        return bool(value.uint_value)
+    if kind in (GGUFValueType.FLOAT32, GGUFValueType.FLOAT64):
+        return value.float_value
+    if kind in _SIGNED:
+        return value.int_value
+    return value.uint_value
+
+
+def _part(view, offset, fmt, count=1):
+    """`count` values of struct format `fmt` at `offset` of `view`"""
+    part = view[offset : offset + struct.calcsize(fmt) * count]
+    try:
+        import numpy as np
+    except ImportError:
+        return part.cast(fmt)
+    return np.frombuffer(part, dtype=np.dtype("<" + fmt))
+
+
+def _value_parts(view, offset, value_type):
+    """The size of the value of `value_type` at `offset`, its parts, the indices of the parts
+    holding the value and its types, split as gguf-py splits them"""
+    types = [value_type]
+    if value_type == GGUFValueType.STRING:
+        length = _part(view, offset, "Q")
+        text = _part(view, offset + 8, "B", int(length[0]))
+        return 8 + int(length[0]), [length, text], [1], types
+    if value_type != GGUFValueType.ARRAY:
+        fmt = _FORMATS[value_type]
+        return struct.calcsize(fmt), [_part(view, offset, fmt)], [0], types
+    item_type, count = _part(view, offset, "I"), _part(view, offset + 4, "Q")
+    parts, data, end = [item_type, count], [], offset + 12
+    for i in range(int(count[0])):
+        size, item_parts, indices, item_types = _value_parts(
+            view, end, GGUFValueType(int(item_type[0]))
+        )
+        if i == 0:
+            types += item_types
+        data += [len(parts) + j for j in indices]
+        parts += item_parts
+        end += size
+    return end - offset, parts, data, types
+
+
+class ReaderField:
+    """A metadata entry, or a count of the header under a `GGUF.` name, as gguf-py has them
+
+    `types` is `[ARRAY, item type]` for arrays with items. `parts` splits the bytes of the
+    entry into its key length, key, type and the pieces of its value, and `data` holds the
+    indices of the parts with the value.
+    """
+
+    def __init__(self, reader, offset, name, types, value=None, length=0):
+        self._reader = reader
+        self.offset = offset
+        self.name = name
+        self.types = types
+        self._value = value
+        self._len = length
+        self._layout = None
+
+    def _parts(self):
+        if self._layout is None:
+            view = memoryview(self._reader._map)
+            # the counts of the header, before the first entry
+            if self.offset < 24:
+                fmt = _FORMATS[self.types[0]]
+                self._layout = [_part(view, self.offset, fmt)], [0]
+            else:
+                length = _part(view, self.offset, "Q")
+                end = self.offset + 8 + int(length[0])
+                parts = [length, _part(view, self.offset + 8, "B", int(length[0]))]
+                parts.append(_part(view, end, "I"))
+                _, value_parts, data, _ = _value_parts(view, end + 4, self.types[0])
+                self._layout = parts + value_parts, [len(parts) + i for i in data]
+        return self._layout
+
+    @property
+    def parts(self):
+        return self._parts()[0]
+
+    @property
+    def data(self):
+        return self._parts()[1]
+
+    def contents(self, index_or_slice=slice(None)):
+        """The value, or for arrays the items at `index_or_slice`, all of them by default"""
+        if self.types[0] != GGUFValueType.ARRAY:
+            return self._value
+        key = self.name.encode()
+        item = _Value()
+
+        def get(i):
+            if not _lib.gguf_get_array_item(self._reader._handle, key, i, ctypes.byref(item)):
+                raise IndexError(_lib.gguf_last_error().decode())
+            if item.value_type == GGUFValueType.ARRAY:
+                raise ValueError(f"{self.name} holds nested arrays, which the C API does not read")
+            return _scalar(item)
+
+        if isinstance(index_or_slice, int):
+            return get(range(self._len)[index_or_slice])
+        return [get(i) for i in range(self._len)[index_or_slice]]
+
+    def __len__(self):
+        return self._len if self.types[0] == GGUFValueType.ARRAY else 1
+
+    def __repr__(self):
+        return f"ReaderField({self.name!r}, types={[t.name for t in self.types]})"
+
+
+def _field(reader, index, key, value):
+    name = key.decode("utf-8", "replace")
+    offset = _lib.gguf_metadata_offset(reader._handle, index)
+    types = [GGUFValueType(value.value_type)]
+    if types[0] != GGUFValueType.ARRAY:
+        return ReaderField(reader, offset, name, types, _scalar(value))
+    # gguf-py takes the item type from the first item
+    if value.len:
+        types.append(GGUFValueType(value.item_type))
+    return ReaderField(reader, offset, name, types, length=value.len)
+
+
+class ReaderTensor:
+    """A tensor info; `shape` is innermost dimension first, as ggml orders it"""
+
+    def __init__(self, reader, info):
+        self._reader = reader
+        self.name = info.name.decode("utf-8", "replace")
+        self.tensor_type = GGMLQuantizationType(info.tensor_type)
+        self.shape = [info.dims[i] for i in range(info.n_dims)]
+        self.n_elements = 1
+        for dim in self.shape:
+            self.n_elements *= dim
+        self.n_bytes = info.size
+        self.data_offset = reader.data_offset + info.offset
+
+    @property
+    def data(self):
+        """The data, a NumPy array of the rows when NumPy is installed, else a memoryview"""
+        view = memoryview(self._reader._map)[self.data_offset : self.data_offset + self.n_bytes]
+        try:
+            import numpy as np
+        except ImportError:
+            return view
+        dtypes = {
+            GGMLQuantizationType.F32: np.float32,
+            GGMLQuantizationType.F16: np.float16,
+            GGMLQuantizationType.I8: np.int8,
+            GGMLQuantizationType.I16: np.int16,
+            GGMLQuantizationType.I32: np.int32,
+            GGMLQuantizationType.I64: np.int64,
+            GGMLQuantizationType.F64: np.float64,
+        }
+        dtype = dtypes.get(self.tensor_type)
+        if dtype is None:
+            rows = self.n_elements // self.shape[0] if self.shape else 1
+            return np.frombuffer(view, dtype=np.uint8).reshape(rows, -1)
+        return np.frombuffer(view, dtype=dtype).reshape(list(reversed(self.shape)))
+
+    def __repr__(self):
+        return f"ReaderTensor({self.name!r}, {self.tensor_type.name}, shape={self.shape})"
+
+
+class GGUFReader:
+    """The header, metadata and tensor infos of a GGUF file"""
+
+    def __init__(self, path, mode="r"):
+        if mode != "r":
+            raise ValueError("the reader is read-only, edit files with GGUFEditor")
+        self._handle = _lib.gguf_open(os.fsencode(path))
+        if not self._handle:
+            raise ValueError(_lib.gguf_last_error().decode())
+        self.data_offset = _lib.gguf_data_offset(self._handle)
+        with open(path, "rb") as f:
+            self._map = mmap.mmap(f.fileno(), 0, access=mmap.ACCESS_READ)
+
+        counts = zip(
+            (4, 8, 16),
+            ("GGUF.version", "GGUF.tensor_count", "GGUF.kv_count"),
+            (GGUFValueType.UINT32, GGUFValueType.UINT64, GGUFValueType.UINT64),
+            struct.unpack_from("<IQQ", self._map, 4),
+        )
+        self.fields = {
+            name: ReaderField(self, offset, name, [value_type], count)
+            for offset, name, value_type, count in counts
+        }
+        value = _Value()
+        for i in range(_lib.gguf_metadata_count(self._handle)):
+            key = _lib.gguf_metadata_key(self._handle, i)
+            _lib.gguf_get_metadata(self._handle, key, ctypes.byref(value))
+            field = _field(self, i, key, value)
+            self.fields[field.name] = field
+        alignment = self.fields.get("general.alignment")
+        self.alignment = alignment.contents() if alignment else 32
+
+        self.tensors = []
+        info = _TensorInfo()
+        for i in range(_lib.gguf_tensor_count(self._handle)):
+            if not _lib.gguf_tensor_info(self._handle, i, ctypes.byref(info)):
+                raise ValueError(_lib.gguf_last_error().decode())
+            self.tensors.append(ReaderTensor(self, info))
+
+    def get_field(self, key):
+        return self.fields.get(key)
+
+    def get_tensor(self, idx):
+        return self.tensors[idx]
+
+    def __del__(self):
+        if getattr(self, "_handle", None):
+            _lib.gguf_free(self._handle)
+            self._handle = None
+
+
+def _c_value(value_type, val, keep):
+    """`val` as a `_Value` of `value_type`, the bytes of a string added to `keep`"""
+    value = _Value(value_type=value_type)
+    if value_type == GGUFValueType.STRING:
+        data = val.encode() if isinstance(val, str) else bytes(val)
+        buffer = ctypes.create_string_buffer(data, len(data))
+        keep.append(buffer)
+        value.string_value, value.len = ctypes.cast(buffer, ctypes.c_void_p), len(data)
+    elif value_type in (GGUFValueType.FLOAT32, GGUFValueType.FLOAT64):
+        value.float_value = float(val)
+    elif value_type in _SIGNED:
+        value.int_value = int(val)
+    elif value_type == GGUFValueType.ARRAY:
+        raise ValueError("arrays are added with add_array")
+    else:
+        if int(val) < 0:
+            raise ValueError(f"{val} does not fit a {GGUFValueType(value_type).name}")
+        value.uint_value = int(val)
+    return value
+
+
+class GGUFEditor:
+    """The metadata of a GGUF file, to change and write out with the tensor data of the file
+
+    The `add_*` methods are those of gguf-py's `GGUFWriter`, setting a key whether or not the
+    file has it; `remove_key` removes one. Nothing changes on disk until `write`, which may
+    replace the file edited.
+    """
+
+    def __init__(self, path):
+        self.path = path
+        self._handle = _lib.gguf_open(os.fsencode(path))
+        if not self._handle:
+            raise ValueError(_lib.gguf_last_error().decode())
+
+    def _check(self, ok):
+        if not ok:
+            raise ValueError(_lib.gguf_last_error().decode())
+
+    def add_key_value(self, key, val, vtype, sub_type=None):
+        """Set `key` to `val` stored as `vtype`, the items of arrays as `sub_type`"""
+        vtype, keep = GGUFValueType(vtype), []
+        if vtype != GGUFValueType.ARRAY:
+            value = _c_value(vtype, val, keep)
+            self._check(_lib.gguf_set_metadata(self._handle, key.encode(), ctypes.byref(value)))
+            return
+        val = list(val)
+        if sub_type is None:
+            sub_type = GGUFValueType.get_type(val[0])
+        if sub_type == GGUFValueType.ARRAY:
+            raise ValueError("the C API does not write nested arrays")
+        items = (_Value * len(val))(*(_c_value(sub_type, v, keep) for v in val))
+        self._check(_lib.gguf_set_array(self._handle, key.encode(), sub_type, items, len(val)))
+
+    def add_uint8(self, key, val):
+        self.add_key_value(key, val, GGUFValueType.UINT8)
+
+    def add_int8(self, key, val):
+        self.add_key_value(key, val, GGUFValueType.INT8)
+
+    def add_uint16(self, key, val):
+        self.add_key_value(key, val, GGUFValueType.UINT16)
+
+    def add_int16(self, key, val):
+        self.add_key_value(key, val, GGUFValueType.INT16)
+
+    def add_uint32(self, key, val):
+        self.add_key_value(key, val, GGUFValueType.UINT32)
+
+    def add_int32(self, key, val):
+        self.add_key_value(key, val, GGUFValueType.INT32)
+
+    def add_float32(self, key, val):
+        self.add_key_value(key, val, GGUFValueType.FLOAT32)
+
+    def add_uint64(self, key, val):
+        self.add_key_value(key, val, GGUFValueType.UINT64)
+
+    def add_int64(self, key, val):
+        self.add_key_value(key, val, GGUFValueType.INT64)
+
+    def add_float64(self, key, val):
+        self.add_key_value(key, val, GGUFValueType.FLOAT64)
+
+    def add_bool(self, key, val):
+        self.add_key_value(key, val, GGUFValueType.BOOL)
+
+    def add_string(self, key, val):
+        if val:
+            self.add_key_value(key, val, GGUFValueType.STRING)
+
+    def add_array(self, key, val):
+        if not isinstance(val, (list, tuple)):
+            raise ValueError("the value is not a list")
+        if val:
+            self.add_key_value(key, val, GGUFValueType.ARRAY)
+
+    def remove_key(self, key):
+        """Remove `key`, raising `KeyError` if the file has none"""
+        if not _lib.gguf_remove_metadata(self._handle, key.encode()):
+            raise KeyError(key)
+
+    def write(self, path=None):
+        """Write the edited file to `path`, by default over the file edited"""
+        target = self.path if path is None else path
+        self._check(_lib.gguf_write(self._handle, os.fsencode(target)))
+
+    def close(self):
+        if getattr(self, "_handle", None):
+            _lib.gguf_free(self._handle)
+            self._handle = None
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc):
+        self.close()
+
+    def __del__(self):
+        self.close()
@@ -0,0 +1,142 @@
+"""Smoke test of gguf_rs against a library built with the `capi` feature
+
+    cargo rustc --lib --features capi --crate-type cdylib
+    GGUF_RS_LIB=target/debug/libgguf.so PYTHONPATH=python python3 -m unittest discover python/tests
+"""
+
+import os
+import struct
+import tempfile
+import unittest
+
+from gguf_rs import GGMLQuantizationType, GGUFEditor, GGUFReader, GGUFValueType
+
+
+def string(s):
+    data = s.encode()
+    return struct.pack("<Q", len(data)) + data
+
+
+def write_gguf(path):
+    """A file with a string, a number and an array of strings, and one F32 tensor of 2x3"""
+    kv = string("general.architecture") + struct.pack("<I", 8) + string("llama")
+    kv += string("llama.context_length") + struct.pack("<II", 4, 4096)
+    kv += string("tokenizer.ggml.tokens") + struct.pack("<IIQ", 9, 8, 3)
+    kv += string("<s>") + string("</s>") + string("héllo")
+    tensor = string("output.weight") + struct.pack("<IQQIQ", 2, 3, 2, 0, 0)
+    header = b"GGUF" + struct.pack("<IQQ", 3, 1, 3) + kv + tensor
+    header += bytes(-len(header) % 32)
+    with open(path, "wb") as f:
+        f.write(header + struct.pack("<6f", 0, 1, 2, 3, 4, 5))
+
+
+class ReaderTest(unittest.TestCase):
+    def setUp(self):
+        fd, self.path = tempfile.mkstemp(suffix=".gguf")
+        os.close(fd)
+        write_gguf(self.path)
+
+    def tearDown(self):
+        os.remove(self.path)
+
+    def test_reads_metadata_and_tensors(self):
+        reader = GGUFReader(self.path)
+        self.assertEqual(reader.fields["general.architecture"].contents(), "llama")
+        self.assertEqual(reader.fields["llama.context_length"].contents(), 4096)
+        tokens = reader.fields["tokenizer.ggml.tokens"]
+        self.assertEqual(tokens.types, [GGUFValueType.ARRAY, GGUFValueType.STRING])
+        self.assertEqual(tokens.contents(), ["<s>", "</s>", "héllo"])
+        self.assertEqual(tokens.contents(-1), "héllo")
+        self.assertEqual(len(tokens), 3)
+
+        (tensor,) = reader.tensors
+        self.assertEqual(tensor.name, "output.weight")
+        self.assertEqual(tensor.tensor_type, GGMLQuantizationType.F32)
+        self.assertEqual(tensor.shape, [3, 2])
+        self.assertEqual(tensor.n_elements, 6)
+        data = tensor.data
+        self.assertEqual(bytes(data), struct.pack("<6f", 0, 1, 2, 3, 4, 5))
+
+    def test_fields_split_as_gguf_py_does(self):
+        # the offsets, parts, data and types gguf-py's GGUFReader gives for this file
+        reader = GGUFReader(self.path)
+        self.assertEqual(
+            list(reader.fields),
+            [
+                "GGUF.version",
+                "GGUF.tensor_count",
+                "GGUF.kv_count",
+                "general.architecture",
+                "llama.context_length",
+                "tokenizer.ggml.tokens",
+            ],
+        )
+        version = reader.fields["GGUF.version"]
+        self.assertEqual((version.offset, version.data), (4, [0]))
+        self.assertEqual(version.types, [GGUFValueType.UINT32])
+        self.assertEqual([list(p) for p in version.parts], [[3]])
+        self.assertEqual(reader.fields["GGUF.kv_count"].contents(), 3)
+
+        def parts(field):
+            return [list(p) for p in field.parts]
+
+        arch = reader.get_field("general.architecture")
+        self.assertEqual((arch.name, arch.offset, arch.data), ("general.architecture", 24, [4]))
+        self.assertEqual(arch.types, [GGUFValueType.STRING])
+        self.assertEqual(
+            parts(arch), [[20], list(b"general.architecture"), [8], [5], list(b"llama")]
+        )
+        self.assertEqual(bytes(arch.parts[arch.data[0]]), b"llama")
+
+        length = reader.get_field("llama.context_length")
+        self.assertEqual((length.offset, length.data), (69, [3]))
+        self.assertEqual(length.types, [GGUFValueType.UINT32])
+        self.assertEqual(parts(length), [[20], list(b"llama.context_length"), [4], [4096]])
+
+        tokens = reader.get_field("tokenizer.ggml.tokens")
+        self.assertEqual((tokens.offset, tokens.data), (105, [6, 8, 10]))
+        self.assertEqual(
+            parts(tokens)[2:],
+            [[9], [8], [3], [3], list(b"<s>"), [4], list(b"</s>"), [6], list("héllo".encode())],
+        )
+        self.assertEqual([bytes(tokens.parts[i]).decode() for i in tokens.data], tokens.contents())
+
+    def test_edits_metadata(self):
+        edited = self.path + ".edited.gguf"
+        self.addCleanup(lambda: os.path.exists(edited) and os.remove(edited))
+        with GGUFEditor(self.path) as editor:
+            editor.add_string("general.name", "tiny")
+            editor.add_uint32("llama.context_length", 8192)
+            editor.add_array("tokenizer.ggml.scores", [0.5, -1.0, 2.0])
+            editor.add_key_value(
+                "tokenizer.ggml.token_type", [1, 3, 1], GGUFValueType.ARRAY, GGUFValueType.INT32
+            )
+            editor.add_bool("tokenizer.ggml.add_bos_token", True)
+            editor.remove_key("tokenizer.ggml.tokens")
+            with self.assertRaises(KeyError):
+                editor.remove_key("tokenizer.ggml.tokens")
+            with self.assertRaises(ValueError):
+                editor.add_uint8("general.file_type", 300)
+            editor.write(edited)
+
+        reader = GGUFReader(edited)
+        self.assertEqual(reader.fields["general.name"].contents(), "tiny")
+        self.assertEqual(reader.fields["llama.context_length"].contents(), 8192)
+        scores = reader.fields["tokenizer.ggml.scores"]
+        self.assertEqual(scores.types, [GGUFValueType.ARRAY, GGUFValueType.FLOAT32])
+        self.assertEqual(scores.contents(), [0.5, -1.0, 2.0])
+        self.assertEqual(reader.fields["tokenizer.ggml.token_type"].contents(), [1, 3, 1])
+        self.assertIs(reader.fields["tokenizer.ggml.add_bos_token"].contents(), True)
+        self.assertNotIn("tokenizer.ggml.tokens", reader.fields)
+        self.assertEqual(bytes(reader.tensors[0].data), struct.pack("<6f", 0, 1, 2, 3, 4, 5))
+        self.assertEqual(GGUFReader(self.path).fields["general.architecture"].contents(), "llama")
+
+    def test_reports_errors(self):
+        with self.assertRaises(ValueError):
+            GGUFReader(self.path + ".missing")
+        with self.assertRaises(ValueError):
+            GGUFReader(self.path, "r+")
+
+
+if __name__ == "__main__":
+    unittest.main()
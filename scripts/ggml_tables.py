@@ -0,0 +1,153 @@
+#!/usr/bin/env python3
+"""Generate src/ggml/tables.rs from the headers of a llama.cpp checkout.
+
+    python3 scripts/ggml_tables.py ../llama.cpp > src/ggml/tables.rs
+    python3 scripts/ggml_tables.py ../llama.cpp --check
+
+Type ids come from `enum ggml_type` in ggml.h and file types from `enum llama_ftype` in
+llama.h. Block and type sizes are the `blck_size` and `type_size` expressions of the
+`type_traits` table in ggml.c, evaluated by compiling them against ggml-common.h with the C
+compiler in $CC, `cc` by default. `--check` exits with 1 and prints a diff when the checked-in
+file differs from what the checkout generates.
+"""
+
+import argparse
+import difflib
+import os
+import re
+import subprocess
+import sys
+import tempfile
+
+TABLES = os.path.join(os.path.dirname(__file__), "..", "src", "ggml", "tables.rs")
+
+
+def read(root, *candidates):
+    for path in candidates:
+        full = os.path.join(root, path)
+        if os.path.exists(full):
+            with open(full, encoding="utf-8") as f:
+                return full, f.read()
+    sys.exit(f"none of {', '.join(candidates)} found under {root}")
+
+
+def enum_values(source, enum, prefix):
+    """The (name, value) pairs of a C enum, skipping commented-out entries"""
+    body = re.search(r"enum\s+" + enum + r"\s*\{(.*?)\}", source, re.S)
+    if body is None:
+        sys.exit(f"enum {enum} not found")
+    values = []
+    for line in body.group(1).splitlines():
+        line = line.split("//")[0]
+        match = re.match(r"\s*" + prefix + r"(\w+)\s*=\s*(\d+)\s*,?", line)
+        if match:
+            values.append((match.group(1), int(match.group(2))))
+    return values
+
+
+def type_traits(source):
+    """name -> (type_name, blck_size, type_size) from the designated initializers in ggml.c"""
+    declaration = "type_traits[GGML_TYPE_COUNT]"
+    start = source.index(declaration) + len(declaration)
+    traits = {}
+    for match in re.finditer(r"\[GGML_TYPE_(\w+)\]\s*=\s*\{(.*?)\n\s*\},", source[start:], re.S):
+        fields = dict(re.findall(r"\.(\w+)\s*=\s*([^,\n]+),", match.group(2) + ","))
+        if "blck_size" in fields and "type_size" in fields:
+            name = fields.get("type_name", '"' + match.group(1).lower() + '"').strip().strip('"')
+            traits[match.group(1)] = (name, fields["blck_size"].strip(), fields["type_size"].strip())
+    return traits
+
+
+def evaluate(root, types, traits):
+    """Compile the size expressions, returning id -> (block size, type size)"""
+    lines = [f'    printf("%d %zu %zu\\n", {i}, (size_t)({traits[n][1]}), (size_t)({traits[n][2]}));'
+             for n, i in types if n in traits]
+    program = "\n".join([
+        "#include <assert.h>",
+        "#include <stdio.h>",
+        '#include "ggml.h"',
+        "#define GGML_COMMON_DECL_C",
+        '#include "ggml-common.h"',
+        "int main(void) {",
+        *lines,
+        "    return 0;",
+        "}",
+    ])
+    with tempfile.TemporaryDirectory() as tmp:
+        c, exe = os.path.join(tmp, "sizes.c"), os.path.join(tmp, "sizes")
+        with open(c, "w") as f:
+            f.write(program)
+        includes = [os.path.join(root, p) for p in ("ggml/include", "ggml/src", "include", "src")]
+        subprocess.run([os.environ.get("CC", "cc"), c, "-o", exe, *(f"-I{p}" for p in includes)], check=True)
+        output = subprocess.run([exe], check=True, capture_output=True, text=True).stdout
+    return {int(i): (int(b), int(s)) for i, b, s in (line.split() for line in output.splitlines())}
+
+
+def revision(root):
+    try:
+        rev = subprocess.run(["git", "-C", root, "describe", "--tags", "--always"],
+                             check=True, capture_output=True, text=True).stdout.strip()
+        return f" at {rev}"
+    except (OSError, subprocess.CalledProcessError):
+        return ""
+
+
+def generate(root):
+    _, ggml_h = read(root, "ggml/include/ggml.h", "ggml.h")
+    _, ggml_c = read(root, "ggml/src/ggml.c", "ggml.c")
+    _, llama_h = read(root, "include/llama.h", "llama.h")
+    types = enum_values(ggml_h, "ggml_type", "GGML_TYPE_")
+    count = dict(types).pop("COUNT")
+    types = [(n, i) for n, i in types if n != "COUNT"]
+    traits = type_traits(ggml_c)
+    sizes = evaluate(root, types, traits)
+    ftypes = enum_values(llama_h, "llama_ftype", "LLAMA_FTYPE_")
+
+    out = [
+        f"// @generated by scripts/ggml_tables.py from llama.cpp{revision(root)}; do not edit.",
+        "use super::{FileTypeName, TypeTraits};",
+        "",
+        "/// One past the largest tensor type id, `GGML_TYPE_COUNT`",
+        f"pub const GGML_TYPE_COUNT: u32 = {count};",
+        "",
+        "/// The tensor types of ggml, by id; removed ids are left out",
+        "pub const GGML_TYPES: &[TypeTraits] = &[",
+    ]
+    for name, i in types:
+        if i not in sizes or sizes[i][0] == 0:
+            continue
+        block, size = sizes[i]
+        out.append(f'    TypeTraits {{ id: {i}, name: "{traits[name][0]}", block_size: {block}, type_size: {size} }},')
+    out += ["];", "", "/// The file types of llama.cpp, by id; removed ids are left out",
+            "pub const LLAMA_FTYPES: &[FileTypeName] = &["]
+    for name, i in ftypes:
+        out.append(f'    FileTypeName {{ id: {i}, name: "{name}" }},')
+    out += ["];", "", "llama_ftypes! {"]
+    for name, i in ftypes:
+        out.append(f"    {name} = {i},")
+    out.append("}")
+    return "\n".join(out) + "\n"
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("llama_cpp", help="the root of a llama.cpp checkout")
+    parser.add_argument("--check", action="store_true", help="compare with src/ggml/tables.rs")
+    args = parser.parse_args()
+    generated = generate(args.llama_cpp)
+    if not args.check:
+        sys.stdout.write(generated)
+        return
+    with open(TABLES, encoding="utf-8") as f:
+        current = f.read()
+    # the revision line changes with every checkout
+    strip = lambda text: text.split("\n", 1)[1]
+    if strip(current) != strip(generated):
+        sys.stdout.writelines(difflib.unified_diff(
+            strip(current).splitlines(True), strip(generated).splitlines(True),
+            "src/ggml/tables.rs", "generated"))
+        sys.exit(1)
+
+
+if __name__ == "__main__":
+    main()
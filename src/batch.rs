@@ -0,0 +1,279 @@
+//! # Batched tensor reads
+//!
+//! Tools that look at every tensor of a model, such as `gguf stats`, spend most of their time on
+//! the syscalls of the many small tensors, norms and biases, rather than on their bytes.
+//! [`TensorReader`] reads many tensors at once with positioned reads, which need no seeking
+//! and so can be shared between threads: tensors lying close together are taken as one range,
+//! and on Linux each range is read with a single `preadv` straight into the buffers of its
+//! tensors, the small gaps between them into scratch. Elsewhere a range is read with one
+//! positioned read and split.
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::{GGUFFile, GGUFTensorInfo, ParseOptions};
+
+/// The most bytes between two tensors read along with them rather than skipped, which covers
+/// alignment padding and a few tensors left out
+const MAX_GAP: u64 = 64 << 10;
+
+/// The most tensors read in one call, half the buffers one `preadv` takes on Linux, the other
+/// half being the gaps
+const MAX_GROUP: usize = 512;
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::os::raw::{c_int, c_void};
+
+    #[repr(C)]
+    pub struct IoVec {
+        pub base: *mut c_void,
+        pub len: usize,
+    }
+
+    extern "C" {
+        pub fn preadv(fd: c_int, iov: *const IoVec, iovcnt: c_int, offset: i64) -> isize;
+    }
+}
+
+/// A file of tensors read many at a time
+#[derive(Debug)]
+pub struct TensorReader {
+    file: File,
+    data_start: u64,
+}
+
+impl TensorReader {
+    /// Read tensors from `file`, whose tensor data starts at `data_start`
+    pub fn new(file: File, data_start: u64) -> Self {
+        TensorReader { file, data_start }
+    }
+
+    /// Open the file at `path`, giving its header and tensor infos as well
+    pub fn open(
+        path: impl AsRef<Path>,
+        options: &ParseOptions,
+    ) -> Result<(Self, GGUFFile), String> {
+        let mut file = File::open(path).map_err(|e| e.to_string())?;
+        let (gguf, data_start) =
+            GGUFFile::read_from(&mut file, options).map_err(|e| e.to_string())?;
+        Ok((TensorReader::new(file, data_start), gguf))
+    }
+
+    /// The data of each of `tensors`, in their order, read in as few syscalls as their layout
+    /// allows
+    pub fn read(&self, tensors: &[&GGUFTensorInfo]) -> Result<Vec<Vec<u8>>, String> {
+        let ranges = tensors
+            .iter()
+            .map(|t| {
+                let size = t
+                    .size_bytes()
+                    .ok_or_else(|| format!("tensor {} has no whole number of blocks", t.name))?;
+                let start = self.data_start + t.offset;
+                Ok(start..start + size)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut out: Vec<Vec<u8>> = ranges
+            .iter()
+            .map(|r| vec![0; (r.end - r.start) as usize])
+            .collect();
+        for group in groups(&ranges) {
+            self.read_group(&ranges, &group, &mut out)
+                .map_err(|(i, e)| format!("reading tensor {}: {e}", tensors[i].name))?;
+        }
+        Ok(out)
+    }
+
+    /// Read the tensors of `group`, indices into `ranges` in the order of their offsets, each
+    /// into its buffer of `out`
+    #[cfg(target_os = "linux")]
+    fn read_group(
+        &self,
+        ranges: &[Range<u64>],
+        group: &[usize],
+        out: &mut [Vec<u8>],
+    ) -> Result<(), (usize, std::io::Error)> {
+        use std::os::unix::fs::FileExt;
+        use std::os::unix::io::AsRawFd;
+
+        let start = ranges[group[0]].start;
+        let mut scratch = vec![0u8; MAX_GAP as usize];
+        let mut iovecs = Vec::with_capacity(group.len() * 2);
+        let mut at = start;
+        for &i in group {
+            if ranges[i].start > at {
+                iovecs.push(sys::IoVec {
+                    base: scratch.as_mut_ptr().cast(),
+                    len: (ranges[i].start - at) as usize,
+                });
+            }
+            iovecs.push(sys::IoVec {
+                base: out[i].as_mut_ptr().cast(),
+                len: out[i].len(),
+            });
+            at = ranges[i].end;
+        }
+        let read = loop {
+            // SAFETY: every buffer is alive and unaliased but for the scratch of the gaps,
+            // whose contents are thrown away, for the length given
+            let n = unsafe {
+                sys::preadv(
+                    self.file.as_raw_fd(),
+                    iovecs.as_ptr(),
+                    iovecs.len() as i32,
+                    start as i64,
+                )
+            };
+            match n {
+                n if n >= 0 => break n as u64,
+                _ => {
+                    let e = std::io::Error::last_os_error();
+                    if e.kind() != std::io::ErrorKind::Interrupted {
+                        return Err((group[0], e));
+                    }
+                }
+            }
+        };
+        // a short read leaves the rest of the tensors to read one by one
+        for &i in group {
+            let done = (start + read)
+                .saturating_sub(ranges[i].start)
+                .min(out[i].len() as u64);
+            if done < out[i].len() as u64 {
+                self.file
+                    .read_exact_at(&mut out[i][done as usize..], ranges[i].start + done)
+                    .map_err(|e| (i, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the tensors of `group`, indices into `ranges` in the order of their offsets, each
+    /// into its buffer of `out`
+    #[cfg(not(target_os = "linux"))]
+    fn read_group(
+        &self,
+        ranges: &[Range<u64>],
+        group: &[usize],
+        out: &mut [Vec<u8>],
+    ) -> Result<(), (usize, std::io::Error)> {
+        let start = ranges[group[0]].start;
+        let end = group.iter().map(|&i| ranges[i].end).max().unwrap_or(start);
+        let mut buf = vec![0; (end - start) as usize];
+        read_exact_at(&self.file, &mut buf, start).map_err(|e| (group[0], e))?;
+        for &i in group {
+            let range = &ranges[i];
+            out[i].copy_from_slice(
+                &buf[(range.start - start) as usize..(range.end - start) as usize],
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_exact_at(_file: &File, _buf: &mut [u8], _offset: u64) -> std::io::Result<()> {
+    Err(std::io::Error::other(
+        "positioned reads need unix or windows",
+    ))
+}
+
+/// The indices of `ranges` in groups each read at once: by offset, with at most [`MAX_GAP`]
+/// bytes between one and the next and none overlapping
+fn groups(ranges: &[Range<u64>]) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..ranges.len()).collect();
+    order.sort_by_key(|&i| ranges[i].start);
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut end = 0;
+    for i in order {
+        let range = &ranges[i];
+        match groups.last_mut() {
+            Some(group)
+                if group.len() < MAX_GROUP
+                    && range.start >= end
+                    && range.start - end <= MAX_GAP =>
+            {
+                group.push(i)
+            }
+            _ => groups.push(vec![i]),
+        }
+        end = end.max(range.end);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path, tensor};
+    use crate::writer::write_file;
+    use crate::GGMLType;
+
+    #[test]
+    fn reads_neighbouring_tensors_at_once() {
+        let tensors: Vec<GGUFTensorInfo> = (0..600)
+            .map(|i| {
+                tensor(
+                    &format!("blk.{i}.attn_norm.weight"),
+                    &[1 + i % 7],
+                    GGMLType::F32,
+                    0,
+                )
+            })
+            .collect();
+        let file = file(header(&[]), tensors);
+        let path = temp_path("gguf-batch.gguf");
+        let mut i = 0u8;
+        write_file(&path, &file, |tensor, out| {
+            i = i.wrapping_add(1);
+            let size = tensor.size_bytes().unwrap() as usize;
+            Ok(out.write_all(&vec![i; size])?)
+        })
+        .unwrap();
+
+        let (reader, file) = TensorReader::open(&path, &ParseOptions::default()).unwrap();
+        let mut wanted: Vec<&GGUFTensorInfo> = file.tensors.iter().step_by(2).collect();
+        wanted.reverse();
+        let data = reader.read(&wanted).unwrap();
+        for (tensor, data) in wanted.iter().zip(&data) {
+            let index = file
+                .tensors
+                .iter()
+                .position(|t| t.name == tensor.name)
+                .unwrap();
+            assert_eq!(data.len() as u64, tensor.size_bytes().unwrap());
+            assert!(data.iter().all(|&b| b == (index + 1) as u8));
+        }
+
+        let ranges: Vec<Range<u64>> = wanted
+            .iter()
+            .map(|t| t.offset..t.offset + t.size_bytes().unwrap())
+            .collect();
+        assert_eq!(groups(&ranges).len(), 1);
+        let apart = [0..8, 8..16, 1 << 20..(1 << 20) + 8, 16..24, 4..12];
+        assert_eq!(groups(&apart), [vec![0], vec![4], vec![1, 3], vec![2]]);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,10 +1,10 @@
 use bytes::{BufMut, BytesMut};
 use clap::{Parser, ValueEnum};
 use comfy_table::Table;
-use gguf::{GGUFFile, GGUFMetadataValue};
+use gguf::{GGUFFile, GGUFMetadataValue, ParseOptions};
 use std::borrow::Borrow;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, ValueEnum)]
@@ -27,27 +27,50 @@ struct Args {
 
     #[arg(short = 't', long, value_enum, default_value_t = OutputFormat::Table)]
     output_format: OutputFormat,
+
+    /// Read past spec deviations such as bad bools, invalid UTF-8 and duplicate keys
+    #[arg(long)]
+    lenient: bool,
 }
 
 type E = Box<dyn std::error::Error>;
 
 fn main() -> Result<(), E> {
+    match run() {
+        // the reader went away, as `head` does once it has its lines
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+fn run() -> Result<(), E> {
     let args = Args::parse();
-    let read_file = read_gguf_file(args.path, args.read_buffer_size)?;
+    let options = if args.lenient {
+        ParseOptions::lenient()
+    } else {
+        ParseOptions::strict()
+    };
+    let read_file = read_gguf_file(args.path, args.read_buffer_size, &options)?;
+    let mut out = std::io::stdout().lock();
     match args.output_format {
         OutputFormat::Yaml => {
-            println!("{}", serde_yaml::to_string(&read_file)?);
+            writeln!(out, "{}", serde_yaml::to_string(&read_file)?)?;
         }
         OutputFormat::Json => {
-            println!("{}", serde_json::to_string_pretty(&read_file)?);
+            writeln!(out, "{}", serde_json::to_string_pretty(&read_file)?)?;
         }
         OutputFormat::Table => {
             let metadata = build_metadata_table(&read_file)?;
-            println!("Metadata:");
-            println!("{metadata}");
+            writeln!(out, "Metadata:")?;
+            writeln!(out, "{metadata}")?;
             let tensor_info = build_tensor_info_table(&read_file)?;
-            println!("Tensors:");
-            println!("{tensor_info}");
+            writeln!(out, "Tensors:")?;
+            writeln!(out, "{tensor_info}")?;
         }
     }
     Ok(())
@@ -100,7 +123,11 @@ fn build_tensor_info_table(read_file: &GGUFFile) -> Result<String, E> {
 }
 
 /// Read a gguf file by trying out different buffer sizes
-fn read_gguf_file(fname: PathBuf, read_buffer_size: usize) -> Result<GGUFFile, E> {
+fn read_gguf_file(
+    fname: PathBuf,
+    read_buffer_size: usize,
+    options: &ParseOptions,
+) -> Result<GGUFFile, E> {
     let mut buffer = BytesMut::with_capacity(read_buffer_size);
     let mut reader = BufReader::with_capacity(read_buffer_size, File::open(fname)?);
     loop {
@@ -111,7 +138,7 @@ fn read_gguf_file(fname: PathBuf, read_buffer_size: usize) -> Result<GGUFFile, E
         let content_length = read.len();
         buffer.put(read);
         reader.consume(content_length);
-        match GGUFFile::read(buffer.borrow()) {
+        match GGUFFile::read_with(buffer.borrow(), options) {
             Ok(Some(file)) => {
                 return Ok(file);
             }
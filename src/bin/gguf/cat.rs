@@ -0,0 +1,156 @@
+use gguf::quant::dequantize;
+use gguf::GGUFFile;
+use serde_json::json;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::{input, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// The tensor to print
+    tensor: String,
+
+    /// The elements to print, e.g. `0..64`, `4096..` or `..8`
+    #[arg(long, default_value = "0..64", value_parser = parse_range)]
+    range: (Option<u64>, Option<u64>),
+
+    /// Print the bytes of the blocks holding the range, undecoded
+    #[arg(long, conflicts_with = "float")]
+    hex: bool,
+
+    /// Print the values decoded to f32, the default
+    #[arg(long)]
+    float: bool,
+}
+
+/// `start..end` with either end left out
+fn parse_range(range: &str) -> Result<(Option<u64>, Option<u64>), String> {
+    let (start, end) = range
+        .split_once("..")
+        .ok_or_else(|| format!("expected a range such as 0..64, not {range}"))?;
+    let bound = |s: &str| {
+        (!s.is_empty())
+            .then(|| s.parse::<u64>().map_err(|e| format!("{s}: {e}")))
+            .transpose()
+    };
+    let (start, end) = (bound(start)?, bound(end)?);
+    if let (Some(start), Some(end)) = (start, end) {
+        if start >= end {
+            return Err(format!("the range {range} is empty"));
+        }
+    }
+    Ok((start, end))
+}
+
+/// `bytes` as lines of 16, each after its offset from `base`
+fn hex_lines(bytes: &[u8], base: u64) -> Vec<String> {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, line)| {
+            let hex: Vec<String> = line.iter().map(|b| format!("{b:02x}")).collect();
+            format!("{:08x}  {}", base + 16 * i as u64, hex.join(" "))
+        })
+        .collect()
+}
+
+/// Reads only the blocks holding the range, so slices of large tensors print quickly, also
+/// over HTTP
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let mut file = input(&args.path)?;
+    let (gguf, data_start) = GGUFFile::read_from(&mut file, &ctx.options)?;
+    let tensor = gguf
+        .tensors
+        .iter()
+        .find(|t| t.name == args.tensor)
+        .ok_or_else(|| format!("no tensor named {}", args.tensor))?;
+    let tensor_type = tensor.tensor_type;
+    let (block, block_bytes) = tensor_type
+        .block_size()
+        .zip(tensor_type.type_size())
+        .ok_or_else(|| format!("{tensor_type:?} data has no block layout"))?;
+    let count = tensor.element_count();
+    let range = Range {
+        start: args.range.0.unwrap_or(0),
+        end: args.range.1.unwrap_or(count).min(count),
+    };
+    if range.start >= range.end {
+        return Err(format!("tensor {} has only {count} elements", tensor.name).into());
+    }
+
+    let blocks = range.start / block..range.end.div_ceil(block);
+    let offset = blocks.start * block_bytes;
+    let size = (blocks.end - blocks.start) * block_bytes;
+    file.seek(SeekFrom::Start(data_start + tensor.offset + offset))?;
+    let mut data = Vec::with_capacity(size as usize);
+    file.take(size).read_to_end(&mut data)?;
+    if (data.len() as u64) < size {
+        return Err(format!("the file ends inside the data of tensor {}", tensor.name).into());
+    }
+
+    if args.hex {
+        if ctx.structured() {
+            let hex: String = data.iter().map(|b| format!("{b:02x}")).collect();
+            return ctx.print(&json!({
+                "tensor": tensor.name,
+                "type": tensor_type,
+                "offset": offset,
+                "hex": hex,
+            }));
+        }
+        for line in hex_lines(&data, offset) {
+            outln!("{line}");
+        }
+        return Ok(());
+    }
+    let skip = (range.start - blocks.start * block) as usize;
+    let values: Vec<f32> = dequantize(tensor_type, &data)?
+        .into_iter()
+        .skip(skip)
+        .take((range.end - range.start) as usize)
+        .collect();
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "tensor": tensor.name,
+            "type": tensor_type,
+            "range": [range.start, range.end],
+            "values": values,
+        }));
+    }
+    let width = range.end.to_string().len();
+    for (i, line) in values.chunks(8).enumerate() {
+        let values: Vec<String> = line.iter().map(|v| format!("{v:>12.6}")).collect();
+        outln!(
+            "{:>width$}: {}",
+            range.start + 8 * i as u64,
+            values.join(" ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_and_hex() {
+        assert_eq!(parse_range("0..64"), Ok((Some(0), Some(64))));
+        assert_eq!(parse_range("4096.."), Ok((Some(4096), None)));
+        assert_eq!(parse_range("..8"), Ok((None, Some(8))));
+        assert!(parse_range("8..8").is_err());
+        assert!(parse_range("8").is_err());
+        assert_eq!(
+            hex_lines(&[0xab; 18], 32),
+            [
+                "00000020  ab ab ab ab ab ab ab ab ab ab ab ab ab ab ab ab",
+                "00000030  ab ab"
+            ]
+        );
+    }
+}
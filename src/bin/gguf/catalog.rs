@@ -0,0 +1,73 @@
+use gguf::catalog::{catalog, write_csv, write_jsonl, Layout};
+use serde_json::json;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use crate::paths::expand;
+use crate::{open, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The files to catalog: paths, URLs, directories to search for `.gguf` files or patterns
+    /// such as `models/**/*.gguf`
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// The file to write, `.csv` or `.jsonl`
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Write a row per metadata key of each file instead of a row per file
+    #[arg(long)]
+    per_key: bool,
+}
+
+/// Reads the header of each file and writes the catalog of them all
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let write = match args.output.extension().and_then(|e| e.to_str()) {
+        Some("csv") => write_csv,
+        Some("jsonl" | "ndjson") => write_jsonl,
+        Some("parquet") => {
+            return Err("there is no Parquet output, write .csv and convert it with DuckDB".into())
+        }
+        _ => return Err("the catalog is written as .csv or .jsonl".into()),
+    };
+    let paths = expand(&args.paths)?;
+    let mut files = Vec::new();
+    for path in &paths {
+        let file = open(path, &ctx.options).map_err(|e| format!("{}: {e}", path.display()))?;
+        let len = std::fs::metadata(path).ok().map(|m| m.len());
+        files.push((path.to_string_lossy().into_owned(), len, file));
+    }
+    let files: Vec<_> = files
+        .iter()
+        .map(|(path, len, file)| (path.as_str(), *len, file))
+        .collect();
+    let layout = if args.per_key {
+        Layout::Keys
+    } else {
+        Layout::Models
+    };
+    let columns = catalog(&files, layout);
+    write(&columns, BufWriter::new(File::create(&args.output)?))?;
+    let rows = columns.first().map_or(0, |c| match &c.values {
+        gguf::catalog::Values::Int64(v) => v.len(),
+        gguf::catalog::Values::Utf8(v) => v.len(),
+    });
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "output": args.output,
+            "files": files.len(),
+            "rows": rows,
+        }));
+    }
+    if !ctx.quiet {
+        eprintln!(
+            "wrote {rows} rows for {} files to {}",
+            files.len(),
+            args.output.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use gguf::chat_template::{ChatTemplate, RenderOptions, Value};
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{open, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// Render these messages instead of printing the template: a JSON list of messages, or an
+    /// object with `messages` and optionally `tools`
+    #[arg(long)]
+    render: Option<PathBuf>,
+
+    /// The named variant, e.g. `rag` for `tokenizer.chat_template.rag`
+    #[arg(long)]
+    name: Option<String>,
+
+    /// End the rendered prompt with the start of the assistant's turn
+    #[arg(long)]
+    add_generation_prompt: bool,
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let file = open(&args.path, &ctx.options)?;
+    let Some(template) = ChatTemplate::from_header(&file.header, args.name.as_deref())? else {
+        let names = ChatTemplate::names(&file.header);
+        let available = if names.is_empty() {
+            "it has none".to_string()
+        } else {
+            format!("it has {}", names.join(", "))
+        };
+        let wanted = args.name.as_deref().unwrap_or("default");
+        return Err(format!(
+            "{} has no {wanted} chat template, {available}",
+            args.path.display()
+        )
+        .into());
+    };
+    let Some(messages) = &args.render else {
+        if ctx.structured() {
+            return ctx.print(&json!({ "name": args.name, "template": template.source() }));
+        }
+        out!("{}", template.source());
+        if !template.source().ends_with('\n') {
+            outln!();
+        }
+        return Ok(());
+    };
+    let input: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(messages)?)?;
+    let (messages, tools) = match input {
+        serde_json::Value::Array(messages) => (messages, None),
+        serde_json::Value::Object(mut doc) => {
+            let messages = match doc.remove("messages") {
+                Some(serde_json::Value::Array(messages)) => messages,
+                _ => return Err("the messages object has no messages list".into()),
+            };
+            let tools = match doc.remove("tools") {
+                Some(serde_json::Value::Array(tools)) => {
+                    Some(tools.into_iter().map(Value::from).collect())
+                }
+                _ => None,
+            };
+            (messages, tools)
+        }
+        _ => return Err("expected a list of messages or an object with messages".into()),
+    };
+    let options = RenderOptions {
+        add_generation_prompt: args.add_generation_prompt,
+        tools,
+        ..Default::default()
+    };
+    let prompt = template.render_with(messages.into_iter().map(Value::from).collect(), &options)?;
+    if ctx.structured() {
+        return ctx.print(&json!({ "name": args.name, "prompt": prompt }));
+    }
+    out!("{prompt}");
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use clap::ValueEnum;
+use gguf::convert::{convert, ConvertOptions};
+use gguf::tokenizer::TokenizerMetadata;
+use gguf::GGMLType;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{Context, E};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, ValueEnum)]
+enum OutType {
+    F32,
+    F16,
+    #[value(name = "q8_0")]
+    Q8_0,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The safetensors checkpoint, or the `model.safetensors.index.json` of a sharded one; PyTorch
+    /// `.bin` and `.pth` checkpoints too when built with the `pytorch` feature
+    path: PathBuf,
+
+    /// The architecture of the model, e.g. `llama`
+    #[arg(long)]
+    arch: String,
+
+    /// The Hugging Face `config.json` holding the hyperparameters
+    #[arg(long)]
+    config: PathBuf,
+
+    /// The Hugging Face `tokenizer.json` to take the vocabulary from
+    #[arg(long)]
+    tokenizer: Option<PathBuf>,
+
+    /// The GGUF file to write
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// The type of the matrices; vectors stay f32
+    #[arg(long, value_enum, default_value_t = OutType::F16)]
+    outtype: OutType,
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let config = serde_json::from_str(&std::fs::read_to_string(&args.config)?)?;
+    let tokenizer = args
+        .tokenizer
+        .as_ref()
+        .map(TokenizerMetadata::from_hf_json)
+        .transpose()?;
+    let options = ConvertOptions {
+        arch: args.arch.clone(),
+        config,
+        tokenizer,
+        out_type: match args.outtype {
+            OutType::F32 => GGMLType::F32,
+            OutType::F16 => GGMLType::F16,
+            OutType::Q8_0 => GGMLType::Q8_0,
+        },
+    };
+    let file = convert(&args.path, &args.output, &options)?;
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "output": args.output,
+            "tensor_count": file.tensors.len(),
+            "metadata_count": file.header.metadata.len(),
+        }));
+    }
+    eprintln!(
+        "wrote {} tensors and {} keys to {}",
+        file.tensors.len(),
+        file.header.metadata.len(),
+        args.output.display()
+    );
+    Ok(())
+}
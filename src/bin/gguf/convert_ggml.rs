@@ -0,0 +1,55 @@
+use gguf::ggjt::{convert, LegacyOptions};
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The legacy GGML, GGMF or GGJT file of a LLaMA model
+    path: PathBuf,
+
+    /// The GGUF file to write
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// The name of the model, stored as `general.name`
+    #[arg(long)]
+    name: Option<String>,
+
+    /// The context length the model was trained with, 4096 for Llama 2
+    #[arg(long, default_value_t = 2048)]
+    context_length: u32,
+
+    /// The RMS norm epsilon, 1e-6 for LLaMA 1 and 1e-5 for Llama 2
+    #[arg(long, default_value_t = 5e-6)]
+    eps: f32,
+
+    /// The number of key-value heads, 8 for Llama 2 70B
+    #[arg(long)]
+    head_count_kv: Option<u32>,
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let options = LegacyOptions {
+        name: args.name.clone(),
+        context_length: args.context_length,
+        rms_eps: args.eps,
+        head_count_kv: args.head_count_kv,
+    };
+    let file = convert(&args.path, &args.output, &options)?;
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "output": args.output,
+            "tensor_count": file.tensors.len(),
+            "metadata_count": file.header.metadata.len(),
+        }));
+    }
+    eprintln!(
+        "wrote {} tensors and {} keys to {}",
+        file.tensors.len(),
+        file.header.metadata.len(),
+        args.output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use gguf::diff::MetadataDiff;
+use gguf::{GGUFMetadata, GGUFTensorInfo};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::dump::format_value;
+use crate::get::to_json;
+use crate::{open, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The original file
+    a: PathBuf,
+
+    /// The file to compare it with
+    b: PathBuf,
+
+    /// Array items to print before eliding the rest
+    #[arg(long, default_value_t = 8)]
+    array_items: usize,
+}
+
+/// tensors only in `b`, tensors only in `a`, and pairs that differ, in the order of `b` then `a`
+struct Changes<'a, T> {
+    added: Vec<&'a T>,
+    removed: Vec<&'a T>,
+    changed: Vec<(&'a T, &'a T)>,
+}
+
+fn changes<'a, T>(
+    a: &'a [T],
+    b: &'a [T],
+    name: impl Fn(&T) -> &str,
+    same: impl Fn(&T, &T) -> bool,
+) -> Changes<'a, T> {
+    let by_name: HashMap<&str, &T> = a.iter().map(|x| (name(x), x)).collect();
+    let in_b: HashMap<&str, &T> = b.iter().map(|x| (name(x), x)).collect();
+    let mut changes = Changes {
+        added: Vec::new(),
+        removed: a.iter().filter(|x| !in_b.contains_key(name(x))).collect(),
+        changed: Vec::new(),
+    };
+    for new in b {
+        match by_name.get(name(new)) {
+            None => changes.added.push(new),
+            Some(old) if !same(old, new) => changes.changed.push((old, new)),
+            Some(_) => {}
+        }
+    }
+    changes
+}
+
+fn tensor_json(t: &GGUFTensorInfo) -> serde_json::Value {
+    json!({
+        "name": t.name,
+        "shape": t.dimensions,
+        "type": t.tensor_type,
+        "bytes": t.size_bytes(),
+    })
+}
+
+fn tensor_line(t: &GGUFTensorInfo) -> String {
+    format!(
+        "{} {:?} {:?} {} bytes",
+        t.name,
+        t.dimensions,
+        t.tensor_type,
+        t.size_bytes()
+            .map_or_else(|| "?".to_string(), |s| s.to_string())
+    )
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let (a, b) = (open(&args.a, &ctx.options)?, open(&args.b, &ctx.options)?);
+    let metadata = MetadataDiff::between(&a.header, &b.header);
+    let tensors = changes(
+        &a.tensors,
+        &b.tensors,
+        |t| &t.name,
+        |x, y| x.dimensions == y.dimensions && x.tensor_type == y.tensor_type,
+    );
+
+    if ctx.structured() {
+        let entry = |m: &GGUFMetadata| json!({"key": m.key, "type": m.value_type, "value": to_json(&m.value)});
+        let diff = json!({
+            "version": (a.header.version != b.header.version)
+                .then_some([a.header.version, b.header.version]),
+            "metadata": {
+                "added": metadata.added.iter().map(entry).collect::<Vec<_>>(),
+                "removed": metadata.removed.iter().map(entry).collect::<Vec<_>>(),
+                "changed": metadata.changed.iter().map(|(old, new)| json!({
+                    "key": new.key,
+                    "old": entry(old),
+                    "new": entry(new),
+                })).collect::<Vec<_>>(),
+            },
+            "tensors": {
+                "added": tensors.added.iter().map(|t| tensor_json(t)).collect::<Vec<_>>(),
+                "removed": tensors.removed.iter().map(|t| tensor_json(t)).collect::<Vec<_>>(),
+                "changed": tensors.changed.iter().map(|(old, new)| json!({
+                    "name": new.name,
+                    "old": tensor_json(old),
+                    "new": tensor_json(new),
+                })).collect::<Vec<_>>(),
+            },
+        });
+        return ctx.print(&diff);
+    }
+
+    let value = |m: &GGUFMetadata| format_value(&m.value, args.array_items);
+    if a.header.version != b.header.version {
+        outln!("version: {} -> {}", a.header.version, b.header.version);
+    }
+    for m in &metadata.removed {
+        outln!("- {} = {}", m.key, value(m));
+    }
+    for m in &metadata.added {
+        outln!("+ {} = {}", m.key, value(m));
+    }
+    for (old, new) in &metadata.changed {
+        outln!("~ {} = {} -> {}", new.key, value(old), value(new));
+    }
+    for t in &tensors.removed {
+        outln!("- tensor {}", tensor_line(t));
+    }
+    for t in &tensors.added {
+        outln!("+ tensor {}", tensor_line(t));
+    }
+    for (old, new) in &tensors.changed {
+        outln!("~ tensor {} -> {}", tensor_line(old), tensor_line(new));
+    }
+    Ok(())
+}
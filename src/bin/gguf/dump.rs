@@ -0,0 +1,98 @@
+use gguf::GGUFMetadataValue;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::get::to_json;
+use crate::{open, table, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// Array items to print before eliding the rest
+    #[arg(long, default_value_t = 8)]
+    array_items: usize,
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let file = open(&args.path, &ctx.options)?;
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "version": file.header.version,
+            "tensor_count": file.tensors.len(),
+            "metadata": file.header.metadata.iter().map(|m| json!({
+                "key": m.key,
+                "type": m.value_type,
+                "value": to_json(&m.value),
+            })).collect::<Vec<_>>(),
+        }));
+    }
+    let tensor_bytes: u64 = file.tensors.iter().filter_map(|t| t.size_bytes()).sum();
+    outln!("version:  {}", file.header.version);
+    outln!(
+        "tensors:  {} ({})",
+        table::count(file.tensors.len() as u64),
+        table::size(tensor_bytes)
+    );
+    outln!(
+        "metadata: {}",
+        table::count(file.header.metadata.len() as u64)
+    );
+    outln!();
+    let rows: Vec<[String; 3]> = file
+        .header
+        .metadata
+        .iter()
+        .map(|entry| {
+            [
+                entry.key.clone(),
+                type_name(&entry.value),
+                format_value(&entry.value, args.array_items),
+            ]
+        })
+        .collect();
+    table::print(
+        &ctx.style,
+        ["key", "type", "value"],
+        &rows,
+        [false, false, false],
+    )?;
+    Ok(())
+}
+
+/// the type of a value, with the item type and length for arrays, e.g. `[String; 32,000]`
+fn type_name(value: &GGUFMetadataValue) -> String {
+    match value {
+        GGUFMetadataValue::Array(array) => match array.value.first() {
+            Some(first @ GGUFMetadataValue::Array(_)) => {
+                format!("[{}; {}]", type_name(&first), table::count(array.len))
+            }
+            _ => format!("[{:?}; {}]", array.value_type, table::count(array.len)),
+        },
+        _ => format!("{:?}", value.value_type()),
+    }
+}
+
+/// a value as it would be written in Rust, with arrays cut after `items` items
+pub fn format_value(value: &GGUFMetadataValue, items: usize) -> String {
+    match value {
+        GGUFMetadataValue::String(s) => format!("{s:?}"),
+        GGUFMetadataValue::Array(array) => {
+            let mut parts: Vec<String> = array
+                .value
+                .iter()
+                .take(items)
+                .map(|v| format_value(&v, items))
+                .collect();
+            if array.value.len() > items {
+                parts.push(format!(
+                    "... {} more",
+                    table::count((array.value.len() - items) as u64)
+                ));
+            }
+            format!("[{}]", parts.join(", "))
+        }
+        other => format!("{other:?}"),
+    }
+}
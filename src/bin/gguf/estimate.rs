@@ -0,0 +1,106 @@
+use gguf::estimate::{effective_context, estimate, EstimateOptions};
+use gguf::quant::parse_type;
+use gguf::GGMLType;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{open, table, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// Tokens of context, by default the context length the model was trained for
+    #[arg(long)]
+    ctx: Option<u64>,
+
+    /// Tokens evaluated at once
+    #[arg(long, default_value_t = 512)]
+    batch: u64,
+
+    /// The type the KV cache is stored as, e.g. `f16` or `q8_0`
+    #[arg(long, default_value = "f16", value_parser = kv_type)]
+    kv_type: GGMLType,
+}
+
+fn kv_type(name: &str) -> Result<GGMLType, String> {
+    parse_type(name).ok_or_else(|| format!("{name} is not a tensor type"))
+}
+
+/// Prints the weights, KV cache and compute memory and their total, as rough bounds for
+/// llama.cpp; offloading splits them between devices but not their sum
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let file = open(&args.path, &ctx.options)?;
+    let options = EstimateOptions {
+        context: args.ctx,
+        batch: args.batch,
+        kv_type: args.kv_type,
+    };
+    let estimate = estimate(&file, &options)?;
+    let window = effective_context(&file);
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "context": estimate.context,
+            "batch": args.batch,
+            "kv_type": args.kv_type,
+            "weights": estimate.weights,
+            "kv_cache": estimate.kv_cache,
+            "state": estimate.state,
+            "compute": estimate.compute,
+            "total": estimate.total(),
+            "effective_context": window.map(|w| w.effective),
+        }));
+    }
+    let kv = format!(
+        "{} tokens of {:?}",
+        table::count(estimate.context),
+        args.kv_type
+    );
+    let mut rows = vec![
+        [
+            "weights".to_string(),
+            table::size(estimate.weights),
+            String::new(),
+        ],
+        ["KV cache".to_string(), table::size(estimate.kv_cache), kv],
+        [
+            "compute".to_string(),
+            table::size(estimate.compute),
+            format!("batches of {}", table::count(args.batch)),
+        ],
+        [
+            "total".to_string(),
+            table::size(estimate.total()),
+            String::new(),
+        ],
+    ];
+    // recurrent models keep a state of one size for any context, attention ones a KV cache
+    if estimate.state > 0 {
+        rows.insert(
+            2,
+            [
+                "state".to_string(),
+                table::size(estimate.state),
+                "F32, at any context".to_string(),
+            ],
+        );
+        if estimate.kv_cache == 0 {
+            rows.remove(1);
+        }
+    }
+    table::print(
+        &ctx.style,
+        ["memory", "size", ""],
+        &rows,
+        [false, true, false],
+    )?;
+    if let Some(window) = window.filter(|w| w.effective > w.trained) {
+        outln!(
+            "{} RoPE scaling extends the context to {} tokens",
+            window.scaling.unwrap_or_default(),
+            table::count(window.effective)
+        );
+    }
+    Ok(())
+}
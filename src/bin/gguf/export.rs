@@ -0,0 +1,99 @@
+use clap::ValueEnum;
+use gguf::convert::{to_mlx, to_safetensors};
+use gguf::{GGMLType, GGUFFile};
+use serde_json::json;
+use std::fs::File;
+use std::path::PathBuf;
+
+use crate::{Context, E};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, ValueEnum)]
+enum Float {
+    F32,
+    F16,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file to export
+    path: PathBuf,
+
+    /// The file to write, whose extension picks the format: `.safetensors`, `.npz` or
+    /// `.onnx_data`; with `--mlx` the folder
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Write a Hugging Face model folder for MLX: `model.safetensors` under transformers
+    /// names, `config.json` and the tokenizer
+    #[arg(long)]
+    mlx: bool,
+
+    /// The type quantized tensors are dequantized to in safetensors and ONNX external data
+    #[arg(long, value_enum, default_value_t = Float::F16)]
+    dtype: Float,
+
+    /// Decode every tensor to f32 in a `.npz` instead of keeping quantized blocks as bytes
+    #[arg(long)]
+    dequantize: bool,
+}
+
+/// Writes the tensors, with the metadata in a `.json` next to them
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let float = match args.dtype {
+        Float::F32 => GGMLType::F32,
+        Float::F16 => GGMLType::F16,
+    };
+    if args.mlx {
+        let count = to_mlx(&args.path, &args.output, float)?.len();
+        if ctx.structured() {
+            return ctx.print(&json!({ "output": args.output, "tensor_count": count }));
+        }
+        if !ctx.quiet {
+            eprintln!("wrote {count} tensors to {}", args.output.display());
+        }
+        return Ok(());
+    }
+    let count = match args.output.extension().and_then(|e| e.to_str()) {
+        Some("safetensors") => to_safetensors(&args.path, &args.output, float)?.len(),
+        Some("npz") => {
+            let mut input = File::open(&args.path)?;
+            let (file, data_start) = GGUFFile::read_from(&mut input, &ctx.options)?;
+            file.export_npz(&mut input, data_start, &args.output, args.dequantize)?;
+            std::fs::write(
+                args.output.with_extension("json"),
+                serde_json::to_vec_pretty(&file.header)?,
+            )?;
+            file.tensors.len()
+        }
+        Some("onnx_data") => {
+            let mut input = File::open(&args.path)?;
+            let (file, data_start) = GGUFFile::read_from(&mut input, &ctx.options)?;
+            file.export_onnx_data(&mut input, data_start, &args.output, float)?
+                .len()
+        }
+        _ => {
+            return Err(format!(
+            "cannot tell the format of {} by its extension, use .safetensors, .npz or .onnx_data",
+            args.output.display()
+        )
+            .into())
+        }
+    };
+    let metadata = args.output.with_extension("json");
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "output": args.output,
+            "metadata": metadata,
+            "tensor_count": count,
+        }));
+    }
+    if !ctx.quiet {
+        eprintln!(
+            "wrote {} tensors to {} and the metadata to {}",
+            count,
+            args.output.display(),
+            metadata.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use gguf::quant::dequantize;
+use gguf::reader::GGUFReader;
+use gguf::GGMLType;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{input, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// The tensor to extract
+    tensor: String,
+
+    /// Where to write the data, as a NumPy array when the name ends in .npy and raw bytes
+    /// otherwise
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Decode quantized data to f32
+    #[arg(long)]
+    dequantize: bool,
+}
+
+/// the NumPy dtype of undecoded data, if it has one
+fn dtype(tensor_type: GGMLType) -> Option<&'static str> {
+    Some(match tensor_type {
+        GGMLType::F32 => "<f4",
+        GGMLType::F16 => "<f2",
+        GGMLType::F64 => "<f8",
+        GGMLType::I8 => "|i1",
+        GGMLType::I16 => "<i2",
+        GGMLType::I32 => "<i4",
+        GGMLType::I64 => "<i8",
+        _ => return None,
+    })
+}
+
+/// A version 1.0 `.npy` header; ggml lists dimensions innermost first, NumPy outermost first
+fn npy_header(dtype: &str, dimensions: &[u64]) -> Vec<u8> {
+    let shape: Vec<String> = dimensions.iter().rev().map(u64::to_string).collect();
+    let shape = match shape.len() {
+        1 => format!("{},", shape[0]),
+        _ => shape.join(", "),
+    };
+    let mut dict = format!("{{'descr': '{dtype}', 'fortran_order': False, 'shape': ({shape}), }}");
+    // the magic, version and length take 10 bytes; the header ends in a newline on a
+    // 64-byte boundary
+    let padded = (10 + dict.len() + 1).next_multiple_of(64);
+    dict.extend(std::iter::repeat_n(' ', padded - 10 - dict.len() - 1));
+    dict.push('\n');
+    let mut out = b"\x93NUMPY\x01\x00".to_vec();
+    out.extend((dict.len() as u16).to_le_bytes());
+    out.extend(dict.as_bytes());
+    out
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let mut reader = GGUFReader::new(input(&args.path)?, &ctx.options)?.index_tensors()?;
+    let tensor = reader
+        .tensor(&args.tensor)
+        .ok_or_else(|| format!("no tensor named {}", args.tensor))?
+        .clone();
+    let data = reader.read_tensor(&args.tensor)?;
+
+    let (dtype, data) = if args.dequantize {
+        let values = dequantize(tensor.tensor_type, &data)?;
+        let bytes = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        (Some("<f4"), bytes)
+    } else {
+        (dtype(tensor.tensor_type), data)
+    };
+    let mut out = Vec::new();
+    let npy = args.output.extension().is_some_and(|e| e == "npy");
+    if npy {
+        let dtype = dtype.ok_or_else(|| {
+            format!(
+                "{:?} data has no NumPy type, pass --dequantize to decode it to f32",
+                tensor.tensor_type
+            )
+        })?;
+        out = npy_header(dtype, &tensor.dimensions);
+    }
+    let bytes = data.len();
+    out.extend(data);
+    std::fs::write(&args.output, out)?;
+    if ctx.structured() {
+        ctx.print(&json!({
+            "tensor": tensor.name,
+            "output": args.output,
+            "format": if npy { "npy" } else { "raw" },
+            "dtype": dtype,
+            "shape": tensor.dimensions,
+            "bytes": bytes,
+        }))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npy_header_is_aligned() {
+        let header = npy_header("<f4", &[4096, 32]);
+        assert!(header.len().is_multiple_of(64));
+        let dict = std::str::from_utf8(&header[10..]).unwrap();
+        assert!(dict.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (32, 4096), }"));
+        assert!(dict.ends_with(" \n"));
+        assert!(String::from_utf8_lossy(&npy_header("<f2", &[7])).contains("(7,)"));
+    }
+}
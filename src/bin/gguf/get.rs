@@ -0,0 +1,57 @@
+use gguf::GGUFMetadataValue;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{open, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// The metadata key, e.g. `tokenizer.chat_template`
+    key: String,
+}
+
+/// Prints the raw value, or `{"key", "type", "value"}` with every array item
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let file = open(&args.path, &ctx.options)?;
+    let entry = file
+        .header
+        .metadata
+        .iter()
+        .find(|m| m.key == args.key)
+        .ok_or_else(|| format!("{} has no key {}", args.path.display(), args.key))?;
+    if ctx.structured() {
+        ctx.print(&json!({
+            "key": entry.key,
+            "type": entry.value_type,
+            "value": to_json(&entry.value),
+        }))?;
+    } else {
+        print_raw(&entry.value)?;
+    }
+    Ok(())
+}
+
+/// strings without quotes or escapes and array items one per line
+fn print_raw(value: &GGUFMetadataValue) -> std::io::Result<()> {
+    match value {
+        GGUFMetadataValue::String(s) => outln!("{s}"),
+        GGUFMetadataValue::Array(array) => {
+            for v in array.value.iter() {
+                print_raw(&v)?;
+            }
+        }
+        other => outln!("{other:?}"),
+    }
+    Ok(())
+}
+
+/// a value as JSON, arrays in full rather than cut short as their `Serialize` does
+pub fn to_json(value: &GGUFMetadataValue) -> serde_json::Value {
+    match value {
+        GGUFMetadataValue::Array(array) => array.value.iter().map(|v| to_json(&v)).collect(),
+        other => serde_json::to_value(other).unwrap_or_default(),
+    }
+}
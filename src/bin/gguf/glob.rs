@@ -0,0 +1,97 @@
+//! Shell-style wildcards: `*` matches any run of characters, `?` any one character and
+//! `[abc]` or `[a-z]` one of a set, `[!abc]` one outside it
+
+/// Whether `text` matches the whole of `pattern`
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // position to resume from after the last `*`, as (pattern, text)
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            Some('[') => {
+                if let Some((matched, len)) = class(&pattern[p..], text[t]) {
+                    if matched {
+                        p += len;
+                        t += 1;
+                        continue;
+                    }
+                } else if text[t] == '[' {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+            }
+            Some(&c) if c == text[t] => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match star {
+            Some((sp, st)) => {
+                p = sp;
+                t = st + 1;
+                star = Some((sp, st + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// match a character against the `[...]` class at the start of `pattern`, giving the result
+/// and the length of the class, `None` if the class is not closed
+fn class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while let Some(&start) = pattern.get(i) {
+        if start == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&e| e != ']') {
+            matched |= (start..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= start == c;
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("blk.*.attn_q.weight", "blk.12.attn_q.weight"));
+        assert!(matches("*", ""));
+        assert!(matches("blk.?.ffn_*", "blk.3.ffn_up.weight"));
+        assert!(!matches("blk.?.ffn_*", "blk.31.ffn_up.weight"));
+        assert!(matches("blk.[0-2].*", "blk.1.attn_k.weight"));
+        assert!(!matches("blk.[!0-2].*", "blk.1.attn_k.weight"));
+        assert!(matches("*.weight", "output.weight"));
+        assert!(!matches("*.bias", "output.weight"));
+        assert!(matches("a[b", "a[b"));
+    }
+}
@@ -0,0 +1,84 @@
+use clap::ValueEnum;
+use gguf::manifest::{create_manifest_with, Algorithm};
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::paths::{expand, models};
+use crate::{Context, E};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, ValueEnum)]
+enum Algo {
+    Sha256,
+    Blake3,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The files to hash: paths, directories to search for `.gguf` files or patterns such as
+    /// `models/**/*.gguf`
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = Algo::Sha256)]
+    algo: Algo,
+
+    /// Also hash the data of each tensor
+    #[arg(long)]
+    per_tensor: bool,
+
+    /// Write the manifest here instead of to stdout; only for one file
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn digests_json(manifest: &str) -> Vec<serde_json::Value> {
+    manifest
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(digest, target)| json!({"target": target, "digest": digest}))
+        .collect()
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let algorithm = match args.algo {
+        Algo::Sha256 => Algorithm::Sha256,
+        Algo::Blake3 => Algorithm::Blake3,
+    };
+    if let [path] = args.paths.as_slice() {
+        if path.is_file() {
+            let manifest = create_manifest_with(path, algorithm, args.per_tensor)?;
+            if let Some(output) = &args.output {
+                std::fs::write(output, &manifest)?;
+            }
+            if ctx.structured() {
+                return ctx.print(&json!({
+                    "algorithm": algorithm.name(),
+                    "digests": digests_json(&manifest),
+                }));
+            }
+            if args.output.is_none() {
+                out!("{manifest}");
+            }
+            return Ok(());
+        }
+    }
+    if args.output.is_some() {
+        return Err("--output takes one file, a manifest covers only one".into());
+    }
+
+    // the shards of a split next to each other, each file's manifest under a `# path` comment
+    let mut files = Vec::new();
+    for model in models(expand(&args.paths)?) {
+        for (_, path) in model.shards {
+            let manifest = create_manifest_with(&path, algorithm, args.per_tensor)?;
+            if !ctx.structured() {
+                out!("# {}\n{manifest}", path.display());
+            }
+            files.push(json!({"path": path, "digests": digests_json(&manifest)}));
+        }
+    }
+    if ctx.structured() {
+        ctx.print(&json!({ "algorithm": algorithm.name(), "files": files }))?;
+    }
+    Ok(())
+}
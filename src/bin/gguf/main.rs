@@ -0,0 +1,474 @@
+//! Command line tool for inspecting and editing GGUF files
+use clap::{Parser, Subcommand, ValueEnum};
+#[cfg(feature = "remote")]
+use gguf::remote::{is_url, RemoteFile};
+use gguf::validate::Severity;
+use gguf::writer::WriteError;
+use gguf::{GGUFFile, ParseError, ParseOptions};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::process::ExitCode;
+
+/// `print!`, failing with the error of the write instead of panicking, as on a closed pipe
+macro_rules! out {
+    ($($arg:tt)*) => {{
+        use std::io::Write as _;
+        write!(std::io::stdout().lock(), $($arg)*)?
+    }};
+}
+
+/// `println!`, failing with the error of the write instead of panicking
+macro_rules! outln {
+    ($($arg:tt)*) => {{
+        use std::io::Write as _;
+        writeln!(std::io::stdout().lock(), $($arg)*)?
+    }};
+}
+
+mod cat;
+mod catalog;
+mod chat_template;
+mod convert;
+mod convert_ggml;
+mod diff;
+mod dump;
+mod estimate;
+mod export;
+mod extract;
+mod get;
+mod glob;
+mod hash;
+mod merge;
+mod merge_lora;
+mod modelcard;
+#[cfg(feature = "remote")]
+mod oci;
+mod ollama;
+#[cfg(feature = "zstd")]
+mod package;
+mod paths;
+mod quantize;
+mod regex;
+mod rename;
+mod rm_key;
+mod serve;
+mod set;
+mod set_chat_template;
+mod split;
+mod stats;
+mod strip;
+mod table;
+mod tensors;
+mod tokenize;
+mod top;
+mod tui;
+mod value;
+mod verify;
+mod vocab;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Read past spec deviations such as bad bools, invalid UTF-8 and duplicate keys
+    #[arg(long, global = true)]
+    lenient: bool,
+
+    /// Print the result as a JSON document instead of text
+    #[arg(long, global = true, conflicts_with = "yaml")]
+    json: bool,
+
+    /// Print the result as a YAML document instead of text
+    #[arg(long, global = true)]
+    yaml: bool,
+
+    /// Print plain text without colors, which are also left out when `NO_COLOR` is set or the
+    /// output is not a terminal
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Print no reports, findings or error messages, leaving the exit status to tell the result
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// The lowest severity of finding that fails `verify` and `set-chat-template --lint`
+    #[arg(long, global = true, value_enum, default_value_t = FailOn::Error)]
+    fail_on: FailOn,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the values of part of a tensor, decoded or as raw bytes
+    CatTensor(cat::Args),
+    /// Write a CSV or JSON Lines catalog of the metadata of many files, a row per model or per key
+    Catalog(catalog::Args),
+    /// Print the chat template, or the prompt it renders for sample messages
+    ChatTemplate(chat_template::Args),
+    /// Convert a safetensors checkpoint to GGUF
+    Convert(convert::Args),
+    /// Convert a legacy GGML, GGMF or GGJT LLaMA file from before GGUF
+    ConvertGgml(convert_ggml::Args),
+    /// Decode token ids into text with the file's tokenizer
+    Detokenize(tokenize::DetokenizeArgs),
+    /// Show the metadata and tensors that differ between two files
+    Diff(diff::Args),
+    /// Print the version, counts and metadata of a file
+    Dump(dump::Args),
+    /// Estimate the memory running a model takes: weights, KV cache and compute buffers
+    Estimate(estimate::Args),
+    /// Export the tensors to another format, such as safetensors
+    Export(export::Args),
+    /// Write the data of one tensor to a NumPy or raw file
+    ExtractTensor(extract::Args),
+    /// Print the value of one metadata key
+    Get(get::Args),
+    /// Merge the shards of a split into one file
+    Merge(merge::Args),
+    /// Bake the deltas of a LoRA adapter into a copy of its base model
+    MergeLora(merge_lora::Args),
+    /// Write a Markdown model card from the metadata, for publishing on model hubs
+    Modelcard(modelcard::Args),
+    /// Write an Ollama Modelfile with the chat template and stop tokens of a file
+    OllamaModelfile(ollama::Args),
+    /// Pack a file into a seekable zstd archive that the other commands read as they read it
+    #[cfg(feature = "zstd")]
+    Package(package::Args),
+    /// Download a GGUF file pushed to a container registry as an OCI artifact
+    #[cfg(feature = "remote")]
+    Pull(oci::PullArgs),
+    /// Upload a file to a container registry as an OCI artifact annotated from its metadata
+    #[cfg(feature = "remote")]
+    Push(oci::PushArgs),
+    /// Requantize a file to a llama.cpp quantization mix
+    Quantize(quantize::Args),
+    /// Rename a tensor, or every tensor matching a regular expression
+    RenameTensor(rename::Args),
+    /// Remove metadata keys, copying the tensor data to the new layout
+    RmKey(rm_key::Args),
+    /// Serve inspect, validate and diff as a JSON HTTP service
+    Serve(serve::Args),
+    /// Set metadata values, in place when the header keeps its size
+    Set(set::Args),
+    /// Store a chat template from a file, checking it parses and keeping the original safe
+    SetChatTemplate(set_chat_template::Args),
+    /// Split a file into shards of a maximum size, in the layout of llama.cpp's gguf-split
+    Split(split::Args),
+    /// Print the min, max, mean, standard deviation and NaN and infinity counts of tensors
+    Stats(stats::Args),
+    /// Write the header alone, a small stub with the metadata and tensor infos of a file
+    Strip(strip::Args),
+    /// Write a checksum manifest of the file and optionally each tensor
+    Hash(hash::Args),
+    /// List the tensors with their shape, type, size and offset
+    Tensors(tensors::Args),
+    /// Encode text with the file's tokenizer, printing each token id and piece
+    Tokenize(tokenize::TokenizeArgs),
+    /// Break the tensor data down by type and layer and list the largest tensors
+    Top(top::Args),
+    /// Browse the metadata, tensors and tokenizer of a file in an interactive terminal view
+    Tui(tui::Args),
+    /// Check a file against the GGUF spec, exiting with status 1 if it fails
+    Verify(verify::Args),
+    /// Export the tokenizer vocabulary as JSON, text or a Hugging Face tokenizer.json
+    Vocab(vocab::Args),
+}
+
+type E = Box<dyn std::error::Error>;
+
+/// A file that was read but failed a check, exiting with status 1
+#[derive(Debug)]
+pub struct Invalid(pub String);
+
+impl std::fmt::Display for Invalid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Invalid {}
+
+/// The exit status of a failed command: 1 when a check failed, 2 when a file is not valid GGUF,
+/// 3 when reading or writing failed and 4 for anything else, bad arguments included
+fn status(e: &(dyn std::error::Error + 'static)) -> u8 {
+    if e.is::<Invalid>() {
+        1
+    } else if let Some(e) = e.downcast_ref::<ParseError>() {
+        if matches!(e, ParseError::Io(_)) {
+            3
+        } else {
+            2
+        }
+    } else if let Some(e) = e.downcast_ref::<WriteError>() {
+        match e {
+            WriteError::Io(_) => 3,
+            WriteError::Parse(e) => status(e),
+            WriteError::Invalid(_) => 4,
+        }
+    } else if e.is::<std::io::Error>() {
+        3
+    } else {
+        4
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum FailOn {
+    Warn,
+    Error,
+}
+
+/// How results are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    Yaml,
+}
+
+/// The global options every command runs with
+pub struct Context {
+    pub options: ParseOptions,
+    format: Format,
+    pub quiet: bool,
+    pub style: table::Style,
+    /// The lowest severity of finding that fails a check
+    pub fail_on: Severity,
+}
+
+impl Context {
+    /// Whether to print a document with [`Context::print`] rather than text
+    pub fn structured(&self) -> bool {
+        self.format != Format::Text
+    }
+
+    /// Print a document as JSON or YAML, unless `--quiet` is given
+    pub fn print(&self, doc: &impl Serialize) -> Result<(), E> {
+        if self.quiet {
+            return Ok(());
+        }
+        match self.format {
+            Format::Yaml => out!("{}", serde_yaml::to_string(doc)?),
+            _ => outln!("{}", serde_json::to_string_pretty(doc)?),
+        }
+        Ok(())
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            // clap exits with 2 on bad arguments, which here means a file that does not parse
+            let _ = e.print();
+            return ExitCode::from(if e.use_stderr() { 4 } else { 0 });
+        }
+    };
+    let quiet = cli.quiet;
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        // the reader went away, as `head` does once it has its lines
+        Err(e) if is_broken_pipe(&*e) => ExitCode::SUCCESS,
+        Err(e) => {
+            if !quiet {
+                eprintln!("Error: {e}");
+            }
+            ExitCode::from(status(&*e))
+        }
+    }
+}
+
+fn is_broken_pipe(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe)
+}
+
+fn run(cli: Cli) -> Result<(), E> {
+    let options = if cli.lenient {
+        ParseOptions::lenient()
+    } else {
+        ParseOptions::strict()
+    };
+    let format = match (cli.json, cli.yaml) {
+        (true, _) => Format::Json,
+        (_, true) => Format::Yaml,
+        _ => Format::Text,
+    };
+    let ctx = Context {
+        options,
+        format,
+        quiet: cli.quiet,
+        style: table::Style::detect(cli.no_color),
+        fail_on: match cli.fail_on {
+            FailOn::Warn => Severity::Warning,
+            FailOn::Error => Severity::Error,
+        },
+    };
+    match cli.command {
+        Command::CatTensor(args) => cat::run(&args, &ctx),
+        Command::Catalog(args) => catalog::run(&args, &ctx),
+        Command::ChatTemplate(args) => chat_template::run(&args, &ctx),
+        Command::Convert(args) => convert::run(&args, &ctx),
+        Command::ConvertGgml(args) => convert_ggml::run(&args, &ctx),
+        Command::Detokenize(args) => tokenize::detokenize(&args, &ctx),
+        Command::Diff(args) => diff::run(&args, &ctx),
+        Command::Dump(args) => dump::run(&args, &ctx),
+        Command::Estimate(args) => estimate::run(&args, &ctx),
+        Command::Export(args) => export::run(&args, &ctx),
+        Command::ExtractTensor(args) => extract::run(&args, &ctx),
+        Command::Get(args) => get::run(&args, &ctx),
+        Command::Hash(args) => hash::run(&args, &ctx),
+        Command::Merge(args) => merge::run(&args, &ctx),
+        Command::MergeLora(args) => merge_lora::run(&args, &ctx),
+        Command::Modelcard(args) => modelcard::run(&args, &ctx),
+        Command::OllamaModelfile(args) => ollama::run(&args, &ctx),
+        #[cfg(feature = "zstd")]
+        Command::Package(args) => package::run(&args, &ctx),
+        #[cfg(feature = "remote")]
+        Command::Pull(args) => oci::run_pull(&args, &ctx),
+        #[cfg(feature = "remote")]
+        Command::Push(args) => oci::run_push(&args, &ctx),
+        Command::Quantize(args) => quantize::run(&args, &ctx),
+        Command::RenameTensor(args) => rename::run(&args, &ctx),
+        Command::RmKey(args) => rm_key::run(&args, &ctx),
+        Command::Serve(args) => serve::run(&args, &ctx),
+        Command::Set(args) => set::run(&args, &ctx),
+        Command::SetChatTemplate(args) => set_chat_template::run(&args, &ctx),
+        Command::Split(args) => split::run(&args, &ctx),
+        Command::Stats(args) => stats::run(&args, &ctx),
+        Command::Strip(args) => strip::run(&args, &ctx),
+        Command::Tensors(args) => tensors::run(&args, &ctx),
+        Command::Tokenize(args) => tokenize::tokenize(&args, &ctx),
+        Command::Top(args) => top::run(&args, &ctx),
+        Command::Tui(args) => tui::run(&args, &ctx),
+        Command::Verify(args) => verify::run(&args, &ctx),
+        Command::Vocab(args) => vocab::run(&args, &ctx),
+    }
+}
+
+/// A file to read tensor data from
+trait Input: Read + Seek {}
+
+impl<T: Read + Seek> Input for T {}
+
+/// A local file, an `http://` or `https://` URL read with range requests with the `remote`
+/// feature, or `ollama:NAME`, a model pulled with Ollama
+fn input(path: &Path) -> Result<Box<dyn Input>, E> {
+    if let Some(name) = path.to_str().and_then(|p| p.strip_prefix("ollama:")) {
+        let blob = gguf::ollama::blob_path(&gguf::ollama::models_dir()?, name)?;
+        return Ok(Box::new(File::open(blob)?));
+    }
+    #[cfg(feature = "zstd")]
+    if path.extension().is_some_and(|e| e == "zst") {
+        let archive = gguf::package::PackageReader::new(File::open(path)?)?;
+        return Ok(Box::new(archive));
+    }
+    #[cfg(feature = "remote")]
+    if let Some(url) = path.to_str().filter(|p| is_url(p)) {
+        return Ok(Box::new(
+            RemoteFile::open(url).map_err(std::io::Error::other)?,
+        ));
+    }
+    #[cfg(not(feature = "remote"))]
+    if path
+        .to_str()
+        .is_some_and(|p| p.starts_with("http://") || p.starts_with("https://"))
+    {
+        return Err("reading a URL needs the `remote` feature".into());
+    }
+    Ok(Box::new(File::open(path)?))
+}
+
+/// Read the header and tensor infos of a file or URL
+fn open(path: &Path, options: &ParseOptions) -> Result<GGUFFile, E> {
+    Ok(GGUFFile::read_from(&mut input(path)?, options)?.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_statuses() {
+        let cases: [(E, u8); 5] = [
+            (Invalid("failed".into()).into(), 1),
+            (ParseError::Invalid("bad magic".into()).into(), 2),
+            (ParseError::Io("truncated".into()).into(), 3),
+            (std::io::Error::other("denied").into(), 3),
+            ("no such key".into(), 4),
+        ];
+        for (e, expected) in cases {
+            assert_eq!(status(&*e), expected, "{e}");
+        }
+    }
+
+    /// The exit status of `gguf --quiet args...`
+    fn exit_status(args: &[&str]) -> u8 {
+        let cli = Cli::try_parse_from(["gguf", "--quiet"].iter().chain(args)).unwrap();
+        run(cli).map_or_else(|e| status(&*e), |()| 0)
+    }
+
+    #[test]
+    fn exit_statuses_of_commands() {
+        use gguf::{GGMLType, GGUFHeader, GGUFMetadata, GGUFMetadataValue, GGUFTensorInfo};
+
+        let dir = std::env::temp_dir().join(format!("gguf-exit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = GGUFFile {
+            header: GGUFHeader {
+                version: 3,
+                tensor_count: 1,
+                metadata: vec![GGUFMetadata::new(
+                    "general.name",
+                    GGUFMetadataValue::String("tiny".to_string()),
+                )],
+            },
+            tensors: vec![GGUFTensorInfo {
+                name: "output.weight".to_string(),
+                dimensions: vec![32].into(),
+                tensor_type: GGMLType::F32,
+                offset: 0,
+            }],
+        };
+        let good = dir.join("good.gguf");
+        gguf::writer::write_file(&good, &file, |_, out| Ok(out.write_all(&[0; 128])?)).unwrap();
+        let short = dir.join("short.gguf");
+        std::fs::copy(&good, &short).unwrap();
+        let len = std::fs::metadata(&short).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&short)
+            .unwrap()
+            .set_len(len - 64)
+            .unwrap();
+        let junk = dir.join("junk.gguf");
+        std::fs::write(&junk, b"not a gguf file").unwrap();
+        let (good, short, junk) = (
+            good.to_str().unwrap(),
+            short.to_str().unwrap(),
+            junk.to_str().unwrap(),
+        );
+        let missing = "/nonexistent/out.gguf";
+
+        assert_eq!(exit_status(&["verify", good]), 0);
+        assert_eq!(exit_status(&["verify", short]), 1);
+        assert_eq!(exit_status(&["dump", junk]), 2);
+        assert_eq!(
+            exit_status(&["rm-key", good, "general.name", "-o", missing]),
+            3
+        );
+        assert_eq!(
+            exit_status(&["export", good, "-o", "/nonexistent/out.safetensors"]),
+            3
+        );
+        assert_eq!(
+            exit_status(&["extract-tensor", good, "output.weight", "-o", missing]),
+            3
+        );
+        assert_eq!(exit_status(&["rm-key", good, "general.license"]), 4);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
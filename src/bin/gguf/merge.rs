@@ -0,0 +1,28 @@
+use gguf::split::merge;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{open, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// Any shard of the split, e.g. `model-00001-of-00004.gguf`
+    path: PathBuf,
+
+    /// The merged file to write
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    merge(&args.path, &args.output)?;
+    if ctx.structured() {
+        let merged = open(&args.output, &ctx.options)?;
+        ctx.print(&json!({
+            "output": args.output,
+            "tensor_count": merged.tensors.len(),
+            "bytes": std::fs::metadata(&args.output)?.len(),
+        }))?;
+    }
+    Ok(())
+}
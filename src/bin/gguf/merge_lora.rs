@@ -0,0 +1,37 @@
+use gguf::lora::merge_lora;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The base model
+    base: PathBuf,
+
+    /// The adapter, a GGUF file of `general.type` adapter
+    adapter: PathBuf,
+
+    /// The merged model to write
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// How strongly to apply the adapter, as llama.cpp's `--lora-scaled`
+    #[arg(long, default_value_t = 1.0)]
+    scale: f32,
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let merged = merge_lora(&args.base, &args.adapter, args.scale, &args.output)?;
+    if ctx.structured() {
+        return ctx.print(&json!({"output": args.output, "merged": merged}));
+    }
+    if !ctx.quiet {
+        outln!(
+            "merged {} tensors into {}",
+            merged.len(),
+            args.output.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use gguf::chat_template::ChatTemplate;
+use gguf::ggml::LlamaFileType;
+use gguf::tokenizer::Vocab;
+use gguf::{GGMLType, GGUFFile, GGUFMetadataValue};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::{Seek, SeekFrom};
+use std::path::PathBuf;
+
+use crate::{input, table, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// Write the card here instead of to stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// A parameter count as model names give it, e.g. `6.74B` or `124M`
+fn params(n: u64) -> String {
+    match n {
+        1_000_000_000.. => format!("{:.2}B", n as f64 / 1e9),
+        1_000_000.. => format!("{:.0}M", n as f64 / 1e6),
+        1_000.. => format!("{:.0}K", n as f64 / 1e3),
+        _ => n.to_string(),
+    }
+}
+
+/// `text` safe to put in a table cell
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// The quantization named by `general.file_type`, or else the type holding the most bytes
+fn quantization(file: &GGUFFile) -> Option<String> {
+    match file.header.file_type() {
+        None | Some(LlamaFileType::Unknown(_) | LlamaFileType::GUESSED) => {}
+        Some(file_type) => return Some(file_type.to_string()),
+    }
+    let mut bytes: BTreeMap<String, u64> = BTreeMap::new();
+    for tensor in &file.tensors {
+        if tensor.dimensions.len() > 1 || tensor.tensor_type != GGMLType::F32 {
+            *bytes
+                .entry(format!("{:?}", tensor.tensor_type))
+                .or_default() += tensor.size_bytes().unwrap_or_default();
+        }
+    }
+    bytes.into_iter().max_by_key(|(_, b)| *b).map(|(t, _)| t)
+}
+
+/// A Markdown model card for `file`, named `file_name`, with Hugging Face front matter
+pub fn card(file: &GGUFFile, file_name: &str, file_len: Option<u64>) -> String {
+    let header = &file.header;
+    let text = |key: &str| header.get(key).and_then(GGUFMetadataValue::as_str);
+    let arch = text("general.architecture");
+    let number = |key: &str| {
+        let key = format!("{}.{key}", arch.unwrap_or_default());
+        header.get(&key).and_then(|v| v.as_u64())
+    };
+    let name = text("general.name").unwrap_or(file_name.trim_end_matches(".gguf"));
+    let mut out = gguf::hub::frontmatter(file);
+    // the tags go before the closing line
+    out.truncate(out.len() - "---\n".len());
+    let _ = writeln!(out, "tags:\n- gguf");
+    if let Some(arch) = arch {
+        let _ = writeln!(out, "- {arch}");
+    }
+    let _ = writeln!(out, "---\n\n# {name}\n");
+    if let Some(description) = text("general.description") {
+        let _ = writeln!(out, "{description}\n");
+    }
+
+    let mut details = Vec::new();
+    let mut row = |field: &str, value: Option<String>| {
+        if let Some(value) = value {
+            details.push(format!("| {field} | {} |", cell(&value)));
+        }
+    };
+    row("Architecture", arch.map(str::to_string));
+    let count: u64 = file.tensors.iter().map(|t| t.element_count()).sum();
+    row(
+        "Parameters",
+        Some(format!("{} ({})", params(count), table::count(count))),
+    );
+    row("Quantization", quantization(file));
+    row("Context length", number("context_length").map(table::count));
+    row(
+        "Embedding length",
+        number("embedding_length").map(table::count),
+    );
+    row("Layers", number("block_count").map(table::count));
+    row(
+        "Decoder layers",
+        number("decoder_block_count").map(table::count),
+    );
+    row("Mel bins", number("audio.mel_bins").map(table::count));
+    if let Some(embedding) = gguf::embedding::EmbeddingConfig::of(header) {
+        row("Pooling", embedding.pooling.map(|p| p.to_string()));
+        row("Embedding dimension", embedding.dimension.map(table::count));
+    }
+    row(
+        "Attention heads",
+        number("attention.head_count").map(|heads| match number("attention.head_count_kv") {
+            Some(kv) if kv != heads => format!("{heads} ({kv} key-value)"),
+            _ => heads.to_string(),
+        }),
+    );
+    row("File size", file_len.map(table::size));
+    row("License", text("general.license").map(str::to_string));
+    row("Author", text("general.author").map(str::to_string));
+    row(
+        "Organization",
+        text("general.organization").map(str::to_string),
+    );
+    row("Source", text("general.source.url").map(str::to_string));
+    let _ = writeln!(out, "## Model details\n\n| | |\n|---|---|");
+    details.iter().for_each(|line| {
+        let _ = writeln!(out, "{line}");
+    });
+
+    if let Ok(vocab) = Vocab::from_header(header) {
+        let special: Vec<String> = vocab
+            .special
+            .named()
+            .into_iter()
+            .filter_map(|(role, id)| {
+                let id = id?;
+                Some(format!("{role} `{}` ({id})", vocab.token(id)?))
+            })
+            .collect();
+        let templates = ChatTemplate::names(header);
+        let _ = writeln!(out, "\n## Tokenizer\n\n| | |\n|---|---|");
+        let _ = writeln!(out, "| Model | {} |", vocab.model.name());
+        if let Some(pre) = text("tokenizer.ggml.pre") {
+            let _ = writeln!(out, "| Pre-tokenizer | {} |", cell(pre));
+        }
+        let _ = writeln!(
+            out,
+            "| Vocabulary | {} tokens |",
+            table::count(vocab.len() as u64)
+        );
+        if !special.is_empty() {
+            let _ = writeln!(out, "| Special tokens | {} |", cell(&special.join(", ")));
+        }
+        let templates = match templates.as_slice() {
+            [] => "none".to_string(),
+            names => names.join(", "),
+        };
+        let _ = writeln!(out, "| Chat templates | {} |", cell(&templates));
+    }
+
+    let _ = writeln!(
+        out,
+        "\n## Usage\n\n```bash\nllama-cli -m {file_name} -cnv\n```"
+    );
+    out
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let mut input = input(&args.path)?;
+    let (file, _) = GGUFFile::read_from(&mut input, &ctx.options)?;
+    let file_len = input.seek(SeekFrom::End(0))?;
+    let file_name = args
+        .path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let card = card(&file, &file_name, Some(file_len));
+    match &args.output {
+        Some(output) => std::fs::write(output, &card)?,
+        None if ctx.structured() => return ctx.print(&json!({ "card": card })),
+        None => out!("{card}"),
+    }
+    if ctx.structured() {
+        ctx.print(&json!({ "output": args.output, "bytes": card.len() }))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gguf::{GGUFHeader, GGUFMetadata, GGUFTensorInfo};
+
+    #[test]
+    fn card_from_metadata() {
+        let string = |s: &str| GGUFMetadataValue::String(s.to_string());
+        let metadata = vec![
+            GGUFMetadata::new("general.architecture", string("llama")),
+            GGUFMetadata::new("general.name", string("Tiny | Chat")),
+            GGUFMetadata::new("general.license", string("apache-2.0")),
+            GGUFMetadata::new("general.file_type", GGUFMetadataValue::Uint32(15)),
+            GGUFMetadata::new("llama.context_length", GGUFMetadataValue::Uint32(8192)),
+            GGUFMetadata::new("llama.attention.head_count", GGUFMetadataValue::Uint32(32)),
+            GGUFMetadata::new(
+                "llama.attention.head_count_kv",
+                GGUFMetadataValue::Uint32(8),
+            ),
+        ];
+        let file = GGUFFile {
+            header: GGUFHeader {
+                version: 3,
+                tensor_count: 1,
+                metadata,
+            },
+            tensors: vec![GGUFTensorInfo {
+                name: "token_embd.weight".to_string(),
+                dimensions: gguf::smallvec![4096, 32000],
+                tensor_type: GGMLType::Q4K,
+                offset: 0,
+            }],
+        };
+        let card = card(&file, "tiny.gguf", Some(3 << 30));
+        assert!(card.starts_with(
+            "---\nlicense: apache-2.0\npipeline_tag: text-generation\nlibrary_name: gguf\n\
+             tags:\n- gguf\n- llama\n---\n"
+        ));
+        assert!(card.contains("# Tiny | Chat\n"));
+        assert!(card.contains("| Parameters | 131M (131,072,000) |"));
+        assert!(card.contains("| Quantization | Q4_K_M |"));
+        assert!(card.contains("| Context length | 8,192 |"));
+        assert!(card.contains("| Attention heads | 32 (8 key-value) |"));
+        assert!(card.contains("| File size | 3.00 GiB |"));
+        assert!(!card.contains("## Tokenizer"));
+    }
+}
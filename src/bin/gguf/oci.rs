@@ -0,0 +1,73 @@
+use gguf::oci::{pull, push, Artifact, Credentials, Reference};
+use std::path::PathBuf;
+
+use crate::{Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct PushArgs {
+    /// The file to push
+    path: PathBuf,
+
+    /// Where to push it, e.g. `ghcr.io/org/model:q4_k_m`
+    reference: String,
+
+    /// The user to log in as, with the password or token in `GGUF_REGISTRY_PASSWORD`
+    #[arg(short, long)]
+    username: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct PullArgs {
+    /// The artifact to pull, e.g. `ghcr.io/org/model:q4_k_m`
+    reference: String,
+
+    /// The file to write
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// The user to log in as, with the password or token in `GGUF_REGISTRY_PASSWORD`
+    #[arg(short, long)]
+    username: Option<String>,
+}
+
+fn credentials(username: &Option<String>) -> Result<Option<Credentials>, E> {
+    let Some(username) = username else {
+        return Ok(None);
+    };
+    let password = std::env::var("GGUF_REGISTRY_PASSWORD")
+        .map_err(|_| "--username needs the password in GGUF_REGISTRY_PASSWORD")?;
+    Ok(Some(Credentials {
+        username: username.clone(),
+        password,
+    }))
+}
+
+fn report(artifact: &Artifact, reference: &Reference, ctx: &Context) -> Result<(), E> {
+    if ctx.structured() {
+        return ctx.print(&serde_json::json!({
+            "reference": reference.to_string(),
+            "manifest_digest": artifact.manifest_digest,
+            "layer_digest": artifact.layer_digest,
+            "size": artifact.size,
+            "annotations": artifact.annotations,
+        }));
+    }
+    if !ctx.quiet {
+        outln!("{reference}@{}", artifact.manifest_digest);
+    }
+    Ok(())
+}
+
+pub fn run_push(args: &PushArgs, ctx: &Context) -> Result<(), E> {
+    let reference = Reference::parse(&args.reference)?;
+    let credentials = credentials(&args.username)?;
+    let artifact = push(&args.path, &reference, credentials.as_ref())?;
+    report(&artifact, &reference, ctx)
+}
+
+pub fn run_pull(args: &PullArgs, ctx: &Context) -> Result<(), E> {
+    let reference = Reference::parse(&args.reference)?;
+    let credentials = credentials(&args.username)?;
+    let artifact = pull(&reference, &args.output, credentials.as_ref())?;
+    report(&artifact, &reference, ctx)
+}
@@ -0,0 +1,178 @@
+use gguf::chat_template::{ChatMessage, ChatTemplate};
+use gguf::tokenizer::Vocab;
+use serde_json::json;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use crate::{open, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file to write a Modelfile for
+    path: PathBuf,
+
+    /// Convert this named chat template variant instead of the default one
+    #[arg(long)]
+    template: Option<String>,
+
+    /// Write the Modelfile here instead of to stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+// message contents the renders are split at; a template never writes these itself
+const SYSTEM: &str = "\u{1}system\u{1}";
+const USER: &str = "\u{1}user\u{1}";
+const RESPONSE: &str = "\u{1}response\u{1}";
+
+/// `text` split around the first `marker`
+fn around<'a>(text: &'a str, marker: &str) -> Result<(&'a str, &'a str), String> {
+    text.split_once(marker)
+        .ok_or_else(|| "the template does not render the message contents as given".to_string())
+}
+
+/// The chat template as an Ollama Go template, found by rendering it with placeholder messages
+/// and cutting the prompts at them
+///
+/// Only templates that render every turn the same way convert; the bos token is left out, as
+/// Ollama adds it.
+pub fn go_template(template: &ChatTemplate, bos: &str) -> Result<String, String> {
+    let user = ChatMessage::new("user", USER);
+    let (prefix, generation) = around(&template.render(std::slice::from_ref(&user), true)?, USER)
+        .map(|(p, g)| (p.to_string(), g.to_string()))?;
+    let answered = template.render(
+        &[user.clone(), ChatMessage::new("assistant", RESPONSE)],
+        false,
+    )?;
+    let (_, end_of_response) = around(&answered, RESPONSE)?;
+
+    // the system block is what a system message adds between the common start and the user
+    // prefix; templates that refuse system messages get none
+    let system = match template.render(&[ChatMessage::new("system", SYSTEM), user], true) {
+        Ok(with_system) => {
+            let (before_user, rest) = around(&with_system, USER)?;
+            if rest != generation {
+                return Err("a system message changes how the user turn ends".to_string());
+            }
+            let (before_system, _) = around(before_user, SYSTEM)?;
+            // the shortest shared start, such as the bos token, after which the user turn
+            // starts as it does without a system message
+            let common = (0..=before_system.len().min(prefix.len()))
+                .filter(|&i| prefix.is_char_boundary(i))
+                .take_while(|&i| before_system.as_bytes()[..i] == prefix.as_bytes()[..i])
+                .find(|&i| before_user[i..].ends_with(&prefix[i..]))
+                .ok_or("a system message changes how the user turn starts")?;
+            let block = &before_user[common..before_user.len() - (prefix.len() - common)];
+            Some((common, block.replacen(SYSTEM, "{{ .System }}", 1)))
+        }
+        Err(_) => None,
+    };
+    let common = system.as_ref().map_or(0, |(common, _)| *common);
+    let mut go = prefix[..common]
+        .strip_prefix(bos)
+        .unwrap_or(&prefix[..common])
+        .to_string();
+    if let Some((_, block)) = &system {
+        let _ = write!(go, "{{{{ if .System }}}}{block}{{{{ end }}}}");
+    }
+    let _ = write!(
+        go,
+        "{{{{ if .Prompt }}}}{}{{{{ .Prompt }}}}{{{{ end }}}}{generation}{{{{ .Response }}}}{end_of_response}",
+        &prefix[common..]
+    );
+    let literal = go
+        .replace("{{ .System }}", "")
+        .replace("{{ .Prompt }}", "")
+        .replace("{{ .Response }}", "")
+        .replace("{{ if .System }}", "")
+        .replace("{{ if .Prompt }}", "")
+        .replace("{{ end }}", "");
+    if literal.contains("{{") || go.contains("\"\"\"") || literal.contains('\u{1}') {
+        return Err("the prompt text cannot be written as a Modelfile template".to_string());
+    }
+    Ok(go)
+}
+
+/// Writes `FROM`, the chat template converted to Ollama's Go template syntax when it converts,
+/// and a `PARAMETER stop` for every token that ends a turn
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let file = open(&args.path, &ctx.options)?;
+    let vocab = Vocab::from_header(&file.header).ok();
+    let template = ChatTemplate::from_header(&file.header, args.template.as_deref())?;
+    if let (Some(name), None) = (&args.template, &template) {
+        return Err(format!("there is no chat template named {name}").into());
+    }
+    let bos = vocab
+        .as_ref()
+        .and_then(|v| v.token(v.special.bos?))
+        .unwrap_or_default();
+    let converted = template.as_ref().map(|t| go_template(t, bos));
+    let stop: Vec<&str> = vocab
+        .iter()
+        .flat_map(|v| v.stop_token_ids().into_iter().filter_map(|id| v.token(id)))
+        .collect();
+
+    let mut modelfile = format!("FROM {}\n", args.path.display());
+    match &converted {
+        Some(Ok(go)) => {
+            let _ = writeln!(modelfile, "TEMPLATE \"\"\"{go}\"\"\"");
+        }
+        Some(Err(e)) => {
+            eprintln!("the chat template was not converted: {e}");
+            let _ = writeln!(
+                modelfile,
+                "# the chat template could not be converted ({e}); Ollama falls back to its own"
+            );
+        }
+        None => {}
+    }
+    for token in &stop {
+        let _ = writeln!(modelfile, "PARAMETER stop {token:?}");
+    }
+
+    match &args.output {
+        Some(output) => std::fs::write(output, &modelfile)?,
+        None if ctx.structured() => {
+            return ctx.print(&json!({
+                "from": args.path,
+                "template": converted.and_then(Result::ok),
+                "stop": stop,
+                "modelfile": modelfile,
+            }))
+        }
+        None => out!("{modelfile}"),
+    }
+    if ctx.structured() {
+        ctx.print(&json!({
+            "output": args.output,
+            "template": matches!(converted, Some(Ok(_))),
+            "stop": stop,
+        }))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_chatml_and_llama2() {
+        let chatml = ChatTemplate::new("{% for message in messages %}{{ '<|im_start|>' + message['role'] + '\n' + message['content'] + '<|im_end|>' + '\n' }}{% endfor %}{% if add_generation_prompt %}{{ '<|im_start|>assistant\n' }}{% endif %}").unwrap();
+        assert_eq!(
+            go_template(&chatml, "").unwrap(),
+            "{{ if .System }}<|im_start|>system\n{{ .System }}<|im_end|>\n{{ end }}\
+             {{ if .Prompt }}<|im_start|>user\n{{ .Prompt }}{{ end }}<|im_end|>\n<|im_start|>assistant\n\
+             {{ .Response }}<|im_end|>\n"
+        );
+
+        let llama2 = ChatTemplate::new("{{ bos_token }}{% for message in messages %}{% if message['role'] == 'system' %}{{ '<<SYS>>\n' + message['content'] + '\n<</SYS>>\n\n' }}{% elif message['role'] == 'user' %}{{ '[INST] ' + message['content'] + ' [/INST]' }}{% else %}{{ message['content'] + eos_token }}{% endif %}{% endfor %}")
+            .unwrap()
+            .with_special_tokens("<s>", "</s>");
+        assert_eq!(
+            go_template(&llama2, "<s>").unwrap(),
+            "{{ if .System }}<<SYS>>\n{{ .System }}\n<</SYS>>\n\n{{ end }}\
+             {{ if .Prompt }}[INST] {{ .Prompt }}{{ end }} [/INST]{{ .Response }}</s>"
+        );
+    }
+}
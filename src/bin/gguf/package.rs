@@ -0,0 +1,51 @@
+use gguf::package::{pack, PackageOptions};
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{table, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file to pack
+    path: PathBuf,
+
+    /// The archive to write, by convention `model.gguf.zst`
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// The zstd compression level, 1 to 22
+    #[arg(long, default_value_t = PackageOptions::default().level)]
+    level: i32,
+
+    /// The largest frame in bytes, the most a read decompresses beyond what it needs
+    #[arg(long, default_value_t = PackageOptions::default().max_frame_size)]
+    frame_size: u32,
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let options = PackageOptions {
+        level: args.level,
+        max_frame_size: args.frame_size,
+    };
+    let seek_table = pack(&args.path, &args.output, &options)?;
+    let packed = std::fs::metadata(&args.output)?.len();
+    let original = seek_table.decompressed_len();
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "output": args.output,
+            "frames": seek_table.frames.len(),
+            "bytes": original,
+            "packed_bytes": packed,
+        }));
+    }
+    if !ctx.quiet {
+        eprintln!(
+            "packed {} into {} frames of {}, {:.1}% of the original",
+            table::size(original),
+            seek_table.frames.len(),
+            table::size(packed),
+            packed as f64 * 100.0 / original.max(1) as f64
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,205 @@
+//! Expanding path arguments into files, and grouping the shards of split models
+use gguf::split::split_prefix;
+use std::path::{Path, PathBuf};
+
+use crate::{glob, E};
+
+/// One model: a single file, or the shards of a split found among the paths
+#[derive(Debug, PartialEq)]
+pub struct Model {
+    /// The file, or the shard prefix of a split
+    pub name: String,
+    /// The shards found, in order, with their index; just the file, as shard 0, for one file
+    pub shards: Vec<(u16, PathBuf)>,
+    /// How many shards the split has, `None` for one file
+    pub count: Option<u16>,
+}
+
+impl Model {
+    /// The shards of the split that are not among the paths, counting from 0
+    pub fn missing(&self) -> Vec<u16> {
+        (0..self.count.unwrap_or_default())
+            .filter(|no| !self.shards.iter().any(|(n, _)| n == no))
+            .collect()
+    }
+}
+
+fn has_wildcards(text: &str) -> bool {
+    text.contains(['*', '?', '['])
+}
+
+/// the `.gguf` files under `dir`, sorted
+fn walk(dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), E> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            walk(&path, out)?;
+        } else if path.extension().is_some_and(|e| e == "gguf") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// the paths under `base` matching the remaining pattern components, `**` matching any number
+/// of directories
+fn glob_from(base: PathBuf, parts: &[String], out: &mut Vec<PathBuf>) -> Result<(), E> {
+    let Some((part, rest)) = parts.split_first() else {
+        out.push(base);
+        return Ok(());
+    };
+    if !has_wildcards(part) {
+        let path = base.join(part);
+        if path.exists() {
+            glob_from(path, rest, out)?;
+        }
+        return Ok(());
+    }
+    let dir = if base.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        &base
+    };
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let mut entries: Vec<_> = std::fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    if part == "**" {
+        glob_from(base.clone(), rest, out)?;
+        for name in entries.iter().filter(|name| !name.starts_with('.')) {
+            if base.join(name).is_dir() {
+                glob_from(base.join(name), parts, out)?;
+            }
+        }
+        return Ok(());
+    }
+    for name in entries {
+        if glob::matches(part, &name) && (!name.starts_with('.') || part.starts_with('.')) {
+            glob_from(base.join(name), rest, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// The files named by `args`: files as they are, every `.gguf` file under a directory, and the
+/// files matching a pattern such as `models/**/*.gguf`, each once in order
+///
+/// Patterns are expanded here so they work when the shell leaves them alone, as it does for
+/// `**` without `globstar` or when quoted.
+pub fn expand(args: &[PathBuf]) -> Result<Vec<PathBuf>, E> {
+    let mut files = Vec::new();
+    for arg in args {
+        let text = arg.to_string_lossy();
+        let mut found = Vec::new();
+        if arg.is_dir() {
+            walk(arg, &mut found)?;
+        } else if !arg.exists() && has_wildcards(&text) {
+            let mut base = PathBuf::new();
+            let mut parts = Vec::new();
+            for component in arg.components() {
+                let part = component.as_os_str().to_string_lossy();
+                if parts.is_empty() && !has_wildcards(&part) {
+                    base.push(component);
+                } else {
+                    parts.push(part.into_owned());
+                }
+            }
+            glob_from(base, &parts, &mut found)?;
+            found.retain(|path| path.is_file());
+        } else {
+            found.push(arg.clone());
+        }
+        if found.is_empty() {
+            return Err(format!("{text} matches no GGUF files").into());
+        }
+        for path in found {
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Group the shards of each split into one model, keeping the order the first of each appears
+pub fn models(files: Vec<PathBuf>) -> Vec<Model> {
+    let mut models: Vec<Model> = Vec::new();
+    for path in files {
+        let name = path.to_string_lossy().into_owned();
+        let Some((prefix, no, count)) = split_prefix(&name) else {
+            models.push(Model {
+                name,
+                shards: vec![(0, path)],
+                count: None,
+            });
+            continue;
+        };
+        let prefix = prefix.to_string();
+        match models
+            .iter_mut()
+            .find(|m| m.name == prefix && m.count == Some(count))
+        {
+            Some(model) => model.shards.push((no, path)),
+            None => models.push(Model {
+                name: prefix,
+                shards: vec![(no, path)],
+                count: Some(count),
+            }),
+        }
+    }
+    for model in &mut models {
+        model.shards.sort();
+    }
+    models
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_and_shard_groups() {
+        let dir = std::env::temp_dir().join(format!("gguf-paths-globs-{}", std::process::id()));
+        for file in [
+            "a.gguf",
+            "notes.txt",
+            "sub/b-00002-of-00003.gguf",
+            "sub/b-00001-of-00003.gguf",
+            "sub/deeper/c.gguf",
+        ] {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+        let pattern = dir.join("**").join("*.gguf");
+        let files = expand(&[pattern, dir.join("a.gguf")]).unwrap();
+        let names: Vec<_> = files
+            .iter()
+            .map(|p| p.strip_prefix(&dir).unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "a.gguf",
+                "sub/b-00001-of-00003.gguf",
+                "sub/b-00002-of-00003.gguf",
+                "sub/deeper/c.gguf"
+            ]
+        );
+        assert_eq!(expand(std::slice::from_ref(&dir)).unwrap().len(), 4);
+        assert!(expand(&[dir.join("*.bin")]).is_err());
+
+        let models = models(files);
+        assert_eq!(models.len(), 3);
+        assert_eq!(models[1].shards.len(), 2);
+        assert_eq!(models[1].missing(), [2]);
+        assert!(models[0].missing().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
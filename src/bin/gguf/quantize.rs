@@ -0,0 +1,89 @@
+use gguf::ggml::LlamaFileType;
+use gguf::quant::{mixes, parse_type, quantize_file, QuantizeOptions};
+use gguf::GGMLType;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{open, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file to quantize
+    path: PathBuf,
+
+    /// The llama.cpp mix, e.g. `Q4_K_M`
+    #[arg(value_parser = parse_file_type)]
+    file_type: LlamaFileType,
+
+    /// The file to write
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Give a tensor its own type, e.g. `output.weight=Q6_K`; may be repeated
+    #[arg(long, value_parser = parse_keep)]
+    keep: Vec<(String, GGMLType)>,
+
+    /// Threads per tensor, by default one per core
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+fn parse_file_type(name: &str) -> Result<LlamaFileType, String> {
+    LlamaFileType::from_name(name)
+        .filter(|t| mixes().any(|m| m == *t))
+        .ok_or_else(|| {
+            let names: Vec<_> = mixes().map(|t| t.to_string()).collect();
+            format!("unknown file type {name}, use one of {}", names.join(", "))
+        })
+}
+
+fn parse_keep(text: &str) -> Result<(String, GGMLType), String> {
+    let (name, tensor_type) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=TYPE, found {text}"))?;
+    let tensor_type =
+        parse_type(tensor_type).ok_or_else(|| format!("unknown tensor type {tensor_type}"))?;
+    Ok((name.to_string(), tensor_type))
+}
+
+/// Prints progress to stderr, and with `--json` or `--yaml` the new type of each tensor to stdout
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let mut quantize = QuantizeOptions::new(args.file_type);
+    quantize.overrides = args.keep.clone();
+    if let Some(threads) = args.threads {
+        quantize.threads = threads;
+    }
+    let total = open(&args.path, &ctx.options)?.tensors.len();
+    let width = total.to_string().len();
+    let mut tensors = Vec::new();
+    quantize_file(&args.path, &args.output, &quantize, |i, from, to| {
+        tensors.push(json!({"name": to.name, "from": from.tensor_type, "to": to.tensor_type}));
+        eprintln!(
+            "[{:>width$}/{total}] {:<32} {:?} -> {:?}",
+            i + 1,
+            from.name,
+            from.tensor_type,
+            to.tensor_type
+        );
+    })?;
+    let (before, after) = (
+        std::fs::metadata(&args.path)?.len(),
+        std::fs::metadata(&args.output)?.len(),
+    );
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "output": args.output,
+            "file_type": args.file_type,
+            "bytes_before": before,
+            "bytes_after": after,
+            "tensors": tensors,
+        }));
+    }
+    eprintln!(
+        "wrote {}: {:.1} MiB, down from {:.1} MiB",
+        args.output.display(),
+        after as f64 / 1048576.0,
+        before as f64 / 1048576.0
+    );
+    Ok(())
+}
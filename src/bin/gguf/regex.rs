@@ -0,0 +1,465 @@
+//! A small backtracking regular expression engine for renaming: literals, `.`, classes such as
+//! `[a-z_]` and `[^.]`, `\d`, `\w` and `\s`, groups, `|`, the quantifiers `*`, `+`, `?` and
+//! `{n,m}` with lazy `?` forms, and the anchors `^` and `$`
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    /// Inclusive ranges, and whether the class is negated
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    /// A group, with its capture index unless it is `(?:...)`
+    Group(Option<usize>, Box<Node>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+/// The start and end of each group in a match, group 0 being the whole match
+type Captures = Vec<Option<(usize, usize)>>;
+
+pub struct Regex {
+    root: Node,
+    groups: usize,
+}
+
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+    groups: usize,
+}
+
+/// the ranges of `\d`, `\w` and `\s`
+fn shorthand(c: char) -> Option<(Vec<(char, char)>, bool)> {
+    let digits = vec![('0', '9')];
+    let word = vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+    let space = vec![('\t', '\r'), (' ', ' ')];
+    Some(match c {
+        'd' => (digits, false),
+        'D' => (digits, true),
+        'w' => (word, false),
+        'W' => (word, true),
+        's' => (space, false),
+        'S' => (space, true),
+        _ => return None,
+    })
+}
+
+fn escaped(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        other => other,
+    }
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn alt(&mut self) -> Result<Node, String> {
+        let mut alternatives = vec![self.concat()?];
+        while self.eat('|') {
+            alternatives.push(self.concat()?);
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.pop().unwrap(),
+            _ => Node::Alt(alternatives),
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while !matches!(self.peek(), None | Some('|' | ')')) {
+            let atom = self.atom()?;
+            nodes.push(self.repeat(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    /// `{n}`, `{n,}` or `{n,m}`, leaving the position alone if what follows is not one
+    fn counts(&mut self) -> Option<(usize, Option<usize>)> {
+        let start = self.pos;
+        let number = |p: &mut Self| {
+            let from = p.pos;
+            while p.peek().is_some_and(|c| c.is_ascii_digit()) {
+                p.pos += 1;
+            }
+            p.chars[from..p.pos].iter().collect::<String>().parse().ok()
+        };
+        let counts = (|| {
+            self.eat('{').then_some(())?;
+            let min = number(self)?;
+            let max = if self.eat(',') {
+                number(self)
+            } else {
+                Some(min)
+            };
+            self.eat('}').then_some((min, max))
+        })();
+        if counts.is_none() {
+            self.pos = start;
+        }
+        counts
+    }
+
+    fn repeat(&mut self, mut node: Node) -> Result<Node, String> {
+        loop {
+            let symbol = match self.peek() {
+                Some('*') => Some((0, None)),
+                Some('+') => Some((1, None)),
+                Some('?') => Some((0, Some(1))),
+                _ => None,
+            };
+            self.pos += usize::from(symbol.is_some());
+            let Some((min, max)) = symbol.or_else(|| self.counts()) else {
+                return Ok(node);
+            };
+            if matches!(node, Node::Start | Node::End) {
+                return Err("an anchor cannot be repeated".to_string());
+            }
+            if max.is_some_and(|max| max < min) {
+                return Err(format!("the count {{{min},{}}} is backwards", max.unwrap()));
+            }
+            let greedy = !self.eat('?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let c = self.peek().ok_or("the pattern ends early")?;
+        self.pos += 1;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return Err("only (?:...) groups are supported".to_string());
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let inner = self.alt()?;
+                if !self.eat(')') {
+                    return Err("a group is not closed".to_string());
+                }
+                Node::Group(index, Box::new(inner))
+            }
+            '[' => self.class()?,
+            '\\' => {
+                let c = self.peek().ok_or("the pattern ends in a backslash")?;
+                self.pos += 1;
+                match shorthand(c) {
+                    Some((ranges, negated)) => Node::Class(ranges, negated),
+                    None => Node::Char(escaped(c)),
+                }
+            }
+            '*' | '+' | '?' => return Err(format!("{c} follows nothing to repeat")),
+            c => Node::Char(c),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or("a class is not closed")?;
+            self.pos += 1;
+            if c == ']' && !first {
+                return Ok(Node::Class(ranges, negated));
+            }
+            first = false;
+            let low = if c == '\\' {
+                let c = self.peek().ok_or("a class is not closed")?;
+                self.pos += 1;
+                match shorthand(c) {
+                    Some((more, false)) => {
+                        ranges.extend(more);
+                        continue;
+                    }
+                    Some((_, true)) => {
+                        return Err(format!("\\{c} is not supported inside a class"))
+                    }
+                    None => escaped(c),
+                }
+            } else {
+                c
+            };
+            let high = match (self.peek(), self.chars.get(self.pos + 1)) {
+                (Some('-'), Some(&high)) if high != ']' => {
+                    self.pos += 2;
+                    high
+                }
+                _ => low,
+            };
+            if high < low {
+                return Err(format!("the range {low}-{high} is backwards"));
+            }
+            ranges.push((low, high));
+        }
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut parser = Parser {
+            chars: &chars,
+            pos: 0,
+            groups: 0,
+        };
+        let root = parser
+            .alt()
+            .and_then(|root| match parser.peek() {
+                Some(_) => Err("a ) has no group to close".to_string()),
+                None => Ok(root),
+            })
+            .map_err(|e| format!("invalid regex {pattern}: {e}"))?;
+        Ok(Regex {
+            root,
+            groups: parser.groups,
+        })
+    }
+
+    /// match `node` at `pos`, then the rest of the pattern through `k`
+    fn go(
+        &self,
+        node: &Node,
+        text: &[char],
+        pos: usize,
+        caps: &mut Captures,
+        k: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        match node {
+            Node::Char(c) => text.get(pos) == Some(c) && k(pos + 1, caps),
+            Node::Any => pos < text.len() && k(pos + 1, caps),
+            Node::Class(ranges, negated) => {
+                text.get(pos).is_some_and(|&c| {
+                    ranges.iter().any(|&(low, high)| (low..=high).contains(&c)) != *negated
+                }) && k(pos + 1, caps)
+            }
+            Node::Start => pos == 0 && k(pos, caps),
+            Node::End => pos == text.len() && k(pos, caps),
+            Node::Group(index, inner) => self.go(inner, text, pos, caps, &mut |end, caps| {
+                let Some(index) = *index else {
+                    return k(end, caps);
+                };
+                let before = caps[index];
+                caps[index] = Some((pos, end));
+                k(end, caps) || {
+                    caps[index] = before;
+                    false
+                }
+            }),
+            Node::Concat(nodes) => self.concat(nodes, text, pos, caps, k),
+            Node::Alt(alternatives) => alternatives
+                .iter()
+                .any(|node| self.go(node, text, pos, caps, k)),
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => self.repeat(node, (*min, *max, *greedy), 0, text, pos, caps, k),
+        }
+    }
+
+    fn concat(
+        &self,
+        nodes: &[Node],
+        text: &[char],
+        pos: usize,
+        caps: &mut Captures,
+        k: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        match nodes.split_first() {
+            None => k(pos, caps),
+            Some((first, rest)) => self.go(first, text, pos, caps, &mut |next, caps| {
+                self.concat(rest, text, next, caps, k)
+            }),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn repeat(
+        &self,
+        node: &Node,
+        (min, max, greedy): (usize, Option<usize>, bool),
+        count: usize,
+        text: &[char],
+        pos: usize,
+        caps: &mut Captures,
+        k: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        let more = |caps: &mut Captures, k: &mut dyn FnMut(usize, &mut Captures) -> bool| {
+            max.is_none_or(|max| count < max)
+                && self.go(node, text, pos, caps, &mut |next, caps| {
+                    // a repetition that consumed nothing would loop forever
+                    (next > pos || count < min)
+                        && self.repeat(node, (min, max, greedy), count + 1, text, next, caps, k)
+                })
+        };
+        // a greedy repetition tries one more first, a lazy one stopping here
+        let enough = count >= min;
+        if greedy && more(caps, k) {
+            return true;
+        }
+        if enough && k(pos, caps) {
+            return true;
+        }
+        !greedy && more(caps, k)
+    }
+
+    /// The captures of the leftmost match starting at or after `from`
+    fn find_at(&self, text: &[char], from: usize) -> Option<Captures> {
+        (from..=text.len()).find_map(|start| {
+            let mut caps = vec![None; self.groups + 1];
+            let mut found = None;
+            self.go(&self.root, text, start, &mut caps, &mut |end, caps| {
+                let mut caps = caps.clone();
+                caps[0] = Some((start, end));
+                found = Some(caps);
+                true
+            });
+            found
+        })
+    }
+
+    /// `text` with every match replaced, `$1` or `${1}` in `replacement` standing for a group,
+    /// `$0` for the whole match and `$$` for a dollar sign
+    pub fn replace_all(&self, text: &str, replacement: &str) -> Result<String, String> {
+        let text: Vec<char> = text.chars().collect();
+        let mut out = String::new();
+        let mut pos = 0;
+        while let Some(caps) = self.find_at(&text, pos) {
+            let (start, end) = caps[0].unwrap();
+            out.extend(&text[pos..start]);
+            self.expand(replacement, &text, &caps, &mut out)?;
+            if end == start {
+                // step past an empty match
+                out.extend(text.get(end));
+                pos = end + 1;
+            } else {
+                pos = end;
+            }
+            if pos > text.len() {
+                return Ok(out);
+            }
+        }
+        out.extend(&text[pos..]);
+        Ok(out)
+    }
+
+    fn expand(
+        &self,
+        replacement: &str,
+        text: &[char],
+        caps: &Captures,
+        out: &mut String,
+    ) -> Result<(), String> {
+        let chars: Vec<char> = replacement.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] != '$' {
+                out.push(chars[i]);
+                i += 1;
+                continue;
+            }
+            let braced = chars.get(i + 1) == Some(&'{');
+            let from = i + 1 + usize::from(braced);
+            let mut to = from;
+            while chars.get(to).is_some_and(|c| c.is_ascii_digit()) {
+                to += 1;
+            }
+            if chars.get(i + 1) == Some(&'$') {
+                out.push('$');
+                i += 2;
+                continue;
+            }
+            if to == from || (braced && chars.get(to) != Some(&'}')) {
+                return Err(format!(
+                    "{replacement}: a $ must be followed by a group number, or written $$"
+                ));
+            }
+            let group: usize = chars[from..to].iter().collect::<String>().parse().unwrap();
+            if group > self.groups {
+                return Err(format!("the pattern has no group {group}"));
+            }
+            if let Some((start, end)) = caps[group] {
+                out.extend(&text[start..end]);
+            }
+            i = to + usize::from(braced);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_and_replaces() {
+        let re = Regex::new(r"^transformer\.h\.(\d+)\.").unwrap();
+        assert_eq!(
+            re.replace_all("transformer.h.12.attn.c_attn.weight", "blk.$1.")
+                .unwrap(),
+            "blk.12.attn.c_attn.weight"
+        );
+        assert_eq!(
+            re.replace_all("model.transformer.h.1.x", "").unwrap(),
+            "model.transformer.h.1.x"
+        );
+
+        let re = Regex::new(r"(?:q|k|v)_proj").unwrap();
+        assert_eq!(
+            re.replace_all("q_proj.k_proj.o_proj", "[$0]").unwrap(),
+            "[q_proj].[k_proj].o_proj"
+        );
+        let re = Regex::new(r"([a-z]+?)(\d{2,3})$").unwrap();
+        assert_eq!(re.replace_all("layer123", "${2}-$1").unwrap(), "123-layer");
+        assert_eq!(re.replace_all("layer1234", "-").unwrap(), "layer1234");
+        assert_eq!(
+            Regex::new("a*").unwrap().replace_all("bab", "-").unwrap(),
+            "-b--b-"
+        );
+        assert_eq!(
+            Regex::new("[^.]+$")
+                .unwrap()
+                .replace_all("a.b.w", "bias")
+                .unwrap(),
+            "a.b.bias"
+        );
+
+        assert!(Regex::new("(a").is_err());
+        assert!(Regex::new("a)").is_err());
+        assert!(Regex::new("*a").is_err());
+        assert!(Regex::new("[z-a]").is_err());
+        assert!(Regex::new("a").unwrap().replace_all("a", "$2").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use gguf::writer::write_file;
+use gguf::GGUFFile;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use crate::regex::Regex;
+use crate::{Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file to edit
+    path: PathBuf,
+
+    /// The tensor to rename and its new name, or with `--regex` the replacement alone, `$1`
+    /// standing for the first group
+    #[arg(num_args = 1..=2, required = true)]
+    names: Vec<String>,
+
+    /// Rename every tensor matching this regular expression, e.g. `^transformer\.h\.(\d+)\.`
+    #[arg(long)]
+    regex: Option<String>,
+
+    /// Write the edited file here instead of replacing the original
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Print the new names without writing anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// The new name of each tensor, failing when two tensors would share one
+fn rename(args: &Args, names: Vec<&str>) -> Result<Vec<String>, E> {
+    let renamed: Vec<String> = match (&args.regex, args.names.as_slice()) {
+        (Some(pattern), [replacement]) => {
+            let re = Regex::new(pattern)?;
+            names
+                .iter()
+                .map(|name| re.replace_all(name, replacement))
+                .collect::<Result<_, _>>()?
+        }
+        (None, [from, to]) => {
+            if !names.contains(&from.as_str()) {
+                return Err(format!("no tensor named {from}").into());
+            }
+            names
+                .iter()
+                .map(|&name| {
+                    if name == from {
+                        to.clone()
+                    } else {
+                        name.to_string()
+                    }
+                })
+                .collect()
+        }
+        (Some(_), _) => return Err("--regex takes one replacement".into()),
+        (None, _) => return Err("expected the tensor to rename and its new name".into()),
+    };
+    let mut seen = HashSet::new();
+    if let Some(name) = renamed.iter().find(|name| !seen.insert(name.as_str())) {
+        return Err(format!("two tensors would be named {name}").into());
+    }
+    if renamed.iter().zip(&names).all(|(new, old)| new == old) {
+        return Err("no tensor name changes".into());
+    }
+    Ok(renamed)
+}
+
+/// Rewrites the file with the tensor data where the new header puts it
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let mut input = File::open(&args.path)?;
+    let (file, data_start) = GGUFFile::read_from(&mut input, &ctx.options)?;
+    let renamed = rename(args, file.tensors.iter().map(|t| t.name.as_str()).collect())?;
+    let changes: Vec<(String, String)> = file
+        .tensors
+        .iter()
+        .zip(&renamed)
+        .filter(|(t, new)| t.name != **new)
+        .map(|(t, new)| (t.name.clone(), new.clone()))
+        .collect();
+
+    if !args.dry_run {
+        let starts: HashMap<&str, u64> = renamed
+            .iter()
+            .zip(&file.tensors)
+            .map(|(new, t)| (new.as_str(), data_start + t.offset))
+            .collect();
+        let mut edited = file.clone();
+        for (tensor, new) in edited.tensors.iter_mut().zip(&renamed) {
+            tensor.name = new.clone();
+        }
+        let output = args.output.as_ref().unwrap_or(&args.path);
+        write_file(output, &edited, |tensor, out| {
+            let size = tensor.size_bytes().unwrap_or_default();
+            input
+                .seek(SeekFrom::Start(starts[tensor.name.as_str()]))
+                .map_err(|e| e.to_string())?;
+            std::io::copy(&mut (&mut input).take(size), out).map_err(|e| e.to_string())?;
+            Ok(())
+        })?;
+    }
+
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "output": (!args.dry_run).then(|| args.output.as_ref().unwrap_or(&args.path)),
+            "renamed": changes.iter().map(|(from, to)| json!({"from": from, "to": to})).collect::<Vec<_>>(),
+            "tensor_count": file.tensors.len(),
+        }));
+    }
+    if args.dry_run {
+        for (from, to) in &changes {
+            outln!("{from} -> {to}");
+        }
+    }
+    Ok(())
+}
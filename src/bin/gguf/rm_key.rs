@@ -0,0 +1,36 @@
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::set::save;
+use crate::{open, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file to edit
+    path: PathBuf,
+
+    /// The metadata keys to remove
+    #[arg(required = true)]
+    keys: Vec<String>,
+
+    /// Write the edited file here instead of replacing the original
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let mut file = open(&args.path, &ctx.options)?;
+    if let Some(key) = args.keys.iter().find(|k| file.header.get(k).is_none()) {
+        return Err(format!("{} has no key {key}", args.path.display()).into());
+    }
+    file.header.metadata.retain(|m| !args.keys.contains(&m.key));
+    let in_place = save(&args.path, args.output.as_ref(), &file, false, ctx)?;
+    if ctx.structured() {
+        ctx.print(&json!({
+            "output": args.output.as_ref().unwrap_or(&args.path),
+            "removed": args.keys,
+            "in_place": in_place,
+        }))?;
+    }
+    Ok(())
+}
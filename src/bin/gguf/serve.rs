@@ -0,0 +1,38 @@
+use gguf::server::{Server, ServerOptions};
+
+use crate::{Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The address to listen on
+    #[arg(short, long, default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// The largest upload in bytes
+    #[arg(long, default_value_t = ServerOptions::default().max_body)]
+    max_body: u64,
+
+    /// Accept files given by URL, which lets clients make the server fetch from its network
+    #[cfg(feature = "remote")]
+    #[arg(long)]
+    allow_urls: bool,
+
+    /// The threads answering requests
+    #[arg(long, default_value_t = ServerOptions::default().workers)]
+    workers: usize,
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let options = ServerOptions {
+        max_body: args.max_body,
+        #[cfg(feature = "remote")]
+        allow_urls: args.allow_urls,
+        workers: args.workers,
+        ..ServerOptions::default()
+    };
+    let server = Server::bind(&args.listen, options)?;
+    if !ctx.quiet {
+        eprintln!("listening on http://{}", server.local_addr()?);
+    }
+    Ok(server.serve()?)
+}
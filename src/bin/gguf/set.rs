@@ -0,0 +1,104 @@
+use gguf::writer::{rewrite, write_header_in_place};
+use gguf::GGUFFile;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::value::{self, ValueType};
+use crate::{open, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file to edit
+    path: PathBuf,
+
+    /// The metadata key to set
+    #[arg(requires = "value")]
+    key: Option<String>,
+
+    /// The new value
+    value: Option<String>,
+
+    /// Type of the value; defaults to the current type of the key, or string for a new key
+    #[arg(long = "type", value_enum)]
+    value_type: Option<ValueType>,
+
+    /// Set every key of a JSON object, e.g. `{"general.name": "My Model"}`
+    #[arg(long, required_unless_present = "key")]
+    from_json: Option<PathBuf>,
+
+    /// Write the edited file here instead of replacing the original
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Fail rather than rewrite the whole file when the new header does not fit in place
+    #[arg(long, conflicts_with = "output")]
+    in_place_only: bool,
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let mut file = open(&args.path, &ctx.options)?;
+    let mut keys = Vec::new();
+    if let (Some(key), Some(text)) = (&args.key, &args.value) {
+        let value_type = match (args.value_type, file.header.get(key)) {
+            (Some(t), _) => t.into(),
+            (None, Some(current)) => current.value_type(),
+            (None, None) => ValueType::String.into(),
+        };
+        file.header.set(key, value::parse(text, value_type)?);
+        keys.push(key.clone());
+    }
+    if let Some(path) = &args.from_json {
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let overrides = json
+            .as_object()
+            .ok_or_else(|| format!("{} does not hold a JSON object", path.display()))?;
+        for (key, json) in overrides {
+            let value =
+                value::from_json(json, file.header.get(key)).map_err(|e| format!("{key}: {e}"))?;
+            file.header.set(key, value);
+            keys.push(key.clone());
+        }
+    }
+    let in_place = save(
+        &args.path,
+        args.output.as_ref(),
+        &file,
+        args.in_place_only,
+        ctx,
+    )?;
+    if ctx.structured() {
+        ctx.print(&json!({
+            "output": args.output.as_ref().unwrap_or(&args.path),
+            "set": keys,
+            "in_place": in_place,
+        }))?;
+    }
+    Ok(())
+}
+
+/// Write an edited file to `output`, or back to `path`, updating the header in place when it
+/// still fits; returns whether it did
+pub fn save(
+    path: &PathBuf,
+    output: Option<&PathBuf>,
+    file: &GGUFFile,
+    in_place_only: bool,
+    ctx: &Context,
+) -> Result<bool, E> {
+    match output {
+        Some(output) if output != path => rewrite(path, output, file)?,
+        _ => {
+            if write_header_in_place(path, file)? {
+                return Ok(true);
+            }
+            if in_place_only {
+                return Err("the new header does not fit in place of the old one".into());
+            }
+            if !ctx.quiet {
+                eprintln!("the new header does not fit in place, rewriting the whole file");
+            }
+            rewrite(path, path, file)?;
+        }
+    }
+    Ok(false)
+}
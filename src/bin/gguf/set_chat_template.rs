@@ -0,0 +1,126 @@
+use gguf::chat_template::{ChatTemplate, Severity};
+use gguf::writer::{rewrite, write_header_in_place};
+use gguf::GGUFMetadataValue;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+use crate::{open, Context, Invalid, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file to edit
+    path: PathBuf,
+
+    /// The Jinja template to store
+    #[arg(long)]
+    file: PathBuf,
+
+    /// Store it as a named variant, e.g. `tool_use` for `tokenizer.chat_template.tool_use`
+    #[arg(long, default_value = "default")]
+    name: String,
+
+    /// Check the template for constructs runtimes reject or a hostile template would use, and
+    /// refuse to store it on errors, or on warnings with `--fail-on warn`
+    #[arg(long)]
+    lint: bool,
+
+    /// Write the edited file here instead of replacing the original
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Keep the original file as `<path>.bak`
+    #[arg(long, conflicts_with = "output")]
+    backup: bool,
+
+    /// Patch the header where it is when it keeps its size, rather than writing a new file and
+    /// renaming it over the old one; faster, but a crash midway leaves the file corrupt
+    #[arg(long, conflicts_with = "output")]
+    in_place: bool,
+}
+
+/// keep the current file as `<path>.bak`; a link to it survives the new file being renamed over
+/// it, but an edit in place needs a copy
+fn backup(path: &Path, copy: bool) -> Result<PathBuf, E> {
+    let mut bak = path.as_os_str().to_owned();
+    bak.push(".bak");
+    let bak = PathBuf::from(bak);
+    if bak.exists() {
+        std::fs::remove_file(&bak)?;
+    }
+    if copy || std::fs::hard_link(path, &bak).is_err() {
+        std::fs::copy(path, &bak)?;
+    }
+    Ok(bak)
+}
+
+/// Unless `--in-place` is given, the new file is written next to the old one and renamed over
+/// it, so the original is intact until the edit is complete
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let source = std::fs::read_to_string(&args.file)?;
+    let template = ChatTemplate::new(source.as_str())
+        .map_err(|e| format!("{} does not parse: {e}", args.file.display()))?;
+    if args.lint {
+        let findings: Vec<_> = template
+            .lint()
+            .into_iter()
+            .chain(template.audit())
+            .collect();
+        for finding in findings.iter().filter(|_| !ctx.quiet) {
+            eprintln!(
+                "{:?}[{}]: {}",
+                finding.severity, finding.code, finding.message
+            );
+        }
+        let failing = findings
+            .iter()
+            .filter(|f| f.severity >= ctx.fail_on)
+            .count();
+        if failing > 0 {
+            let worst = if ctx.fail_on == Severity::Error {
+                "errors"
+            } else {
+                "errors or warnings"
+            };
+            return Err(Invalid(format!(
+                "the template has {failing} {worst}, not storing it"
+            ))
+            .into());
+        }
+    }
+
+    let mut file = open(&args.path, &ctx.options)?;
+    let key = match args.name.as_str() {
+        "default" => "tokenizer.chat_template".to_string(),
+        name => format!("tokenizer.chat_template.{name}"),
+    };
+    file.header
+        .set(&key, GGUFMetadataValue::String(source.clone()));
+
+    let mut bak = None;
+    let mut in_place = false;
+    match &args.output {
+        Some(output) if *output != args.path => rewrite(&args.path, output, &file)?,
+        _ => {
+            if args.backup {
+                bak = Some(backup(&args.path, args.in_place)?);
+            }
+            in_place = args.in_place && write_header_in_place(&args.path, &file)?;
+            if !in_place {
+                if args.in_place {
+                    eprintln!("the new header does not fit in place, rewriting the whole file");
+                }
+                rewrite(&args.path, &args.path, &file)?;
+            }
+        }
+    }
+    if ctx.structured() {
+        ctx.print(&json!({
+            "output": args.output.as_ref().unwrap_or(&args.path),
+            "key": key,
+            "bytes": source.len(),
+            "in_place": in_place,
+            "backup": bak,
+        }))?;
+    }
+    Ok(())
+}
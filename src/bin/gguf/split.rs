@@ -0,0 +1,69 @@
+use gguf::split::split;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file to split
+    path: PathBuf,
+
+    /// The most tensor data per shard, in bytes or with a K, M, G or T suffix, e.g. `48G`
+    #[arg(long, value_parser = parse_size)]
+    max_size: u64,
+
+    /// The shard paths before `-00001-of-00004.gguf`, by default the path without `.gguf`
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+/// a size with an optional decimal suffix, as llama.cpp's `gguf-split` takes them
+fn parse_size(text: &str) -> Result<u64, String> {
+    let (digits, unit) = match text.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => text.split_at(i),
+        None => (text, ""),
+    };
+    let unit: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" => 1_000,
+        "M" => 1_000_000,
+        "G" => 1_000_000_000,
+        "T" => 1_000_000_000_000,
+        _ => return Err(format!("unknown size unit {unit}, use K, M, G or T")),
+    };
+    let size: u64 = digits.parse().map_err(|_| format!("invalid size {text}"))?;
+    size.checked_mul(unit)
+        .filter(|&size| size > 0)
+        .ok_or_else(|| format!("invalid size {text}"))
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let path = args.path.to_string_lossy();
+    let prefix = match &args.output {
+        Some(prefix) => prefix.clone(),
+        None => path.strip_suffix(".gguf").unwrap_or(&path).to_string(),
+    };
+    let shards = split(&args.path, &prefix, args.max_size)?;
+    if ctx.structured() {
+        return ctx.print(&json!({ "shards": shards }));
+    }
+    for shard in shards {
+        outln!("{}", shard.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("48G"), Ok(48_000_000_000));
+        assert_eq!(parse_size("512m"), Ok(512_000_000));
+        assert_eq!(parse_size("100"), Ok(100));
+        assert!(parse_size("1X").is_err());
+        assert!(parse_size("0").is_err());
+    }
+}
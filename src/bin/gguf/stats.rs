@@ -0,0 +1,184 @@
+use gguf::batch::TensorReader;
+use gguf::stats::{data_stats, tensor_stats, Stats};
+use gguf::{GGUFFile, GGUFTensorInfo};
+use serde_json::json;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{glob, input, table, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// Only tensors whose name matches this wildcard pattern, e.g. `blk.*.ffn_down.weight`
+    #[arg(long)]
+    tensor: Option<String>,
+
+    /// Threads reading tensors, by default one per core
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+/// Tensors of at most this many bytes are read in batches rather than streamed
+const SMALL: u64 = 1 << 20;
+
+/// The most tensors read in one batch
+const BATCH: usize = 64;
+
+/// the tensors in runs read at once, each telling whether it is a batch of small tensors, as
+/// those are when `batched`, rather than one tensor streamed
+fn jobs(tensors: &[&GGUFTensorInfo], batched: bool) -> Vec<(Range<usize>, bool)> {
+    let mut jobs: Vec<(Range<usize>, bool)> = Vec::new();
+    for (i, tensor) in tensors.iter().enumerate() {
+        let small = batched && tensor.size_bytes().is_some_and(|size| size <= SMALL);
+        match jobs.last_mut() {
+            Some((job, true)) if small && job.len() < BATCH => job.end = i + 1,
+            _ => jobs.push((i..i + 1, small)),
+        }
+    }
+    jobs
+}
+
+/// the statistics of each tensor, in order, each thread reading through its own handle, and
+/// small tensors of a local file read many at once
+fn collect(
+    path: &Path,
+    data_start: u64,
+    tensors: &[&GGUFTensorInfo],
+    threads: usize,
+) -> Result<Vec<Stats>, E> {
+    let batches = match path.extension().is_some_and(|e| e == "zst") || !path.is_file() {
+        true => None,
+        false => Some(TensorReader::new(std::fs::File::open(path)?, data_start)),
+    };
+    let jobs = jobs(tensors, batches.is_some());
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, Stats)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.clamp(1, jobs.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| -> Result<Vec<(usize, Stats)>, String> {
+                    let mut input = input(path).map_err(|e| e.to_string())?;
+                    let mut done = Vec::new();
+                    loop {
+                        let Some((job, batched)) = jobs.get(next.fetch_add(1, Ordering::Relaxed))
+                        else {
+                            return Ok(done);
+                        };
+                        let batch = &tensors[job.clone()];
+                        match &batches {
+                            Some(reader) if *batched => {
+                                for (i, data) in job.clone().zip(reader.read(batch)?) {
+                                    done.push((i, data_stats(tensors[i], &data)?));
+                                }
+                            }
+                            _ => {
+                                for (i, tensor) in job.clone().zip(batch) {
+                                    done.push((i, tensor_stats(&mut input, data_start, tensor)?));
+                                }
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        let mut results = Vec::new();
+        for worker in workers {
+            results.extend(
+                worker
+                    .join()
+                    .map_err(|_| "a statistics thread panicked")??,
+            );
+        }
+        Ok::<_, String>(results)
+    })?;
+    results.sort_by_key(|(i, _)| *i);
+    Ok(results.into_iter().map(|(_, stats)| stats).collect())
+}
+
+fn stats_json(stats: &Stats) -> serde_json::Value {
+    json!({
+        "count": stats.count,
+        "min": (stats.count > 0).then_some(stats.min),
+        "max": (stats.count > 0).then_some(stats.max),
+        "mean": stats.mean,
+        "std": stats.std(),
+        "zeros": stats.zeros,
+        "nans": stats.nans,
+        "infs": stats.infs,
+    })
+}
+
+/// Prints min, max, mean, standard deviation and NaN and infinity counts per tensor, decoding
+/// quantized data; NaNs and infinities are left out of the other statistics
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let (file, data_start) = GGUFFile::read_from(&mut input(&args.path)?, &ctx.options)?;
+    let tensors: Vec<_> = file
+        .tensors
+        .iter()
+        .filter(|t| {
+            args.tensor
+                .as_ref()
+                .is_none_or(|p| glob::matches(p, &t.name))
+        })
+        .collect();
+    if tensors.is_empty() {
+        return Err("no tensors match".into());
+    }
+    let threads = args
+        .threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    let stats = collect(&args.path, data_start, &tensors, threads)?;
+    let mut total = Stats::default();
+    stats.iter().for_each(|s| total.merge(s));
+
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "tensors": tensors.iter().zip(&stats).map(|(t, s)| {
+                let mut entry = stats_json(s);
+                entry["name"] = json!(t.name);
+                entry["type"] = json!(t.tensor_type);
+                entry
+            }).collect::<Vec<_>>(),
+            "total": stats_json(&total),
+        }));
+    }
+    let number = |v: f64| format!("{v:.6}");
+    let mut rows: Vec<[String; 8]> = tensors
+        .iter()
+        .zip(&stats)
+        .map(|(t, s)| {
+            [
+                t.name.clone(),
+                format!("{:?}", t.tensor_type),
+                number(s.min.into()),
+                number(s.max.into()),
+                number(s.mean),
+                number(s.std()),
+                s.nans.to_string(),
+                s.infs.to_string(),
+            ]
+        })
+        .collect();
+    if tensors.len() > 1 {
+        rows.push([
+            "total".to_string(),
+            String::new(),
+            number(total.min.into()),
+            number(total.max.into()),
+            number(total.mean),
+            number(total.std()),
+            total.nans.to_string(),
+            total.infs.to_string(),
+        ]);
+    }
+    table::print(
+        &ctx.style,
+        ["tensor", "type", "min", "max", "mean", "std", "nan", "inf"],
+        &rows,
+        [false, false, true, true, true, true, true, true],
+    )?;
+    Ok(())
+}
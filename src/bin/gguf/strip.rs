@@ -0,0 +1,58 @@
+use gguf::writer::write_header;
+use gguf::GGUFMetadataValue;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{open, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// Where to write the stub
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Leave out the `tokenizer.ggml.*` arrays, usually most of what is left: the tokens,
+    /// scores, token types and merges, which `verify` then reports missing
+    #[arg(long)]
+    drop_tokenizer_arrays: bool,
+}
+
+/// Writes the header and tensor infos alone, without padding or tensor data
+///
+/// A file that ends with its header is one whose data was left out on purpose, so the stub
+/// dumps and verifies like the original as far as the metadata goes.
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    if args.output.exists()
+        && args.path.exists()
+        && std::fs::canonicalize(&args.output)? == std::fs::canonicalize(&args.path)?
+    {
+        return Err("the stub would replace the file it is made from".into());
+    }
+    let mut file = open(&args.path, &ctx.options)?;
+    let mut dropped = Vec::new();
+    if args.drop_tokenizer_arrays {
+        file.header.metadata.retain(|entry| {
+            let drop = entry.key.starts_with("tokenizer.ggml.")
+                && matches!(entry.value, GGUFMetadataValue::Array(_));
+            if drop {
+                dropped.push(entry.key.clone());
+            }
+            !drop
+        });
+    }
+    let mut out = Vec::new();
+    write_header(&mut out, &file)?;
+    std::fs::write(&args.output, &out)?;
+    if ctx.structured() {
+        ctx.print(&json!({
+            "output": args.output,
+            "bytes": out.len(),
+            "tensor_count": file.tensors.len(),
+            "dropped": dropped,
+        }))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! Text output: columns, sizes and counts
+use crossterm::style::Stylize;
+use std::io::IsTerminal;
+
+/// How tables are drawn
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Style {
+    /// Bold headers and colored names
+    pub color: bool,
+    /// Columns of the terminal printed to; wider rows are cut to fit
+    pub width: Option<usize>,
+}
+
+impl Style {
+    /// Color and cut to width only when printing to a terminal, and color only when neither
+    /// `--no-color` nor `NO_COLOR` asks otherwise
+    pub fn detect(no_color: bool) -> Style {
+        let terminal = std::io::stdout().is_terminal();
+        Style {
+            color: terminal && !no_color && std::env::var_os("NO_COLOR").is_none(),
+            width: crossterm::terminal::size()
+                .ok()
+                .filter(|&(width, _)| terminal && width > 0)
+                .map(|(width, _)| width.into()),
+        }
+    }
+}
+
+/// Columns are not cut to narrower than this
+const MIN_WIDTH: usize = 8;
+
+/// `cell` cut to `width` characters, ending in an ellipsis when cut
+fn truncate(cell: &str, width: usize) -> String {
+    if cell.chars().count() <= width {
+        return cell.to_string();
+    }
+    let mut cut: String = cell.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// The lines of a table, narrowing a left-aligned last column and then the widest left-aligned
+/// column until rows fit the width
+fn lines<const N: usize>(
+    style: &Style,
+    header: [&str; N],
+    rows: &[[String; N]],
+    right: [bool; N],
+) -> Vec<String> {
+    let header = header.map(String::from);
+    let mut widths = [0; N];
+    for row in std::iter::once(&header).chain(rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    if let Some(limit) = style.width {
+        let mut total = widths.iter().sum::<usize>() + 2 * N.saturating_sub(1);
+        while total > limit {
+            let narrowable = |&i: &usize| !right[i] && widths[i] > MIN_WIDTH;
+            let next = N
+                .checked_sub(1)
+                .filter(narrowable)
+                .or_else(|| (0..N).filter(narrowable).max_by_key(|&i| widths[i]));
+            let Some(i) = next else { break };
+            widths[i] -= 1;
+            total -= 1;
+        }
+    }
+    std::iter::once(&header)
+        .chain(rows)
+        .enumerate()
+        .map(|(row_no, row)| {
+            let cells: Vec<String> = (0..N)
+                .map(|i| {
+                    let (cell, width) = (truncate(&row[i], widths[i]), widths[i]);
+                    let cell = match (right[i], i + 1 == N) {
+                        (true, _) => format!("{cell:>width$}"),
+                        (false, false) => format!("{cell:width$}"),
+                        (false, true) => cell,
+                    };
+                    match (style.color, row_no, i) {
+                        (false, _, _) => cell,
+                        (true, 0, _) => cell.bold().to_string(),
+                        (true, _, 0) => cell.cyan().to_string(),
+                        (true, _, _) => cell,
+                    }
+                })
+                .collect();
+            cells.join("  ")
+        })
+        .collect()
+}
+
+/// Print rows in columns under a header, right-aligning the columns flagged in `right`
+pub fn print<const N: usize>(
+    style: &Style,
+    header: [&str; N],
+    rows: &[[String; N]],
+    right: [bool; N],
+) -> std::io::Result<()> {
+    for line in lines(style, header, rows, right) {
+        outln!("{line}");
+    }
+    Ok(())
+}
+
+/// A size in bytes with a binary unit, e.g. `7.24 GiB`
+pub fn size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.2} {}", UNITS[unit])
+}
+
+/// A count with thousands separators, e.g. `32,000`
+pub fn count(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_counts_and_widths() {
+        assert_eq!(size(512), "512 B");
+        assert_eq!(size(7_774_000_000), "7.24 GiB");
+        assert_eq!(count(32_000), "32,000");
+        assert_eq!(count(999), "999");
+        assert_eq!(count(1_234_567), "1,234,567");
+
+        let style = Style {
+            color: false,
+            width: Some(24),
+        };
+        let rows = [["blk.0.attn_output.weight".to_string(), "1024".to_string()]];
+        let lines = lines(&style, ["name", "bytes"], &rows, [false, true]);
+        assert_eq!(
+            lines,
+            ["name               bytes", "blk.0.attn_outpu…   1024"]
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use clap::ValueEnum;
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::{glob, open, table, Context, E};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, ValueEnum)]
+enum Sort {
+    /// The order of the data in the file.
+    Offset,
+    Name,
+    /// Largest first.
+    Size,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = Sort::Offset)]
+    sort: Sort,
+
+    /// Only list tensors whose name matches this wildcard pattern, e.g. `blk.*.attn_q.weight`
+    #[arg(long)]
+    filter: Option<String>,
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let file = open(&args.path, &ctx.options)?;
+    let mut tensors: Vec<_> = file
+        .tensors
+        .iter()
+        .filter(|t| {
+            args.filter
+                .as_ref()
+                .is_none_or(|p| glob::matches(p, &t.name))
+        })
+        .collect();
+    match args.sort {
+        Sort::Offset => tensors.sort_by_key(|t| t.offset),
+        Sort::Name => tensors.sort_by(|a, b| a.name.cmp(&b.name)),
+        Sort::Size => tensors.sort_by_key(|t| std::cmp::Reverse(t.size_bytes())),
+    }
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "tensors": tensors.iter().map(|t| json!({
+                "name": t.name,
+                "shape": t.dimensions,
+                "type": t.tensor_type,
+                "bytes": t.size_bytes(),
+                "offset": t.offset,
+            })).collect::<Vec<_>>(),
+        }));
+    }
+    let rows: Vec<[String; 5]> = tensors
+        .iter()
+        .map(|t| {
+            [
+                t.name.clone(),
+                format!("{:?}", t.dimensions),
+                format!("{:?}", t.tensor_type),
+                t.size_bytes().map_or_else(|| "?".to_string(), table::size),
+                t.offset.to_string(),
+            ]
+        })
+        .collect();
+    table::print(
+        &ctx.style,
+        ["name", "shape", "type", "size", "offset"],
+        &rows,
+        [false, false, false, true, true],
+    )?;
+    Ok(())
+}
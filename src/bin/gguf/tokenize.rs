@@ -0,0 +1,96 @@
+use gguf::tokenizer::{TokenizerMetadata, TokenizerModel, Vocab};
+use serde_json::json;
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::{open, table, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct TokenizeArgs {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// The text to encode, `-` for standard input
+    text: String,
+
+    /// Leave out the bos and eos tokens the file asks to be added
+    #[arg(long)]
+    no_special: bool,
+
+    /// Print only the ids, on one line
+    #[arg(long)]
+    ids: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DetokenizeArgs {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// The token ids to decode
+    #[arg(required = true)]
+    ids: Vec<u32>,
+
+    /// Render control tokens such as `<|eot_id|>` instead of dropping them
+    #[arg(long)]
+    keep_special: bool,
+}
+
+/// Adds bos and eos as llama.cpp does: per `tokenizer.ggml.add_bos_token` and
+/// `add_eos_token`, and bos by default for SentencePiece vocabularies
+pub fn tokenize(args: &TokenizeArgs, ctx: &Context) -> Result<(), E> {
+    let file = open(&args.path, &ctx.options)?;
+    let vocab = Vocab::from_header(&file.header)?;
+    let text = if args.text == "-" {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        args.text.clone()
+    };
+    let mut ids = vocab.encode(&text)?;
+    if !args.no_special {
+        let metadata = TokenizerMetadata::from_header(&file.header)?;
+        let add_bos = metadata
+            .add_bos_token
+            .unwrap_or(vocab.model == TokenizerModel::Llama);
+        if let (true, Some(bos)) = (add_bos, vocab.special.bos) {
+            ids.insert(0, bos);
+        }
+        if let (Some(true), Some(eos)) = (metadata.add_eos_token, vocab.special.eos) {
+            ids.push(eos);
+        }
+    }
+    let piece = |id: u32| vocab.token(id).unwrap_or_default();
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "count": ids.len(),
+            "tokens": ids.iter().map(|&id| json!({"id": id, "piece": piece(id)})).collect::<Vec<_>>(),
+        }));
+    }
+    if args.ids {
+        let ids: Vec<String> = ids.iter().map(u32::to_string).collect();
+        outln!("{}", ids.join(" "));
+        return Ok(());
+    }
+    let rows: Vec<[String; 2]> = ids
+        .iter()
+        .map(|&id| [id.to_string(), format!("{:?}", piece(id))])
+        .collect();
+    table::print(&ctx.style, ["id", "piece"], &rows, [true, false])?;
+    Ok(())
+}
+
+pub fn detokenize(args: &DetokenizeArgs, ctx: &Context) -> Result<(), E> {
+    let file = open(&args.path, &ctx.options)?;
+    let vocab = Vocab::from_header(&file.header)?;
+    if let Some(id) = args.ids.iter().find(|&&id| vocab.token(id).is_none()) {
+        return Err(format!("{id} is past the {} tokens of the vocabulary", vocab.len()).into());
+    }
+    let text = vocab.decode(&args.ids, args.keep_special);
+    if ctx.structured() {
+        return ctx.print(&json!({ "text": text }));
+    }
+    outln!("{text}");
+    Ok(())
+}
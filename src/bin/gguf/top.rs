@@ -0,0 +1,147 @@
+use gguf::{GGUFFile, GGUFTensorInfo};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{Seek, SeekFrom};
+use std::path::PathBuf;
+
+use crate::{input, table, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    /// How many of the largest tensors to list
+    #[arg(short = 'n', long, default_value_t = 10)]
+    count: usize,
+}
+
+/// the group of a tensor in the per-layer breakdown: `blk.N`, or its name before the first dot
+fn layer(name: &str) -> String {
+    match name
+        .strip_prefix("blk.")
+        .and_then(|rest| rest.split_once('.'))
+    {
+        Some((layer, _)) => format!("blk.{layer}"),
+        None => name.split('.').next().unwrap_or(name).to_string(),
+    }
+}
+
+fn share(bytes: u64, total: u64) -> String {
+    format!("{:.1}%", bytes as f64 * 100.0 / total.max(1) as f64)
+}
+
+/// tensor counts and sizes grouped by `key`, largest first
+fn group_by(
+    tensors: &[&GGUFTensorInfo],
+    key: impl Fn(&GGUFTensorInfo) -> String,
+) -> Vec<(String, (usize, u64))> {
+    let mut groups: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    for tensor in tensors {
+        let group = groups.entry(key(tensor)).or_default();
+        group.0 += 1;
+        group.1 += tensor.size_bytes().unwrap_or_default();
+    }
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    groups.sort_by_key(|(_, (_, bytes))| std::cmp::Reverse(*bytes));
+    groups
+}
+
+fn groups_json(title: &str, groups: &[(String, (usize, u64))]) -> Value {
+    groups
+        .iter()
+        .map(|(key, (count, bytes))| json!({title: key, "tensors": count, "bytes": bytes}))
+        .collect()
+}
+
+fn breakdown(
+    style: &table::Style,
+    title: &str,
+    groups: Vec<(String, (usize, u64))>,
+    total: u64,
+) -> std::io::Result<()> {
+    let rows: Vec<[String; 4]> = groups
+        .into_iter()
+        .map(|(key, (count, bytes))| {
+            [
+                key.to_string(),
+                table::count(count as u64),
+                table::size(bytes),
+                share(bytes, total),
+            ]
+        })
+        .collect();
+    table::print(
+        style,
+        [title, "tensors", "size", "share"],
+        &rows,
+        [false, true, true, true],
+    )
+}
+
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let mut input = input(&args.path)?;
+    let (file, _) = GGUFFile::read_from(&mut input, &ctx.options)?;
+    let mut tensors: Vec<_> = file.tensors.iter().collect();
+    tensors.sort_by_key(|t| std::cmp::Reverse(t.size_bytes()));
+    let total: u64 = tensors.iter().filter_map(|t| t.size_bytes()).sum();
+    let file_len = input.seek(SeekFrom::End(0))?;
+    let by_type = group_by(&tensors, |t| format!("{:?}", t.tensor_type));
+    let by_layer = group_by(&tensors, |t| layer(&t.name));
+    let report = file.quant_report();
+    if ctx.structured() {
+        return ctx.print(&json!({
+            "file_type": {
+                "inferred": report.inferred.map(|t| t.to_string()),
+                "declared": report.declared.map(|t| t.to_string()),
+            },
+            "tensor_bytes": total,
+            "other_bytes": file_len.saturating_sub(total),
+            "by_type": groups_json("type", &by_type),
+            "by_layer": groups_json("layer", &by_layer),
+            "largest": tensors.iter().take(args.count).map(|t| json!({
+                "name": t.name,
+                "type": t.tensor_type,
+                "bytes": t.size_bytes(),
+            })).collect::<Vec<_>>(),
+        }));
+    }
+    outln!(
+        "{} of tensor data in {} tensors, {} of header and padding",
+        table::size(total),
+        table::count(tensors.len() as u64),
+        table::size(file_len.saturating_sub(total))
+    );
+    match (report.inferred, report.declared) {
+        (Some(inferred), Some(declared)) if report.mismatch() => {
+            outln!("quantized as {inferred}, though general.file_type says {declared}")
+        }
+        (Some(inferred), _) => outln!("quantized as {inferred}"),
+        (None, _) => {}
+    }
+    outln!();
+    breakdown(&ctx.style, "type", by_type, total)?;
+    outln!();
+    breakdown(&ctx.style, "layer", by_layer, total)?;
+    outln!();
+    let rows: Vec<[String; 4]> = tensors
+        .iter()
+        .take(args.count)
+        .map(|t| {
+            let bytes = t.size_bytes().unwrap_or_default();
+            [
+                t.name.clone(),
+                format!("{:?}", t.tensor_type),
+                table::size(bytes),
+                share(bytes, total),
+            ]
+        })
+        .collect();
+    table::print(
+        &ctx.style,
+        ["tensor", "type", "size", "share"],
+        &rows,
+        [false, false, true, true],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,482 @@
+//! An interactive browser for the metadata, tensors and tokenizer of a file
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use gguf::tokenizer::Vocab;
+use gguf::{GGUFFile, GGUFMetadataValue};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use crate::dump::format_value;
+use crate::{glob, open, Context, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Metadata,
+    Tensors,
+    Tokenizer,
+}
+
+const PANES: [Pane; 3] = [Pane::Metadata, Pane::Tensors, Pane::Tokenizer];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sort {
+    Offset,
+    Name,
+    Size,
+    Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Tab,
+    BackTab,
+    Enter,
+    Esc,
+    Backspace,
+    Char(char),
+}
+
+/// the keys in a chunk of terminal input, arrows and the like arriving as escape sequences
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let (key, len) = match &bytes[i..] {
+            [27, b'[' | b'O', b'A', ..] => (Key::Up, 3),
+            [27, b'[' | b'O', b'B', ..] => (Key::Down, 3),
+            [27, b'[' | b'O', b'H', ..] => (Key::Home, 3),
+            [27, b'[' | b'O', b'F', ..] => (Key::End, 3),
+            [27, b'[', b'Z', ..] => (Key::BackTab, 3),
+            [27, b'[', b'1' | b'7', b'~', ..] => (Key::Home, 4),
+            [27, b'[', b'4' | b'8', b'~', ..] => (Key::End, 4),
+            [27, b'[', b'5', b'~', ..] => (Key::PageUp, 4),
+            [27, b'[', b'6', b'~', ..] => (Key::PageDown, 4),
+            // other sequences, such as left and right, do nothing
+            [27, b'[', rest @ ..] => {
+                let len = rest
+                    .iter()
+                    .position(|b| b.is_ascii_alphabetic() || *b == b'~');
+                i += 3 + len.unwrap_or(rest.len());
+                continue;
+            }
+            [27, ..] => (Key::Esc, 1),
+            [b'\t', ..] => (Key::Tab, 1),
+            [b'\r' | b'\n', ..] => (Key::Enter, 1),
+            [127 | 8, ..] => (Key::Backspace, 1),
+            [b, ..] if *b < 32 => {
+                i += 1;
+                continue;
+            }
+            rest => {
+                let len = rest
+                    .iter()
+                    .position(|b| *b < 32 || *b == 127)
+                    .unwrap_or(rest.len());
+                keys.extend(String::from_utf8_lossy(&rest[..len]).chars().map(Key::Char));
+                i += len;
+                continue;
+            }
+        };
+        keys.push(key);
+        i += len;
+    }
+    keys
+}
+
+/// `text` cut or padded to `width` characters
+fn fit(text: &str, width: usize) -> String {
+    let mut out: String = text.chars().take(width).collect();
+    let len = out.chars().count();
+    out.extend(std::iter::repeat_n(' ', width - len));
+    out
+}
+
+/// whether `text` matches a filter: a wildcard pattern if it has wildcards, otherwise a
+/// case-insensitive substring
+fn matches_filter(filter: &str, text: &str) -> bool {
+    if filter.contains(['*', '?', '[']) {
+        glob::matches(filter, text)
+    } else {
+        text.to_lowercase().contains(&filter.to_lowercase())
+    }
+}
+
+struct App {
+    file: GGUFFile,
+    vocab: Result<Vocab, String>,
+    pane: Pane,
+    sort: Sort,
+    /// per pane: the filter, the selected row and the first row shown
+    filters: [String; 3],
+    selected: [usize; 3],
+    scroll: [usize; 3],
+    editing: bool,
+}
+
+impl App {
+    fn new(file: GGUFFile) -> Self {
+        let vocab = Vocab::from_header(&file.header);
+        App {
+            file,
+            vocab,
+            pane: Pane::Metadata,
+            sort: Sort::Offset,
+            filters: Default::default(),
+            selected: [0; 3],
+            scroll: [0; 3],
+            editing: false,
+        }
+    }
+
+    fn index(&self) -> usize {
+        PANES
+            .iter()
+            .position(|p| *p == self.pane)
+            .unwrap_or_default()
+    }
+
+    /// the items of the pane that pass its filter, in the order shown
+    fn items(&self) -> Vec<usize> {
+        let filter = &self.filters[self.index()];
+        match self.pane {
+            Pane::Metadata => (0..self.file.header.metadata.len())
+                .filter(|&i| {
+                    let entry = &self.file.header.metadata[i];
+                    matches_filter(filter, &entry.key)
+                        || matches_filter(filter, &format_value(&entry.value, 8))
+                })
+                .collect(),
+            Pane::Tensors => {
+                let tensors = &self.file.tensors;
+                let mut items: Vec<usize> = (0..tensors.len())
+                    .filter(|&i| matches_filter(filter, &tensors[i].name))
+                    .collect();
+                match self.sort {
+                    Sort::Offset => items.sort_by_key(|&i| tensors[i].offset),
+                    Sort::Name => items.sort_by(|&a, &b| tensors[a].name.cmp(&tensors[b].name)),
+                    Sort::Size => {
+                        items.sort_by_key(|&i| std::cmp::Reverse(tensors[i].size_bytes()))
+                    }
+                    Sort::Type => items.sort_by_key(|&i| format!("{:?}", tensors[i].tensor_type)),
+                }
+                items
+            }
+            Pane::Tokenizer => match &self.vocab {
+                Ok(vocab) => (0..vocab.len())
+                    .filter(|&i| filter.is_empty() || matches_filter(filter, &vocab.tokens()[i]))
+                    .collect(),
+                Err(_) => Vec::new(),
+            },
+        }
+    }
+
+    fn title(&self, pane: Pane) -> String {
+        match pane {
+            Pane::Metadata => format!("Metadata ({})", self.file.header.metadata.len()),
+            Pane::Tensors => format!("Tensors ({})", self.file.tensors.len()),
+            Pane::Tokenizer => match &self.vocab {
+                Ok(vocab) => format!("Tokenizer ({})", vocab.len()),
+                Err(_) => "Tokenizer".to_string(),
+            },
+        }
+    }
+
+    /// the line above the rows: column names, or a summary of the tokenizer
+    fn columns(&self) -> String {
+        let key_width = self.key_width();
+        match (self.pane, &self.vocab) {
+            (Pane::Metadata, _) => format!("{:key_width$}  {:<16}  value", "key", "type"),
+            (Pane::Tensors, _) => format!(
+                "{:<40}  {:<20}  {:<6}  {:>12}  {:>12}   sorted by {:?}",
+                "name", "shape", "type", "bytes", "offset", self.sort
+            ),
+            (Pane::Tokenizer, Ok(vocab)) => {
+                let special: Vec<String> = vocab
+                    .special
+                    .named()
+                    .into_iter()
+                    .filter_map(|(name, id)| Some(format!("{name} {}", id?)))
+                    .collect();
+                format!(
+                    "{} model, {} pre-tokenizer, {} merges; {}",
+                    vocab.model.name(),
+                    vocab.pre.as_deref().unwrap_or("default"),
+                    vocab.merges().len(),
+                    special.join(", ")
+                )
+            }
+            (Pane::Tokenizer, Err(e)) => format!("no tokenizer: {e}"),
+        }
+    }
+
+    fn key_width(&self) -> usize {
+        let keys = self.file.header.metadata.iter().map(|m| m.key.len());
+        keys.max().unwrap_or_default().min(48)
+    }
+
+    fn row(&self, item: usize) -> String {
+        match self.pane {
+            Pane::Metadata => {
+                let entry = &self.file.header.metadata[item];
+                let value_type = match &entry.value {
+                    GGUFMetadataValue::Array(array) => {
+                        format!("[{:?}; {}]", array.value_type, array.len)
+                    }
+                    value => format!("{:?}", value.value_type()),
+                };
+                format!(
+                    "{:width$}  {value_type:<16}  {}",
+                    entry.key,
+                    format_value(&entry.value, 8),
+                    width = self.key_width()
+                )
+            }
+            Pane::Tensors => {
+                let t = &self.file.tensors[item];
+                format!(
+                    "{:<40}  {:<20}  {:<6}  {:>12}  {:>12}",
+                    t.name,
+                    format!("{:?}", t.dimensions),
+                    format!("{:?}", t.tensor_type),
+                    t.size_bytes().map_or("?".to_string(), |s| s.to_string()),
+                    t.offset
+                )
+            }
+            Pane::Tokenizer => match &self.vocab {
+                Ok(vocab) => format!(
+                    "{item:>7}  {:<12}  {:>10}  {:?}",
+                    format!("{:?}", vocab.token_type(item as u32)),
+                    vocab
+                        .scores
+                        .get(item)
+                        .map_or(String::new(), |s| s.to_string()),
+                    vocab.tokens()[item]
+                ),
+                Err(_) => String::new(),
+            },
+        }
+    }
+
+    /// the full value of the selected key, shown below the rows
+    fn detail(&self, item: Option<usize>) -> Vec<String> {
+        let Some(item) = item.filter(|_| self.pane == Pane::Metadata) else {
+            return Vec::new();
+        };
+        match &self.file.header.metadata[item].value {
+            GGUFMetadataValue::String(s) => s.lines().map(str::to_string).collect(),
+            GGUFMetadataValue::Array(array) => array
+                .value
+                .iter()
+                .enumerate()
+                .map(|(i, v)| format!("{i:>7}  {}", format_value(&v, 8)))
+                .collect(),
+            value => vec![format_value(value, 8)],
+        }
+    }
+
+    /// the lines of the screen, each with whether it is highlighted
+    fn frame(&mut self, width: usize, height: usize) -> Vec<(String, bool)> {
+        let items = self.items();
+        let i = self.index();
+        self.selected[i] = self.selected[i].min(items.len().saturating_sub(1));
+        let selected = items.get(self.selected[i]).copied();
+        let detail = self.detail(selected);
+        let detail_height = if detail.is_empty() { 0 } else { height / 3 };
+        let list_height = height.saturating_sub(3 + detail_height).max(1);
+        if self.selected[i] < self.scroll[i] {
+            self.scroll[i] = self.selected[i];
+        } else if self.selected[i] >= self.scroll[i] + list_height {
+            self.scroll[i] = self.selected[i] + 1 - list_height;
+        }
+
+        let tabs: Vec<String> = PANES
+            .iter()
+            .map(|&p| {
+                let title = self.title(p);
+                if p == self.pane {
+                    format!("[{title}]")
+                } else {
+                    format!(" {title} ")
+                }
+            })
+            .collect();
+        let mut lines = vec![(fit(&tabs.join(" "), width), false)];
+        lines.push((fit(&self.columns(), width), true));
+        for row in 0..list_height {
+            let index = self.scroll[i] + row;
+            let text = items
+                .get(index)
+                .map_or(String::new(), |&item| self.row(item));
+            lines.push((
+                fit(&text, width),
+                index == self.selected[i] && !items.is_empty(),
+            ));
+        }
+        if detail_height > 0 {
+            lines.push((fit(&"─".repeat(width), width), false));
+            for line in detail.iter().take(detail_height - 1) {
+                lines.push((fit(line, width), false));
+            }
+            lines.resize(height - 1, (fit("", width), false));
+        }
+        let filter = &self.filters[i];
+        let status = if self.editing {
+            format!("filter: {filter}_")
+        } else {
+            let position = format!(
+                "{}/{}",
+                (self.selected[i] + 1).min(items.len()),
+                items.len()
+            );
+            let filter = if filter.is_empty() {
+                String::new()
+            } else {
+                format!("  filter {filter:?}")
+            };
+            format!("{position}{filter}  tab pane  / filter  s sort  q quit")
+        };
+        lines.push((fit(&status, width), true));
+        lines
+    }
+
+    /// handle a key, returning false to quit
+    fn key(&mut self, key: Key, page: usize) -> bool {
+        let i = self.index();
+        if self.editing {
+            match key {
+                Key::Enter | Key::Esc => self.editing = false,
+                Key::Backspace => {
+                    self.filters[i].pop();
+                }
+                Key::Char(c) => self.filters[i].push(c),
+                _ => {}
+            }
+            self.selected[i] = 0;
+            return true;
+        }
+        let selected = &mut self.selected[i];
+        match key {
+            Key::Char('q') => return false,
+            Key::Esc => self.filters[i].clear(),
+            Key::Up | Key::Char('k') => *selected = selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => *selected = selected.saturating_add(1),
+            Key::PageUp => *selected = selected.saturating_sub(page),
+            Key::PageDown => *selected = selected.saturating_add(page),
+            Key::Home | Key::Char('g') => *selected = 0,
+            Key::End | Key::Char('G') => *selected = usize::MAX,
+            Key::Tab => self.pane = PANES[(i + 1) % PANES.len()],
+            Key::BackTab => self.pane = PANES[(i + PANES.len() - 1) % PANES.len()],
+            Key::Char(c @ '1'..='3') => self.pane = PANES[c as usize - '1' as usize],
+            Key::Char('/') => self.editing = true,
+            Key::Char('s') if self.pane == Pane::Tensors => {
+                self.sort = match self.sort {
+                    Sort::Offset => Sort::Name,
+                    Sort::Name => Sort::Size,
+                    Sort::Size => Sort::Type,
+                    Sort::Type => Sort::Offset,
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+/// the alternate screen in raw mode, restored when dropped
+struct Screen;
+
+impl Screen {
+    fn enter() -> std::io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        execute!(std::io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(std::io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn draw(app: &mut App) -> std::io::Result<usize> {
+    let (width, height) = terminal::size()?;
+    let mut out = std::io::stdout().lock();
+    for (y, (line, highlighted)) in app.frame(width.into(), height.into()).iter().enumerate() {
+        queue!(
+            out,
+            cursor::MoveTo(0, y as u16),
+            Clear(ClearType::CurrentLine)
+        )?;
+        if *highlighted {
+            queue!(out, SetAttribute(Attribute::Reverse), Print(line))?;
+            queue!(out, SetAttribute(Attribute::Reset))?;
+        } else {
+            queue!(out, Print(line))?;
+        }
+    }
+    out.flush()?;
+    Ok(usize::from(height).saturating_sub(4).max(1))
+}
+
+/// Tab or 1 to 3 switch panes, arrows, j, k, Page Up and Down move, `/` filters by text or a
+/// wildcard pattern, s sorts the tensors and q quits
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let mut app = App::new(open(&args.path, &ctx.options)?);
+    let _screen = Screen::enter()?;
+    let mut stdin = std::io::stdin().lock();
+    let mut buf = [0; 64];
+    loop {
+        let page = draw(&mut app)?;
+        let n = stdin.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        for key in parse_keys(&buf[..n]) {
+            if !app.key(key, page) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_and_filters() {
+        assert_eq!(
+            parse_keys(b"\x1b[A\x1b[6~j/q\x1b\t\x1b[C\xc3\xa9"),
+            [
+                Key::Up,
+                Key::PageDown,
+                Key::Char('j'),
+                Key::Char('/'),
+                Key::Char('q'),
+                Key::Esc,
+                Key::Tab,
+                Key::Char('é'),
+            ]
+        );
+        assert!(matches_filter("LLAMA", "llama.block_count"));
+        assert!(matches_filter("blk.*.attn_q*", "blk.3.attn_q.weight"));
+        assert!(!matches_filter("blk.*.attn_q", "blk.3.attn_q.weight"));
+        assert_eq!(fit("abcdef", 3), "abc");
+        assert_eq!(fit("ab", 3), "ab ");
+    }
+}
@@ -0,0 +1,134 @@
+//! Metadata values from the command line and JSON files
+use clap::ValueEnum;
+use gguf::{GGUFMetadataArrayValue, GGUFMetadataValue, GGUfMetadataValueType};
+
+/// Scalar types a value given on the command line can have
+#[derive(Debug, PartialEq, Eq, Clone, Copy, ValueEnum)]
+pub enum ValueType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    U64,
+    I64,
+    F64,
+    Bool,
+    String,
+}
+
+impl From<ValueType> for GGUfMetadataValueType {
+    fn from(t: ValueType) -> Self {
+        match t {
+            ValueType::U8 => GGUfMetadataValueType::Uint8,
+            ValueType::I8 => GGUfMetadataValueType::Int8,
+            ValueType::U16 => GGUfMetadataValueType::Uint16,
+            ValueType::I16 => GGUfMetadataValueType::Int16,
+            ValueType::U32 => GGUfMetadataValueType::Uint32,
+            ValueType::I32 => GGUfMetadataValueType::Int32,
+            ValueType::F32 => GGUfMetadataValueType::Float32,
+            ValueType::U64 => GGUfMetadataValueType::Uint64,
+            ValueType::I64 => GGUfMetadataValueType::Int64,
+            ValueType::F64 => GGUfMetadataValueType::Float64,
+            ValueType::Bool => GGUfMetadataValueType::Bool,
+            ValueType::String => GGUfMetadataValueType::String,
+        }
+    }
+}
+
+/// Parse command line text as a value of the given scalar type
+pub fn parse(text: &str, value_type: GGUfMetadataValueType) -> Result<GGUFMetadataValue, String> {
+    use GGUfMetadataValueType as T;
+    let bad = |e: &dyn std::fmt::Display| format!("{text:?} is not a valid {value_type:?}: {e}");
+    Ok(match value_type {
+        T::Uint8 => GGUFMetadataValue::Uint8(text.parse().map_err(|e| bad(&e))?),
+        T::Int8 => GGUFMetadataValue::Int8(text.parse().map_err(|e| bad(&e))?),
+        T::Uint16 => GGUFMetadataValue::Uint16(text.parse().map_err(|e| bad(&e))?),
+        T::Int16 => GGUFMetadataValue::Int16(text.parse().map_err(|e| bad(&e))?),
+        T::Uint32 => GGUFMetadataValue::Uint32(text.parse().map_err(|e| bad(&e))?),
+        T::Int32 => GGUFMetadataValue::Int32(text.parse().map_err(|e| bad(&e))?),
+        T::Float32 => GGUFMetadataValue::Float32(text.parse().map_err(|e| bad(&e))?),
+        T::Uint64 => GGUFMetadataValue::Uint64(text.parse().map_err(|e| bad(&e))?),
+        T::Int64 => GGUFMetadataValue::Int64(text.parse().map_err(|e| bad(&e))?),
+        T::Float64 => GGUFMetadataValue::Float64(text.parse().map_err(|e| bad(&e))?),
+        T::Bool => GGUFMetadataValue::Bool(text.parse().map_err(|e| bad(&e))?),
+        T::String => GGUFMetadataValue::String(text.to_string()),
+        T::Array => return Err("arrays can only be set from JSON".to_string()),
+    })
+}
+
+/// Convert a JSON value, keeping the types of `like`, the value it replaces, where it has one
+///
+/// Without one, integers become `Uint32` or `Int32` if they fit and 64-bit otherwise, and
+/// other numbers `Float32`, the types most standardized keys use.
+pub fn from_json(
+    json: &serde_json::Value,
+    like: Option<&GGUFMetadataValue>,
+) -> Result<GGUFMetadataValue, String> {
+    use serde_json::Value;
+    use GGUfMetadataValueType as T;
+    Ok(match json {
+        Value::String(s) => GGUFMetadataValue::String(s.clone()),
+        Value::Bool(b) => GGUFMetadataValue::Bool(*b),
+        Value::Number(n) => {
+            let value_type = match like.map(|v| v.value_type()) {
+                Some(t) if !matches!(t, T::String | T::Bool | T::Array) => t,
+                _ if n.as_u64().is_some_and(|v| v <= u32::MAX.into()) => T::Uint32,
+                _ if n.as_i64().is_some_and(|v| i32::try_from(v).is_ok()) => T::Int32,
+                _ if n.is_u64() => T::Uint64,
+                _ if n.is_i64() => T::Int64,
+                _ => T::Float32,
+            };
+            parse(&n.to_string(), value_type)?
+        }
+        Value::Array(items) => {
+            let like = like.and_then(|v| v.as_array());
+            let item_like = like.and_then(|a| a.value.first());
+            let items = items
+                .iter()
+                .map(|item| from_json(item, item_like.as_ref()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let item_type = match (items.first(), like) {
+                (Some(first), _) => first.value_type(),
+                (None, Some(like)) => like.value_type,
+                (None, None) => T::String,
+            };
+            if let Some(other) = items.iter().find(|v| v.value_type() != item_type) {
+                return Err(format!(
+                    "array items must share one type, found {item_type:?} and {:?}",
+                    other.value_type()
+                ));
+            }
+            GGUFMetadataValue::Array(GGUFMetadataArrayValue::new(item_type, items))
+        }
+        Value::Null | Value::Object(_) => {
+            return Err(format!("{json} cannot be stored as a metadata value"))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn values_keep_the_type_they_replace() {
+        assert_eq!(
+            from_json(&json!(4096), None),
+            Ok(GGUFMetadataValue::Uint32(4096))
+        );
+        assert_eq!(
+            from_json(&json!(4096), Some(&GGUFMetadataValue::Uint64(1))),
+            Ok(GGUFMetadataValue::Uint64(4096))
+        );
+        assert_eq!(
+            from_json(&json!(1e4), None),
+            Ok(GGUFMetadataValue::Float32(1e4))
+        );
+        assert!(from_json(&json!(["a", 1]), None).is_err());
+        assert!(parse("300", GGUfMetadataValueType::Uint8).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use gguf::validate::{arch_profile, Finding, Severity, ValidationReport};
+use gguf::{validate_reader, ParseOptions};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+use crate::paths::{expand, models};
+use crate::{input, open, Context, Invalid, E};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The files to check: paths, directories to search for `.gguf` files or patterns such as
+    /// `models/**/*.gguf`
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Fail on warnings as well as errors, the same as `--fail-on warn`
+    #[arg(long)]
+    strict: bool,
+
+    /// Require the file to be of this architecture, checked against its key profile
+    #[arg(long)]
+    arch_profile: Option<String>,
+}
+
+fn error(code: &'static str, message: String, key: Option<&str>) -> Finding {
+    Finding {
+        severity: Severity::Error,
+        code,
+        message,
+        offset: None,
+        key: key.map(str::to_string),
+        tensor: None,
+    }
+}
+
+/// validate one file, checking its architecture when asked to
+fn check(path: &Path, arch: Option<&str>) -> Result<ValidationReport, E> {
+    let mut report = validate_reader(input(path)?).map_err(std::io::Error::other)?;
+    if let Some(expected) = arch {
+        let file = open(path, &ParseOptions::lenient()).ok();
+        let actual = file
+            .as_ref()
+            .and_then(|f| f.header.get("general.architecture"))
+            .and_then(|v| v.as_str());
+        if actual != Some(expected) {
+            report.findings.push(error(
+                "architecture",
+                format!(
+                    "expected architecture {expected}, found {}",
+                    actual.unwrap_or("none")
+                ),
+                Some("general.architecture"),
+            ));
+        }
+    }
+    Ok(report)
+}
+
+fn print_report(path: &Path, report: &ValidationReport) -> std::io::Result<()> {
+    for finding in &report.findings {
+        let at = finding
+            .offset
+            .map(|o| format!(" at {o:#x}"))
+            .unwrap_or_default();
+        outln!(
+            "{:?}[{}]{at}: {}",
+            finding.severity,
+            finding.code,
+            finding.message
+        );
+    }
+    let count = |s| report.findings.iter().filter(|f| f.severity == s).count();
+    outln!(
+        "{}: {} errors, {} warnings",
+        path.display(),
+        count(Severity::Error),
+        count(Severity::Warning)
+    );
+    Ok(())
+}
+
+/// Fails with [`Invalid`] when any file fails the checks
+///
+/// The shards of a split count as one model, which also fails when shards are missing. One
+/// file prints just its report; more print a report per model and a summary.
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    if let Some(expected) = &args.arch_profile {
+        if arch_profile(expected).is_none() {
+            return Err(format!("there is no key profile for architecture {expected}").into());
+        }
+    }
+    let fail_at = if args.strict {
+        Severity::Warning
+    } else {
+        ctx.fail_on
+    };
+    let arch = args.arch_profile.as_deref();
+    if let [path] = args.paths.as_slice() {
+        if path.is_file() {
+            let report = check(path, arch)?;
+            if ctx.structured() {
+                ctx.print(&report)?;
+            } else if !ctx.quiet {
+                print_report(path, &report)?;
+            }
+            if report.max_severity() >= Some(fail_at) {
+                return Err(Invalid(format!("{} failed the checks", path.display())).into());
+            }
+            return Ok(());
+        }
+    }
+
+    let mut results = Vec::new();
+    let mut failed = 0;
+    for model in models(expand(&args.paths)?) {
+        let mut shards = Vec::new();
+        for (no, path) in &model.shards {
+            let mut report = check(path, arch)?;
+            if model.count.is_some() {
+                // only the first shard holds the metadata, and no shard holds every block
+                report.findings.retain(|f| {
+                    f.code != "missing-block" && (*no == 0 || f.code != "missing-architecture")
+                });
+            }
+            shards.push((path.clone(), report));
+        }
+        let missing: Vec<_> = model
+            .missing()
+            .iter()
+            .map(|no| (no + 1).to_string())
+            .collect();
+        if let (Some(count), Some((_, first))) = (model.count, shards.first_mut()) {
+            if !missing.is_empty() {
+                first.findings.push(error(
+                    "split",
+                    format!("shards {} of {count} are missing", missing.join(", ")),
+                    Some("split.count"),
+                ));
+            }
+        }
+        let ok = shards
+            .iter()
+            .all(|(_, report)| report.max_severity() < Some(fail_at));
+        failed += usize::from(!ok);
+        results.push((model, shards, ok));
+    }
+
+    if ctx.structured() {
+        ctx.print(&json!({
+            "models": results.iter().map(|(model, shards, ok)| json!({
+                "model": model.name,
+                "ok": ok,
+                "shard_count": model.count,
+                "files": shards.iter().map(|(path, report)| json!({
+                    "path": path,
+                    "report": report,
+                })).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+            "summary": {
+                "models": results.len(),
+                "passed": results.len() - failed,
+                "failed": failed,
+            },
+        }))?;
+    } else if !ctx.quiet {
+        for (model, shards, ok) in &results {
+            for (path, report) in shards {
+                print_report(path, report)?;
+            }
+            if let Some(count) = model.count {
+                let verdict = if *ok { "passed" } else { "failed" };
+                outln!("{}: split of {count} shards {verdict}", model.name);
+            }
+        }
+        outln!();
+        outln!(
+            "{} models checked: {} passed, {failed} failed",
+            results.len(),
+            results.len() - failed
+        );
+    }
+    if failed > 0 {
+        return Err(Invalid(format!(
+            "{failed} of {} models failed the checks",
+            results.len()
+        ))
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use clap::ValueEnum;
+use gguf::tokenizer::Vocab;
+use serde_json::{json, Map, Value};
+use std::path::PathBuf;
+
+use crate::{open, Context, E};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, ValueEnum)]
+enum Format {
+    /// The tokens with their ids, types and scores, the merges and the special token ids
+    Json,
+    /// One token per line in id order, with its score after a tab
+    Txt,
+    /// A reconstructed Hugging Face `tokenizer.json`
+    HfTokenizerJson,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The file or URL to read
+    path: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+
+    /// Write the export here instead of to stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn to_json(vocab: &Vocab) -> Value {
+    let tokens: Vec<Value> = vocab
+        .tokens()
+        .iter()
+        .enumerate()
+        .map(|(id, text)| {
+            json!({
+                "id": id,
+                "text": text,
+                "type": vocab.token_type(id as u32),
+                "score": vocab.scores.get(id),
+            })
+        })
+        .collect();
+    let mut special: Map<String, Value> = vocab
+        .special
+        .named()
+        .into_iter()
+        .filter_map(|(name, id)| Some((name.to_string(), id?.into())))
+        .collect();
+    if !vocab.special.stop.is_empty() {
+        special.insert("stop".to_string(), json!(vocab.special.stop));
+    }
+    json!({
+        "model": vocab.model.name(),
+        "pre": vocab.pre,
+        "tokens": tokens,
+        "merges": vocab.merges(),
+        "special": special,
+    })
+}
+
+/// With `--json` or `--yaml` and no output file, prints the JSON export, or the
+/// `tokenizer.json` for `--format hf-tokenizer-json`, in that format
+pub fn run(args: &Args, ctx: &Context) -> Result<(), E> {
+    let file = open(&args.path, &ctx.options)?;
+    let vocab = Vocab::from_header(&file.header)?;
+    if ctx.structured() && args.output.is_none() {
+        return match args.format {
+            Format::HfTokenizerJson => ctx.print(&vocab.to_hf_tokenizer_json()?),
+            _ => ctx.print(&to_json(&vocab)),
+        };
+    }
+    let text = match args.format {
+        Format::Json => serde_json::to_string_pretty(&to_json(&vocab))? + "\n",
+        Format::Txt => vocab.to_vocab_txt(),
+        Format::HfTokenizerJson => {
+            serde_json::to_string_pretty(&vocab.to_hf_tokenizer_json()?)? + "\n"
+        }
+    };
+    match &args.output {
+        Some(output) => std::fs::write(output, text)?,
+        None => out!("{text}"),
+    }
+    if ctx.structured() {
+        ctx.print(&json!({
+            "output": args.output,
+            "format": args.format.to_possible_value().map(|v| v.get_name().to_string()),
+            "token_count": vocab.len(),
+        }))?;
+    }
+    Ok(())
+}
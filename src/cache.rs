@@ -0,0 +1,275 @@
+//! # Header caches
+//!
+//! A GUI refreshing its model list or a server restarting opens the same models again and
+//! again, and parsing the header of a large one, its vocabulary above all, takes a while.
+//! [`HeaderCache`] keeps each header parsed, keyed by path, reusing it while the length and
+//! modification time of the file stay as they were and parsing again as soon as either
+//! changes. With [`HeaderCache::dir`] it also keeps a copy of each header on disk, which
+//! outlives the process: the copy is parsed rather than the model, so a model on a slow or
+//! remote disk is not read at all until its tensors are.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::UNIX_EPOCH;
+
+use crate::writer::header_bytes;
+use crate::{GGUFFile, ParseOptions};
+
+/// The start of a header kept on disk
+const MAGIC: &[u8; 8] = b"GGUFHDR1";
+
+/// A header with the offset of its tensor data, as [`HeaderCache::get`] gives it
+pub type Cached = (Arc<GGUFFile>, u64);
+
+/// What identifies the contents of a file: its length and modification time in nanoseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    len: u64,
+    modified: u128,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Result<Self, String> {
+        let metadata = std::fs::metadata(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+        Ok(Stamp {
+            len: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// Headers parsed before, reused while their files do not change
+#[derive(Debug, Default)]
+pub struct HeaderCache {
+    options: ParseOptions,
+    dir: Option<PathBuf>,
+    entries: Mutex<HashMap<PathBuf, (Stamp, Cached)>>,
+}
+
+impl HeaderCache {
+    /// A cache parsing with `options`
+    pub fn new(options: ParseOptions) -> Self {
+        HeaderCache {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// One cache for the whole process, parsing with the default options
+    pub fn global() -> &'static HeaderCache {
+        static GLOBAL: OnceLock<HeaderCache> = OnceLock::new();
+        GLOBAL.get_or_init(HeaderCache::default)
+    }
+
+    /// Keep a copy of each header in the folder `dir` as well, for later processes
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// The header and tensor infos of the file at `path` with the offset of its tensor data,
+    /// parsed only if the cache has none for the file as it is now
+    pub fn get(&self, path: impl AsRef<Path>) -> Result<Cached, String> {
+        let path = path.as_ref();
+        let key = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let stamp = Stamp::of(&key)?;
+        if let Some((seen, cached)) = self.lock().get(&key) {
+            if *seen == stamp {
+                return Ok(cached.clone());
+            }
+        }
+        let cached = match self.load(&key, stamp) {
+            Some(cached) => cached,
+            None => {
+                let mut input = File::open(&key).map_err(|e| format!("{}: {e}", key.display()))?;
+                let (file, data_start) =
+                    GGUFFile::read_from(&mut input, &self.options).map_err(|e| e.to_string())?;
+                self.store(&key, stamp, &file, data_start);
+                (Arc::new(file), data_start)
+            }
+        };
+        self.lock().insert(key, (stamp, cached.clone()));
+        Ok(cached)
+    }
+
+    /// Forget every header, those on disk included
+    pub fn clear(&self) {
+        let paths: Vec<PathBuf> = self.lock().drain().map(|(path, _)| path).collect();
+        for path in paths {
+            if let Some(copy) = self.copy_path(&path) {
+                let _ = std::fs::remove_file(copy);
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, (Stamp, Cached)>> {
+        // a panic elsewhere leaves the map whole, as it is only inserted into and drained
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Where the copy of the header of `path` goes, named after a hash of the path
+    fn copy_path(&self, path: &Path) -> Option<PathBuf> {
+        let hash = path
+            .as_os_str()
+            .as_encoded_bytes()
+            .iter()
+            .fold(0xcbf29ce484222325u64, |hash, &b| {
+                (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+            });
+        Some(self.dir.as_ref()?.join(format!("{hash:016x}.gguf-header")))
+    }
+
+    /// The copy of the header of `path` on disk, if there is one for the file as it is now
+    fn load(&self, path: &Path, stamp: Stamp) -> Option<Cached> {
+        let mut bytes = Vec::new();
+        File::open(self.copy_path(path)?)
+            .and_then(|mut f| f.read_to_end(&mut bytes))
+            .ok()?;
+        let (key, rest) = decode_key(&bytes)?;
+        if key.stamp != stamp || key.path != path.as_os_str().as_encoded_bytes() {
+            return None;
+        }
+        // the copy may have been made by a cache taking versions this one does not
+        if !self.options.accepts_version(key.version) {
+            return None;
+        }
+        let options = ParseOptions {
+            unknown_versions: true,
+            accepted_versions: None,
+            ..self.options.clone()
+        };
+        let mut file = GGUFFile::read_with(rest, &options).ok()??;
+        file.header.version = key.version;
+        file.header.tensor_count = key.tensor_count;
+        Some((Arc::new(file), key.data_start))
+    }
+
+    /// Keep a copy of the header of `path` on disk, if the cache has a folder; failing to is
+    /// no error, as the copy only saves time
+    fn store(&self, path: &Path, stamp: Stamp, file: &GGUFFile, data_start: u64) {
+        let Some(copy) = self.copy_path(path) else {
+            return;
+        };
+        let path = path.as_os_str().as_encoded_bytes();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(stamp.len.to_le_bytes());
+        bytes.extend(stamp.modified.to_le_bytes());
+        bytes.extend(data_start.to_le_bytes());
+        bytes.extend(file.header.version.to_le_bytes());
+        bytes.extend(file.header.tensor_count.to_le_bytes());
+        bytes.extend((path.len() as u64).to_le_bytes());
+        bytes.extend(path);
+        bytes.extend(header_bytes(file));
+        let mut partial = copy.as_os_str().to_owned();
+        partial.push(".partial");
+        let _ = std::fs::create_dir_all(copy.parent().unwrap_or(Path::new(".")))
+            .and_then(|()| std::fs::write(&partial, bytes))
+            .and_then(|()| std::fs::rename(&partial, &copy));
+    }
+}
+
+/// What a copy on disk was made from
+struct Key<'a> {
+    stamp: Stamp,
+    data_start: u64,
+    version: u32,
+    tensor_count: u64,
+    path: &'a [u8],
+}
+
+/// The key at the start of a copy on disk and the header after it
+fn decode_key(bytes: &[u8]) -> Option<(Key<'_>, &[u8])> {
+    let rest = bytes.strip_prefix(MAGIC)?;
+    let (len, rest) = rest.split_first_chunk::<8>()?;
+    let (modified, rest) = rest.split_first_chunk::<16>()?;
+    let (data_start, rest) = rest.split_first_chunk::<8>()?;
+    let (version, rest) = rest.split_first_chunk::<4>()?;
+    let (tensor_count, rest) = rest.split_first_chunk::<8>()?;
+    let (path_len, rest) = rest.split_first_chunk::<8>()?;
+    let path_len = usize::try_from(u64::from_le_bytes(*path_len)).ok()?;
+    let path = rest.get(..path_len)?;
+    let key = Key {
+        stamp: Stamp {
+            len: u64::from_le_bytes(*len),
+            modified: u128::from_le_bytes(*modified),
+        },
+        data_start: u64::from_le_bytes(*data_start),
+        version: u32::from_le_bytes(*version),
+        tensor_count: u64::from_le_bytes(*tensor_count),
+        path,
+    };
+    Some((key, &rest[path_len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path};
+    use crate::GGUFMetadataValue;
+
+    #[test]
+    fn reuses_headers_until_files_change() {
+        let dir = temp_path("gguf-cache");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("model.gguf");
+        let write = |name: &str| {
+            let header = header(&[("general.name", GGUFMetadataValue::String(name.to_string()))]);
+            let file = file(header, vec![]);
+            std::fs::write(&path, header_bytes(&file)).unwrap();
+        };
+        let name = |cached: &Cached| {
+            let value = cached.0.header.get("general.name").unwrap();
+            value.as_str().unwrap().to_string()
+        };
+
+        write("first");
+        let cache = HeaderCache::default().dir(dir.join("headers"));
+        let first = cache.get(&path).unwrap();
+        assert!(Arc::ptr_eq(&first.0, &cache.get(&path).unwrap().0));
+        assert_eq!(name(&first), "first");
+
+        // a new cache parses the copy on disk, not the file, which looks unchanged
+        assert_eq!(std::fs::read_dir(dir.join("headers")).unwrap().count(), 1);
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        write("FIRST");
+        File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(modified))
+            .unwrap();
+        let later = HeaderCache::default().dir(dir.join("headers"));
+        assert_eq!(later.get(&path).unwrap().0, first.0);
+
+        write("second, longer");
+        assert_eq!(name(&cache.get(&path).unwrap()), "second, longer");
+        assert_eq!(name(&later.get(&path).unwrap()), "second, longer");
+        cache.clear();
+        assert_eq!(std::fs::read_dir(dir.join("headers")).unwrap().count(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn strict_caches_refuse_versions_cached_by_lenient_ones() {
+        let dir = temp_path("gguf-cache-versions");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("model.gguf");
+        let file = file(header(&[]), vec![]);
+        // a version from the future, which only the lenient options read
+        let mut bytes = header_bytes(&file);
+        bytes[4..8].copy_from_slice(&9u32.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+
+        let lenient = HeaderCache::new(ParseOptions::lenient()).dir(dir.join("headers"));
+        assert_eq!(lenient.get(&path).unwrap().0.header.version, 9);
+        let strict = HeaderCache::new(ParseOptions::strict()).dir(dir.join("headers"));
+        assert!(strict.get(&path).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
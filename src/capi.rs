@@ -0,0 +1,933 @@
+//! # C API
+//!
+//! A C ABI over the parser, declared in `include/gguf.h`, so C and C++ projects and other
+//! language runtimes can read GGUF headers without vendoring llama.cpp. Files are opened into
+//! an opaque handle that owns every string the API hands out; those pointers stay valid until
+//! the handle is freed with [`gguf_free`], or until the next edit of it. The metadata of a
+//! handle can be set and removed and the result written to a new file, the tensor data copied
+//! from the one opened. Functions that fail return `NULL` or `false` and leave a message for
+//! [`gguf_last_error`].
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::path::PathBuf;
+use std::ptr;
+
+use crate::validate::{validate_file, Severity, ValidationReport};
+use crate::writer::{encode_value, rewrite};
+use crate::{
+    GGUFFile, GGUFMetadataArrayValue, GGUFMetadataValue, GGUfMetadataValueType, ParseOptions,
+};
+
+/// The most dimensions a tensor has in ggml
+pub const GGUF_MAX_DIMS: usize = 4;
+
+/// An opened file: the parsed header with C copies of its strings
+pub struct GgufFile {
+    path: PathBuf,
+    file: GGUFFile,
+    data_start: u64,
+    keys: Vec<CString>,
+    /// The value of each string metadata entry, in metadata order.
+    strings: Vec<Option<CString>>,
+    /// Where each metadata entry starts in a file written from the handle.
+    offsets: Vec<u64>,
+    names: Vec<CString>,
+}
+
+impl GgufFile {
+    fn new(path: PathBuf, file: GGUFFile, data_start: u64) -> Self {
+        let names = file.tensors.iter().map(|t| c_string(&t.name)).collect();
+        let mut handle = GgufFile {
+            path,
+            file,
+            data_start,
+            keys: Vec::new(),
+            strings: Vec::new(),
+            offsets: Vec::new(),
+            names,
+        };
+        handle.refresh();
+        handle
+    }
+
+    /// Make the C copies of the metadata again after it changed
+    fn refresh(&mut self) {
+        let metadata = &self.file.header.metadata;
+        self.keys = metadata.iter().map(|m| c_string(&m.key)).collect();
+        self.strings = metadata
+            .iter()
+            .map(|m| m.value.as_str().map(c_string))
+            .collect();
+        // the magic, version and the two counts come first
+        let mut offset = 24;
+        let mut value = Vec::new();
+        self.offsets = metadata
+            .iter()
+            .map(|m| {
+                value.clear();
+                encode_value(&mut value, &m.value);
+                let start = offset;
+                offset += 8 + m.key.len() as u64 + 4 + value.len() as u64;
+                start
+            })
+            .collect();
+    }
+}
+
+/// A validation report with C copies of the strings of its findings
+pub struct GgufReport {
+    report: ValidationReport,
+    /// The code, message, key and tensor of each finding.
+    strings: Vec<[Option<CString>; 4]>,
+}
+
+/// A metadata value; which fields are set depends on `value_type`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GgufValue {
+    /// The GGUF value type, 0 for uint8 to 12 for float64.
+    pub value_type: u32,
+    /// The type of the items of an array.
+    pub item_type: u32,
+    /// The value of signed integer types.
+    pub int_value: i64,
+    /// The value of unsigned integer types, and 0 or 1 for booleans.
+    pub uint_value: u64,
+    /// The value of float types.
+    pub float_value: f64,
+    /// The value of strings, NUL-terminated and cut at any NUL within it.
+    pub string_value: *const c_char,
+    /// The length of a string in bytes or the number of items of an array.
+    pub len: u64,
+}
+
+/// A tensor info
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GgufTensorInfo {
+    /// The name, NUL-terminated.
+    pub name: *const c_char,
+    /// The number of dimensions used in `dims`.
+    pub n_dims: u32,
+    /// The dimensions, innermost first; unused ones are 1.
+    pub dims: [u64; GGUF_MAX_DIMS],
+    /// The ggml type of the data.
+    pub tensor_type: u32,
+    /// The offset of the data from the start of the tensor data.
+    pub offset: u64,
+    /// The size of the data in bytes, 0 if the shape does not fit the type's blocks.
+    pub size: u64,
+}
+
+/// A finding of a validation report
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GgufFinding {
+    /// 0 for info, 1 for warning and 2 for error.
+    pub severity: u32,
+    /// The stable identifier of the check, e.g. `duplicate-key`, NUL-terminated.
+    pub code: *const c_char,
+    /// A description of the problem, NUL-terminated.
+    pub message: *const c_char,
+    /// The byte offset in the file the finding refers to, or -1.
+    pub offset: i64,
+    /// The metadata key the finding is about, NUL-terminated, or `NULL`.
+    pub key: *const c_char,
+    /// The tensor the finding is about, NUL-terminated, or `NULL`.
+    pub tensor: *const c_char,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: impl Into<String>) {
+    let message = CString::new(message.into().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+/// A C copy of `text`, cut at the first NUL
+fn c_string(text: &str) -> CString {
+    let end = text.find('\0').unwrap_or(text.len());
+    CString::new(&text[..end]).unwrap_or_default()
+}
+
+/// The message of the last failure on this thread, or `NULL`; valid until the next call into
+/// the API on this thread
+#[no_mangle]
+pub extern "C" fn gguf_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Open a file and parse its header and tensor infos, returning `NULL` on failure
+///
+/// # Safety
+///
+/// `path` must be `NULL` or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_open(path: *const c_char) -> *mut GgufFile {
+    if path.is_null() {
+        set_error("the path is NULL");
+        return ptr::null_mut();
+    }
+    // SAFETY: the caller passes a NUL-terminated string
+    let path = unsafe { CStr::from_ptr(path) };
+    let Ok(path) = path.to_str() else {
+        set_error("the path is not UTF-8");
+        return ptr::null_mut();
+    };
+    let parsed = std::fs::File::open(path)
+        .map_err(|e| format!("{path}: {e}"))
+        .and_then(|mut f| {
+            GGUFFile::read_from(&mut f, &ParseOptions::default())
+                .map_err(|e| format!("{path}: {e}"))
+        });
+    let (file, data_start) = match parsed {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            set_error(message);
+            return ptr::null_mut();
+        }
+    };
+    Box::into_raw(Box::new(GgufFile::new(path.into(), file, data_start)))
+}
+
+/// Look up a metadata key, filling `out` and returning whether the key is there
+///
+/// # Safety
+///
+/// `file` must be `NULL` or a handle from [`gguf_open`] not yet freed, `key` `NULL` or a
+/// NUL-terminated string and `out` `NULL` or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_get_metadata(
+    file: *const GgufFile,
+    key: *const c_char,
+    out: *mut GgufValue,
+) -> bool {
+    if file.is_null() || key.is_null() || out.is_null() {
+        set_error("an argument is NULL");
+        return false;
+    }
+    // SAFETY: the caller passes a live handle and a NUL-terminated key
+    let (handle, key) = unsafe { (&*file, CStr::from_ptr(key)) };
+    let key = key.to_string_lossy();
+    let Some(index) = handle
+        .file
+        .header
+        .metadata
+        .iter()
+        .position(|m| m.key == key)
+    else {
+        set_error(format!("no metadata key {key}"));
+        return false;
+    };
+    let entry = &handle.file.header.metadata[index];
+    let string = handle.strings[index]
+        .as_ref()
+        .map(|s| (s.as_ptr(), s.as_bytes().len()));
+    // SAFETY: the caller passes memory valid for writes
+    unsafe { out.write(c_value(&entry.value, string)) };
+    true
+}
+
+/// The C form of `value`, with the pointer and length handed out for strings
+fn c_value(value: &GGUFMetadataValue, string: Option<(*const c_char, usize)>) -> GgufValue {
+    let mut out = GgufValue {
+        value_type: value.value_type() as u32,
+        item_type: 0,
+        int_value: 0,
+        uint_value: 0,
+        float_value: 0.0,
+        string_value: ptr::null(),
+        len: 0,
+    };
+    match value {
+        GGUFMetadataValue::Uint8(_)
+        | GGUFMetadataValue::Uint16(_)
+        | GGUFMetadataValue::Uint32(_)
+        | GGUFMetadataValue::Uint64(_) => out.uint_value = value.as_u64().unwrap_or(0),
+        GGUFMetadataValue::Int8(_)
+        | GGUFMetadataValue::Int16(_)
+        | GGUFMetadataValue::Int32(_)
+        | GGUFMetadataValue::Int64(_) => out.int_value = value.as_i64().unwrap_or(0),
+        GGUFMetadataValue::Float32(_) | GGUFMetadataValue::Float64(_) => {
+            out.float_value = value.as_f64().unwrap_or(0.0)
+        }
+        GGUFMetadataValue::Bool(b) => out.uint_value = u64::from(*b),
+        GGUFMetadataValue::String(_) => {
+            if let Some((ptr, len)) = string {
+                out.string_value = ptr;
+                out.len = len as u64;
+            }
+        }
+        GGUFMetadataValue::Array(array) => {
+            out.item_type = array.value_type as u32;
+            out.len = array.len;
+        }
+    }
+    out
+}
+
+/// Fill `out` with item `index` of the array at `key`, returning whether there is one
+///
+/// The `string_value` of string items points at `len` bytes of UTF-8 that are not
+/// NUL-terminated, since arrays such as the vocabulary hold too many strings to copy.
+///
+/// # Safety
+///
+/// As for [`gguf_get_metadata`].
+#[no_mangle]
+pub unsafe extern "C" fn gguf_get_array_item(
+    file: *const GgufFile,
+    key: *const c_char,
+    index: u64,
+    out: *mut GgufValue,
+) -> bool {
+    if file.is_null() || key.is_null() || out.is_null() {
+        set_error("an argument is NULL");
+        return false;
+    }
+    // SAFETY: the caller passes a live handle and a NUL-terminated key
+    let (handle, key) = unsafe { (&*file, CStr::from_ptr(key)) };
+    let key = key.to_string_lossy();
+    let Some(array) = handle.file.header.get(&key).and_then(|v| v.as_array()) else {
+        set_error(format!("no array at metadata key {key}"));
+        return false;
+    };
+    let index = usize::try_from(index).unwrap_or(usize::MAX);
+    let Some(item) = array.value.get(index) else {
+        set_error(format!("{key} has no item {index}"));
+        return false;
+    };
+    // the string of the file, not of the copy in `item`, which outlives the call
+    let string = array
+        .value
+        .get_str(index)
+        .map(|s| (s.as_ptr().cast::<c_char>(), s.len()));
+    // SAFETY: the caller passes memory valid for writes
+    unsafe { out.write(c_value(&item, string)) };
+    true
+}
+
+/// The number of metadata entries, 0 for `NULL`
+///
+/// # Safety
+///
+/// `file` must be `NULL` or a handle from [`gguf_open`] not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_metadata_count(file: *const GgufFile) -> u64 {
+    // SAFETY: the caller passes a live handle or NULL
+    unsafe { file.as_ref() }.map_or(0, |handle| handle.keys.len() as u64)
+}
+
+/// The key of metadata entry `index`, NUL-terminated, or `NULL` if there is none
+///
+/// # Safety
+///
+/// `file` must be `NULL` or a handle from [`gguf_open`] not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_metadata_key(file: *const GgufFile, index: u64) -> *const c_char {
+    // SAFETY: the caller passes a live handle or NULL
+    unsafe { file.as_ref() }
+        .and_then(|handle| handle.keys.get(usize::try_from(index).ok()?))
+        .map_or(ptr::null(), |key| key.as_ptr())
+}
+
+/// The offset in the file of metadata entry `index`, where its key starts, or 0 if there is
+/// none
+///
+/// After an edit this is the offset in a file [`gguf_write`] writes from the handle.
+///
+/// # Safety
+///
+/// `file` must be `NULL` or a handle from [`gguf_open`] not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_metadata_offset(file: *const GgufFile, index: u64) -> u64 {
+    // SAFETY: the caller passes a live handle or NULL
+    unsafe { file.as_ref() }
+        .and_then(|handle| handle.offsets.get(usize::try_from(index).ok()?))
+        .copied()
+        .unwrap_or(0)
+}
+
+/// The offset in the file where the tensor data starts, 0 for `NULL`
+///
+/// # Safety
+///
+/// `file` must be `NULL` or a handle from [`gguf_open`] not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_data_offset(file: *const GgufFile) -> u64 {
+    // SAFETY: the caller passes a live handle or NULL
+    unsafe { file.as_ref() }.map_or(0, |handle| handle.data_start)
+}
+
+/// The number of tensors, 0 for `NULL`
+///
+/// # Safety
+///
+/// `file` must be `NULL` or a handle from [`gguf_open`] not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_tensor_count(file: *const GgufFile) -> u64 {
+    // SAFETY: the caller passes a live handle or NULL
+    unsafe { file.as_ref() }.map_or(0, |handle| handle.file.tensors.len() as u64)
+}
+
+/// Fill `out` with the tensor info at `index`, returning whether there is one
+///
+/// # Safety
+///
+/// `file` must be `NULL` or a handle from [`gguf_open`] not yet freed and `out` `NULL` or
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_tensor_info(
+    file: *const GgufFile,
+    index: u64,
+    out: *mut GgufTensorInfo,
+) -> bool {
+    if file.is_null() || out.is_null() {
+        set_error("an argument is NULL");
+        return false;
+    }
+    // SAFETY: the caller passes a live handle
+    let handle = unsafe { &*file };
+    let Some(tensor) = usize::try_from(index)
+        .ok()
+        .and_then(|i| handle.file.tensors.get(i))
+    else {
+        set_error(format!("no tensor {index}"));
+        return false;
+    };
+    if tensor.dimensions.len() > GGUF_MAX_DIMS {
+        set_error(format!(
+            "tensor {} has over {GGUF_MAX_DIMS} dimensions",
+            tensor.name
+        ));
+        return false;
+    }
+    let mut dims = [1; GGUF_MAX_DIMS];
+    dims[..tensor.dimensions.len()].copy_from_slice(&tensor.dimensions);
+    let info = GgufTensorInfo {
+        name: handle.names[index as usize].as_ptr(),
+        n_dims: tensor.dimensions.len() as u32,
+        dims,
+        tensor_type: tensor.tensor_type as u32,
+        offset: tensor.offset,
+        size: tensor.size_bytes().unwrap_or(0),
+    };
+    // SAFETY: the caller passes memory valid for writes
+    unsafe { out.write(info) };
+    true
+}
+
+/// The Rust form of a scalar or string `value`, checking it fits its type
+///
+/// # Safety
+///
+/// The `string_value` of a string must point at `len` readable bytes.
+unsafe fn rust_value(value: &GgufValue) -> Result<GGUFMetadataValue, String> {
+    use GGUFMetadataValue as V;
+
+    let value_type = GGUfMetadataValueType::try_from(value.value_type)
+        .map_err(|_| format!("there is no value type {}", value.value_type))?;
+    let out_of_range = || format!("the value does not fit a {value_type:?}");
+    let (int, uint) = (value.int_value, value.uint_value);
+    Ok(match value_type {
+        GGUfMetadataValueType::Uint8 => V::Uint8(uint.try_into().map_err(|_| out_of_range())?),
+        GGUfMetadataValueType::Int8 => V::Int8(int.try_into().map_err(|_| out_of_range())?),
+        GGUfMetadataValueType::Uint16 => V::Uint16(uint.try_into().map_err(|_| out_of_range())?),
+        GGUfMetadataValueType::Int16 => V::Int16(int.try_into().map_err(|_| out_of_range())?),
+        GGUfMetadataValueType::Uint32 => V::Uint32(uint.try_into().map_err(|_| out_of_range())?),
+        GGUfMetadataValueType::Int32 => V::Int32(int.try_into().map_err(|_| out_of_range())?),
+        GGUfMetadataValueType::Uint64 => V::Uint64(uint),
+        GGUfMetadataValueType::Int64 => V::Int64(int),
+        GGUfMetadataValueType::Float32 => V::Float32(value.float_value as f32),
+        GGUfMetadataValueType::Float64 => V::Float64(value.float_value),
+        GGUfMetadataValueType::Bool => V::Bool(uint != 0),
+        GGUfMetadataValueType::String if value.string_value.is_null() && value.len > 0 => {
+            return Err("the string is NULL".to_string())
+        }
+        GGUfMetadataValueType::String if value.len == 0 => V::String(String::new()),
+        GGUfMetadataValueType::String => {
+            // SAFETY: the caller passes `len` readable bytes
+            let bytes = unsafe {
+                std::slice::from_raw_parts(value.string_value.cast::<u8>(), value.len as usize)
+            };
+            let text = std::str::from_utf8(bytes).map_err(|_| "the string is not UTF-8")?;
+            V::String(text.to_string())
+        }
+        GGUfMetadataValueType::Array => {
+            return Err("arrays are set with gguf_set_array".to_string())
+        }
+    })
+}
+
+/// Set the metadata key `key` to a number, boolean or string, replacing any value it has and
+/// otherwise adding it last, and return whether it was set
+///
+/// The fields of `value` are read as [`gguf_get_metadata`] fills them, strings as the `len`
+/// bytes of UTF-8 at `string_value`. The strings the handle handed out before are invalidated.
+///
+/// # Safety
+///
+/// `file` must be `NULL` or a handle from [`gguf_open`] not yet freed, `key` `NULL` or a
+/// NUL-terminated string and `value` `NULL` or a value whose string, if any, has `len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_set_metadata(
+    file: *mut GgufFile,
+    key: *const c_char,
+    value: *const GgufValue,
+) -> bool {
+    if file.is_null() || key.is_null() || value.is_null() {
+        set_error("an argument is NULL");
+        return false;
+    }
+    // SAFETY: the caller passes a live handle, a NUL-terminated key and a readable value
+    let (handle, key, value) = unsafe { (&mut *file, CStr::from_ptr(key), &*value) };
+    // SAFETY: the caller passes the bytes of the string
+    match unsafe { rust_value(value) } {
+        Ok(value) => {
+            handle.file.header.set(&key.to_string_lossy(), value);
+            handle.refresh();
+            true
+        }
+        Err(message) => {
+            set_error(message);
+            false
+        }
+    }
+}
+
+/// Set the metadata key `key` to an array of the `count` values at `items`, all of
+/// `item_type`, and return whether it was set; see [`gguf_set_metadata`]
+///
+/// # Safety
+///
+/// As for [`gguf_set_metadata`], with `items` `NULL` or `count` readable values.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_set_array(
+    file: *mut GgufFile,
+    key: *const c_char,
+    item_type: u32,
+    items: *const GgufValue,
+    count: u64,
+) -> bool {
+    if file.is_null() || key.is_null() || (items.is_null() && count > 0) {
+        set_error("an argument is NULL");
+        return false;
+    }
+    let Ok(value_type) = GGUfMetadataValueType::try_from(item_type) else {
+        set_error(format!("there is no value type {item_type}"));
+        return false;
+    };
+    // SAFETY: the caller passes a live handle and a NUL-terminated key
+    let (handle, key) = unsafe { (&mut *file, CStr::from_ptr(key)) };
+    let items = match count {
+        0 => &[][..],
+        // SAFETY: the caller passes `count` readable values
+        _ => unsafe { std::slice::from_raw_parts(items, count as usize) },
+    };
+    let mut values = Vec::with_capacity(items.len());
+    for (i, item) in items.iter().enumerate() {
+        if item.value_type != item_type {
+            set_error(format!("item {i} is not a {value_type:?}"));
+            return false;
+        }
+        // SAFETY: the caller passes the bytes of each string
+        match unsafe { rust_value(item) } {
+            Ok(value) => values.push(value),
+            Err(message) => {
+                set_error(format!("item {i}: {message}"));
+                return false;
+            }
+        }
+    }
+    let array = GGUFMetadataArrayValue::new(value_type, values);
+    handle
+        .file
+        .header
+        .set(&key.to_string_lossy(), GGUFMetadataValue::Array(array));
+    handle.refresh();
+    true
+}
+
+/// Remove the metadata key `key`, returning whether it was there
+///
+/// The strings the handle handed out before are invalidated.
+///
+/// # Safety
+///
+/// `file` must be `NULL` or a handle from [`gguf_open`] not yet freed and `key` `NULL` or a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_remove_metadata(file: *mut GgufFile, key: *const c_char) -> bool {
+    if file.is_null() || key.is_null() {
+        set_error("an argument is NULL");
+        return false;
+    }
+    // SAFETY: the caller passes a live handle and a NUL-terminated key
+    let (handle, key) = unsafe { (&mut *file, CStr::from_ptr(key)) };
+    let key = key.to_string_lossy();
+    let metadata = &mut handle.file.header.metadata;
+    let Some(index) = metadata.iter().position(|m| m.key == key) else {
+        set_error(format!("no metadata key {key}"));
+        return false;
+    };
+    metadata.remove(index);
+    handle.refresh();
+    true
+}
+
+/// Write the metadata and tensor infos of a handle to `path` with the tensor data of the file
+/// it was opened from, returning whether the file was written
+///
+/// `path` may be the file opened, which is replaced once the new one is complete; the handle
+/// goes on describing the file as it was opened.
+///
+/// # Safety
+///
+/// `file` must be `NULL` or a handle from [`gguf_open`] not yet freed and `path` `NULL` or a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_write(file: *const GgufFile, path: *const c_char) -> bool {
+    if file.is_null() || path.is_null() {
+        set_error("an argument is NULL");
+        return false;
+    }
+    // SAFETY: the caller passes a live handle and a NUL-terminated path
+    let (handle, path) = unsafe { (&*file, CStr::from_ptr(path)) };
+    let Ok(path) = path.to_str() else {
+        set_error("the path is not UTF-8");
+        return false;
+    };
+    match rewrite(&handle.path, path, &handle.file) {
+        Ok(()) => true,
+        Err(e) => {
+            set_error(format!("{path}: {e}"));
+            false
+        }
+    }
+}
+
+/// Free a handle from [`gguf_open`], invalidating the strings it handed out
+///
+/// # Safety
+///
+/// `file` must be `NULL` or a handle from [`gguf_open`] not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_free(file: *mut GgufFile) {
+    if !file.is_null() {
+        // SAFETY: the handle came from Box::into_raw in gguf_open and is freed once
+        drop(unsafe { Box::from_raw(file) });
+    }
+}
+
+/// Check a file against the spec, returning its report or `NULL` if it cannot be read
+///
+/// # Safety
+///
+/// `path` must be `NULL` or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_validate(path: *const c_char) -> *mut GgufReport {
+    if path.is_null() {
+        set_error("the path is NULL");
+        return ptr::null_mut();
+    }
+    // SAFETY: the caller passes a NUL-terminated string
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+        set_error("the path is not UTF-8");
+        return ptr::null_mut();
+    };
+    let report = match validate_file(path) {
+        Ok(report) => report,
+        Err(message) => {
+            set_error(format!("{path}: {message}"));
+            return ptr::null_mut();
+        }
+    };
+    let strings = report
+        .findings
+        .iter()
+        .map(|f| {
+            [
+                Some(c_string(f.code)),
+                Some(c_string(&f.message)),
+                f.key.as_deref().map(c_string),
+                f.tensor.as_deref().map(c_string),
+            ]
+        })
+        .collect();
+    Box::into_raw(Box::new(GgufReport { report, strings }))
+}
+
+/// Whether a report has no error-level finding, false for `NULL`
+///
+/// # Safety
+///
+/// `report` must be `NULL` or a report from [`gguf_validate`] not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_report_valid(report: *const GgufReport) -> bool {
+    // SAFETY: the caller passes a live report or NULL
+    unsafe { report.as_ref() }.is_some_and(|handle| handle.report.is_valid())
+}
+
+/// The number of findings of a report, 0 for `NULL`
+///
+/// # Safety
+///
+/// `report` must be `NULL` or a report from [`gguf_validate`] not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_report_count(report: *const GgufReport) -> u64 {
+    // SAFETY: the caller passes a live report or NULL
+    unsafe { report.as_ref() }.map_or(0, |handle| handle.report.findings.len() as u64)
+}
+
+/// Fill `out` with finding `index` of a report, returning whether there is one
+///
+/// # Safety
+///
+/// `report` must be `NULL` or a report from [`gguf_validate`] not yet freed and `out` `NULL`
+/// or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_report_finding(
+    report: *const GgufReport,
+    index: u64,
+    out: *mut GgufFinding,
+) -> bool {
+    if report.is_null() || out.is_null() {
+        set_error("an argument is NULL");
+        return false;
+    }
+    // SAFETY: the caller passes a live report
+    let handle = unsafe { &*report };
+    let Some(i) = usize::try_from(index)
+        .ok()
+        .filter(|&i| i < handle.report.findings.len())
+    else {
+        set_error(format!("no finding {index}"));
+        return false;
+    };
+    let finding = &handle.report.findings[i];
+    let [code, message, key, tensor] = handle.strings[i]
+        .each_ref()
+        .map(|s| s.as_ref().map_or(ptr::null(), |s| s.as_ptr()));
+    let out_finding = GgufFinding {
+        severity: match finding.severity {
+            Severity::Info => 0,
+            Severity::Warning => 1,
+            Severity::Error => 2,
+        },
+        code,
+        message,
+        offset: finding
+            .offset
+            .and_then(|o| i64::try_from(o).ok())
+            .unwrap_or(-1),
+        key,
+        tensor,
+    };
+    // SAFETY: the caller passes memory valid for writes
+    unsafe { out.write(out_finding) };
+    true
+}
+
+/// Free a report from [`gguf_validate`], invalidating the strings it handed out
+///
+/// # Safety
+///
+/// `report` must be `NULL` or a report from [`gguf_validate`] not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_report_free(report: *mut GgufReport) {
+    if !report.is_null() {
+        // SAFETY: the report came from Box::into_raw in gguf_validate and is freed once
+        drop(unsafe { Box::from_raw(report) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path, tensor};
+    use crate::GGMLType;
+
+    #[test]
+    fn open_read_and_free() {
+        let header = header(&[
+            (
+                "general.architecture",
+                GGUFMetadataValue::String("llama".into()),
+            ),
+            ("llama.rope.freq_base", GGUFMetadataValue::Float32(0.5)),
+            (
+                "tokenizer.ggml.tokens",
+                GGUFMetadataValue::Array(crate::GGUFMetadataArrayValue::new(
+                    crate::GGUfMetadataValueType::String,
+                    vec![GGUFMetadataValue::String("▁hi".into())],
+                )),
+            ),
+        ]);
+        let mut file = file(
+            header,
+            vec![tensor("output.weight", &[32, 2], GGMLType::Q8_0, 0)],
+        );
+        crate::writer::assign_offsets(&mut file).unwrap();
+        let mut buf = Vec::new();
+        crate::writer::write_header(&mut buf, &file).unwrap();
+        let path = temp_path("gguf-capi.gguf");
+        std::fs::write(&path, buf).unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let handle = gguf_open(c_path.as_ptr());
+            assert!(!handle.is_null());
+            let mut value = std::mem::zeroed::<GgufValue>();
+            assert!(gguf_get_metadata(
+                handle,
+                c"general.architecture".as_ptr(),
+                &mut value
+            ));
+            assert_eq!(value.value_type, 8);
+            assert_eq!(CStr::from_ptr(value.string_value), c"llama");
+            assert!(gguf_get_metadata(
+                handle,
+                c"llama.rope.freq_base".as_ptr(),
+                &mut value
+            ));
+            assert_eq!(value.float_value, 0.5);
+            assert!(!gguf_get_metadata(handle, c"missing".as_ptr(), &mut value));
+            assert_eq!(
+                CStr::from_ptr(gguf_last_error()),
+                c"no metadata key missing"
+            );
+
+            let tokens = c"tokenizer.ggml.tokens".as_ptr();
+            assert!(gguf_get_array_item(handle, tokens, 0, &mut value));
+            let item =
+                std::slice::from_raw_parts(value.string_value.cast::<u8>(), value.len as usize);
+            assert_eq!(item, "▁hi".as_bytes());
+            assert!(!gguf_get_array_item(handle, tokens, 1, &mut value));
+
+            assert_eq!(gguf_metadata_count(handle), 3);
+            assert_eq!(
+                CStr::from_ptr(gguf_metadata_key(handle, 1)),
+                c"llama.rope.freq_base"
+            );
+            assert!(gguf_metadata_key(handle, 3).is_null());
+            assert_eq!(gguf_data_offset(handle) % 32, 0);
+
+            assert_eq!(gguf_tensor_count(handle), 1);
+            let mut info = std::mem::zeroed::<GgufTensorInfo>();
+            assert!(gguf_tensor_info(handle, 0, &mut info));
+            assert_eq!(CStr::from_ptr(info.name), c"output.weight");
+            assert_eq!((info.n_dims, info.dims), (2, [32, 2, 1, 1]));
+            assert_eq!((info.tensor_type, info.size), (8, 68));
+            assert!(!gguf_tensor_info(handle, 1, &mut info));
+            assert_eq!(gguf_metadata_offset(handle, 0), 24);
+            assert_eq!(gguf_metadata_offset(handle, 1), 24 + 28 + 4 + 13);
+            gguf_free(handle);
+
+            assert!(gguf_open(c"/nonexistent.gguf".as_ptr()).is_null());
+            assert!(!gguf_last_error().is_null());
+
+            let report = gguf_validate(c_path.as_ptr());
+            assert!(!report.is_null());
+            let mut finding = std::mem::zeroed::<GgufFinding>();
+            for i in 0..gguf_report_count(report) {
+                assert!(gguf_report_finding(report, i, &mut finding));
+                assert!(!CStr::from_ptr(finding.code).to_bytes().is_empty());
+            }
+            assert!(!gguf_report_finding(
+                report,
+                gguf_report_count(report),
+                &mut finding
+            ));
+            gguf_report_free(report);
+            assert!(gguf_validate(c"/nonexistent.gguf".as_ptr()).is_null());
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn set_remove_and_write() {
+        let header = header(&[
+            ("general.name", GGUFMetadataValue::String("old".into())),
+            ("general.license", GGUFMetadataValue::String("mit".into())),
+        ]);
+        let mut file = file(
+            header,
+            vec![tensor("output.weight", &[4], GGMLType::F32, 0)],
+        );
+        crate::writer::assign_offsets(&mut file).unwrap();
+        let mut buf = Vec::new();
+        crate::writer::write_header(&mut buf, &file).unwrap();
+        buf.resize(buf.len().next_multiple_of(32), 0);
+        buf.extend([1.0f32, 2.0, 3.0, 4.0].iter().flat_map(|v| v.to_le_bytes()));
+        let src = temp_path("gguf-capi-edit.gguf");
+        let dst = temp_path("gguf-capi-edited.gguf");
+        std::fs::write(&src, &buf).unwrap();
+        let c_src = CString::new(src.to_str().unwrap()).unwrap();
+        let c_dst = CString::new(dst.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let handle = gguf_open(c_src.as_ptr());
+            let mut value = std::mem::zeroed::<GgufValue>();
+            value.value_type = 8;
+            value.string_value = c"new".as_ptr();
+            value.len = 3;
+            assert!(gguf_set_metadata(handle, c"general.name".as_ptr(), &value));
+            value = std::mem::zeroed();
+            value.value_type = 4;
+            value.uint_value = 4096;
+            assert!(gguf_set_metadata(
+                handle,
+                c"llama.context_length".as_ptr(),
+                &value
+            ));
+            value.value_type = 0;
+            value.uint_value = 256;
+            assert!(!gguf_set_metadata(handle, c"bad".as_ptr(), &value));
+            assert_eq!(
+                CStr::from_ptr(gguf_last_error()),
+                c"the value does not fit a Uint8"
+            );
+            let mut items = [std::mem::zeroed::<GgufValue>(); 2];
+            for (item, v) in items.iter_mut().zip([-1, 2]) {
+                item.value_type = 5;
+                item.int_value = v;
+            }
+            let ids = c"tokenizer.ggml.token_type".as_ptr();
+            assert!(gguf_set_array(handle, ids, 5, items.as_ptr(), 2));
+            assert!(!gguf_set_array(handle, ids, 4, items.as_ptr(), 2));
+            assert!(gguf_remove_metadata(handle, c"general.license".as_ptr()));
+            assert!(!gguf_remove_metadata(handle, c"general.license".as_ptr()));
+            assert_eq!(gguf_metadata_count(handle), 3);
+            assert!(gguf_write(handle, c_dst.as_ptr()));
+            assert!(!gguf_write(handle, c"/nonexistent/out.gguf".as_ptr()));
+            gguf_free(handle);
+        }
+
+        let mut input = std::fs::File::open(&dst).unwrap();
+        let (edited, start) = GGUFFile::read_from(&mut input, &ParseOptions::default()).unwrap();
+        let header = &edited.header;
+        assert_eq!(
+            header.get("general.name").and_then(|v| v.as_str()),
+            Some("new")
+        );
+        assert_eq!(
+            header.get("llama.context_length").and_then(|v| v.as_u64()),
+            Some(4096)
+        );
+        let ids = header
+            .get("tokenizer.ggml.token_type")
+            .and_then(|v| v.as_array());
+        assert_eq!(
+            ids.and_then(|a| a.value.get(0)),
+            Some(GGUFMetadataValue::Int32(-1))
+        );
+        assert!(header.get("general.license").is_none());
+        let written = std::fs::read(&dst).unwrap();
+        assert_eq!(written[start as usize..][..16], buf[buf.len() - 16..]);
+        std::fs::remove_file(src).unwrap();
+        std::fs::remove_file(dst).unwrap();
+    }
+}
@@ -0,0 +1,379 @@
+//! # Metadata catalogs
+//!
+//! Turns the headers of many GGUF files into one table, a row per model or a row per key, and
+//! writes it as CSV or JSON Lines, which DuckDB, Polars and pandas read as they are.
+//!
+//! [`Catalog`] finds the models in the folders of LM Studio, Jan and llama.cpp, for model
+//! managers to list, and [`scan_dir_parallel`] reads every header under a folder on several
+//! threads. [`ModelSet`] holds the headers of many files in memory and answers queries across
+//! them, such as the Qwen 2 models with 32K of context, grouped by quantization.
+use std::io::Write;
+
+use crate::{GGUFFile, GGUFMetadataValue};
+
+mod parallel;
+pub use parallel::{scan_dir_parallel, ScannedFile};
+#[cfg(feature = "json")]
+mod scan;
+mod set;
+#[cfg(feature = "json")]
+pub use scan::{Catalog, CatalogEntry, ModelInfo, Source};
+pub use set::{Model, ModelSet, Query};
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+/// What a row of the catalog describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// One row per file, with its architecture, size and main hyperparameters.
+    Models,
+    /// One row per metadata key of each file.
+    Keys,
+}
+
+/// The values of a column, `None` for nulls
+#[derive(Debug, Clone, PartialEq)]
+pub enum Values {
+    Int64(Vec<Option<i64>>),
+    Utf8(Vec<Option<String>>),
+}
+
+impl Values {
+    fn len(&self) -> usize {
+        match self {
+            Values::Int64(values) => values.len(),
+            Values::Utf8(values) => values.len(),
+        }
+    }
+
+    /// The value of row `i` as text, `None` for a null
+    fn text(&self, i: usize) -> Option<String> {
+        match self {
+            Values::Int64(values) => values[i].map(|v| v.to_string()),
+            Values::Utf8(values) => values[i].clone(),
+        }
+    }
+}
+
+/// A named column of the catalog
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub values: Values,
+}
+
+fn int(name: &str, values: Vec<Option<i64>>) -> Column {
+    Column {
+        name: name.to_string(),
+        values: Values::Int64(values),
+    }
+}
+
+fn utf8(name: &str, values: Vec<Option<String>>) -> Column {
+    Column {
+        name: name.to_string(),
+        values: Values::Utf8(values),
+    }
+}
+
+/// A scalar value as text, `None` for arrays
+fn scalar(value: &GGUFMetadataValue) -> Option<String> {
+    Some(match value {
+        GGUFMetadataValue::String(s) => s.clone(),
+        GGUFMetadataValue::Bool(b) => b.to_string(),
+        GGUFMetadataValue::Float32(f) => f.to_string(),
+        GGUFMetadataValue::Float64(f) => f.to_string(),
+        GGUFMetadataValue::Array(_) => return None,
+        value => value.as_i64()?.to_string(),
+    })
+}
+
+/// The catalog of `files`, each given with the path or URL it was read from and its length
+/// if known
+pub fn catalog(files: &[(&str, Option<u64>, &GGUFFile)], layout: Layout) -> Vec<Column> {
+    match layout {
+        Layout::Models => {
+            let text = |key: &str| -> Vec<Option<String>> {
+                files
+                    .iter()
+                    .map(|(_, _, f)| {
+                        f.header
+                            .get(key)
+                            .and_then(|v| v.as_str())
+                            .map(str::to_string)
+                    })
+                    .collect()
+            };
+            let arch_number = |name: &str| -> Vec<Option<i64>> {
+                files
+                    .iter()
+                    .map(|(_, _, f)| {
+                        let arch = f.header.get("general.architecture")?.as_str()?;
+                        f.header.get(&format!("{arch}.{name}"))?.as_i64()
+                    })
+                    .collect()
+            };
+            vec![
+                utf8(
+                    "path",
+                    files.iter().map(|(p, _, _)| Some(p.to_string())).collect(),
+                ),
+                utf8("architecture", text("general.architecture")),
+                utf8("name", text("general.name")),
+                int(
+                    "file_type",
+                    files
+                        .iter()
+                        .map(|(_, _, f)| f.header.get("general.file_type")?.as_i64())
+                        .collect(),
+                ),
+                int(
+                    "file_size",
+                    files
+                        .iter()
+                        .map(|(_, len, _)| len.and_then(|len| i64::try_from(len).ok()))
+                        .collect(),
+                ),
+                int(
+                    "parameters",
+                    files
+                        .iter()
+                        .map(|(_, _, f)| {
+                            let count: u64 = f.tensors.iter().map(|t| t.element_count()).sum();
+                            i64::try_from(count).ok()
+                        })
+                        .collect(),
+                ),
+                int(
+                    "tensor_count",
+                    files
+                        .iter()
+                        .map(|(_, _, f)| Some(f.tensors.len() as i64))
+                        .collect(),
+                ),
+                int(
+                    "metadata_count",
+                    files
+                        .iter()
+                        .map(|(_, _, f)| Some(f.header.metadata.len() as i64))
+                        .collect(),
+                ),
+                int("context_length", arch_number("context_length")),
+                int("embedding_length", arch_number("embedding_length")),
+                int("block_count", arch_number("block_count")),
+            ]
+        }
+        Layout::Keys => {
+            let (mut path, mut key, mut kind, mut value, mut length) =
+                (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+            for (p, _, file) in files {
+                for entry in &file.header.metadata {
+                    path.push(Some(p.to_string()));
+                    key.push(Some(entry.key.clone()));
+                    kind.push(Some(match &entry.value {
+                        GGUFMetadataValue::Array(array) => format!("Array[{:?}]", array.value_type),
+                        value => format!("{:?}", value.value_type()),
+                    }));
+                    value.push(scalar(&entry.value));
+                    length.push(match &entry.value {
+                        GGUFMetadataValue::Array(array) => i64::try_from(array.len).ok(),
+                        _ => None,
+                    });
+                }
+            }
+            vec![
+                utf8("path", path),
+                utf8("key", key),
+                utf8("type", kind),
+                utf8("value", value),
+                int("array_length", length),
+            ]
+        }
+    }
+}
+
+/// The rows of `columns`, after checking they all have as many
+fn rows(columns: &[Column]) -> Result<usize, String> {
+    let rows = columns.first().map_or(0, |c| c.values.len());
+    match columns.iter().find(|c| c.values.len() != rows) {
+        Some(column) => Err(format!(
+            "column {} has {} rows, not {rows}",
+            column.name,
+            column.values.len()
+        )),
+        None => Ok(rows),
+    }
+}
+
+/// A CSV field, quoted if it holds a comma, a quote or a line break
+fn csv_field(field: &str, out: &mut String) {
+    if field.contains([',', '"', '\r', '\n']) {
+        out.push('"');
+        out.push_str(&field.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(field);
+    }
+}
+
+/// Write `columns`, all of the same length, as CSV with a header row, nulls as empty fields
+pub fn write_csv(columns: &[Column], mut out: impl Write) -> Result<(), String> {
+    let rows = rows(columns)?;
+    let mut line = String::new();
+    for (i, column) in columns.iter().enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+        csv_field(&column.name, &mut line);
+    }
+    line.push('\n');
+    for row in 0..rows {
+        out.write_all(line.as_bytes()).map_err(|e| e.to_string())?;
+        line.clear();
+        for (i, column) in columns.iter().enumerate() {
+            if i > 0 {
+                line.push(',');
+            }
+            csv_field(&column.values.text(row).unwrap_or_default(), &mut line);
+        }
+        line.push('\n');
+    }
+    out.write_all(line.as_bytes()).map_err(|e| e.to_string())
+}
+
+/// A JSON string literal
+fn json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Write `columns`, all of the same length, as JSON Lines, an object per row keyed by column
+pub fn write_jsonl(columns: &[Column], mut out: impl Write) -> Result<(), String> {
+    let rows = rows(columns)?;
+    let mut line = String::new();
+    for row in 0..rows {
+        line.clear();
+        line.push('{');
+        for (i, column) in columns.iter().enumerate() {
+            if i > 0 {
+                line.push_str(", ");
+            }
+            json_string(&column.name, &mut line);
+            line.push_str(": ");
+            match &column.values {
+                Values::Int64(values) => match values[row] {
+                    Some(v) => line.push_str(&v.to_string()),
+                    None => line.push_str("null"),
+                },
+                Values::Utf8(values) => match &values[row] {
+                    Some(v) => json_string(v, &mut line),
+                    None => line.push_str("null"),
+                },
+            }
+        }
+        line.push_str("}\n");
+        out.write_all(line.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header};
+    use crate::{GGUFMetadataArrayValue, GGUfMetadataValueType};
+
+    #[test]
+    fn catalog_rows_as_csv_and_jsonl() {
+        let file = file(
+            header(&[
+                (
+                    "general.architecture",
+                    GGUFMetadataValue::String("llama".into()),
+                ),
+                ("llama.context_length", GGUFMetadataValue::Uint32(4096)),
+                (
+                    "tokenizer.ggml.tokens",
+                    GGUFMetadataValue::Array(GGUFMetadataArrayValue::new(
+                        GGUfMetadataValueType::String,
+                        vec![GGUFMetadataValue::String("a".into())],
+                    )),
+                ),
+            ]),
+            Vec::new(),
+        );
+        let files = [("a.gguf", Some(10), &file), ("b.gguf", None, &file)];
+        let models = catalog(&files, Layout::Models);
+        let column = |columns: &[Column], name: &str| {
+            columns
+                .iter()
+                .find(|c| c.name == name)
+                .unwrap()
+                .values
+                .clone()
+        };
+        assert_eq!(
+            column(&models, "context_length"),
+            Values::Int64(vec![Some(4096), Some(4096)])
+        );
+        assert_eq!(column(&models, "name"), Values::Utf8(vec![None, None]));
+        let keys = catalog(&files, Layout::Keys);
+        assert_eq!(
+            column(&keys, "type"),
+            Values::Utf8(
+                ["String", "Uint32", "Array[String]"]
+                    .repeat(2)
+                    .into_iter()
+                    .map(|t| Some(t.to_string()))
+                    .collect()
+            )
+        );
+        assert_eq!(
+            column(&keys, "array_length"),
+            Values::Int64([None, None, Some(1)].repeat(2))
+        );
+
+        let mut csv = Vec::new();
+        write_csv(&keys, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], "path,key,type,value,array_length");
+        assert_eq!(lines[2], "a.gguf,llama.context_length,Uint32,4096,");
+        assert_eq!(lines[3], "a.gguf,tokenizer.ggml.tokens,Array[String],,1");
+        assert_eq!(lines.len(), 7);
+        let names = [Column {
+            name: "name".to_string(),
+            values: Values::Utf8(vec![Some("a, \"b\"\nc".to_string()), None]),
+        }];
+        let mut csv = Vec::new();
+        write_csv(&names, &mut csv).unwrap();
+        assert_eq!(csv, b"name\n\"a, \"\"b\"\"\nc\"\n\n");
+
+        let mut jsonl = Vec::new();
+        write_jsonl(&names, &mut jsonl).unwrap();
+        assert_eq!(
+            jsonl,
+            b"{\"name\": \"a, \\\"b\\\"\\nc\"}\n{\"name\": null}\n"
+        );
+        let mut jsonl = Vec::new();
+        write_jsonl(&models, &mut jsonl).unwrap();
+        let first = String::from_utf8(jsonl).unwrap();
+        let first = first.lines().next().unwrap();
+        assert!(first.starts_with(r#"{"path": "a.gguf", "architecture": "llama", "name": null"#));
+        assert!(first.ends_with(
+            r#""context_length": 4096, "embedding_length": null, "block_count": null}"#
+        ));
+        assert!(write_csv(&[models[0].clone(), keys[0].clone()], Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+//! Reading the headers of many files at once
+//!
+//! Refreshing a catalog of hundreds of models is mostly waiting on the disk for the first
+//! megabytes of each file, so the headers are read on a few threads at once. The files are
+//! taken in the order they lie on the disk where the platform tells it, by inode on unix,
+//! which keeps a spinning disk or a network share from seeking back and forth.
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{GGUFFile, ParseOptions};
+
+/// The threads used when none are asked for, as most disks gain little past that.
+const DEFAULT_THREADS: usize = 8;
+
+/// A file found by [`scan_dir_parallel`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannedFile {
+    pub path: PathBuf,
+    /// The length of the file, 0 if unknown.
+    pub len: u64,
+    /// The header and the offset of the tensor data, or why the file could not be read.
+    pub parsed: Result<(GGUFFile, u64), String>,
+}
+
+/// Read the header of every `.gguf` file under `dir`, skipping hidden folders, on up to
+/// `threads` threads, 0 for one per core up to 8; the files are sorted by path
+pub fn scan_dir_parallel(
+    dir: impl AsRef<Path>,
+    options: &ParseOptions,
+    threads: usize,
+) -> Result<Vec<ScannedFile>, String> {
+    let mut paths = Vec::new();
+    walk(dir.as_ref(), &mut paths)?;
+    let order = disk_order(&paths);
+    Ok(parallel_map(&paths, &order, threads, |path| {
+        let len = std::fs::metadata(path).map_or(0, |m| m.len());
+        let parsed = File::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|mut f| GGUFFile::read_from(&mut f, options).map_err(|e| e.to_string()));
+        ScannedFile {
+            path: path.clone(),
+            len,
+            parsed,
+        }
+    }))
+}
+
+/// The `.gguf` files under `dir` sorted by path, skipping hidden folders
+pub(super) fn walk(dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("{}: {e}", dir.display())),
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("{}: {e}", dir.display()))?;
+    paths.sort();
+    for path in paths {
+        let hidden = path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+        if path.is_dir() && !hidden {
+            walk(&path, out)?;
+        } else if path.extension().is_some_and(|e| e == "gguf") && path.is_file() {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The indices of `paths` in the order their files lie on the disk, as far as it is known
+pub(super) fn disk_order(paths: &[PathBuf]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..paths.len()).collect();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let keys: Vec<(u64, u64)> = paths
+            .iter()
+            .map(|p| std::fs::metadata(p).map_or((u64::MAX, 0), |m| (m.dev(), m.ino())))
+            .collect();
+        order.sort_by_key(|&i| keys[i]);
+    }
+    order
+}
+
+/// `f` of each of `items` on up to `threads` threads, 0 for the default, the items taken in
+/// `order`; the results are in the order of `items`
+pub(super) fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    order: &[usize],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get().min(DEFAULT_THREADS)),
+        n => n,
+    }
+    .min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let mut done: Vec<(usize, R)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    while let Some(&i) = order.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        done.push((i, f(&items[i])));
+                    }
+                    done
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+    done.sort_by_key(|(i, _)| *i);
+    done.into_iter().map(|(_, r)| r).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path};
+    use crate::GGUFMetadataValue;
+
+    #[test]
+    fn reads_headers_on_threads() {
+        let dir = temp_path("catalog-parallel");
+        for i in 0..40 {
+            let file = file(
+                header(&[(
+                    "general.name",
+                    GGUFMetadataValue::String(format!("model {i:02}")),
+                )]),
+                vec![],
+            );
+            let path = dir.join(format!("m{}/model-{i:02}.gguf", i % 3));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let mut out = Vec::new();
+            crate::writer::write_header(&mut out, &file).unwrap();
+            std::fs::write(path, out).unwrap();
+        }
+        std::fs::write(dir.join("m0/junk.gguf"), b"junk").unwrap();
+
+        let serial = scan_dir_parallel(&dir, &ParseOptions::default(), 1).unwrap();
+        let parallel = scan_dir_parallel(&dir, &ParseOptions::default(), 4).unwrap();
+        assert_eq!(parallel.len(), 41);
+        assert_eq!(parallel, serial);
+        assert!(parallel.windows(2).all(|w| w[0].path < w[1].path));
+        assert!(parallel[0].parsed.is_err());
+        let (file, _) = parallel[1].parsed.as_ref().unwrap();
+        let name = file.header.get("general.name").and_then(|v| v.as_str());
+        assert_eq!(name, Some("model 00"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
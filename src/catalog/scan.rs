@@ -0,0 +1,362 @@
+//! Scanning the model folders of desktop apps into a catalog of models
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use super::parallel::{disk_order, parallel_map, walk};
+use crate::split::split_prefix;
+use crate::{GGUFFile, ParseOptions};
+
+/// The app a model folder belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Source {
+    /// `~/.lmstudio/models`, or `~/.cache/lm-studio/models` before LM Studio 0.3, holding
+    /// `<publisher>/<repository>/<file>.gguf`.
+    LmStudio,
+    /// `~/jan/models`, a folder per model.
+    Jan,
+    /// The download cache of llama.cpp's `-hf` option, `$LLAMA_CACHE` or `~/.cache/llama.cpp`.
+    LlamaCpp,
+    /// Any other folder.
+    Directory,
+}
+
+impl Source {
+    /// The name it is serialized with, such as `lm-studio`
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::LmStudio => "lm-studio",
+            Source::Jan => "jan",
+            Source::LlamaCpp => "llama-cpp",
+            Source::Directory => "directory",
+        }
+    }
+}
+
+/// What the header of a model tells about it
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ModelInfo {
+    pub architecture: Option<String>,
+    /// `general.name`.
+    pub name: Option<String>,
+    /// `general.file_type`, named by [`crate::ggml::ftype_name`].
+    pub file_type: Option<u32>,
+    /// The number of elements of all tensors.
+    pub parameters: u64,
+    pub tensor_count: u64,
+    pub context_length: Option<u64>,
+}
+
+impl ModelInfo {
+    fn of(file: &GGUFFile) -> Self {
+        let header = &file.header;
+        let text = |key: &str| header.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        let architecture = text("general.architecture");
+        let context_length = architecture.as_ref().and_then(|arch| {
+            header
+                .get(&format!("{arch}.context_length"))
+                .and_then(|v| v.as_u64())
+        });
+        ModelInfo {
+            name: text("general.name"),
+            file_type: header
+                .get("general.file_type")
+                .and_then(|v| v.as_u64())
+                .and_then(|t| u32::try_from(t).ok()),
+            parameters: file.tensors.iter().map(|t| t.element_count()).sum(),
+            tensor_count: file.tensors.len() as u64,
+            context_length,
+            architecture,
+        }
+    }
+}
+
+/// A model of the catalog: one file, or the shards of a split
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct CatalogEntry {
+    /// The path relative to the folder without `.gguf`, or the shard prefix of a split, such
+    /// as `lmstudio-community/Qwen2.5-7B-Instruct-GGUF/Qwen2.5-7B-Instruct-Q4_K_M`.
+    pub name: String,
+    pub source: Source,
+    /// The files, shards in order.
+    pub files: Vec<PathBuf>,
+    /// The shards of a split not found, counting from 0.
+    pub missing_shards: Vec<u16>,
+    /// The size of all files in bytes.
+    pub size: u64,
+    /// The metadata of the first file, with the tensors of all files counted.
+    pub info: ModelInfo,
+    /// Why a file could not be read, if one could not.
+    pub error: Option<String>,
+}
+
+/// What the cache keeps per file, reused while its length and modification time stay
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct Cached {
+    len: u64,
+    modified: u64,
+    info: Option<ModelInfo>,
+    error: Option<String>,
+}
+
+/// The models in a set of folders, for model managers to list
+///
+/// ```no_run
+/// let models = gguf::catalog::Catalog::default_dirs()
+///     .cache("catalog-cache.json")
+///     .scan()?;
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    dirs: Vec<(Source, PathBuf)>,
+    cache: Option<PathBuf>,
+    threads: usize,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A catalog of the model folders of LM Studio, Jan and llama.cpp that exist
+    pub fn default_dirs() -> Self {
+        let home = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(PathBuf::from);
+        let mut dirs = Vec::new();
+        if let Some(home) = &home {
+            dirs.push((Source::LmStudio, home.join(".lmstudio/models")));
+            dirs.push((Source::LmStudio, home.join(".cache/lm-studio/models")));
+            dirs.push((Source::Jan, home.join("jan/models")));
+        }
+        match std::env::var_os("LLAMA_CACHE") {
+            Some(dir) => dirs.push((Source::LlamaCpp, PathBuf::from(dir))),
+            None => {
+                let cache = std::env::var_os("XDG_CACHE_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| home.as_ref().map(|h| h.join(".cache")));
+                dirs.extend(cache.map(|c| (Source::LlamaCpp, c.join("llama.cpp"))));
+                dirs.extend(home.map(|h| (Source::LlamaCpp, h.join("Library/Caches/llama.cpp"))));
+            }
+        }
+        dirs.retain(|(_, dir)| dir.is_dir());
+        Catalog {
+            dirs,
+            ..Self::default()
+        }
+    }
+
+    /// Scan `dir` too
+    pub fn dir(mut self, source: Source, dir: impl Into<PathBuf>) -> Self {
+        self.dirs.push((source, dir.into()));
+        self
+    }
+
+    /// Keep what is read of each file in the JSON file `path`, reading again only files that
+    /// changed since
+    pub fn cache(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cache = Some(path.into());
+        self
+    }
+
+    /// Read headers on up to `threads` threads; 0, the default, is one per core up to 8
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// The folders scanned
+    pub fn dirs(&self) -> &[(Source, PathBuf)] {
+        &self.dirs
+    }
+
+    /// Find the models in the folders, sorted by folder then name; files that cannot be read
+    /// are listed with their error
+    ///
+    /// The headers are read on several threads, see [`threads`](Catalog::threads).
+    pub fn scan(&self) -> Result<Vec<CatalogEntry>, String> {
+        let cache: BTreeMap<PathBuf, Cached> = self
+            .cache
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        let mut entries = Vec::new();
+        for (source, dir) in &self.dirs {
+            let mut files = Vec::new();
+            walk(dir, &mut files)?;
+            for model in group(dir, files) {
+                entries.push(CatalogEntry {
+                    name: model.name,
+                    source: *source,
+                    missing_shards: (0..model.count)
+                        .filter(|no| !model.files.iter().any(|(n, _)| n == no))
+                        .collect(),
+                    files: model.files.into_iter().map(|(_, path)| path).collect(),
+                    size: 0,
+                    info: ModelInfo::default(),
+                    error: None,
+                });
+            }
+        }
+        let paths: Vec<PathBuf> = entries.iter().flat_map(|e| e.files.clone()).collect();
+        let read = parallel_map(&paths, &disk_order(&paths), self.threads, |path| {
+            read(path, cache.get(path))
+        });
+        let seen: BTreeMap<PathBuf, Cached> = paths.into_iter().zip(read).collect();
+        for entry in &mut entries {
+            for (i, path) in entry.files.iter().enumerate() {
+                let file = &seen[path];
+                entry.size += file.len;
+                match (&file.info, i) {
+                    (Some(info), 0) => entry.info = info.clone(),
+                    (Some(info), _) => {
+                        entry.info.parameters += info.parameters;
+                        entry.info.tensor_count += info.tensor_count;
+                    }
+                    (None, _) => {}
+                }
+                entry.error = entry.error.take().or_else(|| file.error.clone());
+            }
+        }
+        if let Some(path) = &self.cache {
+            let json = serde_json::to_vec(&seen).map_err(|e| e.to_string())?;
+            let mut partial = path.as_os_str().to_owned();
+            partial.push(".partial");
+            std::fs::write(&partial, json)
+                .and_then(|()| std::fs::rename(&partial, path))
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+        Ok(entries)
+    }
+}
+
+/// The files of one model, with their shard numbers, and its shard count, 0 if not split
+struct Group {
+    name: String,
+    files: Vec<(u16, PathBuf)>,
+    count: u16,
+}
+
+/// The models among `files`, sorted by name
+fn group(dir: &Path, files: Vec<PathBuf>) -> Vec<Group> {
+    let mut models: Vec<Group> = Vec::new();
+    for path in files {
+        let relative = path.strip_prefix(dir).unwrap_or(&path).to_string_lossy();
+        let relative = relative.replace('\\', "/");
+        match split_prefix(&relative) {
+            Some((prefix, no, count)) => {
+                match models
+                    .iter_mut()
+                    .find(|m| m.name == prefix && m.count == count)
+                {
+                    Some(model) => model.files.push((no, path.clone())),
+                    None => models.push(Group {
+                        name: prefix.to_string(),
+                        files: vec![(no, path.clone())],
+                        count,
+                    }),
+                }
+            }
+            None => models.push(Group {
+                name: relative
+                    .strip_suffix(".gguf")
+                    .unwrap_or(&relative)
+                    .to_string(),
+                files: vec![(0, path.clone())],
+                count: 0,
+            }),
+        }
+    }
+    for model in &mut models {
+        model.files.sort();
+    }
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    models
+}
+
+/// The modification time of a file in seconds since the epoch, 0 if unknown
+pub(super) fn modified(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
+}
+
+/// What is known of the file at `path`, from `cached` if it did not change
+fn read(path: &Path, cached: Option<&Cached>) -> Cached {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            return Cached {
+                len: 0,
+                modified: 0,
+                info: None,
+                error: Some(e.to_string()),
+            }
+        }
+    };
+    let modified = modified(&metadata);
+    if let Some(cached) = cached.filter(|c| c.len == metadata.len() && c.modified == modified) {
+        return cached.clone();
+    }
+    let parsed = File::open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|mut f| {
+            GGUFFile::read_from(&mut f, &ParseOptions::default()).map_err(|e| e.to_string())
+        });
+    Cached {
+        len: metadata.len(),
+        modified,
+        info: parsed.as_ref().ok().map(|(file, _)| ModelInfo::of(file)),
+        error: parsed.err(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path};
+    use crate::GGUFMetadataValue;
+
+    #[test]
+    fn scans_folders() {
+        let dir = temp_path("catalog-scan");
+        let write = |path: &str, name: &str| {
+            let file = file(
+                header(&[("general.name", GGUFMetadataValue::String(name.into()))]),
+                vec![],
+            );
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let mut out = Vec::new();
+            crate::writer::write_header(&mut out, &file).unwrap();
+            std::fs::write(path, out).unwrap();
+        };
+        write("pub/repo/model-Q4_K_M.gguf", "model");
+        write("pub/big/big-00001-of-00002.gguf", "big");
+        write(".trash/old.gguf", "old");
+        std::fs::write(dir.join("pub/junk.gguf"), b"junk").unwrap();
+
+        let cache = dir.join("cache.json");
+        let catalog = Catalog::new().dir(Source::LmStudio, &dir).cache(&cache);
+        let entries = catalog.scan().unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["pub/big/big", "pub/junk", "pub/repo/model-Q4_K_M"]);
+        assert_eq!(entries[0].missing_shards, [1]);
+        assert_eq!(entries[0].info.name.as_deref(), Some("big"));
+        assert!(entries[1].error.is_some());
+        assert_eq!(entries[2].source, Source::LmStudio);
+
+        // unchanged files come from the cache
+        let text = std::fs::read_to_string(&cache).unwrap();
+        std::fs::write(&cache, text.replace("\"model\"", "\"cached\"")).unwrap();
+        let entries = catalog.scan().unwrap();
+        assert_eq!(entries[2].info.name.as_deref(), Some("cached"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+//! Queries over the headers of many files held in memory
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::scan_dir_parallel;
+use crate::{GGUFFile, GGUFMetadataValue, ParseOptions};
+
+/// A file of a [`ModelSet`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Model {
+    pub path: PathBuf,
+    pub file: GGUFFile,
+}
+
+impl Model {
+    /// `general.architecture`
+    pub fn architecture(&self) -> Option<&str> {
+        self.file.header.get("general.architecture")?.as_str()
+    }
+
+    /// The value of `key`, `{arch}` in it standing for the architecture, as in
+    /// `{arch}.context_length`
+    pub fn get(&self, key: &str) -> Option<&GGUFMetadataValue> {
+        match key.strip_prefix("{arch}") {
+            Some(rest) => {
+                let key = format!("{}{rest}", self.architecture()?);
+                self.file.header.get(&key)
+            }
+            None => self.file.header.get(key),
+        }
+    }
+
+    /// The llama.cpp file type, as `general.file_type` declares it or else as the tensors make
+    /// it, such as `Q4_K_M`
+    pub fn quantization(&self) -> Option<String> {
+        let file_type = self
+            .file
+            .header
+            .file_type()
+            .or_else(|| self.file.quant_report().inferred)?;
+        Some(file_type.to_string())
+    }
+}
+
+/// Many parsed files, to query across as a model registry or catalog does
+///
+/// ```
+/// # use gguf::catalog::ModelSet;
+/// # fn qwen(set: &ModelSet) {
+/// let long = set
+///     .query()
+///     .architecture("qwen2")
+///     .at_least("{arch}.context_length", 32768)
+///     .models();
+/// let by_quantization = set.query().group_by(|m| m.quantization());
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelSet {
+    models: Vec<Model>,
+}
+
+impl ModelSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The files under `dir` whose headers can be read, as [`scan_dir_parallel`] reads them
+    pub fn scan(
+        dir: impl AsRef<Path>,
+        options: &ParseOptions,
+        threads: usize,
+    ) -> Result<Self, String> {
+        let scanned = scan_dir_parallel(dir, options, threads)?;
+        Ok(scanned
+            .into_iter()
+            .filter_map(|s| Some((s.path, s.parsed.ok()?.0)))
+            .collect())
+    }
+
+    pub fn insert(&mut self, path: impl Into<PathBuf>, file: GGUFFile) {
+        self.models.push(Model {
+            path: path.into(),
+            file,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.models.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Model> {
+        self.models.iter()
+    }
+
+    /// A query of all the models, to narrow with its filters
+    pub fn query(&self) -> Query<'_> {
+        Query {
+            set: self,
+            filters: Vec::new(),
+        }
+    }
+}
+
+impl FromIterator<(PathBuf, GGUFFile)> for ModelSet {
+    fn from_iter<I: IntoIterator<Item = (PathBuf, GGUFFile)>>(iter: I) -> Self {
+        ModelSet {
+            models: iter
+                .into_iter()
+                .map(|(path, file)| Model { path, file })
+                .collect(),
+        }
+    }
+}
+
+type Filter<'a> = Box<dyn Fn(&Model) -> bool + 'a>;
+
+/// The models of a [`ModelSet`] passing every filter, in the order of the set
+pub struct Query<'a> {
+    set: &'a ModelSet,
+    filters: Vec<Filter<'a>>,
+}
+
+impl<'a> Query<'a> {
+    /// Keep the models `keep` is true for
+    pub fn filter(mut self, keep: impl Fn(&Model) -> bool + 'a) -> Self {
+        self.filters.push(Box::new(keep));
+        self
+    }
+
+    /// Keep the models of architecture `name`
+    pub fn architecture(self, name: &'a str) -> Self {
+        self.filter(move |m| m.architecture() == Some(name))
+    }
+
+    /// Keep the models with the string `value` for `key`
+    pub fn equals(self, key: &'a str, value: &'a str) -> Self {
+        self.filter(move |m| m.get(key).and_then(|v| v.as_str()) == Some(value))
+    }
+
+    /// Keep the models with a number of at least `min` for `key`
+    pub fn at_least(self, key: &'a str, min: u64) -> Self {
+        self.filter(move |m| m.get(key).and_then(|v| v.as_u64()) >= Some(min))
+    }
+
+    /// Keep the models with a number of at most `max` for `key`
+    pub fn at_most(self, key: &'a str, max: u64) -> Self {
+        self.filter(move |m| {
+            m.get(key)
+                .and_then(|v| v.as_u64())
+                .is_some_and(|v| v <= max)
+        })
+    }
+
+    /// Keep the models that have `key`
+    pub fn has(self, key: &'a str) -> Self {
+        self.filter(move |m| m.get(key).is_some())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a Model> + '_ {
+        let set: &'a ModelSet = self.set;
+        set.models
+            .iter()
+            .filter(|m| self.filters.iter().all(|keep| keep(m)))
+    }
+
+    pub fn models(&self) -> Vec<&'a Model> {
+        self.iter().collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// The models by the value `key` gives them, leaving out those it gives none
+    pub fn group_by<K: Ord>(
+        &self,
+        key: impl Fn(&Model) -> Option<K>,
+    ) -> BTreeMap<K, Vec<&'a Model>> {
+        let mut groups: BTreeMap<K, Vec<&'a Model>> = BTreeMap::new();
+        for model in self.iter() {
+            if let Some(k) = key(model) {
+                groups.entry(k).or_default().push(model);
+            }
+        }
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header};
+
+    fn model(arch: &str, context: u32, file_type: u32) -> GGUFFile {
+        let header = header(&[
+            (
+                "general.architecture",
+                GGUFMetadataValue::String(arch.into()),
+            ),
+            ("general.file_type", GGUFMetadataValue::Uint32(file_type)),
+            (
+                &format!("{arch}.context_length"),
+                GGUFMetadataValue::Uint32(context),
+            ),
+        ]);
+        file(header, Vec::new())
+    }
+
+    #[test]
+    fn queries_and_groups_models() {
+        let set: ModelSet = [
+            ("a.gguf", model("qwen2", 32768, 15)),
+            ("b.gguf", model("qwen2", 4096, 15)),
+            ("c.gguf", model("llama", 131072, 7)),
+            ("d.gguf", model("qwen2", 131072, 7)),
+        ]
+        .into_iter()
+        .map(|(path, file)| (PathBuf::from(path), file))
+        .collect();
+        let long = set
+            .query()
+            .architecture("qwen2")
+            .at_least("{arch}.context_length", 32768);
+        let paths: Vec<_> = long.iter().map(|m| m.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["a.gguf", "d.gguf"]);
+        assert_eq!(set.query().at_most("qwen2.context_length", 4096).count(), 1);
+
+        let groups = set.query().group_by(|m| m.quantization());
+        let sizes: Vec<_> = groups.iter().map(|(k, v)| (k.as_str(), v.len())).collect();
+        assert_eq!(sizes, [("Q4_K_M", 2), ("Q8_0", 2)]);
+    }
+}
@@ -0,0 +1,526 @@
+//! Keeping a catalog in an SQLite database, through the system `libsqlite3`
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::fs::File;
+use std::path::Path;
+use std::ptr;
+
+use super::scan::modified;
+use super::{Catalog, CatalogEntry};
+use crate::{GGUFFile, GGUFMetadataValue, ParseOptions};
+
+#[repr(C)]
+struct Sqlite3([u8; 0]);
+#[repr(C)]
+struct Stmt([u8; 0]);
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_OPEN_READWRITE: c_int = 2;
+const SQLITE_OPEN_CREATE: c_int = 4;
+/// `SQLITE_TRANSIENT`, telling SQLite to copy a bound value
+const TRANSIENT: isize = -1;
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_open_v2(
+        filename: *const c_char,
+        db: *mut *mut Sqlite3,
+        flags: c_int,
+        vfs: *const c_char,
+    ) -> c_int;
+    fn sqlite3_close(db: *mut Sqlite3) -> c_int;
+    fn sqlite3_errmsg(db: *mut Sqlite3) -> *const c_char;
+    fn sqlite3_exec(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        callback: *const (),
+        arg: *mut (),
+        errmsg: *mut *mut c_char,
+    ) -> c_int;
+    fn sqlite3_prepare_v2(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        len: c_int,
+        stmt: *mut *mut Stmt,
+        tail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_bind_int64(stmt: *mut Stmt, index: c_int, value: i64) -> c_int;
+    fn sqlite3_bind_double(stmt: *mut Stmt, index: c_int, value: f64) -> c_int;
+    fn sqlite3_bind_text(
+        stmt: *mut Stmt,
+        index: c_int,
+        text: *const c_char,
+        len: c_int,
+        destructor: isize,
+    ) -> c_int;
+    fn sqlite3_bind_null(stmt: *mut Stmt, index: c_int) -> c_int;
+    fn sqlite3_step(stmt: *mut Stmt) -> c_int;
+    fn sqlite3_reset(stmt: *mut Stmt) -> c_int;
+    fn sqlite3_finalize(stmt: *mut Stmt) -> c_int;
+    fn sqlite3_column_int64(stmt: *mut Stmt, column: c_int) -> i64;
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS models (
+    source TEXT NOT NULL,
+    name TEXT NOT NULL,
+    architecture TEXT,
+    general_name TEXT,
+    file_type INTEGER,
+    file_type_name TEXT,
+    parameters INTEGER NOT NULL,
+    tensor_count INTEGER NOT NULL,
+    context_length INTEGER,
+    size INTEGER NOT NULL,
+    files INTEGER NOT NULL,
+    missing_shards TEXT NOT NULL,
+    error TEXT,
+    PRIMARY KEY (source, name)
+);
+CREATE TABLE IF NOT EXISTS files (
+    path TEXT PRIMARY KEY,
+    source TEXT NOT NULL,
+    model TEXT NOT NULL,
+    shard INTEGER NOT NULL,
+    len INTEGER NOT NULL,
+    modified INTEGER NOT NULL,
+    version INTEGER,
+    data_offset INTEGER,
+    error TEXT
+);
+CREATE TABLE IF NOT EXISTS metadata (
+    path TEXT NOT NULL,
+    key TEXT NOT NULL,
+    type TEXT NOT NULL,
+    value,
+    len INTEGER
+);
+CREATE INDEX IF NOT EXISTS metadata_path ON metadata (path);
+CREATE INDEX IF NOT EXISTS metadata_key ON metadata (key);
+CREATE TABLE IF NOT EXISTS tensors (
+    path TEXT NOT NULL,
+    name TEXT NOT NULL,
+    type TEXT NOT NULL,
+    dimensions TEXT NOT NULL,
+    offset INTEGER NOT NULL,
+    size INTEGER
+);
+CREATE INDEX IF NOT EXISTS tensors_path ON tensors (path);
+";
+
+/// Arrays longer than this, such as vocabularies, are stored with their length only
+const MAX_ARRAY_LEN: usize = 64;
+
+/// A value bound to a statement parameter
+enum Sql<'a> {
+    Int(i64),
+    Real(f64),
+    Text(&'a str),
+    Null,
+}
+
+impl From<u64> for Sql<'_> {
+    fn from(v: u64) -> Self {
+        i64::try_from(v).map_or(Sql::Real(v as f64), Sql::Int)
+    }
+}
+
+impl<'a> From<Option<&'a str>> for Sql<'a> {
+    fn from(v: Option<&'a str>) -> Self {
+        v.map_or(Sql::Null, Sql::Text)
+    }
+}
+
+impl From<Option<u64>> for Sql<'_> {
+    fn from(v: Option<u64>) -> Self {
+        v.map_or(Sql::Null, Sql::from)
+    }
+}
+
+struct Db(*mut Sqlite3);
+
+impl Db {
+    fn open(path: &Path) -> Result<Db, String> {
+        let name = CString::new(path.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+        let mut db = ptr::null_mut();
+        let flags = SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE;
+        // SAFETY: name is NUL-terminated; the handle is closed by Drop even when opening failed
+        let status = unsafe { sqlite3_open_v2(name.as_ptr(), &mut db, flags, ptr::null()) };
+        let db = Db(db);
+        if status != SQLITE_OK {
+            return Err(format!("{}: {}", path.display(), db.error()));
+        }
+        Ok(db)
+    }
+
+    fn error(&self) -> String {
+        // SAFETY: the message of a handle is a NUL-terminated string it owns
+        unsafe { CStr::from_ptr(sqlite3_errmsg(self.0)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn check(&self, status: c_int) -> Result<(), String> {
+        match status {
+            SQLITE_OK => Ok(()),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn exec(&self, sql: &str) -> Result<(), String> {
+        let sql = CString::new(sql).map_err(|e| e.to_string())?;
+        // SAFETY: sql is NUL-terminated; no callback is given
+        let status = unsafe {
+            sqlite3_exec(
+                self.0,
+                sql.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        self.check(status)
+    }
+
+    fn prepare(&self, sql: &str) -> Result<Statement<'_>, String> {
+        let mut stmt = ptr::null_mut();
+        let len = c_int::try_from(sql.len()).map_err(|e| e.to_string())?;
+        // SAFETY: sql is valid for len bytes
+        let status = unsafe {
+            sqlite3_prepare_v2(self.0, sql.as_ptr().cast(), len, &mut stmt, ptr::null_mut())
+        };
+        self.check(status)?;
+        Ok(Statement { db: self, stmt })
+    }
+}
+
+impl Drop for Db {
+    fn drop(&mut self) {
+        // SAFETY: every statement borrows the handle, so all are finalized by now
+        unsafe { sqlite3_close(self.0) };
+    }
+}
+
+struct Statement<'a> {
+    db: &'a Db,
+    stmt: *mut Stmt,
+}
+
+impl Statement<'_> {
+    fn bind(&mut self, params: &[Sql]) -> Result<(), String> {
+        for (i, param) in params.iter().enumerate() {
+            let i = i as c_int + 1;
+            // SAFETY: the statement is live and text is copied by SQLite
+            let status = unsafe {
+                match param {
+                    Sql::Int(v) => sqlite3_bind_int64(self.stmt, i, *v),
+                    Sql::Real(v) => sqlite3_bind_double(self.stmt, i, *v),
+                    Sql::Text(text) => match c_int::try_from(text.len()) {
+                        Ok(len) => {
+                            sqlite3_bind_text(self.stmt, i, text.as_ptr().cast(), len, TRANSIENT)
+                        }
+                        Err(_) => return Err("a value too long for SQLite".to_string()),
+                    },
+                    Sql::Null => sqlite3_bind_null(self.stmt, i),
+                }
+            };
+            self.db.check(status)?;
+        }
+        Ok(())
+    }
+
+    /// Run the statement with `params`, returning the first `columns` integers of the first
+    /// row it yields
+    fn run(&mut self, params: &[Sql], columns: c_int) -> Result<Option<Vec<i64>>, String> {
+        self.bind(params)?;
+        let mut row = None;
+        loop {
+            // SAFETY: the statement is live and its parameters are bound
+            match unsafe { sqlite3_step(self.stmt) } {
+                SQLITE_ROW if row.is_none() => {
+                    row = Some(
+                        (0..columns)
+                            // SAFETY: the statement has a row
+                            .map(|i| unsafe { sqlite3_column_int64(self.stmt, i) })
+                            .collect(),
+                    );
+                }
+                SQLITE_ROW => {}
+                SQLITE_DONE => break,
+                _ => {
+                    let error = self.db.error();
+                    // SAFETY: the statement is live
+                    unsafe { sqlite3_reset(self.stmt) };
+                    return Err(error);
+                }
+            }
+        }
+        // SAFETY: the statement is live
+        unsafe { sqlite3_reset(self.stmt) };
+        Ok(row)
+    }
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the statement was prepared and is not used after
+        unsafe { sqlite3_finalize(self.stmt) };
+    }
+}
+
+impl Catalog {
+    /// [`scan`](Catalog::scan) the folders and store the models in the SQLite database at
+    /// `path`, created if missing
+    ///
+    /// The tables are `models`, a row per [`CatalogEntry`]; `files`, a row per file; and
+    /// `metadata` and `tensors`, the key values and tensor infos of each file by `path`. Arrays
+    /// of more than 64 items, such as vocabularies, are stored with their `len` only, shorter
+    /// ones as JSON. Headers are read again only for files whose length or modification time
+    /// changed, and the rows of files no longer found are deleted.
+    pub fn persist_sqlite(&self, path: impl AsRef<Path>) -> Result<Vec<CatalogEntry>, String> {
+        let entries = self.scan()?;
+        let db = Db::open(path.as_ref())?;
+        db.exec(SCHEMA)?;
+        db.exec("BEGIN")?;
+        match store(&db, &entries) {
+            Ok(()) => db.exec("COMMIT")?,
+            Err(e) => {
+                let _ = db.exec("ROLLBACK");
+                return Err(e);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+fn store(db: &Db, entries: &[CatalogEntry]) -> Result<(), String> {
+    db.exec("CREATE TEMP TABLE seen (path TEXT PRIMARY KEY)")?;
+    let mut seen = db.prepare("INSERT OR IGNORE INTO seen VALUES (?)")?;
+    let mut stamp = db.prepare("SELECT len, modified FROM files WHERE path = ?")?;
+    let mut place =
+        db.prepare("UPDATE files SET source = ?, model = ?, shard = ? WHERE path = ?")?;
+    let mut model =
+        db.prepare("INSERT INTO models VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+    let mut file = Files {
+        forget: [
+            db.prepare("DELETE FROM metadata WHERE path = ?")?,
+            db.prepare("DELETE FROM tensors WHERE path = ?")?,
+        ],
+        file: db.prepare("INSERT OR REPLACE INTO files VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")?,
+        metadata: db.prepare("INSERT INTO metadata VALUES (?, ?, ?, ?, ?)")?,
+        tensor: db.prepare("INSERT INTO tensors VALUES (?, ?, ?, ?, ?, ?)")?,
+    };
+
+    db.exec("DELETE FROM models")?;
+    for entry in entries {
+        let source = entry.source.name();
+        for (shard, path) in entry.files.iter().enumerate() {
+            let text = path.to_string_lossy();
+            seen.run(&[Sql::Text(&text)], 0)?;
+            let len_modified = std::fs::metadata(path)
+                .ok()
+                .map(|m| vec![m.len() as i64, modified(&m) as i64]);
+            if len_modified.is_some() && stamp.run(&[Sql::Text(&text)], 2)? == len_modified {
+                let shard = Sql::Int(shard as i64);
+                place.run(
+                    &[
+                        Sql::Text(source),
+                        Sql::Text(&entry.name),
+                        shard,
+                        Sql::Text(&text),
+                    ],
+                    0,
+                )?;
+            } else {
+                let (len, modified) = len_modified.map_or((0, 0), |v| (v[0], v[1]));
+                let place = [source, entry.name.as_str(), &text];
+                file.store(path, place, shard, len, modified)?;
+            }
+        }
+        let info = &entry.info;
+        let file_type = info.file_type.map(u64::from);
+        let missing = serde_json::to_string(&entry.missing_shards).map_err(|e| e.to_string())?;
+        model.run(
+            &[
+                Sql::Text(source),
+                Sql::Text(&entry.name),
+                info.architecture.as_deref().into(),
+                info.name.as_deref().into(),
+                file_type.into(),
+                info.file_type.and_then(crate::ggml::ftype_name).into(),
+                info.parameters.into(),
+                info.tensor_count.into(),
+                info.context_length.into(),
+                entry.size.into(),
+                (entry.files.len() as u64).into(),
+                Sql::Text(&missing),
+                entry.error.as_deref().into(),
+            ],
+            0,
+        )?;
+    }
+    for table in ["files", "metadata", "tensors"] {
+        db.exec(&format!(
+            "DELETE FROM {table} WHERE path NOT IN (SELECT path FROM seen)"
+        ))?;
+    }
+    Ok(())
+}
+
+/// The statements writing the rows of a file
+struct Files<'a> {
+    forget: [Statement<'a>; 2],
+    file: Statement<'a>,
+    metadata: Statement<'a>,
+    tensor: Statement<'a>,
+}
+
+impl Files<'_> {
+    /// Read the header of `path` and replace its rows; `place` is its source, model and path
+    fn store(
+        &mut self,
+        path: &Path,
+        place: [&str; 3],
+        shard: usize,
+        len: i64,
+        modified: i64,
+    ) -> Result<(), String> {
+        let [source, model, text] = place;
+        for forget in &mut self.forget {
+            forget.run(&[Sql::Text(text)], 0)?;
+        }
+        let parsed = File::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|mut f| {
+                GGUFFile::read_from(&mut f, &ParseOptions::default()).map_err(|e| e.to_string())
+            });
+        let (version, data_offset) = match &parsed {
+            Ok((file, data_offset)) => (Some(u64::from(file.header.version)), Some(*data_offset)),
+            Err(_) => (None, None),
+        };
+        self.file.run(
+            &[
+                Sql::Text(text),
+                Sql::Text(source),
+                Sql::Text(model),
+                Sql::Int(shard as i64),
+                Sql::Int(len),
+                Sql::Int(modified),
+                version.into(),
+                data_offset.into(),
+                parsed.as_ref().err().map(String::as_str).into(),
+            ],
+            0,
+        )?;
+        let Ok((file, _)) = parsed else {
+            return Ok(());
+        };
+        for kv in &file.header.metadata {
+            let value_type = format!("{:?}", kv.value_type);
+            let json = match &kv.value {
+                GGUFMetadataValue::Array(array) if array.value.len() <= MAX_ARRAY_LEN => {
+                    Some(serde_json::to_string(&array.value).map_err(|e| e.to_string())?)
+                }
+                _ => None,
+            };
+            let value = match &kv.value {
+                GGUFMetadataValue::Array(_) => json.as_deref().into(),
+                GGUFMetadataValue::String(s) => Sql::Text(s),
+                GGUFMetadataValue::Bool(b) => Sql::Int(i64::from(*b)),
+                GGUFMetadataValue::Float32(v) => Sql::Real(f64::from(*v)),
+                GGUFMetadataValue::Float64(v) => Sql::Real(*v),
+                v => v.as_i64().map_or_else(|| v.as_u64().into(), Sql::Int),
+            };
+            let len = kv.value.as_array().map(|array| array.len);
+            self.metadata.run(
+                &[
+                    Sql::Text(text),
+                    Sql::Text(&kv.key),
+                    Sql::Text(&value_type),
+                    value,
+                    len.into(),
+                ],
+                0,
+            )?;
+        }
+        for tensor in &file.tensors {
+            let dimensions =
+                serde_json::to_string(&tensor.dimensions).map_err(|e| e.to_string())?;
+            self.tensor.run(
+                &[
+                    Sql::Text(text),
+                    Sql::Text(&tensor.name),
+                    Sql::Text(&format!("{:?}", tensor.tensor_type)),
+                    Sql::Text(&dimensions),
+                    tensor.offset.into(),
+                    tensor.size_bytes().into(),
+                ],
+                0,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Source;
+    use crate::test_util::{file, header, temp_path, tensor};
+    use crate::GGMLType;
+
+    #[test]
+    fn persists_and_updates() {
+        let dir = temp_path("catalog-sqlite");
+        let models = dir.join("models");
+        std::fs::create_dir_all(&models).unwrap();
+        let write = |name: &str, tensors: usize| {
+            let header = header(&[
+                (
+                    "general.architecture",
+                    GGUFMetadataValue::String("llama".into()),
+                ),
+                ("llama.context_length", GGUFMetadataValue::Uint32(4096)),
+            ]);
+            let file = file(
+                header,
+                (0..tensors)
+                    .map(|i| tensor(&format!("t{i}"), &[32], GGMLType::F32, 128 * i as u64))
+                    .collect(),
+            );
+            crate::writer::write_file(models.join(name), &file, |_, out| {
+                Ok(out.write_all(&[0; 128])?)
+            })
+            .unwrap();
+        };
+        write("a.gguf", 1);
+        write("b.gguf", 2);
+
+        let path = dir.join("catalog.db");
+        let catalog = Catalog::new().dir(Source::Directory, &models);
+        let count = |sql: &str| {
+            let db = Db::open(&path).unwrap();
+            let mut stmt = db.prepare(sql).unwrap();
+            stmt.run(&[], 1).unwrap().unwrap()[0]
+        };
+        catalog.persist_sqlite(&path).unwrap();
+        assert_eq!(count("SELECT count(*) FROM models"), 2);
+        assert_eq!(count("SELECT count(*) FROM tensors"), 3);
+        assert_eq!(
+            count("SELECT value FROM metadata WHERE key = 'llama.context_length' LIMIT 1"),
+            4096
+        );
+
+        // an unchanged file keeps its rows, a removed one loses them
+        let db = Db::open(&path).unwrap();
+        db.exec("UPDATE tensors SET offset = -1").unwrap();
+        drop(db);
+        std::fs::remove_file(models.join("b.gguf")).unwrap();
+        catalog.persist_sqlite(&path).unwrap();
+        assert_eq!(count("SELECT count(*) FROM models"), 1);
+        assert_eq!(count("SELECT offset FROM tensors"), -1);
+        assert_eq!(count("SELECT count(*) FROM metadata"), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
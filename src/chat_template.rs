@@ -0,0 +1,282 @@
+//! # Chat template rendering
+//!
+//! Renders `tokenizer.chat_template`, and the named variants stored under
+//! `tokenizer.chat_template.<name>`, against a list of chat messages. Templates are interpreted by a
+//! built-in renderer covering the Jinja features chat templates use.
+mod audit;
+mod jinja;
+mod lint;
+
+pub use crate::validate::Severity;
+use crate::{GGUFHeader, GGUFMetadataValue};
+pub use jinja::Value;
+pub use lint::LintFinding;
+
+const TEMPLATE_KEY: &str = "tokenizer.chat_template";
+
+/// A single chat message
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
+        ChatMessage {
+            role: role.into(),
+            content: content.into(),
+        }
+    }
+}
+
+impl From<&ChatMessage> for Value {
+    fn from(message: &ChatMessage) -> Self {
+        Value::map([
+            ("role", Value::from(message.role.as_str())),
+            ("content", Value::from(message.content.as_str())),
+        ])
+    }
+}
+
+/// Extra inputs for rendering
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    /// Append the prompt that starts the assistant's turn.
+    pub add_generation_prompt: bool,
+    /// Tool definitions, passed to the template as `tools`.
+    pub tools: Option<Vec<Value>>,
+    /// Additional variables made available to the template.
+    pub extra: Vec<(String, Value)>,
+}
+
+/// A parsed chat template
+pub struct ChatTemplate {
+    name: Option<String>,
+    source: String,
+    bos_token: String,
+    eos_token: String,
+    template: jinja::Template,
+}
+
+/// text of a token, looked up in the header without building the whole vocab
+fn token_text(header: &GGUFHeader, id_key: &str) -> Option<String> {
+    let id = header.get(id_key)?.as_u64()?;
+    let tokens = header.get("tokenizer.ggml.tokens")?.as_array()?;
+    tokens
+        .value
+        .get_str(usize::try_from(id).ok()?)
+        .map(str::to_string)
+}
+
+impl ChatTemplate {
+    /// Parse a template from source
+    pub fn new(source: impl Into<String>) -> Result<Self, String> {
+        let source = source.into();
+        let template =
+            jinja::Template::parse(&source).map_err(|e| format!("invalid chat template: {e}"))?;
+        Ok(ChatTemplate {
+            name: None,
+            source,
+            bos_token: String::new(),
+            eos_token: String::new(),
+            template,
+        })
+    }
+
+    /// Read the default template, or the named variant, from a header
+    ///
+    /// The bos and eos token texts are taken from the header's vocabulary.
+    pub fn from_header(header: &GGUFHeader, name: Option<&str>) -> Result<Option<Self>, String> {
+        let key = match name {
+            None | Some("default") => TEMPLATE_KEY.to_string(),
+            Some(name) => format!("{TEMPLATE_KEY}.{name}"),
+        };
+        let Some(value) = header.get(&key) else {
+            return Ok(None);
+        };
+        let source = value
+            .as_str()
+            .ok_or_else(|| format!("{key} is not a string"))?;
+        let mut template = Self::new(source)?;
+        template.name = name.map(str::to_string);
+        template.bos_token = token_text(header, "tokenizer.ggml.bos_token_id").unwrap_or_default();
+        template.eos_token = token_text(header, "tokenizer.ggml.eos_token_id").unwrap_or_default();
+        Ok(Some(template))
+    }
+
+    /// Names of the templates in a header, `default` being the unnamed one
+    pub fn names(header: &GGUFHeader) -> Vec<String> {
+        let prefix = format!("{TEMPLATE_KEY}.");
+        let mut names = Vec::new();
+        if header.get(TEMPLATE_KEY).is_some() {
+            names.push("default".to_string());
+        }
+        for metadata in &header.metadata {
+            if let Some(name) = metadata.key.strip_prefix(&prefix) {
+                if matches!(metadata.value, GGUFMetadataValue::String(_)) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names
+    }
+
+    /// Set the texts rendered for `bos_token` and `eos_token`
+    pub fn with_special_tokens(mut self, bos: impl Into<String>, eos: impl Into<String>) -> Self {
+        self.bos_token = bos.into();
+        self.eos_token = eos.into();
+        self
+    }
+
+    /// The variant name, `None` for the default template
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The template source
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Render the messages into a prompt
+    pub fn render(
+        &self,
+        messages: &[ChatMessage],
+        add_generation_prompt: bool,
+    ) -> Result<String, String> {
+        let options = RenderOptions {
+            add_generation_prompt,
+            ..Default::default()
+        };
+        self.render_with(messages.iter().map(Value::from).collect(), &options)
+    }
+
+    /// Render arbitrary message values, e.g. ones carrying `tool_calls`
+    pub fn render_with(
+        &self,
+        messages: Vec<Value>,
+        options: &RenderOptions,
+    ) -> Result<String, String> {
+        let mut globals = vec![
+            ("messages".to_string(), Value::List(messages)),
+            (
+                "add_generation_prompt".to_string(),
+                Value::Bool(options.add_generation_prompt),
+            ),
+            (
+                "bos_token".to_string(),
+                Value::from(self.bos_token.as_str()),
+            ),
+            (
+                "eos_token".to_string(),
+                Value::from(self.eos_token.as_str()),
+            ),
+        ];
+        if let Some(tools) = &options.tools {
+            globals.push(("tools".to_string(), Value::List(tools.clone())));
+        }
+        globals.extend(options.extra.iter().cloned());
+        self.template.render(globals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_chatml() {
+        let source = "{% for message in messages %}{{ '<|im_start|>' + message['role'] + '\n' + message['content'] + '<|im_end|>' + '\n' }}{% endfor %}{% if add_generation_prompt %}{{ '<|im_start|>assistant\n' }}{% endif %}";
+        let template = ChatTemplate::new(source).unwrap();
+        let prompt = template
+            .render(
+                &[
+                    ChatMessage::new("system", "Be brief."),
+                    ChatMessage::new("user", "Hi"),
+                ],
+                true,
+            )
+            .unwrap();
+        assert_eq!(
+            prompt,
+            "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n"
+        );
+    }
+
+    #[test]
+    fn render_llama2_with_bos() {
+        let source = "{{ bos_token }}{% for message in messages %}{% if message['role'] == 'user' %}{{ '[INST] ' + message['content'] | trim + ' [/INST]' }}{% elif message['role'] == 'assistant' %}{{ ' ' + message['content'] | trim + ' ' + eos_token }}{% else %}{{ raise_exception('Only user and assistant roles are supported!') }}{% endif %}{% endfor %}";
+        let template = ChatTemplate::new(source)
+            .unwrap()
+            .with_special_tokens("<s>", "</s>");
+        let messages = [
+            ChatMessage::new("user", " Hello "),
+            ChatMessage::new("assistant", "Hi!"),
+        ];
+        assert_eq!(
+            template.render(&messages, false).unwrap(),
+            "<s>[INST] Hello [/INST] Hi! </s>"
+        );
+        assert!(template
+            .render(&[ChatMessage::new("tool", "x")], false)
+            .is_err());
+    }
+
+    /// Render the templates of real models, as their GGUF files carry them, against what
+    /// `transformers` renders: the `.txt` of each fixture is jinja2's output for its `.json`,
+    /// in the environment `apply_chat_template` sets up
+    #[cfg(feature = "json")]
+    #[test]
+    fn render_model_templates_like_transformers() {
+        use crate::test_util::header;
+        use crate::{GGUFMetadataArrayValue, GGUfMetadataValueType};
+        macro_rules! fixture {
+            ($name:literal) => {
+                (
+                    $name,
+                    include_str!(concat!("chat_template/fixtures/", $name, ".jinja")),
+                    include_str!(concat!("chat_template/fixtures/", $name, ".json")),
+                    include_str!(concat!("chat_template/fixtures/", $name, ".txt")),
+                )
+            };
+        }
+        let fixtures = [
+            fixture!("llama3.1"),
+            fixture!("qwen2.5"),
+            fixture!("mistral-v0.3"),
+            fixture!("gemma2"),
+        ];
+        for (name, source, input, expected) in fixtures {
+            let input: serde_json::Value = serde_json::from_str(input).unwrap();
+            let token = |key: &str| GGUFMetadataValue::String(input[key].as_str().unwrap().into());
+            let tokens = vec![token("bos_token"), token("eos_token")];
+            let header = header(&[
+                (TEMPLATE_KEY, GGUFMetadataValue::String(source.into())),
+                (
+                    "tokenizer.ggml.tokens",
+                    GGUFMetadataValue::Array(GGUFMetadataArrayValue::new(
+                        GGUfMetadataValueType::String,
+                        tokens,
+                    )),
+                ),
+                ("tokenizer.ggml.bos_token_id", GGUFMetadataValue::Uint32(0)),
+                ("tokenizer.ggml.eos_token_id", GGUFMetadataValue::Uint32(1)),
+            ]);
+            let template = ChatTemplate::from_header(&header, None).unwrap().unwrap();
+            let list = |key: &str| {
+                let items = input[key].as_array()?;
+                Some(items.iter().cloned().map(Value::from).collect::<Vec<_>>())
+            };
+            let options = RenderOptions {
+                add_generation_prompt: input["add_generation_prompt"].as_bool().unwrap(),
+                tools: list("tools"),
+                extra: Vec::new(),
+            };
+            let prompt = template
+                .render_with(list("messages").unwrap(), &options)
+                .unwrap_or_else(|e| panic!("{name}: {e}"));
+            assert_eq!(prompt, expected, "{name}");
+        }
+    }
+}
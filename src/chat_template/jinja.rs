@@ -0,0 +1,2773 @@
+//! A small interpreter for the subset of Jinja used by chat templates
+//!
+//! Templates are rendered with the settings Hugging Face uses for chat templates: `trim_blocks`
+//! and `lstrip_blocks` are on, the `loopcontrols` extension is available and undefined values are
+//! lenient.
+use std::cell::RefCell;
+use std::fmt::{self, Write as _};
+use std::rc::Rc;
+
+/// A template value
+#[derive(Debug, Clone, Default)]
+pub enum Value {
+    #[default]
+    Undefined,
+    None,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    List(Vec<Value>),
+    /// A mapping that keeps its insertion order.
+    Map(Vec<(String, Value)>),
+    /// A mutable object created by `namespace()`.
+    Namespace(Namespace),
+    /// A macro defined in the template.
+    Macro(Macro),
+}
+
+/// A mutable object created by `namespace()`
+#[derive(Debug, Clone)]
+pub struct Namespace(Rc<RefCell<Vec<(String, Value)>>>);
+
+/// A macro defined with `{% macro %}`
+#[derive(Clone)]
+pub struct Macro(Rc<MacroDef>);
+
+impl fmt::Debug for Macro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<macro {}>", self.0.name)
+    }
+}
+
+pub(crate) struct MacroDef {
+    pub(crate) name: String,
+    pub(crate) params: Vec<(String, Option<Expr>)>,
+    pub(crate) body: Vec<Node>,
+}
+
+impl Value {
+    /// Build a mapping from key-value pairs
+    pub fn map<K: Into<String>>(pairs: impl IntoIterator<Item = (K, Value)>) -> Value {
+        Value::Map(pairs.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Look up a key of a mapping
+    pub fn get(&self, key: &str) -> Option<Value> {
+        match self {
+            Value::Map(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone()),
+            Value::Namespace(ns) => {
+                ns.0.borrow()
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the value is truthy in a condition
+    pub fn is_true(&self) -> bool {
+        match self {
+            Value::Undefined | Value::None => false,
+            Value::Bool(b) => *b,
+            Value::Int(i) => *i != 0,
+            Value::Float(f) => *f != 0.0,
+            Value::String(s) => !s.is_empty(),
+            Value::List(l) => !l.is_empty(),
+            Value::Map(m) => !m.is_empty(),
+            Value::Namespace(_) | Value::Macro(_) => true,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Undefined => "undefined",
+            Value::None => "none",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "mapping",
+            Value::Namespace(_) => "namespace",
+            Value::Macro(_) => "macro",
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Int(i) => Some(i as f64),
+            Value::Float(f) => Some(f),
+            Value::Bool(b) => Some(b as i64 as f64),
+            _ => None,
+        }
+    }
+
+    fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Int(i) => Some(i),
+            Value::Bool(b) => Some(b as i64),
+            _ => None,
+        }
+    }
+
+    /// elements when iterating over the value
+    fn iter_values(&self) -> Result<Vec<Value>, String> {
+        Ok(match self {
+            Value::Undefined | Value::None => Vec::new(),
+            Value::List(l) => l.clone(),
+            Value::Map(m) => m.iter().map(|(k, _)| Value::String(k.clone())).collect(),
+            Value::String(s) => s.chars().map(|c| Value::String(c.to_string())).collect(),
+            other => return Err(format!("{} is not iterable", other.type_name())),
+        })
+    }
+
+    /// Python `repr`-like rendering used inside containers
+    fn repr(&self, out: &mut String) {
+        match self {
+            Value::String(s) => {
+                let quote = if s.contains('\'') && !s.contains('"') {
+                    '"'
+                } else {
+                    '\''
+                };
+                out.push(quote);
+                for c in s.chars() {
+                    match c {
+                        '\n' => out.push_str("\\n"),
+                        '\\' => out.push_str("\\\\"),
+                        c if c == quote => {
+                            out.push('\\');
+                            out.push(c);
+                        }
+                        c => out.push(c),
+                    }
+                }
+                out.push(quote);
+            }
+            other => {
+                let _ = write!(out, "{}", other);
+            }
+        }
+    }
+
+    /// JSON rendering as produced by the `tojson` filter
+    fn to_json(&self, out: &mut String, indent: Option<usize>, level: usize) {
+        let newline = |out: &mut String, level: usize| {
+            if let Some(n) = indent {
+                out.push('\n');
+                out.extend(std::iter::repeat_n(' ', n * level));
+            }
+        };
+        let separator = if indent.is_some() { "," } else { ", " };
+        match self {
+            Value::Undefined | Value::None => out.push_str("null"),
+            Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Value::Int(_) | Value::Float(_) => {
+                let _ = write!(out, "{}", self);
+            }
+            Value::String(s) => json_string(s, out),
+            Value::List(items) => {
+                if items.is_empty() {
+                    out.push_str("[]");
+                    return;
+                }
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(separator);
+                    }
+                    newline(out, level + 1);
+                    item.to_json(out, indent, level + 1);
+                }
+                newline(out, level);
+                out.push(']');
+            }
+            Value::Map(_) | Value::Namespace(_) => {
+                let pairs = match self {
+                    Value::Map(pairs) => pairs.clone(),
+                    Value::Namespace(ns) => ns.0.borrow().clone(),
+                    _ => unreachable!(),
+                };
+                if pairs.is_empty() {
+                    out.push_str("{}");
+                    return;
+                }
+                out.push('{');
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(separator);
+                    }
+                    newline(out, level + 1);
+                    json_string(key, out);
+                    out.push_str(": ");
+                    value.to_json(out, indent, level + 1);
+                }
+                newline(out, level);
+                out.push('}');
+            }
+            Value::Macro(_) => out.push_str("null"),
+        }
+    }
+}
+
+/// write a JSON string literal, keeping non-ASCII characters as is
+fn json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Undefined => Ok(()),
+            Value::None => write!(f, "None"),
+            Value::Bool(true) => write!(f, "True"),
+            Value::Bool(false) => write!(f, "False"),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(v) if v.is_finite() && v.fract() == 0.0 && v.abs() < 1e16 => {
+                write!(f, "{:.1}", v)
+            }
+            Value::Float(v) => write!(f, "{}", v),
+            Value::String(s) => write!(f, "{}", s),
+            Value::List(items) => {
+                let mut out = String::from("[");
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    item.repr(&mut out);
+                }
+                out.push(']');
+                write!(f, "{}", out)
+            }
+            Value::Map(pairs) => {
+                let mut out = String::from("{");
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    Value::String(key.clone()).repr(&mut out);
+                    out.push_str(": ");
+                    value.repr(&mut out);
+                }
+                out.push('}');
+                write!(f, "{}", out)
+            }
+            Value::Namespace(_) => write!(f, "<Namespace>"),
+            Value::Macro(m) => write!(f, "{:?}", m),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Undefined, Value::Undefined) | (Value::None, Value::None) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::Namespace(a), Value::Namespace(b)) => Rc::ptr_eq(&a.0, &b.0),
+            (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+                match (self, other) {
+                    (Value::Int(a), Value::Int(b)) => a == b,
+                    _ => self.as_f64() == other.as_f64(),
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::String(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::String(v)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Int(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(v: Vec<Value>) -> Self {
+        Value::List(v)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Value> for Value {
+    fn from(v: serde_json::Value) -> Self {
+        match v {
+            serde_json::Value::Null => Value::None,
+            serde_json::Value::Bool(b) => Value::Bool(b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Value::Int(i),
+                None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(s) => Value::String(s),
+            serde_json::Value::Array(items) => {
+                Value::List(items.into_iter().map(Value::from).collect())
+            }
+            serde_json::Value::Object(map) => {
+                Value::Map(map.into_iter().map(|(k, v)| (k, Value::from(v))).collect())
+            }
+        }
+    }
+}
+
+/// token inside a tag
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Name(String),
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Op(&'static str),
+}
+
+/// a piece of the template source
+#[derive(Debug)]
+enum Segment {
+    Text(String),
+    Expr(Vec<Tok>),
+    Stmt(Vec<Tok>),
+}
+
+const OPS: &[&str] = &[
+    "**", "//", "==", "!=", "<=", ">=", "+", "-", "*", "/", "%", "~", "<", ">", "=", "(", ")", "[",
+    "]", "{", "}", ",", ".", ":", "|",
+];
+
+/// split tag content into tokens
+fn tokenize(src: &str) -> Result<Vec<Tok>, String> {
+    let mut toks = Vec::new();
+    let chars: Vec<char> = src.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            let mut s = String::new();
+            i += 1;
+            loop {
+                let Some(&ch) = chars.get(i) else {
+                    return Err("unterminated string literal".to_string());
+                };
+                i += 1;
+                if ch == c {
+                    break;
+                }
+                if ch == '\\' {
+                    let Some(&esc) = chars.get(i) else {
+                        return Err("unterminated string literal".to_string());
+                    };
+                    i += 1;
+                    s.push(match esc {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '0' => '\0',
+                        other => other,
+                    });
+                } else {
+                    s.push(ch);
+                }
+            }
+            toks.push(Tok::Str(s));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '_') {
+                i += 1;
+            }
+            let mut is_float = false;
+            if i + 1 < chars.len() && chars[i] == '.' && chars[i + 1].is_ascii_digit() {
+                is_float = true;
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let text: String = chars[start..i].iter().filter(|&&c| c != '_').collect();
+            toks.push(if is_float {
+                Tok::Float(text.parse().map_err(|_| format!("bad number {text}"))?)
+            } else {
+                Tok::Int(text.parse().map_err(|_| format!("bad number {text}"))?)
+            });
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            toks.push(Tok::Name(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let op = OPS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| format!("unexpected character {c:?}"))?;
+            toks.push(Tok::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(toks)
+}
+
+/// find the closing delimiter of a tag, skipping string literals
+fn find_tag_end(src: &str, end: &str) -> Option<usize> {
+    let bytes = src.as_bytes();
+    let mut quote = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        match quote {
+            Some(_) if b == b'\\' => i += 1,
+            Some(q) if b == q => quote = None,
+            Some(_) => {}
+            None if b == b'\'' || b == b'"' => quote = Some(b),
+            None if src[i..].starts_with(end) => return Some(i),
+            None => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// split the source into text and tags, applying whitespace control
+fn lex(source: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut rest = source;
+    let mut strip_next = false;
+    let mut trim_newline = false;
+    let mut line_start = true;
+    loop {
+        // the first tag, found without scanning past it for the kinds it is not
+        let start = rest
+            .match_indices('{')
+            .map(|(i, _)| i)
+            .find(|&i| matches!(rest.as_bytes().get(i + 1), Some(b'{' | b'%' | b'#')));
+        let mut text = &rest[..start.unwrap_or(rest.len())];
+        if strip_next {
+            text = text.trim_start();
+        } else if trim_newline {
+            if let Some(t) = text
+                .strip_prefix("\r\n")
+                .or_else(|| text.strip_prefix('\n'))
+            {
+                text = t;
+                line_start = true;
+            }
+        }
+        let Some(start) = start else {
+            if !text.is_empty() {
+                segments.push(Segment::Text(text.to_string()));
+            }
+            return Ok(segments);
+        };
+        let kind = rest.as_bytes()[start + 1];
+        let mut inner = &rest[start + 2..];
+        let mut keep_indent = false;
+        if let Some(i) = inner.strip_prefix('-') {
+            inner = i;
+            text = text.trim_end();
+        } else if let Some(i) = inner.strip_prefix('+') {
+            inner = i;
+            keep_indent = true;
+        }
+        if kind != b'{' && !keep_indent {
+            // lstrip_blocks: drop indentation before a block tag on its own line
+            let line = text.rfind('\n').map(|i| i + 1).unwrap_or(0);
+            if (line > 0 || line_start) && text[line..].chars().all(|c| c == ' ' || c == '\t') {
+                text = &text[..line];
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text.to_string()));
+        }
+        let end = match kind {
+            b'{' => "}}",
+            b'%' => "%}",
+            _ => "#}",
+        };
+        let close = if kind == b'#' {
+            inner.find(end)
+        } else {
+            find_tag_end(inner, end)
+        }
+        .ok_or_else(|| format!("unclosed tag, expected {end}"))?;
+        let mut content = &inner[..close];
+        rest = &inner[close + 2..];
+        strip_next = false;
+        if let Some(c) = content.strip_suffix('-') {
+            content = c;
+            strip_next = true;
+        } else if let Some(c) = content.strip_suffix('+') {
+            content = c;
+        }
+        trim_newline = kind != b'{';
+        line_start = false;
+        match kind {
+            b'{' => segments.push(Segment::Expr(tokenize(content)?)),
+            b'%' => {
+                let toks = tokenize(content)?;
+                if toks == [Tok::Name("raw".to_string())] {
+                    let end = rest
+                        .find("endraw")
+                        .and_then(|i| rest[..i].rfind("{%"))
+                        .ok_or("unclosed raw block")?;
+                    let mut raw = &rest[..end];
+                    if rest[end + 2..].starts_with('-') {
+                        raw = raw.trim_end();
+                    }
+                    segments.push(Segment::Text(raw.to_string()));
+                    let close = rest[end..].find("%}").ok_or("unclosed raw block")?;
+                    strip_next = rest[end..end + close].ends_with('-');
+                    rest = &rest[end + close + 2..];
+                } else {
+                    segments.push(Segment::Stmt(toks));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    FloorDiv,
+    Mod,
+    Pow,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    In,
+    NotIn,
+    And,
+    Or,
+}
+
+/// expression tree
+pub(crate) enum Expr {
+    Literal(Value),
+    List(Vec<Expr>),
+    Dict(Vec<(Expr, Expr)>),
+    Name(String),
+    Attr(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Slice(Box<Expr>, [Option<Box<Expr>>; 3]),
+    Call(Box<Expr>, Vec<Expr>, Vec<(String, Expr)>),
+    Filter(Box<Expr>, String, Vec<Expr>, Vec<(String, Expr)>),
+    Test(Box<Expr>, String, Vec<Expr>, bool),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+}
+
+/// assignment target of `set`
+pub(crate) enum Target {
+    Name(String),
+    Attr(String, String),
+    Unpack(Vec<String>),
+}
+
+/// statement tree
+pub(crate) enum Node {
+    Text(String),
+    Output(Expr),
+    If(Vec<(Expr, Vec<Node>)>, Vec<Node>),
+    For {
+        targets: Vec<String>,
+        iter: Expr,
+        filter: Option<Expr>,
+        body: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Set(Target, Expr),
+    SetBlock(String, Vec<Node>),
+    Macro(Rc<MacroDef>),
+    Break,
+    Continue,
+}
+
+/// positional and keyword arguments of a call
+type CallArgs = (Vec<Expr>, Vec<(String, Expr)>);
+
+/// The deepest expressions and blocks nest, before the stack of the parser would overflow
+const MAX_NESTING: usize = 64;
+
+/// The most operators, filters, calls and lookups one tag applies, bounding how deep its
+/// expression tree grows
+const MAX_OPERATIONS: usize = 256;
+
+/// recursive descent parser over the tokens of one tag
+struct ExprParser<'a> {
+    toks: &'a [Tok],
+    pos: usize,
+    /// expressions open around the one being parsed
+    depth: usize,
+    operations: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn new(toks: &'a [Tok]) -> Self {
+        ExprParser {
+            toks,
+            pos: 0,
+            depth: 0,
+            operations: 0,
+        }
+    }
+
+    /// count an operation wrapping the expression parsed so far
+    fn operation(&mut self) -> Result<(), String> {
+        self.operations += 1;
+        if self.operations > MAX_OPERATIONS {
+            return Err(format!("more than {MAX_OPERATIONS} operations in one tag"));
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&'a Tok> {
+        self.toks.get(self.pos)
+    }
+
+    fn peek_at(&self, n: usize) -> Option<&'a Tok> {
+        self.toks.get(self.pos + n)
+    }
+
+    fn next(&mut self) -> Option<&'a Tok> {
+        let t = self.toks.get(self.pos);
+        self.pos += 1;
+        t
+    }
+
+    fn at_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Op(o)) if *o == op)
+    }
+
+    fn at_name(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Name(n)) if n == name)
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        let found = self.at_op(op);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_name(&mut self, name: &str) -> bool {
+        let found = self.at_name(name);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), String> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            Err(format!("expected '{op}', found {:?}", self.peek()))
+        }
+    }
+
+    fn expect_name(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Tok::Name(n)) => Ok(n.clone()),
+            other => Err(format!("expected a name, found {other:?}")),
+        }
+    }
+
+    fn done(&self) -> Result<(), String> {
+        match self.peek() {
+            None => Ok(()),
+            Some(t) => Err(format!("unexpected token {t:?}")),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        if self.depth == MAX_NESTING {
+            return Err(format!("expression nested more than {MAX_NESTING} deep"));
+        }
+        self.depth += 1;
+        let e = self.conditional();
+        self.depth -= 1;
+        e
+    }
+
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let value = self.or()?;
+        if self.eat_name("if") {
+            let cond = self.or()?;
+            let otherwise = if self.eat_name("else") {
+                Some(Box::new(self.expr()?))
+            } else {
+                None
+            };
+            self.operation()?;
+            return Ok(Expr::Cond(Box::new(cond), Box::new(value), otherwise));
+        }
+        Ok(value)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.eat_name("or") {
+            self.operation()?;
+            left = Expr::Binary(BinOp::Or, Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.not()?;
+        while self.eat_name("and") {
+            self.operation()?;
+            left = Expr::Binary(BinOp::And, Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        let mut nots = 0;
+        while self.eat_name("not") {
+            nots += 1;
+        }
+        let mut e = self.compare()?;
+        for _ in 0..nots {
+            self.operation()?;
+            e = Expr::Not(Box::new(e));
+        }
+        Ok(e)
+    }
+
+    fn compare(&mut self) -> Result<Expr, String> {
+        let mut left = self.math1()?;
+        loop {
+            let op = match self.peek() {
+                Some(Tok::Op("==")) => BinOp::Eq,
+                Some(Tok::Op("!=")) => BinOp::Ne,
+                Some(Tok::Op("<")) => BinOp::Lt,
+                Some(Tok::Op(">")) => BinOp::Gt,
+                Some(Tok::Op("<=")) => BinOp::Le,
+                Some(Tok::Op(">=")) => BinOp::Ge,
+                Some(Tok::Name(n)) if n == "in" => BinOp::In,
+                Some(Tok::Name(n))
+                    if n == "not" && matches!(self.peek_at(1), Some(Tok::Name(n)) if n == "in") =>
+                {
+                    self.pos += 1;
+                    BinOp::NotIn
+                }
+                Some(Tok::Name(n)) if n == "is" => {
+                    self.pos += 1;
+                    let negated = self.eat_name("not");
+                    let name = self.expect_name()?;
+                    let mut args = Vec::new();
+                    if self.at_op("(") {
+                        args = self.call_args()?.0;
+                    } else if matches!(self.peek(), Some(Tok::Str(_) | Tok::Int(_) | Tok::Float(_)))
+                        || matches!(self.peek(), Some(Tok::Name(n))
+                        if !["and", "or", "else", "if", "not", "in", "is"].contains(&n.as_str()))
+                    {
+                        args.push(self.primary()?);
+                    }
+                    self.operation()?;
+                    left = Expr::Test(Box::new(left), name, args, negated);
+                    continue;
+                }
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            self.operation()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.math1()?));
+        }
+    }
+
+    fn math1(&mut self) -> Result<Expr, String> {
+        let mut left = self.concat()?;
+        loop {
+            let op = if self.eat_op("+") {
+                BinOp::Add
+            } else if self.eat_op("-") {
+                BinOp::Sub
+            } else {
+                return Ok(left);
+            };
+            self.operation()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.concat()?));
+        }
+    }
+
+    fn concat(&mut self) -> Result<Expr, String> {
+        let mut left = self.math2()?;
+        while self.eat_op("~") {
+            self.operation()?;
+            left = Expr::Binary(BinOp::Concat, Box::new(left), Box::new(self.math2()?));
+        }
+        Ok(left)
+    }
+
+    fn math2(&mut self) -> Result<Expr, String> {
+        let mut left = self.pow()?;
+        loop {
+            let op = if self.eat_op("*") {
+                BinOp::Mul
+            } else if self.eat_op("/") {
+                BinOp::Div
+            } else if self.eat_op("//") {
+                BinOp::FloorDiv
+            } else if self.eat_op("%") {
+                BinOp::Mod
+            } else {
+                return Ok(left);
+            };
+            self.operation()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.pow()?));
+        }
+    }
+
+    fn pow(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while self.eat_op("**") {
+            self.operation()?;
+            left = Expr::Binary(BinOp::Pow, Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let mut negated = Vec::new();
+        loop {
+            if self.eat_op("-") {
+                negated.push(true);
+            } else if !self.eat_op("+") {
+                break;
+            }
+        }
+        let mut e = self.postfix()?;
+        for _ in negated {
+            self.operation()?;
+            e = Expr::Neg(Box::new(e));
+        }
+        Ok(e)
+    }
+
+    fn call_args(&mut self) -> Result<CallArgs, String> {
+        self.expect_op("(")?;
+        let mut args = Vec::new();
+        let mut kwargs = Vec::new();
+        while !self.eat_op(")") {
+            if let (Some(Tok::Name(n)), Some(Tok::Op("="))) = (self.peek(), self.peek_at(1)) {
+                self.pos += 2;
+                kwargs.push((n.clone(), self.expr()?));
+            } else {
+                args.push(self.expr()?);
+            }
+            if !self.eat_op(",") {
+                self.expect_op(")")?;
+                break;
+            }
+        }
+        Ok((args, kwargs))
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut e = self.primary()?;
+        loop {
+            if self.eat_op(".") {
+                match self.next() {
+                    Some(Tok::Name(n)) => {
+                        self.operation()?;
+                        e = Expr::Attr(Box::new(e), n.clone())
+                    }
+                    Some(Tok::Int(i)) => {
+                        self.operation()?;
+                        e = Expr::Index(Box::new(e), Box::new(Expr::Literal(Value::Int(*i))))
+                    }
+                    other => return Err(format!("expected attribute name, found {other:?}")),
+                }
+            } else if self.eat_op("[") {
+                let start = if self.at_op(":") {
+                    None
+                } else {
+                    Some(Box::new(self.expr()?))
+                };
+                if self.eat_op(":") {
+                    let stop = if self.at_op(":") || self.at_op("]") {
+                        None
+                    } else {
+                        Some(Box::new(self.expr()?))
+                    };
+                    let step = if self.eat_op(":") && !self.at_op("]") {
+                        Some(Box::new(self.expr()?))
+                    } else {
+                        None
+                    };
+                    self.expect_op("]")?;
+                    self.operation()?;
+                    e = Expr::Slice(Box::new(e), [start, stop, step]);
+                } else {
+                    self.expect_op("]")?;
+                    self.operation()?;
+                    e = Expr::Index(Box::new(e), start.ok_or("empty subscript")?);
+                }
+            } else if self.at_op("(") {
+                let (args, kwargs) = self.call_args()?;
+                self.operation()?;
+                e = Expr::Call(Box::new(e), args, kwargs);
+            } else if self.eat_op("|") {
+                let name = self.expect_name()?;
+                let (args, kwargs) = if self.at_op("(") {
+                    self.call_args()?
+                } else {
+                    (Vec::new(), Vec::new())
+                };
+                self.operation()?;
+                e = Expr::Filter(Box::new(e), name, args, kwargs);
+            } else {
+                return Ok(e);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        Ok(match self.next() {
+            Some(Tok::Name(n)) => match n.as_str() {
+                "true" | "True" => Expr::Literal(Value::Bool(true)),
+                "false" | "False" => Expr::Literal(Value::Bool(false)),
+                "none" | "None" => Expr::Literal(Value::None),
+                _ => Expr::Name(n.clone()),
+            },
+            Some(Tok::Str(s)) => {
+                let mut s = s.clone();
+                while let Some(Tok::Str(more)) = self.peek() {
+                    s.push_str(more);
+                    self.pos += 1;
+                }
+                Expr::Literal(Value::String(s))
+            }
+            Some(Tok::Int(i)) => Expr::Literal(Value::Int(*i)),
+            Some(Tok::Float(f)) => Expr::Literal(Value::Float(*f)),
+            Some(Tok::Op("(")) => {
+                if self.eat_op(")") {
+                    return Ok(Expr::List(Vec::new()));
+                }
+                let first = self.expr()?;
+                if self.at_op(",") {
+                    let mut items = vec![first];
+                    while self.eat_op(",") && !self.at_op(")") {
+                        items.push(self.expr()?);
+                    }
+                    self.expect_op(")")?;
+                    Expr::List(items)
+                } else {
+                    self.expect_op(")")?;
+                    first
+                }
+            }
+            Some(Tok::Op("[")) => {
+                let mut items = Vec::new();
+                while !self.eat_op("]") {
+                    items.push(self.expr()?);
+                    if !self.eat_op(",") {
+                        self.expect_op("]")?;
+                        break;
+                    }
+                }
+                Expr::List(items)
+            }
+            Some(Tok::Op("{")) => {
+                let mut pairs = Vec::new();
+                while !self.eat_op("}") {
+                    let key = self.expr()?;
+                    self.expect_op(":")?;
+                    pairs.push((key, self.expr()?));
+                    if !self.eat_op(",") {
+                        self.expect_op("}")?;
+                        break;
+                    }
+                }
+                Expr::Dict(pairs)
+            }
+            other => return Err(format!("unexpected token {other:?}")),
+        })
+    }
+}
+
+/// parse a complete expression
+fn parse_expr(toks: &[Tok]) -> Result<Expr, String> {
+    let mut p = ExprParser::new(toks);
+    let e = p.expr()?;
+    p.done()?;
+    Ok(e)
+}
+
+/// statement parser over all segments
+struct Parser {
+    segments: std::vec::IntoIter<Segment>,
+    /// blocks open around the nodes being parsed
+    depth: usize,
+}
+
+impl Parser {
+    /// parse nodes until one of the given end tags, returning the tokens of that tag
+    fn nodes(&mut self, until: &[&str]) -> Result<(Vec<Node>, Option<Vec<Tok>>), String> {
+        if self.depth > MAX_NESTING {
+            return Err(format!("blocks nested more than {MAX_NESTING} deep"));
+        }
+        self.depth += 1;
+        let nodes = self.block(until);
+        self.depth -= 1;
+        nodes
+    }
+
+    fn block(&mut self, until: &[&str]) -> Result<(Vec<Node>, Option<Vec<Tok>>), String> {
+        let mut nodes = Vec::new();
+        while let Some(segment) = self.segments.next() {
+            match segment {
+                Segment::Text(t) => nodes.push(Node::Text(t)),
+                Segment::Expr(toks) => nodes.push(Node::Output(parse_expr(&toks)?)),
+                Segment::Stmt(toks) => {
+                    let Some(Tok::Name(keyword)) = toks.first() else {
+                        return Err(format!("expected a statement, found {:?}", toks.first()));
+                    };
+                    if until.contains(&keyword.as_str()) {
+                        return Ok((nodes, Some(toks)));
+                    }
+                    if let (true, Some(end)) = (keyword.starts_with("end"), until.last()) {
+                        return Err(format!("{{% {keyword} %}} before {{% {end} %}}"));
+                    }
+                    nodes.push(self.statement(&toks)?);
+                }
+            }
+        }
+        if until.is_empty() {
+            Ok((nodes, None))
+        } else {
+            Err(format!("missing {{% {} %}}", until.last().unwrap()))
+        }
+    }
+
+    fn statement(&mut self, toks: &[Tok]) -> Result<Node, String> {
+        let mut p = ExprParser::new(toks);
+        let keyword = p.expect_name()?;
+        match keyword.as_str() {
+            "if" => {
+                let mut branches = Vec::new();
+                let mut cond = parse_expr(&toks[1..])?;
+                loop {
+                    let (body, end) = self.nodes(&["elif", "else", "endif"])?;
+                    branches.push((cond, body));
+                    let end = end.unwrap();
+                    match &end[0] {
+                        Tok::Name(n) if n == "elif" => cond = parse_expr(&end[1..])?,
+                        Tok::Name(n) if n == "else" => {
+                            let (otherwise, _) = self.nodes(&["endif"])?;
+                            return Ok(Node::If(branches, otherwise));
+                        }
+                        _ => return Ok(Node::If(branches, Vec::new())),
+                    }
+                }
+            }
+            "for" => {
+                let mut targets = vec![p.expect_name()?];
+                while p.eat_op(",") {
+                    targets.push(p.expect_name()?);
+                }
+                if !p.eat_name("in") {
+                    return Err("expected 'in' in for loop".to_string());
+                }
+                let iter = p.or()?;
+                let filter = if p.eat_name("if") {
+                    Some(p.expr()?)
+                } else {
+                    None
+                };
+                p.eat_name("recursive");
+                p.done()?;
+                let (body, end) = self.nodes(&["else", "endfor"])?;
+                let otherwise = match end.as_deref() {
+                    Some([Tok::Name(n), ..]) if n == "else" => self.nodes(&["endfor"])?.0,
+                    _ => Vec::new(),
+                };
+                Ok(Node::For {
+                    targets,
+                    iter,
+                    filter,
+                    body,
+                    otherwise,
+                })
+            }
+            "set" => {
+                let name = p.expect_name()?;
+                let target = if p.eat_op(".") {
+                    Target::Attr(name, p.expect_name()?)
+                } else if p.at_op(",") {
+                    let mut names = vec![name];
+                    while p.eat_op(",") {
+                        names.push(p.expect_name()?);
+                    }
+                    Target::Unpack(names)
+                } else {
+                    Target::Name(name)
+                };
+                if !p.eat_op("=") {
+                    let Target::Name(name) = target else {
+                        return Err("block set needs a plain name".to_string());
+                    };
+                    p.done()?;
+                    let (body, _) = self.nodes(&["endset"])?;
+                    return Ok(Node::SetBlock(name, body));
+                }
+                let value = p.expr()?;
+                p.done()?;
+                Ok(Node::Set(target, value))
+            }
+            "macro" => {
+                let name = p.expect_name()?;
+                p.expect_op("(")?;
+                let mut params = Vec::new();
+                while !p.eat_op(")") {
+                    let param = p.expect_name()?;
+                    let default = if p.eat_op("=") { Some(p.expr()?) } else { None };
+                    params.push((param, default));
+                    if !p.eat_op(",") {
+                        p.expect_op(")")?;
+                        break;
+                    }
+                }
+                p.done()?;
+                let (body, _) = self.nodes(&["endmacro"])?;
+                Ok(Node::Macro(Rc::new(MacroDef { name, params, body })))
+            }
+            "generation" => {
+                let (body, _) = self.nodes(&["endgeneration"])?;
+                Ok(Node::If(
+                    vec![(Expr::Literal(Value::Bool(true)), body)],
+                    Vec::new(),
+                ))
+            }
+            "break" => Ok(Node::Break),
+            "continue" => Ok(Node::Continue),
+            other => Err(format!("unsupported tag {other}")),
+        }
+    }
+}
+
+/// What a template refers to, gathered without rendering it
+#[derive(Debug, Default)]
+pub(crate) struct Usage {
+    pub(crate) filters: Vec<String>,
+    pub(crate) tests: Vec<String>,
+    /// Functions called by name that the template does not define itself.
+    pub(crate) functions: Vec<String>,
+    pub(crate) methods: Vec<String>,
+    /// Variables read by the template.
+    pub(crate) names: Vec<String>,
+    /// Names assigned by `set`, `for` or `macro`.
+    pub(crate) defined: Vec<String>,
+    /// Messages passed to `raise_exception` calls, `None` if not a string literal.
+    pub(crate) raises: Vec<(Option<String>, bool)>,
+    pub(crate) loop_controls: bool,
+    /// Attributes read with `.name` or with a string literal subscript.
+    pub(crate) attributes: Vec<String>,
+    /// Text blocks and string literals.
+    pub(crate) texts: Vec<String>,
+    /// Integer literals passed to `range` or multiplying a value.
+    pub(crate) counts: Vec<i64>,
+    /// Deepest nesting of `for` loops.
+    pub(crate) max_loop_depth: usize,
+    loop_depth: usize,
+}
+
+impl Usage {
+    fn push(list: &mut Vec<String>, name: &str) {
+        if !list.iter().any(|n| n == name) {
+            list.push(name.to_string());
+        }
+    }
+
+    /// record what the nodes use, `conditional` telling whether they always run
+    fn nodes(&mut self, nodes: &[Node], conditional: bool) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => self.texts.push(text.clone()),
+                Node::Output(e) => self.expr(e, conditional),
+                Node::If(branches, otherwise) => {
+                    for (i, (cond, body)) in branches.iter().enumerate() {
+                        self.expr(cond, conditional || i > 0);
+                        self.nodes(body, true);
+                    }
+                    self.nodes(otherwise, true);
+                }
+                Node::For {
+                    targets,
+                    iter,
+                    filter,
+                    body,
+                    otherwise,
+                } => {
+                    for t in targets {
+                        Self::push(&mut self.defined, t);
+                    }
+                    self.expr(iter, conditional);
+                    if let Some(f) = filter {
+                        self.expr(f, true);
+                    }
+                    self.loop_depth += 1;
+                    self.max_loop_depth = self.max_loop_depth.max(self.loop_depth);
+                    self.nodes(body, true);
+                    self.loop_depth -= 1;
+                    self.nodes(otherwise, true);
+                }
+                Node::Set(target, value) => {
+                    match target {
+                        Target::Name(n) | Target::Attr(n, _) => Self::push(&mut self.defined, n),
+                        Target::Unpack(names) => {
+                            for n in names {
+                                Self::push(&mut self.defined, n);
+                            }
+                        }
+                    }
+                    self.expr(value, conditional);
+                }
+                Node::SetBlock(name, body) => {
+                    Self::push(&mut self.defined, name);
+                    self.nodes(body, conditional);
+                }
+                Node::Macro(def) => {
+                    Self::push(&mut self.defined, &def.name);
+                    for (param, default) in &def.params {
+                        Self::push(&mut self.defined, param);
+                        if let Some(d) = default {
+                            self.expr(d, true);
+                        }
+                    }
+                    self.nodes(&def.body, true);
+                }
+                Node::Break | Node::Continue => self.loop_controls = true,
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &Expr, conditional: bool) {
+        match e {
+            Expr::Literal(Value::String(s)) => self.texts.push(s.clone()),
+            Expr::Literal(_) => {}
+            Expr::List(items) => items.iter().for_each(|i| self.expr(i, conditional)),
+            Expr::Dict(pairs) => {
+                for (k, v) in pairs {
+                    self.expr(k, conditional);
+                    self.expr(v, conditional);
+                }
+            }
+            Expr::Name(n) => Self::push(&mut self.names, n),
+            Expr::Attr(obj, attr) => {
+                Self::push(&mut self.attributes, attr);
+                self.expr(obj, conditional);
+            }
+            Expr::Index(obj, index) => {
+                if let Expr::Literal(Value::String(attr)) = &**index {
+                    Self::push(&mut self.attributes, attr);
+                }
+                self.expr(obj, conditional);
+                self.expr(index, conditional);
+            }
+            Expr::Slice(obj, bounds) => {
+                self.expr(obj, conditional);
+                for b in bounds.iter().flatten() {
+                    self.expr(b, conditional);
+                }
+            }
+            Expr::Call(callee, args, kwargs) => {
+                match &**callee {
+                    Expr::Name(name) => {
+                        Self::push(&mut self.functions, name);
+                        if name == "range" {
+                            for arg in args {
+                                if let Expr::Literal(Value::Int(n)) = arg {
+                                    self.counts.push(*n);
+                                }
+                            }
+                        }
+                        if name == "raise_exception" {
+                            let message = match args.first() {
+                                Some(Expr::Literal(Value::String(m))) => Some(m.clone()),
+                                _ => None,
+                            };
+                            self.raises.push((message, conditional));
+                        }
+                    }
+                    Expr::Attr(obj, method) => {
+                        Self::push(&mut self.methods, method);
+                        self.expr(obj, conditional);
+                    }
+                    other => self.expr(other, conditional),
+                }
+                args.iter().for_each(|a| self.expr(a, conditional));
+                kwargs.iter().for_each(|(_, v)| self.expr(v, conditional));
+            }
+            Expr::Filter(value, name, args, kwargs) => {
+                Self::push(&mut self.filters, name);
+                if name == "attr" {
+                    if let Some(Expr::Literal(Value::String(attr))) = args.first() {
+                        Self::push(&mut self.attributes, attr);
+                    }
+                }
+                self.expr(value, conditional);
+                args.iter().for_each(|a| self.expr(a, conditional));
+                kwargs.iter().for_each(|(_, v)| self.expr(v, conditional));
+            }
+            Expr::Test(value, name, args, _) => {
+                Self::push(&mut self.tests, name);
+                self.expr(value, conditional);
+                args.iter().for_each(|a| self.expr(a, conditional));
+            }
+            Expr::Not(e) | Expr::Neg(e) => self.expr(e, conditional),
+            Expr::Binary(op, a, b) => {
+                if matches!(op, BinOp::Mul | BinOp::Pow) {
+                    for operand in [a, b] {
+                        if let Expr::Literal(Value::Int(n)) = &**operand {
+                            self.counts.push(*n);
+                        }
+                    }
+                }
+                self.expr(a, conditional);
+                self.expr(b, conditional || matches!(op, BinOp::And | BinOp::Or));
+            }
+            Expr::Cond(cond, value, otherwise) => {
+                self.expr(cond, conditional);
+                self.expr(value, true);
+                if let Some(o) = otherwise {
+                    self.expr(o, true);
+                }
+            }
+        }
+    }
+}
+
+/// A parsed template
+pub(crate) struct Template {
+    pub(crate) nodes: Vec<Node>,
+}
+
+impl Template {
+    /// Parse template source
+    pub(crate) fn parse(source: &str) -> Result<Template, String> {
+        let mut parser = Parser {
+            segments: lex(source)?.into_iter(),
+            depth: 0,
+        };
+        let (nodes, _) = parser.nodes(&[])?;
+        Ok(Template { nodes })
+    }
+
+    /// Gather the names, filters and calls the template uses
+    pub(crate) fn usage(&self) -> Usage {
+        let mut usage = Usage::default();
+        usage.nodes(&self.nodes, false);
+        usage
+    }
+
+    /// Render with the given global variables
+    pub(crate) fn render(&self, globals: Vec<(String, Value)>) -> Result<String, String> {
+        let mut env = Env {
+            scopes: vec![globals],
+            calls: 0,
+            depth: 0,
+        };
+        let mut out = String::new();
+        env.exec(&self.nodes, &mut out)?;
+        Ok(out)
+    }
+}
+
+/// how a block finished
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+}
+
+/// The deepest macros call each other
+const MAX_CALL_DEPTH: usize = 32;
+
+/// The deepest blocks, expressions and macro calls nest together, before the stack of the
+/// renderer would overflow
+const MAX_RENDER_DEPTH: usize = 128;
+
+/// variable scopes while rendering
+struct Env {
+    scopes: Vec<Vec<(String, Value)>>,
+    /// macro calls open
+    calls: usize,
+    /// blocks and expressions open
+    depth: usize,
+}
+
+impl Env {
+    fn lookup(&self, name: &str) -> Option<Value> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.iter().rev().find(|(k, _)| k == name))
+            .map(|(_, v)| v.clone())
+    }
+
+    fn assign(&mut self, name: &str, value: Value) {
+        let scope = self.scopes.last_mut().unwrap();
+        match scope.iter_mut().find(|(k, _)| k == name) {
+            Some(slot) => slot.1 = value,
+            None => scope.push((name.to_string(), value)),
+        }
+    }
+
+    fn exec(&mut self, nodes: &[Node], out: &mut String) -> Result<Flow, String> {
+        self.enter()?;
+        let flow = self.exec_nodes(nodes, out);
+        self.depth -= 1;
+        flow
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        if self.depth == MAX_RENDER_DEPTH {
+            return Err(format!("template nested more than {MAX_RENDER_DEPTH} deep"));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn exec_nodes(&mut self, nodes: &[Node], out: &mut String) -> Result<Flow, String> {
+        for node in nodes {
+            match node {
+                Node::Text(t) => out.push_str(t),
+                Node::Output(e) => {
+                    let _ = write!(out, "{}", self.eval(e)?);
+                }
+                Node::If(branches, otherwise) => {
+                    let mut taken = None;
+                    for (cond, body) in branches {
+                        if self.eval(cond)?.is_true() {
+                            taken = Some(body);
+                            break;
+                        }
+                    }
+                    match self.exec(taken.unwrap_or(otherwise), out)? {
+                        Flow::Normal => {}
+                        flow => return Ok(flow),
+                    }
+                }
+                Node::For {
+                    targets,
+                    iter,
+                    filter,
+                    body,
+                    otherwise,
+                } => {
+                    let mut items = Vec::new();
+                    for item in self.eval(iter)?.iter_values()? {
+                        if let Some(filter) = filter {
+                            self.scopes.push(Vec::new());
+                            self.bind(targets, item.clone())?;
+                            let keep = self.eval(filter);
+                            self.scopes.pop();
+                            if !keep?.is_true() {
+                                continue;
+                            }
+                        }
+                        items.push(item);
+                    }
+                    if items.is_empty() {
+                        self.exec(otherwise, out)?;
+                        continue;
+                    }
+                    let len = items.len();
+                    for (i, item) in items.iter().enumerate() {
+                        let loop_info = Value::map([
+                            ("index", Value::Int(i as i64 + 1)),
+                            ("index0", Value::Int(i as i64)),
+                            ("revindex", Value::Int((len - i) as i64)),
+                            ("revindex0", Value::Int((len - i - 1) as i64)),
+                            ("first", Value::Bool(i == 0)),
+                            ("last", Value::Bool(i + 1 == len)),
+                            ("length", Value::Int(len as i64)),
+                            (
+                                "previtem",
+                                i.checked_sub(1)
+                                    .map(|p| items[p].clone())
+                                    .unwrap_or_default(),
+                            ),
+                            ("nextitem", items.get(i + 1).cloned().unwrap_or_default()),
+                        ]);
+                        self.scopes.push(vec![("loop".to_string(), loop_info)]);
+                        let result = self
+                            .bind(targets, item.clone())
+                            .and_then(|_| self.exec(body, out));
+                        self.scopes.pop();
+                        if let Flow::Break = result? {
+                            break;
+                        }
+                    }
+                }
+                Node::Set(target, value) => {
+                    let value = self.eval(value)?;
+                    match target {
+                        Target::Name(name) => self.assign(name, value),
+                        Target::Unpack(names) => self.bind(names, value)?,
+                        Target::Attr(name, attr) => match self.lookup(name) {
+                            Some(Value::Namespace(ns)) => {
+                                let mut pairs = ns.0.borrow_mut();
+                                match pairs.iter_mut().find(|(k, _)| k == attr) {
+                                    Some(slot) => slot.1 = value,
+                                    None => pairs.push((attr.clone(), value)),
+                                }
+                            }
+                            _ => return Err(format!("cannot assign attribute of {name}")),
+                        },
+                    }
+                }
+                Node::SetBlock(name, body) => {
+                    let mut captured = String::new();
+                    self.exec(body, &mut captured)?;
+                    self.assign(name, Value::String(captured));
+                }
+                Node::Macro(def) => self.assign(&def.name, Value::Macro(Macro(def.clone()))),
+                Node::Break => return Ok(Flow::Break),
+                Node::Continue => return Ok(Flow::Continue),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// bind loop or unpacking targets in the innermost scope
+    fn bind(&mut self, targets: &[String], item: Value) -> Result<(), String> {
+        if let [target] = targets {
+            self.assign(target, item);
+            return Ok(());
+        }
+        let values = item.iter_values()?;
+        if values.len() != targets.len() {
+            return Err(format!(
+                "cannot unpack {} values into {} names",
+                values.len(),
+                targets.len()
+            ));
+        }
+        for (target, value) in targets.iter().zip(values) {
+            self.assign(target, value);
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, e: &Expr) -> Result<Value, String> {
+        self.enter()?;
+        let value = self.eval_expr(e);
+        self.depth -= 1;
+        value
+    }
+
+    fn eval_expr(&mut self, e: &Expr) -> Result<Value, String> {
+        Ok(match e {
+            Expr::Literal(v) => v.clone(),
+            Expr::List(items) => Value::List(
+                items
+                    .iter()
+                    .map(|i| self.eval(i))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expr::Dict(pairs) => {
+                let mut map = Vec::new();
+                for (k, v) in pairs {
+                    map.push((self.eval(k)?.to_string(), self.eval(v)?));
+                }
+                Value::Map(map)
+            }
+            Expr::Name(n) => self.lookup(n).unwrap_or_default(),
+            Expr::Attr(obj, name) => {
+                let value = self.eval(obj)?;
+                defined(obj, &value)?;
+                value.get(name).unwrap_or_default()
+            }
+            Expr::Index(obj, index) => {
+                let value = self.eval(obj)?;
+                defined(obj, &value)?;
+                let index = self.eval(index)?;
+                subscript(&value, &index)
+            }
+            Expr::Slice(obj, [start, stop, step]) => {
+                let obj = self.eval(obj)?;
+                let mut bound = |b: &Option<Box<Expr>>| -> Result<Option<i64>, String> {
+                    match b {
+                        None => Ok(None),
+                        Some(e) => Ok(self.eval(e)?.as_i64()),
+                    }
+                };
+                let (start, stop, step) = (bound(start)?, bound(stop)?, bound(step)?);
+                slice(&obj, start, stop, step.unwrap_or(1))?
+            }
+            Expr::Call(callee, args, kwargs) => {
+                let args = args
+                    .iter()
+                    .map(|a| self.eval(a))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let kwargs = kwargs
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), self.eval(v)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                match &**callee {
+                    Expr::Attr(obj, method) => {
+                        let obj = self.eval(obj)?;
+                        match obj.get(method) {
+                            Some(Value::Macro(m)) => self.call_macro(&m, args, kwargs)?,
+                            _ => call_method(&obj, method, &args, &kwargs)?,
+                        }
+                    }
+                    Expr::Name(name) => match self.lookup(name) {
+                        Some(Value::Macro(m)) => self.call_macro(&m, args, kwargs)?,
+                        Some(other) => {
+                            return Err(format!("{} is not callable", other.type_name()))
+                        }
+                        None => call_global(name, &args, &kwargs)?,
+                    },
+                    _ => return Err("expression is not callable".to_string()),
+                }
+            }
+            Expr::Filter(value, name, args, kwargs) => {
+                let value = self.eval(value)?;
+                let args = args
+                    .iter()
+                    .map(|a| self.eval(a))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let kwargs = kwargs
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), self.eval(v)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                apply_filter(name, value, &args, &kwargs)?
+            }
+            Expr::Test(value, name, args, negated) => {
+                let value = self.eval(value)?;
+                let args = args
+                    .iter()
+                    .map(|a| self.eval(a))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Value::Bool(apply_test(name, &value, &args)? != *negated)
+            }
+            Expr::Not(e) => Value::Bool(!self.eval(e)?.is_true()),
+            Expr::Neg(e) => match self.eval(e)? {
+                Value::Int(i) => Value::Int(-i),
+                Value::Float(f) => Value::Float(-f),
+                other => return Err(format!("cannot negate {}", other.type_name())),
+            },
+            Expr::Binary(BinOp::And, a, b) => {
+                let a = self.eval(a)?;
+                if a.is_true() {
+                    self.eval(b)?
+                } else {
+                    a
+                }
+            }
+            Expr::Binary(BinOp::Or, a, b) => {
+                let a = self.eval(a)?;
+                if a.is_true() {
+                    a
+                } else {
+                    self.eval(b)?
+                }
+            }
+            Expr::Binary(op, a, b) => {
+                let a = self.eval(a)?;
+                let b = self.eval(b)?;
+                binary(*op, &a, &b)?
+            }
+            Expr::Cond(cond, value, otherwise) => {
+                if self.eval(cond)?.is_true() {
+                    self.eval(value)?
+                } else {
+                    match otherwise {
+                        Some(e) => self.eval(e)?,
+                        None => Value::Undefined,
+                    }
+                }
+            }
+        })
+    }
+
+    fn call_macro(
+        &mut self,
+        m: &Macro,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<Value, String> {
+        let def = m.0.clone();
+        let mut scope = Vec::new();
+        let mut args = args.into_iter();
+        for (param, default) in &def.params {
+            let value = match args.next() {
+                Some(v) => v,
+                None => match kwargs.iter().find(|(k, _)| k == param) {
+                    Some((_, v)) => v.clone(),
+                    None => match default {
+                        Some(e) => self.eval(e)?,
+                        None => Value::Undefined,
+                    },
+                },
+            };
+            scope.push((param.clone(), value));
+        }
+        if self.calls == MAX_CALL_DEPTH {
+            return Err(format!(
+                "macro {} called more than {MAX_CALL_DEPTH} deep",
+                def.name
+            ));
+        }
+        // macros only see the template globals, not the caller's locals
+        let saved = self.scopes.split_off(1);
+        self.scopes.push(scope);
+        self.calls += 1;
+        let mut out = String::new();
+        let result = self.exec(&def.body, &mut out);
+        self.calls -= 1;
+        self.scopes.truncate(1);
+        self.scopes.extend(saved);
+        result?;
+        Ok(Value::String(out))
+    }
+}
+
+/// An error for looking into `value`, the value of `e`, if it is undefined, as jinja2 raises one
+fn defined(e: &Expr, value: &Value) -> Result<(), String> {
+    match (e, value) {
+        (Expr::Name(n), Value::Undefined) => Err(format!("'{n}' is undefined")),
+        (Expr::Attr(_, n), Value::Undefined) => Err(format!("no attribute '{n}'")),
+        (_, Value::Undefined) => Err("undefined value has no attributes".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// `obj[index]`
+fn subscript(obj: &Value, index: &Value) -> Value {
+    match (obj, index) {
+        (Value::List(items), Value::Int(i)) => {
+            let i = if *i < 0 { items.len() as i64 + i } else { *i };
+            usize::try_from(i)
+                .ok()
+                .and_then(|i| items.get(i))
+                .cloned()
+                .unwrap_or_default()
+        }
+        (Value::String(s), Value::Int(i)) => {
+            let chars: Vec<char> = s.chars().collect();
+            let i = if *i < 0 { chars.len() as i64 + i } else { *i };
+            usize::try_from(i)
+                .ok()
+                .and_then(|i| chars.get(i))
+                .map(|c| Value::String(c.to_string()))
+                .unwrap_or_default()
+        }
+        (_, Value::String(key)) => obj.get(key).unwrap_or_default(),
+        _ => Value::Undefined,
+    }
+}
+
+/// Python slice semantics over lists and strings
+fn slice(obj: &Value, start: Option<i64>, stop: Option<i64>, step: i64) -> Result<Value, String> {
+    if step == 0 {
+        return Err("slice step cannot be zero".to_string());
+    }
+    let items = match obj {
+        Value::List(items) => items.clone(),
+        Value::String(s) => s.chars().map(|c| Value::String(c.to_string())).collect(),
+        Value::Undefined | Value::None => Vec::new(),
+        other => return Err(format!("cannot slice {}", other.type_name())),
+    };
+    let len = items.len() as i64;
+    let clamp = |v: i64, lo: i64, hi: i64| v.max(lo).min(hi);
+    let norm = |v: i64| if v < 0 { v + len } else { v };
+    let mut picked = Vec::new();
+    if step > 0 {
+        let start = clamp(start.map(norm).unwrap_or(0), 0, len);
+        let stop = clamp(stop.map(norm).unwrap_or(len), 0, len);
+        let mut i = start;
+        while i < stop {
+            picked.push(items[i as usize].clone());
+            i += step;
+        }
+    } else {
+        let start = clamp(start.map(norm).unwrap_or(len - 1), -1, len - 1);
+        let stop = clamp(stop.map(norm).unwrap_or(-1), -1, len - 1);
+        let mut i = start;
+        while i > stop {
+            picked.push(items[i as usize].clone());
+            i += step;
+        }
+    }
+    Ok(match obj {
+        Value::String(_) => Value::String(picked.iter().map(|v| v.to_string()).collect()),
+        _ => Value::List(picked),
+    })
+}
+
+fn binary(op: BinOp, a: &Value, b: &Value) -> Result<Value, String> {
+    use std::cmp::Ordering;
+    let numeric = |f: fn(f64, f64) -> f64, i: fn(i64, i64) -> Option<i64>| match (a, b) {
+        (Value::Int(x), Value::Int(y)) => i(*x, *y)
+            .map(Value::Int)
+            .ok_or_else(|| "integer overflow".to_string()),
+        _ => match (a.as_f64(), b.as_f64()) {
+            (Some(x), Some(y)) => Ok(Value::Float(f(x, y))),
+            _ => Err(format!(
+                "unsupported operand types {} and {}",
+                a.type_name(),
+                b.type_name()
+            )),
+        },
+    };
+    let order = || -> Result<Ordering, String> {
+        match (a, b) {
+            (Value::String(x), Value::String(y)) => Ok(x.cmp(y)),
+            _ => match (a.as_f64(), b.as_f64()) {
+                (Some(x), Some(y)) => x.partial_cmp(&y).ok_or_else(|| "cannot compare NaN".into()),
+                _ => Err(format!(
+                    "cannot compare {} and {}",
+                    a.type_name(),
+                    b.type_name()
+                )),
+            },
+        }
+    };
+    Ok(match op {
+        BinOp::Add => match (a, b) {
+            (Value::String(x), Value::String(y)) => Value::String(format!("{x}{y}")),
+            (Value::List(x), Value::List(y)) => Value::List(x.iter().chain(y).cloned().collect()),
+            _ => numeric(|x, y| x + y, i64::checked_add)?,
+        },
+        BinOp::Sub => numeric(|x, y| x - y, i64::checked_sub)?,
+        BinOp::Mul => match (a, b) {
+            (Value::String(s), Value::Int(n)) | (Value::Int(n), Value::String(s)) => {
+                Value::String(s.repeat((*n).max(0) as usize))
+            }
+            _ => numeric(|x, y| x * y, i64::checked_mul)?,
+        },
+        BinOp::Div => match (a.as_f64(), b.as_f64()) {
+            (Some(x), Some(y)) => {
+                if y == 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                Value::Float(x / y)
+            }
+            _ => return Err("unsupported operands for /".to_string()),
+        },
+        BinOp::FloorDiv => {
+            if b.as_f64() == Some(0.0) {
+                return Err("division by zero".to_string());
+            }
+            numeric(|x, y| (x / y).floor(), |x, y| Some(x.div_euclid(y)))?
+        }
+        BinOp::Mod => {
+            if b.as_f64() == Some(0.0) {
+                return Err("division by zero".to_string());
+            }
+            numeric(|x, y| x.rem_euclid(y), |x, y| Some(x.rem_euclid(y)))?
+        }
+        BinOp::Pow => numeric(f64::powf, |x, y| {
+            u32::try_from(y).ok().and_then(|y| x.checked_pow(y))
+        })?,
+        BinOp::Concat => Value::String(format!("{a}{b}")),
+        BinOp::Eq => Value::Bool(a == b),
+        BinOp::Ne => Value::Bool(a != b),
+        BinOp::Lt => Value::Bool(order()? == Ordering::Less),
+        BinOp::Gt => Value::Bool(order()? == Ordering::Greater),
+        BinOp::Le => Value::Bool(order()? != Ordering::Greater),
+        BinOp::Ge => Value::Bool(order()? != Ordering::Less),
+        BinOp::In | BinOp::NotIn => {
+            let found = match b {
+                Value::String(s) => s.contains(&a.to_string()),
+                Value::List(items) => items.contains(a),
+                Value::Map(_) | Value::Namespace(_) => b.get(&a.to_string()).is_some(),
+                Value::Undefined | Value::None => false,
+                other => return Err(format!("cannot search in {}", other.type_name())),
+            };
+            Value::Bool(found == (op == BinOp::In))
+        }
+        BinOp::And | BinOp::Or => unreachable!("short-circuit operators are evaluated lazily"),
+    })
+}
+
+fn kwarg<'a>(kwargs: &'a [(String, Value)], name: &str) -> Option<&'a Value> {
+    kwargs.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+}
+
+/// positional argument or keyword argument
+fn arg<'a>(
+    args: &'a [Value],
+    kwargs: &'a [(String, Value)],
+    index: usize,
+    name: &str,
+) -> Option<&'a Value> {
+    args.get(index).or_else(|| kwarg(kwargs, name))
+}
+
+/// Names of the filters the renderer implements
+pub(crate) const FILTERS: &[&str] = &[
+    "abs",
+    "attr",
+    "capitalize",
+    "count",
+    "d",
+    "default",
+    "dictsort",
+    "e",
+    "escape",
+    "first",
+    "float",
+    "indent",
+    "int",
+    "items",
+    "join",
+    "last",
+    "length",
+    "list",
+    "lower",
+    "map",
+    "max",
+    "min",
+    "reject",
+    "rejectattr",
+    "replace",
+    "reverse",
+    "round",
+    "safe",
+    "select",
+    "selectattr",
+    "sort",
+    "string",
+    "sum",
+    "title",
+    "tojson",
+    "trim",
+    "unique",
+    "upper",
+    "wordcount",
+];
+
+fn apply_filter(
+    name: &str,
+    value: Value,
+    args: &[Value],
+    kwargs: &[(String, Value)],
+) -> Result<Value, String> {
+    let string = || value.to_string();
+    Ok(match name {
+        "trim" => Value::String(string().trim().to_string()),
+        "upper" => Value::String(string().to_uppercase()),
+        "lower" => Value::String(string().to_lowercase()),
+        "title" => Value::String(title_case(&string())),
+        "capitalize" => Value::String(capitalize(&string())),
+        "length" | "count" => Value::Int(match &value {
+            Value::String(s) => s.chars().count(),
+            Value::List(l) => l.len(),
+            Value::Map(m) => m.len(),
+            Value::Undefined | Value::None => 0,
+            other => return Err(format!("{} has no length", other.type_name())),
+        } as i64),
+        "default" | "d" => {
+            let fallback = arg(args, kwargs, 0, "default_value")
+                .cloned()
+                .unwrap_or(Value::String(String::new()));
+            let falsy = arg(args, kwargs, 1, "boolean").is_some_and(Value::is_true);
+            match value {
+                Value::Undefined => fallback,
+                v if falsy && !v.is_true() => fallback,
+                v => v,
+            }
+        }
+        "first" => value.iter_values()?.into_iter().next().unwrap_or_default(),
+        "last" => value.iter_values()?.pop().unwrap_or_default(),
+        "list" => Value::List(value.iter_values()?),
+        "string" | "safe" | "e" | "escape" => match value {
+            Value::String(_) => value,
+            v => Value::String(v.to_string()),
+        },
+        "join" => {
+            let sep = arg(args, kwargs, 0, "d")
+                .map(|v| v.to_string())
+                .unwrap_or_default();
+            let attribute = kwarg(kwargs, "attribute");
+            let parts: Vec<String> = value
+                .iter_values()?
+                .iter()
+                .map(|v| match attribute {
+                    Some(a) => v.get(&a.to_string()).unwrap_or_default().to_string(),
+                    None => v.to_string(),
+                })
+                .collect();
+            Value::String(parts.join(&sep))
+        }
+        "replace" => {
+            let (Some(old), Some(new)) = (args.first(), args.get(1)) else {
+                return Err("replace expects two arguments".to_string());
+            };
+            let s = string();
+            match args.get(2).and_then(Value::as_i64) {
+                Some(count) => Value::String(s.replacen(
+                    &old.to_string(),
+                    &new.to_string(),
+                    count.max(0) as usize,
+                )),
+                None => Value::String(s.replace(&old.to_string(), &new.to_string())),
+            }
+        }
+        "tojson" => {
+            let indent = arg(args, kwargs, 0, "indent")
+                .and_then(Value::as_i64)
+                .map(|i| i.max(0) as usize);
+            let mut out = String::new();
+            value.to_json(&mut out, indent, 0);
+            Value::String(out)
+        }
+        "items" => match value {
+            Value::Map(pairs) => Value::List(
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| Value::List(vec![Value::String(k), v]))
+                    .collect(),
+            ),
+            Value::Undefined | Value::None => Value::List(Vec::new()),
+            other => return Err(format!("{} has no items", other.type_name())),
+        },
+        "dictsort" => match value {
+            Value::Map(mut pairs) => {
+                pairs.sort_by(|a, b| a.0.cmp(&b.0));
+                Value::List(
+                    pairs
+                        .into_iter()
+                        .map(|(k, v)| Value::List(vec![Value::String(k), v]))
+                        .collect(),
+                )
+            }
+            other => return Err(format!("cannot dictsort {}", other.type_name())),
+        },
+        "int" => Value::Int(match &value {
+            Value::Int(i) => *i,
+            Value::Float(f) => *f as i64,
+            Value::Bool(b) => *b as i64,
+            Value::String(s) => s.trim().parse().unwrap_or(0),
+            _ => 0,
+        }),
+        "float" => Value::Float(match &value {
+            Value::String(s) => s.trim().parse().unwrap_or(0.0),
+            v => v.as_f64().unwrap_or(0.0),
+        }),
+        "abs" => match value {
+            Value::Int(i) => Value::Int(i.abs()),
+            Value::Float(f) => Value::Float(f.abs()),
+            other => return Err(format!("cannot take abs of {}", other.type_name())),
+        },
+        "round" => {
+            let precision = arg(args, kwargs, 0, "precision")
+                .and_then(Value::as_i64)
+                .unwrap_or(0);
+            let factor = 10f64.powi(precision as i32);
+            Value::Float((value.as_f64().unwrap_or(0.0) * factor).round() / factor)
+        }
+        "reverse" => match value {
+            Value::String(s) => Value::String(s.chars().rev().collect()),
+            v => Value::List(v.iter_values()?.into_iter().rev().collect()),
+        },
+        "sort" => {
+            let mut items = value.iter_values()?;
+            let attribute = kwarg(kwargs, "attribute").map(|a| a.to_string());
+            let key = |v: &Value| match &attribute {
+                Some(a) => v.get(a).unwrap_or_default(),
+                None => v.clone(),
+            };
+            items.sort_by(|a, b| {
+                binary(BinOp::Lt, &key(a), &key(b))
+                    .map(|lt| {
+                        if lt.is_true() {
+                            std::cmp::Ordering::Less
+                        } else if key(a) == key(b) {
+                            std::cmp::Ordering::Equal
+                        } else {
+                            std::cmp::Ordering::Greater
+                        }
+                    })
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            if kwarg(kwargs, "reverse").is_some_and(Value::is_true) {
+                items.reverse();
+            }
+            Value::List(items)
+        }
+        "unique" => {
+            let mut seen: Vec<Value> = Vec::new();
+            for item in value.iter_values()? {
+                if !seen.contains(&item) {
+                    seen.push(item);
+                }
+            }
+            Value::List(seen)
+        }
+        "sum" => {
+            let mut total = Value::Int(0);
+            for item in value.iter_values()? {
+                total = binary(BinOp::Add, &total, &item)?;
+            }
+            total
+        }
+        "min" | "max" => {
+            let items = value.iter_values()?;
+            let mut best: Option<Value> = None;
+            for item in items {
+                best = Some(match best {
+                    None => item,
+                    Some(b) => {
+                        let less = binary(BinOp::Lt, &item, &b)?.is_true();
+                        if less == (name == "min") {
+                            item
+                        } else {
+                            b
+                        }
+                    }
+                });
+            }
+            best.unwrap_or_default()
+        }
+        "attr" => {
+            let key = args.first().map(|a| a.to_string()).unwrap_or_default();
+            value.get(&key).unwrap_or_default()
+        }
+        "map" => {
+            let items = value.iter_values()?;
+            if let Some(attribute) = kwarg(kwargs, "attribute") {
+                let attribute = attribute.to_string();
+                let fallback = kwarg(kwargs, "default").cloned();
+                Value::List(
+                    items
+                        .iter()
+                        .map(|v| {
+                            v.get(&attribute)
+                                .or_else(|| fallback.clone())
+                                .unwrap_or_default()
+                        })
+                        .collect(),
+                )
+            } else {
+                let Some(filter) = args.first() else {
+                    return Err("map expects a filter name or attribute".to_string());
+                };
+                let filter = filter.to_string();
+                Value::List(
+                    items
+                        .into_iter()
+                        .map(|v| apply_filter(&filter, v, &args[1..], &[]))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+        }
+        "select" | "reject" | "selectattr" | "rejectattr" => {
+            let by_attr = name.ends_with("attr");
+            let keep_matching = name.starts_with("select");
+            let (attribute, rest) = if by_attr {
+                let Some(a) = args.first() else {
+                    return Err(format!("{name} expects an attribute name"));
+                };
+                (Some(a.to_string()), &args[1..])
+            } else {
+                (None, args)
+            };
+            let mut kept = Vec::new();
+            for item in value.iter_values()? {
+                let subject = match &attribute {
+                    Some(a) => item.get(a).unwrap_or_default(),
+                    None => item.clone(),
+                };
+                let matched = match rest.first() {
+                    Some(test) => apply_test(&test.to_string(), &subject, &rest[1..])?,
+                    None => subject.is_true(),
+                };
+                if matched == keep_matching {
+                    kept.push(item);
+                }
+            }
+            Value::List(kept)
+        }
+        "indent" => {
+            let width = arg(args, kwargs, 0, "width")
+                .and_then(Value::as_i64)
+                .unwrap_or(4)
+                .max(0) as usize;
+            let first = arg(args, kwargs, 1, "first").is_some_and(Value::is_true);
+            let pad = " ".repeat(width);
+            let mut out = String::new();
+            for (i, line) in string().split('\n').enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                }
+                if (i > 0 || first) && !line.is_empty() {
+                    out.push_str(&pad);
+                }
+                out.push_str(line);
+            }
+            Value::String(out)
+        }
+        "wordcount" => Value::Int(string().split_whitespace().count() as i64),
+        other => return Err(format!("unknown filter {other}")),
+    })
+}
+
+/// Names of the tests the renderer implements
+pub(crate) const TESTS: &[&str] = &[
+    "boolean",
+    "callable",
+    "defined",
+    "divisibleby",
+    "eq",
+    "equalto",
+    "even",
+    "false",
+    "float",
+    "ge",
+    "gt",
+    "in",
+    "integer",
+    "iterable",
+    "le",
+    "lower",
+    "lt",
+    "mapping",
+    "ne",
+    "none",
+    "number",
+    "odd",
+    "sameas",
+    "sequence",
+    "string",
+    "true",
+    "undefined",
+    "upper",
+];
+
+fn apply_test(name: &str, value: &Value, args: &[Value]) -> Result<bool, String> {
+    let other = || args.first().cloned().unwrap_or_default();
+    Ok(match name {
+        "defined" => !matches!(value, Value::Undefined),
+        "undefined" => matches!(value, Value::Undefined),
+        "none" => matches!(value, Value::None),
+        "boolean" => matches!(value, Value::Bool(_)),
+        "true" => matches!(value, Value::Bool(true)),
+        "false" => matches!(value, Value::Bool(false)),
+        "string" => matches!(value, Value::String(_)),
+        "number" => matches!(value, Value::Int(_) | Value::Float(_)),
+        "integer" => matches!(value, Value::Int(_)),
+        "float" => matches!(value, Value::Float(_)),
+        "mapping" => matches!(value, Value::Map(_) | Value::Namespace(_)),
+        "iterable" => matches!(value, Value::List(_) | Value::Map(_) | Value::String(_)),
+        "sequence" => matches!(value, Value::List(_) | Value::String(_)),
+        "callable" => matches!(value, Value::Macro(_)),
+        "lower" => value.to_string() == value.to_string().to_lowercase(),
+        "upper" => value.to_string() == value.to_string().to_uppercase(),
+        "even" => value.as_i64().is_some_and(|i| i % 2 == 0),
+        "odd" => value.as_i64().is_some_and(|i| i % 2 != 0),
+        "divisibleby" => match (value.as_i64(), other().as_i64()) {
+            (Some(a), Some(b)) if b != 0 => a % b == 0,
+            _ => false,
+        },
+        "eq" | "equalto" | "sameas" => *value == other(),
+        "ne" => *value != other(),
+        "lt" => binary(BinOp::Lt, value, &other())?.is_true(),
+        "le" => binary(BinOp::Le, value, &other())?.is_true(),
+        "gt" => binary(BinOp::Gt, value, &other())?.is_true(),
+        "ge" => binary(BinOp::Ge, value, &other())?.is_true(),
+        "in" => binary(BinOp::In, value, &other())?.is_true(),
+        other => return Err(format!("unknown test {other}")),
+    })
+}
+
+/// Names of the global functions the renderer implements
+pub(crate) const GLOBALS: &[&str] = &[
+    "dict",
+    "namespace",
+    "raise_exception",
+    "range",
+    "strftime_now",
+];
+
+fn call_global(name: &str, args: &[Value], kwargs: &[(String, Value)]) -> Result<Value, String> {
+    Ok(match name {
+        "raise_exception" => {
+            let message = args.first().map(|m| m.to_string()).unwrap_or_default();
+            return Err(format!("template raised an exception: {message}"));
+        }
+        "range" => {
+            let ints: Vec<i64> = args.iter().filter_map(Value::as_i64).collect();
+            let (start, stop, step) = match ints[..] {
+                [stop] => (0, stop, 1),
+                [start, stop] => (start, stop, 1),
+                [start, stop, step] if step != 0 => (start, stop, step),
+                _ => return Err("invalid range arguments".to_string()),
+            };
+            let mut items = Vec::new();
+            let mut i = start;
+            while (step > 0 && i < stop) || (step < 0 && i > stop) {
+                items.push(Value::Int(i));
+                i += step;
+                if items.len() > 1_000_000 {
+                    return Err("range is too large".to_string());
+                }
+            }
+            Value::List(items)
+        }
+        "namespace" => {
+            let mut pairs = match args.first() {
+                Some(Value::Map(pairs)) => pairs.clone(),
+                _ => Vec::new(),
+            };
+            pairs.extend(kwargs.iter().cloned());
+            Value::Namespace(Namespace(Rc::new(RefCell::new(pairs))))
+        }
+        "dict" => {
+            let mut pairs = match args.first() {
+                Some(Value::Map(pairs)) => pairs.clone(),
+                _ => Vec::new(),
+            };
+            pairs.extend(kwargs.iter().cloned());
+            Value::Map(pairs)
+        }
+        "strftime_now" => {
+            let format = args.first().map(|f| f.to_string()).unwrap_or_default();
+            let secs = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            Value::String(strftime(&format, secs))
+        }
+        other => return Err(format!("unknown function {other}")),
+    })
+}
+
+/// Names of the methods the renderer implements on strings, mappings and lists
+pub(crate) const METHODS: &[&str] = &[
+    "capitalize",
+    "count",
+    "cycle",
+    "endswith",
+    "find",
+    "get",
+    "index",
+    "isdigit",
+    "items",
+    "join",
+    "keys",
+    "lower",
+    "lstrip",
+    "replace",
+    "rstrip",
+    "split",
+    "splitlines",
+    "startswith",
+    "strip",
+    "title",
+    "upper",
+    "values",
+];
+
+fn call_method(
+    obj: &Value,
+    method: &str,
+    args: &[Value],
+    kwargs: &[(String, Value)],
+) -> Result<Value, String> {
+    let str_arg = |i: usize| args.get(i).map(|a| a.to_string());
+    let matches_any = |s: &str, f: &dyn Fn(&str, &str) -> bool| match args.first() {
+        Some(Value::List(options)) => options.iter().any(|o| f(s, &o.to_string())),
+        Some(v) => f(s, &v.to_string()),
+        None => false,
+    };
+    Ok(match (obj, method) {
+        (Value::String(s), "strip") => Value::String(match str_arg(0) {
+            Some(chars) => s.trim_matches(|c| chars.contains(c)).to_string(),
+            None => s.trim().to_string(),
+        }),
+        (Value::String(s), "lstrip") => Value::String(match str_arg(0) {
+            Some(chars) => s.trim_start_matches(|c| chars.contains(c)).to_string(),
+            None => s.trim_start().to_string(),
+        }),
+        (Value::String(s), "rstrip") => Value::String(match str_arg(0) {
+            Some(chars) => s.trim_end_matches(|c| chars.contains(c)).to_string(),
+            None => s.trim_end().to_string(),
+        }),
+        (Value::String(s), "upper") => Value::String(s.to_uppercase()),
+        (Value::String(s), "lower") => Value::String(s.to_lowercase()),
+        (Value::String(s), "title") => Value::String(title_case(s)),
+        (Value::String(s), "capitalize") => Value::String(capitalize(s)),
+        (Value::String(s), "startswith") => Value::Bool(matches_any(s, &|s, p| s.starts_with(p))),
+        (Value::String(s), "endswith") => Value::Bool(matches_any(s, &|s, p| s.ends_with(p))),
+        (Value::String(s), "replace") => match (str_arg(0), str_arg(1)) {
+            (Some(old), Some(new)) => Value::String(s.replace(&old, &new)),
+            _ => return Err("replace expects two arguments".to_string()),
+        },
+        (Value::String(s), "find") => Value::Int(match str_arg(0) {
+            Some(needle) => s
+                .find(&needle)
+                .map(|i| s[..i].chars().count() as i64)
+                .unwrap_or(-1),
+            None => -1,
+        }),
+        (Value::String(s), "count") => {
+            Value::Int(str_arg(0).map(|n| s.matches(&n).count()).unwrap_or(0) as i64)
+        }
+        (Value::String(s), "split") => {
+            let max = arg(args, kwargs, 1, "maxsplit")
+                .and_then(Value::as_i64)
+                .filter(|m| *m >= 0);
+            let parts: Vec<Value> = match arg(args, kwargs, 0, "sep") {
+                Some(Value::None) | None => match max {
+                    Some(m) => {
+                        let mut parts = Vec::new();
+                        let mut rest = s.trim_start();
+                        while !rest.is_empty() {
+                            if parts.len() as i64 == m {
+                                parts.push(rest.to_string());
+                                break;
+                            }
+                            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                            parts.push(rest[..end].to_string());
+                            rest = rest[end..].trim_start();
+                        }
+                        parts.into_iter().map(Value::String).collect()
+                    }
+                    None => s
+                        .split_whitespace()
+                        .map(|p| Value::String(p.to_string()))
+                        .collect(),
+                },
+                Some(sep) => {
+                    let sep = sep.to_string();
+                    if sep.is_empty() {
+                        return Err("empty separator".to_string());
+                    }
+                    match max {
+                        Some(m) => s
+                            .splitn(m as usize + 1, sep.as_str())
+                            .map(|p| Value::String(p.to_string()))
+                            .collect(),
+                        None => s
+                            .split(sep.as_str())
+                            .map(|p| Value::String(p.to_string()))
+                            .collect(),
+                    }
+                }
+            };
+            Value::List(parts)
+        }
+        (Value::String(s), "splitlines") => {
+            Value::List(s.lines().map(|l| Value::String(l.to_string())).collect())
+        }
+        (Value::String(s), "join") => {
+            let parts: Vec<String> = args
+                .first()
+                .map(Value::iter_values)
+                .transpose()?
+                .unwrap_or_default()
+                .iter()
+                .map(|v| v.to_string())
+                .collect();
+            Value::String(parts.join(s))
+        }
+        (Value::String(s), "isdigit") => {
+            Value::Bool(!s.is_empty() && s.chars().all(|c| c.is_ascii_digit()))
+        }
+        (Value::Map(pairs), "items") => Value::List(
+            pairs
+                .iter()
+                .map(|(k, v)| Value::List(vec![Value::String(k.clone()), v.clone()]))
+                .collect(),
+        ),
+        (Value::Map(pairs), "keys") => Value::List(
+            pairs
+                .iter()
+                .map(|(k, _)| Value::String(k.clone()))
+                .collect(),
+        ),
+        (Value::Map(pairs), "values") => {
+            Value::List(pairs.iter().map(|(_, v)| v.clone()).collect())
+        }
+        (Value::Map(_), "get") => {
+            let key = str_arg(0).unwrap_or_default();
+            obj.get(&key)
+                .or_else(|| args.get(1).cloned())
+                .unwrap_or(Value::None)
+        }
+        (Value::List(items), "count") => {
+            let needle = args.first().cloned().unwrap_or_default();
+            Value::Int(items.iter().filter(|i| **i == needle).count() as i64)
+        }
+        (Value::List(items), "index") => {
+            let needle = args.first().cloned().unwrap_or_default();
+            match items.iter().position(|i| *i == needle) {
+                Some(i) => Value::Int(i as i64),
+                None => return Err("value is not in list".to_string()),
+            }
+        }
+        (Value::Map(_), "cycle") => {
+            let index = obj.get("index0").and_then(|i| i.as_i64()).unwrap_or(0);
+            if args.is_empty() {
+                Value::Undefined
+            } else {
+                args[index as usize % args.len()].clone()
+            }
+        }
+        (obj, method) => {
+            return Err(format!("{} has no method {method}", obj.type_name()));
+        }
+    })
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+fn title_case(s: &str) -> String {
+    let mut out = String::new();
+    let mut start = true;
+    for c in s.chars() {
+        if start {
+            out.extend(c.to_uppercase());
+        } else {
+            out.extend(c.to_lowercase());
+        }
+        start = !c.is_alphanumeric();
+    }
+    out
+}
+
+/// format a UTC timestamp with the common strftime directives
+fn strftime(format: &str, secs: i64) -> String {
+    const MONTHS: [&str; 12] = [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ];
+    const DAYS: [&str; 7] = [
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+    ];
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+    // civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    let weekday = (days + 3).rem_euclid(7) as usize;
+    let (hour, minute, second) = (rem / 3600, rem % 3600 / 60, rem % 60);
+
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let month_name = MONTHS[month as usize - 1];
+        let _ = match chars.next() {
+            Some('Y') => write!(out, "{year}"),
+            Some('y') => write!(out, "{:02}", year % 100),
+            Some('m') => write!(out, "{month:02}"),
+            Some('d') => write!(out, "{day:02}"),
+            Some('e') => write!(out, "{day:2}"),
+            Some('B') => write!(out, "{month_name}"),
+            Some('b') => write!(out, "{}", &month_name[..3]),
+            Some('A') => write!(out, "{}", DAYS[weekday]),
+            Some('a') => write!(out, "{}", &DAYS[weekday][..3]),
+            Some('H') => write!(out, "{hour:02}"),
+            Some('M') => write!(out, "{minute:02}"),
+            Some('S') => write!(out, "{second:02}"),
+            Some('j') => write!(out, "{:03}", doy_of(year, month, day)),
+            Some('%') => write!(out, "%"),
+            Some(other) => write!(out, "%{other}"),
+            None => write!(out, "%"),
+        };
+    }
+    out
+}
+
+/// day of the year, starting at 1
+fn doy_of(year: i64, month: i64, day: i64) -> i64 {
+    const CUMULATIVE: [i64; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    CUMULATIVE[month as usize - 1] + day + (leap && month > 2) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str, globals: Vec<(&str, Value)>) -> String {
+        Template::parse(source)
+            .unwrap()
+            .render(
+                globals
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v))
+                    .collect(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn whitespace_control_and_loops() {
+        let source =
+            "{% for x in items %}\n  {{- x ~ (',' if not loop.last else '') -}}\n{% endfor %}";
+        let items = Value::List(vec![1.into(), 2.into(), Value::from("a")]);
+        assert_eq!(render(source, vec![("items", items)]), "1,2,a");
+    }
+
+    #[test]
+    fn namespace_filters_and_slices() {
+        let source = "{% set ns = namespace(n=0) %}{% for m in msgs[1:] if m.role == 'user' %}\
+            {% set ns.n = ns.n + 1 %}{% endfor %}{{ ns.n }} {{ msgs | map(attribute='role') | join('/') }} \
+            {{ msgs[0].content | trim | upper }} {{ {'a': [1, none]} | tojson }} {{ 'x' if undefined_var is defined else 'y' }}";
+        let msg = |role: &str, content: &str| {
+            Value::map([
+                ("role", Value::from(role)),
+                ("content", Value::from(content)),
+            ])
+        };
+        let msgs = Value::List(vec![
+            msg("system", " be nice "),
+            msg("user", "hi"),
+            msg("assistant", "hello"),
+            msg("user", "bye"),
+        ]);
+        assert_eq!(
+            render(source, vec![("msgs", msgs)]),
+            "2 system/user/assistant/user BE NICE {\"a\": [1, null]} y"
+        );
+    }
+
+    #[test]
+    fn macros_and_errors() {
+        let source = "{% macro tag(name, body='') %}<{{ name }}>{{ body }}</{{ name }}>{% endmacro %}{{ tag('b', body='x') }}";
+        assert_eq!(render(source, vec![]), "<b>x</b>");
+        let t = Template::parse("{{ raise_exception('no') }}").unwrap();
+        assert!(t.render(Vec::new()).unwrap_err().contains("no"));
+        assert_eq!(strftime("%d %b %Y", 1_720_000_000), "03 Jul 2024");
+    }
+
+    fn error(source: &str) -> String {
+        Template::parse(source)
+            .and_then(|t| t.render(Vec::new()))
+            .unwrap_err()
+    }
+
+    #[test]
+    fn errors_where_jinja2_raises() {
+        // an undefined name prints as nothing, looking into one is an error
+        assert_eq!(render("[{{ user }}]", vec![]), "[]");
+        assert_eq!(error("{{ user.name }}"), "'user' is undefined");
+        assert_eq!(error("{{ user['name'] }}"), "'user' is undefined");
+        assert_eq!(error("{{ 'a' | shout }}"), "unknown filter shout");
+        assert_eq!(error("{% if x %}a"), "missing {% endif %}");
+        assert_eq!(
+            error("{% for x in y %}{% if x %}{% endfor %}"),
+            "{% endfor %} before {% endif %}"
+        );
+        assert_eq!(error("{% macro m() %}a"), "missing {% endmacro %}");
+        assert_eq!(error("{{ 'a' "), "unclosed tag, expected }}");
+    }
+
+    #[test]
+    fn limits_recursion_and_nesting() {
+        let source = "{% macro down(n) %}{{ n }}{{ down(n + 1) }}{% endmacro %}{{ down(0) }}";
+        assert_eq!(error(source), "macro down called more than 32 deep");
+        let countdown = "{% macro down(n) %}{{ n }}{% if n %}{{ down(n - 1) }}{% endif %}{% endmacro %}{{ down(5) }}";
+        assert_eq!(render(countdown, vec![]), "543210");
+
+        // sources past the limits are refused, not the stack overflowed
+        let n = 100_000;
+        let parens = format!("{{{{ {}1{} }}}}", "(".repeat(n), ")".repeat(n));
+        assert_eq!(error(&parens), "expression nested more than 64 deep");
+        let chain = format!("{{{{ 1{} }}}}", " + 1".repeat(n));
+        assert_eq!(error(&chain), "more than 256 operations in one tag");
+        let blocks = format!("{}x{}", "{% if 1 %}".repeat(n), "{% endif %}".repeat(n));
+        assert_eq!(error(&blocks), "blocks nested more than 64 deep");
+        let ifs = "{% if 1 %}".repeat(40) + "{{ m(n + 1) }}" + &"{% endif %}".repeat(40);
+        let source = format!("{{% macro m(n) %}}{ifs}{{% endmacro %}}{{{{ m(0) }}}}");
+        assert_eq!(error(&source), "template nested more than 128 deep");
+    }
+}
@@ -0,0 +1,143 @@
+//! Compatibility checks for chat templates
+use super::jinja::{FILTERS, GLOBALS, METHODS, TESTS};
+use super::ChatTemplate;
+use crate::validate::Severity;
+
+/// A problem found in a chat template
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    pub severity: Severity,
+    /// Stable identifier of the check, e.g. `unknown-filter`.
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ChatTemplate {
+    /// Report constructs that break common runtimes
+    pub fn lint(&self) -> Vec<LintFinding> {
+        let usage = self.template.usage();
+        let mut findings = Vec::new();
+        let mut report = |severity, code, message: String| {
+            findings.push(LintFinding {
+                severity,
+                code,
+                message,
+            })
+        };
+        for filter in &usage.filters {
+            if !FILTERS.contains(&filter.as_str()) {
+                report(
+                    Severity::Error,
+                    "unknown-filter",
+                    format!("filter `{filter}` is not supported by common chat template runtimes"),
+                );
+            }
+        }
+        for test in &usage.tests {
+            if !TESTS.contains(&test.as_str()) {
+                report(
+                    Severity::Error,
+                    "unknown-test",
+                    format!("test `{test}` is not supported by common chat template runtimes"),
+                );
+            }
+        }
+        for function in &usage.functions {
+            if usage.defined.contains(function) {
+                continue;
+            }
+            if !GLOBALS.contains(&function.as_str()) {
+                report(
+                    Severity::Error,
+                    "unknown-function",
+                    format!("function `{function}` is not provided to chat templates"),
+                );
+            } else if function == "strftime_now" {
+                report(
+                    Severity::Warning,
+                    "strftime-now",
+                    "`strftime_now` is missing from older runtimes and makes the prompt depend on \
+                     the current date"
+                        .to_string(),
+                );
+            }
+        }
+        for method in &usage.methods {
+            if !METHODS.contains(&method.as_str()) {
+                report(
+                    Severity::Warning,
+                    "unknown-method",
+                    format!("method `{method}` is not portable across chat template runtimes"),
+                );
+            }
+        }
+        for (message, conditional) in &usage.raises {
+            if !conditional {
+                report(
+                    Severity::Error,
+                    "unconditional-raise",
+                    "`raise_exception` is called outside of any condition, so rendering always \
+                     fails"
+                        .to_string(),
+                );
+            }
+            if message.as_deref().is_none_or(str::is_empty) {
+                report(
+                    Severity::Warning,
+                    "raise-without-message",
+                    "`raise_exception` should be given a literal message explaining the failure"
+                        .to_string(),
+                );
+            }
+        }
+        if usage.loop_controls {
+            report(
+                Severity::Warning,
+                "loop-controls",
+                "`break` and `continue` need the loopcontrols extension".to_string(),
+            );
+        }
+        if !usage.names.iter().any(|n| n == "messages") {
+            report(
+                Severity::Warning,
+                "unused-messages",
+                "the template never reads `messages`".to_string(),
+            );
+        }
+        if !usage.names.iter().any(|n| n == "add_generation_prompt") {
+            report(
+                Severity::Warning,
+                "missing-generation-prompt",
+                "the template ignores `add_generation_prompt`, so it cannot open the assistant turn"
+                    .to_string(),
+            );
+        }
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(source: &str) -> Vec<&'static str> {
+        ChatTemplate::new(source)
+            .unwrap()
+            .lint()
+            .into_iter()
+            .map(|f| f.code)
+            .collect()
+    }
+
+    #[test]
+    fn lint_reports_portability_problems() {
+        assert_eq!(
+            codes("{{ strftime_now('%d') }}{% for m in messages %}{{ m.content | wordwrap }}{% endfor %}{{ raise_exception('x') }}"),
+            ["unknown-filter", "strftime-now", "unconditional-raise", "missing-generation-prompt"]
+        );
+        assert!(codes(
+            "{% for m in messages %}{% if m.role == 'tool' %}{{ raise_exception('no tools') }}{% endif %}{{ m.content }}{% endfor %}{% if add_generation_prompt %}>{% endif %}"
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,882 @@
+//! # Converting safetensors checkpoints
+//!
+//! Reads a Hugging Face `model.safetensors` and its `config.json` and writes a GGUF file, mapping
+//! the tensor names and hyperparameters the way llama.cpp's `convert_hf_to_gguf.py` does for the
+//! architectures in [`ARCHITECTURES`], and exports GGUF tensors the other way with
+//! [`to_safetensors`], or as a Hugging Face model folder MLX loads with [`to_mlx`].
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::quant::{f16_to_f32, quantize};
+use crate::tokenizer::TokenizerMetadata;
+use crate::writer::{write_file, WriteError};
+use crate::{
+    Dimensions, GGMLType, GGUFFile, GGUFHeader, GGUFMetadata, GGUFMetadataValue, GGUFTensorInfo,
+};
+
+mod mlx;
+#[cfg(feature = "pytorch")]
+mod pytorch;
+
+pub use mlx::to_mlx;
+
+/// The architectures [`convert`] knows the tensor layout of
+pub const ARCHITECTURES: &[&str] = &["llama", "qwen2"];
+
+/// A tensor in a safetensors file
+#[derive(Debug, Clone, PartialEq)]
+pub struct SafeTensor {
+    pub name: String,
+    /// The element type, e.g. `BF16`.
+    pub dtype: String,
+    /// The shape, outermost dimension first.
+    pub shape: Vec<u64>,
+    /// Range of the data, relative to the end of the header.
+    pub data: (u64, u64),
+}
+
+/// The header of a safetensors file
+#[derive(Debug)]
+pub struct SafeTensors {
+    file: File,
+    data_start: u64,
+    /// The tensors in the order of their data.
+    pub tensors: Vec<SafeTensor>,
+}
+
+fn element_size(dtype: &str) -> Option<u64> {
+    match dtype {
+        "F32" => Some(4),
+        "F16" | "BF16" => Some(2),
+        _ => None,
+    }
+}
+
+impl SafeTensors {
+    /// Read the header of a safetensors file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let mut file = File::open(path).map_err(|e| e.to_string())?;
+        let file_len = file.metadata().map_err(|e| e.to_string())?.len();
+        let mut len = [0; 8];
+        file.read_exact(&mut len).map_err(|e| e.to_string())?;
+        let len = u64::from_le_bytes(len);
+        if len > file_len - 8 {
+            return Err(format!(
+                "the header length {len} runs past the end of the file"
+            ));
+        }
+        let mut header = vec![0; len as usize];
+        file.read_exact(&mut header).map_err(|e| e.to_string())?;
+        let header: Value = serde_json::from_slice(&header).map_err(|e| e.to_string())?;
+        let data_start = 8 + len;
+        let mut tensors = Vec::new();
+        for (name, info) in header
+            .as_object()
+            .ok_or("the header is not a JSON object")?
+        {
+            if name == "__metadata__" {
+                continue;
+            }
+            let invalid = || format!("tensor {name} has an invalid entry");
+            let dtype = info["dtype"].as_str().ok_or_else(invalid)?.to_string();
+            let shape = info["shape"]
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|d| d.as_u64().ok_or_else(invalid))
+                .collect::<Result<Vec<_>, _>>()?;
+            let (start, end) = match info["data_offsets"].as_array().map(Vec::as_slice) {
+                Some([start, end]) => (
+                    start.as_u64().ok_or_else(invalid)?,
+                    end.as_u64().ok_or_else(invalid)?,
+                ),
+                _ => return Err(invalid()),
+            };
+            let elements: u64 = shape.iter().product();
+            if element_size(&dtype)
+                .is_some_and(|size| end.checked_sub(start) != Some(elements * size))
+                || data_start + end > file_len
+            {
+                return Err(format!(
+                    "the data of tensor {name} does not match its shape or lies past the end of the file"
+                ));
+            }
+            tensors.push(SafeTensor {
+                name: name.clone(),
+                dtype,
+                shape,
+                data: (start, end),
+            });
+        }
+        tensors.sort_by_key(|t| t.data.0);
+        Ok(SafeTensors {
+            file,
+            data_start,
+            tensors,
+        })
+    }
+
+    /// Read a safetensors file, or with the `pytorch` feature a `.bin`, `.pth` or `.pt`
+    /// PyTorch checkpoint
+    pub fn open_any(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let pytorch = path
+            .extension()
+            .is_some_and(|e| e == "bin" || e == "pth" || e == "pt");
+        #[cfg(feature = "pytorch")]
+        if pytorch {
+            return SafeTensors::open_pytorch(path);
+        }
+        #[cfg(not(feature = "pytorch"))]
+        if pytorch {
+            return Err("reading PyTorch checkpoints needs the pytorch feature".to_string());
+        }
+        SafeTensors::open(path)
+    }
+
+    /// Read every shard of a checkpoint: those listed in the `weight_map` of a
+    /// `model.safetensors.index.json` or `pytorch_model.bin.index.json`, or the one file at
+    /// `path`, as [`open_any`](SafeTensors::open_any) does
+    pub fn open_shards(path: impl AsRef<Path>) -> Result<Vec<Self>, String> {
+        let path = path.as_ref();
+        if !path.to_string_lossy().ends_with(".index.json") {
+            return Ok(vec![SafeTensors::open_any(path)?]);
+        }
+        let index: Value = serde_json::from_str(
+            &std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?,
+        )
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+        let weight_map = index["weight_map"]
+            .as_object()
+            .ok_or("the index has no weight_map")?;
+        let mut shards: Vec<&str> = Vec::new();
+        for shard in weight_map.values() {
+            let shard = shard
+                .as_str()
+                .ok_or("the weight_map names a shard by a non-string")?;
+            if !shards.contains(&shard) {
+                shards.push(shard);
+            }
+        }
+        shards.sort_unstable();
+        let dir = path.parent().unwrap_or(Path::new(""));
+        shards
+            .into_iter()
+            .map(|shard| {
+                SafeTensors::open_any(dir.join(shard)).map_err(|e| format!("{shard}: {e}"))
+            })
+            .collect()
+    }
+
+    /// Read the data of a tensor as `f32` values
+    pub fn read_f32(&mut self, tensor: &SafeTensor) -> Result<Vec<f32>, String> {
+        let (start, end) = tensor.data;
+        self.file
+            .seek(SeekFrom::Start(self.data_start + start))
+            .map_err(|e| e.to_string())?;
+        let mut data = vec![0; (end - start) as usize];
+        self.file.read_exact(&mut data).map_err(|e| e.to_string())?;
+        Ok(match tensor.dtype.as_str() {
+            "F32" => data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            "F16" => data
+                .chunks_exact(2)
+                .map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]])))
+                .collect(),
+            "BF16" => data
+                .chunks_exact(2)
+                .map(|b| f32::from_bits(u32::from(u16::from_le_bytes([b[0], b[1]])) << 16))
+                .collect(),
+            dtype => {
+                return Err(format!(
+                    "tensor {} has unsupported dtype {dtype}",
+                    tensor.name
+                ))
+            }
+        })
+    }
+}
+
+/// What to convert and how
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+    /// One of [`ARCHITECTURES`].
+    pub arch: String,
+    /// The parsed `config.json`.
+    pub config: Value,
+    /// The tokenizer keys, e.g. from [`TokenizerMetadata::from_hf_json`].
+    pub tokenizer: Option<TokenizerMetadata>,
+    /// F32, F16 or Q8_0, used for the matrices; vectors stay F32.
+    pub out_type: GGMLType,
+}
+
+/// How the tensor names of a Hugging Face checkpoint map to GGUF ones
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorMapping {
+    /// The architecture written to `general.architecture`.
+    pub arch: String,
+    /// Tensors outside the layers by name without suffix, e.g. `model.norm` to `output_norm`.
+    pub globals: Vec<(String, String)>,
+    /// Tensors of `model.layers.{n}.`, e.g. `self_attn.q_proj` to `attn_q`, named `blk.{n}.attn_q`.
+    pub layers: Vec<(String, String)>,
+    /// Name suffixes of tensors left out, e.g. `.rotary_emb.inv_freq`.
+    pub skip: Vec<String>,
+    /// The query and key head counts of checkpoints whose query and key rows are permuted
+    /// for RoPE, as Hugging Face llama checkpoints are.
+    pub rope_heads: Option<(usize, usize)>,
+}
+
+impl TensorMapping {
+    /// The mapping of one of [`ARCHITECTURES`], without RoPE permutation
+    pub fn for_arch(arch: &str) -> Result<Self, String> {
+        if !ARCHITECTURES.contains(&arch) {
+            return Err(format!(
+                "cannot convert {arch} models, known architectures are {}",
+                ARCHITECTURES.join(", ")
+            ));
+        }
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|&(from, to)| (from.to_string(), to.to_string()))
+                .collect()
+        };
+        Ok(TensorMapping {
+            arch: arch.to_string(),
+            globals: pairs(&[
+                ("model.embed_tokens", "token_embd"),
+                ("model.norm", "output_norm"),
+                ("lm_head", "output"),
+            ]),
+            layers: pairs(&[
+                ("input_layernorm", "attn_norm"),
+                ("self_attn.q_proj", "attn_q"),
+                ("self_attn.k_proj", "attn_k"),
+                ("self_attn.v_proj", "attn_v"),
+                ("self_attn.o_proj", "attn_output"),
+                ("post_attention_layernorm", "ffn_norm"),
+                ("mlp.gate_proj", "ffn_gate"),
+                ("mlp.up_proj", "ffn_up"),
+                ("mlp.down_proj", "ffn_down"),
+            ]),
+            skip: vec![".rotary_emb.inv_freq".to_string()],
+            rope_heads: None,
+        })
+    }
+
+    /// The GGUF name of a Hugging Face tensor, `None` for tensors that are left out
+    pub fn map(&self, name: &str) -> Result<Option<String>, String> {
+        if self
+            .skip
+            .iter()
+            .any(|suffix| name.ends_with(suffix.as_str()))
+        {
+            return Ok(None);
+        }
+        let unknown = || format!("cannot map tensor {name}");
+        let (stem, suffix) = name.rsplit_once('.').ok_or_else(unknown)?;
+        let find = |pairs: &[(String, String)], from: &str| {
+            pairs
+                .iter()
+                .find(|(f, _)| f == from)
+                .map(|(_, to)| to.clone())
+        };
+        let mapped = match find(&self.globals, stem) {
+            Some(mapped) => mapped,
+            None => {
+                let (layer, part) = stem
+                    .strip_prefix("model.layers.")
+                    .and_then(|rest| rest.split_once('.'))
+                    .ok_or_else(unknown)?;
+                let part = find(&self.layers, part).ok_or_else(unknown)?;
+                let layer: u32 = layer.parse().map_err(|_| unknown())?;
+                format!("blk.{layer}.{part}")
+            }
+        };
+        Ok(Some(format!("{mapped}.{suffix}")))
+    }
+
+    /// The Hugging Face name of a GGUF tensor, the inverse of [`TensorMapping::map`]
+    pub fn unmap(&self, name: &str) -> Option<String> {
+        let (stem, suffix) = name.rsplit_once('.')?;
+        let find = |pairs: &[(String, String)], to: &str| {
+            pairs
+                .iter()
+                .find(|(_, t)| t == to)
+                .map(|(from, _)| from.clone())
+        };
+        let original = match find(&self.globals, stem) {
+            Some(original) => original,
+            None => {
+                let (layer, part) = stem.strip_prefix("blk.")?.split_once('.')?;
+                let layer: u32 = layer.parse().ok()?;
+                format!("model.layers.{layer}.{}", find(&self.layers, part)?)
+            }
+        };
+        Some(format!("{original}.{suffix}"))
+    }
+}
+
+/// Reorder the rows of the query and key projections from the rotate-half layout of Hugging
+/// Face llama checkpoints to the interleaved pairs llama.cpp applies RoPE to
+fn permute(values: &[f32], rows: usize, heads: usize) -> Result<Vec<f32>, String> {
+    if heads == 0 || !rows.is_multiple_of(heads * 2) {
+        return Err(format!(
+            "{rows} rows do not split into {heads} heads of pairs"
+        ));
+    }
+    let cols = values.len() / rows;
+    let half = rows / heads / 2;
+    let mut out = Vec::with_capacity(values.len());
+    for head in 0..heads {
+        for i in 0..half {
+            for j in 0..2 {
+                let row = head * half * 2 + j * half + i;
+                out.extend_from_slice(&values[row * cols..(row + 1) * cols]);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn hyperparameters(options: &ConvertOptions) -> Result<Vec<GGUFMetadata>, String> {
+    let config = &options.config;
+    let arch = &options.arch;
+    let int = |key: &str| config[key].as_u64().and_then(|v| u32::try_from(v).ok());
+    let required = |key: &str| int(key).ok_or_else(|| format!("config.json has no {key}"));
+    let heads = required("num_attention_heads")?;
+    let hidden = required("hidden_size")?;
+    let file_type = match options.out_type {
+        GGMLType::F32 => 0,
+        GGMLType::F16 => 1,
+        GGMLType::Q8_0 => 7,
+        other => return Err(format!("cannot convert to {other:?}, use F32, F16 or Q8_0")),
+    };
+    let mut metadata = vec![
+        GGUFMetadata::new(
+            "general.architecture",
+            GGUFMetadataValue::String(arch.clone()),
+        ),
+        GGUFMetadata::new("general.file_type", GGUFMetadataValue::Uint32(file_type)),
+        GGUFMetadata::new("general.quantization_version", GGUFMetadataValue::Uint32(2)),
+    ];
+    let mut push =
+        |key: &str, value| metadata.push(GGUFMetadata::new(format!("{arch}.{key}"), value));
+    push(
+        "vocab_size",
+        GGUFMetadataValue::Uint32(required("vocab_size")?),
+    );
+    push(
+        "context_length",
+        GGUFMetadataValue::Uint32(required("max_position_embeddings")?),
+    );
+    push("embedding_length", GGUFMetadataValue::Uint32(hidden));
+    push(
+        "block_count",
+        GGUFMetadataValue::Uint32(required("num_hidden_layers")?),
+    );
+    push(
+        "feed_forward_length",
+        GGUFMetadataValue::Uint32(required("intermediate_size")?),
+    );
+    push("attention.head_count", GGUFMetadataValue::Uint32(heads));
+    push(
+        "attention.head_count_kv",
+        GGUFMetadataValue::Uint32(int("num_key_value_heads").unwrap_or(heads)),
+    );
+    push(
+        "rope.dimension_count",
+        GGUFMetadataValue::Uint32(int("head_dim").unwrap_or(hidden / heads.max(1))),
+    );
+    if let Some(theta) = config["rope_theta"].as_f64() {
+        push("rope.freq_base", GGUFMetadataValue::Float32(theta as f32));
+    }
+    let epsilon = config["rms_norm_eps"]
+        .as_f64()
+        .ok_or("config.json has no rms_norm_eps")?;
+    push(
+        "attention.layer_norm_rms_epsilon",
+        GGUFMetadataValue::Float32(epsilon as f32),
+    );
+    if let Some(tokenizer) = &options.tokenizer {
+        metadata.extend(tokenizer.to_metadata());
+    }
+    Ok(metadata)
+}
+
+/// Write the tensors of `shards` that `mapping` names to `dst`, each as the type
+/// `tensor_type` picks from the source tensor and its GGUF dimensions
+fn write_tensors(
+    shards: &mut [SafeTensors],
+    mapping: &TensorMapping,
+    metadata: Vec<GGUFMetadata>,
+    tensor_type: impl Fn(&SafeTensor, &[u64]) -> GGMLType,
+    dst: impl AsRef<Path>,
+) -> Result<GGUFFile, WriteError> {
+    let mut sources = Vec::new();
+    let mut tensors: Vec<GGUFTensorInfo> = Vec::new();
+    for (shard, source) in shards.iter().enumerate() {
+        for tensor in &source.tensors {
+            let Some(name) = mapping.map(&tensor.name)? else {
+                continue;
+            };
+            element_size(&tensor.dtype).ok_or_else(|| {
+                format!(
+                    "tensor {} has unsupported dtype {}",
+                    tensor.name, tensor.dtype
+                )
+            })?;
+            if tensors.iter().any(|t| t.name == name) {
+                return Err(format!("two tensors map to {name}").into());
+            }
+            let dimensions: Dimensions = tensor.shape.iter().rev().copied().collect();
+            sources.push((shard, tensor.clone()));
+            tensors.push(GGUFTensorInfo {
+                name,
+                tensor_type: tensor_type(tensor, &dimensions),
+                dimensions,
+                offset: 0,
+            });
+        }
+    }
+    let file = GGUFFile {
+        header: GGUFHeader {
+            version: 3,
+            tensor_count: tensors.len() as u64,
+            metadata,
+        },
+        tensors,
+    };
+
+    let mut next = sources.iter();
+    write_file(&dst, &file, |tensor, out| {
+        let (shard, from) = next.next().expect("one source per tensor");
+        let mut values = shards[*shard].read_f32(from)?;
+        if let Some((heads, kv_heads)) = mapping.rope_heads {
+            let rows = from.shape.first().copied().unwrap_or(1) as usize;
+            if from.name.contains("self_attn.q_proj.") {
+                values = permute(&values, rows, heads)?;
+            } else if from.name.contains("self_attn.k_proj.") {
+                values = permute(&values, rows, kv_heads)?;
+            }
+        }
+        let data = quantize(tensor.tensor_type, &values)?;
+        Ok(out.write_all(&data)?)
+    })?;
+    Ok(file)
+}
+
+/// Convert the tensors of a checkpoint, one safetensors file or the shards of a
+/// `model.safetensors.index.json`, to a GGUF file with only `general.architecture` and
+/// `general.file_type` for metadata
+///
+/// Vectors and F32 matrices are written as F32, F16 and BF16 matrices as F16. Use [`convert`]
+/// for a file with the hyperparameters and tokenizer llama.cpp needs to run it.
+pub fn from_safetensors(
+    st_path: impl AsRef<Path>,
+    mapping: &TensorMapping,
+    out: impl AsRef<Path>,
+) -> Result<GGUFFile, WriteError> {
+    let mut shards = SafeTensors::open_shards(st_path)?;
+    let half = shards
+        .iter()
+        .flat_map(|shard| &shard.tensors)
+        .any(|t| t.shape.len() >= 2 && t.dtype != "F32");
+    let metadata = vec![
+        GGUFMetadata::new(
+            "general.architecture",
+            GGUFMetadataValue::String(mapping.arch.clone()),
+        ),
+        GGUFMetadata::new(
+            "general.file_type",
+            GGUFMetadataValue::Uint32(u32::from(half)),
+        ),
+    ];
+    write_tensors(
+        &mut shards,
+        mapping,
+        metadata,
+        |source, dimensions| match source.dtype.as_str() {
+            _ if dimensions.len() < 2 => GGMLType::F32,
+            "F32" => GGMLType::F32,
+            _ => GGMLType::F16,
+        },
+        out,
+    )
+}
+
+/// Convert a safetensors checkpoint, one file or the shards of a
+/// `model.safetensors.index.json`, to a GGUF file, returning the header and tensor infos written
+pub fn convert(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    options: &ConvertOptions,
+) -> Result<GGUFFile, WriteError> {
+    let mut mapping = TensorMapping::for_arch(&options.arch)?;
+    let metadata = hyperparameters(options)?;
+    if options.arch == "llama" {
+        let int = |key: &str| options.config[key].as_u64().unwrap_or_default() as usize;
+        let heads = int("num_attention_heads");
+        let kv_heads = options.config["num_key_value_heads"]
+            .as_u64()
+            .map_or(heads, |v| v as usize);
+        mapping.rope_heads = Some((heads, kv_heads));
+    }
+    let mut shards = SafeTensors::open_shards(src)?;
+    write_tensors(
+        &mut shards,
+        &mapping,
+        metadata,
+        |_, dimensions| match options.out_type {
+            _ if dimensions.len() < 2 => GGMLType::F32,
+            GGMLType::Q8_0 if !dimensions[0].is_multiple_of(32) => GGMLType::F16,
+            out_type => out_type,
+        },
+        dst,
+    )
+}
+
+/// The safetensors dtype of data GGUF stores as `tensor_type`, for types laid out the same
+fn safetensors_dtype(tensor_type: GGMLType) -> Option<&'static str> {
+    match tensor_type {
+        GGMLType::F32 => Some("F32"),
+        GGMLType::F16 => Some("F16"),
+        GGMLType::BF16 => Some("BF16"),
+        GGMLType::F64 => Some("F64"),
+        GGMLType::I8 => Some("I8"),
+        GGMLType::I16 => Some("I16"),
+        GGMLType::I32 => Some("I32"),
+        GGMLType::I64 => Some("I64"),
+        _ => None,
+    }
+}
+
+/// Export the tensors of the GGUF file `src` to the safetensors file `dst`, and its metadata to
+/// a JSON sidecar, `dst` with the extension `.json`
+///
+/// F32, F16 and integer tensors are copied as they are; quantized ones are dequantized to
+/// `float`, F32 or F16. Returns the tensors written.
+pub fn to_safetensors(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    float: GGMLType,
+) -> Result<Vec<SafeTensor>, WriteError> {
+    let mut input = File::open(src)?;
+    let (file, data_start) = GGUFFile::read_from(&mut input, &crate::ParseOptions::default())?;
+    let tensors = file.tensors.iter().map(|t| (t, t.name.clone())).collect();
+    let dst = dst.as_ref();
+    let written = write_safetensors(&mut input, data_start, tensors, dst, float, |_, data| {
+        Ok(data)
+    })?;
+    let metadata = serde_json::to_vec_pretty(&file.header).map_err(|e| e.to_string())?;
+    std::fs::write(dst.with_extension("json"), metadata)?;
+    Ok(written)
+}
+
+/// Write `tensors` of the GGUF data at `data_start` in `input` under the paired names to the
+/// safetensors file `dst`, passing the data of each through `transform` once dequantized
+fn write_safetensors(
+    input: &mut File,
+    data_start: u64,
+    tensors: Vec<(&GGUFTensorInfo, String)>,
+    dst: &Path,
+    float: GGMLType,
+    transform: impl Fn(&GGUFTensorInfo, Vec<u8>) -> Result<Vec<u8>, String>,
+) -> Result<Vec<SafeTensor>, WriteError> {
+    let float_dtype = match float {
+        GGMLType::F32 | GGMLType::F16 => safetensors_dtype(float).unwrap_or_default(),
+        other => return Err(format!("cannot dequantize to {other:?}, use F32 or F16").into()),
+    };
+    let mut written = Vec::new();
+    let mut end = 0;
+    for (tensor, name) in &tensors {
+        let dtype = safetensors_dtype(tensor.tensor_type).unwrap_or(float_dtype);
+        let size = match dtype {
+            _ if safetensors_dtype(tensor.tensor_type).is_some() => tensor.size_bytes(),
+            dtype => element_size(dtype).map(|size| size * tensor.element_count()),
+        }
+        .ok_or_else(|| format!("tensor {} has no valid size", tensor.name))?;
+        written.push(SafeTensor {
+            name: name.clone(),
+            dtype: dtype.to_string(),
+            shape: tensor.dimensions.iter().rev().copied().collect(),
+            data: (end, end + size),
+        });
+        end += size;
+    }
+    let mut header = serde_json::Map::new();
+    header.insert(
+        "__metadata__".to_string(),
+        serde_json::json!({"format": "pt"}),
+    );
+    for tensor in &written {
+        header.insert(
+            tensor.name.clone(),
+            serde_json::json!({
+                "dtype": tensor.dtype,
+                "shape": tensor.shape,
+                "data_offsets": [tensor.data.0, tensor.data.1],
+            }),
+        );
+    }
+    let mut header = serde_json::to_vec(&header).map_err(|e| e.to_string())?;
+    // the data starts 8-byte aligned, the header padded with spaces as the format allows
+    header.resize(header.len().next_multiple_of(8), b' ');
+
+    let mut out = BufWriter::new(File::create(dst)?);
+    out.write_all(&(header.len() as u64).to_le_bytes())
+        .and_then(|()| out.write_all(&header))?;
+    for ((tensor, _), safetensor) in tensors.iter().zip(&written) {
+        let size = tensor.size_bytes().unwrap_or_default();
+        input.seek(SeekFrom::Start(data_start + tensor.offset))?;
+        let mut data = Vec::with_capacity(size as usize);
+        input.take(size).read_to_end(&mut data)?;
+        if (data.len() as u64) < size {
+            return Err(format!("the file ends inside the data of tensor {}", tensor.name).into());
+        }
+        if safetensors_dtype(tensor.tensor_type).is_none() {
+            data = quantize(float, &crate::quant::dequantize(tensor.tensor_type, &data)?)?;
+        }
+        let data = transform(tensor, data)?;
+        debug_assert_eq!(data.len() as u64, safetensor.data.1 - safetensor.data.0);
+        out.write_all(&data)?;
+    }
+    out.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path, tensor};
+    use serde_json::json;
+
+    /// A safetensors file of tensors holding 0, 1/64, 2/64, ...
+    fn safetensors(tensors: &[(&str, &str, Vec<u64>)]) -> Vec<u8> {
+        let mut header = serde_json::Map::new();
+        let mut data = Vec::new();
+        for (name, dtype, shape) in tensors {
+            let elements = shape.iter().product::<u64>();
+            let start = data.len();
+            for i in 0..elements {
+                let value = (i as f32) / 64.0;
+                match *dtype {
+                    "BF16" => data.extend(((value.to_bits() >> 16) as u16).to_le_bytes()),
+                    _ => data.extend(value.to_le_bytes()),
+                }
+            }
+            header.insert(
+                name.to_string(),
+                json!({"dtype": dtype, "shape": shape, "data_offsets": [start, data.len()]}),
+            );
+        }
+        let header = serde_json::to_vec(&header).unwrap();
+        let mut buf = (header.len() as u64).to_le_bytes().to_vec();
+        buf.extend(header);
+        buf.extend(data);
+        buf
+    }
+
+    #[test]
+    fn convert_llama_checkpoint() {
+        let buf = safetensors(&[
+            ("model.embed_tokens.weight", "F32", vec![4u64, 32]),
+            ("model.layers.0.self_attn.q_proj.weight", "F32", vec![4, 32]),
+            ("model.layers.0.input_layernorm.weight", "F32", vec![32]),
+            (
+                "model.layers.0.self_attn.rotary_emb.inv_freq",
+                "F32",
+                vec![2],
+            ),
+        ]);
+
+        let dir = temp_path("gguf-convert");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (src, dst) = (dir.join("model.safetensors"), dir.join("model.gguf"));
+        std::fs::write(&src, buf).unwrap();
+        let mut vocab = crate::tokenizer::Vocab::default();
+        vocab.model = crate::tokenizer::TokenizerModel::Llama;
+        *vocab.tokens_mut() = ["<unk>", "a", "b", "c"].map(String::from).to_vec();
+        let options = ConvertOptions {
+            arch: "llama".to_string(),
+            config: json!({
+                "vocab_size": 4,
+                "max_position_embeddings": 64,
+                "hidden_size": 32,
+                "num_hidden_layers": 1,
+                "intermediate_size": 64,
+                "num_attention_heads": 1,
+                "rms_norm_eps": 1e-6,
+            }),
+            tokenizer: Some(TokenizerMetadata {
+                vocab,
+                add_bos_token: None,
+                add_eos_token: None,
+            }),
+            out_type: GGMLType::Q8_0,
+        };
+        convert(&src, &dst, &options).unwrap();
+
+        let out = std::fs::read(&dst).unwrap();
+        let (file, data_start) =
+            GGUFFile::read_from(&mut out.as_slice(), &crate::ParseOptions::default()).unwrap();
+        let tensors: Vec<_> = file
+            .tensors
+            .iter()
+            .map(|t| (t.name.as_str(), t.dimensions.to_vec(), t.tensor_type))
+            .collect();
+        assert_eq!(
+            tensors,
+            [
+                ("token_embd.weight", vec![32, 4], GGMLType::Q8_0),
+                ("blk.0.attn_q.weight", vec![32, 4], GGMLType::Q8_0),
+                ("blk.0.attn_norm.weight", vec![32], GGMLType::F32),
+            ]
+        );
+        assert_eq!(
+            file.header.get("llama.attention.head_count_kv"),
+            Some(&GGUFMetadataValue::Uint32(1))
+        );
+        assert!(crate::validate(&out).is_valid());
+
+        // one head of four rows: the halves [0, 1] and [2, 3] interleave to rows 0, 2, 1, 3
+        let start = (data_start + file.tensors[1].offset) as usize;
+        let q = crate::quant::dequantize(GGMLType::Q8_0, &out[start..start + 34 * 4]).unwrap();
+        let first: Vec<f32> = q.chunks(32).map(|row| row[0] * 64.0).collect();
+        assert_eq!(
+            first.iter().map(|v| v.round()).collect::<Vec<_>>(),
+            [0.0, 64.0, 32.0, 96.0]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn convert_sharded_checkpoint() {
+        let dir = temp_path("gguf-shards");
+        std::fs::create_dir_all(&dir).unwrap();
+        let shards = [
+            (
+                "model-00001-of-00002.safetensors",
+                safetensors(&[
+                    ("model.embed_tokens.weight", "BF16", vec![4, 32]),
+                    ("model.layers.0.input_layernorm.weight", "BF16", vec![32]),
+                ]),
+            ),
+            (
+                "model-00002-of-00002.safetensors",
+                safetensors(&[
+                    ("lm_head.weight", "F32", vec![4, 32]),
+                    (
+                        "model.layers.0.self_attn.rotary_emb.inv_freq",
+                        "F32",
+                        vec![2],
+                    ),
+                ]),
+            ),
+        ];
+        let mut weight_map = serde_json::Map::new();
+        for (shard, data) in &shards {
+            std::fs::write(dir.join(shard), data).unwrap();
+            for tensor in SafeTensors::open(dir.join(shard)).unwrap().tensors {
+                weight_map.insert(tensor.name, json!(shard));
+            }
+        }
+        let index = dir.join("model.safetensors.index.json");
+        std::fs::write(&index, json!({"weight_map": weight_map}).to_string()).unwrap();
+        let dst = dir.join("model.gguf");
+        let mapping = TensorMapping::for_arch("qwen2").unwrap();
+        from_safetensors(&index, &mapping, &dst).unwrap();
+
+        let out = std::fs::read(&dst).unwrap();
+        let (file, data_start) =
+            GGUFFile::read_from(&mut out.as_slice(), &crate::ParseOptions::default()).unwrap();
+        let tensors: Vec<_> = file
+            .tensors
+            .iter()
+            .map(|t| (t.name.as_str(), t.tensor_type))
+            .collect();
+        assert_eq!(
+            tensors,
+            [
+                ("token_embd.weight", GGMLType::F16),
+                ("blk.0.attn_norm.weight", GGMLType::F32),
+                ("output.weight", GGMLType::F32),
+            ]
+        );
+        assert_eq!(
+            file.header.get("general.file_type"),
+            Some(&GGUFMetadataValue::Uint32(1))
+        );
+        // the layout is sound; only the hyperparameters `convert` adds are missing
+        let report = crate::validate(&out);
+        assert!(report.findings.iter().all(|f| f.code == "missing-key"));
+        let start = (data_start + file.tensors[0].offset) as usize;
+        let embd = crate::quant::dequantize(GGMLType::F16, &out[start..start + 8]).unwrap();
+        assert_eq!(embd, [0.0, 1.0 / 64.0, 2.0 / 64.0, 3.0 / 64.0]);
+
+        assert_eq!(
+            mapping.map("model.layers.0.mlp.experts.0.weight"),
+            Err("cannot map tensor model.layers.0.mlp.experts.0.weight".to_string())
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn export_safetensors() {
+        let dir = temp_path("gguf-export");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (src, dst) = (dir.join("model.gguf"), dir.join("model.safetensors"));
+        let file = file(
+            header(&[(
+                "general.architecture",
+                GGUFMetadataValue::String("llama".to_string()),
+            )]),
+            vec![
+                tensor("token_embd.weight", &[32, 2], GGMLType::Q8_0, 0),
+                tensor("output_norm.weight", &[32], GGMLType::F32, 0),
+                tensor("positions", &[3], GGMLType::I32, 0),
+            ],
+        );
+        let values: Vec<f32> = (0..64).map(|i| i as f32 / 8.0).collect();
+        write_file(&src, &file, |tensor, out| {
+            let values = &values[..tensor.element_count() as usize];
+            let data = match tensor.tensor_type {
+                GGMLType::I32 => values
+                    .iter()
+                    .flat_map(|&v| (v as i32).to_le_bytes())
+                    .collect(),
+                tensor_type => quantize(tensor_type, values)?,
+            };
+            Ok(out.write_all(&data)?)
+        })
+        .unwrap();
+
+        to_safetensors(&src, &dst, GGMLType::F16).unwrap();
+        let mut exported = SafeTensors::open(&dst).unwrap();
+        let tensors: Vec<_> = exported
+            .tensors
+            .iter()
+            .map(|t| (t.name.as_str(), t.dtype.as_str(), t.shape.clone()))
+            .collect();
+        assert_eq!(
+            tensors,
+            [
+                ("token_embd.weight", "F16", vec![2, 32]),
+                ("output_norm.weight", "F32", vec![32]),
+                ("positions", "I32", vec![3]),
+            ]
+        );
+        let embd = exported.tensors[0].clone();
+        let embd = exported.read_f32(&embd).unwrap();
+        assert!(embd.iter().zip(&values).all(|(a, b)| (a - b).abs() < 0.04));
+        let metadata: Value =
+            serde_json::from_slice(&std::fs::read(dir.join("model.json")).unwrap()).unwrap();
+        assert!(metadata.to_string().contains("general.architecture"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
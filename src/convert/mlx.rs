@@ -0,0 +1,289 @@
+//! Exporting GGUF models as Hugging Face model folders, the layout `mlx_lm` loads
+//!
+//! The folder holds `model.safetensors` under the transformers tensor names, a `config.json`
+//! rebuilt from the hyperparameters and, when the file has a vocabulary, `tokenizer.json` and
+//! `tokenizer_config.json`. Llama query and key rows go back to the rotate-half layout
+//! [`super::convert`] permutes them out of.
+use std::fs::File;
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+
+use super::{write_safetensors, SafeTensor, TensorMapping};
+use crate::tokenizer::Vocab;
+use crate::writer::WriteError;
+use crate::{GGMLType, GGUFFile, GGUFHeader, GGUFMetadataValue};
+
+/// Reorder the rows of a query or key projection from llama.cpp's interleaved pairs back to
+/// the rotate-half layout, `data` holding `rows` rows of equal size
+fn unpermute(data: &[u8], rows: usize, heads: usize) -> Result<Vec<u8>, String> {
+    if heads == 0 || !rows.is_multiple_of(heads * 2) {
+        return Err(format!(
+            "{rows} rows do not split into {heads} heads of pairs"
+        ));
+    }
+    let row_len = data.len() / rows;
+    let half = rows / heads / 2;
+    let mut out = vec![0; data.len()];
+    for head in 0..heads {
+        for i in 0..half {
+            for j in 0..2 {
+                let from = head * half * 2 + i * 2 + j;
+                let to = head * half * 2 + j * half + i;
+                out[to * row_len..(to + 1) * row_len]
+                    .copy_from_slice(&data[from * row_len..(from + 1) * row_len]);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// A number rounded to the shortest decimal that reads back as the same f32, so `1e-5`
+/// stays `1e-5` rather than `9.999999747378752e-6`
+fn number(value: &GGUFMetadataValue) -> Option<Value> {
+    match value {
+        GGUFMetadataValue::Float32(v) => format!("{v}").parse::<f64>().ok().map(Value::from),
+        GGUFMetadataValue::Float64(v) => Some(Value::from(*v)),
+        other => other.as_u64().map(Value::from),
+    }
+}
+
+/// The transformers `config.json` of a GGUF model
+fn config(file: &GGUFFile, float: GGMLType) -> Result<Value, String> {
+    let header = &file.header;
+    let arch = header
+        .get("general.architecture")
+        .and_then(GGUFMetadataValue::as_str)
+        .ok_or("the file has no general.architecture")?;
+    let class = match arch {
+        "llama" => "LlamaForCausalLM",
+        "qwen2" => "Qwen2ForCausalLM",
+        other => return Err(format!("cannot export {other} models for MLX")),
+    };
+    let get = |key: &str| header.get(&format!("{arch}.{key}")).and_then(number);
+    let required = |key: &str| get(key).ok_or_else(|| format!("the file has no {arch}.{key}"));
+    let vocab_size = get("vocab_size").or_else(|| {
+        let embeddings = file
+            .tensors
+            .iter()
+            .find(|t| t.name == "token_embd.weight")?;
+        embeddings.dimensions.get(1).map(|&n| Value::from(n))
+    });
+    let heads = required("attention.head_count")?;
+    let hidden = required("embedding_length")?;
+    let mut config = Map::new();
+    let mut set = |key: &str, value: Option<Value>| {
+        if let Some(value) = value {
+            config.insert(key.to_string(), value);
+        }
+    };
+    set("architectures", Some(json!([class])));
+    set("model_type", Some(json!(arch)));
+    set("vocab_size", vocab_size);
+    set("hidden_size", Some(hidden.clone()));
+    set("intermediate_size", Some(required("feed_forward_length")?));
+    set("num_hidden_layers", Some(required("block_count")?));
+    set("num_attention_heads", Some(heads.clone()));
+    set(
+        "num_key_value_heads",
+        get("attention.head_count_kv").or(Some(heads.clone())),
+    );
+    let head_dim = match (hidden.as_u64(), heads.as_u64()) {
+        (Some(hidden), Some(heads)) if heads > 0 => Some(hidden / heads),
+        _ => None,
+    };
+    let rope_dim = get("rope.dimension_count");
+    if rope_dim.as_ref().and_then(Value::as_u64) != head_dim {
+        set("head_dim", rope_dim);
+    }
+    set("max_position_embeddings", get("context_length"));
+    set(
+        "rms_norm_eps",
+        Some(required("attention.layer_norm_rms_epsilon")?),
+    );
+    set("rope_theta", get("rope.freq_base"));
+    let tied = !file.tensors.iter().any(|t| t.name == "output.weight");
+    set("tie_word_embeddings", Some(json!(tied)));
+    let dtype = match float {
+        GGMLType::F32 => "float32",
+        _ => "float16",
+    };
+    set("torch_dtype", Some(json!(dtype)));
+    for (key, id) in [
+        ("bos_token_id", "tokenizer.ggml.bos_token_id"),
+        ("eos_token_id", "tokenizer.ggml.eos_token_id"),
+    ] {
+        set(key, header.get(id).and_then(number));
+    }
+    Ok(Value::Object(config))
+}
+
+/// The `tokenizer_config.json` naming the special tokens and the chat template
+fn tokenizer_config(header: &GGUFHeader, vocab: &Vocab) -> Value {
+    let mut config = Map::new();
+    let token = |id: Option<u32>| id.and_then(|id| vocab.token(id)).map(|t| json!(t));
+    for (key, token) in [
+        ("bos_token", token(vocab.special.bos)),
+        ("eos_token", token(vocab.special.eos)),
+        ("unk_token", token(vocab.special.unk)),
+    ] {
+        if let Some(token) = token {
+            config.insert(key.to_string(), token);
+        }
+    }
+    for (key, flag) in [
+        ("add_bos_token", "tokenizer.ggml.add_bos_token"),
+        ("add_eos_token", "tokenizer.ggml.add_eos_token"),
+    ] {
+        if let Some(GGUFMetadataValue::Bool(flag)) = header.get(flag) {
+            config.insert(key.to_string(), json!(flag));
+        }
+    }
+    if let Some(template) = header
+        .get("tokenizer.chat_template")
+        .and_then(GGUFMetadataValue::as_str)
+    {
+        config.insert("chat_template".to_string(), json!(template));
+    }
+    config.insert(
+        "tokenizer_class".to_string(),
+        json!("PreTrainedTokenizerFast"),
+    );
+    Value::Object(config)
+}
+
+/// Export the GGUF file `src` to the folder `dst` as a Hugging Face checkpoint MLX loads
+///
+/// Tensors are renamed to their transformers names and dequantized to `float`, F32 or F16,
+/// as [`super::to_safetensors`] does. Returns the tensors written to `model.safetensors`.
+pub fn to_mlx(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    float: GGMLType,
+) -> Result<Vec<SafeTensor>, WriteError> {
+    let mut input = File::open(src)?;
+    let (file, data_start) = GGUFFile::read_from(&mut input, &crate::ParseOptions::default())?;
+    let config = config(&file, float)?;
+    let arch = config["model_type"].as_str().unwrap_or_default();
+    let mapping = TensorMapping::for_arch(arch)?;
+    let mut tensors = Vec::new();
+    for tensor in &file.tensors {
+        // llama.cpp's precomputed RoPE scaling, which transformers derives from the config
+        if tensor.name == "rope_freqs.weight" {
+            continue;
+        }
+        let name = mapping
+            .unmap(&tensor.name)
+            .ok_or_else(|| format!("tensor {} has no transformers name", tensor.name))?;
+        tensors.push((tensor, name));
+    }
+
+    let heads = |key: &str| config[key].as_u64().unwrap_or_default() as usize;
+    let (q_heads, k_heads) = (heads("num_attention_heads"), heads("num_key_value_heads"));
+    let dst = dst.as_ref();
+    std::fs::create_dir_all(dst)?;
+    let written = write_safetensors(
+        &mut input,
+        data_start,
+        tensors,
+        &dst.join("model.safetensors"),
+        float,
+        |tensor, data| {
+            let heads = match tensor.name.rsplit_once('.') {
+                Some((stem, "weight")) if arch == "llama" && stem.ends_with(".attn_q") => q_heads,
+                Some((stem, "weight")) if arch == "llama" && stem.ends_with(".attn_k") => k_heads,
+                _ => return Ok(data),
+            };
+            let rows = tensor.dimensions.get(1).copied().unwrap_or(1) as usize;
+            unpermute(&data, rows, heads)
+        },
+    )?;
+
+    let text = |value: &Value| serde_json::to_string_pretty(value).map_err(|e| e.to_string());
+    std::fs::write(dst.join("config.json"), text(&config)?)?;
+    if let Ok(vocab) = Vocab::from_header(&file.header) {
+        vocab.export_hf_tokenizer_json(dst.join("tokenizer.json"))?;
+        let tokenizer_config = tokenizer_config(&file.header, &vocab);
+        std::fs::write(dst.join("tokenizer_config.json"), text(&tokenizer_config)?)?;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path, tensor};
+
+    #[test]
+    fn exports_llama_folder() {
+        let dir = temp_path("gguf-mlx");
+        std::fs::create_dir_all(&dir).unwrap();
+        let uint = GGUFMetadataValue::Uint32;
+        let header = header(&[
+            (
+                "general.architecture",
+                GGUFMetadataValue::String("llama".to_string()),
+            ),
+            ("llama.embedding_length", uint(2)),
+            ("llama.feed_forward_length", uint(4)),
+            ("llama.block_count", uint(1)),
+            ("llama.attention.head_count", uint(1)),
+            (
+                "llama.attention.layer_norm_rms_epsilon",
+                GGUFMetadataValue::Float32(1e-5),
+            ),
+        ]);
+        let file = file(
+            header,
+            vec![
+                tensor("token_embd.weight", &[2, 3], GGMLType::F32, 0),
+                tensor("blk.0.attn_q.weight", &[2, 4], GGMLType::F32, 0),
+                tensor("rope_freqs.weight", &[1], GGMLType::F32, 0),
+            ],
+        );
+        let src = dir.join("model.gguf");
+        // the query rows of a rotate-half checkpoint interleaved, each row holding its index
+        crate::writer::write_file(&src, &file, |tensor, out| {
+            let values: &[f32] = match tensor.name.as_str() {
+                "blk.0.attn_q.weight" => &[0.0, 0.0, 2.0, 2.0, 1.0, 1.0, 3.0, 3.0],
+                _ => &[1.0; 6][..tensor.element_count() as usize],
+            };
+            values
+                .iter()
+                .try_for_each(|v| out.write_all(&v.to_le_bytes()))?;
+            Ok(())
+        })
+        .unwrap();
+
+        let out = dir.join("model");
+        let written = to_mlx(&src, &out, GGMLType::F16).unwrap();
+        let names: Vec<_> = written.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "model.embed_tokens.weight",
+                "model.layers.0.self_attn.q_proj.weight"
+            ]
+        );
+        let mut safetensors =
+            super::super::SafeTensors::open(out.join("model.safetensors")).unwrap();
+        let q = safetensors.tensors[1].clone();
+        assert_eq!(q.shape, [4, 2]);
+        let rows: Vec<f32> = safetensors
+            .read_f32(&q)
+            .unwrap()
+            .into_iter()
+            .step_by(2)
+            .collect();
+        assert_eq!(rows, [0.0, 1.0, 2.0, 3.0]);
+
+        let config: Value =
+            serde_json::from_slice(&std::fs::read(out.join("config.json")).unwrap()).unwrap();
+        assert_eq!(config["architectures"], json!(["LlamaForCausalLM"]));
+        assert_eq!(config["vocab_size"], json!(3));
+        assert_eq!(config["rms_norm_eps"], json!(1e-5));
+        assert_eq!(config["tie_word_embeddings"], json!(true));
+        assert!(!out.join("tokenizer.json").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,540 @@
+//! Reading PyTorch checkpoints, the zip archives `torch.save` writes since PyTorch 1.6
+//!
+//! An archive holds `<name>/data.pkl`, a pickle of the state dict whose tensors refer to
+//! storages by key, and each storage uncompressed as `<name>/data/<key>`. The pickle is run on
+//! a small virtual machine that knows only the opcodes and classes checkpoints use, so no code
+//! of the checkpoint is ever executed.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use super::{element_size, SafeTensor, SafeTensors};
+
+/// A member of a zip archive
+struct Member {
+    name: String,
+    /// The offset and size of the stored data.
+    data: (u64, u64),
+}
+
+fn u16_at(b: &[u8], at: usize) -> u64 {
+    u64::from(u16::from_le_bytes([b[at], b[at + 1]]))
+}
+
+fn u32_at(b: &[u8], at: usize) -> u64 {
+    u64::from(u32::from_le_bytes(b[at..at + 4].try_into().unwrap()))
+}
+
+fn u64_at(b: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(b[at..at + 8].try_into().unwrap())
+}
+
+fn read_at(file: &mut File, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| e.to_string())?;
+    let mut buf = vec![0; len as usize];
+    file.read_exact(&mut buf)
+        .map_err(|_| "the archive is truncated".to_string())?;
+    Ok(buf)
+}
+
+/// The members of the zip archive `file`, zip64 included
+fn members(file: &mut File) -> Result<Vec<Member>, String> {
+    const ZIP64: u64 = u32::MAX as u64;
+    let len = file.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+    let tail_len = len.min(22 + 0xffff);
+    let tail = read_at(file, len - tail_len, tail_len)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| tail[i..i + 4] == [0x50, 0x4b, 0x05, 0x06])
+        .ok_or("the file is not a zip archive")?;
+    let (mut count, mut start) = (u16_at(&tail, end + 10), u32_at(&tail, end + 16));
+    if end >= 20 && tail[end - 20..end - 16] == [0x50, 0x4b, 0x06, 0x07] {
+        let record = read_at(file, u64_at(&tail, end - 12), 56)?;
+        (count, start) = (u64_at(&record, 32), u64_at(&record, 48));
+    }
+
+    let directory = read_at(file, start, len - tail_len + end as u64 - start)?;
+    let mut members = Vec::new();
+    let mut at = 0;
+    for _ in 0..count {
+        if directory.len() < at + 46 || directory[at..at + 4] != [0x50, 0x4b, 0x01, 0x02] {
+            return Err("the central directory of the archive is corrupt".to_string());
+        }
+        let entry = &directory[at..];
+        let (name_len, extra_len, comment_len) = (
+            u16_at(entry, 28) as usize,
+            u16_at(entry, 30) as usize,
+            u16_at(entry, 32) as usize,
+        );
+        let name = String::from_utf8_lossy(&entry[46..46 + name_len]).into_owned();
+        let (mut size, mut offset) = (u32_at(entry, 24), u32_at(entry, 42));
+        // the zip64 extra field holds the values set to all ones, in this order
+        let mut extra = &entry[46 + name_len..46 + name_len + extra_len];
+        while extra.len() >= 4 {
+            let (id, field_len) = (u16_at(extra, 0), u16_at(extra, 2) as usize);
+            let mut field = &extra[4..(4 + field_len).min(extra.len())];
+            if id == 1 {
+                for value in [&mut size, &mut 0, &mut offset] {
+                    if *value == ZIP64 && field.len() >= 8 {
+                        *value = u64_at(field, 0);
+                        field = &field[8..];
+                    }
+                }
+            }
+            extra = &extra[(4 + field_len).min(extra.len())..];
+        }
+        if u16_at(entry, 10) != 0 {
+            return Err(format!("{name} is compressed, which torch.save never does"));
+        }
+        let local = read_at(file, offset, 30)?;
+        let data = offset + 30 + u16_at(&local, 26) + u16_at(&local, 28);
+        members.push(Member {
+            name,
+            data: (data, size),
+        });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(members)
+}
+
+/// A value on the stack of the pickle machine
+#[derive(Debug, Clone, PartialEq)]
+enum Py {
+    None,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Tuple(Vec<Py>),
+    List(Vec<Py>),
+    Dict(Vec<(Py, Py)>),
+    /// A class or function, by module and name.
+    Global(String, String),
+    /// A storage, by key and storage class such as `FloatStorage`.
+    Storage(String, String),
+    Tensor(Tensor),
+    /// The result of calling anything else.
+    Object,
+    Mark,
+}
+
+/// A tensor of a state dict
+#[derive(Debug, Clone, PartialEq)]
+struct Tensor {
+    storage: String,
+    dtype: &'static str,
+    offset: u64,
+    shape: Vec<u64>,
+    strides: Vec<u64>,
+}
+
+/// The safetensors dtype of a storage class
+fn dtype(storage: &str) -> Option<&'static str> {
+    Some(match storage {
+        "FloatStorage" => "F32",
+        "HalfStorage" => "F16",
+        "BFloat16Storage" => "BF16",
+        "DoubleStorage" => "F64",
+        "LongStorage" => "I64",
+        "IntStorage" => "I32",
+        "ShortStorage" => "I16",
+        "CharStorage" => "I8",
+        "ByteStorage" => "U8",
+        "BoolStorage" => "BOOL",
+        _ => return None,
+    })
+}
+
+fn ints(value: &Py) -> Option<Vec<u64>> {
+    match value {
+        Py::Tuple(items) => items
+            .iter()
+            .map(|v| match v {
+                Py::Int(i) => u64::try_from(*i).ok(),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// `callable(*args)` for the callables of checkpoints
+fn call(callable: Py, args: Py) -> Result<Py, String> {
+    let Py::Global(module, name) = callable else {
+        return Ok(Py::Object);
+    };
+    let Py::Tuple(args) = args else {
+        return Ok(Py::Object);
+    };
+    Ok(match (module.as_str(), name.as_str(), args.as_slice()) {
+        ("collections", "OrderedDict", []) => Py::Dict(Vec::new()),
+        ("torch._utils", "_rebuild_tensor_v2", [storage, offset, shape, strides, ..]) => {
+            let (Py::Storage(key, class), Py::Int(offset)) = (storage, offset) else {
+                return Err("a tensor is rebuilt from something other than a storage".into());
+            };
+            Py::Tensor(Tensor {
+                storage: key.clone(),
+                dtype: dtype(class).ok_or_else(|| format!("unknown storage class {class}"))?,
+                offset: u64::try_from(*offset).map_err(|e| e.to_string())?,
+                shape: ints(shape).ok_or("a tensor has an invalid shape")?,
+                strides: ints(strides).ok_or("a tensor has invalid strides")?,
+            })
+        }
+        ("torch._utils", "_rebuild_parameter", [data, ..]) => data.clone(),
+        _ => Py::Object,
+    })
+}
+
+/// The bytes of a pickle and the position of the next opcode
+struct Input<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Input<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.at..self.at + n)
+            .ok_or("the pickle is truncated")?;
+        self.at += n;
+        Ok(bytes)
+    }
+
+    /// The text up to the next newline
+    fn line(&mut self) -> Result<String, String> {
+        let rest = &self.data[self.at..];
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or("the pickle is truncated")?;
+        self.at += end + 1;
+        Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+    }
+}
+
+/// Run the pickle `data`, returning the object it builds
+fn unpickle(data: &[u8]) -> Result<Py, String> {
+    let mut stack: Vec<Py> = Vec::new();
+    let mut memo: HashMap<u64, Py> = HashMap::new();
+    let mut input = Input { data, at: 0 };
+    fn pop(stack: &mut Vec<Py>) -> Result<Py, String> {
+        stack
+            .pop()
+            .ok_or_else(|| "the pickle pops an empty stack".to_string())
+    }
+    /// The items above the topmost mark, which is removed
+    fn pop_mark(stack: &mut Vec<Py>) -> Result<Vec<Py>, String> {
+        let mark = stack
+            .iter()
+            .rposition(|v| *v == Py::Mark)
+            .ok_or("the pickle has no mark")?;
+        let items = stack.split_off(mark + 1);
+        stack.pop();
+        Ok(items)
+    }
+    let len = |b: &[u8]| b.iter().rev().fold(0usize, |n, &b| n << 8 | b as usize);
+    let text = |b: &[u8]| String::from_utf8_lossy(b).into_owned();
+    loop {
+        let op = input.take(1)?[0];
+        match op {
+            0x80 => drop(input.take(1)?),   // PROTO
+            0x95 => drop(input.take(8)?),   // FRAME
+            b'.' => return pop(&mut stack), // STOP
+            b'}' => stack.push(Py::Dict(Vec::new())),
+            b']' => stack.push(Py::List(Vec::new())),
+            b')' => stack.push(Py::Tuple(Vec::new())),
+            b'(' => stack.push(Py::Mark),
+            b'N' => stack.push(Py::None),
+            0x88 => stack.push(Py::Bool(true)),
+            0x89 => stack.push(Py::Bool(false)),
+            b'K' => stack.push(Py::Int(i64::from(input.take(1)?[0]))),
+            b'M' => stack.push(Py::Int(len(input.take(2)?) as i64)),
+            b'J' => stack.push(Py::Int(i64::from(i32::from_le_bytes(
+                input.take(4)?.try_into().unwrap(),
+            )))),
+            0x8a => {
+                // LONG1, little-endian two's complement
+                let n = input.take(1)?[0] as usize;
+                let bytes = input.take(n)?;
+                if n > 8 {
+                    return Err("the pickle has an integer over 64 bits".to_string());
+                }
+                let fill = if bytes.last().is_some_and(|b| b & 0x80 != 0) {
+                    0xff
+                } else {
+                    0
+                };
+                let mut full = [fill; 8];
+                full[..n].copy_from_slice(bytes);
+                stack.push(Py::Int(i64::from_le_bytes(full)));
+            }
+            b'G' => stack.push(Py::Float(f64::from_be_bytes(
+                input.take(8)?.try_into().unwrap(),
+            ))),
+            b'X' | b'T' | b'B' => {
+                let n = len(input.take(4)?);
+                let bytes = input.take(n)?;
+                stack.push(match op {
+                    b'B' => Py::Bytes(bytes.to_vec()),
+                    _ => Py::Str(text(bytes)),
+                });
+            }
+            0x8c | b'U' | b'C' => {
+                let n = input.take(1)?[0] as usize;
+                let bytes = input.take(n)?;
+                stack.push(match op {
+                    b'C' => Py::Bytes(bytes.to_vec()),
+                    _ => Py::Str(text(bytes)),
+                });
+            }
+            0x8d => {
+                let n = len(input.take(8)?);
+                let bytes = input.take(n)?;
+                stack.push(Py::Str(text(bytes)));
+            }
+            b'c' => {
+                let (module, name) = (input.line()?, input.line()?);
+                stack.push(Py::Global(module, name));
+            }
+            0x93 => {
+                // STACK_GLOBAL
+                let (Py::Str(name), Py::Str(module)) = (pop(&mut stack)?, pop(&mut stack)?) else {
+                    return Err("STACK_GLOBAL of a non-string".to_string());
+                };
+                stack.push(Py::Global(module, name));
+            }
+            b'q' | b'r' | 0x94 => {
+                let key = match op {
+                    b'q' => len(input.take(1)?),
+                    b'r' => len(input.take(4)?),
+                    _ => memo.len(),
+                };
+                let top = stack.last().ok_or("the pickle memoizes an empty stack")?;
+                memo.insert(key as u64, top.clone());
+            }
+            b'h' | b'j' => {
+                let key = len(input.take(if op == b'h' { 1 } else { 4 })?) as u64;
+                let value = memo.get(&key).ok_or("the pickle gets an unset memo")?;
+                stack.push(value.clone());
+            }
+            b't' => {
+                let items = pop_mark(&mut stack)?;
+                stack.push(Py::Tuple(items));
+            }
+            0x85..=0x87 => {
+                let n = (op - 0x84) as usize;
+                if stack.len() < n {
+                    return Err("the pickle pops an empty stack".to_string());
+                }
+                let items = stack.split_off(stack.len() - n);
+                stack.push(Py::Tuple(items));
+            }
+            b'l' => {
+                let items = pop_mark(&mut stack)?;
+                stack.push(Py::List(items));
+            }
+            b'd' => {
+                let items = pop_mark(&mut stack)?;
+                let mut pairs = Vec::new();
+                let mut items = items.into_iter();
+                while let (Some(k), Some(v)) = (items.next(), items.next()) {
+                    pairs.push((k, v));
+                }
+                stack.push(Py::Dict(pairs));
+            }
+            b'a' | b'e' => {
+                let items = match op {
+                    b'a' => vec![pop(&mut stack)?],
+                    _ => pop_mark(&mut stack)?,
+                };
+                if let Some(Py::List(list)) = stack.last_mut() {
+                    list.extend(items);
+                }
+            }
+            b's' | b'u' => {
+                let items = match op {
+                    b's' => {
+                        let value = pop(&mut stack)?;
+                        vec![pop(&mut stack)?, value]
+                    }
+                    _ => pop_mark(&mut stack)?,
+                };
+                if let Some(Py::Dict(dict)) = stack.last_mut() {
+                    let mut items = items.into_iter();
+                    while let (Some(k), Some(v)) = (items.next(), items.next()) {
+                        dict.push((k, v));
+                    }
+                }
+            }
+            b'Q' => {
+                // ('storage', storage class, key, location, size)
+                let id = pop(&mut stack)?;
+                let storage = match id {
+                    Py::Tuple(items) => match items.as_slice() {
+                        [Py::Str(kind), Py::Global(_, class), Py::Str(key), ..]
+                            if kind == "storage" =>
+                        {
+                            Some(Py::Storage(key.clone(), class.clone()))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                stack.push(storage.ok_or("the pickle has an unknown persistent id")?);
+            }
+            b'R' | 0x81 => {
+                let args = pop(&mut stack)?;
+                let callable = pop(&mut stack)?;
+                stack.push(call(callable, args)?);
+            }
+            b'b' => drop(pop(&mut stack)?), // BUILD, the state of an object
+            b'0' => drop(pop(&mut stack)?),
+            b'1' => drop(pop_mark(&mut stack)?),
+            b'2' => {
+                let top = stack.last().ok_or("the pickle duplicates an empty stack")?;
+                stack.push(top.clone());
+            }
+            op => return Err(format!("the pickle has the unsupported opcode 0x{op:02x}")),
+        }
+    }
+}
+
+/// The tensors of a state dict, or of the `state_dict` or `model` entry of a training checkpoint
+fn state_dict(object: &Py) -> Option<Vec<(String, Tensor)>> {
+    let Py::Dict(items) = object else {
+        return None;
+    };
+    let tensors: Vec<(String, Tensor)> = items
+        .iter()
+        .filter_map(|(k, v)| match (k, v) {
+            (Py::Str(name), Py::Tensor(t)) => Some((name.clone(), t.clone())),
+            _ => None,
+        })
+        .collect();
+    if !tensors.is_empty() {
+        return Some(tensors);
+    }
+    items.iter().find_map(|(k, v)| match k {
+        Py::Str(key) if ["state_dict", "model", "module"].contains(&key.as_str()) => state_dict(v),
+        _ => None,
+    })
+}
+
+impl SafeTensors {
+    /// Read the tensors of a PyTorch checkpoint, a `.pth`, `.pt` or `pytorch_model.bin`
+    /// written by `torch.save`
+    ///
+    /// Tensors must be contiguous, as those of saved state dicts are; the data ranges are
+    /// relative to the start of the file.
+    pub fn open_pytorch(path: impl AsRef<Path>) -> Result<Self, String> {
+        let mut file = File::open(path).map_err(|e| e.to_string())?;
+        let mut magic = [0; 2];
+        file.read_exact(&mut magic).map_err(|e| e.to_string())?;
+        if magic == [0x80, 0x02] {
+            return Err(
+                "the checkpoint is in the format of PyTorch before 1.6; save it again".to_string(),
+            );
+        }
+        let members = members(&mut file)?;
+        let pickle = members
+            .iter()
+            .find(|m| m.name.ends_with("/data.pkl") && m.name.matches('/').count() == 1)
+            .ok_or("the archive has no data.pkl")?;
+        let prefix = pickle.name.trim_end_matches("data.pkl");
+        if let Some(order) = members
+            .iter()
+            .find(|m| m.name == format!("{prefix}byteorder"))
+        {
+            if read_at(&mut file, order.data.0, order.data.1)? != b"little" {
+                return Err("the checkpoint is big-endian".to_string());
+            }
+        }
+        let object = unpickle(&read_at(&mut file, pickle.data.0, pickle.data.1)?)?;
+        let tensors = state_dict(&object).ok_or("the checkpoint holds no state dict")?;
+
+        let mut safe_tensors = Vec::new();
+        for (name, tensor) in tensors {
+            let storage = members
+                .iter()
+                .find(|m| m.name == format!("{prefix}data/{}", tensor.storage))
+                .ok_or_else(|| format!("the storage of tensor {name} is missing"))?;
+            let mut contiguous = 1;
+            for (dim, stride) in tensor.shape.iter().zip(&tensor.strides).rev() {
+                if *dim != 1 && *stride != contiguous {
+                    return Err(format!("tensor {name} is not contiguous"));
+                }
+                contiguous *= dim;
+            }
+            let size = element_size(tensor.dtype).unwrap_or(match tensor.dtype {
+                "F64" | "I64" => 8,
+                "I32" => 4,
+                "I16" => 2,
+                _ => 1,
+            });
+            let start = storage.data.0 + tensor.offset * size;
+            let end = start + contiguous * size;
+            if end > storage.data.0 + storage.data.1 {
+                return Err(format!("tensor {name} runs past the end of its storage"));
+            }
+            safe_tensors.push(SafeTensor {
+                name,
+                dtype: tensor.dtype.to_string(),
+                shape: tensor.shape,
+                data: (start, end),
+            });
+        }
+        safe_tensors.sort_by_key(|t| t.data.0);
+        Ok(SafeTensors {
+            file,
+            data_start: 0,
+            tensors: safe_tensors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npz::ZipWriter;
+    use crate::test_util::temp_path;
+
+    #[test]
+    fn reads_state_dicts() {
+        // what torch.save writes for OrderedDict(w=torch.arange(6.).reshape(2, 3)), with
+        // the tensor memoized and referred to again as a parameter
+        let mut pkl = vec![0x80, 2];
+        pkl.extend(b"ccollections\nOrderedDict\nq\x00)Rq\x01(");
+        pkl.extend(b"X\x01\x00\x00\x00w");
+        pkl.extend(b"ctorch._utils\n_rebuild_tensor_v2\nq\x02(");
+        pkl.extend(b"(X\x07\x00\x00\x00storagectorch\nFloatStorage\nX\x01\x00\x00\x000");
+        pkl.extend(b"X\x03\x00\x00\x00cpuK\x08tQ");
+        pkl.extend(b"K\x02K\x02K\x03\x86K\x03K\x01\x86\x89ccollections\nOrderedDict\n)Rtq\x03R");
+        pkl.extend(b"q\x04X\x01\x00\x00\x00vh\x04u}b.");
+        let data: Vec<u8> = (0..8).flat_map(|i| (i as f32).to_le_bytes()).collect();
+
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add("archive/data.pkl".to_string(), &[&pkl]).unwrap();
+        zip.add("archive/byteorder".to_string(), &[b"little"])
+            .unwrap();
+        zip.add("archive/data/0".to_string(), &[&data]).unwrap();
+        let bytes = zip.finish().unwrap();
+        let path = temp_path("pytorch.pth");
+        std::fs::write(&path, &bytes).unwrap();
+        let mut checkpoint = SafeTensors::open_pytorch(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let names: Vec<&str> = checkpoint.tensors.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["w", "v"]);
+        let w = checkpoint.tensors[0].clone();
+        assert_eq!((w.dtype.as_str(), w.shape.as_slice()), ("F32", &[2, 3][..]));
+        // storage offset 2
+        assert_eq!(
+            checkpoint.read_f32(&w).unwrap(),
+            [2.0, 3.0, 4.0, 5.0, 6.0, 7.0]
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! # Metadata diffs
+//!
+//! [`MetadataDiff::between`] tells what metadata one header adds, removes and changes relative
+//! to another, and [`MetadataDiff::apply`] makes the same edits to any header, which carries a
+//! fix made to one file of a family, say the name or chat template of one quantization, over
+//! to the rest. [`merge`] joins the metadata of two headers, a [`MergeStrategy`] settling the
+//! keys they give different values.
+use std::collections::HashMap;
+
+use crate::{GGUFHeader, GGUFMetadata};
+
+/// The metadata entries only in the second header, only in the first, and in both with
+/// different values, the first value before the second
+///
+/// Added and changed entries are in the order of the second header, removed ones in the order
+/// of the first.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MetadataDiff {
+    pub added: Vec<GGUFMetadata>,
+    pub removed: Vec<GGUFMetadata>,
+    pub changed: Vec<(GGUFMetadata, GGUFMetadata)>,
+}
+
+/// What [`merge`] does with a key both headers have, with different values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Keep the value of the first header
+    Ours,
+    /// Take the value of the second header
+    Theirs,
+    /// Fail, naming the key
+    Error,
+}
+
+impl MetadataDiff {
+    /// What turns the metadata of `a` into that of `b`
+    pub fn between(a: &GGUFHeader, b: &GGUFHeader) -> Self {
+        let in_a: HashMap<&str, &GGUFMetadata> =
+            a.metadata.iter().map(|m| (m.key.as_str(), m)).collect();
+        let in_b: HashMap<&str, &GGUFMetadata> =
+            b.metadata.iter().map(|m| (m.key.as_str(), m)).collect();
+        let mut diff = MetadataDiff {
+            removed: a
+                .metadata
+                .iter()
+                .filter(|m| !in_b.contains_key(m.key.as_str()))
+                .cloned()
+                .collect(),
+            ..MetadataDiff::default()
+        };
+        for new in &b.metadata {
+            match in_a.get(new.key.as_str()) {
+                None => diff.added.push(new.clone()),
+                Some(old) if old.value != new.value => {
+                    diff.changed.push(((*old).clone(), new.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Remove the removed keys from `header` and set the added and changed ones, whatever
+    /// values `header` had for them
+    pub fn apply(&self, header: &mut GGUFHeader) {
+        header
+            .metadata
+            .retain(|m| !self.removed.iter().any(|r| r.key == m.key));
+        let set = self
+            .added
+            .iter()
+            .chain(self.changed.iter().map(|(_, new)| new));
+        for entry in set {
+            header.set(&entry.key, entry.value.clone());
+        }
+    }
+}
+
+/// The header of `a` with the metadata of both: the entries of `a`, the value of a key both
+/// have settled by `strategy`, then those only `b` has
+pub fn merge(
+    a: &GGUFHeader,
+    b: &GGUFHeader,
+    strategy: MergeStrategy,
+) -> Result<GGUFHeader, String> {
+    let diff = MetadataDiff::between(a, b);
+    let mut merged = a.clone();
+    merged.metadata.extend(diff.added);
+    for (_, theirs) in diff.changed {
+        match strategy {
+            MergeStrategy::Ours => {}
+            MergeStrategy::Theirs => merged.set(&theirs.key, theirs.value),
+            MergeStrategy::Error => {
+                return Err(format!("the headers give {} different values", theirs.key))
+            }
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use crate::GGUFMetadataValue;
+
+    fn header(entries: &[(&str, u32)]) -> GGUFHeader {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|&(key, v)| (key, GGUFMetadataValue::Uint32(v)))
+            .collect();
+        test_util::header(&entries)
+    }
+
+    #[test]
+    fn applies_and_merges_differences() {
+        let a = header(&[("a", 1), ("b", 2), ("c", 3)]);
+        let b = header(&[("c", 4), ("d", 5), ("a", 1)]);
+        let diff = MetadataDiff::between(&a, &b);
+        assert_eq!(diff.added, header(&[("d", 5)]).metadata);
+        assert_eq!(diff.removed, header(&[("b", 2)]).metadata);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].1.key, "c");
+        assert!(MetadataDiff::between(&a, &a).is_empty());
+
+        let mut sibling = header(&[("a", 7), ("b", 8), ("c", 9)]);
+        diff.apply(&mut sibling);
+        assert_eq!(sibling, header(&[("a", 7), ("c", 4), ("d", 5)]));
+
+        let ours = merge(&a, &b, MergeStrategy::Ours).unwrap();
+        assert_eq!(ours, header(&[("a", 1), ("b", 2), ("c", 3), ("d", 5)]));
+        let theirs = merge(&a, &b, MergeStrategy::Theirs).unwrap();
+        assert_eq!(theirs, header(&[("a", 1), ("b", 2), ("c", 4), ("d", 5)]));
+        let error = merge(&a, &b, MergeStrategy::Error).unwrap_err();
+        assert!(error.contains(" c "));
+    }
+}
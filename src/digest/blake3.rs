@@ -0,0 +1,255 @@
+//! BLAKE3 in its default hashing mode, with a 32-byte output
+
+const IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+const PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+const CHUNK_START: u32 = 1;
+const CHUNK_END: u32 = 2;
+const PARENT: u32 = 4;
+const ROOT: u32 = 8;
+
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+
+fn g(s: &mut [u32; 16], [a, b, c, d]: [usize; 4], x: u32, y: u32) {
+    s[a] = s[a].wrapping_add(s[b]).wrapping_add(x);
+    s[d] = (s[d] ^ s[a]).rotate_right(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_right(12);
+    s[a] = s[a].wrapping_add(s[b]).wrapping_add(y);
+    s[d] = (s[d] ^ s[a]).rotate_right(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_right(7);
+}
+
+fn compress(cv: &[u32; 8], block: &[u32; 16], counter: u64, len: u32, flags: u32) -> [u32; 16] {
+    let mut s = [
+        cv[0],
+        cv[1],
+        cv[2],
+        cv[3],
+        cv[4],
+        cv[5],
+        cv[6],
+        cv[7],
+        IV[0],
+        IV[1],
+        IV[2],
+        IV[3],
+        counter as u32,
+        (counter >> 32) as u32,
+        len,
+        flags,
+    ];
+    let mut m = *block;
+    for round in 0..7 {
+        g(&mut s, [0, 4, 8, 12], m[0], m[1]);
+        g(&mut s, [1, 5, 9, 13], m[2], m[3]);
+        g(&mut s, [2, 6, 10, 14], m[4], m[5]);
+        g(&mut s, [3, 7, 11, 15], m[6], m[7]);
+        g(&mut s, [0, 5, 10, 15], m[8], m[9]);
+        g(&mut s, [1, 6, 11, 12], m[10], m[11]);
+        g(&mut s, [2, 7, 8, 13], m[12], m[13]);
+        g(&mut s, [3, 4, 9, 14], m[14], m[15]);
+        if round < 6 {
+            m = PERMUTATION.map(|i| m[i]);
+        }
+    }
+    for i in 0..8 {
+        s[i] ^= s[i + 8];
+        s[i + 8] ^= cv[i];
+    }
+    s
+}
+
+fn words(bytes: &[u8]) -> [u32; 16] {
+    let mut block = [0u8; BLOCK_LEN];
+    block[..bytes.len()].copy_from_slice(bytes);
+    let mut words = [0; 16];
+    for (word, chunk) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    words
+}
+
+fn first_8(s: [u32; 16]) -> [u32; 8] {
+    [s[0], s[1], s[2], s[3], s[4], s[5], s[6], s[7]]
+}
+
+/// the inputs of a compression whose output is not yet known to be the root
+struct Output {
+    cv: [u32; 8],
+    block: [u32; 16],
+    counter: u64,
+    len: u32,
+    flags: u32,
+}
+
+impl Output {
+    fn chaining_value(&self) -> [u32; 8] {
+        first_8(compress(
+            &self.cv,
+            &self.block,
+            self.counter,
+            self.len,
+            self.flags,
+        ))
+    }
+}
+
+fn parent(left: [u32; 8], right: [u32; 8]) -> Output {
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(&left);
+    block[8..].copy_from_slice(&right);
+    Output {
+        cv: IV,
+        block,
+        counter: 0,
+        len: BLOCK_LEN as u32,
+        flags: PARENT,
+    }
+}
+
+/// Incremental BLAKE3 hasher
+#[derive(Clone)]
+pub(crate) struct Blake3 {
+    /// chaining value of the current chunk
+    cv: [u32; 8],
+    chunk: u64,
+    block: [u8; BLOCK_LEN],
+    filled: usize,
+    blocks: usize,
+    /// chaining values of the complete subtrees to the left
+    stack: Vec<[u32; 8]>,
+}
+
+impl Default for Blake3 {
+    fn default() -> Self {
+        Blake3 {
+            cv: IV,
+            chunk: 0,
+            block: [0; BLOCK_LEN],
+            filled: 0,
+            blocks: 0,
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl Blake3 {
+    fn chunk_output(&self) -> Output {
+        Output {
+            cv: self.cv,
+            block: words(&self.block[..self.filled]),
+            counter: self.chunk,
+            len: self.filled as u32,
+            flags: self.start_flag() | CHUNK_END,
+        }
+    }
+
+    fn start_flag(&self) -> u32 {
+        if self.blocks == 0 {
+            CHUNK_START
+        } else {
+            0
+        }
+    }
+
+    /// close the full current chunk and merge every subtree it completes
+    fn finish_chunk(&mut self) {
+        let mut cv = self.chunk_output().chaining_value();
+        let mut chunks = self.chunk + 1;
+        while chunks.is_multiple_of(2) {
+            let left = self.stack.pop().unwrap_or(IV);
+            cv = parent(left, cv).chaining_value();
+            chunks >>= 1;
+        }
+        self.stack.push(cv);
+        self.cv = IV;
+        self.chunk += 1;
+        self.filled = 0;
+        self.blocks = 0;
+    }
+
+    pub(crate) fn finish(&self) -> [u8; 32] {
+        let mut output = self.chunk_output();
+        for left in self.stack.iter().rev() {
+            output = parent(*left, output.chaining_value());
+        }
+        let words = compress(
+            &output.cv,
+            &output.block,
+            0,
+            output.len,
+            output.flags | ROOT,
+        );
+        let mut out = [0; 32];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.blocks * BLOCK_LEN + self.filled == CHUNK_LEN {
+                self.finish_chunk();
+            }
+            // the last block of a chunk stays buffered until it is known to be the last
+            if self.filled == BLOCK_LEN {
+                let block = words(&self.block);
+                let flags = self.start_flag();
+                self.cv = first_8(compress(
+                    &self.cv,
+                    &block,
+                    self.chunk,
+                    BLOCK_LEN as u32,
+                    flags,
+                ));
+                self.blocks += 1;
+                self.filled = 0;
+            }
+            let n = (BLOCK_LEN - self.filled).min(data.len());
+            self.block[self.filled..self.filled + n].copy_from_slice(&data[..n]);
+            self.filled += n;
+            data = &data[n..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::hex;
+
+    #[test]
+    fn known_digests() {
+        let digest = |len: usize| {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let mut whole = Blake3::default();
+            whole.update(&data);
+            let mut pieces = Blake3::default();
+            data.chunks(100).for_each(|c| pieces.update(c));
+            assert_eq!(whole.finish(), pieces.finish());
+            hex(&whole.finish())
+        };
+        assert_eq!(
+            digest(0),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+        assert_eq!(
+            digest(1024),
+            "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7"
+        );
+        assert_eq!(
+            digest(3073),
+            "7124b49501012f81cc7f11ca069ec9226cecb8a2c850cfe644e327d22d3e1cd3"
+        );
+        assert_eq!(
+            digest(102400),
+            "bc3e3d41a1146b069abffad3c0d44860cf664390afce4d9661f7902e7943e085"
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! # Embedding models
+//!
+//! Models such as BERT turn a text into one vector rather than generating from it: the encoder
+//! gives an embedding for each token, and the pooling the file declares joins them.
+//! [`EmbeddingConfig::of`] reads what a server needs to serve one from the header alone, the
+//! pooling and the width of the vectors it returns.
+use core::fmt;
+
+use crate::keys::arch;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::GGUFHeader;
+
+/// Architectures of embedding models
+const EMBEDDING_ARCHITECTURES: &[&str] = &[
+    "bert",
+    "nomic-bert",
+    "nomic-bert-moe",
+    "jina-bert-v2",
+    "neo-bert",
+    "modern-bert",
+    "t5encoder",
+];
+
+/// How the embeddings of the tokens of a text are joined into one, llama.cpp's
+/// `enum llama_pooling_type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum PoolingType {
+    /// An embedding for each token
+    None,
+    /// The mean of the tokens' embeddings
+    Mean,
+    /// The embedding of the first token, the `[CLS]` one
+    Cls,
+    /// The embedding of the last token, as decoder models are pooled
+    Last,
+    /// A score of how well a document answers a query, from the classifier head of a reranker
+    Rank,
+    Unknown(u32),
+}
+
+impl PoolingType {
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            0 => PoolingType::None,
+            1 => PoolingType::Mean,
+            2 => PoolingType::Cls,
+            3 => PoolingType::Last,
+            4 => PoolingType::Rank,
+            id => PoolingType::Unknown(id),
+        }
+    }
+
+    pub fn id(self) -> u32 {
+        match self {
+            PoolingType::None => 0,
+            PoolingType::Mean => 1,
+            PoolingType::Cls => 2,
+            PoolingType::Last => 3,
+            PoolingType::Rank => 4,
+            PoolingType::Unknown(id) => id,
+        }
+    }
+
+    /// The name llama.cpp's `--pooling` takes, `None` for an unknown type
+    pub fn name(self) -> Option<&'static str> {
+        Some(match self {
+            PoolingType::None => "none",
+            PoolingType::Mean => "mean",
+            PoolingType::Cls => "cls",
+            PoolingType::Last => "last",
+            PoolingType::Rank => "rank",
+            PoolingType::Unknown(_) => return None,
+        })
+    }
+}
+
+impl From<u32> for PoolingType {
+    fn from(id: u32) -> Self {
+        PoolingType::from_id(id)
+    }
+}
+
+impl fmt::Display for PoolingType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "unknown pooling type {}", self.id()),
+        }
+    }
+}
+
+/// What serving an embedding model takes, from its header
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct EmbeddingConfig {
+    /// `{arch}.pooling_type`, `None` if unset, when llama.cpp picks by architecture.
+    pub pooling: Option<PoolingType>,
+    /// The width of each vector returned: the embedding length, or for a reranker the number
+    /// of labels of its classifier.
+    pub dimension: Option<u64>,
+    /// `{arch}.attention.causal`, whether a token attends only to the tokens before it, as in
+    /// decoder models turned into embedding ones.
+    pub causal: bool,
+    /// The longest text the model embeds, in tokens.
+    pub context_length: Option<u64>,
+}
+
+impl EmbeddingConfig {
+    /// The configuration of `header`, `None` if it is not an embedding model: one of an
+    /// embedding architecture, or one declaring a pooling other than `none`
+    pub fn of(header: &GGUFHeader) -> Option<Self> {
+        let name = header.get(crate::keys::general::ARCHITECTURE)?.as_str()?;
+        let key = |suffix: &str| header.get(&format!("{name}{suffix}"));
+        let number = |suffix: &str| key(suffix).and_then(|v| v.as_u64());
+        let pooling = number(arch::POOLING_TYPE)
+            .and_then(|id| u32::try_from(id).ok())
+            .map(PoolingType::from_id);
+        let pooled = pooling.is_some_and(|p| p != PoolingType::None);
+        if !pooled && !EMBEDDING_ARCHITECTURES.contains(&name) {
+            return None;
+        }
+        let dimension = match pooling {
+            Some(PoolingType::Rank) => Some(
+                key(arch::CLASSIFIER_OUTPUT_LABELS)
+                    .and_then(|v| v.as_array())
+                    .map_or(1, |labels| labels.len),
+            ),
+            _ => number(arch::EMBEDDING_LENGTH),
+        };
+        Some(EmbeddingConfig {
+            pooling,
+            dimension,
+            causal: key(arch::ATTENTION_CAUSAL)
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+            context_length: number(arch::CONTEXT_LENGTH),
+        })
+    }
+}
+
+/// Whether `header` is that of an embedding model, see [`EmbeddingConfig::of`]
+pub fn is_embedding_model(header: &GGUFHeader) -> bool {
+    EmbeddingConfig::of(header).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::header;
+    use crate::GGUFMetadataValue;
+
+    #[test]
+    fn configures_from_the_header() {
+        use GGUFMetadataValue::{String as Str, Uint32};
+        let mut header = header(&[
+            ("general.architecture", Str("bert".into())),
+            ("bert.embedding_length", Uint32(384)),
+            ("bert.context_length", Uint32(512)),
+            ("bert.pooling_type", Uint32(1)),
+        ]);
+        let config = EmbeddingConfig::of(&header).unwrap();
+        assert_eq!(config.pooling, Some(PoolingType::Mean));
+        assert_eq!(config.dimension, Some(384));
+        assert!(!config.causal);
+
+        header.set("bert.pooling_type", Uint32(4));
+        assert_eq!(EmbeddingConfig::of(&header).unwrap().dimension, Some(1));
+        header.set("bert.pooling_type", Uint32(9));
+        let pooling = EmbeddingConfig::of(&header).unwrap().pooling.unwrap();
+        assert_eq!(pooling.to_string(), "unknown pooling type 9");
+
+        header.set("general.architecture", Str("qwen3".into()));
+        assert!(!is_embedding_model(&header));
+        header.set("qwen3.pooling_type", Uint32(3));
+        assert_eq!(
+            EmbeddingConfig::of(&header)
+                .unwrap()
+                .pooling
+                .map(|p| p.to_string()),
+            Some("last".to_string())
+        );
+    }
+}
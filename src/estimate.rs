@@ -0,0 +1,287 @@
+//! # Memory estimates
+//!
+//! Rough memory needs of running a model with llama.cpp: the weights, the KV cache for a
+//! context length or the state of a recurrent model, and the scratch memory of evaluating one
+//! batch, read from the `{arch}.*` hyperparameters so that the fit can be checked before
+//! downloading or launching.
+use crate::recurrent::{MambaConfig, RwkvConfig};
+use crate::{GGMLType, GGUFFile, GGUFMetadataValue};
+
+/// What the model is run with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EstimateOptions {
+    /// Tokens of context; the model's trained context length if `None`.
+    pub context: Option<u64>,
+    /// Tokens evaluated at once.
+    pub batch: u64,
+    /// The type the keys and values are cached as.
+    pub kv_type: GGMLType,
+}
+
+impl Default for EstimateOptions {
+    fn default() -> Self {
+        EstimateOptions {
+            context: None,
+            batch: 512,
+            kv_type: GGMLType::F16,
+        }
+    }
+}
+
+/// Bytes needed, by use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct MemoryEstimate {
+    /// The context length the estimate is for.
+    pub context: u64,
+    pub weights: u64,
+    pub kv_cache: u64,
+    /// The states of the recurrent layers for one sequence, which do not grow with the context.
+    pub state: u64,
+    /// Activations, attention scores without flash attention and logits of one batch.
+    pub compute: u64,
+}
+
+impl MemoryEstimate {
+    pub fn total(&self) -> u64 {
+        self.weights + self.kv_cache + self.state + self.compute
+    }
+}
+
+/// How far the context of a model reaches, as [`effective_context`] gives it
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ContextWindow {
+    /// `{arch}.context_length`.
+    pub trained: u64,
+    /// `{arch}.rope.scaling.type`, `linear` for the `rope.scale_linear` of older files.
+    pub scaling: Option<String>,
+    /// `{arch}.rope.scaling.factor`.
+    pub factor: Option<f64>,
+    /// The context before scaling, `{arch}.rope.scaling.original_context_length`.
+    pub original: Option<u64>,
+    /// The tokens of context the scaling makes usable, at least `trained`.
+    pub effective: u64,
+}
+
+/// The context `file` is usable for once its RoPE scaling is applied
+///
+/// Converters give the context the weights were trained for as `context_length` and leave the
+/// scaling that stretches it to the runtime, so a YaRN model with 32K of context and a factor
+/// of 4 serves 128K. The effective context is the original context times the factor, for
+/// `linear` and `yarn` scaling, or the trained one when it is longer or the original is unset.
+/// `None` if the file has no `{arch}.context_length`.
+pub fn effective_context(file: &GGUFFile) -> Option<ContextWindow> {
+    let header = &file.header;
+    let arch = header.get("general.architecture")?.as_str()?;
+    let key = |name: &str| header.get(&format!("{arch}.{name}"));
+    let trained = key("context_length")?.as_u64()?;
+    let legacy = key("rope.scale_linear").and_then(GGUFMetadataValue::as_f64);
+    let scaling = key("rope.scaling.type")
+        .and_then(GGUFMetadataValue::as_str)
+        .map(str::to_string)
+        .or_else(|| legacy.map(|_| "linear".to_string()));
+    let factor = key("rope.scaling.factor")
+        .and_then(GGUFMetadataValue::as_f64)
+        .or(legacy);
+    let original = key("rope.scaling.original_context_length").and_then(GGUFMetadataValue::as_u64);
+    let stretched = match (scaling.as_deref(), factor, original) {
+        (Some("linear" | "yarn"), Some(factor), Some(original)) if factor > 1.0 => {
+            (original as f64 * factor) as u64
+        }
+        _ => 0,
+    };
+    Some(ContextWindow {
+        effective: trained.max(stretched),
+        trained,
+        scaling,
+        factor,
+        original,
+    })
+}
+
+/// A hyperparameter that may be given per layer, as the counts of models with varying
+/// attention are; one value per layer
+fn per_layer(value: Option<&GGUFMetadataValue>, layers: u64) -> Option<Vec<u64>> {
+    match value? {
+        GGUFMetadataValue::Array(array) => array.value.iter().map(|v| v.as_u64()).collect(),
+        value => Some(vec![value.as_u64()?; layers as usize]),
+    }
+}
+
+/// Estimate the memory needs of running `file`
+///
+/// Models without attention hyperparameters are given no KV cache. Those with `{arch}.ssm.*`
+/// or `{arch}.wkv.*` keys, Mamba and RWKV, are given a state instead, which llama.cpp keeps as
+/// F32 whatever `kv_type` is.
+pub fn estimate(file: &GGUFFile, options: &EstimateOptions) -> Result<MemoryEstimate, String> {
+    let header = &file.header;
+    let arch = header
+        .get("general.architecture")
+        .and_then(GGUFMetadataValue::as_str)
+        .ok_or("the file has no general.architecture")?;
+    let key = |name: &str| header.get(&format!("{arch}.{name}"));
+    let number = |name: &str| key(name).and_then(GGUFMetadataValue::as_u64);
+    let context = options
+        .context
+        .or_else(|| number("context_length"))
+        .ok_or_else(|| format!("the file has no {arch}.context_length, give a context length"))?;
+    let weights = file.tensors.iter().filter_map(|t| t.size_bytes()).sum();
+
+    let layers = number("block_count").unwrap_or_default();
+    let embedding = number("embedding_length").unwrap_or_default();
+    let heads = per_layer(key("attention.head_count"), layers);
+    let kv_cache = match &heads {
+        Some(heads) => {
+            let kv_heads =
+                per_layer(key("attention.head_count_kv"), layers).unwrap_or_else(|| heads.clone());
+            let mut elements = 0;
+            for (&heads, &kv_heads) in heads.iter().zip(&kv_heads) {
+                let head_dim = embedding.checked_div(heads).unwrap_or_default();
+                let key_len = number("attention.key_length").unwrap_or(head_dim);
+                let value_len = number("attention.value_length").unwrap_or(head_dim);
+                elements += context * kv_heads * (key_len + value_len);
+            }
+            options
+                .kv_type
+                .size_of(elements)
+                .ok_or_else(|| format!("{:?} cannot hold the KV cache", options.kv_type))?
+        }
+        None => 0,
+    };
+
+    let mut state_elements = 0;
+    if let Some(mamba) = MambaConfig::of(header) {
+        state_elements += mamba.state_elements();
+    }
+    if let Some(rwkv) = RwkvConfig::of(header) {
+        state_elements += rwkv.state_elements(embedding);
+    }
+    let state = layers * GGMLType::F32.size_of(state_elements).unwrap_or_default();
+
+    let vocab = header
+        .get("tokenizer.ggml.tokens")
+        .and_then(GGUFMetadataValue::as_array)
+        .map_or(0, |tokens| tokens.len);
+    let feed_forward = per_layer(key("feed_forward_length"), layers)
+        .and_then(|lengths| lengths.into_iter().max())
+        .unwrap_or(4 * embedding);
+    let max_heads = heads.and_then(|h| h.into_iter().max()).unwrap_or_default();
+    let compute = 4 * options.batch * (vocab + feed_forward + 3 * embedding + max_heads * context);
+    Ok(MemoryEstimate {
+        context,
+        weights,
+        kv_cache,
+        state,
+        compute,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, tensor};
+
+    #[test]
+    fn kv_cache_of_grouped_query_attention() {
+        let int = GGUFMetadataValue::Uint32;
+        let header = header(&[
+            (
+                "general.architecture",
+                GGUFMetadataValue::String("llama".into()),
+            ),
+            ("llama.context_length", int(8192)),
+            ("llama.block_count", int(32)),
+            ("llama.embedding_length", int(4096)),
+            ("llama.feed_forward_length", int(14336)),
+            ("llama.attention.head_count", int(32)),
+            ("llama.attention.head_count_kv", int(8)),
+        ]);
+        let file = file(
+            header,
+            vec![tensor(
+                "token_embd.weight",
+                &[4096, 1024],
+                GGMLType::Q8_0,
+                0,
+            )],
+        );
+        let f16 = estimate(&file, &EstimateOptions::default()).unwrap();
+        assert_eq!(f16.context, 8192);
+        assert_eq!(f16.weights, 4096 * 1024 / 32 * 34);
+        // keys and values of 8 heads of 128 for 8192 tokens in 32 layers
+        assert_eq!(f16.kv_cache, 1 << 30);
+        let q8 = estimate(
+            &file,
+            &EstimateOptions {
+                context: Some(4096),
+                kv_type: GGMLType::Q8_0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(q8.kv_cache, (1 << 29) / 64 * 34);
+        assert!(q8.compute < f16.compute);
+        assert_eq!(q8.total(), q8.weights + q8.kv_cache + q8.compute);
+        assert_eq!(q8.state, 0);
+    }
+
+    #[test]
+    fn rope_scaling_extends_the_context() {
+        let qwen2 = |mut entries: Vec<(&str, GGUFMetadataValue)>| {
+            let arch = GGUFMetadataValue::String("qwen2".into());
+            entries.insert(0, ("general.architecture", arch));
+            file(header(&entries), Vec::new())
+        };
+        let context = GGUFMetadataValue::Uint32(32768);
+        let yarn = qwen2(vec![
+            ("qwen2.context_length", context.clone()),
+            (
+                "qwen2.rope.scaling.type",
+                GGUFMetadataValue::String("yarn".into()),
+            ),
+            ("qwen2.rope.scaling.factor", GGUFMetadataValue::Float32(4.0)),
+            (
+                "qwen2.rope.scaling.original_context_length",
+                context.clone(),
+            ),
+        ]);
+        let window = effective_context(&yarn).unwrap();
+        assert_eq!((window.trained, window.effective), (32768, 131072));
+        assert_eq!(window.scaling.as_deref(), Some("yarn"));
+
+        let plain = qwen2(vec![("qwen2.context_length", context)]);
+        assert_eq!(effective_context(&plain).unwrap().effective, 32768);
+        assert_eq!(effective_context(&qwen2(Vec::new())), None);
+    }
+
+    #[test]
+    fn state_of_recurrent_models_ignores_the_context() {
+        let int = GGUFMetadataValue::Uint32;
+        let file = file(
+            header(&[
+                (
+                    "general.architecture",
+                    GGUFMetadataValue::String("mamba".into()),
+                ),
+                ("mamba.context_length", int(1 << 20)),
+                ("mamba.block_count", int(24)),
+                ("mamba.embedding_length", int(768)),
+                ("mamba.ssm.conv_kernel", int(4)),
+                ("mamba.ssm.state_size", int(16)),
+                ("mamba.ssm.inner_size", int(1536)),
+            ]),
+            Vec::new(),
+        );
+        let long = estimate(&file, &EstimateOptions::default()).unwrap();
+        let short = estimate(
+            &file,
+            &EstimateOptions {
+                context: Some(512),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(long.kv_cache, 0);
+        assert_eq!(long.state, 24 * 4 * (3 * 1536 + 16 * 1536));
+        assert_eq!(short.state, long.state);
+    }
+}
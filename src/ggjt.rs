@@ -0,0 +1,474 @@
+//! # Legacy GGML files
+//!
+//! Reads the containers llama.cpp wrote before GGUF: unversioned `ggml`, `ggmf` v1 and `ggjt`
+//! v1 to v3, each a magic, seven LLaMA hyperparameters, the vocabulary and a record per tensor,
+//! and converts them to GGUF the way llama.cpp's `convert_llama_ggml_to_gguf.py` does. F32 and
+//! F16 tensors convert from any version; quantized ones only from ggjt v3, as the block layouts
+//! of the versions before it no longer load anywhere.
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::tokenizer::{SpecialTokens, TokenType, TokenizerMetadata, TokenizerModel, Vocab};
+use crate::writer::{write_file, WriteError};
+use crate::{GGMLType, GGUFFile, GGUFHeader, GGUFMetadata, GGUFMetadataValue, GGUFTensorInfo};
+
+/// The container of a legacy file, named by its magic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyFormat {
+    /// `ggml`, without a version or token scores.
+    Ggml,
+    /// `ggmf` and its version.
+    Ggmf(u32),
+    /// `ggjt` and its version, with tensor data aligned to 32 bytes.
+    Ggjt(u32),
+}
+
+/// The hyperparameters of a legacy LLaMA file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LlamaHparams {
+    pub n_vocab: u32,
+    pub n_embd: u32,
+    /// The multiple the feed-forward length was rounded up to.
+    pub n_mult: u32,
+    pub n_head: u32,
+    pub n_layer: u32,
+    pub n_rot: u32,
+    /// The llama.cpp file type, with the quantization version times 1000 added since ggjt v2.
+    pub ftype: u32,
+}
+
+/// A record of a legacy file
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacyTensor {
+    pub name: String,
+    /// Innermost first, as in GGUF.
+    pub dimensions: Vec<u64>,
+    pub tensor_type: GGMLType,
+    /// The offset of the data in the file.
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// The header of a legacy file
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacyFile {
+    pub format: LegacyFormat,
+    pub hparams: LlamaHparams,
+    /// The token bytes and scores, spaces as spaces and byte tokens as the byte itself.
+    pub vocab: Vec<(Vec<u8>, f32)>,
+    pub tensors: Vec<LegacyTensor>,
+}
+
+/// What a legacy file does not record
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacyOptions {
+    /// `general.name`.
+    pub name: Option<String>,
+    /// The training context, 2048 for LLaMA 1 and 4096 for Llama 2.
+    pub context_length: u32,
+    /// The RMS norm epsilon, 1e-6 for LLaMA 1 and 1e-5 for Llama 2.
+    pub rms_eps: f32,
+    /// The number of key-value heads of a grouped-query model such as Llama 2 70B, by default
+    /// as many as there are heads.
+    pub head_count_kv: Option<u32>,
+}
+
+impl Default for LegacyOptions {
+    fn default() -> Self {
+        // the defaults of convert_llama_ggml_to_gguf.py
+        LegacyOptions {
+            name: None,
+            context_length: 2048,
+            rms_eps: 5e-6,
+            head_count_kv: None,
+        }
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+impl LegacyFile {
+    /// Read the header of the legacy file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::read_from(&mut BufReader::new(file))
+    }
+
+    /// Read the header of a legacy file, seeking past the tensor data
+    pub fn read_from(reader: &mut (impl Read + Seek)) -> Result<Self, String> {
+        let eof = |e: io::Error| match e.kind() {
+            io::ErrorKind::UnexpectedEof => "the file ends inside its header".to_string(),
+            _ => e.to_string(),
+        };
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic).map_err(eof)?;
+        let format = match &magic {
+            b"lmgg" => LegacyFormat::Ggml,
+            b"fmgg" => LegacyFormat::Ggmf(read_u32(reader).map_err(eof)?),
+            b"tjgg" => LegacyFormat::Ggjt(read_u32(reader).map_err(eof)?),
+            b"GGUF" => return Err("the file is GGUF already".to_string()),
+            _ => return Err("the file is not a GGML, GGMF or GGJT file".to_string()),
+        };
+        match format {
+            LegacyFormat::Ggmf(1) | LegacyFormat::Ggjt(1..=3) | LegacyFormat::Ggml => {}
+            _ => return Err(format!("{format:?} is not a known version")),
+        }
+
+        let mut hparams = [0; 7];
+        for value in &mut hparams {
+            *value = read_u32(reader).map_err(eof)?;
+        }
+        let [n_vocab, n_embd, n_mult, n_head, n_layer, n_rot, ftype] = hparams;
+        let hparams = LlamaHparams {
+            n_vocab,
+            n_embd,
+            n_mult,
+            n_head,
+            n_layer,
+            n_rot,
+            ftype,
+        };
+
+        let mut vocab = Vec::new();
+        for _ in 0..n_vocab {
+            let len = read_u32(reader).map_err(eof)?;
+            let mut text = Vec::new();
+            reader
+                .take(u64::from(len))
+                .read_to_end(&mut text)
+                .map_err(eof)?;
+            if text.len() != len as usize {
+                return Err(eof(io::ErrorKind::UnexpectedEof.into()));
+            }
+            let score = match format {
+                LegacyFormat::Ggml => 0.0,
+                _ => f32::from_bits(read_u32(reader).map_err(eof)?),
+            };
+            vocab.push((text, score));
+        }
+
+        let mut position = reader.stream_position().map_err(|e| e.to_string())?;
+        let len = reader.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+        reader
+            .seek(SeekFrom::Start(position))
+            .map_err(|e| e.to_string())?;
+        let mut tensors = Vec::new();
+        while position < len {
+            let n_dims = read_u32(reader).map_err(eof)?;
+            let name_len = read_u32(reader).map_err(eof)?;
+            let tensor_type = read_u32(reader).map_err(eof)?;
+            if !(1..=4).contains(&n_dims) {
+                return Err(format!(
+                    "a tensor record at {position} has {n_dims} dimensions"
+                ));
+            }
+            let mut dimensions = Vec::new();
+            for _ in 0..n_dims {
+                dimensions.push(u64::from(read_u32(reader).map_err(eof)?));
+            }
+            let mut name = Vec::new();
+            reader
+                .take(u64::from(name_len))
+                .read_to_end(&mut name)
+                .map_err(eof)?;
+            let name = String::from_utf8_lossy(&name).into_owned();
+            let tensor_type = GGMLType::try_from(tensor_type).map_err(|_| {
+                format!("tensor {name} has the type {tensor_type}, removed from ggml")
+            })?;
+            let mut offset = reader.stream_position().map_err(|e| e.to_string())?;
+            if let LegacyFormat::Ggjt(_) = format {
+                offset = offset.next_multiple_of(32);
+            }
+            let tensor = GGUFTensorInfo {
+                name,
+                dimensions: dimensions.into(),
+                tensor_type,
+                offset: 0,
+            };
+            let size = tensor
+                .size_bytes()
+                .ok_or_else(|| format!("tensor {} has no whole number of blocks", tensor.name))?;
+            if offset + size > len {
+                return Err(format!(
+                    "the file ends inside the data of tensor {}",
+                    tensor.name
+                ));
+            }
+            position = reader
+                .seek(SeekFrom::Start(offset + size))
+                .map_err(|e| e.to_string())?;
+            tensors.push(LegacyTensor {
+                name: tensor.name,
+                dimensions: tensor.dimensions.into_vec(),
+                tensor_type,
+                offset,
+                size,
+            });
+        }
+        Ok(LegacyFile {
+            format,
+            hparams,
+            vocab,
+            tensors,
+        })
+    }
+
+    /// The GGUF header and tensor infos of the file, with offsets left to
+    /// [`write_file`] to assign
+    pub fn to_gguf(&self, options: &LegacyOptions) -> Result<GGUFFile, String> {
+        let quantized = |t: &&LegacyTensor| !matches!(t.tensor_type, GGMLType::F32 | GGMLType::F16);
+        if let Some(tensor) = self.tensors.iter().find(quantized) {
+            if self.format != LegacyFormat::Ggjt(3) {
+                return Err(format!(
+                    "tensor {} is {:?} in the block layout of {:?}, which ggml no longer reads; \
+                     convert the F16 file instead",
+                    tensor.name, tensor.tensor_type, self.format
+                ));
+            }
+        }
+        let hp = &self.hparams;
+        let n_ff = self
+            .tensors
+            .iter()
+            .find(|t| t.name == "layers.0.feed_forward.w1.weight")
+            .and_then(|t| t.dimensions.get(1).copied())
+            .unwrap_or_else(|| {
+                let n_mult = u64::from(hp.n_mult.max(1));
+                (2 * 4 * u64::from(hp.n_embd) / 3).next_multiple_of(n_mult)
+            });
+        let uint = |key: &str, value: u32| GGUFMetadata::new(key, GGUFMetadataValue::Uint32(value));
+        let mut metadata = vec![GGUFMetadata::new(
+            "general.architecture",
+            GGUFMetadataValue::String("llama".to_string()),
+        )];
+        if let Some(name) = &options.name {
+            metadata.push(GGUFMetadata::new(
+                "general.name",
+                GGUFMetadataValue::String(name.clone()),
+            ));
+        }
+        metadata.extend([
+            uint("general.file_type", hp.ftype % 1000),
+            uint("llama.context_length", options.context_length),
+            uint("llama.embedding_length", hp.n_embd),
+            uint("llama.block_count", hp.n_layer),
+            uint("llama.feed_forward_length", n_ff as u32),
+            uint("llama.rope.dimension_count", hp.n_rot),
+            uint("llama.attention.head_count", hp.n_head),
+            uint(
+                "llama.attention.head_count_kv",
+                options.head_count_kv.unwrap_or(hp.n_head),
+            ),
+            GGUFMetadata::new(
+                "llama.attention.layer_norm_rms_epsilon",
+                GGUFMetadataValue::Float32(options.rms_eps),
+            ),
+        ]);
+        if hp.ftype >= 1000 {
+            metadata.push(uint("general.quantization_version", hp.ftype / 1000));
+        }
+        metadata.extend(self.tokenizer().to_metadata());
+
+        let tensors: Vec<GGUFTensorInfo> = self
+            .tensors
+            .iter()
+            .map(|t| GGUFTensorInfo {
+                name: gguf_name(&t.name),
+                dimensions: t.dimensions[..].into(),
+                tensor_type: t.tensor_type,
+                offset: 0,
+            })
+            .collect();
+        Ok(GGUFFile {
+            header: GGUFHeader {
+                version: 3,
+                tensor_count: tensors.len() as u64,
+                metadata,
+            },
+            tensors,
+        })
+    }
+
+    /// The vocabulary as GGUF stores it, with SentencePiece spaces, `<0xNN>` byte tokens and
+    /// the token types llama.cpp gives them
+    fn tokenizer(&self) -> TokenizerMetadata {
+        let (mut tokens, mut token_types) = (Vec::new(), Vec::new());
+        for (id, (text, _)) in self.vocab.iter().enumerate() {
+            let (token, token_type) = match (id, text.as_slice()) {
+                (0, _) => ("<unk>".to_string(), TokenType::Unknown),
+                (1, _) => ("<s>".to_string(), TokenType::Control),
+                (2, _) => ("</s>".to_string(), TokenType::Control),
+                (_, []) => (String::new(), TokenType::Control),
+                (3..=258, [byte]) => (format!("<0x{byte:02X}>"), TokenType::Byte),
+                _ => (
+                    String::from_utf8_lossy(text).replace(' ', "\u{2581}"),
+                    TokenType::Normal,
+                ),
+            };
+            tokens.push(token);
+            token_types.push(token_type);
+        }
+        let mut vocab = Vocab::default();
+        vocab.model = TokenizerModel::Llama;
+        *vocab.tokens_mut() = tokens;
+        vocab.scores = self.vocab.iter().map(|(_, score)| *score).collect();
+        vocab.token_types = token_types;
+        vocab.special = SpecialTokens {
+            bos: Some(1),
+            eos: Some(2),
+            unk: Some(0),
+            ..Default::default()
+        };
+        vocab.add_space_prefix = true;
+        TokenizerMetadata {
+            vocab,
+            add_bos_token: None,
+            add_eos_token: None,
+        }
+    }
+}
+
+/// The GGUF name of a legacy LLaMA tensor name, e.g. `blk.0.attn_q.weight` for
+/// `layers.0.attention.wq.weight`
+pub fn gguf_name(name: &str) -> String {
+    match name {
+        "tok_embeddings.weight" => return "token_embd.weight".to_string(),
+        "norm.weight" => return "output_norm.weight".to_string(),
+        _ => {}
+    }
+    let Some((layer, rest)) = name
+        .strip_prefix("layers.")
+        .and_then(|rest| rest.split_once('.'))
+    else {
+        return name.to_string();
+    };
+    let part = match rest {
+        "attention.wq.weight" => "attn_q",
+        "attention.wk.weight" => "attn_k",
+        "attention.wv.weight" => "attn_v",
+        "attention.wo.weight" => "attn_output",
+        "attention_norm.weight" => "attn_norm",
+        "feed_forward.w1.weight" => "ffn_gate",
+        "feed_forward.w2.weight" => "ffn_down",
+        "feed_forward.w3.weight" => "ffn_up",
+        "ffn_norm.weight" => "ffn_norm",
+        _ => return name.to_string(),
+    };
+    format!("blk.{layer}.{part}.weight")
+}
+
+/// Convert the legacy file `src` to the GGUF file `dst`, returning the header and tensor infos
+/// written
+pub fn convert(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    options: &LegacyOptions,
+) -> Result<GGUFFile, WriteError> {
+    let src = src.as_ref();
+    let legacy = LegacyFile::open(src)?;
+    let file = legacy.to_gguf(options)?;
+    let mut input = File::open(src).map_err(|e| format!("{}: {e}", src.display()))?;
+    let mut records = legacy.tensors.iter();
+    write_file(dst, &file, |_, out: &mut dyn Write| {
+        // write_file goes through the tensors in the order to_gguf made them
+        let record = records.next().ok_or("more tensors than records")?;
+        input.seek(SeekFrom::Start(record.offset))?;
+        io::copy(&mut (&mut input).take(record.size), out)?;
+        Ok(())
+    })?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+    use crate::ParseOptions;
+
+    /// A ggjt v3 file of two tokens past the special ones and two tensors
+    fn ggjt() -> Vec<u8> {
+        let mut out = b"tjgg".to_vec();
+        let u32s = |out: &mut Vec<u8>, values: &[u32]| {
+            values.iter().for_each(|v| out.extend(v.to_le_bytes()));
+        };
+        u32s(&mut out, &[3, 5, 4, 256, 1, 1, 2, 1]);
+        for (text, score) in [
+            ("", 0.0),
+            ("", 0.0),
+            ("", 0.0),
+            ("\n", -1.0),
+            (" hi", -2.0f32),
+        ] {
+            u32s(&mut out, &[text.len() as u32]);
+            out.extend(text.as_bytes());
+            out.extend(score.to_le_bytes());
+        }
+        for (name, dims, tensor_type) in [
+            ("tok_embeddings.weight", &[4, 5][..], 1),
+            ("layers.0.attention_norm.weight", &[4], 0),
+        ] {
+            u32s(
+                &mut out,
+                &[dims.len() as u32, name.len() as u32, tensor_type],
+            );
+            u32s(&mut out, dims);
+            out.extend(name.as_bytes());
+            out.resize(out.len().next_multiple_of(32), 0);
+            let size = dims.iter().product::<u32>() * [4, 2][tensor_type as usize];
+            out.extend((0..size).map(|i| i as u8));
+        }
+        out
+    }
+
+    #[test]
+    fn converts_ggjt() {
+        let bytes = ggjt();
+        let legacy = LegacyFile::read_from(&mut io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(legacy.format, LegacyFormat::Ggjt(3));
+        assert_eq!(legacy.hparams.n_embd, 4);
+        assert_eq!(legacy.tensors[1].dimensions, [4]);
+        assert_eq!(legacy.tensors[1].offset % 32, 0);
+
+        let src = temp_path("ggjt.bin");
+        let dst = src.with_extension("gguf");
+        std::fs::write(&src, &bytes).unwrap();
+        convert(&src, &dst, &LegacyOptions::default()).unwrap();
+        let out = std::fs::read(&dst).unwrap();
+        std::fs::remove_file(&src).unwrap();
+        std::fs::remove_file(&dst).unwrap();
+
+        let (file, data_start) =
+            GGUFFile::read_from(&mut out.as_slice(), &ParseOptions::default()).unwrap();
+        let header = &file.header;
+        assert_eq!(
+            header.get("general.file_type").and_then(|v| v.as_u64()),
+            Some(1)
+        );
+        assert_eq!(
+            header
+                .get("llama.feed_forward_length")
+                .and_then(|v| v.as_u64()),
+            Some(256)
+        );
+        let vocab = Vocab::from_header(header).unwrap();
+        assert_eq!(
+            vocab.tokens(),
+            ["<unk>", "<s>", "</s>", "<0x0A>", "\u{2581}hi"]
+        );
+        assert_eq!(vocab.token_types[3], TokenType::Byte);
+        assert_eq!(file.tensors[0].name, "token_embd.weight");
+        assert_eq!(file.tensors[1].name, "blk.0.attn_norm.weight");
+        let norm = &legacy.tensors[1];
+        let data = (data_start + file.tensors[1].offset) as usize;
+        assert_eq!(
+            out[data..data + 16],
+            bytes[norm.offset as usize..norm.offset as usize + 16]
+        );
+    }
+}
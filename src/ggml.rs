@@ -0,0 +1,185 @@
+//! # ggml constant tables
+//!
+//! The tensor types and file types of upstream ggml and llama.cpp, in `tables.rs` generated by
+//! `scripts/ggml_tables.py` from `ggml.h`, `ggml.c` and `llama.h` of a llama.cpp checkout:
+//!
+//! ```sh
+//! python3 scripts/ggml_tables.py ../llama.cpp > src/ggml/tables.rs
+//! python3 scripts/ggml_tables.py ../llama.cpp --check
+//! ```
+//!
+//! [`GGMLType`](crate::GGMLType) takes its block and type sizes from here, and the tests fail
+//! when a type of the table is missing from it, so regenerating after upstream adds a type
+//! points at what to add. [`LlamaFileType`] is generated from the same table.
+use core::fmt;
+
+/// The file types of llama.cpp as an enum, with `Unknown` for the ids it does not know
+macro_rules! llama_ftypes {
+    ($($name:ident = $id:literal,)*) => {
+        /// A file type of llama.cpp, the value of `general.file_type`, named as in
+        /// `enum llama_ftype` without the `LLAMA_FTYPE_` prefix
+        ///
+        /// It displays as the name without the `ALL_` or `MOSTLY_` prefix either, such as
+        /// `Q4_K_M`.
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum LlamaFileType {
+            $($name,)*
+            Unknown(u32),
+        }
+
+        impl LlamaFileType {
+            pub fn from_id(id: u32) -> Self {
+                match id {
+                    $($id => LlamaFileType::$name,)*
+                    id => LlamaFileType::Unknown(id),
+                }
+            }
+
+            /// The value of `general.file_type`
+            pub fn id(self) -> u32 {
+                match self {
+                    $(LlamaFileType::$name => $id,)*
+                    LlamaFileType::Unknown(id) => id,
+                }
+            }
+
+            /// The llama.cpp name, such as `MOSTLY_Q4_K_M`, `None` if unknown
+            pub fn name(self) -> Option<&'static str> {
+                match self {
+                    $(LlamaFileType::$name => Some(stringify!($name)),)*
+                    LlamaFileType::Unknown(_) => None,
+                }
+            }
+        }
+    };
+}
+
+#[rustfmt::skip]
+mod tables;
+
+pub use tables::{LlamaFileType, GGML_TYPES, GGML_TYPE_COUNT, LLAMA_FTYPES};
+
+/// A tensor type of ggml, from the `type_traits` of `ggml.c`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeTraits {
+    /// The value of `enum ggml_type`, stored in tensor infos.
+    pub id: u32,
+    /// The `type_name`, such as `q4_K`.
+    pub name: &'static str,
+    /// The number of elements in a block.
+    pub block_size: u64,
+    /// The size of a block in bytes.
+    pub type_size: u64,
+}
+
+/// A file type of llama.cpp, from `enum llama_ftype` of `llama.h`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileTypeName {
+    /// The value stored in `general.file_type`.
+    pub id: u32,
+    /// The name without the `LLAMA_FTYPE_` prefix, such as `MOSTLY_Q4_K_M`.
+    pub name: &'static str,
+}
+
+/// `name` without its `ALL_` or `MOSTLY_` prefix
+fn short_name(name: &str) -> &str {
+    let name = name.strip_prefix("ALL_").unwrap_or(name);
+    name.strip_prefix("MOSTLY_").unwrap_or(name)
+}
+
+impl LlamaFileType {
+    /// The file type named `name`, such as `Q4_K_M` or `MOSTLY_Q4_K_M`, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        LLAMA_FTYPES
+            .iter()
+            .map(|t| LlamaFileType::from_id(t.id))
+            .find(|t| {
+                let full = t.name().unwrap_or_default();
+                full.eq_ignore_ascii_case(name) || short_name(full).eq_ignore_ascii_case(name)
+            })
+    }
+}
+
+impl From<u32> for LlamaFileType {
+    fn from(id: u32) -> Self {
+        LlamaFileType::from_id(id)
+    }
+}
+
+impl fmt::Display for LlamaFileType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(short_name(name)),
+            None => write!(f, "unknown file type {}", self.id()),
+        }
+    }
+}
+
+/// Serialized as it displays
+impl serde::Serialize for LlamaFileType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The traits of the tensor type `id`
+pub fn type_traits(id: u32) -> Option<&'static TypeTraits> {
+    GGML_TYPES.iter().find(|t| t.id == id)
+}
+
+/// The llama.cpp name of the file type `id`
+pub fn ftype_name(id: u32) -> Option<&'static str> {
+    LLAMA_FTYPES.iter().find(|t| t.id == id).map(|t| t.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GGMLType;
+
+    fn normalize(name: &str) -> String {
+        name.to_ascii_uppercase().replace('_', "")
+    }
+
+    #[test]
+    fn in_sync_with_ggml() {
+        for traits in GGML_TYPES {
+            let Ok(t) = GGMLType::try_from(traits.id) else {
+                panic!("GGMLType lacks {} = {}", traits.name, traits.id);
+            };
+            assert_eq!(normalize(&format!("{t:?}")), normalize(traits.name));
+            assert_eq!(t.block_size(), Some(traits.block_size), "{t:?}");
+            assert_eq!(t.type_size(), Some(traits.type_size), "{t:?}");
+        }
+        assert_eq!(GGMLType::Count as u32, GGML_TYPE_COUNT);
+        for id in (0..GGML_TYPE_COUNT).filter(|&id| type_traits(id).is_none()) {
+            assert!(GGMLType::try_from(id).is_err(), "ggml removed type {id}");
+        }
+
+        assert_eq!(type_traits(12).map(|t| t.name), Some("q4_K"));
+        assert_eq!(ftype_name(15), Some("MOSTLY_Q4_K_M"));
+        for ftype in LLAMA_FTYPES {
+            assert_eq!(LlamaFileType::from_id(ftype.id).name(), Some(ftype.name));
+            assert_eq!(LlamaFileType::from_id(ftype.id).id(), ftype.id);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn file_types_display_short_names() {
+        assert_eq!(LlamaFileType::from(15), LlamaFileType::MOSTLY_Q4_K_M);
+        assert_eq!(LlamaFileType::MOSTLY_Q4_K_M.to_string(), "Q4_K_M");
+        assert_eq!(LlamaFileType::ALL_F32.to_string(), "F32");
+        assert_eq!(LlamaFileType::from(4).to_string(), "unknown file type 4");
+        assert_eq!(
+            LlamaFileType::from_name("q4_k_m"),
+            Some(LlamaFileType::MOSTLY_Q4_K_M)
+        );
+        assert_eq!(
+            LlamaFileType::from_name("ALL_F32"),
+            Some(LlamaFileType::ALL_F32)
+        );
+        assert_eq!(LlamaFileType::from_name("Q4_K"), None);
+    }
+}
@@ -0,0 +1,116 @@
+// @generated by scripts/ggml_tables.py from llama.cpp; do not edit.
+use super::{FileTypeName, TypeTraits};
+
+/// One past the largest tensor type id, `GGML_TYPE_COUNT`
+pub const GGML_TYPE_COUNT: u32 = 40;
+
+/// The tensor types of ggml, by id; removed ids are left out
+pub const GGML_TYPES: &[TypeTraits] = &[
+    TypeTraits { id: 0, name: "f32", block_size: 1, type_size: 4 },
+    TypeTraits { id: 1, name: "f16", block_size: 1, type_size: 2 },
+    TypeTraits { id: 2, name: "q4_0", block_size: 32, type_size: 18 },
+    TypeTraits { id: 3, name: "q4_1", block_size: 32, type_size: 20 },
+    TypeTraits { id: 6, name: "q5_0", block_size: 32, type_size: 22 },
+    TypeTraits { id: 7, name: "q5_1", block_size: 32, type_size: 24 },
+    TypeTraits { id: 8, name: "q8_0", block_size: 32, type_size: 34 },
+    TypeTraits { id: 9, name: "q8_1", block_size: 32, type_size: 36 },
+    TypeTraits { id: 10, name: "q2_K", block_size: 256, type_size: 84 },
+    TypeTraits { id: 11, name: "q3_K", block_size: 256, type_size: 110 },
+    TypeTraits { id: 12, name: "q4_K", block_size: 256, type_size: 144 },
+    TypeTraits { id: 13, name: "q5_K", block_size: 256, type_size: 176 },
+    TypeTraits { id: 14, name: "q6_K", block_size: 256, type_size: 210 },
+    TypeTraits { id: 15, name: "q8_K", block_size: 256, type_size: 292 },
+    TypeTraits { id: 16, name: "iq2_xxs", block_size: 256, type_size: 66 },
+    TypeTraits { id: 17, name: "iq2_xs", block_size: 256, type_size: 74 },
+    TypeTraits { id: 18, name: "iq3_xxs", block_size: 256, type_size: 98 },
+    TypeTraits { id: 19, name: "iq1_s", block_size: 256, type_size: 50 },
+    TypeTraits { id: 20, name: "iq4_nl", block_size: 32, type_size: 18 },
+    TypeTraits { id: 21, name: "iq3_s", block_size: 256, type_size: 110 },
+    TypeTraits { id: 22, name: "iq2_s", block_size: 256, type_size: 82 },
+    TypeTraits { id: 23, name: "iq4_xs", block_size: 256, type_size: 136 },
+    TypeTraits { id: 24, name: "i8", block_size: 1, type_size: 1 },
+    TypeTraits { id: 25, name: "i16", block_size: 1, type_size: 2 },
+    TypeTraits { id: 26, name: "i32", block_size: 1, type_size: 4 },
+    TypeTraits { id: 27, name: "i64", block_size: 1, type_size: 8 },
+    TypeTraits { id: 28, name: "f64", block_size: 1, type_size: 8 },
+    TypeTraits { id: 29, name: "iq1_m", block_size: 256, type_size: 56 },
+    TypeTraits { id: 30, name: "bf16", block_size: 1, type_size: 2 },
+    TypeTraits { id: 34, name: "tq1_0", block_size: 256, type_size: 54 },
+    TypeTraits { id: 35, name: "tq2_0", block_size: 256, type_size: 66 },
+    TypeTraits { id: 39, name: "mxfp4", block_size: 32, type_size: 17 },
+];
+
+/// The file types of llama.cpp, by id; removed ids are left out
+pub const LLAMA_FTYPES: &[FileTypeName] = &[
+    FileTypeName { id: 0, name: "ALL_F32" },
+    FileTypeName { id: 1, name: "MOSTLY_F16" },
+    FileTypeName { id: 2, name: "MOSTLY_Q4_0" },
+    FileTypeName { id: 3, name: "MOSTLY_Q4_1" },
+    FileTypeName { id: 7, name: "MOSTLY_Q8_0" },
+    FileTypeName { id: 8, name: "MOSTLY_Q5_0" },
+    FileTypeName { id: 9, name: "MOSTLY_Q5_1" },
+    FileTypeName { id: 10, name: "MOSTLY_Q2_K" },
+    FileTypeName { id: 11, name: "MOSTLY_Q3_K_S" },
+    FileTypeName { id: 12, name: "MOSTLY_Q3_K_M" },
+    FileTypeName { id: 13, name: "MOSTLY_Q3_K_L" },
+    FileTypeName { id: 14, name: "MOSTLY_Q4_K_S" },
+    FileTypeName { id: 15, name: "MOSTLY_Q4_K_M" },
+    FileTypeName { id: 16, name: "MOSTLY_Q5_K_S" },
+    FileTypeName { id: 17, name: "MOSTLY_Q5_K_M" },
+    FileTypeName { id: 18, name: "MOSTLY_Q6_K" },
+    FileTypeName { id: 19, name: "MOSTLY_IQ2_XXS" },
+    FileTypeName { id: 20, name: "MOSTLY_IQ2_XS" },
+    FileTypeName { id: 21, name: "MOSTLY_Q2_K_S" },
+    FileTypeName { id: 22, name: "MOSTLY_IQ3_XS" },
+    FileTypeName { id: 23, name: "MOSTLY_IQ3_XXS" },
+    FileTypeName { id: 24, name: "MOSTLY_IQ1_S" },
+    FileTypeName { id: 25, name: "MOSTLY_IQ4_NL" },
+    FileTypeName { id: 26, name: "MOSTLY_IQ3_S" },
+    FileTypeName { id: 27, name: "MOSTLY_IQ3_M" },
+    FileTypeName { id: 28, name: "MOSTLY_IQ2_S" },
+    FileTypeName { id: 29, name: "MOSTLY_IQ2_M" },
+    FileTypeName { id: 30, name: "MOSTLY_IQ4_XS" },
+    FileTypeName { id: 31, name: "MOSTLY_IQ1_M" },
+    FileTypeName { id: 32, name: "MOSTLY_BF16" },
+    FileTypeName { id: 36, name: "MOSTLY_TQ1_0" },
+    FileTypeName { id: 37, name: "MOSTLY_TQ2_0" },
+    FileTypeName { id: 38, name: "MOSTLY_MXFP4_MOE" },
+    FileTypeName { id: 1024, name: "GUESSED" },
+];
+
+llama_ftypes! {
+    ALL_F32 = 0,
+    MOSTLY_F16 = 1,
+    MOSTLY_Q4_0 = 2,
+    MOSTLY_Q4_1 = 3,
+    MOSTLY_Q8_0 = 7,
+    MOSTLY_Q5_0 = 8,
+    MOSTLY_Q5_1 = 9,
+    MOSTLY_Q2_K = 10,
+    MOSTLY_Q3_K_S = 11,
+    MOSTLY_Q3_K_M = 12,
+    MOSTLY_Q3_K_L = 13,
+    MOSTLY_Q4_K_S = 14,
+    MOSTLY_Q4_K_M = 15,
+    MOSTLY_Q5_K_S = 16,
+    MOSTLY_Q5_K_M = 17,
+    MOSTLY_Q6_K = 18,
+    MOSTLY_IQ2_XXS = 19,
+    MOSTLY_IQ2_XS = 20,
+    MOSTLY_Q2_K_S = 21,
+    MOSTLY_IQ3_XS = 22,
+    MOSTLY_IQ3_XXS = 23,
+    MOSTLY_IQ1_S = 24,
+    MOSTLY_IQ4_NL = 25,
+    MOSTLY_IQ3_S = 26,
+    MOSTLY_IQ3_M = 27,
+    MOSTLY_IQ2_S = 28,
+    MOSTLY_IQ2_M = 29,
+    MOSTLY_IQ4_XS = 30,
+    MOSTLY_IQ1_M = 31,
+    MOSTLY_BF16 = 32,
+    MOSTLY_TQ1_0 = 36,
+    MOSTLY_TQ2_0 = 37,
+    MOSTLY_MXFP4_MOE = 38,
+    GUESSED = 1024,
+}
@@ -0,0 +1,168 @@
+//! # Hugging Face Hub
+//!
+//! The YAML front matter of a model card, the block between `---` lines at the top of a repo's
+//! `README.md` that the Hub reads its license, base model and task from, inferred from the
+//! metadata of a file.
+use std::fmt::Write;
+
+use crate::{GGUFFile, GGUFHeader};
+
+/// The front matter of a Hub model card for `file`, `---` lines included
+///
+/// Writes `license` from `general.license`, `base_model` from the Hugging Face URLs of
+/// `general.base_model.*.repo_url` or else `general.source.repo_url`, `quantized_by` from
+/// `general.quantized_by`, `pipeline_tag` from the architecture, `language` from
+/// `general.languages` and `library_name: gguf`; fields without a value are left out.
+pub fn frontmatter(file: &GGUFFile) -> String {
+    let header = &file.header;
+    let text = |key: &str| header.get(key).and_then(|v| v.as_str());
+    let mut out = String::from("---\n");
+    if let Some(license) = text("general.license") {
+        let _ = writeln!(out, "license: {}", scalar(license));
+    }
+    list(&mut out, "base_model", &base_models(header));
+    if let Some(quantized_by) = text("general.quantized_by") {
+        let _ = writeln!(out, "quantized_by: {}", scalar(quantized_by));
+    }
+    if let Some(tag) = pipeline_tag(header) {
+        let _ = writeln!(out, "pipeline_tag: {tag}");
+    }
+    let languages: Vec<&str> = header
+        .get("general.languages")
+        .and_then(|v| v.as_array())
+        .map(|a| a.value.strs().collect())
+        .unwrap_or_default();
+    list(&mut out, "language", &languages);
+    out.push_str("library_name: gguf\n---\n");
+    out
+}
+
+/// The Hub repo ids of the models `header` was made from
+fn base_models(header: &GGUFHeader) -> Vec<&str> {
+    let count = header
+        .get("general.base_model.count")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0);
+    let keys: Vec<String> = match count {
+        // the source is the base model when none is listed
+        0 => vec!["general.source.repo_url".to_string()],
+        _ => (0..count)
+            .map(|i| format!("general.base_model.{i}.repo_url"))
+            .collect(),
+    };
+    keys.iter()
+        .filter_map(|key| header.get(key)?.as_str())
+        .filter_map(repo_id)
+        .collect()
+}
+
+/// `org/name` of a `https://huggingface.co/org/name` URL
+fn repo_id(url: &str) -> Option<&str> {
+    let path = url
+        .strip_prefix("https://huggingface.co/")
+        .or_else(|| url.strip_prefix("https://hf.co/"))?;
+    let path = path.trim_end_matches('/');
+    (path.split('/').count() == 2 && !path.contains(['?', '#'])).then_some(path)
+}
+
+fn pipeline_tag(header: &GGUFHeader) -> Option<&'static str> {
+    let arch = header.get("general.architecture")?.as_str()?;
+    if crate::embedding::is_embedding_model(header) {
+        Some("feature-extraction")
+    } else if arch == "whisper" {
+        Some("automatic-speech-recognition")
+    } else if arch == "clip" {
+        // a projector of a vision model, no model on its own
+        None
+    } else {
+        Some("text-generation")
+    }
+}
+
+fn list(out: &mut String, field: &str, items: &[&str]) {
+    if items.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "{field}:");
+    for item in items {
+        let _ = writeln!(out, "- {}", scalar(item));
+    }
+}
+
+/// `text` as a YAML scalar, double-quoted unless it is plain
+fn scalar(text: &str) -> String {
+    let plain = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./ ".contains(c))
+        && text.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && !text.ends_with(' ')
+        && !["true", "false", "yes", "no", "on", "off", "null", "~"]
+            .contains(&text.to_ascii_lowercase().as_str());
+    if plain {
+        return text.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header};
+    use crate::{GGUFMetadataArrayValue, GGUFMetadataValue, GGUfMetadataValueType};
+
+    #[test]
+    fn frontmatter_from_metadata() {
+        let string = |s: &str| GGUFMetadataValue::String(s.to_string());
+        let languages = ["en", "no"].map(string).to_vec();
+        let header = header(&[
+            ("general.architecture", string("llama")),
+            ("general.license", string("llama3.1")),
+            ("general.quantized_by", string("Jane: \"Q\" Doe")),
+            ("general.base_model.count", GGUFMetadataValue::Uint32(1)),
+            (
+                "general.base_model.0.repo_url",
+                string("https://huggingface.co/meta-llama/Llama-3.1-8B"),
+            ),
+            (
+                "general.source.repo_url",
+                string("https://huggingface.co/meta-llama/Llama-3.1-8B-Instruct"),
+            ),
+            (
+                "general.languages",
+                GGUFMetadataValue::Array(GGUFMetadataArrayValue::new(
+                    GGUfMetadataValueType::String,
+                    languages,
+                )),
+            ),
+        ]);
+        let mut file = file(header, Vec::new());
+        assert_eq!(
+            frontmatter(&file),
+            "---\nlicense: llama3.1\nbase_model:\n- meta-llama/Llama-3.1-8B\n\
+             quantized_by: \"Jane: \\\"Q\\\" Doe\"\npipeline_tag: text-generation\n\
+             language:\n- en\n- \"no\"\nlibrary_name: gguf\n---\n"
+        );
+
+        file.header
+            .metadata
+            .retain(|m| !m.key.contains("base_model"));
+        file.header.set("general.architecture", string("bert"));
+        let yaml = frontmatter(&file);
+        assert!(yaml.contains("base_model:\n- meta-llama/Llama-3.1-8B-Instruct\n"));
+        assert!(yaml.contains("pipeline_tag: feature-extraction\n"));
+    }
+}
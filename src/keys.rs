@@ -0,0 +1,319 @@
+//! # Standardized keys
+//!
+//! Constants for the metadata keys the GGUF spec and llama.cpp standardize, so a typo in a key
+//! is a compile error rather than a lookup that quietly finds nothing. Each constant's doc
+//! gives the type of its value. The keys of an architecture start with its name, as in
+//! [`llama::CONTEXT_LENGTH`]; [`arch`] has the part after the name, for architectures without
+//! a module of their own. Each module lists its keys in `ALL`.
+//!
+//! ```
+//! use gguf::keys;
+//! assert_eq!(keys::tokenizer::TOKENS, "tokenizer.ggml.tokens");
+//! assert_eq!(keys::llama::CONTEXT_LENGTH, "llama.context_length");
+//! assert_eq!(format!("olmo{}", keys::arch::CONTEXT_LENGTH), "olmo.context_length");
+//! ```
+
+/// Constants each `concat!($prefix, ...)` with their docs, and `ALL` listing them
+macro_rules! keys {
+    ($prefix:literal; $($(#[$doc:meta])* $name:ident = $key:literal;)*) => {
+        $($(#[$doc])* pub const $name: &str = concat!($prefix, $key);)*
+
+        /// Every key of the module
+        pub const ALL: &[&str] = &[$($name),*];
+    };
+}
+
+/// A module of the keys of each architecture, after its name, and of the keys in braces only
+/// it has
+macro_rules! architectures {
+    ($(
+        $(#[$doc:meta])* $module:ident = $arch:literal
+        $({ $($(#[$extra_doc:meta])* $extra:ident = $extra_key:literal;)* })?;
+    )*) => {$(
+        $(#[$doc])*
+        pub mod $module {
+            keys! {
+                $arch;
+                /// u32: the number of tokens in the vocabulary
+                VOCAB_SIZE = ".vocab_size";
+                /// u32: the longest context the model was trained for, in tokens
+                CONTEXT_LENGTH = ".context_length";
+                /// u32: the width of the embeddings
+                EMBEDDING_LENGTH = ".embedding_length";
+                /// u32: the number of transformer blocks
+                BLOCK_COUNT = ".block_count";
+                /// u32, or an array of them by layer: the width of the feed forward layers
+                FEED_FORWARD_LENGTH = ".feed_forward_length";
+                /// u32: the number of experts of a mixture of experts
+                EXPERT_COUNT = ".expert_count";
+                /// u32: the number of experts used for each token
+                EXPERT_USED_COUNT = ".expert_used_count";
+                /// u32, or an array of them by layer: the number of attention heads
+                ATTENTION_HEAD_COUNT = ".attention.head_count";
+                /// u32, or an array of them by layer: the number of key and value heads
+                ATTENTION_HEAD_COUNT_KV = ".attention.head_count_kv";
+                /// u32: the width of each key head
+                ATTENTION_KEY_LENGTH = ".attention.key_length";
+                /// u32: the width of each value head
+                ATTENTION_VALUE_LENGTH = ".attention.value_length";
+                /// f32: the epsilon of layer normalization
+                ATTENTION_LAYER_NORM_EPSILON = ".attention.layer_norm_epsilon";
+                /// f32: the epsilon of RMS normalization
+                ATTENTION_LAYER_NORM_RMS_EPSILON = ".attention.layer_norm_rms_epsilon";
+                /// u32: the width of the window of sliding window attention
+                ATTENTION_SLIDING_WINDOW = ".attention.sliding_window";
+                /// u32: the number of dimensions RoPE rotates
+                ROPE_DIMENSION_COUNT = ".rope.dimension_count";
+                /// f32: the base frequency of RoPE
+                ROPE_FREQ_BASE = ".rope.freq_base";
+                /// string: how RoPE is scaled, `none`, `linear` or `yarn`
+                ROPE_SCALING_TYPE = ".rope.scaling.type";
+                /// f32: the factor RoPE is scaled by
+                ROPE_SCALING_FACTOR = ".rope.scaling.factor";
+                /// u32: the context length before scaling
+                ROPE_SCALING_ORIGINAL_CONTEXT_LENGTH = ".rope.scaling.original_context_length";
+                /// u32: how an embedding model pools, see [`crate::embedding::PoolingType`]
+                POOLING_TYPE = ".pooling_type";
+                /// bool: whether attention is causal, false for the encoders of embedding models
+                ATTENTION_CAUSAL = ".attention.causal";
+                /// array of strings: the labels of a classifier head, one per output
+                CLASSIFIER_OUTPUT_LABELS = ".classifier.output_labels";
+                $($($(#[$extra_doc])* $extra = $extra_key;)*)?
+            }
+        }
+    )*};
+}
+
+/// Keys of every file
+pub mod general {
+    keys! {
+        "general.";
+        /// string: the architecture, which the names of its keys start with
+        ARCHITECTURE = "architecture";
+        /// u32: the version of the quantization formats
+        QUANTIZATION_VERSION = "quantization_version";
+        /// u32: the alignment of the tensor data, 32 if unset
+        ALIGNMENT = "alignment";
+        /// u32: the type most tensors have, see [`crate::ggml::LlamaFileType`]
+        FILE_TYPE = "file_type";
+        /// string: the name of the model
+        NAME = "name";
+        /// string: the name of the family of models
+        BASENAME = "basename";
+        /// string: what the model was fine-tuned for
+        FINETUNE = "finetune";
+        /// string: who made the model
+        AUTHOR = "author";
+        /// string: the organization that made the model
+        ORGANIZATION = "organization";
+        /// string: the version of the model
+        VERSION = "version";
+        /// string: what the model is
+        DESCRIPTION = "description";
+        /// string: the license, an SPDX expression
+        LICENSE = "license";
+        /// string: where the model is described
+        URL = "url";
+        /// string: the size class, `7B` or `8x7B`
+        SIZE_LABEL = "size_label";
+        /// array of strings: tags for search
+        TAGS = "tags";
+        /// array of strings: the languages the model knows
+        LANGUAGES = "languages";
+        /// array of strings: the datasets the model was trained on
+        DATASETS = "datasets";
+    }
+}
+
+/// Keys of the tokenizer
+pub mod tokenizer {
+    keys! {
+        "tokenizer.";
+        /// string: the kind of tokenizer, `llama`, `gpt2` or `bert`
+        MODEL = "ggml.model";
+        /// string: how text is split before tokenizing
+        PRE = "ggml.pre";
+        /// array of strings: the vocabulary
+        TOKENS = "ggml.tokens";
+        /// array of f32: the score of each token
+        SCORES = "ggml.scores";
+        /// array of i32: the type of each token
+        TOKEN_TYPE = "ggml.token_type";
+        /// array of strings: the BPE merges
+        MERGES = "ggml.merges";
+        /// array of strings: tokens added to the vocabulary
+        ADDED_TOKENS = "ggml.added_tokens";
+        /// u32: the beginning of sequence token
+        BOS_TOKEN_ID = "ggml.bos_token_id";
+        /// u32: the end of sequence token
+        EOS_TOKEN_ID = "ggml.eos_token_id";
+        /// u32: the token of unknown text
+        UNKNOWN_TOKEN_ID = "ggml.unknown_token_id";
+        /// u32: the separator token
+        SEPARATOR_TOKEN_ID = "ggml.separator_token_id";
+        /// u32: the padding token
+        PADDING_TOKEN_ID = "ggml.padding_token_id";
+        /// u32: the classification token
+        CLS_TOKEN_ID = "ggml.cls_token_id";
+        /// u32: the mask token
+        MASK_TOKEN_ID = "ggml.mask_token_id";
+        /// u32: the end of turn token
+        EOT_TOKEN_ID = "ggml.eot_token_id";
+        /// u32: the end of message token
+        EOM_TOKEN_ID = "ggml.eom_token_id";
+        /// bool: whether a beginning of sequence token is added
+        ADD_BOS_TOKEN = "ggml.add_bos_token";
+        /// bool: whether an end of sequence token is added
+        ADD_EOS_TOKEN = "ggml.add_eos_token";
+        /// bool: whether a space is put before the text
+        ADD_SPACE_PREFIX = "ggml.add_space_prefix";
+        /// string: the Jinja chat template
+        CHAT_TEMPLATE = "chat_template";
+        /// string: the Hugging Face `tokenizer.json`
+        HUGGINGFACE_JSON = "huggingface.json";
+    }
+}
+
+/// Keys of the vision and audio encoders of a multimodal projector, an `mmproj` file, whose
+/// architecture is `clip`
+pub mod clip {
+    keys! {
+        "clip.";
+        /// bool: whether the file holds a text encoder
+        HAS_TEXT_ENCODER = "has_text_encoder";
+        /// bool: whether the file holds a vision encoder
+        HAS_VISION_ENCODER = "has_vision_encoder";
+        /// bool: whether the file holds an audio encoder
+        HAS_AUDIO_ENCODER = "has_audio_encoder";
+        /// bool: whether the projector is LLaVA's, from before `projector_type`
+        HAS_LLAVA_PROJECTOR = "has_llava_projector";
+        /// string: the kind of projector, `mlp`, `ldp`, `resampler`, `gemma3`, ...
+        PROJECTOR_TYPE = "projector_type";
+        /// u32: the width and height of the images the encoder takes, in pixels
+        VISION_IMAGE_SIZE = "vision.image_size";
+        /// u32: the width and height of a patch, in pixels
+        VISION_PATCH_SIZE = "vision.patch_size";
+        /// u32: the width of the encoder's embeddings
+        VISION_EMBEDDING_LENGTH = "vision.embedding_length";
+        /// u32: the width of the encoder's feed forward layers
+        VISION_FEED_FORWARD_LENGTH = "vision.feed_forward_length";
+        /// u32: the width of the projector's output, the language model's embedding length
+        VISION_PROJECTION_DIM = "vision.projection_dim";
+        /// u32: the number of encoder blocks
+        VISION_BLOCK_COUNT = "vision.block_count";
+        /// u32: the number of attention heads of the encoder
+        VISION_ATTENTION_HEAD_COUNT = "vision.attention.head_count";
+        /// f32: the epsilon of the encoder's layer normalization
+        VISION_ATTENTION_LAYER_NORM_EPSILON = "vision.attention.layer_norm_epsilon";
+        /// array of f32: the mean of each color channel images are normalized by
+        VISION_IMAGE_MEAN = "vision.image_mean";
+        /// array of f32: the standard deviation of each color channel
+        VISION_IMAGE_STD = "vision.image_std";
+    }
+}
+
+/// Keys of the shards of a split file
+pub mod split {
+    keys! {
+        "split.";
+        /// u16: which shard this is, counting from 0
+        NO = "no";
+        /// u16: how many shards there are
+        COUNT = "count";
+        /// i32: how many tensors all shards hold
+        TENSORS_COUNT = "tensors.count";
+    }
+}
+
+architectures! {
+    /// The keys of any architecture, without its name in front
+    arch = "";
+    /// Keys of Llama, and of Mistral and others converted as Llama
+    llama = "llama";
+    /// Keys of Qwen 2
+    qwen2 = "qwen2";
+    /// Keys of Qwen 3
+    qwen3 = "qwen3";
+    /// Keys of Gemma 2
+    gemma2 = "gemma2";
+    /// Keys of Gemma 3
+    gemma3 = "gemma3";
+    /// Keys of Phi-3
+    phi3 = "phi3";
+    /// Keys of Falcon
+    falcon = "falcon";
+    /// Keys of GPT-2
+    gpt2 = "gpt2";
+    /// Keys of BERT
+    bert = "bert";
+    /// Keys of Mamba, and of the state space layers of other architectures after their name
+    mamba = "mamba" {
+        /// u32: the width of the convolution, `d_conv`
+        SSM_CONV_KERNEL = ".ssm.conv_kernel";
+        /// u32: the width of the state of each channel, `d_state`
+        SSM_STATE_SIZE = ".ssm.state_size";
+        /// u32: the number of channels inside a layer, `d_inner`
+        SSM_INNER_SIZE = ".ssm.inner_size";
+        /// u32: the rank of the time step projection, `dt_rank`
+        SSM_TIME_STEP_RANK = ".ssm.time_step_rank";
+        /// u32: the number of groups sharing B and C, in Mamba 2
+        SSM_GROUP_COUNT = ".ssm.group_count";
+    };
+    /// Keys of RWKV 6, and of the other RWKV versions after their name
+    rwkv6 = "rwkv6" {
+        /// u32: the width of each head of the WKV state
+        WKV_HEAD_SIZE = ".wkv.head_size";
+        /// u32: the rank of the time mix projections
+        TIME_MIX_EXTRA_DIM = ".time_mix_extra_dim";
+        /// u32: the rank of the time decay projections
+        TIME_DECAY_EXTRA_DIM = ".time_decay_extra_dim";
+        /// u32: the layers after which activations are halved, 0 for none
+        RESCALE_EVERY_N_LAYERS = ".rescale_every_n_layers";
+        /// u32: the number of token shift states of each layer, 2 if unset
+        TOKEN_SHIFT_COUNT = ".token_shift_count";
+    };
+    /// Keys of T5, whose `BLOCK_COUNT` counts the encoder blocks
+    t5 = "t5" {
+        /// u32: the number of decoder blocks
+        DECODER_BLOCK_COUNT = ".decoder_block_count";
+        /// u32: the token the decoder starts from
+        DECODER_START_TOKEN_ID = ".decoder_start_token_id";
+    };
+    /// Keys of Whisper, whose `BLOCK_COUNT` counts the encoder blocks and `CONTEXT_LENGTH` the
+    /// tokens of the decoder
+    whisper = "whisper" {
+        /// u32: the number of decoder blocks
+        DECODER_BLOCK_COUNT = ".decoder_block_count";
+        /// u32: the number of mel frequency bins of the spectrogram the encoder takes
+        AUDIO_MEL_BINS = ".audio.mel_bins";
+        /// u32: the number of positions of the encoder, 1500 for 30 seconds of audio
+        AUDIO_CONTEXT_LENGTH = ".audio.context_length";
+    };
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::validate::key_schema;
+
+    #[test]
+    fn keys_are_registered() {
+        for key in general::ALL.iter().chain(tokenizer::ALL) {
+            assert!(key_schema(key, None).is_some(), "{key}");
+        }
+        for key in qwen2::ALL {
+            assert!(key_schema(key, Some("qwen2")).is_some(), "{key}");
+        }
+        assert_eq!(arch::ALL.len(), llama::ALL.len());
+        for key in mamba::ALL {
+            assert!(key_schema(key, Some("mamba")).is_some(), "{key}");
+        }
+        for key in rwkv6::ALL {
+            assert!(key_schema(key, Some("rwkv6")).is_some(), "{key}");
+        }
+        for key in whisper::ALL {
+            assert!(key_schema(key, Some("whisper")).is_some(), "{key}");
+        }
+        assert_eq!(split::TENSORS_COUNT, "split.tensors.count");
+    }
+}
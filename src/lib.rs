@@ -1,9 +1,134 @@
 //! # GGUF file parsing and struct definitions
+//!
+//! Without the default `std` feature the crate is `no_std` with `alloc`: headers are parsed
+//! from and written to buffers in memory, and the modules that need files are left out.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+/// Time the rest of the scope as a [`trace::Span`] with the `tracing` feature, its detail
+/// formatted as `format!` takes it
+macro_rules! span {
+    ($name:literal) => {
+        #[cfg(feature = "tracing")]
+        let _span = crate::trace::span($name, String::new);
+    };
+    ($name:literal, $($detail:tt)+) => {
+        #[cfg(feature = "tracing")]
+        let _span = crate::trace::span($name, || format!($($detail)+));
+    };
+}
+
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "std")]
+pub mod catalog;
+#[cfg(feature = "chat-template")]
+pub mod chat_template;
+#[cfg(feature = "json")]
+pub mod convert;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+mod digest;
+pub mod embedding;
+#[cfg(feature = "std")]
+pub mod estimate;
+#[cfg(feature = "std")]
+pub mod ggjt;
+pub mod ggml;
+#[cfg(feature = "std")]
+pub mod hub;
+pub mod keys;
+#[cfg(feature = "std")]
+pub mod loader;
+#[cfg(feature = "std")]
+pub mod lora;
+#[cfg(feature = "std")]
+pub mod manifest;
+pub mod mmap;
+#[cfg(feature = "std")]
+pub mod mmproj;
+#[cfg(feature = "napi")]
+pub mod napi;
+#[cfg(feature = "std")]
+pub mod npz;
+#[cfg(all(feature = "json", feature = "remote"))]
+pub mod oci;
+#[cfg(feature = "json")]
+pub mod ollama;
+#[cfg(feature = "json")]
+pub mod onnx;
+#[cfg(feature = "zstd")]
+pub mod package;
 pub mod parser;
-use parser::gguf_file;
-use std::fmt;
-extern crate serde;
+#[cfg(feature = "std")]
+pub mod quant;
+pub mod raw;
+#[cfg(feature = "std")]
+pub mod reader;
+pub mod recurrent;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "std")]
+pub mod schema;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(all(feature = "signing", feature = "experimental"))]
+pub mod signing;
+#[cfg(all(feature = "signing", not(feature = "experimental")))]
+compile_error!("the `signing` feature is experimental; enable `experimental` as well");
+#[cfg(feature = "std")]
+pub mod split;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod summary;
+#[cfg(test)]
+pub(crate) mod test_util;
+#[cfg(feature = "std")]
+pub mod tokenizer;
+#[cfg(feature = "tracing")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod validate;
+#[cfg(feature = "std")]
+pub mod visit;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod whisper;
+pub mod writer;
+use core::fmt;
+use parser::{dedup_metadata, parse_file};
+pub use parser::{AssumeUtf8, DuplicateKeys, ParseError, ParseOptions};
 use serde::ser::SerializeSeq;
+#[cfg(feature = "std")]
+use std::io::Read;
+#[cfg(feature = "std")]
+pub use validate::{scan, validate, validate_file, validate_reader};
+#[cfg(feature = "std")]
+pub use writer::repair;
+extern crate serde;
+
+/// What the std prelude gives, for the modules that build without std
+#[cfg(not(feature = "std"))]
+mod prelude {
+    pub(crate) use alloc::string::{String, ToString};
+    pub(crate) use alloc::vec::Vec;
+    pub(crate) use alloc::{format, vec};
+}
+#[cfg(not(feature = "std"))]
+use prelude::*;
+
+/// The strings of a string array by hash, for [`StringArray::push_interned`]
+#[cfg(feature = "std")]
+pub(crate) type Seen = std::collections::HashMap<u64, usize>;
+#[cfg(not(feature = "std"))]
+pub(crate) type Seen = alloc::collections::BTreeMap<u64, usize>;
 
 /// GGUF metadata value type
 #[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
@@ -60,13 +185,38 @@ impl TryFrom<u32> for GGUfMetadataValueType {
 }
 
 /// GGUF header
-#[derive(PartialEq, serde::Serialize)]
+#[derive(PartialEq, Debug, Clone, serde::Serialize)]
 pub struct GGUFHeader {
     pub version: u32,
     pub tensor_count: u64,
     pub metadata: Vec<GGUFMetadata>,
 }
 
+impl GGUFHeader {
+    /// Look up a metadata value by key
+    pub fn get(&self, key: &str) -> Option<&GGUFMetadataValue> {
+        self.metadata
+            .iter()
+            .find(|m| m.key == key)
+            .map(|m| &m.value)
+    }
+
+    /// The file type `general.file_type` names, `None` if unset or not an integer
+    pub fn file_type(&self) -> Option<ggml::LlamaFileType> {
+        let id = self.get(keys::general::FILE_TYPE)?.as_u64()?;
+        Some(ggml::LlamaFileType::from_id(u32::try_from(id).ok()?))
+    }
+
+    /// Replace the value of a key, or add the key at the end
+    pub fn set(&mut self, key: &str, value: GGUFMetadataValue) {
+        let entry = GGUFMetadata::new(key, value);
+        match self.metadata.iter_mut().find(|m| m.key == key) {
+            Some(existing) => *existing = entry,
+            None => self.metadata.push(entry),
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone, Copy, serde::Serialize)]
 pub enum GGMLType {
     F32 = 0,
@@ -83,10 +233,25 @@ pub enum GGMLType {
     Q5K = 13,
     Q6K = 14,
     Q8K = 15,
-    I8 = 16,
-    I16 = 17,
-    I32 = 18,
-    Count = 19,
+    IQ2XXS = 16,
+    IQ2XS = 17,
+    IQ3XXS = 18,
+    IQ1S = 19,
+    IQ4NL = 20,
+    IQ3S = 21,
+    IQ2S = 22,
+    IQ4XS = 23,
+    I8 = 24,
+    I16 = 25,
+    I32 = 26,
+    I64 = 27,
+    F64 = 28,
+    IQ1M = 29,
+    BF16 = 30,
+    TQ1_0 = 34,
+    TQ2_0 = 35,
+    MXFP4 = 39,
+    Count = 40,
 }
 
 impl TryFrom<u32> for GGMLType {
@@ -108,53 +273,229 @@ impl TryFrom<u32> for GGMLType {
             13 => GGMLType::Q5K,
             14 => GGMLType::Q6K,
             15 => GGMLType::Q8K,
-            16 => GGMLType::I8,
-            17 => GGMLType::I16,
-            18 => GGMLType::I32,
-            19 => GGMLType::Count,
+            16 => GGMLType::IQ2XXS,
+            17 => GGMLType::IQ2XS,
+            18 => GGMLType::IQ3XXS,
+            19 => GGMLType::IQ1S,
+            20 => GGMLType::IQ4NL,
+            21 => GGMLType::IQ3S,
+            22 => GGMLType::IQ2S,
+            23 => GGMLType::IQ4XS,
+            24 => GGMLType::I8,
+            25 => GGMLType::I16,
+            26 => GGMLType::I32,
+            27 => GGMLType::I64,
+            28 => GGMLType::F64,
+            29 => GGMLType::IQ1M,
+            30 => GGMLType::BF16,
+            34 => GGMLType::TQ1_0,
+            35 => GGMLType::TQ2_0,
+            39 => GGMLType::MXFP4,
+            40 => GGMLType::Count,
             _ => return Err(format!("invalid GGML type 0x{:x}", item)),
         })
     }
 }
 
-#[derive(PartialEq, Debug, serde::Serialize)]
+impl GGMLType {
+    /// Number of elements stored together in one block
+    pub fn block_size(&self) -> Option<u64> {
+        ggml::type_traits(*self as u32).map(|t| t.block_size)
+    }
+
+    /// Size in bytes of one block
+    pub fn type_size(&self) -> Option<u64> {
+        ggml::type_traits(*self as u32).map(|t| t.type_size)
+    }
+
+    /// Size in bytes of `elements` values, `None` if they do not fill whole blocks
+    pub fn size_of(&self, elements: u64) -> Option<u64> {
+        let block = self.block_size()?;
+        if !elements.is_multiple_of(block) {
+            return None;
+        }
+        (elements / block).checked_mul(self.type_size()?)
+    }
+}
+
+/// The dimensions of a tensor, innermost first; ggml has at most 4, which are stored inline
+pub type Dimensions = smallvec::SmallVec<[u64; 4]>;
+pub use smallvec::smallvec;
+
+#[derive(PartialEq, Debug, Clone, serde::Serialize)]
 pub struct GGUFTensorInfo {
     pub name: String,
-    pub dimensions: Vec<u64>,
+    pub dimensions: Dimensions,
     #[serde(rename = "type")]
     pub tensor_type: GGMLType,
     pub offset: u64,
 }
 
-#[derive(PartialEq, serde::Serialize)]
+impl GGUFTensorInfo {
+    /// Number of elements, the product of the dimensions, saturating at `u64::MAX`
+    pub fn element_count(&self) -> u64 {
+        self.dimensions
+            .iter()
+            .try_fold(1u64, |n, &d| n.checked_mul(d))
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Size of the tensor data in bytes, `None` if the shape does not fit the type's blocks
+    pub fn size_bytes(&self) -> Option<u64> {
+        // blocks run along the first dimension
+        let row = *self.dimensions.first().unwrap_or(&1);
+        if !row.is_multiple_of(self.tensor_type.block_size()?) {
+            return None;
+        }
+        self.tensor_type.size_of(self.element_count())
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, serde::Serialize)]
 pub struct GGUFFile {
     pub header: GGUFHeader,
     pub tensors: Vec<GGUFTensorInfo>,
 }
 
 impl GGUFFile {
+    /// Parse a file with the strict [`ParseOptions`], `None` if `buf` ends before the tensor infos
     pub fn read(buf: &[u8]) -> Result<Option<GGUFFile>, String> {
-        match gguf_file(buf) {
-            Ok((_, file)) => Ok(Some(file)),
-            Err(nom::Err::Incomplete(_)) => Ok(None),
-            Err(e) => Err(format!(
-                "Failed to parse GGUF file, please check for file integrity: {:?}",
-                e.map_input(|i| {
-                    // print only the next few bytes as hex
-                    let len = i.len().min(16);
-                    let mut s = String::new();
-                    for b in &i[..len] {
-                        s.push_str(&format!("0x{:02x} ", b));
-                    }
-                    s
-                })
-            )),
+        Ok(Self::read_with(buf, &ParseOptions::default())?)
+    }
+
+    /// Parse a file, tolerating the deviations `options` allows and enforcing its limits
+    pub fn read_with(buf: &[u8], options: &ParseOptions) -> Result<Option<GGUFFile>, ParseError> {
+        Ok(Self::read_with_len(buf, options)?.map(|(file, _)| file))
+    }
+
+    /// [`GGUFFile::read_with`], also giving the length of the header and tensor infos
+    pub(crate) fn read_with_len(
+        buf: &[u8],
+        options: &ParseOptions,
+    ) -> Result<Option<(GGUFFile, usize)>, ParseError> {
+        Self::parse_in(buf, options, None)
+    }
+
+    /// [`GGUFFile::read_with_len`], indexing string arrays rather than copying them if `buf`
+    /// is `mapping`
+    pub(crate) fn parse_in(
+        buf: &[u8],
+        options: &ParseOptions,
+        mapping: Option<&alloc::sync::Arc<mmap::Mmap>>,
+    ) -> Result<Option<(GGUFFile, usize)>, ParseError> {
+        span!("parse", "{} bytes", buf.len());
+        if let Some(version) = buf.get(4..8) {
+            let version = u32::from_le_bytes(version.try_into().unwrap_or_default());
+            if buf.starts_with(b"GGUF") && !options.accepts_version(version) {
+                return Err(ParseError::UnsupportedVersion { version });
+            }
+        }
+        let limit = options
+            .max_header_bytes
+            .and_then(|max| usize::try_from(max).ok())
+            .filter(|&max| max < buf.len());
+        let input = limit.map_or(buf, |max| &buf[..max]);
+        match parse_file(input, options, mapping)? {
+            Some((mut file, len)) => {
+                dedup_metadata(&mut file.header.metadata, options.duplicate_keys)
+                    .map_err(ParseError::Invalid)?;
+                Ok(Some((file, len)))
+            }
+            None => match limit {
+                Some(max) => Err(ParseError::HeaderTooLarge { limit: max as u64 }),
+                None => Ok(None),
+            },
+        }
+    }
+
+    /// Read the header and tensor infos from the start of a file, returning them with the
+    /// offset of the tensor data
+    #[cfg(feature = "std")]
+    pub fn read_from(
+        reader: &mut impl Read,
+        options: &ParseOptions,
+    ) -> Result<(GGUFFile, u64), ParseError> {
+        let (file, len) = Self::read_prefix(reader, options)?;
+        let data_start = (len as u64).next_multiple_of(file.alignment());
+        Ok((file, data_start))
+    }
+
+    /// [`GGUFFile::read_from`], giving the length of the header and tensor infos instead
+    #[cfg(feature = "std")]
+    pub(crate) fn read_prefix(
+        reader: &mut impl Read,
+        options: &ParseOptions,
+    ) -> Result<(GGUFFile, usize), ParseError> {
+        Self::read_buffered(reader, options).map(|(file, len, _)| (file, len))
+    }
+
+    /// [`GGUFFile::read_prefix`], also giving the bytes read, which run past the header
+    #[cfg(feature = "std")]
+    pub(crate) fn read_buffered(
+        reader: &mut impl Read,
+        options: &ParseOptions,
+    ) -> Result<(GGUFFile, usize, Vec<u8>), ParseError> {
+        span!("read_header");
+        let limit = options
+            .max_header_bytes
+            .unwrap_or(parser::STREAM_HEADER_BYTES);
+        let mut buf = Vec::new();
+        // at least the magic, version and counts
+        let mut declared = 24;
+        loop {
+            if declared > limit {
+                return Err(ParseError::HeaderTooLarge { limit });
+            }
+            // headers can hold tens of megabytes of vocabulary, read in growing chunks, never
+            // past the limit
+            let len = buf.len() as u64;
+            let chunk = (declared - len).max(len.clamp(1 << 16, 1 << 26));
+            (&mut *reader)
+                .take(chunk.min(limit - len))
+                .read_to_end(&mut buf)
+                .map_err(|e| ParseError::Io(e.to_string()))?;
+            if let Some((file, len)) = Self::read_with_len(&buf, options)? {
+                return Ok((file, len, buf));
+            }
+            if (buf.len() as u64) < declared {
+                return Err(ParseError::Invalid(format!(
+                    "the file ends at byte {} inside the header, which runs to at least byte {declared}",
+                    buf.len()
+                )));
+            }
+            declared = parser::declared_len(&buf, options)?;
         }
     }
+
+    /// The alignment of the tensor data, `general.alignment` or 32 if unset
+    pub fn alignment(&self) -> u64 {
+        self.header
+            .get(keys::general::ALIGNMENT)
+            .and_then(|v| v.as_u64())
+            .filter(|a| a.is_power_of_two())
+            .unwrap_or(32)
+    }
+}
+
+/// Parse untrusted input with every limit `options` leaves unset taken from
+/// [`ParseOptions::hardened`]
+///
+/// This never panics, recurses no deeper than the array depth limit and allocates at most a
+/// small multiple of the header byte limit, whatever the input, so it is safe to fuzz and to
+/// run on uploads.
+pub fn parse_hardened(buf: &[u8], options: &ParseOptions) -> Result<Option<GGUFFile>, ParseError> {
+    let defaults = ParseOptions::hardened();
+    let options = ParseOptions {
+        max_header_bytes: options.max_header_bytes.or(defaults.max_header_bytes),
+        max_array_len: options.max_array_len.or(defaults.max_array_len),
+        max_array_depth: options.max_array_depth.or(defaults.max_array_depth),
+        ..options.clone()
+    };
+    GGUFFile::read_with(buf, &options)
 }
 
 /// GGUF metadata
-#[derive(PartialEq, serde::Serialize)]
+#[derive(PartialEq, Debug, Clone, serde::Serialize)]
 pub struct GGUFMetadata {
     pub key: String,
     #[serde(rename = "type")]
@@ -162,8 +503,19 @@ pub struct GGUFMetadata {
     pub value: GGUFMetadataValue,
 }
 
+impl GGUFMetadata {
+    /// Create a metadata entry, deriving the type from the value
+    pub fn new(key: impl Into<String>, value: GGUFMetadataValue) -> Self {
+        GGUFMetadata {
+            key: key.into(),
+            value_type: value.value_type(),
+            value,
+        }
+    }
+}
+
 /// GGUF metadata value
-#[derive(PartialEq, serde::Serialize)]
+#[derive(PartialEq, Clone, serde::Serialize)]
 #[serde(untagged)]
 pub enum GGUFMetadataValue {
     Uint8(u8),
@@ -181,6 +533,90 @@ pub enum GGUFMetadataValue {
     Array(GGUFMetadataArrayValue),
 }
 
+impl GGUFMetadataValue {
+    /// The type tag of the value
+    pub fn value_type(&self) -> GGUfMetadataValueType {
+        match self {
+            Self::Uint8(_) => GGUfMetadataValueType::Uint8,
+            Self::Int8(_) => GGUfMetadataValueType::Int8,
+            Self::Uint16(_) => GGUfMetadataValueType::Uint16,
+            Self::Int16(_) => GGUfMetadataValueType::Int16,
+            Self::Uint32(_) => GGUfMetadataValueType::Uint32,
+            Self::Int32(_) => GGUfMetadataValueType::Int32,
+            Self::Float32(_) => GGUfMetadataValueType::Float32,
+            Self::Uint64(_) => GGUfMetadataValueType::Uint64,
+            Self::Int64(_) => GGUfMetadataValueType::Int64,
+            Self::Float64(_) => GGUfMetadataValueType::Float64,
+            Self::Bool(_) => GGUfMetadataValueType::Bool,
+            Self::String(_) => GGUfMetadataValueType::String,
+            Self::Array(_) => GGUfMetadataValueType::Array,
+        }
+    }
+
+    /// The value as a string slice, if it is a string
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The value as an unsigned integer, if it is a non-negative integer
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Self::Uint8(v) => Some(v as u64),
+            Self::Uint16(v) => Some(v as u64),
+            Self::Uint32(v) => Some(v as u64),
+            Self::Uint64(v) => Some(v),
+            Self::Int8(v) => u64::try_from(v).ok(),
+            Self::Int16(v) => u64::try_from(v).ok(),
+            Self::Int32(v) => u64::try_from(v).ok(),
+            Self::Int64(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    /// The value as a signed integer, if it is an integer that fits
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Self::Uint8(v) => Some(v as i64),
+            Self::Uint16(v) => Some(v as i64),
+            Self::Uint32(v) => Some(v as i64),
+            Self::Uint64(v) => i64::try_from(v).ok(),
+            Self::Int8(v) => Some(v as i64),
+            Self::Int16(v) => Some(v as i64),
+            Self::Int32(v) => Some(v as i64),
+            Self::Int64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The value as a float, if it is numeric
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Self::Float32(v) => Some(v as f64),
+            Self::Float64(v) => Some(v),
+            _ => self.as_i64().map(|v| v as f64),
+        }
+    }
+
+    /// The value as a boolean, if it is a boolean
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Self::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The value as an array, if it is an array
+    pub fn as_array(&self) -> Option<&GGUFMetadataArrayValue> {
+        match self {
+            Self::Array(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Debug for GGUFMetadataValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -199,8 +635,8 @@ impl fmt::Debug for GGUFMetadataValue {
             Self::Array(v) => {
                 // write up to 3 values
                 let len = v.value.len().min(3);
-                for i in 0..len {
-                    write!(f, "{:?}", v.value[i])?;
+                for (i, value) in v.value.iter().take(len).enumerate() {
+                    write!(f, "{:?}", value)?;
                     if i < len - 1 {
                         write!(f, ", ")?;
                     }
@@ -214,25 +650,365 @@ impl fmt::Debug for GGUFMetadataValue {
     }
 }
 
-#[derive(PartialEq, Debug, serde::Serialize)]
+#[derive(PartialEq, Debug, Clone, serde::Serialize)]
 pub struct GGUFMetadataArrayValue {
     #[serde(rename = "type")]
     pub value_type: GGUfMetadataValueType,
     pub len: u64,
     #[serde(serialize_with = "serialize_array")]
-    pub value: Vec<GGUFMetadataValue>,
+    pub value: ArrayValues,
+}
+
+impl GGUFMetadataArrayValue {
+    /// Create an array of elements of the given type
+    pub fn new(value_type: GGUfMetadataValueType, value: Vec<GGUFMetadataValue>) -> Self {
+        let mut packed = ArrayValues::with_capacity(value_type, value.len());
+        value.into_iter().for_each(|v| packed.push(v));
+        Self::packed(value_type, packed)
+    }
+
+    /// Create an array of elements already packed
+    pub fn packed(value_type: GGUfMetadataValueType, value: ArrayValues) -> Self {
+        GGUFMetadataArrayValue {
+            value_type,
+            len: value.len() as u64,
+            value,
+        }
+    }
+}
+
+/// The elements of an array, a vector of the element type rather than of
+/// [`GGUFMetadataValue`]s, so a vocabulary of 128k tokens costs the text and an offset a token
+#[derive(Debug, Clone)]
+pub enum ArrayValues {
+    Uint8(Vec<u8>),
+    Int8(Vec<i8>),
+    Uint16(Vec<u16>),
+    Int16(Vec<i16>),
+    Uint32(Vec<u32>),
+    Int32(Vec<i32>),
+    Float32(Vec<f32>),
+    Uint64(Vec<u64>),
+    Int64(Vec<i64>),
+    Float64(Vec<f64>),
+    Bool(Vec<bool>),
+    String(StringArray),
+    /// Strings left in a memory mapping, as [`mmap::MappedFile`] reads them.
+    Mapped(mmap::MappedStrings),
+    /// Elements left as they are stored, as [`ParseOptions::lazy_arrays`] has the parser keep
+    /// large arrays.
+    Raw(raw::RawArray),
+    /// Arrays of arrays, and elements of more than one type.
+    Values(Vec<GGUFMetadataValue>),
+}
+
+impl ArrayValues {
+    /// An empty array for elements of `value_type`, with room for `capacity`
+    pub fn with_capacity(value_type: GGUfMetadataValueType, capacity: usize) -> Self {
+        use GGUfMetadataValueType as T;
+        match value_type {
+            T::Uint8 => Self::Uint8(Vec::with_capacity(capacity)),
+            T::Int8 => Self::Int8(Vec::with_capacity(capacity)),
+            T::Uint16 => Self::Uint16(Vec::with_capacity(capacity)),
+            T::Int16 => Self::Int16(Vec::with_capacity(capacity)),
+            T::Uint32 => Self::Uint32(Vec::with_capacity(capacity)),
+            T::Int32 => Self::Int32(Vec::with_capacity(capacity)),
+            T::Float32 => Self::Float32(Vec::with_capacity(capacity)),
+            T::Uint64 => Self::Uint64(Vec::with_capacity(capacity)),
+            T::Int64 => Self::Int64(Vec::with_capacity(capacity)),
+            T::Float64 => Self::Float64(Vec::with_capacity(capacity)),
+            T::Bool => Self::Bool(Vec::with_capacity(capacity)),
+            T::String => Self::String(StringArray::with_capacity(capacity)),
+            T::Array => Self::Values(Vec::with_capacity(capacity)),
+        }
+    }
+
+    /// Append a value, unpacking the array into [`ArrayValues::Values`] if it is of another type
+    pub fn push(&mut self, value: GGUFMetadataValue) {
+        use GGUFMetadataValue as V;
+        match (&mut *self, value) {
+            (Self::Uint8(a), V::Uint8(v)) => a.push(v),
+            (Self::Int8(a), V::Int8(v)) => a.push(v),
+            (Self::Uint16(a), V::Uint16(v)) => a.push(v),
+            (Self::Int16(a), V::Int16(v)) => a.push(v),
+            (Self::Uint32(a), V::Uint32(v)) => a.push(v),
+            (Self::Int32(a), V::Int32(v)) => a.push(v),
+            (Self::Float32(a), V::Float32(v)) => a.push(v),
+            (Self::Uint64(a), V::Uint64(v)) => a.push(v),
+            (Self::Int64(a), V::Int64(v)) => a.push(v),
+            (Self::Float64(a), V::Float64(v)) => a.push(v),
+            (Self::Bool(a), V::Bool(v)) => a.push(v),
+            (Self::String(a), V::String(v)) if a.has_room(&v) => a.push(&v),
+            (Self::Values(a), v) => a.push(v),
+            (_, v) => {
+                *self = Self::Values(self.iter().collect());
+                self.push(v);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Uint8(a) => a.len(),
+            Self::Int8(a) => a.len(),
+            Self::Uint16(a) => a.len(),
+            Self::Int16(a) => a.len(),
+            Self::Uint32(a) => a.len(),
+            Self::Int32(a) => a.len(),
+            Self::Float32(a) => a.len(),
+            Self::Uint64(a) => a.len(),
+            Self::Int64(a) => a.len(),
+            Self::Float64(a) => a.len(),
+            Self::Bool(a) => a.len(),
+            Self::String(a) => a.len(),
+            Self::Mapped(a) => a.len(),
+            Self::Raw(a) => a.len() as usize,
+            Self::Values(a) => a.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The element at `index` as a value of its own
+    pub fn get(&self, index: usize) -> Option<GGUFMetadataValue> {
+        use GGUFMetadataValue as V;
+        Some(match self {
+            Self::Uint8(a) => V::Uint8(*a.get(index)?),
+            Self::Int8(a) => V::Int8(*a.get(index)?),
+            Self::Uint16(a) => V::Uint16(*a.get(index)?),
+            Self::Int16(a) => V::Int16(*a.get(index)?),
+            Self::Uint32(a) => V::Uint32(*a.get(index)?),
+            Self::Int32(a) => V::Int32(*a.get(index)?),
+            Self::Float32(a) => V::Float32(*a.get(index)?),
+            Self::Uint64(a) => V::Uint64(*a.get(index)?),
+            Self::Int64(a) => V::Int64(*a.get(index)?),
+            Self::Float64(a) => V::Float64(*a.get(index)?),
+            Self::Bool(a) => V::Bool(*a.get(index)?),
+            Self::String(a) => V::String(a.get(index)?.to_string()),
+            Self::Mapped(a) => V::String(String::from_utf8_lossy(a.get_bytes(index)?).into_owned()),
+            Self::Raw(a) => a.get(index)?,
+            Self::Values(a) => a.get(index)?.clone(),
+        })
+    }
+
+    pub fn first(&self) -> Option<GGUFMetadataValue> {
+        self.get(0)
+    }
+
+    /// The elements as values of their own, which copies strings; see [`ArrayValues::strs`]
+    pub fn iter(&self) -> impl Iterator<Item = GGUFMetadataValue> + '_ {
+        // a raw array walks its strings once rather than once for each
+        let (raw, len) = match self {
+            Self::Raw(a) => (Some(a), 0),
+            _ => (None, self.len()),
+        };
+        raw.into_iter()
+            .flat_map(raw::RawArray::iter)
+            .chain((0..len).filter_map(|i| self.get(i)))
+    }
+
+    /// The string elements, borrowed
+    pub fn strs(&self) -> impl Iterator<Item = &str> {
+        let (packed, mapped, raw, values) = match self {
+            Self::String(a) => (Some(a), None, None, None),
+            Self::Mapped(a) => (None, Some(a), None, None),
+            Self::Raw(a) => (None, None, Some(a), None),
+            Self::Values(a) => (None, None, None, Some(a)),
+            _ => (None, None, None, None),
+        };
+        let values = values.into_iter().flatten().filter_map(|v| v.as_str());
+        let mapped = mapped.into_iter().flat_map(mmap::MappedStrings::iter);
+        let raw = raw.into_iter().flat_map(raw::RawArray::strs);
+        packed
+            .into_iter()
+            .flat_map(StringArray::iter)
+            .chain(mapped)
+            .chain(raw)
+            .chain(values)
+    }
+
+    /// The element at `index`, borrowed, if it is a string
+    pub fn get_str(&self, index: usize) -> Option<&str> {
+        match self {
+            Self::String(a) => a.get(index),
+            Self::Mapped(a) => a.get(index),
+            Self::Raw(a) => a.get_str(index),
+            Self::Values(a) => a.get(index)?.as_str(),
+            _ => None,
+        }
+    }
+
+    /// Decode a [`ArrayValues::Raw`] array in place, so the accessors handing out slices such
+    /// as [`ArrayValues::as_u32s`] see its elements; other arrays are left as they are
+    pub fn decode(&mut self) -> Result<(), String> {
+        if let Self::Raw(a) = self {
+            *self = a.decode()?;
+        }
+        Ok(())
+    }
+
+    pub fn as_strings(&self) -> Option<&StringArray> {
+        match self {
+            Self::String(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32s(&self) -> Option<&[u32]> {
+        match self {
+            Self::Uint32(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32s(&self) -> Option<&[i32]> {
+        match self {
+            Self::Int32(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32s(&self) -> Option<&[f32]> {
+        match self {
+            Self::Float32(a) => Some(a),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for ArrayValues {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl serde::Serialize for ArrayValues {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::String(a) => s.collect_seq(a.iter()),
+            _ => s.collect_seq(self.iter()),
+        }
+    }
+}
+
+impl From<Vec<String>> for ArrayValues {
+    fn from(strings: Vec<String>) -> Self {
+        Self::String(strings.iter().map(String::as_str).collect())
+    }
+}
+
+/// Strings stored end to end in one buffer, each a span of it
+///
+/// One array holds at most 4 GiB of text. An array built with [`StringArray::interned`], or
+/// parsed with [`ParseOptions::intern_strings`], stores equal strings once, their spans shared.
+#[derive(Debug, Clone, Default)]
+pub struct StringArray {
+    text: String,
+    spans: Vec<(u32, u32)>,
+}
+
+impl StringArray {
+    pub fn with_capacity(capacity: usize) -> Self {
+        StringArray {
+            text: String::new(),
+            spans: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Strings storing each distinct string once
+    pub fn interned<'a>(strings: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut array = StringArray::default();
+        let mut seen = Seen::new();
+        strings
+            .into_iter()
+            .for_each(|s| array.push_interned(s, &mut seen));
+        array
+    }
+
+    /// Append a string, panicking if the text would pass 4 GiB
+    pub fn push(&mut self, s: &str) {
+        assert!(
+            self.has_room(s),
+            "a string array holds at most 4 GiB of text"
+        );
+        let start = self.text.len() as u32;
+        self.text.push_str(s);
+        self.spans.push((start, self.text.len() as u32));
+    }
+
+    /// Append a string, reusing the span of an equal one `seen`, which maps the hashes of the
+    /// strings pushed so to their index
+    pub(crate) fn push_interned(&mut self, s: &str, seen: &mut Seen) {
+        // FNV-1a, which needs no std and is quick on strings as short as tokens
+        let hash = s.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+        });
+        match seen.get(&hash) {
+            Some(&index) if self.get(index) == Some(s) => self.spans.push(self.spans[index]),
+            found => {
+                if found.is_none() {
+                    seen.insert(hash, self.spans.len());
+                }
+                self.push(s);
+            }
+        }
+    }
+
+    pub(crate) fn has_room(&self, s: &str) -> bool {
+        self.text.len() + s.len() <= u32::MAX as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// The bytes of text stored, less than the strings' total length when they are interned
+    pub fn text_len(&self) -> usize {
+        self.text.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        let (start, end) = *self.spans.get(index)?;
+        Some(&self.text[start as usize..end as usize])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.spans
+            .iter()
+            .map(|&(start, end)| &self.text[start as usize..end as usize])
+    }
+}
+
+impl PartialEq for StringArray {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<'a> FromIterator<&'a str> for StringArray {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> Self {
+        let mut array = StringArray::default();
+        iter.into_iter().for_each(|s| array.push(s));
+        array
+    }
 }
 
 /// serialize_array
-fn serialize_array<S>(v: &Vec<GGUFMetadataValue>, s: S) -> Result<S::Ok, S::Error>
+fn serialize_array<S>(v: &ArrayValues, s: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
 {
     let len = v.len().min(3);
     let has_more = v.len() > 3;
     let mut seq = s.serialize_seq(Some(if has_more { 4 } else { len }))?;
-    for e in &v[..len] {
-        seq.serialize_element(e)?;
+    for e in v.iter().take(len) {
+        seq.serialize_element(&e)?;
     }
     if has_more {
         let ellipse = format!("... and {} more items", v.len() - 3);
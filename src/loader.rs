@@ -0,0 +1,171 @@
+//! # Streaming model loading
+//!
+//! [`load`] reads a file front to back and hands each tensor to a [`ModelLoader`] as a reader
+//! over exactly its bytes, so an inference engine can copy weights straight into device
+//! buffers, or dequantize them on the way, without the file or any whole tensor ever being
+//! held in memory. Any [`Read`] works, a file as well as an HTTP body.
+use std::io::{self, Cursor, Read};
+
+use crate::{GGUFFile, GGUFHeader, GGUFTensorInfo, ParseOptions};
+
+/// Callbacks for [`load`]
+pub trait ModelLoader {
+    /// Called once with the metadata and every tensor info, before any tensor data, e.g. to
+    /// allocate buffers
+    fn visit_metadata(
+        &mut self,
+        header: &GGUFHeader,
+        tensors: &[GGUFTensorInfo],
+    ) -> Result<(), String> {
+        let _ = (header, tensors);
+        Ok(())
+    }
+
+    /// Called for each tensor in the order of its data, with a reader over its
+    /// [`size_bytes`](GGUFTensorInfo::size_bytes) bytes; whatever is left unread is skipped
+    fn visit_tensor(&mut self, info: &GGUFTensorInfo, reader: &mut dyn Read) -> Result<(), String>;
+}
+
+/// Stream a file from `reader` into `loader`, returning its header and tensor infos
+///
+/// Tensors are visited in the order of their offsets, which the reader gets to without
+/// seeking. Fails if tensors overlap, or if the input ends before the data of a tensor does.
+pub fn load(
+    mut reader: impl Read,
+    options: &ParseOptions,
+    loader: &mut impl ModelLoader,
+) -> Result<GGUFFile, String> {
+    span!("load");
+    let (file, len, buf) =
+        GGUFFile::read_buffered(&mut reader, options).map_err(|e| e.to_string())?;
+    loader.visit_metadata(&file.header, &file.tensors)?;
+
+    // the header was read in chunks that run into the data, so go through them again
+    let data_start = (len as u64).next_multiple_of(file.alignment());
+    let mut input = Cursor::new(buf).chain(reader);
+    skip(&mut input, data_start, "the tensor data")?;
+
+    let mut order: Vec<&GGUFTensorInfo> = file.tensors.iter().collect();
+    order.sort_by_key(|t| t.offset);
+    let mut position = 0;
+    for tensor in order {
+        span!("tensor", "{}", tensor.name);
+        let size = tensor.size_bytes().ok_or_else(|| {
+            format!(
+                "tensor {} has no whole number of {:?} blocks",
+                tensor.name, tensor.tensor_type
+            )
+        })?;
+        if tensor.offset < position {
+            return Err(format!(
+                "tensor {} overlaps the tensor before it",
+                tensor.name
+            ));
+        }
+        skip(&mut input, tensor.offset - position, &tensor.name)?;
+        let mut data = (&mut input).take(size);
+        loader.visit_tensor(tensor, &mut data)?;
+        io::copy(&mut data, &mut io::sink()).map_err(|e| e.to_string())?;
+        if data.limit() > 0 {
+            return Err(format!(
+                "the file ends inside the data of tensor {}",
+                tensor.name
+            ));
+        }
+        position = tensor.offset + size;
+    }
+    Ok(file)
+}
+
+/// Read past `n` bytes of `input`
+fn skip(input: &mut impl Read, n: u64, what: &str) -> Result<(), String> {
+    let skipped = io::copy(&mut input.take(n), &mut io::sink()).map_err(|e| e.to_string())?;
+    if skipped < n {
+        return Err(format!("the file ends before {what}"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path, tensor};
+    use crate::writer::write_file;
+    use crate::{GGMLType, GGUFMetadataValue};
+
+    #[derive(Default)]
+    struct Collect {
+        keys: Vec<String>,
+        tensors: Vec<(String, Vec<u8>)>,
+    }
+
+    impl ModelLoader for Collect {
+        fn visit_metadata(
+            &mut self,
+            header: &GGUFHeader,
+            tensors: &[GGUFTensorInfo],
+        ) -> Result<(), String> {
+            self.keys = header.metadata.iter().map(|m| m.key.clone()).collect();
+            assert_eq!(tensors.len(), 2);
+            Ok(())
+        }
+
+        fn visit_tensor(
+            &mut self,
+            info: &GGUFTensorInfo,
+            reader: &mut dyn Read,
+        ) -> Result<(), String> {
+            // read a byte only, leaving the rest for load to skip
+            let mut data = vec![0; 1];
+            reader.read_exact(&mut data).map_err(|e| e.to_string())?;
+            self.tensors.push((info.name.clone(), data));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn streams_tensors() {
+        // a header longer than one chunk of read_from
+        let header = header(&[(
+            "general.name",
+            GGUFMetadataValue::String("x".repeat(100_000)),
+        )]);
+        let file = file(
+            header,
+            vec![
+                tensor("a", &[40], GGMLType::F32, 0),
+                tensor("b", &[40], GGMLType::F32, 0),
+            ],
+        );
+        let path = temp_path("loader.gguf");
+        write_file(&path, &file, |t, out| {
+            let fill = t.name.as_bytes()[0];
+            Ok(out.write_all(&[fill; 160])?)
+        })
+        .unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // put tensor b's info first, keeping the data order
+        let (mut parsed, len) =
+            GGUFFile::read_prefix(&mut bytes.as_slice(), &ParseOptions::default()).unwrap();
+        parsed.tensors.swap(0, 1);
+        let mut header = Vec::new();
+        crate::writer::write_header(&mut header, &parsed).unwrap();
+        assert_eq!(header.len(), len);
+        bytes.splice(..len, header);
+
+        let mut collect = Collect::default();
+        let loaded = load(bytes.as_slice(), &ParseOptions::default(), &mut collect).unwrap();
+        assert_eq!(loaded.tensors[0].name, "b");
+        assert_eq!(collect.keys, ["general.name"]);
+        assert_eq!(
+            collect.tensors,
+            [("a".to_string(), vec![b'a']), ("b".to_string(), vec![b'b'])]
+        );
+
+        let truncated = &bytes[..bytes.len() - 1];
+        let error = load(truncated, &ParseOptions::default(), &mut Collect::default()).unwrap_err();
+        assert_eq!(error, "the file ends inside the data of tensor b");
+    }
+}
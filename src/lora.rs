@@ -0,0 +1,280 @@
+//! # LoRA adapters
+//!
+//! llama.cpp stores a LoRA adapter as a GGUF file of `general.type` `adapter`, with
+//! `adapter.type` `lora`, the `adapter.lora.alpha` it was trained with and, for each weight it
+//! adapts, a pair of low-rank tensors named after the weight with `.lora_a` and `.lora_b`
+//! appended. [`LoraAdapter`] reads that layout and [`merge_lora`] bakes the deltas into a copy
+//! of the base model, so the result runs without `--lora`.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::manifest::read_header;
+use crate::quant::{dequantize, quantize};
+use crate::writer::{copy_data, write_file, WriteError};
+use crate::{GGUFFile, GGUFTensorInfo};
+
+/// The low-rank pair adapting one weight of the base model
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoraPair {
+    /// The name of the adapted weight, e.g. `blk.0.attn_q.weight`.
+    pub target: String,
+    /// The down projection, `[n_in, rank]` in ggml's order.
+    pub a: GGUFTensorInfo,
+    /// The up projection, `[rank, n_out]` in ggml's order.
+    pub b: GGUFTensorInfo,
+}
+
+impl LoraPair {
+    pub fn rank(&self) -> u64 {
+        self.a.dimensions.get(1).copied().unwrap_or(1)
+    }
+}
+
+/// The adapter a GGUF file holds
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoraAdapter {
+    /// The `general.architecture` of the models it applies to.
+    pub architecture: Option<String>,
+    /// `adapter.lora.alpha`, 0 when unset, which llama.cpp takes as no scaling by rank.
+    pub alpha: f32,
+    /// The pairs in the order of their `lora_a` tensors.
+    pub pairs: Vec<LoraPair>,
+}
+
+impl LoraAdapter {
+    /// Whether `file` declares itself a LoRA adapter
+    pub fn is_adapter(file: &GGUFFile) -> bool {
+        let get = |key| file.header.get(key).and_then(|v| v.as_str());
+        get("general.type") == Some("adapter") && get("adapter.type").is_none_or(|t| t == "lora")
+    }
+
+    /// Read the adapter of `file`, checking every tensor belongs to a pair of matching rank
+    pub fn from_file(file: &GGUFFile) -> Result<Self, String> {
+        if !Self::is_adapter(file) {
+            return Err("the file is not a LoRA adapter, general.type is not adapter".to_string());
+        }
+        let mut halves: HashMap<&str, [Option<&GGUFTensorInfo>; 2]> = HashMap::new();
+        let mut order = Vec::new();
+        for tensor in &file.tensors {
+            let (target, half) = match tensor.name.rsplit_once('.') {
+                Some((target, "lora_a")) => (target, 0),
+                Some((target, "lora_b")) => (target, 1),
+                _ => return Err(format!("tensor {} is not a lora_a or lora_b", tensor.name)),
+            };
+            let entry = halves.entry(target).or_insert_with(|| {
+                order.push(target);
+                [None, None]
+            });
+            entry[half] = Some(tensor);
+        }
+        let pairs = order
+            .into_iter()
+            .map(|target| match halves[target] {
+                [Some(a), Some(b)] => {
+                    let pair = LoraPair {
+                        target: target.to_string(),
+                        a: a.clone(),
+                        b: b.clone(),
+                    };
+                    match (a.dimensions.len(), b.dimensions.first()) {
+                        (2, Some(&rank)) if b.dimensions.len() == 2 && rank == pair.rank() => {
+                            Ok(pair)
+                        }
+                        _ => Err(format!(
+                            "{target}: lora_a {:?} and lora_b {:?} are not matrices of one rank",
+                            a.dimensions, b.dimensions
+                        )),
+                    }
+                }
+                _ => Err(format!("{target} has only one of lora_a and lora_b")),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(LoraAdapter {
+            architecture: file
+                .header
+                .get("general.architecture")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            alpha: file
+                .header
+                .get("adapter.lora.alpha")
+                .and_then(|v| v.as_f64())
+                .unwrap_or_default() as f32,
+            pairs,
+        })
+    }
+
+    /// The factor the product of a pair is multiplied by, as llama.cpp applies it at `scale`
+    pub fn pair_scale(&self, pair: &LoraPair, scale: f32) -> f32 {
+        match self.alpha {
+            0.0 => scale,
+            alpha => scale * alpha / pair.rank() as f32,
+        }
+    }
+}
+
+fn read_values(input: &mut File, start: u64, tensor: &GGUFTensorInfo) -> Result<Vec<f32>, String> {
+    let size = tensor.size_bytes().unwrap_or_default();
+    let mut data = vec![0; size as usize];
+    input
+        .seek(SeekFrom::Start(start + tensor.offset))
+        .and_then(|_| input.read_exact(&mut data))
+        .map_err(|e| format!("reading tensor {}: {e}", tensor.name))?;
+    dequantize(tensor.tensor_type, &data)
+}
+
+/// Write `base` with the deltas of `adapter` added at `scale` to `out`, returning the names
+/// of the weights changed
+///
+/// Each adapted weight becomes `W + s·B·A`, `s` being [`LoraAdapter::pair_scale`], and is
+/// encoded back in its own type, so quantized weights lose a little more precision; the other
+/// tensors and the metadata are copied.
+pub fn merge_lora(
+    base: impl AsRef<Path>,
+    adapter: impl AsRef<Path>,
+    scale: f32,
+    out: impl AsRef<Path>,
+) -> Result<Vec<String>, WriteError> {
+    let mut base_input = File::open(base)?;
+    let (base_file, _, base_start) = read_header(&mut base_input)?;
+    let mut adapter_input = File::open(adapter)?;
+    let (adapter_file, _, adapter_start) = read_header(&mut adapter_input)?;
+    let lora = LoraAdapter::from_file(&adapter_file)?;
+
+    let base_architecture = base_file
+        .header
+        .get("general.architecture")
+        .and_then(|v| v.as_str());
+    if let (Some(base), Some(adapter)) = (base_architecture, lora.architecture.as_deref()) {
+        if base != adapter {
+            return Err(format!("the adapter is for {adapter}, the base model is {base}").into());
+        }
+    }
+    let mut pairs = HashMap::new();
+    for pair in &lora.pairs {
+        let target = base_file
+            .tensors
+            .iter()
+            .find(|t| t.name == pair.target)
+            .ok_or_else(|| format!("the base model has no tensor {}", pair.target))?;
+        let (n_in, n_out) = (pair.a.dimensions[0], pair.b.dimensions[1]);
+        if target.dimensions[..] != [n_in, n_out] {
+            return Err(format!(
+                "{}: the adapter makes a [{n_in}, {n_out}] delta for a {:?} weight",
+                pair.target, target.dimensions
+            )
+            .into());
+        }
+        pairs.insert(pair.target.as_str(), pair);
+    }
+
+    let mut index = 0;
+    write_file(out, &base_file, |tensor, out| {
+        let from = &base_file.tensors[index];
+        index += 1;
+        let Some(pair) = pairs.get(tensor.name.as_str()) else {
+            return copy_data(&mut base_input, base_start + from.offset, from, out);
+        };
+        let mut weight = read_values(&mut base_input, base_start, from)?;
+        let a = read_values(&mut adapter_input, adapter_start, &pair.a)?;
+        let b = read_values(&mut adapter_input, adapter_start, &pair.b)?;
+        let (n_in, rank) = (pair.a.dimensions[0] as usize, pair.rank() as usize);
+        let s = lora.pair_scale(pair, scale);
+        for (row, b_row) in weight.chunks_exact_mut(n_in).zip(b.chunks_exact(rank)) {
+            for (&b, a_row) in b_row.iter().zip(a.chunks_exact(n_in)) {
+                let b = s * b;
+                row.iter_mut().zip(a_row).for_each(|(w, &a)| *w += b * a);
+            }
+        }
+        let data = quantize(tensor.tensor_type, &weight)
+            .map_err(|e| format!("re-encoding {}: {e}", tensor.name))?;
+        Ok(out.write_all(&data)?)
+    })?;
+    Ok(lora.pairs.into_iter().map(|p| p.target).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path, tensor};
+    use crate::{GGMLType, GGUFHeader, GGUFMetadataValue};
+
+    fn write(path: &Path, header: GGUFHeader, tensors: Vec<(&str, Vec<u64>, Vec<f32>)>) {
+        let file = file(
+            header,
+            tensors
+                .iter()
+                .map(|(name, dimensions, _)| tensor(name, &dimensions[..], GGMLType::F32, 0))
+                .collect(),
+        );
+        let mut values = tensors.into_iter().map(|(_, _, values)| values);
+        write_file(path, &file, |_, out| {
+            let data = quantize(GGMLType::F32, &values.next().unwrap_or_default())?;
+            Ok(out.write_all(&data)?)
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn merges_scaled_deltas() {
+        let dir = temp_path("gguf-lora");
+        std::fs::create_dir_all(&dir).unwrap();
+        let string = |value: &str| GGUFMetadataValue::String(value.to_string());
+        let arch = || ("general.architecture", string("llama"));
+        write(
+            &dir.join("base.gguf"),
+            header(&[arch()]),
+            vec![
+                ("blk.0.attn_q.weight", vec![3, 2], vec![0.0; 6]),
+                ("output_norm.weight", vec![3], vec![1.0, 2.0, 3.0]),
+            ],
+        );
+        write(
+            &dir.join("adapter.gguf"),
+            header(&[
+                arch(),
+                ("general.type", string("adapter")),
+                ("adapter.type", string("lora")),
+                ("adapter.lora.alpha", GGUFMetadataValue::Float32(2.0)),
+            ]),
+            vec![
+                (
+                    "blk.0.attn_q.weight.lora_a",
+                    vec![3, 1],
+                    vec![1.0, 2.0, 3.0],
+                ),
+                ("blk.0.attn_q.weight.lora_b", vec![1, 2], vec![1.0, -1.0]),
+            ],
+        );
+
+        let merged = merge_lora(
+            dir.join("base.gguf"),
+            dir.join("adapter.gguf"),
+            0.5,
+            dir.join("merged.gguf"),
+        )
+        .unwrap();
+        assert_eq!(merged, ["blk.0.attn_q.weight"]);
+        let mut input = File::open(dir.join("merged.gguf")).unwrap();
+        let (file, _, data_start) = read_header(&mut input).unwrap();
+        let q = read_values(&mut input, data_start, &file.tensors[0]).unwrap();
+        assert_eq!(q, [1.0, 2.0, 3.0, -1.0, -2.0, -3.0]);
+        let norm = read_values(&mut input, data_start, &file.tensors[1]).unwrap();
+        assert_eq!(norm, [1.0, 2.0, 3.0]);
+
+        write(&dir.join("odd.gguf"), header(&[]), vec![]);
+        let error = merge_lora(
+            dir.join("base.gguf"),
+            dir.join("odd.gguf"),
+            1.0,
+            dir.join("merged.gguf"),
+        );
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .contains("not a LoRA adapter"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
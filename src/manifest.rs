@@ -0,0 +1,280 @@
+//! # Checksum manifests
+//!
+//! A manifest is a text file with one digest per line, followed by what it covers: `file` for
+//! the whole file or `tensor:<name>` for the data of one tensor. Digests are SHA-256 or BLAKE3,
+//! named by their prefix. Blank lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08 file
+//! sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae tensor:output.weight
+//! ```
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::digest::{hex, Blake3, Digest, Sha256};
+use crate::{GGUFFile, ParseError, ParseOptions};
+
+/// Digest algorithms a manifest can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl Algorithm {
+    /// The prefix of its digests in a manifest
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [Algorithm::Sha256, Algorithm::Blake3]
+            .into_iter()
+            .find(|a| a.name() == name)
+    }
+}
+
+/// A digest that did not match
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestMismatch {
+    /// `file` or `tensor:<name>`, as written in the manifest.
+    pub target: String,
+    pub expected: String,
+    /// `None` if the file has no such tensor.
+    pub actual: Option<String>,
+}
+
+/// Outcome of [`verify_manifest`]
+#[derive(serde::Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestReport {
+    /// Number of digests compared.
+    pub checked: usize,
+    pub mismatches: Vec<ManifestMismatch>,
+}
+
+impl ManifestReport {
+    /// Whether every digest matched
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Read the header and tensor infos, returning them with their length and the offset of the
+/// tensor data
+pub(crate) fn read_header(file: &mut File) -> Result<(GGUFFile, u64, u64), ParseError> {
+    let (gguf, len) = GGUFFile::read_prefix(file, &ParseOptions::default())?;
+    let data_start = (len as u64).next_multiple_of(gguf.alignment());
+    Ok((gguf, len as u64, data_start))
+}
+
+/// hash `len` bytes of the file from `start`, or up to its end if `len` is `None`
+fn hash_range(
+    file: &mut File,
+    start: u64,
+    len: Option<u64>,
+    algorithm: Algorithm,
+) -> Result<String, String> {
+    let digest = match algorithm {
+        Algorithm::Sha256 => {
+            let mut hasher = Sha256::default();
+            hash_into(&mut hasher, file, start, len)?;
+            hasher.finish()
+        }
+        Algorithm::Blake3 => {
+            let mut hasher = Blake3::default();
+            hash_into(&mut hasher, file, start, len)?;
+            hasher.finish()
+        }
+    };
+    Ok(format!("{}:{}", algorithm.name(), hex(&digest)))
+}
+
+/// feed `len` bytes of the file from `start`, or up to its end if `len` is `None`, to a hasher
+pub(crate) fn hash_into(
+    hasher: &mut impl Digest,
+    file: &mut File,
+    start: u64,
+    len: Option<u64>,
+) -> Result<(), String> {
+    file.seek(SeekFrom::Start(start))
+        .map_err(|e| e.to_string())?;
+    let mut chunk = vec![0; 1 << 20];
+    let mut left = len.unwrap_or(u64::MAX);
+    while left > 0 {
+        let want = chunk.len().min(usize::try_from(left).unwrap_or(usize::MAX));
+        let n = file.read(&mut chunk[..want]).map_err(|e| e.to_string())?;
+        if n == 0 {
+            if len.is_some() {
+                return Err(format!(
+                    "the file ends {left} bytes before the tensor data does"
+                ));
+            }
+            break;
+        }
+        hasher.update(&chunk[..n]);
+        left -= n as u64;
+    }
+    Ok(())
+}
+
+/// digests the file or one of its tensors, `None` for a tensor it does not have
+fn digest(
+    file: &mut File,
+    gguf: &GGUFFile,
+    data_start: u64,
+    target: &str,
+    algorithm: Algorithm,
+) -> Result<Option<String>, String> {
+    if target == "file" {
+        return hash_range(file, 0, None, algorithm).map(Some);
+    }
+    let name = target
+        .strip_prefix("tensor:")
+        .ok_or_else(|| format!("unknown manifest target {target}"))?;
+    let Some(tensor) = gguf.tensors.iter().find(|t| t.name == name) else {
+        return Ok(None);
+    };
+    let size = tensor
+        .size_bytes()
+        .ok_or_else(|| format!("tensor {name} has no whole number of blocks"))?;
+    hash_range(file, data_start + tensor.offset, Some(size), algorithm).map(Some)
+}
+
+/// Write a SHA-256 manifest covering the whole file and each of its tensors
+pub fn create_manifest(file: impl AsRef<Path>) -> Result<String, String> {
+    create_manifest_with(file, Algorithm::Sha256, true)
+}
+
+/// Write a manifest covering the whole file and, if `per_tensor`, each of its tensors
+///
+/// The digests are computed on as many threads as there are cores, each reading the file
+/// through its own handle.
+pub fn create_manifest_with(
+    path: impl AsRef<Path>,
+    algorithm: Algorithm,
+    per_tensor: bool,
+) -> Result<String, String> {
+    let path = path.as_ref();
+    let (gguf, _, data_start) = read_header(&mut File::open(path).map_err(|e| e.to_string())?)?;
+    let mut targets = vec!["file".to_string()];
+    if per_tensor {
+        targets.extend(gguf.tensors.iter().map(|t| format!("tensor:{}", t.name)));
+    }
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(targets.len());
+    let mut digests = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|worker| {
+                let (gguf, targets) = (&gguf, &targets);
+                scope.spawn(move || -> Result<Vec<(usize, Option<String>)>, String> {
+                    let mut file = File::open(path).map_err(|e| e.to_string())?;
+                    (worker..targets.len())
+                        .step_by(threads)
+                        .map(|i| {
+                            digest(&mut file, gguf, data_start, &targets[i], algorithm)
+                                .map(|d| (i, d))
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        let mut digests = Vec::new();
+        for worker in workers {
+            digests.extend(worker.join().map_err(|_| "a hashing thread panicked")??);
+        }
+        Ok::<_, String>(digests)
+    })?;
+    digests.sort_by_key(|(i, _)| *i);
+    let mut manifest = String::new();
+    for (i, digest) in digests {
+        if let Some(digest) = digest {
+            manifest.push_str(&format!("{digest} {}\n", targets[i]));
+        }
+    }
+    Ok(manifest)
+}
+
+/// Compare a file against the digests of a manifest
+pub fn verify_manifest(
+    file: impl AsRef<Path>,
+    manifest_path: impl AsRef<Path>,
+) -> Result<ManifestReport, String> {
+    let manifest = std::fs::read_to_string(manifest_path).map_err(|e| e.to_string())?;
+    let mut file = File::open(file).map_err(|e| e.to_string())?;
+    let (gguf, _, data_start) = read_header(&mut file)?;
+    let mut report = ManifestReport::default();
+    for line in manifest.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (expected, target) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("malformed manifest line {line:?}"))?;
+        let algorithm = expected
+            .split_once(':')
+            .and_then(|(name, _)| Algorithm::from_name(name))
+            .ok_or_else(|| format!("unsupported digest {expected}, expected sha256 or blake3"))?;
+        let target = target.trim();
+        let actual = digest(&mut file, &gguf, data_start, target, algorithm)?;
+        report.checked += 1;
+        if actual.as_deref() != Some(&expected.to_ascii_lowercase()) {
+            report.mismatches.push(ManifestMismatch {
+                target: target.to_string(),
+                expected: expected.to_string(),
+                actual,
+            });
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{header_start, temp_path, tensor_info};
+
+    #[test]
+    fn manifest_round_trip() {
+        let mut buf = header_start(3, 1, 0);
+        tensor_info(&mut buf, "w", &[4], 0, 0);
+        buf.resize(64, 0);
+        buf.extend([1; 16]);
+
+        let dir = temp_path("gguf-manifest");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (model, manifest) = (dir.join("m.gguf"), dir.join("m.sha256"));
+        std::fs::write(&model, &buf).unwrap();
+        let text = create_manifest(&model).unwrap();
+        assert!(text.ends_with(&format!(
+            "sha256:{} tensor:w\n",
+            crate::digest::sha256_hex(&[1; 16])
+        )));
+        let blake3 = create_manifest_with(&model, Algorithm::Blake3, false).unwrap();
+        assert!(blake3.starts_with("blake3:") && blake3.ends_with(" file\n"));
+        std::fs::write(
+            &manifest,
+            format!("# mirror\n{text}{blake3}sha256:00 tensor:gone\n"),
+        )
+        .unwrap();
+        let report = verify_manifest(&model, &manifest).unwrap();
+        assert_eq!(report.checked, 4);
+        assert_eq!(report.mismatches.len(), 1);
+
+        buf[70] = 2;
+        std::fs::write(&model, &buf).unwrap();
+        let report = verify_manifest(&model, &manifest).unwrap();
+        let targets: Vec<_> = report
+            .mismatches
+            .iter()
+            .map(|m| m.target.as_str())
+            .collect();
+        assert_eq!(targets, ["file", "tensor:w", "file", "tensor:gone"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,391 @@
+//! # Memory-mapped files
+//!
+//! [`MappedFile`] maps a file read-only and parses the header from the mapping. String arrays
+//! such as `tokenizer.ggml.tokens` and `merges` are not copied: the array is an index of where
+//! each string lies in the mapping, [`ArrayValues::Mapped`], and a string is read from the page
+//! cache when it is looked up. The pages of the arrays are released after indexing, so a
+//! tokenizer section of hundreds of megabytes adds little more than its index, 16 bytes a
+//! string, to resident memory. Tensor data is borrowed from the mapping as well.
+//!
+//! Mapping needs a 64-bit unix; elsewhere [`MappedFile::open`] fails. Without the `std`
+//! feature only the types are left, and nothing can be mapped.
+use alloc::sync::Arc;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+#[cfg(feature = "std")]
+use crate::{ArrayValues, GGUFFile, GGUFTensorInfo, ParseOptions};
+
+#[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+mod sys {
+    use std::os::raw::{c_int, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const PROT_WRITE: c_int = 2;
+    pub const MAP_SHARED: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+    pub const MADV_DONTNEED: c_int = 4;
+    pub const MS_ASYNC: c_int = 1;
+    #[cfg(target_os = "linux")]
+    pub const ENOSPC: c_int = 28;
+
+    extern "C" {
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: i64,
+        ) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+        pub fn madvise(addr: *mut c_void, len: usize, advice: c_int) -> c_int;
+        pub fn msync(addr: *mut c_void, len: usize, flags: c_int) -> c_int;
+        #[cfg(target_os = "linux")]
+        pub fn posix_fallocate(fd: c_int, offset: i64, len: i64) -> c_int;
+    }
+}
+
+/// A read-only mapping of a whole file
+pub struct Mmap {
+    #[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+    ptr: *mut u8,
+    #[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+    len: usize,
+    #[cfg(not(all(feature = "std", unix, target_pointer_width = "64")))]
+    never: core::convert::Infallible,
+}
+
+// SAFETY: the mapping is read-only and unmapped only on drop
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Map `file`
+    ///
+    /// # Safety
+    ///
+    /// The file must not be written or truncated while mapped, which would change or take away
+    /// memory the mapping hands out as immutable.
+    #[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+    pub unsafe fn map(file: &File) -> Result<Self, String> {
+        use std::os::unix::io::AsRawFd;
+        let len = file.metadata().map_err(|e| e.to_string())?.len() as usize;
+        if len == 0 {
+            return Ok(Mmap {
+                ptr: core::ptr::NonNull::dangling().as_ptr(),
+                len,
+            });
+        }
+        let fd = file.as_raw_fd();
+        let ptr = sys::mmap(
+            core::ptr::null_mut(),
+            len,
+            sys::PROT_READ,
+            sys::MAP_PRIVATE,
+            fd,
+            0,
+        );
+        if ptr as isize == -1 {
+            return Err(format!(
+                "mapping the file: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(Mmap {
+            ptr: ptr.cast(),
+            len,
+        })
+    }
+
+    /// Mapping needs a 64-bit unix
+    ///
+    /// # Safety
+    ///
+    /// Always safe, as it always fails.
+    #[cfg(all(feature = "std", not(all(unix, target_pointer_width = "64"))))]
+    pub unsafe fn map(_file: &File) -> Result<Self, String> {
+        Err("memory mapping needs a 64-bit unix".to_string())
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        #[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+        // SAFETY: the mapping is `len` readable bytes for as long as `self` lives
+        return unsafe { core::slice::from_raw_parts(self.ptr, self.len) };
+        #[cfg(not(all(feature = "std", unix, target_pointer_width = "64")))]
+        match self.never {}
+    }
+
+    /// Drop the pages wholly inside `range` from resident memory; they are read from the file
+    /// again when next touched
+    pub(crate) fn release(&self, range: core::ops::Range<usize>) {
+        #[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+        {
+            // a multiple of the page sizes of every platform, 4 to 64 KiB
+            const PAGE: usize = 64 << 10;
+            let (start, end) = (range.start.next_multiple_of(PAGE), range.end / PAGE * PAGE);
+            if start < end && end <= self.len {
+                // SAFETY: the pages are of the mapping, which is private and read-only, so
+                // dropping them loses nothing
+                unsafe {
+                    sys::madvise(self.ptr.add(start).cast(), end - start, sys::MADV_DONTNEED)
+                };
+            }
+        }
+        #[cfg(not(all(feature = "std", unix, target_pointer_width = "64")))]
+        let _ = range;
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+        if self.len > 0 {
+            // SAFETY: `ptr` and `len` are those mmap returned
+            unsafe { sys::munmap(self.ptr.cast(), self.len) };
+        }
+    }
+}
+
+impl fmt::Debug for Mmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Mmap({} bytes)", self.as_slice().len())
+    }
+}
+
+/// A writable mapping of a whole file, whose writes go to the file
+#[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+pub struct MmapMut {
+    ptr: *mut u8,
+    len: usize,
+}
+
+#[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+impl MmapMut {
+    /// Grow or cut `file`, which must be open for reading and writing, to `len` bytes of
+    /// zeros and map it
+    ///
+    /// On Linux the blocks are allocated up front, so a full disk fails here; elsewhere it
+    /// kills the process on the first write to a page that finds no room.
+    pub fn create(file: &File, len: u64) -> Result<Self, String> {
+        use std::os::unix::io::AsRawFd;
+        file.set_len(len).map_err(|e| e.to_string())?;
+        let len = usize::try_from(len).map_err(|e| e.to_string())?;
+        if len == 0 {
+            return Ok(MmapMut {
+                ptr: core::ptr::NonNull::dangling().as_ptr(),
+                len,
+            });
+        }
+        let fd = file.as_raw_fd();
+        #[cfg(target_os = "linux")]
+        // SAFETY: the call only reads its arguments
+        if unsafe { sys::posix_fallocate(fd, 0, len as i64) } == sys::ENOSPC {
+            return Err("there is no room on the disk for the file".to_string());
+        }
+        // SAFETY: a fresh shared mapping of the file, which nothing else in the process owns
+        let ptr = unsafe {
+            sys::mmap(
+                core::ptr::null_mut(),
+                len,
+                sys::PROT_READ | sys::PROT_WRITE,
+                sys::MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+        if ptr as isize == -1 {
+            return Err(format!(
+                "mapping the file: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(MmapMut {
+            ptr: ptr.cast(),
+            len,
+        })
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: the mapping is `len` writable bytes for as long as `self` lives, and only
+        // borrowed through `self`
+        unsafe { core::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+
+    /// Start writing the pages of `range` back to the file, without waiting for it
+    pub fn flush_async(&self, range: core::ops::Range<usize>) {
+        // msync takes a page aligned start
+        const PAGE: usize = 64 << 10;
+        let start = range.start / PAGE * PAGE;
+        let end = range.end.min(self.len);
+        if start < end {
+            // SAFETY: the range is inside the mapping
+            unsafe { sys::msync(self.ptr.add(start).cast(), end - start, sys::MS_ASYNC) };
+        }
+    }
+}
+
+#[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+impl Drop for MmapMut {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: `ptr` and `len` are those mmap returned
+            unsafe { sys::munmap(self.ptr.cast(), self.len) };
+        }
+    }
+}
+
+/// The strings of an array, as offsets and lengths into a mapping
+///
+/// UTF-8 is checked when a string is looked up, so an invalid one is found only then:
+/// [`MappedStrings::get`] gives `None` for it.
+#[derive(Clone)]
+pub struct MappedStrings {
+    map: Arc<Mmap>,
+    spans: Vec<(u64, u32)>,
+}
+
+impl MappedStrings {
+    pub(crate) fn new(map: Arc<Mmap>, spans: Vec<(u64, u32)>) -> Self {
+        MappedStrings { map, spans }
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// The offset in the file and the length of each string
+    pub fn spans(&self) -> &[(u64, u32)] {
+        &self.spans
+    }
+
+    pub fn get_bytes(&self, index: usize) -> Option<&[u8]> {
+        let (offset, len) = *self.spans.get(index)?;
+        self.map
+            .as_slice()
+            .get(offset as usize..offset as usize + len as usize)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        core::str::from_utf8(self.get_bytes(index)?).ok()
+    }
+
+    /// The strings, those that are not UTF-8 skipped
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        (0..self.len()).filter_map(|i| self.get(i))
+    }
+}
+
+impl fmt::Debug for MappedStrings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MappedStrings({} strings)", self.len())
+    }
+}
+
+/// A GGUF file parsed from a memory mapping
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct MappedFile {
+    map: Arc<Mmap>,
+    pub file: GGUFFile,
+    /// The offset of the tensor data.
+    pub data_start: u64,
+}
+
+#[cfg(feature = "std")]
+impl MappedFile {
+    /// Map and parse the file at `path`
+    ///
+    /// # Safety
+    ///
+    /// As for [`Mmap::map`], the file must not change while the result or any string array
+    /// of it is alive.
+    pub unsafe fn open(path: impl AsRef<Path>, options: &ParseOptions) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let map = Arc::new(Mmap::map(&file)?);
+        let (gguf, len) = GGUFFile::parse_in(map.as_slice(), options, Some(&map))?
+            .ok_or("the file ends within its header")?;
+        let data_start = (len as u64).next_multiple_of(gguf.alignment());
+        Ok(MappedFile {
+            map,
+            file: gguf,
+            data_start,
+        })
+    }
+
+    /// The whole file
+    pub fn as_bytes(&self) -> &[u8] {
+        self.map.as_slice()
+    }
+
+    /// The data of a tensor, `None` if it runs past the end of the file
+    pub fn tensor_data(&self, tensor: &GGUFTensorInfo) -> Option<&[u8]> {
+        let start = usize::try_from(self.data_start + tensor.offset).ok()?;
+        let size = usize::try_from(tensor.size_bytes()?).ok()?;
+        self.as_bytes().get(start..start.checked_add(size)?)
+    }
+
+    /// The strings of the string array at `key`, if it is one
+    pub fn strings(&self, key: &str) -> Option<&MappedStrings> {
+        match &self.file.header.get(key)?.as_array()?.value {
+            ArrayValues::Mapped(strings) => Some(strings),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::smallvec;
+    use crate::test_util::{file, header, temp_path};
+    use crate::writer::write_file;
+    use crate::{GGMLType, GGUFMetadataArrayValue, GGUFMetadataValue};
+    use crate::{GGUfMetadataValueType, StringArray};
+
+    #[test]
+    fn maps_token_arrays() {
+        let tokens: Vec<String> = (0..5000).map(|i| format!("tok{i}")).collect();
+        let file = file(
+            header(&[(
+                "tokenizer.ggml.tokens",
+                GGUFMetadataValue::Array(GGUFMetadataArrayValue::packed(
+                    GGUfMetadataValueType::String,
+                    ArrayValues::String(tokens.iter().map(String::as_str).collect()),
+                )),
+            )]),
+            vec![GGUFTensorInfo {
+                name: "output_norm.weight".to_string(),
+                dimensions: smallvec![4],
+                tensor_type: GGMLType::F32,
+                offset: 0,
+            }],
+        );
+        let path = temp_path("gguf-mmap.gguf");
+        write_file(&path, &file, |_, out| Ok(out.write_all(&[7; 16])?)).unwrap();
+
+        // SAFETY: nothing writes the file while it is mapped
+        let mapped = unsafe { MappedFile::open(&path, &ParseOptions::default()) }.unwrap();
+        let strings = mapped.strings("tokenizer.ggml.tokens").unwrap();
+        assert_eq!(strings.len(), 5000);
+        assert_eq!(strings.get(4321), Some("tok4321"));
+        let value = &mapped.file.header.get("tokenizer.ggml.tokens").unwrap();
+        assert_eq!(value.as_array().unwrap().value.get_str(7), Some("tok7"));
+        let owned: StringArray = tokens.iter().map(String::as_str).collect();
+        assert_eq!(value.as_array().unwrap().value, ArrayValues::String(owned),);
+        assert_eq!(
+            mapped.tensor_data(&mapped.file.tensors[0]),
+            Some(&[7; 16][..])
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
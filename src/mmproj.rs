@@ -0,0 +1,224 @@
+//! # Multimodal projectors
+//!
+//! llama.cpp runs vision and audio models as two files: the language model, and an `mmproj`
+//! file of architecture `clip` holding the encoder and the projector that maps its output into
+//! the language model's embeddings. [`Mmproj::of`] recognizes such a file and reads its
+//! `clip.*` keys, and [`pair`] finds the language model an `mmproj` file belongs with among
+//! others, by the width its projector outputs.
+use std::path::Path;
+
+use crate::keys::clip;
+use crate::{GGUFFile, GGUFHeader};
+
+/// The vision encoder of a projector file, from its `clip.vision.*` keys
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct VisionEncoder {
+    /// The width and height of the images it takes, in pixels.
+    pub image_size: Option<u64>,
+    /// The width and height of a patch, in pixels.
+    pub patch_size: Option<u64>,
+    pub embedding_length: Option<u64>,
+    pub feed_forward_length: Option<u64>,
+    pub block_count: Option<u64>,
+    pub head_count: Option<u64>,
+    /// The mean of each color channel images are normalized by.
+    pub image_mean: Vec<f32>,
+    /// The standard deviation of each color channel.
+    pub image_std: Vec<f32>,
+}
+
+impl VisionEncoder {
+    /// The patches an image is cut into
+    pub fn patch_count(&self) -> Option<u64> {
+        let per_side = self.image_size?.checked_div(self.patch_size?)?;
+        Some(per_side * per_side)
+    }
+}
+
+/// What an `mmproj` file holds
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct Mmproj {
+    /// `clip.projector_type`, `mlp` for LLaVA files from before the key.
+    pub projector_type: Option<String>,
+    pub vision: Option<VisionEncoder>,
+    pub has_audio_encoder: bool,
+    /// The names of the projector's tensors, the `mm.*` ones.
+    pub projector_tensors: Vec<String>,
+    /// The width of the projector's output, which is the embedding length of the language model
+    /// it belongs with: `clip.vision.projection_dim`, or else the output width of the last
+    /// projector tensor.
+    pub output_width: Option<u64>,
+}
+
+/// Whether `header` is that of an `mmproj` file
+pub fn is_mmproj(header: &GGUFHeader) -> bool {
+    header
+        .get(crate::keys::general::ARCHITECTURE)
+        .and_then(|v| v.as_str())
+        == Some("clip")
+}
+
+impl Mmproj {
+    /// The projector `file` is, `None` if it is not an `mmproj` file
+    pub fn of(file: &GGUFFile) -> Option<Self> {
+        let header = &file.header;
+        if !is_mmproj(header) {
+            return None;
+        }
+        let int = |key: &str| header.get(key).and_then(|v| v.as_u64());
+        let flag = |key: &str| header.get(key).and_then(|v| v.as_bool());
+        let floats = |key: &str| {
+            let array = header.get(key).and_then(|v| v.as_array());
+            array.map_or_else(Vec::new, |a| {
+                a.value
+                    .iter()
+                    .filter_map(|v| v.as_f64())
+                    .map(|f| f as f32)
+                    .collect()
+            })
+        };
+        let projector_type = header
+            .get(clip::PROJECTOR_TYPE)
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .or_else(|| flag(clip::HAS_LLAVA_PROJECTOR)?.then(|| "mlp".to_string()));
+        let vision = flag(clip::HAS_VISION_ENCODER)
+            .unwrap_or(int(clip::VISION_IMAGE_SIZE).is_some())
+            .then(|| VisionEncoder {
+                image_size: int(clip::VISION_IMAGE_SIZE),
+                patch_size: int(clip::VISION_PATCH_SIZE),
+                embedding_length: int(clip::VISION_EMBEDDING_LENGTH),
+                feed_forward_length: int(clip::VISION_FEED_FORWARD_LENGTH),
+                block_count: int(clip::VISION_BLOCK_COUNT),
+                head_count: int(clip::VISION_ATTENTION_HEAD_COUNT),
+                image_mean: floats(clip::VISION_IMAGE_MEAN),
+                image_std: floats(clip::VISION_IMAGE_STD),
+            });
+        let mut projector: Vec<_> = file
+            .tensors
+            .iter()
+            .filter(|t| t.name.starts_with("mm."))
+            .collect();
+        // `mm.0.weight` before `mm.2.weight` before `mm.10.weight`
+        projector.sort_by_key(|t| {
+            let index = t.name[3..]
+                .split('.')
+                .next()
+                .and_then(|i| i.parse::<u64>().ok());
+            (index.unwrap_or(u64::MAX), t.name.clone())
+        });
+        let last_weight = projector
+            .iter()
+            .rev()
+            .find(|t| t.name.ends_with(".weight") && t.dimensions.len() == 2);
+        Some(Mmproj {
+            projector_type,
+            vision,
+            has_audio_encoder: flag(clip::HAS_AUDIO_ENCODER).unwrap_or(false),
+            projector_tensors: projector.iter().map(|t| t.name.clone()).collect(),
+            output_width: int(clip::VISION_PROJECTION_DIM)
+                .or_else(|| last_weight.map(|t| t.dimensions[1])),
+        })
+    }
+}
+
+/// The embedding length of a language model
+fn embedding_length(header: &GGUFHeader) -> Option<u64> {
+    let arch = header.get(crate::keys::general::ARCHITECTURE)?.as_str()?;
+    header
+        .get(&format!("{arch}{}", crate::keys::arch::EMBEDDING_LENGTH))?
+        .as_u64()
+}
+
+/// The index into `models` of the language model the projector at `path` belongs with: one
+/// whose embedding length is the projector's output width, the one in the same folder with the
+/// most of its file name in common if several are
+pub fn pair(path: &Path, mmproj: &Mmproj, models: &[(&Path, &GGUFFile)]) -> Option<usize> {
+    let width = mmproj.output_width?;
+    let stem = |p: &Path| {
+        let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        stem.to_ascii_lowercase()
+            .replace("mmproj", "")
+            .trim_matches(['-', '_', '.'])
+            .to_string()
+    };
+    let name = stem(path);
+    let common = |other: &str| {
+        name.chars()
+            .zip(other.chars())
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+    models
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, file))| {
+            !is_mmproj(&file.header) && embedding_length(&file.header) == Some(width)
+        })
+        .max_by_key(|(_, (model, _))| (model.parent() == path.parent(), common(&stem(model))))
+        .map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, tensor};
+    use crate::{GGMLType, GGUFMetadataValue, GGUFTensorInfo};
+
+    /// F16 matrices of 1024 inputs and the given outputs
+    fn matrices(tensors: &[(&str, u64)]) -> Vec<GGUFTensorInfo> {
+        tensors
+            .iter()
+            .map(|&(name, out)| tensor(name, &[1024, out], GGMLType::F16, 0))
+            .collect()
+    }
+
+    #[test]
+    fn pairs_projectors_with_their_models() {
+        use GGUFMetadataValue::{Bool, String as Str, Uint32};
+        let projector = file(
+            header(&[
+                ("general.architecture", Str("clip".to_string())),
+                ("clip.has_vision_encoder", Bool(true)),
+                ("clip.projector_type", Str("mlp".to_string())),
+                ("clip.vision.image_size", Uint32(336)),
+                ("clip.vision.patch_size", Uint32(14)),
+            ]),
+            matrices(&[
+                ("mm.0.weight", 4096),
+                ("mm.2.weight", 4096),
+                ("mm.10.weight", 5120),
+                ("v.patch_embd.weight", 1024),
+            ]),
+        );
+        let mmproj = Mmproj::of(&projector).unwrap();
+        assert_eq!(
+            mmproj.projector_tensors,
+            ["mm.0.weight", "mm.2.weight", "mm.10.weight"]
+        );
+        assert_eq!(mmproj.output_width, Some(5120));
+        assert_eq!(mmproj.vision.as_ref().unwrap().patch_count(), Some(576));
+
+        let model = |width| {
+            file(
+                header(&[
+                    ("general.architecture", Str("llama".to_string())),
+                    ("llama.embedding_length", Uint32(width)),
+                ]),
+                Vec::new(),
+            )
+        };
+        assert_eq!(Mmproj::of(&model(5120)), None);
+        let (small, large, other) = (model(4096), model(5120), model(5120));
+        let models = [
+            (Path::new("/models/llava-7b.Q4_K_M.gguf"), &small),
+            (Path::new("/other/llava-13b.Q4_K_M.gguf"), &other),
+            (Path::new("/models/llava-13b.Q4_K_M.gguf"), &large),
+            (Path::new("/models/mmproj-llava-13b-f16.gguf"), &projector),
+        ];
+        let path = Path::new("/models/mmproj-llava-13b-f16.gguf");
+        assert_eq!(pair(path, &mmproj, &models), Some(2));
+        assert_eq!(pair(path, &mmproj, &models[..2]), Some(1));
+        assert_eq!(pair(path, &mmproj, &models[..1]), None);
+    }
+}
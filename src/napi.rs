@@ -0,0 +1,361 @@
+//! # Node.js addon
+//!
+//! An N-API module so Node and Electron applications can read GGUF headers in process rather
+//! than running the `gguf` tool. The N-API functions are declared here and resolved from the
+//! host process when the library is loaded, so building needs neither Node headers nor
+//! `napi-rs`; any Node release with N-API 1 loads it:
+//!
+//! ```sh
+//! cargo rustc --lib --release --features napi --crate-type cdylib && cp target/release/libgguf.so gguf.node
+//! ```
+//!
+//! The module exports
+//!
+//! - `open(path)`: the header and tensor infos of a file, with the `data_offset` of its tensor
+//!   data;
+//! - `inspect(bytes)`: the same for the start of a file in a `Buffer` or `Uint8Array`, or
+//!   `null` when it ends inside the header;
+//! - `validate(path)`: the [`ValidationReport`](crate::validate::ValidationReport) of a file.
+//!
+//! Values are the JSON the crate serializes, described by [`crate::schema`], and failures throw
+//! an `Error`.
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::ptr;
+
+use serde_json::json;
+
+use crate::{validate_file, GGUFFile, ParseOptions};
+
+#[repr(C)]
+struct Env([u8; 0]);
+#[repr(C)]
+struct Value([u8; 0]);
+#[repr(C)]
+struct CallbackInfo([u8; 0]);
+
+type NapiEnv = *mut Env;
+type NapiValue = *mut Value;
+type Callback = unsafe extern "C" fn(NapiEnv, *mut CallbackInfo) -> NapiValue;
+
+/// `napi_ok`; every other status is a failure.
+const OK: c_int = 0;
+
+extern "C" {
+    fn napi_create_function(
+        env: NapiEnv,
+        name: *const c_char,
+        length: usize,
+        cb: Callback,
+        data: *mut c_void,
+        result: *mut NapiValue,
+    ) -> c_int;
+    fn napi_set_named_property(
+        env: NapiEnv,
+        object: NapiValue,
+        name: *const c_char,
+        value: NapiValue,
+    ) -> c_int;
+    fn napi_get_named_property(
+        env: NapiEnv,
+        object: NapiValue,
+        name: *const c_char,
+        result: *mut NapiValue,
+    ) -> c_int;
+    fn napi_get_cb_info(
+        env: NapiEnv,
+        info: *mut CallbackInfo,
+        argc: *mut usize,
+        argv: *mut NapiValue,
+        this: *mut NapiValue,
+        data: *mut *mut c_void,
+    ) -> c_int;
+    fn napi_get_value_string_utf8(
+        env: NapiEnv,
+        value: NapiValue,
+        buf: *mut c_char,
+        size: usize,
+        result: *mut usize,
+    ) -> c_int;
+    fn napi_get_typedarray_info(
+        env: NapiEnv,
+        value: NapiValue,
+        kind: *mut c_int,
+        length: *mut usize,
+        data: *mut *mut c_void,
+        buffer: *mut NapiValue,
+        offset: *mut usize,
+    ) -> c_int;
+    fn napi_create_string_utf8(
+        env: NapiEnv,
+        s: *const c_char,
+        length: usize,
+        result: *mut NapiValue,
+    ) -> c_int;
+    fn napi_get_global(env: NapiEnv, result: *mut NapiValue) -> c_int;
+    fn napi_get_null(env: NapiEnv, result: *mut NapiValue) -> c_int;
+    fn napi_call_function(
+        env: NapiEnv,
+        recv: NapiValue,
+        func: NapiValue,
+        argc: usize,
+        argv: *const NapiValue,
+        result: *mut NapiValue,
+    ) -> c_int;
+    fn napi_throw_error(env: NapiEnv, code: *const c_char, msg: *const c_char) -> c_int;
+}
+
+/// The JSON `open` returns
+fn open_json(path: &str) -> Result<String, String> {
+    let mut f = std::fs::File::open(path).map_err(|e| format!("{path}: {e}"))?;
+    let (file, data_start) = GGUFFile::read_from(&mut f, &ParseOptions::default())
+        .map_err(|e| format!("{path}: {e}"))?;
+    let mut value = serde_json::to_value(file).map_err(|e| e.to_string())?;
+    value["data_offset"] = json!(data_start);
+    Ok(value.to_string())
+}
+
+/// The JSON `inspect` returns, `None` for `null`
+fn inspect_json(bytes: &[u8]) -> Result<Option<String>, String> {
+    match GGUFFile::read_with(bytes, &ParseOptions::default()) {
+        Ok(Some(file)) => serde_json::to_string(&file)
+            .map(Some)
+            .map_err(|e| e.to_string()),
+        Ok(None) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The JSON `validate` returns
+fn validate_json(path: &str) -> Result<String, String> {
+    validate_file(path)
+        .map(|report| report.to_json())
+        .map_err(|e| format!("{path}: {e}"))
+}
+
+/// Fail with `what` unless `status` is [`OK`]
+fn check(status: c_int, what: &str) -> Result<(), String> {
+    if status == OK {
+        Ok(())
+    } else {
+        Err(format!("{what} failed with N-API status {status}"))
+    }
+}
+
+/// The first argument of a call
+///
+/// # Safety
+///
+/// `env` and `info` must be those of the running callback.
+unsafe fn argument(env: NapiEnv, info: *mut CallbackInfo) -> Result<NapiValue, String> {
+    let mut argc = 1;
+    let mut argv = ptr::null_mut();
+    // SAFETY: argv has room for argc values
+    check(
+        unsafe {
+            napi_get_cb_info(
+                env,
+                info,
+                &mut argc,
+                &mut argv,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        },
+        "reading the arguments",
+    )?;
+    if argc == 0 {
+        return Err("missing argument".to_string());
+    }
+    Ok(argv)
+}
+
+/// A string argument
+///
+/// # Safety
+///
+/// `env` and `info` must be those of the running callback.
+unsafe fn string_argument(env: NapiEnv, info: *mut CallbackInfo) -> Result<String, String> {
+    // SAFETY: forwarded from the caller
+    let value = unsafe { argument(env, info) }?;
+    let mut len = 0;
+    // SAFETY: a null buffer asks for the length
+    check(
+        unsafe { napi_get_value_string_utf8(env, value, ptr::null_mut(), 0, &mut len) },
+        "expected a string; reading it",
+    )?;
+    let mut buf = vec![0u8; len + 1];
+    // SAFETY: buf holds len bytes and the NUL N-API writes after them
+    check(
+        unsafe {
+            napi_get_value_string_utf8(env, value, buf.as_mut_ptr().cast(), buf.len(), &mut len)
+        },
+        "reading the string",
+    )?;
+    buf.truncate(len);
+    String::from_utf8(buf).map_err(|e| e.to_string())
+}
+
+/// Parse `json` with the host's `JSON.parse`, or return `null` for `None`
+///
+/// # Safety
+///
+/// `env` must be that of the running callback.
+unsafe fn parse(env: NapiEnv, json: Option<String>) -> Result<NapiValue, String> {
+    let mut result = ptr::null_mut();
+    let Some(json) = json else {
+        // SAFETY: result is a valid out pointer
+        check(unsafe { napi_get_null(env, &mut result) }, "creating null")?;
+        return Ok(result);
+    };
+    let (mut global, mut object, mut function, mut string) = (
+        ptr::null_mut(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+    );
+    // SAFETY: the names are NUL-terminated and the out pointers valid; the string is passed
+    // with its length
+    unsafe {
+        check(
+            napi_get_global(env, &mut global),
+            "finding the global object",
+        )?;
+        check(
+            napi_get_named_property(env, global, c"JSON".as_ptr(), &mut object),
+            "finding JSON",
+        )?;
+        check(
+            napi_get_named_property(env, object, c"parse".as_ptr(), &mut function),
+            "finding JSON.parse",
+        )?;
+        check(
+            napi_create_string_utf8(env, json.as_ptr().cast(), json.len(), &mut string),
+            "creating a string",
+        )?;
+        check(
+            napi_call_function(env, object, function, 1, &string, &mut result),
+            "JSON.parse",
+        )?;
+    }
+    Ok(result)
+}
+
+/// Return the result of a callback, or throw its error and return nothing
+///
+/// # Safety
+///
+/// `env` must be that of the running callback.
+unsafe fn finish(env: NapiEnv, result: Result<NapiValue, String>) -> NapiValue {
+    result.unwrap_or_else(|e| {
+        let message = CString::new(e.replace('\0', " ")).unwrap_or_default();
+        // SAFETY: the message is NUL-terminated and a null code is allowed
+        unsafe { napi_throw_error(env, ptr::null(), message.as_ptr()) };
+        ptr::null_mut()
+    })
+}
+
+unsafe extern "C" fn open(env: NapiEnv, info: *mut CallbackInfo) -> NapiValue {
+    // SAFETY: called by N-API with the env and info of this call
+    unsafe {
+        let result = string_argument(env, info).and_then(|path| open_json(&path));
+        let result = result.and_then(|json| parse(env, Some(json)));
+        finish(env, result)
+    }
+}
+
+unsafe extern "C" fn inspect(env: NapiEnv, info: *mut CallbackInfo) -> NapiValue {
+    // SAFETY: called by N-API with the env and info of this call; the typed array data is
+    // `length` elements of `kind`, read as bytes only for 8-bit kinds
+    unsafe {
+        let result = argument(env, info).and_then(|value| {
+            let (mut kind, mut length, mut data) = (0, 0, ptr::null_mut());
+            let status = napi_get_typedarray_info(
+                env,
+                value,
+                &mut kind,
+                &mut length,
+                &mut data,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            // napi_int8_array, napi_uint8_array and napi_uint8_clamped_array
+            if status != OK || !(0..=2).contains(&kind) {
+                return Err("expected a Buffer or Uint8Array".to_string());
+            }
+            let bytes = if length == 0 {
+                &[][..]
+            } else {
+                std::slice::from_raw_parts(data.cast::<u8>(), length)
+            };
+            parse(env, inspect_json(bytes)?)
+        });
+        finish(env, result)
+    }
+}
+
+unsafe extern "C" fn validate(env: NapiEnv, info: *mut CallbackInfo) -> NapiValue {
+    // SAFETY: called by N-API with the env and info of this call
+    unsafe {
+        let result = string_argument(env, info).and_then(|path| validate_json(&path));
+        let result = result.and_then(|json| parse(env, Some(json)));
+        finish(env, result)
+    }
+}
+
+/// Add the functions to the `exports` of the module; Node calls this when loading the library
+///
+/// # Safety
+///
+/// Only Node should call it, with a live `env` and `exports`.
+#[no_mangle]
+unsafe extern "C" fn napi_register_module_v1(env: NapiEnv, exports: NapiValue) -> NapiValue {
+    let functions: [(&std::ffi::CStr, Callback); 3] = [
+        (c"open", open),
+        (c"inspect", inspect),
+        (c"validate", validate),
+    ];
+    for (name, cb) in functions {
+        let mut function = ptr::null_mut();
+        // SAFETY: the name is NUL-terminated and passed with its length
+        unsafe {
+            let status = napi_create_function(
+                env,
+                name.as_ptr(),
+                name.to_bytes().len(),
+                cb,
+                ptr::null_mut(),
+                &mut function,
+            );
+            if status != OK || napi_set_named_property(env, exports, name.as_ptr(), function) != OK
+            {
+                return finish(env, Err(format!("registering {name:?} failed")));
+            }
+        }
+    }
+    exports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{header_start, temp_path};
+
+    #[test]
+    fn json_results() {
+        let buf = header_start(3, 0, 0);
+        let path = temp_path("napi.gguf");
+        std::fs::write(&path, &buf).unwrap();
+        let path = path.to_str().unwrap();
+
+        let opened: serde_json::Value = serde_json::from_str(&open_json(path).unwrap()).unwrap();
+        assert_eq!(opened["data_offset"], 32);
+        assert!(opened["tensors"].as_array().unwrap().is_empty());
+        let report: serde_json::Value =
+            serde_json::from_str(&validate_json(path).unwrap()).unwrap();
+        assert_eq!(report["valid"], true);
+        assert_eq!(inspect_json(&buf[..6]), Ok(None));
+        assert!(inspect_json(b"junk junk junk").is_err());
+        assert!(open_json("/nonexistent.gguf").is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
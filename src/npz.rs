@@ -0,0 +1,318 @@
+//! # NumPy archives
+//!
+//! Writes the tensors of a GGUF file as the `.npy` members of an uncompressed `.npz` zip
+//! archive, which `numpy.load` opens as a mapping from tensor name to array. Members and
+//! archives over 4 GiB use the zip64 extensions, as large models need.
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::digest::Crc32;
+use crate::quant::dequantize;
+use crate::writer::WriteError;
+use crate::{GGMLType, GGUFFile, GGUFTensorInfo};
+
+/// The NumPy dtype of data GGUF stores as `tensor_type`, for types laid out the same
+fn descr(tensor_type: GGMLType) -> Option<&'static str> {
+    match tensor_type {
+        GGMLType::F32 => Some("<f4"),
+        GGMLType::F16 => Some("<f2"),
+        GGMLType::F64 => Some("<f8"),
+        GGMLType::I8 => Some("|i1"),
+        GGMLType::I16 => Some("<i2"),
+        GGMLType::I32 => Some("<i4"),
+        GGMLType::I64 => Some("<i8"),
+        _ => None,
+    }
+}
+
+/// The `.npy` header of an array, padded so that the data starts 64-byte aligned
+fn npy_header(descr: &str, shape: &[u64]) -> Vec<u8> {
+    let shape = match shape {
+        [n] => format!("({n},)"),
+        shape => format!(
+            "({})",
+            shape
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut dict = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}");
+    let len = (10 + dict.len() + 1).next_multiple_of(64) - 10;
+    while dict.len() < len - 1 {
+        dict.push(' ');
+    }
+    dict.push('\n');
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend((dict.len() as u16).to_le_bytes());
+    header.extend(dict.as_bytes());
+    header
+}
+
+/// A member written, as the central directory lists it
+struct Member {
+    name: String,
+    crc: u32,
+    size: u64,
+    offset: u64,
+}
+
+const ZIP64: u64 = u32::MAX as u64;
+
+/// A zip archive of stored members
+pub(crate) struct ZipWriter<W> {
+    out: W,
+    written: u64,
+    members: Vec<Member>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub(crate) fn new(out: W) -> Self {
+        ZipWriter {
+            out,
+            written: 0,
+            members: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.out.write_all(bytes).map_err(|e| e.to_string())?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Add a member made of `parts`, stored without compression
+    pub(crate) fn add(&mut self, name: String, parts: &[&[u8]]) -> Result<(), String> {
+        let size: u64 = parts.iter().map(|p| p.len() as u64).sum();
+        let mut crc = Crc32::default();
+        for part in parts {
+            crc.update(part);
+        }
+        let crc = crc.finish();
+        let offset = self.written;
+        let large = size >= ZIP64;
+        let mut local = Vec::with_capacity(30 + name.len() + 20);
+        local.extend(0x0403_4b50u32.to_le_bytes());
+        local.extend((if large { 45u16 } else { 20 }).to_le_bytes());
+        // no flags, stored, at midnight on 1980-01-01
+        local.extend([0, 0, 0, 0, 0, 0, 0x21, 0]);
+        local.extend(crc.to_le_bytes());
+        let size32 = size.min(ZIP64) as u32;
+        local.extend(size32.to_le_bytes());
+        local.extend(size32.to_le_bytes());
+        local.extend((name.len() as u16).to_le_bytes());
+        local.extend((if large { 20u16 } else { 0 }).to_le_bytes());
+        local.extend(name.as_bytes());
+        if large {
+            local.extend(1u16.to_le_bytes());
+            local.extend(16u16.to_le_bytes());
+            local.extend(size.to_le_bytes());
+            local.extend(size.to_le_bytes());
+        }
+        self.write(&local)?;
+        for part in parts {
+            self.write(part)?;
+        }
+        self.members.push(Member {
+            name,
+            crc,
+            size,
+            offset,
+        });
+        Ok(())
+    }
+
+    /// Write the central directory and the end records
+    pub(crate) fn finish(mut self) -> Result<W, String> {
+        let start = self.written;
+        for member in std::mem::take(&mut self.members) {
+            let mut extra = Vec::new();
+            if member.size >= ZIP64 {
+                extra.extend(member.size.to_le_bytes());
+                extra.extend(member.size.to_le_bytes());
+            }
+            if member.offset >= ZIP64 {
+                extra.extend(member.offset.to_le_bytes());
+            }
+            let mut entry = Vec::with_capacity(46 + member.name.len() + 4 + extra.len());
+            entry.extend(0x0201_4b50u32.to_le_bytes());
+            entry.extend(45u16.to_le_bytes());
+            entry.extend((if extra.is_empty() { 20u16 } else { 45 }).to_le_bytes());
+            entry.extend([0, 0, 0, 0, 0, 0, 0x21, 0]);
+            entry.extend(member.crc.to_le_bytes());
+            let size32 = member.size.min(ZIP64) as u32;
+            entry.extend(size32.to_le_bytes());
+            entry.extend(size32.to_le_bytes());
+            entry.extend((member.name.len() as u16).to_le_bytes());
+            let extra_len = if extra.is_empty() { 0 } else { 4 + extra.len() };
+            entry.extend((extra_len as u16).to_le_bytes());
+            // no comment, disk 0, no attributes
+            entry.extend([0; 10]);
+            entry.extend((member.offset.min(ZIP64) as u32).to_le_bytes());
+            entry.extend(member.name.as_bytes());
+            if !extra.is_empty() {
+                entry.extend(1u16.to_le_bytes());
+                entry.extend((extra.len() as u16).to_le_bytes());
+                entry.extend(extra);
+            }
+            self.write(&entry)?;
+            self.members.push(member);
+        }
+        let (count, size) = (self.members.len() as u64, self.written - start);
+        let mut end = Vec::new();
+        if count >= 0xffff || size >= ZIP64 || start >= ZIP64 {
+            let record = self.written;
+            end.extend(0x0606_4b50u32.to_le_bytes());
+            end.extend(44u64.to_le_bytes());
+            end.extend(45u16.to_le_bytes());
+            end.extend(45u16.to_le_bytes());
+            end.extend([0; 8]);
+            end.extend(count.to_le_bytes());
+            end.extend(count.to_le_bytes());
+            end.extend(size.to_le_bytes());
+            end.extend(start.to_le_bytes());
+            end.extend(0x0706_4b50u32.to_le_bytes());
+            end.extend(0u32.to_le_bytes());
+            end.extend(record.to_le_bytes());
+            end.extend(1u32.to_le_bytes());
+        }
+        end.extend(0x0605_4b50u32.to_le_bytes());
+        end.extend([0; 4]);
+        end.extend((count.min(0xffff) as u16).to_le_bytes());
+        end.extend((count.min(0xffff) as u16).to_le_bytes());
+        end.extend((size.min(ZIP64) as u32).to_le_bytes());
+        end.extend((start.min(ZIP64) as u32).to_le_bytes());
+        end.extend([0; 2]);
+        self.write(&end)?;
+        self.out.flush().map_err(|e| e.to_string())?;
+        Ok(self.out)
+    }
+}
+
+/// The dtype, shape and bytes of the array a tensor is exported as
+fn array(
+    tensor: &GGUFTensorInfo,
+    data: Vec<u8>,
+    dequantized: bool,
+) -> Result<(&'static str, Vec<u64>, Vec<u8>), String> {
+    let mut shape: Vec<u64> = tensor.dimensions.iter().rev().copied().collect();
+    if dequantized && tensor.tensor_type != GGMLType::F32 {
+        let values = dequantize(tensor.tensor_type, &data)?;
+        return Ok((
+            "<f4",
+            shape,
+            values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        ));
+    }
+    if let Some(descr) = descr(tensor.tensor_type) {
+        return Ok((descr, shape, data));
+    }
+    // quantized rows as their bytes, the innermost dimension counted in bytes
+    let block = tensor.tensor_type.block_size().unwrap_or(1);
+    let type_size = tensor.tensor_type.type_size().unwrap_or(1);
+    if let Some(row) = shape.last_mut() {
+        *row = *row / block * type_size;
+    }
+    Ok(("|u1", shape, data))
+}
+
+impl GGUFFile {
+    /// Export every tensor to the `.npz` archive at `path`, reading the data from `input`, a
+    /// file whose tensor data starts at `data_start`
+    ///
+    /// Arrays are named after the tensors and shaped outermost dimension first. F32, F16 and
+    /// integer tensors keep their type; with `dequantize` every tensor is decoded to f32,
+    /// otherwise quantized tensors are kept as `uint8` arrays of their blocks, one row of bytes
+    /// per row of the tensor.
+    pub fn export_npz(
+        &self,
+        input: &mut (impl Read + Seek),
+        data_start: u64,
+        path: impl AsRef<Path>,
+        dequantize: bool,
+    ) -> Result<(), WriteError> {
+        let out = File::create(path)?;
+        let mut zip = ZipWriter::new(BufWriter::new(out));
+        for tensor in &self.tensors {
+            let size = tensor
+                .size_bytes()
+                .ok_or_else(|| format!("tensor {} has no whole number of blocks", tensor.name))?;
+            input.seek(SeekFrom::Start(data_start + tensor.offset))?;
+            let mut data = vec![0; size as usize];
+            input
+                .read_exact(&mut data)
+                .map_err(|e| format!("reading tensor {}: {e}", tensor.name))?;
+            let (descr, shape, data) = array(tensor, data, dequantize)?;
+            let header = npy_header(descr, &shape);
+            zip.add(format!("{}.npy", tensor.name), &[&header, &data])?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::crc32;
+    use crate::quant::quantize;
+    use crate::test_util::{self, file, temp_path, tensor};
+    use crate::ParseOptions;
+
+    #[test]
+    fn npy_members_in_a_zip() {
+        let header = npy_header("<f4", &[32]);
+        assert_eq!(header.len() % 64, 0);
+        assert!(header.starts_with(b"\x93NUMPY\x01\x00"));
+        assert!(String::from_utf8_lossy(&header)
+            .contains("{'descr': '<f4', 'fortran_order': False, 'shape': (32,), }"));
+        assert!(String::from_utf8_lossy(&npy_header("|u1", &[2, 34])).contains("(2, 34)"));
+
+        let mut file = file(
+            test_util::header(&[]),
+            vec![
+                tensor("token_embd.weight", &[32, 2], GGMLType::Q8_0, 0),
+                tensor("output_norm.weight", &[32], GGMLType::F32, 0),
+            ],
+        );
+        let data_start = crate::writer::assign_offsets(&mut file).unwrap();
+        let values: Vec<f32> = (0..64).map(|i| i as f32).collect();
+        let mut buf = Vec::new();
+        crate::writer::write_header(&mut buf, &file).unwrap();
+        buf.resize(data_start as usize, 0);
+        for tensor in &file.tensors {
+            buf.resize((data_start + tensor.offset) as usize, 0);
+            let n = tensor.element_count() as usize;
+            buf.extend(quantize(tensor.tensor_type, &values[..n]).unwrap());
+        }
+        let (parsed, data_start) =
+            GGUFFile::read_from(&mut buf.as_slice(), &ParseOptions::default()).unwrap();
+
+        let path = temp_path("gguf-npz.npz");
+        for dequantize in [false, true] {
+            let mut input = std::io::Cursor::new(&buf);
+            parsed
+                .export_npz(&mut input, data_start, &path, dequantize)
+                .unwrap();
+            let zip = std::fs::read(&path).unwrap();
+            let end = &zip[zip.len() - 22..];
+            assert_eq!(end[..4], 0x0605_4b50u32.to_le_bytes());
+            assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+            let first = 30 + "token_embd.weight.npy".len();
+            assert_eq!(zip[30..first], *b"token_embd.weight.npy");
+            let npy = String::from_utf8_lossy(&zip[first..first + 64]).to_string();
+            let (descr, size) = if dequantize {
+                ("<f4", 64 * 4)
+            } else {
+                ("|u1", 2 * 34)
+            };
+            assert!(npy.contains(&format!("'descr': '{descr}'")), "{npy}");
+            let header = 10 + u16::from_le_bytes([zip[first + 8], zip[first + 9]]) as usize;
+            let crc = u32::from_le_bytes([zip[14], zip[15], zip[16], zip[17]]);
+            assert_eq!(crc, crc32(&zip[first..first + header + size]));
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
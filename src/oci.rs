@@ -0,0 +1,679 @@
+//! # OCI artifacts
+//!
+//! Pushes GGUF files to container registries as OCI artifacts and pulls them back, through the
+//! HTTP API of the OCI distribution spec. The file is the single layer of an image manifest of
+//! artifact type [`ARTIFACT_TYPE`] with the empty config OCI 1.1 recommends for artifacts, and
+//! the manifest carries [`annotations`] from the header: the standard
+//! `org.opencontainers.image.*` ones and the architecture, quantization and size of the model
+//! under `org.gguf.*`. Registries that ask for a bearer token get one from the realm they name;
+//! `https://` ones are reached through `curl`, as [`crate::remote`] does.
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::digest::{hex, sha256_hex, Sha256};
+use crate::remote::{origin, request, Body, Head, Upload};
+use crate::{GGUFFile, GGUFMetadataValue, ParseOptions};
+
+/// The `artifactType` of the manifests [`push`] writes
+pub const ARTIFACT_TYPE: &str = "application/vnd.gguf.model.v1";
+/// The media type of the GGUF layer
+pub const LAYER_MEDIA_TYPE: &str = "application/vnd.gguf.file.v1";
+/// Layer media types of GGUF files other tools push, which [`pull`] also takes
+pub const KNOWN_LAYER_MEDIA_TYPES: &[&str] = &[
+    LAYER_MEDIA_TYPE,
+    "application/vnd.docker.ai.gguf.v3",
+    crate::ollama::MODEL_MEDIA_TYPE,
+];
+
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const EMPTY_MEDIA_TYPE: &str = "application/vnd.oci.empty.v1+json";
+const MAX_REDIRECTS: usize = 10;
+
+/// A repository in a registry and a tag or digest in it, as in
+/// `ghcr.io/org/model:q4_k_m` or `localhost:5000/model@sha256:...`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    /// `http` or `https`; `http` only when given or for `localhost`.
+    pub scheme: String,
+    pub registry: String,
+    pub repository: String,
+    /// A tag, `latest` when none is given, or a `sha256:` digest.
+    pub reference: String,
+}
+
+impl Reference {
+    /// Parse a reference the way container tools do: the first part is the registry when it
+    /// has a dot or port or is `localhost`, otherwise the registry is Docker Hub
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let invalid =
+            || format!("{name} is not a registry reference such as ghcr.io/org/model:tag");
+        let (scheme, rest) = match name.split_once("://") {
+            Some((scheme @ ("http" | "https"), rest)) => (Some(scheme), rest),
+            Some(_) => return Err(invalid()),
+            None => (None, name),
+        };
+        let (registry, path) = match rest.split_once('/') {
+            Some((first, path))
+                if first.contains('.') || first.contains(':') || first == "localhost" =>
+            {
+                (first, path.to_string())
+            }
+            _ if rest.contains('/') => ("docker.io", rest.to_string()),
+            _ => ("docker.io", format!("library/{rest}")),
+        };
+        let (repository, reference) = match path.split_once('@') {
+            Some((repository, digest)) => (repository, digest),
+            None => match path.rsplit_once(':') {
+                Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+                _ => (path.as_str(), "latest"),
+            },
+        };
+        let valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-/".contains(c);
+        if repository.is_empty() || !repository.chars().all(valid) || reference.is_empty() {
+            return Err(invalid());
+        }
+        let host = registry.split(':').next().unwrap_or_default();
+        let local = host == "localhost" || host == "127.0.0.1";
+        Ok(Reference {
+            scheme: scheme
+                .unwrap_or(if local { "http" } else { "https" })
+                .to_string(),
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            reference: reference.to_string(),
+        })
+    }
+
+    /// The URL of `path` under the repository in the registry API
+    fn url(&self, path: &str) -> String {
+        let host = match self.registry.as_str() {
+            "docker.io" => "registry-1.docker.io",
+            registry => registry,
+        };
+        format!("{}://{host}/v2/{}/{path}", self.scheme, self.repository)
+    }
+}
+
+impl std::fmt::Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let separator = if self.reference.contains(':') {
+            '@'
+        } else {
+            ':'
+        };
+        write!(
+            f,
+            "{}/{}{separator}{}",
+            self.registry, self.repository, self.reference
+        )
+    }
+}
+
+/// A user name and password or token for a registry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// A GGUF artifact pushed or pulled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    /// The digest of the manifest, which names this exact artifact.
+    pub manifest_digest: String,
+    /// The digest and size of the GGUF layer.
+    pub layer_digest: String,
+    pub size: u64,
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// The manifest annotations of a GGUF file, from its general metadata and hyperparameters
+pub fn annotations(file: &GGUFFile) -> BTreeMap<String, String> {
+    let header = &file.header;
+    let mut annotations = BTreeMap::new();
+    let text = |key: &str| header.get(key).and_then(GGUFMetadataValue::as_str);
+    for (annotation, key) in [
+        ("org.opencontainers.image.title", "general.name"),
+        (
+            "org.opencontainers.image.description",
+            "general.description",
+        ),
+        ("org.opencontainers.image.version", "general.version"),
+        ("org.opencontainers.image.authors", "general.author"),
+        ("org.opencontainers.image.vendor", "general.organization"),
+        ("org.opencontainers.image.licenses", "general.license"),
+        ("org.opencontainers.image.url", "general.url"),
+        ("org.opencontainers.image.source", "general.source.url"),
+        ("org.gguf.quantized_by", "general.quantized_by"),
+    ] {
+        if let Some(value) = text(key) {
+            annotations.insert(annotation.to_string(), value.to_string());
+        }
+    }
+    let arch = text("general.architecture");
+    if let Some(arch) = arch {
+        annotations.insert("org.gguf.architecture".to_string(), arch.to_string());
+    }
+    if let Some(file_type) = header.file_type().filter(|t| t.name().is_some()) {
+        annotations.insert("org.gguf.file_type".to_string(), file_type.to_string());
+    }
+    if let Some(length) = arch
+        .and_then(|arch| header.get(&format!("{arch}.context_length")))
+        .and_then(|v| v.as_u64())
+    {
+        annotations.insert("org.gguf.context_length".to_string(), length.to_string());
+    }
+    let parameters: u64 = file.tensors.iter().map(|t| t.element_count()).sum();
+    annotations.insert("org.gguf.parameters".to_string(), parameters.to_string());
+    annotations.insert("org.gguf.version".to_string(), header.version.to_string());
+    annotations
+}
+
+/// Standard base64 with padding, for basic authentication
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// The `key="value"` parameters of a `WWW-Authenticate` challenge
+fn challenge_params(params: &str) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key
+            .trim()
+            .trim_start_matches(',')
+            .trim()
+            .to_ascii_lowercase();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        out.insert(key, value.to_string());
+        rest = after.trim_start_matches(',').trim();
+    }
+    out
+}
+
+/// The body of a response, read to the end
+fn read_body(mut body: Body) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    body.reader
+        .read_to_end(&mut data)
+        .map_err(|e| e.to_string())?;
+    Ok(data)
+}
+
+/// The error of a failed request, with the messages the registry gives
+fn failed(method: &str, url: &str, head: &Head, body: Body) -> String {
+    let body = read_body(body).unwrap_or_default();
+    let errors: Vec<String> = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|doc| doc["errors"].as_array().cloned())
+        .unwrap_or_default()
+        .iter()
+        .map(|e| {
+            format!(
+                "{} {}",
+                e["code"].as_str().unwrap_or_default(),
+                e["message"].as_str().unwrap_or_default()
+            )
+        })
+        .collect();
+    match errors.as_slice() {
+        [] => format!("{method} {url}: HTTP {}", head.status),
+        errors => format!(
+            "{method} {url}: HTTP {}: {}",
+            head.status,
+            errors.join("; ")
+        ),
+    }
+}
+
+/// Requests to one repository, with the authorization the registry asked for so far
+struct Client<'a> {
+    reference: &'a Reference,
+    credentials: Option<&'a Credentials>,
+    authorization: Option<String>,
+}
+
+impl Client<'_> {
+    /// Send a request, getting a token and sending it again when the registry asks for one
+    fn send(
+        &mut self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        upload: Upload,
+    ) -> Result<(Head, Body), String> {
+        let send = |authorization: &Option<String>| {
+            let mut all = headers.to_vec();
+            if let Some(authorization) = authorization {
+                all.push(("Authorization", authorization));
+            }
+            request(method, url, &all, upload).map_err(|e| format!("{method} {url}: {e}"))
+        };
+        let (head, body) = send(&self.authorization)?;
+        match head.get("www-authenticate") {
+            Some(challenge) if head.status == 401 => {
+                let challenge = challenge.to_string();
+                drop(body);
+                self.authenticate(&challenge)?;
+                send(&self.authorization)
+            }
+            _ => Ok((head, body)),
+        }
+    }
+
+    /// Answer a challenge: basic authentication with the credentials, or a bearer token from
+    /// the realm it names
+    fn authenticate(&mut self, challenge: &str) -> Result<(), String> {
+        let (scheme, params) = challenge.split_once(' ').unwrap_or((challenge, ""));
+        let basic = self.credentials.map(|c| {
+            format!(
+                "Basic {}",
+                base64(format!("{}:{}", c.username, c.password).as_bytes())
+            )
+        });
+        if scheme.eq_ignore_ascii_case("basic") {
+            let needed = || format!("{} needs credentials", self.reference.registry);
+            self.authorization = Some(basic.ok_or_else(needed)?);
+            return Ok(());
+        }
+        let params = challenge_params(params);
+        let realm = params
+            .get("realm")
+            .ok_or_else(|| format!("the challenge {challenge:?} names no realm"))?;
+        let query: Vec<String> = ["service", "scope"]
+            .iter()
+            .filter_map(|&key| Some(format!("{key}={}", params.get(key)?.replace(' ', "%20"))))
+            .collect();
+        let url = if query.is_empty() {
+            realm.clone()
+        } else {
+            format!("{realm}?{}", query.join("&"))
+        };
+        let headers: Vec<(&str, &str)> = basic
+            .as_deref()
+            .map(|basic| ("Authorization", basic))
+            .into_iter()
+            .collect();
+        let (head, body) =
+            request("GET", &url, &headers, Upload::None).map_err(|e| format!("GET {url}: {e}"))?;
+        if head.status != 200 {
+            return Err(failed("GET", &url, &head, body));
+        }
+        let doc: Value = serde_json::from_slice(&read_body(body)?).map_err(|e| e.to_string())?;
+        let token = doc["token"]
+            .as_str()
+            .or(doc["access_token"].as_str())
+            .ok_or_else(|| format!("{url} gave no token"))?;
+        self.authorization = Some(format!("Bearer {token}"));
+        Ok(())
+    }
+
+    /// GET `url`, following redirects, which registries use to send blobs from storage; the
+    /// authorization only goes to the registry itself
+    fn get(&mut self, url: &str, headers: &[(&str, &str)]) -> Result<(Head, Body), String> {
+        let mut url = url.to_string();
+        for _ in 0..MAX_REDIRECTS {
+            let (head, body) = if origin(&url) == origin(&self.reference.url("")) {
+                self.send("GET", &url, headers, Upload::None)?
+            } else {
+                request("GET", &url, headers, Upload::None)
+                    .map_err(|e| format!("GET {url}: {e}"))?
+            };
+            match (head.status, head.get("location")) {
+                (301 | 302 | 303 | 307 | 308, Some(location)) => url = absolute(&url, location),
+                _ => return Ok((head, body)),
+            }
+        }
+        Err(format!("too many redirects from {url}"))
+    }
+
+    /// Upload a blob unless the repository has it
+    fn upload(&mut self, digest: &str, upload: Upload) -> Result<(), String> {
+        let url = self.reference.url(&format!("blobs/{digest}"));
+        let (head, _) = self.send("HEAD", &url, &[], Upload::None)?;
+        if head.status == 200 {
+            return Ok(());
+        }
+        let url = self.reference.url("blobs/uploads/");
+        let (head, body) = self.send("POST", &url, &[], Upload::Bytes(&[]))?;
+        let location = match (head.status, head.get("location")) {
+            (202, Some(location)) => absolute(&url, location),
+            _ => return Err(failed("POST", &url, &head, body)),
+        };
+        let separator = if location.contains('?') { '&' } else { '?' };
+        let url = format!("{location}{separator}digest={}", digest.replace(':', "%3A"));
+        let headers = [("Content-Type", "application/octet-stream")];
+        let (head, body) = self.send("PUT", &url, &headers, upload)?;
+        if head.status != 201 {
+            return Err(failed("PUT", &url, &head, body));
+        }
+        Ok(())
+    }
+}
+
+/// `location` from a response to `url`, made absolute
+fn absolute(url: &str, location: &str) -> String {
+    match location.strip_prefix('/') {
+        Some(path) => format!("{}/{path}", origin(url)),
+        None => location.to_string(),
+    }
+}
+
+/// Push the GGUF file at `path` to the registry as an artifact tagged `reference`
+pub fn push(
+    path: impl AsRef<Path>,
+    reference: &Reference,
+    credentials: Option<&Credentials>,
+) -> Result<Artifact, String> {
+    let path = path.as_ref();
+    let mut input = File::open(path).map_err(|e| e.to_string())?;
+    let (file, _) =
+        GGUFFile::read_from(&mut input, &ParseOptions::default()).map_err(|e| e.to_string())?;
+    let mut input = File::open(path).map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::default();
+    let mut buf = vec![0; 1 << 20];
+    let mut size = 0;
+    loop {
+        let n = input.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    let layer_digest = format!("sha256:{}", hex(&hasher.finish()));
+    let annotations = annotations(&file);
+
+    let mut client = Client {
+        reference,
+        credentials,
+        authorization: None,
+    };
+    client.upload(&layer_digest, Upload::File(path))?;
+    let empty = b"{}";
+    let empty_digest = format!("sha256:{}", sha256_hex(empty));
+    client.upload(&empty_digest, Upload::Bytes(empty))?;
+    let title = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let manifest = json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_MEDIA_TYPE,
+        "artifactType": ARTIFACT_TYPE,
+        "config": {"mediaType": EMPTY_MEDIA_TYPE, "digest": empty_digest, "size": empty.len()},
+        "layers": [{
+            "mediaType": LAYER_MEDIA_TYPE,
+            "digest": layer_digest,
+            "size": size,
+            "annotations": {"org.opencontainers.image.title": title},
+        }],
+        "annotations": annotations,
+    });
+    let manifest = serde_json::to_vec(&manifest).map_err(|e| e.to_string())?;
+    let url = reference.url(&format!("manifests/{}", reference.reference));
+    let headers = [("Content-Type", MANIFEST_MEDIA_TYPE)];
+    let (head, body) = client.send("PUT", &url, &headers, Upload::Bytes(&manifest))?;
+    if head.status != 201 {
+        return Err(failed("PUT", &url, &head, body));
+    }
+    Ok(Artifact {
+        manifest_digest: format!("sha256:{}", sha256_hex(&manifest)),
+        layer_digest,
+        size,
+        annotations,
+    })
+}
+
+/// Pull the GGUF layer of the artifact `reference` to `dst`, checking its digest
+///
+/// The file is written next to `dst` and renamed once complete.
+pub fn pull(
+    reference: &Reference,
+    dst: impl AsRef<Path>,
+    credentials: Option<&Credentials>,
+) -> Result<Artifact, String> {
+    let mut client = Client {
+        reference,
+        credentials,
+        authorization: None,
+    };
+    let url = reference.url(&format!("manifests/{}", reference.reference));
+    let accept = [(
+        "Accept",
+        "application/vnd.oci.image.manifest.v1+json, \
+         application/vnd.docker.distribution.manifest.v2+json",
+    )];
+    let (head, body) = client.get(&url, &accept)?;
+    if head.status != 200 {
+        return Err(failed("GET", &url, &head, body));
+    }
+    let data = read_body(body)?;
+    let manifest_digest = format!("sha256:{}", sha256_hex(&data));
+    if reference.reference.starts_with("sha256:") && reference.reference != manifest_digest {
+        return Err(format!(
+            "the manifest of {reference} has digest {manifest_digest}"
+        ));
+    }
+    let manifest: Value = serde_json::from_slice(&data).map_err(|e| e.to_string())?;
+    let layer = manifest["layers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|layer| {
+            let media_type = layer["mediaType"].as_str().unwrap_or_default();
+            KNOWN_LAYER_MEDIA_TYPES.contains(&media_type)
+        })
+        .ok_or_else(|| format!("{reference} has no GGUF layer"))?;
+    let layer_digest = layer["digest"].as_str().unwrap_or_default().to_string();
+    let size = layer["size"].as_u64().unwrap_or_default();
+    let annotations = manifest["annotations"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+        .collect();
+
+    let url = reference.url(&format!("blobs/{layer_digest}"));
+    let (head, mut body) = client.get(&url, &[])?;
+    if head.status != 200 {
+        return Err(failed("GET", &url, &head, body));
+    }
+    let dst = dst.as_ref();
+    let mut partial = dst.as_os_str().to_owned();
+    partial.push(".partial");
+    let mut write = || -> Result<(), String> {
+        let mut out = File::create(&partial).map_err(|e| e.to_string())?;
+        let mut hasher = Sha256::default();
+        let mut buf = vec![0; 1 << 20];
+        let mut written = 0;
+        loop {
+            let n = body.reader.read(&mut buf).map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            out.write_all(&buf[..n]).map_err(|e| e.to_string())?;
+            written += n as u64;
+        }
+        let digest = format!("sha256:{}", hex(&hasher.finish()));
+        if written != size || digest != layer_digest {
+            return Err(format!(
+                "the layer of {reference} arrived as {written} bytes of digest {digest}, \
+                 expected {size} of {layer_digest}"
+            ));
+        }
+        Ok(())
+    };
+    match write() {
+        Ok(()) => std::fs::rename(&partial, dst).map_err(|e| e.to_string())?,
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e);
+        }
+    }
+    Ok(Artifact {
+        manifest_digest,
+        layer_digest,
+        size,
+        annotations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path};
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn parses_references() {
+        let parse = |name| Reference::parse(name).unwrap();
+        let reference = parse("ghcr.io/org/model:q4_k_m");
+        assert_eq!(
+            (reference.registry.as_str(), reference.repository.as_str()),
+            ("ghcr.io", "org/model")
+        );
+        assert_eq!(
+            reference.url("manifests/q4_k_m"),
+            "https://ghcr.io/v2/org/model/manifests/q4_k_m"
+        );
+        let reference = parse("localhost:5000/model");
+        assert_eq!(
+            (reference.scheme.as_str(), reference.reference.as_str()),
+            ("http", "latest")
+        );
+        assert_eq!(parse("tinyllama").repository, "library/tinyllama");
+        assert_eq!(
+            parse("org/model@sha256:ab").to_string(),
+            "docker.io/org/model@sha256:ab"
+        );
+        assert!(Reference::parse("ghcr.io/Org/Model").is_err());
+        assert_eq!(base64(b"user:pass"), "dXNlcjpwYXNz");
+    }
+
+    /// a registry keeping blobs and manifests in memory, asking for a bearer token first
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let store: Arc<Mutex<HashMap<String, Vec<u8>>>> = Arc::default();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap().to_string();
+                let path = parts.next().unwrap().to_string();
+                let (mut len, mut authorized) = (0, false);
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                    let (name, value) = line.trim_end().split_once(": ").unwrap();
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-length" => len = value.parse().unwrap(),
+                        "authorization" => authorized = value == "Bearer secret",
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                let mut store = store.lock().unwrap();
+                let (status, headers, body) = match (method.as_str(), path.as_str()) {
+                    (_, path) if path.starts_with("/token?service=test&scope=") => {
+                        ("200 OK", String::new(), b"{\"token\":\"secret\"}".to_vec())
+                    }
+                    _ if !authorized => {
+                        let challenge = format!(
+                            "WWW-Authenticate: Bearer realm=\"http://{address}/token\",\
+                             service=\"test\",scope=\"repository:models/tiny:pull,push\"\r\n"
+                        );
+                        ("401 Unauthorized", challenge, Vec::new())
+                    }
+                    ("POST", _) => {
+                        let location = "Location: /v2/models/tiny/blobs/uploads/1\r\n";
+                        ("202 Accepted", location.to_string(), Vec::new())
+                    }
+                    ("PUT", path) => {
+                        let key = match path.split_once("?digest=") {
+                            Some((_, digest)) => digest.replace("%3A", ":"),
+                            None => path.rsplit('/').next().unwrap().to_string(),
+                        };
+                        store.insert(key, body);
+                        ("201 Created", String::new(), Vec::new())
+                    }
+                    (method, path) => match store.get(path.rsplit('/').next().unwrap()) {
+                        Some(data) if method == "GET" => ("200 OK", String::new(), data.clone()),
+                        Some(_) => ("200 OK", String::new(), Vec::new()),
+                        None => {
+                            let error =
+                                r#"{"errors":[{"code":"BLOB_UNKNOWN","message":"unknown"}]}"#;
+                            ("404 Not Found", String::new(), error.as_bytes().to_vec())
+                        }
+                    },
+                };
+                write!(stream, "HTTP/1.0 {status}\r\n{headers}\r\n").unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        format!("{address}/models/tiny:q8_0")
+    }
+
+    #[test]
+    fn pushes_and_pulls() {
+        let dir = temp_path("gguf-oci");
+        std::fs::create_dir_all(&dir).unwrap();
+        let header = header(&[
+            (
+                "general.architecture",
+                GGUFMetadataValue::String("llama".to_string()),
+            ),
+            ("general.file_type", GGUFMetadataValue::Uint32(7)),
+        ]);
+        let file = file(header, Vec::new());
+        let src = dir.join("tiny.gguf");
+        crate::writer::write_file(&src, &file, |_, _| Ok(())).unwrap();
+
+        let reference = Reference::parse(&format!("http://{}", serve())).unwrap();
+        let pushed = push(&src, &reference, None).unwrap();
+        assert_eq!(pushed.annotations["org.gguf.architecture"], "llama");
+        assert_eq!(pushed.annotations["org.gguf.file_type"], "Q8_0");
+        let dst = dir.join("pulled.gguf");
+        let pulled = pull(&reference, &dst, None).unwrap();
+        assert_eq!(pulled, pushed);
+        assert_eq!(std::fs::read(&dst).unwrap(), std::fs::read(&src).unwrap());
+
+        let missing = Reference::parse(&format!("http://{}", serve().replace("q8_0", "f16")));
+        let error = pull(&missing.unwrap(), &dst, None).unwrap_err();
+        assert!(error.contains("HTTP 404: BLOB_UNKNOWN unknown"), "{error}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
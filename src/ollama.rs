@@ -0,0 +1,137 @@
+//! # Ollama models
+//!
+//! Ollama keeps pulled models in a content-addressed store, by default `~/.ollama/models` or
+//! `$OLLAMA_MODELS`: a manifest per model at `manifests/<registry>/<namespace>/<model>/<tag>`
+//! lists its layers by digest, and each layer is a file under `blobs/`. The weights are the
+//! layer of media type `application/vnd.ollama.image.model`, a plain GGUF file.
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::{GGUFFile, ParseOptions};
+
+/// The media type of the GGUF layer of a manifest
+pub const MODEL_MEDIA_TYPE: &str = "application/vnd.ollama.image.model";
+
+/// A locally pulled model, opened
+#[derive(Debug, Clone)]
+pub struct OllamaModel {
+    /// The GGUF blob in the store.
+    pub path: PathBuf,
+    pub file: GGUFFile,
+    /// The offset of the tensor data in the blob.
+    pub data_start: u64,
+}
+
+/// The store of the local Ollama install, `$OLLAMA_MODELS` or `~/.ollama/models`
+pub fn models_dir() -> Result<PathBuf, String> {
+    if let Some(dir) = std::env::var_os("OLLAMA_MODELS") {
+        return Ok(PathBuf::from(dir));
+    }
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .ok_or("neither OLLAMA_MODELS nor a home directory is set")?;
+    Ok(Path::new(&home).join(".ollama").join("models"))
+}
+
+/// The manifest path of a model name, in Ollama's forms `model`, `model:tag`,
+/// `namespace/model:tag` and `registry/namespace/model:tag`
+fn manifest_path(models: &Path, name: &str) -> Result<PathBuf, String> {
+    let (name, tag) = match name.rsplit_once(':') {
+        // a colon before the last slash is a registry port
+        Some((name, tag)) if !tag.contains('/') => (name, tag),
+        _ => (name, "latest"),
+    };
+    let parts: Vec<&str> = name.split('/').collect();
+    let (registry, namespace, model) = match parts[..] {
+        [model] => ("registry.ollama.ai", "library", model),
+        [namespace, model] => ("registry.ollama.ai", namespace, model),
+        [registry, namespace, model] => (registry, namespace, model),
+        _ => return Err(format!("{name} is not an Ollama model name")),
+    };
+    if [registry, namespace, model, tag]
+        .iter()
+        .any(|p| p.is_empty() || *p == "." || *p == "..")
+    {
+        return Err(format!("{name}:{tag} is not an Ollama model name"));
+    }
+    Ok(models
+        .join("manifests")
+        .join(registry)
+        .join(namespace)
+        .join(model)
+        .join(tag))
+}
+
+/// The path of the GGUF blob of model `name` in the store `models`
+pub fn blob_path(models: &Path, name: &str) -> Result<PathBuf, String> {
+    let manifest = manifest_path(models, name)?;
+    let text = std::fs::read_to_string(&manifest).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => format!("{name} is not pulled, no {}", manifest.display()),
+        _ => format!("{}: {e}", manifest.display()),
+    })?;
+    let manifest: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("the manifest of {name}: {e}"))?;
+    let digest = manifest["layers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|layer| layer["mediaType"] == MODEL_MEDIA_TYPE)
+        .and_then(|layer| layer["digest"].as_str())
+        .ok_or_else(|| format!("the manifest of {name} has no model layer"))?;
+    // blobs are named by digest with a dash, sha256-<hex>
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .filter(|(a, h)| !a.is_empty() && h.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or_else(|| format!("the manifest of {name} has the invalid digest {digest}"))?;
+    Ok(models.join("blobs").join(format!("{algorithm}-{hex}")))
+}
+
+/// Find model `name` in the local store and read its header and tensor infos
+pub fn resolve(name: &str) -> Result<OllamaModel, String> {
+    let path = blob_path(&models_dir()?, name)?;
+    let mut f = File::open(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let (file, data_start) = GGUFFile::read_from(&mut f, &ParseOptions::default())
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(OllamaModel {
+        path,
+        file,
+        data_start,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    #[test]
+    fn finds_model_blobs() {
+        let models = temp_path("ollama");
+        let manifest = models.join("manifests/registry.ollama.ai/library/llama3");
+        std::fs::create_dir_all(&manifest).unwrap();
+        let layers = serde_json::json!({
+            "schemaVersion": 2,
+            "layers": [
+                {"mediaType": "application/vnd.ollama.image.template", "digest": "sha256:01"},
+                {"mediaType": MODEL_MEDIA_TYPE, "digest": "sha256:ab12", "size": 4},
+            ],
+        });
+        std::fs::write(manifest.join("8b"), layers.to_string()).unwrap();
+
+        let blob = models.join("blobs/sha256-ab12");
+        assert_eq!(blob_path(&models, "llama3:8b"), Ok(blob.clone()));
+        assert_eq!(
+            blob_path(&models, "registry.ollama.ai/library/llama3:8b"),
+            Ok(blob)
+        );
+        assert!(blob_path(&models, "llama3")
+            .unwrap_err()
+            .contains("not pulled"));
+        assert_eq!(
+            manifest_path(&models, "localhost:5000/me/tiny").unwrap(),
+            models.join("manifests/localhost:5000/me/tiny/latest")
+        );
+        assert!(manifest_path(&models, "../x").is_err());
+        std::fs::remove_dir_all(&models).unwrap();
+    }
+}
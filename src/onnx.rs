@@ -0,0 +1,177 @@
+//! # ONNX external data
+//!
+//! Writes the tensors of a GGUF file as one raw blob in the layout ONNX keeps initializers
+//! outside the model protobuf, with a JSON manifest giving each tensor the `data_type`, `dims`
+//! and `location`, `offset` and `length` entries its `TensorProto.external_data` names. Tools
+//! building an ONNX graph around the weights point their initializers at the blob as it is.
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::quant::{dequantize, quantize};
+use crate::writer::WriteError;
+use crate::{GGMLType, GGUFFile};
+
+/// Tensors from this size on start at [`PAGE`] offsets so they can be memory-mapped, as the
+/// `onnx` package aligns them
+const ALIGN_THRESHOLD: u64 = 1 << 20;
+const PAGE: u64 = 4096;
+
+/// The `TensorProto.DataType` of data GGUF stores as `tensor_type`, for types laid out the same
+fn data_type(tensor_type: GGMLType) -> Option<i32> {
+    match tensor_type {
+        GGMLType::F32 => Some(1),
+        GGMLType::I8 => Some(3),
+        GGMLType::I16 => Some(5),
+        GGMLType::I32 => Some(6),
+        GGMLType::I64 => Some(7),
+        GGMLType::F16 => Some(10),
+        GGMLType::F64 => Some(11),
+        GGMLType::BF16 => Some(16),
+        _ => None,
+    }
+}
+
+/// A tensor in the blob, as the manifest lists it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExternalTensor {
+    pub name: String,
+    /// The ONNX `TensorProto.DataType`, e.g. 1 for float and 10 for float16.
+    pub data_type: i32,
+    /// Outermost dimension first, as ONNX orders them.
+    pub dims: Vec<u64>,
+    /// The blob's file name, relative to the manifest.
+    pub location: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl GGUFFile {
+    /// Export every tensor to the ONNX external data blob at `path`, reading the data from
+    /// `input`, a file whose tensor data starts at `data_start`, and write the manifest with
+    /// the metadata to `path` with the extension `.json`
+    ///
+    /// F32, F16, BF16 and integer tensors keep their type; quantized ones, which ONNX has no
+    /// type for, are dequantized to `float`, F32 or F16. Returns the tensors written.
+    pub fn export_onnx_data(
+        &self,
+        input: &mut (impl Read + Seek),
+        data_start: u64,
+        path: impl AsRef<Path>,
+        float: GGMLType,
+    ) -> Result<Vec<ExternalTensor>, WriteError> {
+        let float_type = match float {
+            GGMLType::F32 | GGMLType::F16 => data_type(float).unwrap_or_default(),
+            other => return Err(format!("cannot dequantize to {other:?}, use F32 or F16").into()),
+        };
+        let path = path.as_ref();
+        let location = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut out = BufWriter::new(File::create(path)?);
+        let mut tensors = Vec::new();
+        let mut end = 0u64;
+        for tensor in &self.tensors {
+            let size = tensor
+                .size_bytes()
+                .ok_or_else(|| format!("tensor {} has no whole number of blocks", tensor.name))?;
+            input.seek(SeekFrom::Start(data_start + tensor.offset))?;
+            let mut data = vec![0; size as usize];
+            input
+                .read_exact(&mut data)
+                .map_err(|e| format!("reading tensor {}: {e}", tensor.name))?;
+            let data_type = match data_type(tensor.tensor_type) {
+                Some(data_type) => data_type,
+                None => {
+                    data = quantize(float, &dequantize(tensor.tensor_type, &data)?)?;
+                    float_type
+                }
+            };
+            let length = data.len() as u64;
+            let offset = match length {
+                ALIGN_THRESHOLD.. => end.next_multiple_of(PAGE),
+                _ => end,
+            };
+            out.write_all(&vec![0; (offset - end) as usize])
+                .and_then(|()| out.write_all(&data))?;
+            end = offset + length;
+            tensors.push(ExternalTensor {
+                name: tensor.name.clone(),
+                data_type,
+                dims: tensor.dimensions.iter().rev().copied().collect(),
+                location: location.clone(),
+                offset,
+                length,
+            });
+        }
+        out.flush()?;
+
+        let manifest = serde_json::json!({
+            "location": location,
+            "metadata": self.header,
+            "tensors": tensors,
+        });
+        let manifest = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+        std::fs::write(path.with_extension("json"), manifest)?;
+        Ok(tensors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path, tensor};
+    use crate::ParseOptions;
+
+    #[test]
+    fn blob_with_offsets() {
+        let mut file = file(
+            header(&[]),
+            vec![
+                tensor("token_embd.weight", &[32, 2], GGMLType::Q8_0, 0),
+                tensor("output_norm.weight", &[3], GGMLType::F32, 0),
+                tensor("output.weight", &[512, 1024], GGMLType::F32, 0),
+            ],
+        );
+        crate::writer::assign_offsets(&mut file).unwrap();
+        let mut buf = Vec::new();
+        crate::writer::write_header(&mut buf, &file).unwrap();
+        let data_start = buf.len().next_multiple_of(32);
+        for tensor in &file.tensors {
+            buf.resize(data_start + tensor.offset as usize, 0);
+            let values: Vec<f32> = (0..tensor.element_count()).map(|i| i as f32).collect();
+            buf.extend(quantize(tensor.tensor_type, &values).unwrap());
+        }
+        let (parsed, data_start) =
+            GGUFFile::read_from(&mut buf.as_slice(), &ParseOptions::default()).unwrap();
+
+        let path = temp_path("gguf-onnx.onnx_data");
+        let mut input = std::io::Cursor::new(&buf);
+        let tensors = parsed
+            .export_onnx_data(&mut input, data_start, &path, GGMLType::F16)
+            .unwrap();
+        let placed: Vec<_> = tensors
+            .iter()
+            .map(|t| (t.data_type, t.dims.clone(), t.offset, t.length))
+            .collect();
+        assert_eq!(
+            placed,
+            [
+                (10, vec![2, 32], 0, 128),
+                (1, vec![3], 128, 12),
+                (1, vec![1024, 512], 4096, 1 << 21),
+            ]
+        );
+        let blob = std::fs::read(&path).unwrap();
+        assert_eq!(blob.len(), 4096 + (1 << 21));
+        assert_eq!(blob[136..140], 2f32.to_le_bytes());
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(path.with_extension("json")).unwrap()).unwrap();
+        assert_eq!(manifest["tensors"][2]["location"], tensors[2].location);
+        std::fs::remove_file(path.with_extension("json")).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,390 @@
+//! # Compressed packages
+//!
+//! Packs a GGUF file into a seekable zstd archive, in the [seekable format] of zstd's contrib:
+//! independent frames followed by a skippable frame holding a seek table of their compressed
+//! and decompressed sizes. Frames start at the header, at every tensor and every
+//! [`PackageOptions::max_frame_size`] bytes within a tensor, so a tensor is read by
+//! decompressing only its own frames. The archive decompresses to the original file with plain
+//! `zstd -d`, and [`PackageReader`] reads and seeks it as that file, which the parser and every
+//! reader of tensor data take as they take a [`File`]. Compression goes through the system
+//! `libzstd`.
+//!
+//! [seekable format]: https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md
+use std::ffi::{c_int, c_uint, CStr};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::{GGUFFile, ParseOptions};
+
+#[link(name = "zstd")]
+extern "C" {
+    fn ZSTD_compressBound(src_size: usize) -> usize;
+    fn ZSTD_compress(
+        dst: *mut u8,
+        dst_capacity: usize,
+        src: *const u8,
+        src_size: usize,
+        level: c_int,
+    ) -> usize;
+    fn ZSTD_decompress(
+        dst: *mut u8,
+        dst_capacity: usize,
+        src: *const u8,
+        compressed_size: usize,
+    ) -> usize;
+    fn ZSTD_isError(code: usize) -> c_uint;
+    fn ZSTD_getErrorName(code: usize) -> *const std::ffi::c_char;
+}
+
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A5E;
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+/// The number of frames, the descriptor byte and the magic number closing the seek table
+const FOOTER_LEN: u64 = 9;
+
+/// The result of a zstd call, or its error
+fn check(code: usize) -> Result<usize, String> {
+    // SAFETY: both take any value and ZSTD_getErrorName returns a static string
+    unsafe {
+        if ZSTD_isError(code) == 0 {
+            return Ok(code);
+        }
+        let name = CStr::from_ptr(ZSTD_getErrorName(code));
+        Err(format!("zstd: {}", name.to_string_lossy()))
+    }
+}
+
+fn compress(data: &[u8], level: i32) -> Result<Vec<u8>, String> {
+    // SAFETY: the buffer holds the bound zstd gives for the input
+    unsafe {
+        let mut out = vec![0; ZSTD_compressBound(data.len())];
+        let n = check(ZSTD_compress(
+            out.as_mut_ptr(),
+            out.len(),
+            data.as_ptr(),
+            data.len(),
+            level,
+        ))?;
+        out.truncate(n);
+        Ok(out)
+    }
+}
+
+fn decompress(data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let mut out = vec![0; size];
+    // SAFETY: zstd writes at most `size` bytes to the buffer of that length
+    let n = unsafe {
+        check(ZSTD_decompress(
+            out.as_mut_ptr(),
+            out.len(),
+            data.as_ptr(),
+            data.len(),
+        ))?
+    };
+    if n != size {
+        return Err(format!(
+            "a frame decompressed to {n} bytes, expected {size}"
+        ));
+    }
+    Ok(out)
+}
+
+/// How to pack a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageOptions {
+    /// The zstd compression level, 1 to 22.
+    pub level: i32,
+    /// The largest frame, the most a read decompresses beyond what it asks for.
+    pub max_frame_size: u32,
+}
+
+impl Default for PackageOptions {
+    fn default() -> Self {
+        PackageOptions {
+            level: 9,
+            max_frame_size: 4 << 20,
+        }
+    }
+}
+
+/// A frame of an archive: where it is and what part of the file it holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub compressed_offset: u64,
+    pub compressed_size: u32,
+    pub offset: u64,
+    pub size: u32,
+}
+
+/// The frames of an archive, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeekTable {
+    pub frames: Vec<Frame>,
+}
+
+impl SeekTable {
+    /// Read the seek table at the end of an archive
+    pub fn read_from(input: &mut (impl Read + Seek)) -> Result<Self, String> {
+        let len = input.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+        let invalid = || "not a seekable zstd archive".to_string();
+        if len < FOOTER_LEN + 8 {
+            return Err(invalid());
+        }
+        let mut footer = [0; FOOTER_LEN as usize];
+        input
+            .seek(SeekFrom::Start(len - FOOTER_LEN))
+            .and_then(|_| input.read_exact(&mut footer))
+            .map_err(|e| e.to_string())?;
+        let u32_at = |bytes: &[u8], at: usize| {
+            u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+        if u32_at(&footer, 5) != SEEKABLE_MAGIC {
+            return Err(invalid());
+        }
+        let count = u32_at(&footer, 0) as u64;
+        let entry_len = if footer[4] & 0x80 != 0 { 12 } else { 8 };
+        let table_len = count * entry_len + FOOTER_LEN;
+        if table_len + 8 > len {
+            return Err(invalid());
+        }
+        let mut table = vec![0; (table_len + 8) as usize];
+        input
+            .seek(SeekFrom::Start(len - table_len - 8))
+            .and_then(|_| input.read_exact(&mut table))
+            .map_err(|e| e.to_string())?;
+        if u32_at(&table, 0) != SKIPPABLE_MAGIC || u32_at(&table, 4) as u64 != table_len {
+            return Err(invalid());
+        }
+        let mut frames = Vec::with_capacity(count as usize);
+        let (mut compressed_offset, mut offset) = (0, 0);
+        for entry in table[8..]
+            .chunks_exact(entry_len as usize)
+            .take(count as usize)
+        {
+            let frame = Frame {
+                compressed_offset,
+                compressed_size: u32_at(entry, 0),
+                offset,
+                size: u32_at(entry, 4),
+            };
+            compressed_offset += frame.compressed_size as u64;
+            offset += frame.size as u64;
+            frames.push(frame);
+        }
+        if compressed_offset > len - table_len - 8 {
+            return Err("the seek table lists more data than the archive holds".to_string());
+        }
+        Ok(SeekTable { frames })
+    }
+
+    /// The length of the file the archive holds
+    pub fn decompressed_len(&self) -> u64 {
+        self.frames.last().map_or(0, |f| f.offset + f.size as u64)
+    }
+
+    /// Write the table as the closing skippable frame, without checksums
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let table_len = self.frames.len() as u32 * 8 + FOOTER_LEN as u32;
+        out.write_all(&SKIPPABLE_MAGIC.to_le_bytes())?;
+        out.write_all(&table_len.to_le_bytes())?;
+        for frame in &self.frames {
+            out.write_all(&frame.compressed_size.to_le_bytes())?;
+            out.write_all(&frame.size.to_le_bytes())?;
+        }
+        out.write_all(&(self.frames.len() as u32).to_le_bytes())?;
+        out.write_all(&[0])?;
+        out.write_all(&SEEKABLE_MAGIC.to_le_bytes())
+    }
+}
+
+/// Pack the GGUF file `src` into the seekable archive `dst`, a frame boundary at the start of
+/// the tensor data and of every tensor
+pub fn pack(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    options: &PackageOptions,
+) -> Result<SeekTable, String> {
+    if options.max_frame_size == 0 {
+        return Err("the frame size must be at least 1".to_string());
+    }
+    let mut input = File::open(src).map_err(|e| e.to_string())?;
+    let (file, data_start) =
+        GGUFFile::read_from(&mut input, &ParseOptions::default()).map_err(|e| e.to_string())?;
+    let len = input.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+    let mut boundaries: Vec<u64> = file
+        .tensors
+        .iter()
+        .map(|t| data_start + t.offset)
+        .chain([0, data_start, len])
+        .filter(|&at| at <= len)
+        .collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    input.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+    let mut out = BufWriter::new(File::create(dst).map_err(|e| e.to_string())?);
+    let mut frames = Vec::new();
+    let mut compressed_offset = 0;
+    for span in boundaries.windows(2) {
+        let mut at = span[0];
+        while at < span[1] {
+            let size = (span[1] - at).min(options.max_frame_size as u64) as u32;
+            let mut data = vec![0; size as usize];
+            input.read_exact(&mut data).map_err(|e| e.to_string())?;
+            let compressed = compress(&data, options.level)?;
+            out.write_all(&compressed).map_err(|e| e.to_string())?;
+            let frame = Frame {
+                compressed_offset,
+                compressed_size: compressed.len() as u32,
+                offset: at,
+                size,
+            };
+            compressed_offset += compressed.len() as u64;
+            at += size as u64;
+            frames.push(frame);
+        }
+    }
+    let table = SeekTable { frames };
+    table
+        .write_to(&mut out)
+        .and_then(|()| out.flush())
+        .map_err(|e| e.to_string())?;
+    Ok(table)
+}
+
+/// A seekable archive read and seeked as the file it holds, one decompressed frame kept
+#[derive(Debug)]
+pub struct PackageReader<R> {
+    inner: R,
+    table: SeekTable,
+    pos: u64,
+    /// the index and data of the last frame decompressed
+    frame: Option<(usize, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> PackageReader<R> {
+    /// Open an archive, reading its seek table
+    pub fn new(mut inner: R) -> Result<Self, String> {
+        let table = SeekTable::read_from(&mut inner)?;
+        Ok(PackageReader {
+            inner,
+            table,
+            pos: 0,
+            frame: None,
+        })
+    }
+
+    pub fn table(&self) -> &SeekTable {
+        &self.table
+    }
+
+    /// The decompressed frame holding `pos`, which is within the file
+    fn frame_at(&mut self, pos: u64) -> io::Result<(&Frame, &[u8])> {
+        let index = self
+            .table
+            .frames
+            .partition_point(|f| f.offset + f.size as u64 <= pos);
+        if self.frame.as_ref().is_none_or(|(i, _)| *i != index) {
+            let frame = self.table.frames[index];
+            let mut compressed = vec![0; frame.compressed_size as usize];
+            self.inner.seek(SeekFrom::Start(frame.compressed_offset))?;
+            self.inner.read_exact(&mut compressed)?;
+            let data = decompress(&compressed, frame.size as usize).map_err(io::Error::other)?;
+            self.frame = Some((index, data));
+        }
+        let (index, data) = self.frame.as_ref().expect("a frame was just decompressed");
+        Ok((&self.table.frames[*index], data))
+    }
+}
+
+impl<R: Read + Seek> Read for PackageReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let pos = self.pos;
+        if out.is_empty() || pos >= self.table.decompressed_len() {
+            return Ok(0);
+        }
+        let (frame, data) = self.frame_at(pos)?;
+        let at = (pos - frame.offset) as usize;
+        let n = out.len().min(data.len() - at);
+        out[..n].copy_from_slice(&data[at..at + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for PackageReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.table.decompressed_len().checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        self.pos = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path, tensor};
+    use crate::GGMLType;
+
+    #[test]
+    fn packs_and_reads_tensors() {
+        let dir = temp_path("gguf-package");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = file(
+            header(&[]),
+            vec![
+                tensor("a.weight", &[1000], GGMLType::F32, 0),
+                tensor("b.weight", &[8], GGMLType::F32, 0),
+            ],
+        );
+        let src = dir.join("model.gguf");
+        crate::writer::write_file(&src, &file, |tensor, out| {
+            (0..tensor.element_count())
+                .try_for_each(|i| out.write_all(&(i as f32).to_le_bytes()))?;
+            Ok(())
+        })
+        .unwrap();
+        let original = std::fs::read(&src).unwrap();
+
+        let dst = dir.join("model.gguf.zst");
+        let options = PackageOptions {
+            level: 3,
+            max_frame_size: 1024,
+        };
+        let table = pack(&src, &dst, &options).unwrap();
+        assert_eq!(table.decompressed_len(), original.len() as u64);
+        let mut reader = PackageReader::new(File::open(&dst).unwrap()).unwrap();
+        assert_eq!(reader.table(), &table);
+        let (parsed, data_start) =
+            GGUFFile::read_from(&mut reader, &ParseOptions::default()).unwrap();
+        // the header, four frames of a.weight and one of b.weight
+        let starts: Vec<u64> = table.frames.iter().map(|f| f.offset).collect();
+        let b = data_start + parsed.tensors[1].offset;
+        assert_eq!(
+            starts,
+            [
+                0,
+                data_start,
+                data_start + 1024,
+                data_start + 2048,
+                data_start + 3072,
+                b
+            ]
+        );
+
+        reader.seek(SeekFrom::Start(b)).unwrap();
+        let mut data = [0; 32];
+        reader.read_exact(&mut data).unwrap();
+        assert_eq!(data[..], original[b as usize..]);
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut all = Vec::new();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(all, original);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,137 +1,493 @@
-use crate::{
-    GGMLType, GGUFFile, GGUFHeader, GGUFMetadata, GGUFMetadataArrayValue, GGUFMetadataValue,
-    GGUFTensorInfo, GGUfMetadataValueType,
-};
-use nom::bytes::streaming::take;
-use nom::combinator::{map, map_res};
-use nom::multi::count;
-use nom::number::streaming::{le_u32, le_u64, le_u8, *};
-use nom::{bytes::streaming::tag, IResult};
-
-/// parse gguf string
-fn gguf_string(i: &[u8]) -> IResult<&[u8], String> {
-    let (i, len) = le_u64(i)?;
-    let (i, data) = map_res(take(len), std::str::from_utf8)(i)?;
-    Ok((i, data.to_string()))
+use alloc::borrow::Cow;
+
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::GGUFMetadata;
+
+#[cfg(feature = "nom")]
+mod combinators;
+mod cursor;
+
+/// How the parser treats input that deviates from the spec
+///
+/// [`ParseOptions::strict`], the default, rejects every deviation, which suits production
+/// loaders; [`ParseOptions::lenient`] reads as much as possible, which suits forensics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Read bool bytes other than 0 and 1 as `true` instead of failing.
+    pub lenient_bools: bool,
+    /// Replace invalid UTF-8 in strings with U+FFFD instead of failing.
+    pub lossy_utf8: bool,
+    /// Take strings as UTF-8 without checking, for input known to be valid; see [`AssumeUtf8`].
+    pub unchecked_utf8: Option<AssumeUtf8>,
+    /// What to do with a metadata key that appears more than once.
+    pub duplicate_keys: DuplicateKeys,
+    /// Read versions other than 2 and 3 with the version 3 layout instead of failing.
+    pub unknown_versions: bool,
+    /// Accept only these versions, failing with [`ParseError::UnsupportedVersion`] on any
+    /// other whatever `unknown_versions` says. Versions are read little-endian, so big-endian
+    /// files show up as versions in the millions and are rejected.
+    pub accepted_versions: Option<Vec<u32>>,
+    /// Stop reading metadata at a value of unknown type, keeping the entries before it and
+    /// skipping the tensor infos, instead of failing. Nothing after such a value can be located.
+    pub truncate_at_unknown_type: bool,
+    /// Give up with [`ParseError::HeaderTooLarge`] once the header and tensor infos run past
+    /// this many bytes, however much of the input is available. Read from a stream, they are
+    /// given up on past 1 GiB when unset.
+    pub max_header_bytes: Option<u64>,
+    /// Give up with [`ParseError::ArrayTooLarge`] at an array declaring more items than this,
+    /// before reading any of them.
+    pub max_array_len: Option<u64>,
+    /// Give up with [`ParseError::ArrayTooDeep`] at arrays nested deeper than this.
+    pub max_array_depth: Option<usize>,
+    /// Store each distinct string of a string array once, as repeated byte and special
+    /// tokens are, at the cost of hashing every string.
+    pub intern_strings: bool,
+    /// Keep arrays of numbers or strings of at least this many elements undecoded, as a
+    /// [`crate::raw::RawArray`] read an element at a time. Their strings are not checked for
+    /// UTF-8 until read.
+    pub lazy_arrays: Option<u64>,
+    /// Reserve room up front for at most this many metadata entries, tensor infos or array
+    /// elements, however many the header declares; the rest grow as they are read. The room
+    /// reserved is bounded by the input left as well, each item taking at least a byte.
+    pub max_reserved_items: usize,
 }
 
-/// the magic of GGUF
-fn magic(input: &[u8]) -> IResult<&[u8], &[u8]> {
-    tag("GGUF")(input)
+/// The most bytes of header and tensor infos buffered from a stream unless
+/// [`ParseOptions::max_header_bytes`] says otherwise
+pub(crate) const STREAM_HEADER_BYTES: u64 = 1 << 30;
+
+/// Permission to skip UTF-8 validation, which only `unsafe` code can give
+///
+/// Parsing invalid UTF-8 with it is undefined behaviour, so it suits input this program wrote
+/// or has validated before, such as a file of a cache it keeps, not files from elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssumeUtf8(());
+
+impl AssumeUtf8 {
+    /// # Safety
+    ///
+    /// Every string of the input parsed with the options must be valid UTF-8.
+    pub unsafe fn new() -> Self {
+        AssumeUtf8(())
+    }
 }
 
-/// parse value type of a metadata
-fn gguf_metadata_value_type(i: &[u8]) -> IResult<&[u8], GGUfMetadataValueType> {
-    map_res(le_u32, GGUfMetadataValueType::try_from)(i)
+/// Handling of a metadata key that appears more than once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Fail to parse.
+    Reject,
+    /// Keep the first entry, which is the one ggml looks up.
+    KeepFirst,
+    /// Keep the last entry.
+    KeepLast,
+    /// Keep every entry.
+    KeepAll,
 }
 
-/// parse metadata value
-fn gguf_metadata_value(
-    value_type: GGUfMetadataValueType,
-) -> impl FnMut(&[u8]) -> IResult<&[u8], GGUFMetadataValue> {
-    move |i: &[u8]| {
-        // parse all metadata value type
-        match value_type {
-            GGUfMetadataValueType::Uint8 => map(le_u8, GGUFMetadataValue::Uint8)(i),
-            GGUfMetadataValueType::Int8 => map(le_i8, GGUFMetadataValue::Int8)(i),
-            GGUfMetadataValueType::Uint16 => map(le_u16, GGUFMetadataValue::Uint16)(i),
-            GGUfMetadataValueType::Int16 => map(le_i16, GGUFMetadataValue::Int16)(i),
-            GGUfMetadataValueType::Uint32 => map(le_u32, GGUFMetadataValue::Uint32)(i),
-            GGUfMetadataValueType::Int32 => map(le_i32, GGUFMetadataValue::Int32)(i),
-            GGUfMetadataValueType::Float32 => map(le_f32, GGUFMetadataValue::Float32)(i),
-            GGUfMetadataValueType::Uint64 => map(le_u64, GGUFMetadataValue::Uint64)(i),
-            GGUfMetadataValueType::Int64 => map(le_i64, GGUFMetadataValue::Int64)(i),
-            GGUfMetadataValueType::Float64 => map(le_f64, GGUFMetadataValue::Float64)(i),
-            GGUfMetadataValueType::Bool => map_res(le_u8, |b| {
-                if b == 0 {
-                    Ok(GGUFMetadataValue::Bool(false))
-                } else if b == 1 {
-                    Ok(GGUFMetadataValue::Bool(true))
-                } else {
-                    Err("invalid bool value".to_string())
-                }
-            })(i),
-            GGUfMetadataValueType::String => map(gguf_string, GGUFMetadataValue::String)(i),
-            GGUfMetadataValueType::Array => {
-                let (i, value_type) = gguf_metadata_value_type(i)?;
-                let (i, len) = le_u64(i)?;
-                let (i, v) = count(gguf_metadata_value(value_type), len as usize)(i)?;
-                let value = GGUFMetadataValue::Array(GGUFMetadataArrayValue {
-                    value_type,
-                    len,
-                    value: v,
-                });
-                Ok((i, value))
-            }
+impl ParseOptions {
+    /// Reject anything that deviates from the spec
+    pub fn strict() -> Self {
+        ParseOptions {
+            lenient_bools: false,
+            lossy_utf8: false,
+            unchecked_utf8: None,
+            duplicate_keys: DuplicateKeys::Reject,
+            unknown_versions: false,
+            accepted_versions: None,
+            truncate_at_unknown_type: false,
+            max_header_bytes: None,
+            max_array_len: None,
+            max_array_depth: None,
+            intern_strings: false,
+            lazy_arrays: None,
+            max_reserved_items: 1 << 20,
+        }
+    }
+
+    /// Accept every deviation that can be read past
+    pub fn lenient() -> Self {
+        ParseOptions {
+            lenient_bools: true,
+            lossy_utf8: true,
+            unchecked_utf8: None,
+            duplicate_keys: DuplicateKeys::KeepFirst,
+            unknown_versions: true,
+            accepted_versions: None,
+            truncate_at_unknown_type: true,
+            max_header_bytes: None,
+            max_array_len: None,
+            max_array_depth: None,
+            intern_strings: false,
+            lazy_arrays: None,
+            max_reserved_items: 1 << 20,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Strict parsing with limits that bound the memory and stack used on untrusted input
+    pub fn hardened() -> Self {
+        ParseOptions {
+            max_header_bytes: Some(STREAM_HEADER_BYTES),
+            max_array_len: Some(1 << 24),
+            max_array_depth: Some(8),
+            max_reserved_items: 1 << 16,
+            ..Self::strict()
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Whether files of this version may be read
+    pub fn accepts_version(&self, version: u32) -> bool {
+        match &self.accepted_versions {
+            Some(versions) => versions.contains(&version),
+            None => self.unknown_versions || (2..=3).contains(&version),
         }
     }
 }
 
-/// parse metadata
-fn gguf_metadata(i: &[u8]) -> IResult<&[u8], GGUFMetadata> {
-    let (i, key) = gguf_string(i)?;
-    let (i, value_type) = gguf_metadata_value_type(i)?;
-    let (i, value) = gguf_metadata_value(value_type)(i)?;
-    Ok((
-        i,
-        GGUFMetadata {
-            key,
-            value_type,
-            value,
-        },
-    ))
+impl ParseOptions {
+    /// The items to reserve room for when the header declares `count`, each taking at least
+    /// `min_size` bytes of the `remaining` input
+    pub fn reserved(&self, count: u64, min_size: usize, remaining: usize) -> usize {
+        let count = usize::try_from(count).unwrap_or(usize::MAX);
+        count
+            .min(self.max_reserved_items)
+            .min(remaining / min_size.max(1))
+    }
 }
 
-/// parse header
-fn gguf_header(i: &[u8]) -> IResult<&[u8], GGUFHeader> {
-    let (i, _) = magic(i)?;
-    let (i, version) = le_u32(i)?;
-    let (i, tensor_count) = le_u64(i)?;
-    let (i, metadata_count) = le_u64(i)?;
-    let (i, metadata) = count(gguf_metadata, metadata_count as usize)(i)?;
-    Ok((
-        i,
-        GGUFHeader {
-            version,
-            tensor_count,
-            metadata,
-        },
-    ))
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self::strict()
+    }
 }
 
-/// parse tensor info
-fn gguf_tensor_info(i: &[u8]) -> IResult<&[u8], GGUFTensorInfo> {
-    let (i, name) = gguf_string(i)?;
-    let (i, n_dimensions) = le_u32(i)?;
-    let (i, dimensions) = count(le_u64, n_dimensions as usize)(i)?;
-    let (i, tensor_type) = map_res(le_u32, GGMLType::try_from)(i)?;
-    let (i, offset) = le_u64(i)?;
-    Ok((
-        i,
-        GGUFTensorInfo {
-            name,
-            dimensions,
-            tensor_type,
-            offset,
-        },
-    ))
+/// Why a file could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The header did not end within [`ParseOptions::max_header_bytes`].
+    HeaderTooLarge { limit: u64 },
+    /// An array declared more items than [`ParseOptions::max_array_len`].
+    ArrayTooLarge { limit: u64 },
+    /// Arrays were nested deeper than [`ParseOptions::max_array_depth`].
+    ArrayTooDeep { limit: usize },
+    /// The file has a version the options do not accept.
+    UnsupportedVersion { version: u32 },
+    /// The input is not a file the options accept.
+    Invalid(String),
+    /// Reading the input failed.
+    Io(String),
 }
 
-/// parse file
-pub(crate) fn gguf_file(i: &[u8]) -> IResult<&[u8], GGUFFile> {
-    let (i, header) = gguf_header(i)?;
-    let (i, tensors) = count(gguf_tensor_info, header.tensor_count as usize)(i)?;
-    Ok((i, GGUFFile { header, tensors }))
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::HeaderTooLarge { limit } => {
+                write!(f, "the GGUF header does not end within {limit} bytes")
+            }
+            ParseError::ArrayTooLarge { limit } => {
+                write!(f, "a metadata array has more than {limit} items")
+            }
+            ParseError::ArrayTooDeep { limit } => {
+                write!(f, "metadata arrays are nested more than {limit} deep")
+            }
+            ParseError::UnsupportedVersion { version } => {
+                write!(f, "unsupported GGUF version {version}")
+            }
+            ParseError::Invalid(message) | ParseError::Io(message) => f.write_str(message),
+        }
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+impl From<ParseError> for String {
+    fn from(e: ParseError) -> String {
+        e.to_string()
+    }
+}
+
+/// decode the bytes of a string as the options say, borrowing them unless lossy decoding
+/// replaced some
+pub(crate) fn decode<'a>(data: &'a [u8], options: &ParseOptions) -> Option<Cow<'a, str>> {
+    if options.unchecked_utf8.is_some() {
+        // SAFETY: whoever made the `AssumeUtf8` vouched for the input being UTF-8
+        return Some(Cow::Borrowed(unsafe {
+            core::str::from_utf8_unchecked(data)
+        }));
+    }
+    match core::str::from_utf8(data) {
+        Ok(s) => Some(Cow::Borrowed(s)),
+        Err(_) if options.lossy_utf8 => Some(String::from_utf8_lossy(data)),
+        Err(_) => None,
+    }
+}
+
+#[cfg(feature = "nom")]
+pub(crate) use combinators::parse_file;
+#[cfg(feature = "std")]
+pub(crate) use cursor::declared_len;
+#[cfg(not(feature = "nom"))]
+pub(crate) use cursor::parse_file;
+
+/// Apply the duplicate key handling to parsed metadata
+pub(crate) fn dedup_metadata(
+    metadata: &mut Vec<GGUFMetadata>,
+    handling: DuplicateKeys,
+) -> Result<(), String> {
+    let mut seen = alloc::collections::BTreeMap::new();
+    let mut keep = vec![true; metadata.len()];
+    for (i, entry) in metadata.iter().enumerate() {
+        if let Some(first) = seen.insert(entry.key.as_str(), i) {
+            match handling {
+                DuplicateKeys::Reject => {
+                    return Err(format!("duplicate metadata key {}", entry.key))
+                }
+                DuplicateKeys::KeepFirst => {
+                    keep[i] = false;
+                    seen.insert(entry.key.as_str(), first);
+                }
+                DuplicateKeys::KeepLast => keep[first] = false,
+                DuplicateKeys::KeepAll => {}
+            }
+        }
+    }
+    let mut keep = keep.into_iter();
+    metadata.retain(|_| keep.next().unwrap_or(true));
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::{entry, header_start, string, tensor_info};
+    use crate::{ArrayValues, GGUFFile, GGUFMetadataValue};
 
     #[test]
-    fn parse_magic() {
-        let data = &[0x47, 0x47, 0x55, 0x46];
-        let result = magic(data);
-        assert_eq!(result, Ok((&[][..], &data[..])));
+    fn strict_and_lenient_options() {
+        let mut buf = header_start(3, 0, 5);
+        entry(&mut buf, "a", 7, &[2]);
+        entry(&mut buf, "a", 7, &[0]);
+        entry(&mut buf, "b", 8, &[2, 0, 0, 0, 0, 0, 0, 0, 0xff, b'x']);
+        entry(&mut buf, "c", 99, &[]);
+        entry(&mut buf, "d", 4, &[1, 0, 0, 0]);
+
+        assert!(GGUFFile::read(&buf).is_err());
+        let mut options = ParseOptions::lenient();
+        let file = GGUFFile::read_with(&buf, &options).unwrap().unwrap();
+        let metadata = &file.header.metadata;
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata[0].value, GGUFMetadataValue::Bool(true));
+        assert_eq!(metadata[1].value.as_str(), Some("\u{fffd}x"));
+
+        options.duplicate_keys = DuplicateKeys::Reject;
+        assert!(GGUFFile::read_with(&buf, &options).is_err());
+        options.duplicate_keys = DuplicateKeys::KeepLast;
+        let file = GGUFFile::read_with(&buf, &options).unwrap().unwrap();
+        assert_eq!(
+            file.header.metadata[0].value,
+            GGUFMetadataValue::Bool(false)
+        );
+
+        buf[4] = 1;
+        assert_eq!(
+            GGUFFile::read(&buf),
+            Err("unsupported GGUF version 1".to_string())
+        );
+        assert!(GGUFFile::read_with(&buf, &ParseOptions::lenient()).is_ok());
+    }
+
+    #[test]
+    fn packs_arrays() {
+        let mut buf = header_start(3, 0, 2);
+        let mut tokens = 8u32.to_le_bytes().to_vec();
+        tokens.extend(4u64.to_le_bytes());
+        for token in ["<s>", "", "hello", "<s>"] {
+            string(&mut tokens, token.as_bytes());
+        }
+        entry(&mut buf, "tokens", 9, &tokens);
+        let mut ids = 4u32.to_le_bytes().to_vec();
+        ids.extend(2u64.to_le_bytes());
+        ids.extend([7, 0, 0, 0, 9, 0, 0, 0]);
+        entry(&mut buf, "ids", 9, &ids);
+
+        let file = GGUFFile::read(&buf).unwrap().unwrap();
+        let tokens = &file.header.get("tokens").unwrap().as_array().unwrap().value;
+        let strings = tokens.as_strings().unwrap();
+        assert_eq!(
+            strings.iter().collect::<Vec<_>>(),
+            ["<s>", "", "hello", "<s>"]
+        );
+        assert_eq!(strings.text_len(), 11);
+        assert_eq!(tokens.get_str(2), Some("hello"));
+        let ids = &file.header.get("ids").unwrap().as_array().unwrap().value;
+        assert_eq!(ids.as_u32s(), Some(&[7, 9][..]));
+
+        let mut mixed = ids.clone();
+        mixed.push(GGUFMetadataValue::Bool(true));
+        assert!(matches!(mixed, ArrayValues::Values(_)));
+        assert_eq!(mixed.first(), Some(GGUFMetadataValue::Uint32(7)));
+        assert_eq!(crate::writer::header_bytes(&file), buf);
+
+        let options = ParseOptions {
+            intern_strings: true,
+            ..ParseOptions::strict()
+        };
+        let interned = GGUFFile::read_with(&buf, &options).unwrap().unwrap();
+        let value = &interned
+            .header
+            .get("tokens")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .value;
+        assert_eq!(value.as_strings().unwrap().text_len(), 8);
+        assert_eq!(interned, file);
+        let options = ParseOptions {
+            // SAFETY: the strings above are valid UTF-8
+            unchecked_utf8: Some(unsafe { AssumeUtf8::new() }),
+            ..ParseOptions::strict()
+        };
+        assert_eq!(GGUFFile::read_with(&buf, &options).unwrap().unwrap(), file);
+    }
+
+    #[test]
+    fn version_policy() {
+        let mut buf = header_start(2, 0, 0);
+        let v3_only = ParseOptions {
+            accepted_versions: Some(vec![3]),
+            ..ParseOptions::lenient()
+        };
+        assert!(GGUFFile::read(&buf).unwrap().is_some());
+        assert_eq!(
+            GGUFFile::read_with(&buf, &v3_only),
+            Err(ParseError::UnsupportedVersion { version: 2 })
+        );
+        buf[4..8].copy_from_slice(&3u32.to_be_bytes());
+        assert_eq!(
+            GGUFFile::read_with(&buf, &v3_only),
+            Err(ParseError::UnsupportedVersion { version: 3 << 24 })
+        );
+    }
+
+    #[test]
+    fn hardened_parse_survives_hostile_input() {
+        let mut buf = header_start(3, 0, 1);
+        entry(&mut buf, "a", 9, &[]);
+        for _ in 0..100_000 {
+            buf.extend(9u32.to_le_bytes());
+            buf.extend(1u64.to_le_bytes());
+        }
+        assert_eq!(
+            crate::parse_hardened(&buf, &ParseOptions::lenient()),
+            Err(ParseError::ArrayTooDeep { limit: 8 })
+        );
+
+        // flip bytes of a small valid file, which must never panic
+        let mut file = header_start(3, 1, 2);
+        entry(&mut file, "s", 8, &[1, 0, 0, 0, 0, 0, 0, 0, b'x']);
+        entry(
+            &mut file,
+            "a",
+            9,
+            &[4, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0],
+        );
+        tensor_info(&mut file, "t", &[32], 0, 0);
+        let mut state = 0x9e3779b97f4a7c15u64;
+        for _ in 0..2000 {
+            let mut mutated = file.clone();
+            for _ in 0..3 {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let at = (state >> 8) as usize % mutated.len();
+                mutated[at] = state as u8;
+            }
+            let len = (state >> 32) as usize % (mutated.len() + 1);
+            let _ = crate::parse_hardened(&mutated[..len], &ParseOptions::lenient());
+        }
+    }
+
+    #[test]
+    fn size_limits() {
+        let mut buf = header_start(3, 0, 1);
+        let mut array = 4u32.to_le_bytes().to_vec();
+        array.extend(1000u64.to_le_bytes());
+        entry(&mut buf, "a", 9, &array);
+
+        let options = ParseOptions {
+            max_array_len: Some(100),
+            ..ParseOptions::strict()
+        };
+        assert_eq!(
+            GGUFFile::read_with(&buf, &options),
+            Err(ParseError::ArrayTooLarge { limit: 100 })
+        );
+        let options = ParseOptions {
+            max_header_bytes: Some(64),
+            ..ParseOptions::strict()
+        };
+        assert_eq!(GGUFFile::read_with(&buf, &options), Ok(None));
+        buf.resize(buf.len() + 4000, 0);
+        assert_eq!(
+            GGUFFile::read_with(&buf, &options),
+            Err(ParseError::HeaderTooLarge { limit: 64 })
+        );
+
+        let cramped = ParseOptions {
+            max_reserved_items: 2,
+            ..ParseOptions::strict()
+        };
+        assert_eq!(ParseOptions::strict().reserved(1000, 4, 100), 25);
+        assert_eq!(cramped.reserved(u64::MAX, 1, buf.len()), 2);
+        let file = GGUFFile::read_with(&buf, &cramped).unwrap().unwrap();
+        let value = &file.header.get("a").unwrap().as_array().unwrap().value;
+        assert_eq!(value.len(), 1000);
+        assert_eq!(
+            GGUFFile::read_with(&buf, &ParseOptions::strict()),
+            Ok(Some(file))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn streams_stop_at_the_declared_length() {
+        use std::io::{Cursor, Read};
+
+        // zeros read as empty tensor infos forever
+        let mut endless = Cursor::new(header_start(3, 1 << 40, 0)).chain(std::io::repeat(0));
+        assert_eq!(
+            GGUFFile::read_from(&mut endless, &ParseOptions::strict()).unwrap_err(),
+            ParseError::HeaderTooLarge {
+                limit: STREAM_HEADER_BYTES
+            }
+        );
+
+        // a corrupt string length fails before the string is read
+        let mut buf = header_start(3, 0, 1);
+        buf.extend((1u64 << 40).to_le_bytes());
+        buf.resize(1 << 22, b'a');
+        let mut input = Cursor::new(&buf);
+        assert!(matches!(
+            GGUFFile::read_from(&mut input, &ParseOptions::strict()),
+            Err(ParseError::HeaderTooLarge { .. })
+        ));
+        assert!(input.position() < 1 << 17);
+
+        // and one within the limit at the end of the file
+        buf[24..32].copy_from_slice(&(1u64 << 23).to_le_bytes());
+        let Err(ParseError::Invalid(e)) =
+            GGUFFile::read_from(&mut Cursor::new(&buf), &ParseOptions::strict())
+        else {
+            panic!("a header past the end of the file parsed");
+        };
+        assert_eq!(
+            e,
+            "the file ends at byte 4194304 inside the header, which runs to at least byte 8388640"
+        );
     }
 }
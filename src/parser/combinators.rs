@@ -0,0 +1,380 @@
+//! The header parser in nom combinators
+use alloc::sync::Arc;
+
+use nom::bytes::streaming::take;
+use nom::combinator::{map, map_res};
+use nom::error::{Error, ErrorKind};
+use nom::number::streaming::{le_u32, le_u64, le_u8, *};
+use nom::{bytes::streaming::tag, IResult};
+
+use super::{decode, ParseError, ParseOptions};
+use crate::mmap::{MappedStrings, Mmap};
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::raw::{keeps_raw, raw_extent, RawArray};
+use crate::{
+    ArrayValues, Dimensions, GGMLType, GGUFFile, GGUFHeader, GGUFMetadata, GGUFMetadataArrayValue,
+    GGUFMetadataValue, GGUFTensorInfo, GGUfMetadataValueType, Seen, StringArray,
+};
+
+/// error kind marking an array over the length limit
+const ARRAY_TOO_LARGE: ErrorKind = ErrorKind::TooLarge;
+
+/// error kind marking arrays nested over the depth limit
+const ARRAY_TOO_DEEP: ErrorKind = ErrorKind::Many0;
+
+/// error kind marking an unknown value type, which the header parser may truncate at
+const UNKNOWN_TYPE: ErrorKind = ErrorKind::Switch;
+
+/// parse gguf string, copying its bytes once
+fn gguf_string(options: &ParseOptions) -> impl FnMut(&[u8]) -> IResult<&[u8], String> + '_ {
+    move |input: &[u8]| {
+        let (i, len) = le_u64(input)?;
+        let (i, data) = take(len)(i)?;
+        match decode(data, options) {
+            Some(s) => Ok((i, s.into_owned())),
+            None => Err(nom::Err::Error(Error::new(input, ErrorKind::MapRes))),
+        }
+    }
+}
+
+/// index the `len` strings of a string array in `map`, which `i` is a part of
+fn gguf_mapped_strings<'i>(
+    options: &ParseOptions,
+    map: &Arc<Mmap>,
+    len: u64,
+    mut i: &'i [u8],
+) -> IResult<&'i [u8], ArrayValues> {
+    let base = map.as_slice().as_ptr() as usize;
+    let start = i.as_ptr() as usize - base;
+    let mut spans = Vec::with_capacity(options.reserved(len, 8, i.len()));
+    for _ in 0..len {
+        let (rest, n) = le_u64(i)?;
+        let (rest, data) = take(n)(rest)?;
+        let n =
+            u32::try_from(n).map_err(|_| nom::Err::Failure(Error::new(i, ErrorKind::Verify)))?;
+        spans.push(((data.as_ptr() as usize - base) as u64, n));
+        i = rest;
+    }
+    map.release(start..i.as_ptr() as usize - base);
+    Ok((
+        i,
+        ArrayValues::Mapped(MappedStrings::new(map.clone(), spans)),
+    ))
+}
+
+/// parse the `len` strings of a string array into one buffer, without allocating each, or
+/// index them if the input is `mapping`
+fn gguf_string_array<'a>(
+    options: &'a ParseOptions,
+    mapping: Option<&'a Arc<Mmap>>,
+    len: u64,
+) -> impl FnMut(&[u8]) -> IResult<&[u8], ArrayValues> + 'a {
+    move |mut i: &[u8]| {
+        if let Some(map) = mapping {
+            return gguf_mapped_strings(options, map, len, i);
+        }
+        // a string takes at least its 8 length bytes, which bounds the capacity by the input
+        let mut array = StringArray::with_capacity(options.reserved(len, 8, i.len()));
+        let mut seen = Seen::new();
+        for _ in 0..len {
+            let (rest, n) = le_u64(i)?;
+            let (rest, data) = take(n)(rest)?;
+            let Some(s) = decode(data, options) else {
+                return Err(nom::Err::Error(Error::new(i, ErrorKind::MapRes)));
+            };
+            if !array.has_room(&s) {
+                return Err(nom::Err::Failure(Error::new(i, ErrorKind::Verify)));
+            }
+            if options.intern_strings {
+                array.push_interned(&s, &mut seen);
+            } else {
+                array.push(&s);
+            }
+            i = rest;
+        }
+        Ok((i, ArrayValues::String(array)))
+    }
+}
+
+/// take the `len` elements of an array undecoded, copying them unless `i` is a part of
+/// `mapping`, `origin` being the address of the start of the input
+fn gguf_raw_array<'i>(
+    mapping: Option<&Arc<Mmap>>,
+    origin: usize,
+    value_type: GGUfMetadataValueType,
+    len: u64,
+    i: &'i [u8],
+) -> IResult<&'i [u8], ArrayValues> {
+    let n = raw_extent(value_type, len, i).ok_or(nom::Err::Incomplete(nom::Needed::Unknown))?;
+    let (rest, bytes) = take(n)(i)?;
+    let raw = match mapping {
+        Some(map) => {
+            let start = bytes.as_ptr() as usize - map.as_slice().as_ptr() as usize;
+            map.release(start..start + n);
+            RawArray::mapped(value_type, len, map, start..start + n)
+        }
+        None => RawArray::copied(value_type, len, bytes.as_ptr() as usize - origin, bytes),
+    };
+    Ok((rest, ArrayValues::Raw(raw)))
+}
+
+/// the magic of GGUF
+fn magic(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    tag("GGUF")(input)
+}
+
+/// parse value type of a metadata
+fn gguf_metadata_value_type(i: &[u8]) -> IResult<&[u8], GGUfMetadataValueType> {
+    let (rest, raw) = le_u32(i)?;
+    match GGUfMetadataValueType::try_from(raw) {
+        Ok(value_type) => Ok((rest, value_type)),
+        Err(_) => Err(nom::Err::Error(Error::new(i, UNKNOWN_TYPE))),
+    }
+}
+
+/// parse metadata value, `depth` counting the arrays it is nested in
+fn gguf_metadata_value<'a>(
+    value_type: GGUfMetadataValueType,
+    options: &'a ParseOptions,
+    mapping: Option<&'a Arc<Mmap>>,
+    origin: usize,
+    depth: usize,
+) -> impl FnMut(&[u8]) -> IResult<&[u8], GGUFMetadataValue> + 'a {
+    move |i: &[u8]| {
+        // parse all metadata value type
+        match value_type {
+            GGUfMetadataValueType::Uint8 => map(le_u8, GGUFMetadataValue::Uint8)(i),
+            GGUfMetadataValueType::Int8 => map(le_i8, GGUFMetadataValue::Int8)(i),
+            GGUfMetadataValueType::Uint16 => map(le_u16, GGUFMetadataValue::Uint16)(i),
+            GGUfMetadataValueType::Int16 => map(le_i16, GGUFMetadataValue::Int16)(i),
+            GGUfMetadataValueType::Uint32 => map(le_u32, GGUFMetadataValue::Uint32)(i),
+            GGUfMetadataValueType::Int32 => map(le_i32, GGUFMetadataValue::Int32)(i),
+            GGUfMetadataValueType::Float32 => map(le_f32, GGUFMetadataValue::Float32)(i),
+            GGUfMetadataValueType::Uint64 => map(le_u64, GGUFMetadataValue::Uint64)(i),
+            GGUfMetadataValueType::Int64 => map(le_i64, GGUFMetadataValue::Int64)(i),
+            GGUfMetadataValueType::Float64 => map(le_f64, GGUFMetadataValue::Float64)(i),
+            GGUfMetadataValueType::Bool => map_res(le_u8, |b| {
+                if b == 0 {
+                    Ok(GGUFMetadataValue::Bool(false))
+                } else if b == 1 || options.lenient_bools {
+                    Ok(GGUFMetadataValue::Bool(true))
+                } else {
+                    Err("invalid bool value".to_string())
+                }
+            })(i),
+            GGUfMetadataValueType::String => {
+                map(gguf_string(options), GGUFMetadataValue::String)(i)
+            }
+            GGUfMetadataValueType::Array => {
+                if options.max_array_depth.is_some_and(|max| depth >= max) {
+                    return Err(nom::Err::Failure(Error::new(i, ARRAY_TOO_DEEP)));
+                }
+                let (i, value_type) = gguf_metadata_value_type(i)?;
+                let (i, len) = le_u64(i)?;
+                if options.max_array_len.is_some_and(|max| len > max) {
+                    return Err(nom::Err::Failure(Error::new(i, ARRAY_TOO_LARGE)));
+                }
+                if keeps_raw(options, value_type, len) {
+                    let (i, raw) = gguf_raw_array(mapping, origin, value_type, len, i)?;
+                    let value = GGUFMetadataValue::Array(GGUFMetadataArrayValue {
+                        value_type,
+                        len,
+                        value: raw,
+                    });
+                    return Ok((i, value));
+                }
+                if value_type == GGUfMetadataValueType::String {
+                    let (i, strings) = gguf_string_array(options, mapping, len)(i)?;
+                    let value = GGUFMetadataValue::Array(GGUFMetadataArrayValue {
+                        value_type,
+                        len,
+                        value: strings,
+                    });
+                    return Ok((i, value));
+                }
+                // the elements are packed as they are read; the capacity is bounded by the
+                // input so a bogus length cannot allocate more than the file is long
+                let capacity = options.reserved(len, 1, i.len());
+                let mut values = ArrayValues::with_capacity(value_type, capacity);
+                let mut i = i;
+                for _ in 0..len {
+                    let (rest, value) =
+                        gguf_metadata_value(value_type, options, mapping, origin, depth + 1)(i)?;
+                    values.push(value);
+                    i = rest;
+                }
+                let value = GGUFMetadataValue::Array(GGUFMetadataArrayValue {
+                    value_type,
+                    len,
+                    value: values,
+                });
+                Ok((i, value))
+            }
+        }
+    }
+}
+
+/// parse metadata
+fn gguf_metadata<'a>(
+    options: &'a ParseOptions,
+    mapping: Option<&'a Arc<Mmap>>,
+    origin: usize,
+) -> impl FnMut(&[u8]) -> IResult<&[u8], GGUFMetadata> + 'a {
+    move |i: &[u8]| {
+        let (i, key) = gguf_string(options)(i)?;
+        let (i, value_type) = gguf_metadata_value_type(i)?;
+        let (i, value) = gguf_metadata_value(value_type, options, mapping, origin, 0)(i)?;
+        Ok((
+            i,
+            GGUFMetadata {
+                key,
+                value_type,
+                value,
+            },
+        ))
+    }
+}
+
+/// parse header, also telling whether the metadata was read to the end
+fn gguf_header<'a>(
+    options: &'a ParseOptions,
+    mapping: Option<&'a Arc<Mmap>>,
+    origin: usize,
+) -> impl FnMut(&[u8]) -> IResult<&[u8], (GGUFHeader, bool)> + 'a {
+    move |i: &[u8]| {
+        let (i, _) = magic(i)?;
+        let (i, version) = le_u32(i)?;
+        let (i, tensor_count) = le_u64(i)?;
+        let (mut i, metadata_count) = le_u64(i)?;
+        // an entry takes at least its key length, type and a byte of value
+        let mut metadata = Vec::with_capacity(options.reserved(metadata_count, 13, i.len()));
+        let mut complete = true;
+        for _ in 0..metadata_count {
+            match gguf_metadata(options, mapping, origin)(i) {
+                Ok((rest, entry)) => {
+                    metadata.push(entry);
+                    i = rest;
+                }
+                Err(nom::Err::Error(e))
+                    if e.code == UNKNOWN_TYPE && options.truncate_at_unknown_type =>
+                {
+                    complete = false;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok((
+            i,
+            (
+                GGUFHeader {
+                    version,
+                    tensor_count,
+                    metadata,
+                },
+                complete,
+            ),
+        ))
+    }
+}
+
+/// parse tensor info
+fn gguf_tensor_info(
+    options: &ParseOptions,
+) -> impl FnMut(&[u8]) -> IResult<&[u8], GGUFTensorInfo> + '_ {
+    move |i: &[u8]| {
+        let (i, name) = gguf_string(options)(i)?;
+        let (i, n_dimensions) = le_u32(i)?;
+        let mut dimensions = Dimensions::new();
+        let mut i = i;
+        for _ in 0..n_dimensions {
+            let (rest, dimension) = le_u64(i)?;
+            dimensions.push(dimension);
+            i = rest;
+        }
+        let (i, tensor_type) = map_res(le_u32, GGMLType::try_from)(i)?;
+        let (i, offset) = le_u64(i)?;
+        Ok((
+            i,
+            GGUFTensorInfo {
+                name,
+                dimensions,
+                tensor_type,
+                offset,
+            },
+        ))
+    }
+}
+
+/// parse file, indexing its string arrays rather than copying them if the input is `mapping`
+fn gguf_file<'a>(
+    options: &'a ParseOptions,
+    mapping: Option<&'a Arc<Mmap>>,
+    origin: usize,
+) -> impl FnMut(&[u8]) -> IResult<&[u8], GGUFFile> + 'a {
+    move |i: &[u8]| {
+        let (i, (header, complete)) = gguf_header(options, mapping, origin)(i)?;
+        if !complete {
+            return Ok((
+                i,
+                GGUFFile {
+                    header,
+                    tensors: Vec::new(),
+                },
+            ));
+        }
+        // a tensor info takes at least 24 bytes
+        let mut tensors = Vec::with_capacity(options.reserved(header.tensor_count, 24, i.len()));
+        let mut i = i;
+        for _ in 0..header.tensor_count {
+            let (rest, tensor) = gguf_tensor_info(options)(i)?;
+            tensors.push(tensor);
+            i = rest;
+        }
+        Ok((i, GGUFFile { header, tensors }))
+    }
+}
+
+/// Parse the header and tensor infos at the start of `input`, giving them with their length,
+/// or `None` if `input` ends within them
+pub(crate) fn parse_file(
+    input: &[u8],
+    options: &ParseOptions,
+    mapping: Option<&Arc<Mmap>>,
+) -> Result<Option<(GGUFFile, usize)>, ParseError> {
+    match gguf_file(options, mapping, input.as_ptr() as usize)(input) {
+        Ok((rest, file)) => Ok(Some((file, input.len() - rest.len()))),
+        Err(nom::Err::Incomplete(_)) => Ok(None),
+        Err(nom::Err::Failure(e)) if e.code == ARRAY_TOO_DEEP => Err(ParseError::ArrayTooDeep {
+            limit: options.max_array_depth.unwrap_or_default(),
+        }),
+        Err(nom::Err::Failure(e)) if e.code == ARRAY_TOO_LARGE => Err(ParseError::ArrayTooLarge {
+            limit: options.max_array_len.unwrap_or_default(),
+        }),
+        Err(e) => Err(ParseError::Invalid(format!(
+            "Failed to parse GGUF file, please check for file integrity: {:?}",
+            e.map_input(|i| {
+                // print only the next few bytes as hex
+                let len = i.len().min(16);
+                let mut s = String::new();
+                for b in &i[..len] {
+                    s.push_str(&format!("0x{:02x} ", b));
+                }
+                s
+            })
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_magic() {
+        let data = &[0x47, 0x47, 0x55, 0x46];
+        let result = magic(data);
+        assert_eq!(result, Ok((&[][..], &data[..])));
+    }
+}
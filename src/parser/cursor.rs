@@ -0,0 +1,435 @@
+//! The header parser without nom: a cursor over the input reading little-endian fields
+//!
+//! It gives what [`super::combinators`] gives for every input, file, `None` for input that
+//! ends too soon and error alike, only the messages of [`ParseError::Invalid`] differ. With
+//! nom parsing, it still tells how long a header that ends too soon declares itself to be.
+use alloc::sync::Arc;
+
+use super::{decode, ParseError, ParseOptions};
+use crate::mmap::{MappedStrings, Mmap};
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::raw::{keeps_raw, raw_extent, RawArray};
+use crate::{
+    ArrayValues, Dimensions, GGMLType, GGUFFile, GGUFHeader, GGUFMetadata, GGUFMetadataArrayValue,
+    GGUFMetadataValue, GGUFTensorInfo, GGUfMetadataValueType, Seen, StringArray,
+};
+
+/// Why parsing stopped short of the end of the tensor infos
+enum Stop {
+    /// The input ended, so more of it may parse.
+    Incomplete,
+    /// A value type at this offset is unknown, which the metadata may be truncated at.
+    UnknownType(usize),
+    Failed(ParseError),
+}
+
+impl From<ParseError> for Stop {
+    fn from(e: ParseError) -> Self {
+        Stop::Failed(e)
+    }
+}
+
+type Parsed<T> = Result<T, Stop>;
+
+struct Cursor<'a> {
+    input: &'a [u8],
+    pos: usize,
+    options: &'a ParseOptions,
+    mapping: Option<&'a Arc<Mmap>>,
+    /// The least length of input the lengths and counts read so far declare.
+    declared: u64,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a [u8], options: &'a ParseOptions, mapping: Option<&'a Arc<Mmap>>) -> Self {
+        Cursor {
+            input,
+            pos: 0,
+            options,
+            mapping,
+            declared: 0,
+        }
+    }
+
+    fn take(&mut self, len: u64) -> Parsed<&'a [u8]> {
+        self.declare(len, 1);
+        let end = usize::try_from(len)
+            .ok()
+            .and_then(|len| self.pos.checked_add(len))
+            .filter(|&end| end <= self.input.len())
+            .ok_or(Stop::Incomplete)?;
+        let data = &self.input[self.pos..end];
+        self.pos = end;
+        Ok(data)
+    }
+
+    /// Note that `count` items of at least `min_size` bytes each follow
+    fn declare(&mut self, count: u64, min_size: u64) {
+        let end = (self.pos as u64).saturating_add(count.saturating_mul(min_size));
+        self.declared = self.declared.max(end);
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Parsed<[u8; N]> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.take(N as u64)?);
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Parsed<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Parsed<u64> {
+        self.bytes().map(u64::from_le_bytes)
+    }
+
+    /// The error of invalid input at `at`, showing the next few bytes as hex
+    fn invalid(&self, at: usize, what: &str) -> ParseError {
+        let next = &self.input[at..self.input.len().min(at + 16)];
+        let hex: Vec<String> = next.iter().map(|b| format!("0x{b:02x}")).collect();
+        ParseError::Invalid(format!(
+            "Failed to parse GGUF file, please check for file integrity: {what} at byte {at}, \
+             next bytes {}",
+            hex.join(" ")
+        ))
+    }
+
+    fn magic(&mut self) -> Parsed<()> {
+        let seen = &self.input[..self.input.len().min(4)];
+        if !b"GGUF".starts_with(seen) {
+            return Err(self.invalid(0, "not a GGUF file").into());
+        }
+        self.take(4).map(drop)
+    }
+
+    fn string(&mut self) -> Parsed<String> {
+        let at = self.pos;
+        let len = self.u64()?;
+        let data = self.take(len)?;
+        match decode(data, self.options) {
+            Some(s) => Ok(s.into_owned()),
+            None => Err(self.invalid(at, "invalid UTF-8").into()),
+        }
+    }
+
+    fn value_type(&mut self) -> Parsed<GGUfMetadataValueType> {
+        let at = self.pos;
+        GGUfMetadataValueType::try_from(self.u32()?).map_err(|_| Stop::UnknownType(at))
+    }
+
+    /// A value, `depth` counting the arrays it is nested in
+    fn value(
+        &mut self,
+        value_type: GGUfMetadataValueType,
+        depth: usize,
+    ) -> Parsed<GGUFMetadataValue> {
+        use GGUFMetadataValue as V;
+        Ok(match value_type {
+            GGUfMetadataValueType::Uint8 => V::Uint8(u8::from_le_bytes(self.bytes()?)),
+            GGUfMetadataValueType::Int8 => V::Int8(i8::from_le_bytes(self.bytes()?)),
+            GGUfMetadataValueType::Uint16 => V::Uint16(u16::from_le_bytes(self.bytes()?)),
+            GGUfMetadataValueType::Int16 => V::Int16(i16::from_le_bytes(self.bytes()?)),
+            GGUfMetadataValueType::Uint32 => V::Uint32(self.u32()?),
+            GGUfMetadataValueType::Int32 => V::Int32(i32::from_le_bytes(self.bytes()?)),
+            GGUfMetadataValueType::Float32 => V::Float32(f32::from_le_bytes(self.bytes()?)),
+            GGUfMetadataValueType::Uint64 => V::Uint64(self.u64()?),
+            GGUfMetadataValueType::Int64 => V::Int64(i64::from_le_bytes(self.bytes()?)),
+            GGUfMetadataValueType::Float64 => V::Float64(f64::from_le_bytes(self.bytes()?)),
+            GGUfMetadataValueType::Bool => {
+                let at = self.pos;
+                match self.bytes::<1>()? {
+                    [0] => V::Bool(false),
+                    [1] => V::Bool(true),
+                    _ if self.options.lenient_bools => V::Bool(true),
+                    _ => return Err(self.invalid(at, "invalid bool value").into()),
+                }
+            }
+            GGUfMetadataValueType::String => V::String(self.string()?),
+            GGUfMetadataValueType::Array => V::Array(self.array(depth)?),
+        })
+    }
+
+    fn array(&mut self, depth: usize) -> Parsed<GGUFMetadataArrayValue> {
+        if let Some(limit) = self.options.max_array_depth.filter(|&max| depth >= max) {
+            return Err(Stop::Failed(ParseError::ArrayTooDeep { limit }));
+        }
+        let value_type = self.value_type()?;
+        let len = self.u64()?;
+        if let Some(limit) = self.options.max_array_len.filter(|&max| len > max) {
+            return Err(Stop::Failed(ParseError::ArrayTooLarge { limit }));
+        }
+        self.declare(len, 1);
+        let value = match (value_type, self.mapping) {
+            _ if keeps_raw(self.options, value_type, len) => self.raw_array(value_type, len)?,
+            (GGUfMetadataValueType::String, Some(map)) => self.mapped_strings(map, len)?,
+            (GGUfMetadataValueType::String, None) => self.strings(len)?,
+            _ => {
+                // the capacity is bounded by the input so a bogus length cannot allocate more
+                // than the file is long
+                let remaining = self.input.len() - self.pos;
+                let capacity = self.options.reserved(len, 1, remaining);
+                let mut values = ArrayValues::with_capacity(value_type, capacity);
+                for _ in 0..len {
+                    values.push(self.value(value_type, depth + 1)?);
+                }
+                values
+            }
+        };
+        Ok(GGUFMetadataArrayValue {
+            value_type,
+            len,
+            value,
+        })
+    }
+
+    /// The `len` strings of a string array in one buffer
+    fn strings(&mut self, len: u64) -> Parsed<ArrayValues> {
+        // a string takes at least its 8 length bytes
+        self.declare(len, 8);
+        let remaining = self.input.len() - self.pos;
+        let mut array = StringArray::with_capacity(self.options.reserved(len, 8, remaining));
+        let mut seen = Seen::new();
+        for _ in 0..len {
+            let at = self.pos;
+            let n = self.u64()?;
+            let data = self.take(n)?;
+            let Some(s) = decode(data, self.options) else {
+                return Err(self.invalid(at, "invalid UTF-8").into());
+            };
+            if !array.has_room(&s) {
+                return Err(self.invalid(at, "a string array of more than 4 GiB").into());
+            }
+            if self.options.intern_strings {
+                array.push_interned(&s, &mut seen);
+            } else {
+                array.push(&s);
+            }
+        }
+        Ok(ArrayValues::String(array))
+    }
+
+    /// The `len` strings of a string array as an index into `map`, which the input is a part of
+    fn mapped_strings(&mut self, map: &Arc<Mmap>, len: u64) -> Parsed<ArrayValues> {
+        let origin = self.input.as_ptr() as usize - map.as_slice().as_ptr() as usize;
+        let start = self.pos;
+        let remaining = self.input.len() - self.pos;
+        let mut spans = Vec::with_capacity(self.options.reserved(len, 8, remaining));
+        for _ in 0..len {
+            let at = self.pos;
+            let n = self.u64()?;
+            self.take(n)?;
+            let Ok(n) = u32::try_from(n) else {
+                return Err(self.invalid(at, "a string of more than 4 GiB").into());
+            };
+            spans.push(((origin + at + 8) as u64, n));
+        }
+        map.release(origin + start..origin + self.pos);
+        Ok(ArrayValues::Mapped(MappedStrings::new(map.clone(), spans)))
+    }
+
+    /// The `len` elements of an array left undecoded, copied unless the input is a mapping
+    fn raw_array(&mut self, value_type: GGUfMetadataValueType, len: u64) -> Parsed<ArrayValues> {
+        let start = self.pos;
+        let n = raw_extent(value_type, len, &self.input[start..]).ok_or(Stop::Incomplete)?;
+        let bytes = self.take(n as u64)?;
+        Ok(ArrayValues::Raw(match self.mapping {
+            Some(map) => {
+                let origin = self.input.as_ptr() as usize - map.as_slice().as_ptr() as usize;
+                let range = origin + start..origin + self.pos;
+                map.release(range.clone());
+                RawArray::mapped(value_type, len, map, range)
+            }
+            None => RawArray::copied(value_type, len, start, bytes),
+        }))
+    }
+
+    /// The header, also telling whether the metadata was read to the end
+    fn header(&mut self) -> Parsed<(GGUFHeader, bool)> {
+        self.magic()?;
+        let version = self.u32()?;
+        let tensor_count = self.u64()?;
+        let metadata_count = self.u64()?;
+        // an entry takes at least its key length, type and a byte of value
+        self.declare(metadata_count, 13);
+        let remaining = self.input.len() - self.pos;
+        let mut metadata = Vec::with_capacity(self.options.reserved(metadata_count, 13, remaining));
+        for _ in 0..metadata_count {
+            let start = self.pos;
+            let key = self.string()?;
+            let entry = self.value_type().and_then(|value_type| {
+                let value = self.value(value_type, 0)?;
+                Ok(GGUFMetadata {
+                    key,
+                    value_type,
+                    value,
+                })
+            });
+            match entry {
+                Ok(entry) => metadata.push(entry),
+                Err(Stop::UnknownType(_)) if self.options.truncate_at_unknown_type => {
+                    self.pos = start;
+                    let header = GGUFHeader {
+                        version,
+                        tensor_count,
+                        metadata,
+                    };
+                    return Ok((header, false));
+                }
+                Err(stop) => return Err(stop),
+            }
+        }
+        let header = GGUFHeader {
+            version,
+            tensor_count,
+            metadata,
+        };
+        Ok((header, true))
+    }
+
+    fn tensor_info(&mut self) -> Parsed<GGUFTensorInfo> {
+        let name = self.string()?;
+        let n_dimensions = self.u32()?;
+        let mut dimensions = Dimensions::new();
+        for _ in 0..n_dimensions {
+            dimensions.push(self.u64()?);
+        }
+        let at = self.pos;
+        let tensor_type =
+            GGMLType::try_from(self.u32()?).map_err(|_| self.invalid(at, "unknown tensor type"))?;
+        let offset = self.u64()?;
+        Ok(GGUFTensorInfo {
+            name,
+            dimensions,
+            tensor_type,
+            offset,
+        })
+    }
+
+    fn file(&mut self) -> Parsed<GGUFFile> {
+        let (header, complete) = self.header()?;
+        if !complete {
+            return Ok(GGUFFile {
+                header,
+                tensors: Vec::new(),
+            });
+        }
+        // a tensor info takes at least 24 bytes
+        self.declare(header.tensor_count, 24);
+        let remaining = self.input.len() - self.pos;
+        let mut tensors =
+            Vec::with_capacity(self.options.reserved(header.tensor_count, 24, remaining));
+        for _ in 0..header.tensor_count {
+            tensors.push(self.tensor_info()?);
+        }
+        Ok(GGUFFile { header, tensors })
+    }
+}
+
+/// Parse the header and tensor infos at the start of `input`, giving them with their length,
+/// or `None` if `input` ends within them
+#[cfg(any(test, not(feature = "nom")))]
+pub(crate) fn parse_file(
+    input: &[u8],
+    options: &ParseOptions,
+    mapping: Option<&Arc<Mmap>>,
+) -> Result<Option<(GGUFFile, usize)>, ParseError> {
+    let mut cursor = Cursor::new(input, options, mapping);
+    match cursor.file() {
+        Ok(file) => Ok(Some((file, cursor.pos))),
+        Err(Stop::Incomplete) => Ok(None),
+        Err(Stop::UnknownType(at)) => Err(cursor.invalid(at, "unknown value type")),
+        Err(Stop::Failed(e)) => Err(e),
+    }
+}
+
+/// The least length the header and tensor infos at the start of `input` declare, as far as
+/// `input` goes: longer than `input` if it ends within them, which reading more of the file
+/// must supply before they can parse
+#[cfg(feature = "std")]
+pub(crate) fn declared_len(input: &[u8], options: &ParseOptions) -> Result<u64, ParseError> {
+    let mut cursor = Cursor::new(input, options, None);
+    match cursor.file() {
+        Ok(_) => Ok(cursor.pos as u64),
+        Err(Stop::Incomplete) => Ok(cursor.declared.max(input.len() as u64 + 1)),
+        Err(Stop::UnknownType(at)) => Err(cursor.invalid(at, "unknown value type")),
+        Err(Stop::Failed(e)) => Err(e),
+    }
+}
+
+#[cfg(all(test, feature = "nom"))]
+mod tests {
+    use super::*;
+    use crate::test_util::{entry, header_start, string, tensor_info};
+
+    /// The result with the message of an invalid input left out, which the parsers word
+    /// differently
+    fn outcome(result: Result<Option<(GGUFFile, usize)>, ParseError>) -> String {
+        match result {
+            Err(ParseError::Invalid(_)) => "invalid".to_string(),
+            result => format!("{result:?}"),
+        }
+    }
+
+    #[test]
+    fn agrees_with_nom() {
+        let mut file = header_start(3, 2, 5);
+        entry(&mut file, "b", 7, &[1]);
+        entry(&mut file, "f", 6, &1.5f32.to_le_bytes());
+        let mut tokens = 8u32.to_le_bytes().to_vec();
+        tokens.extend(3u64.to_le_bytes());
+        for token in ["<s>", "", "<s>"] {
+            string(&mut tokens, token.as_bytes());
+        }
+        entry(&mut file, "tokens", 9, &tokens);
+        let mut nested = 9u32.to_le_bytes().to_vec();
+        nested.extend(1u64.to_le_bytes());
+        nested.extend(2u32.to_le_bytes());
+        nested.extend(2u64.to_le_bytes());
+        nested.extend([1, 0, 0xff, 0xff]);
+        entry(&mut file, "nested", 9, &nested);
+        entry(&mut file, "s", 8, &[2, 0, 0, 0, 0, 0, 0, 0, b'h', b'i']);
+        tensor_info(&mut file, "a", &[4, 2], 0, 64);
+        tensor_info(&mut file, "b", &[7], 0, 64);
+
+        let hardened = ParseOptions {
+            max_array_depth: Some(1),
+            max_array_len: Some(2),
+            ..ParseOptions::strict()
+        };
+        let intern = ParseOptions {
+            intern_strings: true,
+            ..ParseOptions::strict()
+        };
+        let lazy = ParseOptions {
+            lazy_arrays: Some(2),
+            ..ParseOptions::strict()
+        };
+        let all = [
+            ParseOptions::strict(),
+            ParseOptions::lenient(),
+            hardened,
+            intern,
+            lazy,
+        ];
+        let parsed = parse_file(&file, &all[0], None).unwrap().unwrap();
+        assert_eq!(parsed.1, file.len());
+        assert_eq!(parsed.0.tensors[0].dimensions[..], [4, 2]);
+        for options in &all {
+            let check = |input: &[u8]| {
+                let nom = super::super::combinators::parse_file(input, options, None);
+                assert_eq!(outcome(parse_file(input, options, None)), outcome(nom));
+            };
+            for len in 0..=file.len() {
+                check(&file[..len]);
+            }
+            for at in 0..file.len() {
+                for byte in [0, 2, 0x63, 0xff] {
+                    let mut corrupt = file.clone();
+                    corrupt[at] = byte;
+                    check(&corrupt);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! # Tensor data encoding and decoding
+//!
+//! [`dequantize`] turns the blocks of every [`GGMLType`] into `f32` values and [`quantize`]
+//! encodes `f32` values as most of them, following the reference implementation in ggml.
+//! [`quantize_file`] requantizes a whole file to one of llama.cpp's [`mixes`], and
+//! [`GGUFFile::quant_report`](crate::GGUFFile::quant_report) tells which mix a file is.
+use crate::GGMLType;
+
+mod encode;
+mod ftype;
+mod report;
+
+pub use ftype::{mix_base_type, mix_tensor_type, mixes, quantize_file, QuantizeOptions};
+pub use report::{QuantReport, TypeUsage};
+
+/// Convert IEEE 754 half-precision bits to `f32`
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = u32::from(bits >> 15) << 31;
+    let exponent = u32::from(bits >> 10) & 0x1f;
+    let mantissa = u32::from(bits) & 0x3ff;
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        (0, _) => {
+            // subnormal: normalize the mantissa
+            let shift = mantissa.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | ((mantissa << shift) & 0x3ff) << 13
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 112) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+/// Convert `f32` to IEEE 754 half-precision bits, rounding to nearest even
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exponent = exponent - 112;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    // the bits shifted out decide the rounding; a carry into the exponent is still correct
+    let round = |value: u32, shift: u32| {
+        let (kept, rest, half) = (value >> shift, value & ((1 << shift) - 1), 1 << (shift - 1));
+        kept + u32::from(rest > half || (rest == half && kept & 1 == 1))
+    };
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        return sign | round(mantissa | 0x80_0000, (14 - exponent) as u32) as u16;
+    }
+    sign | round(((exponent as u32) << 23) | mantissa, 13) as u16
+}
+
+fn f16(bytes: &[u8], at: usize) -> f32 {
+    f16_to_f32(u16::from_le_bytes([bytes[at], bytes[at + 1]]))
+}
+
+/// the 6-bit scale and min of sub-block `j` packed in the 12 scale bytes of Q4_K and Q5_K
+fn scale_min_k4(j: usize, q: &[u8]) -> (f32, f32) {
+    let (scale, min) = if j < 4 {
+        (q[j] & 63, q[j + 4] & 63)
+    } else {
+        (
+            (q[j + 4] & 0xf) | ((q[j - 4] >> 6) << 4),
+            (q[j + 4] >> 4) | ((q[j] >> 6) << 4),
+        )
+    };
+    (f32::from(scale), f32::from(min))
+}
+
+fn block_q4_0(b: &[u8], out: &mut Vec<f32>) {
+    let d = f16(b, 0);
+    let qs = &b[2..18];
+    out.extend(qs.iter().map(|q| (i32::from(q & 0xf) - 8) as f32 * d));
+    out.extend(qs.iter().map(|q| (i32::from(q >> 4) - 8) as f32 * d));
+}
+
+fn block_q4_1(b: &[u8], out: &mut Vec<f32>) {
+    let (d, m) = (f16(b, 0), f16(b, 2));
+    let qs = &b[4..20];
+    out.extend(qs.iter().map(|q| f32::from(q & 0xf) * d + m));
+    out.extend(qs.iter().map(|q| f32::from(q >> 4) * d + m));
+}
+
+/// the 5-bit values of Q5_0 and Q5_1, from the low nibbles and the 32 high bits
+fn q5_values(qh: u32, qs: &[u8]) -> [u8; 32] {
+    let mut values = [0; 32];
+    for (j, q) in qs.iter().enumerate() {
+        let high_0 = (((qh >> j) << 4) & 0x10) as u8;
+        let high_1 = ((qh >> (j + 12)) & 0x10) as u8;
+        values[j] = (q & 0xf) | high_0;
+        values[j + 16] = (q >> 4) | high_1;
+    }
+    values
+}
+
+fn block_q5_0(b: &[u8], out: &mut Vec<f32>) {
+    let d = f16(b, 0);
+    let qh = u32::from_le_bytes([b[2], b[3], b[4], b[5]]);
+    let values = q5_values(qh, &b[6..22]);
+    out.extend(values.iter().map(|&v| (i32::from(v) - 16) as f32 * d));
+}
+
+fn block_q5_1(b: &[u8], out: &mut Vec<f32>) {
+    let (d, m) = (f16(b, 0), f16(b, 2));
+    let qh = u32::from_le_bytes([b[4], b[5], b[6], b[7]]);
+    let values = q5_values(qh, &b[8..24]);
+    out.extend(values.iter().map(|&v| f32::from(v) * d + m));
+}
+
+fn block_q8(d: f32, qs: &[u8], out: &mut Vec<f32>) {
+    out.extend(qs.iter().map(|&q| f32::from(q as i8) * d));
+}
+
+fn block_q2_k(b: &[u8], out: &mut Vec<f32>) {
+    let (scales, qs) = (&b[..16], &b[16..80]);
+    let (d, min) = (f16(b, 80), f16(b, 82));
+    let mut is = 0;
+    for q in qs.chunks_exact(32) {
+        for shift in [0, 2, 4, 6] {
+            for half in q.chunks_exact(16) {
+                let sc = scales[is];
+                is += 1;
+                let (dl, ml) = (d * f32::from(sc & 0xf), min * f32::from(sc >> 4));
+                out.extend(half.iter().map(|q| dl * f32::from((q >> shift) & 3) - ml));
+            }
+        }
+    }
+}
+
+fn block_q3_k(b: &[u8], out: &mut Vec<f32>) {
+    let (hmask, qs, packed) = (&b[..32], &b[32..96], &b[96..108]);
+    let d = f16(b, 108);
+    let mut aux = [0u32; 3];
+    for (word, chunk) in aux.iter_mut().zip(packed.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    let (k1, k2) = (0x0303_0303, 0x0f0f_0f0f);
+    let words = [
+        (aux[0] & k2) | ((aux[2] & k1) << 4),
+        (aux[1] & k2) | (((aux[2] >> 2) & k1) << 4),
+        ((aux[0] >> 4) & k2) | (((aux[2] >> 4) & k1) << 4),
+        ((aux[1] >> 4) & k2) | (((aux[2] >> 6) & k1) << 4),
+    ];
+    let scales: Vec<i32> = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .map(|s| i32::from(s as i8) - 32)
+        .collect();
+    let mut is = 0;
+    let mut m = 1u8;
+    for q in qs.chunks_exact(32) {
+        for shift in [0, 2, 4, 6] {
+            for (h, half) in q.chunks_exact(16).enumerate() {
+                let dl = d * scales[is] as f32;
+                is += 1;
+                let hm = &hmask[h * 16..h * 16 + 16];
+                out.extend(half.iter().zip(hm).map(|(q, hm)| {
+                    let low = i32::from((q >> shift) & 3);
+                    let high = if hm & m != 0 { 0 } else { 4 };
+                    dl * (low - high) as f32
+                }));
+            }
+            m <<= 1;
+        }
+    }
+}
+
+fn block_q4_k(b: &[u8], out: &mut Vec<f32>) {
+    let (d, min) = (f16(b, 0), f16(b, 2));
+    let (scales, qs) = (&b[4..16], &b[16..144]);
+    for (i, q) in qs.chunks_exact(32).enumerate() {
+        let (sc1, m1) = scale_min_k4(2 * i, scales);
+        let (sc2, m2) = scale_min_k4(2 * i + 1, scales);
+        out.extend(q.iter().map(|q| d * sc1 * f32::from(q & 0xf) - min * m1));
+        out.extend(q.iter().map(|q| d * sc2 * f32::from(q >> 4) - min * m2));
+    }
+}
+
+fn block_q5_k(b: &[u8], out: &mut Vec<f32>) {
+    let (d, min) = (f16(b, 0), f16(b, 2));
+    let (scales, qh, qs) = (&b[4..16], &b[16..48], &b[48..176]);
+    for (i, q) in qs.chunks_exact(32).enumerate() {
+        let (sc1, m1) = scale_min_k4(2 * i, scales);
+        let (sc2, m2) = scale_min_k4(2 * i + 1, scales);
+        let (u1, u2) = (1u8 << (2 * i), 2u8 << (2 * i));
+        out.extend(q.iter().zip(qh).map(|(q, h)| {
+            let high = if h & u1 != 0 { 16 } else { 0 };
+            d * sc1 * f32::from((q & 0xf) + high) - min * m1
+        }));
+        out.extend(q.iter().zip(qh).map(|(q, h)| {
+            let high = if h & u2 != 0 { 16 } else { 0 };
+            d * sc2 * f32::from((q >> 4) + high) - min * m2
+        }));
+    }
+}
+
+fn block_q6_k(b: &[u8], out: &mut Vec<f32>) {
+    let (ql, qh, scales) = (&b[..128], &b[128..192], &b[192..208]);
+    let d = f16(b, 208);
+    for n in 0..2 {
+        let (ql, qh, sc) = (&ql[n * 64..], &qh[n * 32..], &scales[n * 8..]);
+        let mut values = [0f32; 128];
+        for l in 0..32 {
+            let is = l / 16;
+            let q = |low: u8, shift: u8| {
+                f32::from(((low & 0xf) | (((qh[l] >> shift) & 3) << 4)) as i8 - 32)
+            };
+            let scale = |k: usize| d * f32::from(sc[is + k] as i8);
+            values[l] = scale(0) * q(ql[l], 0);
+            values[l + 32] = scale(2) * q(ql[l + 32], 2);
+            values[l + 64] = scale(4) * q(ql[l] >> 4, 4);
+            values[l + 96] = scale(6) * q(ql[l + 32] >> 4, 6);
+        }
+        out.extend(values);
+    }
+}
+
+/// Decode tensor data of the given type into `f32` values
+pub fn dequantize(tensor_type: GGMLType, data: &[u8]) -> Result<Vec<f32>, String> {
+    let (Some(block), Some(size)) = (tensor_type.block_size(), tensor_type.type_size()) else {
+        return Err(format!("{tensor_type:?} is not a tensor type"));
+    };
+    let size = size as usize;
+    if !data.len().is_multiple_of(size) {
+        return Err(format!(
+            "{} bytes are not a whole number of {tensor_type:?} blocks of {size} bytes",
+            data.len()
+        ));
+    }
+    let mut out = Vec::with_capacity(data.len() / size * block as usize);
+    for b in data.chunks_exact(size) {
+        match tensor_type {
+            GGMLType::F32 => out.push(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            GGMLType::F16 => out.push(f16(b, 0)),
+            GGMLType::BF16 => out.push(f32::from_bits(
+                u32::from(u16::from_le_bytes([b[0], b[1]])) << 16,
+            )),
+            GGMLType::F64 => out.push(f64::from_le_bytes(b.try_into().unwrap()) as f32),
+            GGMLType::I8 => out.push(f32::from(b[0] as i8)),
+            GGMLType::I16 => out.push(f32::from(i16::from_le_bytes([b[0], b[1]]))),
+            GGMLType::I32 => out.push(i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32),
+            GGMLType::I64 => out.push(i64::from_le_bytes(b.try_into().unwrap()) as f32),
+            GGMLType::Q4_0 => block_q4_0(b, &mut out),
+            GGMLType::Q4_1 => block_q4_1(b, &mut out),
+            GGMLType::Q5_0 => block_q5_0(b, &mut out),
+            GGMLType::Q5_1 => block_q5_1(b, &mut out),
+            GGMLType::Q8_0 => block_q8(f16(b, 0), &b[2..], &mut out),
+            GGMLType::Q8_1 => block_q8(f16(b, 0), &b[4..], &mut out),
+            GGMLType::Q2K => block_q2_k(b, &mut out),
+            GGMLType::Q3K => block_q3_k(b, &mut out),
+            GGMLType::Q4K => block_q4_k(b, &mut out),
+            GGMLType::Q5K => block_q5_k(b, &mut out),
+            GGMLType::Q6K => block_q6_k(b, &mut out),
+            GGMLType::Q8K => {
+                let d = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                block_q8(d, &b[4..260], &mut out)
+            }
+            GGMLType::IQ2XXS
+            | GGMLType::IQ2XS
+            | GGMLType::IQ3XXS
+            | GGMLType::IQ1S
+            | GGMLType::IQ4NL
+            | GGMLType::IQ3S
+            | GGMLType::IQ2S
+            | GGMLType::IQ4XS
+            | GGMLType::IQ1M
+            | GGMLType::TQ1_0
+            | GGMLType::TQ2_0
+            | GGMLType::MXFP4 => {
+                return Err(format!("dequantizing {tensor_type:?} is not supported"))
+            }
+            GGMLType::Count => unreachable!("Count has no block size"),
+        }
+    }
+    Ok(out)
+}
+
+/// Encode `f32` values as F32, F16, Q4_0, Q4_1, Q5_0, Q5_1, Q8_0, Q4_K, Q5_K or Q6_K data
+pub fn quantize(tensor_type: GGMLType, values: &[f32]) -> Result<Vec<u8>, String> {
+    let block = tensor_type.block_size().unwrap_or(1) as usize;
+    if !values.len().is_multiple_of(block) {
+        return Err(format!(
+            "{} values are not a whole number of {tensor_type:?} blocks of {block}",
+            values.len()
+        ));
+    }
+    let encode = match tensor_type {
+        GGMLType::F32 => return Ok(values.iter().flat_map(|v| v.to_le_bytes()).collect()),
+        GGMLType::F16 => {
+            return Ok(values
+                .iter()
+                .flat_map(|&v| f32_to_f16(v).to_le_bytes())
+                .collect())
+        }
+        GGMLType::Q4_0 => encode::q4_0,
+        GGMLType::Q4_1 => encode::q4_1,
+        GGMLType::Q5_0 => encode::q5_0,
+        GGMLType::Q5_1 => encode::q5_1,
+        GGMLType::Q8_0 => encode::q8_0,
+        GGMLType::Q4K => encode::q4_k,
+        GGMLType::Q5K => encode::q5_k,
+        GGMLType::Q6K => encode::q6_k,
+        _ => return Err(format!("quantizing to {tensor_type:?} is not supported")),
+    };
+    let size = tensor_type.type_size().unwrap_or_default() as usize;
+    let mut out = Vec::with_capacity(values.len() / block * size);
+    values.chunks_exact(block).for_each(|b| encode(b, &mut out));
+    Ok(out)
+}
+
+/// Parse a tensor type name such as `Q4_K` or `f16`
+pub fn parse_type(name: &str) -> Option<GGMLType> {
+    let name = name.to_ascii_uppercase().replace('_', "");
+    (0..crate::ggml::GGML_TYPE_COUNT)
+        .filter_map(|id| GGMLType::try_from(id).ok())
+        .find(|t| format!("{t:?}").replace('_', "") == name && *t != GGMLType::Count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_blocks() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert!(f16_to_f32(0x7e00).is_nan());
+        assert_eq!(dequantize(GGMLType::BF16, &[0x80, 0x3f]), Ok(vec![1.0]));
+        assert!(dequantize(GGMLType::IQ4NL, &[0; 18]).is_err());
+
+        // d = 0.5, nibbles 0..16 then 16..0 less 8
+        let mut q4 = 0x3800u16.to_le_bytes().to_vec();
+        q4.extend((0..16).map(|i| i as u8 | ((15 - i as u8) << 4)));
+        let values = dequantize(GGMLType::Q4_0, &q4).unwrap();
+        assert_eq!(values[..3], [-4.0, -3.5, -3.0]);
+        assert_eq!(values[16..18], [3.5, 3.0]);
+
+        let mut q8 = 0x4000u16.to_le_bytes().to_vec();
+        q8.extend((0..32).map(|i| (i as i8 - 16) as u8));
+        assert_eq!(dequantize(GGMLType::Q8_0, &q8).unwrap()[0], -32.0);
+
+        // Q4_K with d = 1, no min and every sub-block scale 1: values are the nibbles
+        let mut q4k = [0x3c00u16.to_le_bytes(), [0, 0]].concat();
+        q4k.extend([1, 1, 1, 1, 0, 0, 0, 0, 1, 1, 1, 1]);
+        q4k.extend([0x21; 128]);
+        let values = dequantize(GGMLType::Q4K, &q4k).unwrap();
+        assert_eq!(values.len(), 256);
+        assert_eq!((values[0], values[32]), (1.0, 2.0));
+
+        // Q6_K with d = 1 and scale 1: all-zero quants decode to -32
+        let mut q6k = vec![0; 192];
+        q6k.extend([1; 16]);
+        q6k.extend(0x3c00u16.to_le_bytes());
+        assert!(dequantize(GGMLType::Q6K, &q6k)
+            .unwrap()
+            .iter()
+            .all(|&v| v == -32.0));
+        assert!(dequantize(GGMLType::Q4_0, &q4[..10]).is_err());
+    }
+
+    #[test]
+    fn encode_round_trips() {
+        for bits in (0..=0xffffu16).filter(|b| b & 0x7c00 != 0x7c00) {
+            assert_eq!(f32_to_f16(f16_to_f32(bits)), bits, "{bits:#x}");
+        }
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+
+        let values: Vec<f32> = (0..64).map(|i| (i as f32 - 20.0) / 8.0).collect();
+        let data = quantize(GGMLType::Q8_0, &values).unwrap();
+        assert_eq!(data.len(), 68);
+        let decoded = dequantize(GGMLType::Q8_0, &data).unwrap();
+        assert!(values
+            .iter()
+            .zip(decoded)
+            .all(|(a, b)| (a - b).abs() < 0.03));
+        assert!(quantize(GGMLType::Q2K, &values).is_err());
+
+        // every encoder decodes back close to its input
+        let values: Vec<f32> = (0..512)
+            .map(|i| ((i * 37 % 101) as f32 - 50.0) / 25.0)
+            .collect();
+        for (tensor_type, tolerance) in [
+            (GGMLType::Q4_0, 0.3),
+            (GGMLType::Q4_1, 0.15),
+            (GGMLType::Q5_0, 0.15),
+            (GGMLType::Q5_1, 0.07),
+            (GGMLType::Q4K, 0.14),
+            (GGMLType::Q5K, 0.07),
+            (GGMLType::Q6K, 0.04),
+        ] {
+            let data = quantize(tensor_type, &values).unwrap();
+            assert_eq!(Some(data.len() as u64), tensor_type.size_of(512));
+            let decoded = dequantize(tensor_type, &data).unwrap();
+            let error = values
+                .iter()
+                .zip(decoded)
+                .fold(0f32, |max, (a, b)| max.max((a - b).abs()));
+            assert!(error < tolerance, "{tensor_type:?} is off by {error}");
+        }
+        assert_eq!(parse_type("q4_k"), Some(GGMLType::Q4K));
+        assert_eq!(parse_type("F16"), Some(GGMLType::F16));
+    }
+}
@@ -0,0 +1,473 @@
+//! Block encoders, ported from the reference (`_ref`) quantizers in ggml
+use super::{f16_to_f32, f32_to_f16};
+
+/// groups whose largest magnitude is below this are all zeros
+const GROUP_MAX_EPS: f32 = 1e-15;
+
+fn nearest_int(value: f32) -> i32 {
+    value.round_ties_even() as i32
+}
+
+/// the value of largest magnitude, with its sign
+fn signed_max(values: &[f32]) -> f32 {
+    values
+        .iter()
+        .fold(0f32, |max, &v| if v.abs() > max.abs() { v } else { max })
+}
+
+fn inverse(d: f32) -> f32 {
+    if d == 0.0 {
+        0.0
+    } else {
+        1.0 / d
+    }
+}
+
+pub(super) fn q4_0(x: &[f32], out: &mut Vec<u8>) {
+    let d = signed_max(x) / -8.0;
+    let id = inverse(d);
+    out.extend(f32_to_f16(d).to_le_bytes());
+    for j in 0..16 {
+        let low = ((x[j] * id + 8.5) as i8).min(15) as u8;
+        let high = ((x[j + 16] * id + 8.5) as i8).min(15) as u8;
+        out.push(low | (high << 4));
+    }
+}
+
+fn min_max(x: &[f32]) -> (f32, f32) {
+    x.iter()
+        .fold((f32::MAX, f32::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)))
+}
+
+pub(super) fn q4_1(x: &[f32], out: &mut Vec<u8>) {
+    let (min, max) = min_max(x);
+    let d = (max - min) / 15.0;
+    let id = inverse(d);
+    out.extend(f32_to_f16(d).to_le_bytes());
+    out.extend(f32_to_f16(min).to_le_bytes());
+    for j in 0..16 {
+        let low = (((x[j] - min) * id + 0.5) as i8).min(15) as u8;
+        let high = (((x[j + 16] - min) * id + 0.5) as i8).min(15) as u8;
+        out.push(low | (high << 4));
+    }
+}
+
+/// pack 32 5-bit values as the 32 high bits followed by the low nibbles
+fn q5_pack(values: [u8; 32], out: &mut Vec<u8>) {
+    let mut qh = 0u32;
+    for (j, &v) in values.iter().enumerate() {
+        qh |= u32::from(v >> 4) << j;
+    }
+    out.extend(qh.to_le_bytes());
+    out.extend((0..16).map(|j| (values[j] & 0xf) | ((values[j + 16] & 0xf) << 4)));
+}
+
+pub(super) fn q5_0(x: &[f32], out: &mut Vec<u8>) {
+    let d = signed_max(x) / -16.0;
+    let id = inverse(d);
+    out.extend(f32_to_f16(d).to_le_bytes());
+    q5_pack(
+        std::array::from_fn(|j| ((x[j] * id + 16.5) as i8).min(31) as u8),
+        out,
+    );
+}
+
+pub(super) fn q5_1(x: &[f32], out: &mut Vec<u8>) {
+    let (min, max) = min_max(x);
+    let d = (max - min) / 31.0;
+    let id = inverse(d);
+    out.extend(f32_to_f16(d).to_le_bytes());
+    out.extend(f32_to_f16(min).to_le_bytes());
+    q5_pack(
+        std::array::from_fn(|j| ((x[j] - min) * id + 0.5) as u8),
+        out,
+    );
+}
+
+pub(super) fn q8_0(x: &[f32], out: &mut Vec<u8>) {
+    let max = x.iter().fold(0f32, |max, v| max.max(v.abs()));
+    let d = max / 127.0;
+    let id = inverse(d);
+    out.extend(f32_to_f16(d).to_le_bytes());
+    out.extend(x.iter().map(|v| (v * id).round() as i8 as u8));
+}
+
+/// Find a scale and a minimum for `x` with levels `0..=max_level`, searching `steps` scales
+/// around the plain one for the smallest weighted squared error, as ggml's `make_qkx2_quants`
+///
+/// Returns the scale and the negated minimum.
+fn scale_min_search(
+    x: &[f32],
+    weights: &[f32],
+    levels: &mut [u8],
+    max_level: u8,
+    rmin: f32,
+    rdelta: f32,
+    steps: u32,
+) -> (f32, f32) {
+    let n_max = f32::from(max_level);
+    let (mut min, max) = min_max(x);
+    let sum_w: f32 = weights.iter().sum();
+    let sum_x: f32 = x.iter().zip(weights).map(|(x, w)| x * w).sum();
+    if min > 0.0 {
+        min = 0.0;
+    }
+    if max == min {
+        levels.fill(0);
+        return (0.0, -min);
+    }
+    let quantize = |iscale: f32, x: f32, min: f32| {
+        nearest_int(iscale * (x - min)).clamp(0, i32::from(max_level)) as u8
+    };
+    let mut iscale = n_max / (max - min);
+    let mut scale = 1.0 / iscale;
+    let mut best_error = 0.0;
+    for ((l, &x), w) in levels.iter_mut().zip(x).zip(weights) {
+        *l = quantize(iscale, x, min);
+        let diff = scale * f32::from(*l) + min - x;
+        best_error += w * (diff * diff);
+    }
+    let mut candidate = vec![0u8; x.len()];
+    for step in 0..=steps {
+        iscale = (rmin + rdelta * step as f32 + n_max) / (max - min);
+        let (mut sum_l, mut sum_l2, mut sum_xl) = (0.0, 0.0, 0.0);
+        for ((l, &x), &w) in candidate.iter_mut().zip(x).zip(weights) {
+            *l = quantize(iscale, x, min);
+            let l = f32::from(*l);
+            sum_l += w * l;
+            sum_l2 += w * l * l;
+            sum_xl += w * l * x;
+        }
+        let det = sum_w * sum_l2 - sum_l * sum_l;
+        if det > 0.0 {
+            let mut this_scale = (sum_w * sum_xl - sum_x * sum_l) / det;
+            let mut this_min = (sum_l2 * sum_x - sum_l * sum_xl) / det;
+            if this_min > 0.0 {
+                this_min = 0.0;
+                this_scale = sum_xl / sum_l2;
+            }
+            let error: f32 = candidate
+                .iter()
+                .zip(x)
+                .zip(weights)
+                .map(|((&l, &x), w)| {
+                    let diff = this_scale * f32::from(l) + this_min - x;
+                    w * (diff * diff)
+                })
+                .sum();
+            if error < best_error {
+                levels.copy_from_slice(&candidate);
+                best_error = error;
+                scale = this_scale;
+                min = this_min;
+            }
+        }
+    }
+    (scale, -min)
+}
+
+/// Find a symmetric scale for `x` with levels `-max_level..max_level`, weighting by `x²`, as
+/// ggml's `make_qx_quants`; `levels` are offset by `max_level`
+fn symmetric_search(x: &[f32], levels: &mut [u8], max_level: i32) -> f32 {
+    let max = signed_max(x);
+    if max.abs() < GROUP_MAX_EPS {
+        levels.fill(0);
+        return 0.0;
+    }
+    let n_max = max_level as f32;
+    let level = |iscale: f32, x: f32| nearest_int(iscale * x).clamp(-max_level, max_level - 1);
+    let sums = |iscale: f32| {
+        x.iter().fold((0.0, 0.0), |(sum_lx, sum_l2), &x| {
+            let (l, w) = (level(iscale, x) as f32, x * x);
+            (sum_lx + w * x * l, sum_l2 + w * l * l)
+        })
+    };
+    let iscale = -n_max / max;
+    for (l, &x) in levels.iter_mut().zip(x) {
+        *l = (level(iscale, x) + max_level) as u8;
+    }
+    let (sum_lx, sum_l2) = sums(iscale);
+    let mut scale = if sum_l2 != 0.0 { sum_lx / sum_l2 } else { 0.0 };
+    let mut best = scale * sum_lx;
+    for step in (-9..=9).filter(|&s| s != 0) {
+        let iscale = -(n_max + 0.1 * step as f32) / max;
+        let (sum_lx, sum_l2) = sums(iscale);
+        if sum_l2 > 0.0 && sum_lx * sum_lx > best * sum_l2 {
+            for (l, &x) in levels.iter_mut().zip(x) {
+                *l = (level(iscale, x) + max_level) as u8;
+            }
+            scale = sum_lx / sum_l2;
+            best = scale * sum_lx;
+        }
+    }
+    scale
+}
+
+/// the scales and minimums of the eight sub-blocks of Q4_K and Q5_K, each quantized to six bits
+/// against a super-block scale, packed into 12 bytes
+fn pack_scales_k4(scales: &[f32; 8], mins: &[f32; 8], out: &mut Vec<u8>) -> [(f32, f32); 8] {
+    let max_scale = scales.iter().fold(0f32, |m, &s| m.max(s));
+    let max_min = mins.iter().fold(0f32, |m, &s| m.max(s));
+    let inv_scale = if max_scale > 0.0 {
+        63.0 / max_scale
+    } else {
+        0.0
+    };
+    let inv_min = if max_min > 0.0 { 63.0 / max_min } else { 0.0 };
+    let mut packed = [0u8; 12];
+    for j in 0..8 {
+        let ls = nearest_int(inv_scale * scales[j]).min(63) as u8;
+        let lm = nearest_int(inv_min * mins[j]).min(63) as u8;
+        if j < 4 {
+            packed[j] = ls;
+            packed[j + 4] = lm;
+        } else {
+            packed[j + 4] = (ls & 0xf) | ((lm & 0xf) << 4);
+            packed[j - 4] |= (ls >> 4) << 6;
+            packed[j] |= (lm >> 4) << 6;
+        }
+    }
+    let d = f32_to_f16(max_scale / 63.0);
+    let dmin = f32_to_f16(max_min / 63.0);
+    out.extend(d.to_le_bytes());
+    out.extend(dmin.to_le_bytes());
+    out.extend(packed);
+    std::array::from_fn(|j| {
+        let (sc, m) = super::scale_min_k4(j, &packed);
+        (f16_to_f32(d) * sc, f16_to_f32(dmin) * m)
+    })
+}
+
+/// levels of the 256 values of a Q4_K or Q5_K super-block
+fn k4_levels(x: &[f32], max_level: u8, rmin: f32, steps: u32, out: &mut Vec<u8>) -> [u8; 256] {
+    let mut levels = [0u8; 256];
+    let (mut scales, mut mins) = ([0f32; 8], [0f32; 8]);
+    for j in 0..8 {
+        let sub = &x[32 * j..32 * j + 32];
+        let av_x = (sub.iter().map(|v| v * v).sum::<f32>() / 32.0).sqrt();
+        let weights: Vec<f32> = sub.iter().map(|v| av_x + v.abs()).collect();
+        (scales[j], mins[j]) = scale_min_search(
+            sub,
+            &weights,
+            &mut levels[32 * j..32 * j + 32],
+            max_level,
+            rmin,
+            0.1,
+            steps,
+        );
+    }
+    let dequant = pack_scales_k4(&scales, &mins, out);
+    for (j, (d, dm)) in dequant.into_iter().enumerate() {
+        if d == 0.0 {
+            continue;
+        }
+        for i in 32 * j..32 * j + 32 {
+            levels[i] = nearest_int((x[i] + dm) / d).clamp(0, i32::from(max_level)) as u8;
+        }
+    }
+    levels
+}
+
+pub(super) fn q4_k(x: &[f32], out: &mut Vec<u8>) {
+    let levels = k4_levels(x, 15, -1.0, 20, out);
+    for n in (0..256).step_by(64) {
+        out.extend((0..32).map(|l| levels[n + l] | (levels[n + l + 32] << 4)));
+    }
+}
+
+pub(super) fn q5_k(x: &[f32], out: &mut Vec<u8>) {
+    let levels = k4_levels(x, 31, -0.5, 15, out);
+    let mut qh = [0u8; 32];
+    let mut ql = Vec::with_capacity(128);
+    for (i, n) in (0..256).step_by(64).enumerate() {
+        let (m1, m2) = (1u8 << (2 * i), 2u8 << (2 * i));
+        for j in 0..32 {
+            let (l1, l2) = (levels[n + j], levels[n + j + 32]);
+            if l1 > 15 {
+                qh[j] |= m1;
+            }
+            if l2 > 15 {
+                qh[j] |= m2;
+            }
+            ql.push((l1 & 0xf) | ((l2 & 0xf) << 4));
+        }
+    }
+    out.extend(qh);
+    out.extend(ql);
+}
+
+pub(super) fn q6_k(x: &[f32], out: &mut Vec<u8>) {
+    let mut levels = [0u8; 256];
+    let mut scales = [0f32; 16];
+    for (ib, scale) in scales.iter_mut().enumerate() {
+        *scale = symmetric_search(
+            &x[16 * ib..16 * ib + 16],
+            &mut levels[16 * ib..16 * ib + 16],
+            32,
+        );
+    }
+    let max_scale = signed_max(&scales);
+    if max_scale.abs() < GROUP_MAX_EPS {
+        out.extend([0; 210]);
+        return;
+    }
+    let iscale = -128.0 / max_scale;
+    let d = f32_to_f16(1.0 / iscale);
+    let packed_scales: [i8; 16] =
+        std::array::from_fn(|ib| nearest_int(iscale * scales[ib]).min(127) as i8);
+    for (j, &sc) in packed_scales.iter().enumerate() {
+        let d = f16_to_f32(d) * f32::from(sc);
+        if d == 0.0 {
+            continue;
+        }
+        for i in 16 * j..16 * j + 16 {
+            levels[i] = (nearest_int(x[i] / d).clamp(-32, 31) + 32) as u8;
+        }
+    }
+    let (mut ql, mut qh) = (Vec::with_capacity(128), Vec::with_capacity(64));
+    for j in (0..256).step_by(128) {
+        let l = &levels[j..j + 128];
+        ql.extend((0..32).map(|i| (l[i] & 0xf) | ((l[i + 64] & 0xf) << 4)));
+        ql.extend((0..32).map(|i| (l[i + 32] & 0xf) | ((l[i + 96] & 0xf) << 4)));
+        qh.extend((0..32).map(|i| {
+            (l[i] >> 4)
+                | ((l[i + 32] >> 4) << 2)
+                | ((l[i + 64] >> 4) << 4)
+                | ((l[i + 96] >> 4) << 6)
+        }));
+    }
+    out.extend(ql);
+    out.extend(qh);
+    out.extend(packed_scales.map(|s| s as u8));
+    out.extend(d.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::hex;
+    use crate::quant::dequantize;
+    use crate::GGMLType;
+
+    /// 256 values spread over `-2..2` in no particular order
+    fn values() -> Vec<f32> {
+        (0..256)
+            .map(|i| ((i * 37 % 101) as f32 - 50.0) / 25.0)
+            .collect()
+    }
+
+    fn encode(encoder: fn(&[f32], &mut Vec<u8>), x: &[f32]) -> String {
+        let mut out = Vec::new();
+        encoder(x, &mut out);
+        hex(&out)
+    }
+
+    #[test]
+    fn golden_blocks() {
+        // -8..8 twice: d is 1 and every value is its own level
+        let ramp: Vec<f32> = (0..32).map(|i| (i % 16) as f32 - 8.0).collect();
+        assert_eq!(encode(q4_0, &ramp), "003c00112233445566778899aabbccddeeff");
+        assert_eq!(encode(q6_k, &[0.0; 256]), hex(&[0; 210]));
+
+        // the bytes of ggml's quantize_row_*_ref for these values, worked out by a transcription
+        // of it into Python that rounds every operation to f32
+        let x = values();
+        assert_eq!(
+            encode(q4_0, &x[..32]),
+            "0034e046acf258bd1379df358be046acf268"
+        );
+        assert_eq!(
+            encode(q4_1, &x[..32]),
+            "443400c0d0369bf157ad1369ce258ad0469cf257"
+        );
+        assert_eq!(
+            encode(q5_0, &x[..32]),
+            "0030a4a52d6dc07c38f3bf6b26e2ae5a15d18d49f4c0"
+        );
+        assert_eq!(
+            encode(q5_1, &x[..32]),
+            "213000c0a4a52d6db07b37e3af6a26d29d5905c18c48f4b0"
+        );
+        assert_eq!(
+            encode(q8_0, &x[..32]),
+            "082481df3d9af856b41270cd2b89e745a2005ebb1977d53390ee4caa0866c321\
+            7fdc"
+        );
+        assert_eq!(
+            encode(q4_k, &x),
+            "651ce627fefffffcfffdfefecfdcfffea0156bc1278cd3489e045ab0167bc237\
+            8de349ae056ab1267cd2389de459af15268ce248ad0459b0157bd1379ce348ae\
+            046ac0267bd2379df359be156ac1268c9df359be157ac1268ce248ad0459b015\
+            7bd1379ce348ae046ac0267bd2379df3156ac1268cd2489df459b0157bc1378c\
+            e348ae046ab0267bd2379de359ae156a"
+        );
+        assert_eq!(
+            encode(q5_k, &x),
+            "32180c28fffdfdfdfffcfeffdfeffdcf326c9936cc9366c9b36499324c9926cc\
+            9366c9336c99364c9b66cd9366d9336c702be7a35f1ad6824d09c5813cf8b46f\
+            2be7935e1ad6824d09c5703cf8a36f2b5e1ad5914d09c4803cf8b36f2be6a25e\
+            1ad5914d09c4703cf7a36f2be6a25e1a3be8b36e2be6925e19c5814c08b4703c\
+            f7a36f2ad6925d19c5814cf8b4703be71bd7824e0ab5713de9a4602cd8935f1b\
+            c6824e0ab5713de9a4601cd7935f1ac6"
+        );
+        assert_eq!(
+            encode(q6_k, &x),
+            "d158bf37be25ad048b047bf37ad159d0c941ea63eb74fb741d951ea72fc841c9\
+            bf35ad169b048df26be069d147cf38bd1a922c840d85ed77ff57d038b04aa22b\
+            67df58c139b22aa22b931c85fd76ee57ce5ad137b31a902c830f86ec78ff55d1\
+            c841da62eb63fc840d951ea63fb840c9d129b30b931c74fe86de67ff47d129b1\
+            18b1c66cb11b6c861b71c61cb1c76cb22453f9248ef9138ef4538d2452f9248e\
+            d4a209d47e09a37e14a349d4ae09d77ec863be1463c9147ec923bec463bd1463\
+            807f897a867a84877f737d7a81877c892290"
+        );
+    }
+
+    #[test]
+    fn round_trips_within_a_step() {
+        let mut seed = 12345u32;
+        let x: Vec<f32> = (0..1024)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                ((seed >> 8) % 2001) as f32 / 500.0 - 2.0
+            })
+            .collect();
+        // the largest error, in steps of the block's range over its levels, the range of the
+        // symmetric types being twice the largest magnitude: half a step and the rounding of the
+        // f16 scales, which Q8_0 multiplies by up to 127, and Q4_0 and Q5_0 lose a whole step at
+        // the clipped end
+        for (tensor_type, encoder, levels, bound) in [
+            (GGMLType::Q4_0, q4_0 as fn(&[f32], &mut Vec<u8>), 15.0, 1.0),
+            (GGMLType::Q4_1, q4_1, 15.0, 0.51),
+            (GGMLType::Q5_0, q5_0, 31.0, 1.0),
+            (GGMLType::Q5_1, q5_1, 31.0, 0.51),
+            (GGMLType::Q8_0, q8_0, 254.0, 0.6),
+            (GGMLType::Q4K, q4_k, 15.0, 0.75),
+            (GGMLType::Q5K, q5_k, 31.0, 0.75),
+            (GGMLType::Q6K, q6_k, 63.0, 0.75),
+        ] {
+            let block = tensor_type.block_size().unwrap() as usize;
+            for b in x.chunks(block) {
+                let mut data = Vec::new();
+                encoder(b, &mut data);
+                let decoded = dequantize(tensor_type, &data).unwrap();
+                let (min, max) = min_max(b);
+                let span = match tensor_type {
+                    GGMLType::Q4_0 | GGMLType::Q5_0 | GGMLType::Q8_0 | GGMLType::Q6K => {
+                        2.0 * signed_max(b).abs()
+                    }
+                    _ => max - min,
+                };
+                let step = span / levels;
+                let error = b
+                    .iter()
+                    .zip(decoded)
+                    .fold(0f32, |e, (a, b)| e.max((a - b).abs()));
+                assert!(
+                    error <= bound * step,
+                    "{tensor_type:?} is off by {} steps",
+                    error / step
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! llama.cpp's quantization mixes, and requantizing whole files to them
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use super::{dequantize, quantize};
+use crate::ggml::{LlamaFileType, LLAMA_FTYPES};
+use crate::manifest::read_header;
+use crate::writer::{copy_data, write_file, WriteError};
+use crate::{GGMLType, GGUFMetadataValue, GGUFTensorInfo};
+
+/// whether a layer of the `Q*_K_M` mixes gets more bits: the first and last eighth and every
+/// third one in between
+fn use_more_bits(layer: u64, layers: u64) -> bool {
+    layer < layers / 8 || layer >= 7 * layers / 8 || (layer - layers / 8) % 3 == 2
+}
+
+/// the layer of a `blk.N.` tensor
+fn layer(name: &str) -> Option<u64> {
+    name.strip_prefix("blk.")?.split('.').next()?.parse().ok()
+}
+
+/// The type of most matrices of a mix, `None` for the mixes this crate cannot quantize to
+pub fn mix_base_type(file_type: LlamaFileType) -> Option<GGMLType> {
+    use LlamaFileType as F;
+    Some(match file_type {
+        F::ALL_F32 => GGMLType::F32,
+        F::MOSTLY_F16 => GGMLType::F16,
+        F::MOSTLY_Q4_0 => GGMLType::Q4_0,
+        F::MOSTLY_Q4_1 => GGMLType::Q4_1,
+        F::MOSTLY_Q5_0 => GGMLType::Q5_0,
+        F::MOSTLY_Q5_1 => GGMLType::Q5_1,
+        F::MOSTLY_Q8_0 => GGMLType::Q8_0,
+        F::MOSTLY_Q4_K_S | F::MOSTLY_Q4_K_M => GGMLType::Q4K,
+        F::MOSTLY_Q5_K_S | F::MOSTLY_Q5_K_M => GGMLType::Q5K,
+        F::MOSTLY_Q6_K => GGMLType::Q6K,
+        _ => return None,
+    })
+}
+
+/// The mixes this crate can quantize to, in the order of llama.cpp
+pub fn mixes() -> impl Iterator<Item = LlamaFileType> {
+    LLAMA_FTYPES
+        .iter()
+        .map(|t| LlamaFileType::from_id(t.id))
+        .filter(|&t| mix_base_type(t).is_some())
+}
+
+/// The type a matrix gets in a mix, `layers` being the block count, `None` if the mix is not
+/// one of [`mixes`]
+///
+/// The output projection gets Q6_K, and the value projections and feed forward outputs of
+/// some layers get more bits in the K mixes. Types whose blocks do not fit the rows fall back
+/// to a legacy type, or to F16.
+pub fn mix_tensor_type(
+    file_type: LlamaFileType,
+    tensor: &GGUFTensorInfo,
+    layers: u64,
+) -> Option<GGMLType> {
+    use LlamaFileType as F;
+    let base = mix_base_type(file_type)?;
+    let layer = layer(&tensor.name);
+    let wanted = match (file_type, tensor.name.rsplit_once('.').map_or("", |n| n.0)) {
+        (F::ALL_F32 | F::MOSTLY_F16 | F::MOSTLY_Q8_0 | F::MOSTLY_Q6_K, _) => base,
+        (_, "output") => GGMLType::Q6K,
+        (F::MOSTLY_Q4_K_M | F::MOSTLY_Q5_K_M, name)
+            if name.ends_with(".attn_v") || name.ends_with(".ffn_down") =>
+        {
+            match layer {
+                Some(layer) if use_more_bits(layer, layers) => GGMLType::Q6K,
+                _ => base,
+            }
+        }
+        (F::MOSTLY_Q4_K_S, name) if name.ends_with(".attn_v") => match layer {
+            Some(layer) if layer < 4 => GGMLType::Q5K,
+            _ => base,
+        },
+        (F::MOSTLY_Q4_K_S, name) if name.ends_with(".ffn_down") => match layer {
+            Some(layer) if layer < layers / 8 => GGMLType::Q5K,
+            _ => base,
+        },
+        _ => base,
+    };
+    Some(fits(wanted, tensor))
+}
+
+/// `wanted` if its blocks fit the rows of the tensor, otherwise the closest type that does
+fn fits(wanted: GGMLType, tensor: &GGUFTensorInfo) -> GGMLType {
+    let row = tensor.dimensions.first().copied().unwrap_or(1);
+    let fits = |t: GGMLType| t.block_size().is_some_and(|b| row.is_multiple_of(b));
+    let fallback = match wanted {
+        GGMLType::Q4K => GGMLType::Q5_0,
+        GGMLType::Q5K => GGMLType::Q5_1,
+        GGMLType::Q6K => GGMLType::Q8_0,
+        _ => GGMLType::F16,
+    };
+    [wanted, fallback]
+        .into_iter()
+        .find(|&t| fits(t))
+        .unwrap_or(GGMLType::F16)
+}
+
+/// How to requantize a file
+#[derive(Debug, Clone)]
+pub struct QuantizeOptions {
+    /// One of [`mixes`].
+    pub file_type: LlamaFileType,
+    /// Types to use for tensors by name instead of the mix's.
+    pub overrides: Vec<(String, GGMLType)>,
+    /// Threads to quantize each tensor with.
+    pub threads: usize,
+}
+
+impl QuantizeOptions {
+    /// Quantize to `file_type` with a thread per core
+    pub fn new(file_type: LlamaFileType) -> Self {
+        QuantizeOptions {
+            file_type,
+            overrides: Vec::new(),
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+/// quantize on `threads` threads, each taking a run of whole blocks
+fn quantize_parallel(
+    tensor_type: GGMLType,
+    values: &[f32],
+    threads: usize,
+) -> Result<Vec<u8>, String> {
+    let block = tensor_type.block_size().unwrap_or(1) as usize;
+    let chunk = values
+        .len()
+        .div_ceil(threads.max(1))
+        .next_multiple_of(block)
+        .max(block);
+    let parts = std::thread::scope(|scope| {
+        let handles: Vec<_> = values
+            .chunks(chunk)
+            .map(|part| scope.spawn(move || quantize(tensor_type, part)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("quantizing does not panic"))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    Ok(parts.concat())
+}
+
+/// Requantize `src` into `dst`
+///
+/// Matrices are decoded and encoded in the types of the mix, or of an override; vectors and
+/// tensors already of the right type are copied. `progress` is called before each tensor with
+/// its index and its old and new info.
+pub fn quantize_file(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    options: &QuantizeOptions,
+    mut progress: impl FnMut(usize, &GGUFTensorInfo, &GGUFTensorInfo),
+) -> Result<(), WriteError> {
+    if mix_base_type(options.file_type).is_none() {
+        return Err(WriteError::Invalid(format!(
+            "quantizing to {} is not supported",
+            options.file_type
+        )));
+    }
+    let mut input = File::open(src)?;
+    let (source, _, data_start) = read_header(&mut input)?;
+    let layers = source
+        .tensors
+        .iter()
+        .filter_map(|t| layer(&t.name))
+        .max()
+        .map_or(0, |l| l + 1);
+    let mut file = source.clone();
+    for tensor in &mut file.tensors {
+        let wanted = options
+            .overrides
+            .iter()
+            .find(|(name, _)| *name == tensor.name)
+            .map(|(_, t)| fits(*t, tensor));
+        let is_matrix = tensor.dimensions.len() >= 2 && tensor.name.ends_with("weight");
+        tensor.tensor_type = match wanted {
+            Some(wanted) => wanted,
+            None if is_matrix => {
+                mix_tensor_type(options.file_type, tensor, layers).unwrap_or(tensor.tensor_type)
+            }
+            None => tensor.tensor_type,
+        };
+    }
+    let header = &mut file.header;
+    header.set(
+        "general.file_type",
+        GGUFMetadataValue::Uint32(options.file_type.id()),
+    );
+    header.set("general.quantization_version", GGUFMetadataValue::Uint32(2));
+
+    let mut index = 0;
+    write_file(dst, &file, |tensor, out| {
+        let from = &source.tensors[index];
+        progress(index, from, tensor);
+        index += 1;
+        let start = data_start + from.offset;
+        if from.tensor_type == tensor.tensor_type {
+            return copy_data(&mut input, start, from, out);
+        }
+        let size = from.size_bytes().unwrap_or_default();
+        let mut data = vec![0; size as usize];
+        input
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| input.read_exact(&mut data))
+            .map_err(|e| format!("reading tensor {}: {e}", from.name))?;
+        let values = dequantize(from.tensor_type, &data)?;
+        let data = quantize_parallel(tensor.tensor_type, &values, options.threads)?;
+        Ok(out.write_all(&data)?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn mixes_give_more_bits_where_llama_cpp_does() {
+        use LlamaFileType as F;
+        let tensor = |name: &str, row| test_util::tensor(name, &[row, 64], GGMLType::F16, 0);
+        let types = |file_type, name: &str| {
+            (0..16)
+                .map(|l| {
+                    let tensor = tensor(&name.replace('N', &l.to_string()), 256);
+                    mix_tensor_type(file_type, &tensor, 16).unwrap()
+                })
+                .filter(|t| *t == GGMLType::Q6K)
+                .count()
+        };
+        assert_eq!(types(F::MOSTLY_Q4_K_M, "blk.N.attn_v.weight"), 8);
+        assert_eq!(types(F::MOSTLY_Q4_K_M, "blk.N.attn_q.weight"), 0);
+        assert_eq!(types(F::MOSTLY_Q4_K_S, "blk.N.ffn_down.weight"), 0);
+        let output = tensor("output.weight", 256);
+        assert_eq!(
+            mix_tensor_type(F::MOSTLY_Q4_0, &output, 16),
+            Some(GGMLType::Q6K)
+        );
+        assert_eq!(
+            mix_tensor_type(F::MOSTLY_Q4_K_M, &tensor("blk.0.attn_q.weight", 96), 16),
+            Some(GGMLType::Q5_0)
+        );
+        assert_eq!(mix_tensor_type(F::MOSTLY_Q2_K, &output, 16), None);
+        let names: Vec<String> = mixes().map(|t| t.to_string()).collect();
+        assert_eq!(
+            names,
+            [
+                "F32", "F16", "Q4_0", "Q4_1", "Q8_0", "Q5_0", "Q5_1", "Q4_K_S", "Q4_K_M", "Q5_K_S",
+                "Q5_K_M", "Q6_K"
+            ]
+        );
+    }
+}
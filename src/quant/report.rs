@@ -0,0 +1,196 @@
+//! What a file is quantized as, told from its tensors
+
+use crate::ggml::LlamaFileType;
+use crate::{GGMLType, GGUFFile, GGUFTensorInfo};
+
+/// The tensors of one type and the bytes of their data
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeUsage {
+    pub tensor_type: GGMLType,
+    pub tensors: usize,
+    pub bytes: u64,
+}
+
+/// The types of the tensors of a file and the file type they add up to, as
+/// [`GGUFFile::quant_report`] gives them
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantReport {
+    /// Each type the tensors have, the most bytes first.
+    pub types: Vec<TypeUsage>,
+    /// The file type the types make, `None` if the file has no matrices.
+    pub inferred: Option<LlamaFileType>,
+    /// The file type `general.file_type` declares.
+    pub declared: Option<LlamaFileType>,
+}
+
+impl QuantReport {
+    /// Whether the file declares a file type other than the one its tensors make
+    pub fn mismatch(&self) -> bool {
+        matches!((self.declared, self.inferred), (Some(d), Some(i)) if d != i)
+    }
+}
+
+/// Bits per weight, to tell which types spend more on a tensor
+fn bits(t: GGMLType) -> f64 {
+    match (t.type_size(), t.block_size()) {
+        (Some(size), Some(block)) => (size * 8) as f64 / block as f64,
+        _ => 0.0,
+    }
+}
+
+/// The file type of a file whose matrices mostly have the type `base`, `more` telling the
+/// types of its `attn_v` and `ffn_down` tensors, which the K mixes give more bits
+fn file_type(base: GGMLType, more: &[GGMLType]) -> Option<LlamaFileType> {
+    use GGMLType as T;
+    use LlamaFileType as F;
+    let share = |t: GGMLType| more.iter().filter(|&&m| m == t).count() * 2 > more.len();
+    let any = |t: GGMLType| more.contains(&t);
+    let upgraded = more.iter().any(|&m| bits(m) > bits(base));
+    Some(match base {
+        T::F32 => F::ALL_F32,
+        T::F16 => F::MOSTLY_F16,
+        T::BF16 => F::MOSTLY_BF16,
+        T::Q4_0 => F::MOSTLY_Q4_0,
+        T::Q4_1 => F::MOSTLY_Q4_1,
+        T::Q5_0 => F::MOSTLY_Q5_0,
+        T::Q5_1 => F::MOSTLY_Q5_1,
+        T::Q8_0 => F::MOSTLY_Q8_0,
+        T::Q2K if upgraded => F::MOSTLY_Q2_K,
+        T::Q2K => F::MOSTLY_Q2_K_S,
+        T::Q3K if share(T::Q5K) => F::MOSTLY_Q3_K_L,
+        T::Q3K if upgraded => F::MOSTLY_Q3_K_M,
+        T::Q3K => F::MOSTLY_Q3_K_S,
+        T::Q4K if any(T::Q6K) => F::MOSTLY_Q4_K_M,
+        T::Q4K => F::MOSTLY_Q4_K_S,
+        T::Q5K if any(T::Q6K) => F::MOSTLY_Q5_K_M,
+        T::Q5K => F::MOSTLY_Q5_K_S,
+        T::Q6K => F::MOSTLY_Q6_K,
+        T::IQ2XXS => F::MOSTLY_IQ2_XXS,
+        T::IQ2XS => F::MOSTLY_IQ2_XS,
+        T::IQ2S => F::MOSTLY_IQ2_S,
+        T::IQ3XXS => F::MOSTLY_IQ3_XXS,
+        T::IQ3S => F::MOSTLY_IQ3_S,
+        T::IQ1S => F::MOSTLY_IQ1_S,
+        T::IQ1M => F::MOSTLY_IQ1_M,
+        T::IQ4NL => F::MOSTLY_IQ4_NL,
+        T::IQ4XS => F::MOSTLY_IQ4_XS,
+        T::TQ1_0 => F::MOSTLY_TQ1_0,
+        T::TQ2_0 => F::MOSTLY_TQ2_0,
+        T::MXFP4 => F::MOSTLY_MXFP4_MOE,
+        _ => return None,
+    })
+}
+
+/// The matrices a mix is named after: not the embeddings and output, which mixes treat apart
+fn is_weight(t: &GGUFTensorInfo) -> bool {
+    t.dimensions.len() > 1 && !["token_embd.weight", "output.weight"].contains(&t.name.as_str())
+}
+
+impl GGUFFile {
+    /// The tensor count and bytes of each type, and the llama.cpp file type they make next to
+    /// the one `general.file_type` declares
+    ///
+    /// The file type is a best guess: the type holding the most bytes of matrices, and for the
+    /// K mixes the types of the `attn_v` and `ffn_down` tensors, which the `_M` and `_L` mixes
+    /// give more bits. The IQ mixes are named after their type alone.
+    pub fn quant_report(&self) -> QuantReport {
+        let mut types: Vec<TypeUsage> = Vec::new();
+        let mut weights: Vec<(GGMLType, u64)> = Vec::new();
+        for tensor in &self.tensors {
+            let bytes = tensor.size_bytes().unwrap_or_default();
+            match types
+                .iter_mut()
+                .find(|u| u.tensor_type == tensor.tensor_type)
+            {
+                Some(usage) => {
+                    usage.tensors += 1;
+                    usage.bytes += bytes;
+                }
+                None => types.push(TypeUsage {
+                    tensor_type: tensor.tensor_type,
+                    tensors: 1,
+                    bytes,
+                }),
+            }
+            if is_weight(tensor) {
+                match weights.iter_mut().find(|(t, _)| *t == tensor.tensor_type) {
+                    Some((_, b)) => *b += bytes,
+                    None => weights.push((tensor.tensor_type, bytes)),
+                }
+            }
+        }
+        types.sort_by_key(|u| std::cmp::Reverse(u.bytes));
+        let base = weights.iter().max_by_key(|(_, b)| *b).map(|(t, _)| *t);
+        let more: Vec<GGMLType> = self
+            .tensors
+            .iter()
+            .filter(|t| t.name.ends_with(".attn_v.weight") || t.name.ends_with(".ffn_down.weight"))
+            .map(|t| t.tensor_type)
+            .collect();
+        QuantReport {
+            types,
+            inferred: base.and_then(|base| file_type(base, &more)),
+            declared: self.header.file_type(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quant::mix_tensor_type;
+    use crate::test_util::{file, header, tensor};
+    use crate::GGUFMetadataValue;
+
+    #[test]
+    fn names_the_mix_of_the_tensors() {
+        let names = ["attn_q", "attn_k", "attn_v", "ffn_up", "ffn_down"];
+        let tensors: Vec<GGUFTensorInfo> = (0..16)
+            .flat_map(|layer| {
+                names.map(|name| {
+                    tensor(
+                        &format!("blk.{layer}.{name}.weight"),
+                        &[256, 256],
+                        GGMLType::F32,
+                        0,
+                    )
+                })
+            })
+            .collect();
+        let mix = |file_type: LlamaFileType| {
+            let tensors = tensors
+                .iter()
+                .map(|t| GGUFTensorInfo {
+                    tensor_type: mix_tensor_type(file_type, t, 16).unwrap(),
+                    ..t.clone()
+                })
+                .collect();
+            file(
+                header(&[(
+                    "general.file_type",
+                    GGUFMetadataValue::Uint32(file_type.id()),
+                )]),
+                tensors,
+            )
+        };
+        for file_type in [
+            LlamaFileType::MOSTLY_Q4_K_M,
+            LlamaFileType::MOSTLY_Q4_K_S,
+            LlamaFileType::MOSTLY_Q5_K_M,
+            LlamaFileType::MOSTLY_Q8_0,
+        ] {
+            let report = mix(file_type).quant_report();
+            assert_eq!(report.inferred, Some(file_type));
+            assert!(!report.mismatch(), "{file_type:?}");
+        }
+
+        let mut relabelled = mix(LlamaFileType::MOSTLY_Q4_K_S);
+        relabelled
+            .header
+            .set("general.file_type", GGUFMetadataValue::Uint32(15));
+        let report = relabelled.quant_report();
+        assert!(report.mismatch());
+        assert_eq!(report.types[0].tensor_type, GGMLType::Q4K);
+        assert_eq!(report.types.iter().map(|u| u.tensors).sum::<usize>(), 80);
+    }
+}
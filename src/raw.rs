@@ -0,0 +1,324 @@
+//! # Arrays left undecoded
+//!
+//! With [`ParseOptions::lazy_arrays`] set, the parser keeps arrays of numbers and strings of at
+//! least that many elements as the bytes they are stored as, a [`RawArray`], rather than
+//! decoding them: a program that never looks at the tokenizer pays for a copy of its bytes, or
+//! nothing when the file is mapped, instead of a string for each token. Looking an element up
+//! decodes only that element, a number in constant time and a string by walking the lengths of
+//! the strings before it, and iterating decodes each element once. [`ArrayValues::decode`]
+//! turns the array into a packed one for the accessors that hand out slices.
+//!
+//! The strings of a raw array are checked for UTF-8 only when read, so even a strict parse
+//! lets invalid ones through: [`RawArray::get_str`] gives `None` for them and
+//! [`RawArray::decode`] fails.
+use alloc::sync::Arc;
+use core::fmt;
+use core::ops::Range;
+
+use crate::mmap::Mmap;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::{ArrayValues, GGUFMetadataValue, GGUfMetadataValueType, ParseOptions, StringArray};
+
+#[derive(Clone)]
+enum Bytes {
+    /// A copy of the elements.
+    Owned(Arc<[u8]>),
+    /// The mapping the elements lie in, at the byte range of the array.
+    Mapped(Arc<Mmap>),
+}
+
+/// The elements of an array as they are stored in the file, decoded when read
+#[derive(Clone)]
+pub struct RawArray {
+    elem_type: GGUfMetadataValueType,
+    len: u64,
+    byte_range: Range<u64>,
+    bytes: Bytes,
+}
+
+/// The size of an element of `elem_type` if arrays of it are kept raw, 0 for strings
+fn element_size(elem_type: GGUfMetadataValueType) -> Option<usize> {
+    use GGUfMetadataValueType as T;
+    match elem_type {
+        T::Uint8 | T::Int8 => Some(1),
+        T::Uint16 | T::Int16 => Some(2),
+        T::Uint32 | T::Int32 | T::Float32 => Some(4),
+        T::Uint64 | T::Int64 | T::Float64 => Some(8),
+        T::String => Some(0),
+        T::Bool | T::Array => None,
+    }
+}
+
+/// Whether `options` have the parser keep an array of `len` elements of `elem_type` raw
+pub(crate) fn keeps_raw(
+    options: &ParseOptions,
+    elem_type: GGUfMetadataValueType,
+    len: u64,
+) -> bool {
+    options.lazy_arrays.is_some_and(|min| len >= min) && element_size(elem_type).is_some()
+}
+
+/// The bytes taken by `len` elements of `elem_type` at the start of `input`, `None` if they
+/// run past its end
+pub(crate) fn raw_extent(
+    elem_type: GGUfMetadataValueType,
+    len: u64,
+    input: &[u8],
+) -> Option<usize> {
+    match element_size(elem_type)? {
+        0 => {
+            // each string takes at least its 8 length bytes, so this ends with the input
+            let mut pos = 0;
+            for _ in 0..len {
+                pos = next_string(input, pos)?.1;
+            }
+            Some(pos)
+        }
+        size => usize::try_from(len)
+            .ok()?
+            .checked_mul(size)
+            .filter(|&n| n <= input.len()),
+    }
+}
+
+/// The bytes of the string at `pos` of `bytes` and the position after it
+fn next_string(bytes: &[u8], pos: usize) -> Option<(&[u8], usize)> {
+    let start = pos.checked_add(8)?;
+    let len = u64::from_le_bytes(bytes.get(pos..start)?.try_into().ok()?);
+    let end = start.checked_add(usize::try_from(len).ok()?)?;
+    Some((bytes.get(start..end)?, end))
+}
+
+/// The number an element of `elem_type` stored as `b` is
+fn number(elem_type: GGUfMetadataValueType, b: &[u8]) -> Option<GGUFMetadataValue> {
+    use GGUFMetadataValue as V;
+    use GGUfMetadataValueType as T;
+    Some(match elem_type {
+        T::Uint8 => V::Uint8(*b.first()?),
+        T::Int8 => V::Int8(*b.first()? as i8),
+        T::Uint16 => V::Uint16(u16::from_le_bytes(b.try_into().ok()?)),
+        T::Int16 => V::Int16(i16::from_le_bytes(b.try_into().ok()?)),
+        T::Uint32 => V::Uint32(u32::from_le_bytes(b.try_into().ok()?)),
+        T::Int32 => V::Int32(i32::from_le_bytes(b.try_into().ok()?)),
+        T::Float32 => V::Float32(f32::from_le_bytes(b.try_into().ok()?)),
+        T::Uint64 => V::Uint64(u64::from_le_bytes(b.try_into().ok()?)),
+        T::Int64 => V::Int64(i64::from_le_bytes(b.try_into().ok()?)),
+        T::Float64 => V::Float64(f64::from_le_bytes(b.try_into().ok()?)),
+        T::Bool | T::String | T::Array => return None,
+    })
+}
+
+impl RawArray {
+    /// The array of `len` elements stored as `bytes`, found at `offset` of the input
+    pub(crate) fn copied(
+        elem_type: GGUfMetadataValueType,
+        len: u64,
+        offset: usize,
+        bytes: &[u8],
+    ) -> Self {
+        RawArray {
+            elem_type,
+            len,
+            byte_range: offset as u64..(offset + bytes.len()) as u64,
+            bytes: Bytes::Owned(bytes.into()),
+        }
+    }
+
+    /// The array of `len` elements stored at `range` of `map`
+    pub(crate) fn mapped(
+        elem_type: GGUfMetadataValueType,
+        len: u64,
+        map: &Arc<Mmap>,
+        range: Range<usize>,
+    ) -> Self {
+        RawArray {
+            elem_type,
+            len,
+            byte_range: range.start as u64..range.end as u64,
+            bytes: Bytes::Mapped(map.clone()),
+        }
+    }
+
+    pub fn elem_type(&self) -> GGUfMetadataValueType {
+        self.elem_type
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Where the elements lie in the input parsed, which is the file unless only a part of it
+    /// was
+    pub fn byte_range(&self) -> Range<u64> {
+        self.byte_range.clone()
+    }
+
+    /// The elements as they are stored, strings each after their length
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.bytes {
+            Bytes::Owned(bytes) => bytes,
+            Bytes::Mapped(map) => {
+                let range = self.byte_range.start as usize..self.byte_range.end as usize;
+                map.as_slice().get(range).unwrap_or_default()
+            }
+        }
+    }
+
+    /// The bytes of each string, none if the elements are not strings
+    pub fn string_bytes(&self) -> impl Iterator<Item = &[u8]> {
+        let bytes = self.as_bytes();
+        let mut pos = 0;
+        let len = match self.elem_type {
+            GGUfMetadataValueType::String => self.len,
+            _ => 0,
+        };
+        core::iter::from_fn(move || {
+            let (s, next) = next_string(bytes, pos)?;
+            pos = next;
+            Some(s)
+        })
+        .take(len as usize)
+    }
+
+    /// The element at `index`, a string in a walk over the strings before it, with invalid
+    /// UTF-8 replaced
+    pub fn get(&self, index: usize) -> Option<GGUFMetadataValue> {
+        match element_size(self.elem_type)? {
+            0 => {
+                let s = self.string_bytes().nth(index)?;
+                Some(GGUFMetadataValue::String(
+                    String::from_utf8_lossy(s).into_owned(),
+                ))
+            }
+            size => {
+                let start = index.checked_mul(size)?;
+                number(self.elem_type, self.as_bytes().get(start..start + size)?)
+            }
+        }
+    }
+
+    /// The string at `index`, found in a walk over the strings before it, `None` if it is not
+    /// UTF-8
+    pub fn get_str(&self, index: usize) -> Option<&str> {
+        core::str::from_utf8(self.string_bytes().nth(index)?).ok()
+    }
+
+    /// The strings, those that are not UTF-8 skipped
+    pub fn strs(&self) -> impl Iterator<Item = &str> {
+        self.string_bytes()
+            .filter_map(|s| core::str::from_utf8(s).ok())
+    }
+
+    /// The elements, each decoded as it is reached
+    pub fn iter(&self) -> impl Iterator<Item = GGUFMetadataValue> + '_ {
+        let numbers = match element_size(self.elem_type) {
+            Some(size) if size > 0 => Some(self.as_bytes().chunks_exact(size)),
+            _ => None,
+        };
+        let strings = self
+            .string_bytes()
+            .map(|s| GGUFMetadataValue::String(String::from_utf8_lossy(s).into_owned()));
+        numbers
+            .into_iter()
+            .flatten()
+            .filter_map(|b| number(self.elem_type, b))
+            .chain(strings)
+    }
+
+    /// The elements decoded into a packed array, failing at a string that is not UTF-8
+    pub fn decode(&self) -> Result<ArrayValues, String> {
+        if self.elem_type != GGUfMetadataValueType::String {
+            let mut values = ArrayValues::with_capacity(self.elem_type, self.len as usize);
+            self.iter().for_each(|v| values.push(v));
+            return Ok(values);
+        }
+        let mut strings = StringArray::with_capacity(self.len as usize);
+        for (i, s) in self.string_bytes().enumerate() {
+            let s = core::str::from_utf8(s).map_err(|e| format!("string {i}: {e}"))?;
+            if !strings.has_room(s) {
+                return Err("a string array of more than 4 GiB".to_string());
+            }
+            strings.push(s);
+        }
+        Ok(ArrayValues::String(strings))
+    }
+}
+
+impl fmt::Debug for RawArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RawArray({} {:?} at {:?})",
+            self.len, self.elem_type, self.byte_range
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header};
+    use crate::writer::header_bytes;
+    use crate::{GGUFFile, GGUFMetadataArrayValue};
+
+    #[test]
+    fn decodes_elements_when_read() {
+        let tokens: Vec<String> = (0..300).map(|i| format!("tok{i}")).collect();
+        let array = |values: ArrayValues| {
+            GGUFMetadataValue::Array(GGUFMetadataArrayValue::packed(
+                GGUfMetadataValueType::String,
+                values,
+            ))
+        };
+        let scores = GGUFMetadataArrayValue::packed(
+            GGUfMetadataValueType::Float32,
+            ArrayValues::Float32((0..300).map(|i| i as f32 / 2.0).collect()),
+        );
+        let file = file(
+            header(&[
+                ("tokenizer.ggml.tokens", array(tokens.clone().into())),
+                ("tokenizer.ggml.scores", GGUFMetadataValue::Array(scores)),
+                ("small", array(vec!["a".to_string()].into())),
+            ]),
+            Vec::new(),
+        );
+        let bytes = header_bytes(&file);
+        let options = ParseOptions {
+            lazy_arrays: Some(100),
+            ..ParseOptions::default()
+        };
+        let parsed = GGUFFile::read_with(&bytes, &options).unwrap().unwrap();
+        assert_eq!(parsed, file);
+        assert_eq!(header_bytes(&parsed), bytes);
+
+        let value = |key| &parsed.header.get(key).unwrap().as_array().unwrap().value;
+        let ArrayValues::Raw(raw) = value("tokenizer.ggml.tokens") else {
+            panic!("the tokens are decoded");
+        };
+        assert_eq!(raw.len(), 300);
+        let range = raw.byte_range();
+        assert_eq!(
+            &bytes[range.start as usize..range.end as usize],
+            raw.as_bytes()
+        );
+        assert_eq!(raw.get_str(123), Some("tok123"));
+        assert!(raw.strs().eq(tokens.iter().map(String::as_str)));
+        assert_eq!(
+            value("tokenizer.ggml.scores").get(3),
+            Some(GGUFMetadataValue::Float32(1.5))
+        );
+        assert!(matches!(value("small"), ArrayValues::String(_)));
+
+        let mut decoded = value("tokenizer.ggml.tokens").clone();
+        decoded.decode().unwrap();
+        assert_eq!(
+            decoded.as_strings().and_then(|s| s.get(299)),
+            Some("tok299")
+        );
+    }
+}
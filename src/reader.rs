@@ -0,0 +1,213 @@
+//! # Reading a file in stages
+//!
+//! [`GGUFReader`] carries in its type what it has checked of the file. Parsing the header gives
+//! a `GGUFReader<HeaderParsed>`, which answers for the metadata; [`GGUFReader::index_tensors`]
+//! checks the tensor infos once, that each name is unique and the data of each tensor whole
+//! and inside the file, and gives a `GGUFReader<TensorsIndexed>`, the only state that reads
+//! tensor data. Past that point a tensor is found by name in constant time and read with one
+//! seek, nothing of the header scanned or checked again, which suits a service keeping files
+//! open for many requests.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Take};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::{GGUFFile, GGUFHeader, GGUFTensorInfo, ParseError, ParseOptions};
+
+/// The state of a reader whose header and tensor infos are parsed, but not yet checked
+#[derive(Debug)]
+pub struct HeaderParsed(());
+
+/// The state of a reader whose tensors are checked and indexed by name
+#[derive(Debug)]
+pub struct TensorsIndexed {
+    by_name: HashMap<String, usize>,
+    /// The bytes of the file each tensor takes, in tensor info order.
+    ranges: Vec<Range<u64>>,
+}
+
+/// A GGUF file read from `R`, in the state `S`
+#[derive(Debug)]
+pub struct GGUFReader<S, R = File> {
+    input: R,
+    file: GGUFFile,
+    data_start: u64,
+    state: S,
+}
+
+impl GGUFReader<HeaderParsed> {
+    /// Open the file at `path` and parse its header
+    pub fn open(path: impl AsRef<Path>, options: &ParseOptions) -> Result<Self, String> {
+        let input = File::open(path).map_err(|e| e.to_string())?;
+        Self::new(input, options).map_err(|e| e.to_string())
+    }
+}
+
+impl<R: Read + Seek> GGUFReader<HeaderParsed, R> {
+    /// Parse the header at the start of `input`
+    pub fn new(mut input: R, options: &ParseOptions) -> Result<Self, ParseError> {
+        let (file, data_start) = GGUFFile::read_from(&mut input, options)?;
+        Ok(GGUFReader {
+            input,
+            file,
+            data_start,
+            state: HeaderParsed(()),
+        })
+    }
+
+    /// Check that every tensor has a name of its own and data that is a whole number of blocks
+    /// lying inside the file and apart from the data of the others, and index them by name
+    pub fn index_tensors(mut self) -> Result<GGUFReader<TensorsIndexed, R>, String> {
+        let len = self
+            .input
+            .seek(SeekFrom::End(0))
+            .map_err(|e| e.to_string())?;
+        let mut by_name = HashMap::with_capacity(self.file.tensors.len());
+        let mut ranges = Vec::with_capacity(self.file.tensors.len());
+        for (i, tensor) in self.file.tensors.iter().enumerate() {
+            if by_name.insert(tensor.name.clone(), i).is_some() {
+                return Err(format!("more than one tensor is named {}", tensor.name));
+            }
+            let size = tensor
+                .size_bytes()
+                .ok_or_else(|| format!("tensor {} has no whole number of blocks", tensor.name))?;
+            let range = self
+                .data_start
+                .checked_add(tensor.offset)
+                .and_then(|start| Some(start..start.checked_add(size)?))
+                .filter(|range| range.end <= len)
+                .ok_or_else(|| {
+                    format!("the file ends inside the data of tensor {}", tensor.name)
+                })?;
+            ranges.push(range);
+        }
+        let mut order: Vec<usize> = (0..ranges.len()).collect();
+        order.sort_by_key(|&i| ranges[i].start);
+        if let Some(w) = order
+            .windows(2)
+            .find(|w| ranges[w[0]].end > ranges[w[1]].start)
+        {
+            let name = |i: usize| &self.file.tensors[i].name;
+            return Err(format!(
+                "the data of tensors {} and {} overlap",
+                name(w[0]),
+                name(w[1])
+            ));
+        }
+        Ok(GGUFReader {
+            input: self.input,
+            file: self.file,
+            data_start: self.data_start,
+            state: TensorsIndexed { by_name, ranges },
+        })
+    }
+}
+
+impl<S, R> GGUFReader<S, R> {
+    pub fn file(&self) -> &GGUFFile {
+        &self.file
+    }
+
+    pub fn header(&self) -> &GGUFHeader {
+        &self.file.header
+    }
+
+    /// The offset of the tensor data
+    pub fn data_start(&self) -> u64 {
+        self.data_start
+    }
+
+    /// The input and the parsed file
+    pub fn into_parts(self) -> (R, GGUFFile) {
+        (self.input, self.file)
+    }
+}
+
+impl<R: Read + Seek> GGUFReader<TensorsIndexed, R> {
+    pub fn tensor(&self, name: &str) -> Option<&GGUFTensorInfo> {
+        Some(&self.file.tensors[*self.state.by_name.get(name)?])
+    }
+
+    /// The bytes of the file the data of the tensor `name` takes
+    pub fn tensor_range(&self, name: &str) -> Option<Range<u64>> {
+        Some(self.state.ranges[*self.state.by_name.get(name)?].clone())
+    }
+
+    /// A reader over exactly the data of the tensor `name`
+    pub fn tensor_reader(&mut self, name: &str) -> Result<Take<&mut R>, String> {
+        let range = self
+            .tensor_range(name)
+            .ok_or_else(|| format!("no tensor named {name}"))?;
+        self.input
+            .seek(SeekFrom::Start(range.start))
+            .map_err(|e| e.to_string())?;
+        Ok((&mut self.input).take(range.end - range.start))
+    }
+
+    /// The data of the tensor `name`
+    pub fn read_tensor(&mut self, name: &str) -> Result<Vec<u8>, String> {
+        span!("tensor", "{name}");
+        let mut reader = self.tensor_reader(name)?;
+        let mut data = Vec::with_capacity(reader.limit() as usize);
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| format!("reading tensor {name}: {e}"))?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, temp_path, tensor};
+    use crate::writer::write_file;
+    use crate::GGMLType;
+    use std::io::Cursor;
+
+    #[test]
+    fn reads_tensors_once_indexed() {
+        let mut file = file(
+            header(&[]),
+            vec![
+                tensor("a", &[4], GGMLType::F32, 0),
+                tensor("b", &[4], GGMLType::F32, 0),
+            ],
+        );
+        let path = temp_path("gguf-reader.gguf");
+        let mut fill = 0;
+        write_file(&path, &file, |_, out| {
+            fill += 1;
+            Ok(out.write_all(&[fill; 16])?)
+        })
+        .unwrap();
+
+        let reader = GGUFReader::open(&path, &ParseOptions::default()).unwrap();
+        assert_eq!(reader.file().tensors[1].offset, 32);
+        let mut indexed = reader.index_tensors().unwrap();
+        assert_eq!(indexed.read_tensor("b").unwrap(), [2; 16]);
+        assert_eq!(indexed.read_tensor("a").unwrap(), [1; 16]);
+        let start = indexed.data_start();
+        assert_eq!(indexed.tensor_range("b"), Some(start + 32..start + 48));
+        assert!(indexed.read_tensor("c").is_err());
+
+        let bytes = std::fs::read(&path).unwrap();
+        let truncated = Cursor::new(&bytes[..start as usize + 47]);
+        let error = GGUFReader::new(truncated, &ParseOptions::default())
+            .unwrap()
+            .index_tensors()
+            .unwrap_err();
+        assert!(error.contains("tensor b"));
+
+        file.tensors[1].name = "a".to_string();
+        let mut header = Vec::new();
+        crate::writer::write_header(&mut header, &file).unwrap();
+        header.resize(header.len() + 64, 0);
+        let error = GGUFReader::new(Cursor::new(header), &ParseOptions::default())
+            .unwrap()
+            .index_tensors()
+            .unwrap_err();
+        assert!(error.contains("more than one"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+//! # Recurrent models
+//!
+//! Mamba and RWKV carry a fixed-size state from token to token instead of attending to the
+//! keys and values of all tokens before, so their memory does not grow with the context.
+//! [`MambaConfig`] and [`RwkvConfig`] read the hyperparameters that size the state, from the
+//! `{arch}.ssm.*` and `{arch}.wkv.*` keys of any architecture with such layers.
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::GGUFHeader;
+
+/// A hyperparameter of the architecture of `header`, `suffix` following its name
+fn number(header: &GGUFHeader, suffix: &str) -> Option<u64> {
+    let arch = header.get(crate::keys::general::ARCHITECTURE)?.as_str()?;
+    header.get(&format!("{arch}{suffix}"))?.as_u64()
+}
+
+/// The state space layers of Mamba and Mamba 2, in the names of the paper where it has them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct MambaConfig {
+    /// `d_conv`, the width of the convolution over the last tokens.
+    pub conv_kernel: u64,
+    /// `d_state`, the width of the state of each channel.
+    pub state_size: u64,
+    /// `d_inner`, the number of channels.
+    pub inner_size: u64,
+    /// `dt_rank`.
+    pub time_step_rank: Option<u64>,
+    /// The groups sharing B and C, 0 for Mamba 1.
+    pub group_count: u64,
+}
+
+impl MambaConfig {
+    /// The `{arch}.ssm.*` keys of `header`, `None` if it has no state space layers
+    pub fn of(header: &GGUFHeader) -> Option<Self> {
+        Some(MambaConfig {
+            conv_kernel: number(header, ".ssm.conv_kernel")?,
+            state_size: number(header, ".ssm.state_size")?,
+            inner_size: number(header, ".ssm.inner_size")?,
+            time_step_rank: number(header, ".ssm.time_step_rank"),
+            group_count: number(header, ".ssm.group_count").unwrap_or(0),
+        })
+    }
+
+    /// The elements of the state of one layer for one sequence: the last `d_conv - 1` inputs
+    /// of the convolution and the `d_state` wide state of each channel
+    pub fn state_elements(&self) -> u64 {
+        let conv_channels = self.inner_size + 2 * self.group_count * self.state_size;
+        self.conv_kernel.saturating_sub(1) * conv_channels + self.state_size * self.inner_size
+    }
+}
+
+/// The time mix layers of RWKV 6 and 7
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct RwkvConfig {
+    /// The width of each head of the WKV state.
+    pub head_size: u64,
+    pub time_mix_extra_dim: Option<u64>,
+    pub time_decay_extra_dim: Option<u64>,
+    /// The layers after which activations are halved, 0 for none.
+    pub rescale_every_n_layers: u64,
+    /// The embeddings of past tokens each layer keeps for its token shifts.
+    pub token_shift_count: u64,
+}
+
+impl RwkvConfig {
+    /// The `{arch}.wkv.*` and related keys of `header`, `None` if it has no WKV layers
+    pub fn of(header: &GGUFHeader) -> Option<Self> {
+        Some(RwkvConfig {
+            head_size: number(header, ".wkv.head_size")?,
+            time_mix_extra_dim: number(header, ".time_mix_extra_dim"),
+            time_decay_extra_dim: number(header, ".time_decay_extra_dim"),
+            rescale_every_n_layers: number(header, ".rescale_every_n_layers").unwrap_or(0),
+            token_shift_count: number(header, ".token_shift_count").unwrap_or(2),
+        })
+    }
+
+    /// The elements of the state of one layer with embeddings `embedding` wide for one
+    /// sequence: the token shifts, and a `head_size` square matrix for each head
+    pub fn state_elements(&self, embedding: u64) -> u64 {
+        self.token_shift_count * embedding + embedding * self.head_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use crate::GGUFMetadataValue;
+
+    fn header(arch: &str, entries: &[(&str, u32)]) -> GGUFHeader {
+        let mut header = test_util::header(&[(
+            "general.architecture",
+            GGUFMetadataValue::String(arch.into()),
+        )]);
+        for &(key, value) in entries {
+            header.set(&format!("{arch}.{key}"), GGUFMetadataValue::Uint32(value));
+        }
+        header
+    }
+
+    #[test]
+    fn sizes_the_state_of_a_layer() {
+        // mamba-130m
+        let mamba = header(
+            "mamba",
+            &[
+                ("ssm.conv_kernel", 4),
+                ("ssm.state_size", 16),
+                ("ssm.inner_size", 1536),
+                ("ssm.time_step_rank", 48),
+            ],
+        );
+        let config = MambaConfig::of(&mamba).unwrap();
+        assert_eq!(config.time_step_rank, Some(48));
+        assert_eq!(config.state_elements(), 3 * 1536 + 16 * 1536);
+        assert_eq!(RwkvConfig::of(&mamba), None);
+
+        let rwkv = header("rwkv6", &[("wkv.head_size", 64)]);
+        let config = RwkvConfig::of(&rwkv).unwrap();
+        assert_eq!(config.token_shift_count, 2);
+        assert_eq!(config.state_elements(2048), 2 * 2048 + 2048 * 64);
+        assert_eq!(MambaConfig::of(&rwkv), None);
+    }
+}
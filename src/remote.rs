@@ -0,0 +1,498 @@
+//! # Remote files
+//!
+//! Reads a file over HTTP with range requests, fetching only the bytes that are read, so the
+//! header of a model can be parsed without downloading its tensor data. `http://` URLs are
+//! fetched directly; `https://` ones through the `curl` command, as the crate has no TLS of its
+//! own.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+/// The first request reads this much; each one continuing the last reads twice as much
+const MIN_CHUNK: u64 = 1 << 18;
+const MAX_CHUNK: u64 = 1 << 24;
+const MAX_REDIRECTS: usize = 10;
+/// How long connecting, or a read or write of a request, may stall before it fails
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// The longest a request through `curl` may take, but for an upload
+const MAX_TIME: Duration = Duration::from_secs(3600);
+
+/// Whether `path` is a URL [`RemoteFile`] can read
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// A file on an HTTP server, read and seeked like a local one
+#[derive(Debug)]
+pub struct RemoteFile {
+    url: String,
+    len: Option<u64>,
+    pos: u64,
+    /// the bytes of the last request and where they start
+    buf: Vec<u8>,
+    start: u64,
+    chunk: u64,
+    requests: usize,
+    fetched: u64,
+}
+
+/// the status line and headers of a response
+pub(crate) struct Head {
+    pub(crate) status: u16,
+    headers: Vec<(String, String)>,
+}
+
+impl Head {
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+fn read_head(reader: &mut impl BufRead) -> io::Result<Head> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the connection closed before a response",
+        ));
+    }
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| invalid(format!("malformed status line {:?}", line.trim_end())))?;
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    Ok(Head { status, headers })
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// the total length in a `Content-Range` of `bytes 0-99/1000` or `bytes */1000`
+fn total_len(content_range: &str) -> Option<u64> {
+    content_range.rsplit_once('/')?.1.parse().ok()
+}
+
+/// the scheme and authority of a URL, e.g. `http://localhost:8080`
+pub(crate) fn origin(url: &str) -> &str {
+    let after_scheme = url.find("://").map_or(0, |i| i + 3);
+    match url[after_scheme..].find('/') {
+        Some(i) => &url[..after_scheme + i],
+        None => url,
+    }
+}
+
+/// a response body, and the process producing it when there is one
+pub(crate) struct Body {
+    pub(crate) reader: Box<dyn BufRead>,
+    child: Option<Child>,
+}
+
+impl Drop for Body {
+    fn drop(&mut self) {
+        // a server ignoring the range keeps sending the whole file
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// the body a request sends; only [`crate::oci`] sends one
+#[derive(Clone, Copy)]
+#[cfg_attr(not(feature = "json"), allow(dead_code))]
+pub(crate) enum Upload<'a> {
+    None,
+    Bytes(&'a [u8]),
+    File(&'a Path),
+}
+
+/// connect to the first address of `address` that answers within [`TIMEOUT`]
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let mut last = None;
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last = Some(e),
+        }
+    }
+    Err(last.unwrap_or_else(|| invalid(format!("{address} resolves to no address"))))
+}
+
+/// `path` with its bytes outside ASCII percent-encoded, as a request line takes it
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii() {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// refuse a URL or header that would break out of its line of a request: one with control
+/// characters, or a URL with whitespace
+fn check_request(url: &str, headers: &[(&str, &str)]) -> io::Result<()> {
+    if !is_url(url) {
+        return Err(invalid(format!("{url:?} is not an http or https URL")));
+    }
+    if url.chars().any(|c| c.is_control() || c.is_whitespace()) {
+        return Err(invalid(format!(
+            "{url:?} has whitespace or control characters"
+        )));
+    }
+    for (name, value) in headers {
+        if name.chars().chain(value.chars()).any(|c| c.is_control()) {
+            return Err(invalid(format!(
+                "the header {name:?} has control characters"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// send a plain HTTP/1.0 request, which keeps the body free of chunked encoding
+fn request_http(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    upload: Upload,
+) -> io::Result<(Head, Body)> {
+    let rest = &url["http://".len()..];
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let host = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{host}:80")
+    };
+    let stream = connect(&address)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let path = if path.is_empty() {
+        "/".to_string()
+    } else {
+        encode_path(path)
+    };
+    let mut out = io::BufWriter::new(&stream);
+    write!(
+        out,
+        "{method} {path} HTTP/1.0\r\nHost: {host}\r\nUser-Agent: gguf\r\n"
+    )?;
+    for (name, value) in headers {
+        write!(out, "{name}: {value}\r\n")?;
+    }
+    match upload {
+        Upload::None => write!(out, "\r\n")?,
+        Upload::Bytes(data) => {
+            write!(out, "Content-Length: {}\r\n\r\n", data.len())?;
+            out.write_all(data)?;
+        }
+        Upload::File(path) => {
+            let mut file = File::open(path)?;
+            write!(out, "Content-Length: {}\r\n\r\n", file.metadata()?.len())?;
+            io::copy(&mut file, &mut out)?;
+        }
+    }
+    out.flush()?;
+    drop(out);
+    let mut reader = BufReader::new(stream);
+    let head = read_head(&mut reader)?;
+    let body = Body {
+        reader: Box::new(reader),
+        child: None,
+    };
+    Ok((head, body))
+}
+
+/// run `curl`, which follows the redirects of GET requests itself and prints the head of each
+/// response
+fn request_curl(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    upload: Upload,
+) -> io::Result<(Head, Body)> {
+    let mut command = Command::new("curl");
+    command.args(["-sS", "-A", "gguf", "--globoff"]);
+    // Only web URLs, as given and as redirected to: curl also speaks file://, ftp:// and more
+    command.args(["--proto", "=http,https", "--proto-redir", "=http,https"]);
+    command.arg("--max-redirs").arg(MAX_REDIRECTS.to_string());
+    command
+        .arg("--connect-timeout")
+        .arg(TIMEOUT.as_secs().to_string());
+    // A transfer making no progress for as long fails, and so does one taking over an hour
+    // but for an upload, whose size is the user's own
+    command
+        .args(["--speed-limit", "1", "--speed-time"])
+        .arg(TIMEOUT.as_secs().to_string());
+    if !matches!(upload, Upload::File(_)) {
+        command
+            .arg("--max-time")
+            .arg(MAX_TIME.as_secs().to_string());
+    }
+    match method {
+        "GET" => command.args(["-L", "-D", "-"]),
+        "HEAD" => command.arg("-I"),
+        method => command.args(["-D", "-", "-X", method]),
+    };
+    for (name, value) in headers {
+        command.arg("-H").arg(format!("{name}: {value}"));
+    }
+    match upload {
+        Upload::None => &mut command,
+        Upload::Bytes(_) => command.args(["--data-binary", "@-"]),
+        Upload::File(path) => command.arg("-T").arg(path),
+    };
+    let mut child = command
+        .arg(url)
+        .stdin(match upload {
+            Upload::Bytes(_) => Stdio::piped(),
+            _ => Stdio::null(),
+        })
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("https URLs need curl: {e}")))?;
+    if let (Upload::Bytes(data), Some(mut stdin)) = (upload, child.stdin.take()) {
+        stdin.write_all(data)?;
+    }
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut body = Body {
+        reader: Box::new(BufReader::new(stdout)),
+        child: Some(child),
+    };
+    loop {
+        let head = read_head(&mut body.reader)
+            .map_err(|e| io::Error::new(e.kind(), format!("curl could not fetch {url}: {e}")))?;
+        let redirected =
+            method == "GET" && (300..400).contains(&head.status) && head.get("location").is_some();
+        if head.status >= 200 && !redirected {
+            return Ok((head, body));
+        }
+    }
+}
+
+/// Send a request to an `http://` or `https://` URL, the latter through `curl`
+pub(crate) fn request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    upload: Upload,
+) -> io::Result<(Head, Body)> {
+    check_request(url, headers)?;
+    if url.starts_with("https://") {
+        request_curl(method, url, headers, upload)
+    } else {
+        request_http(method, url, headers, upload)
+    }
+}
+
+impl RemoteFile {
+    /// A file at `url`; nothing is fetched until it is read or its length is asked for
+    pub fn open(url: &str) -> Result<Self, String> {
+        if !is_url(url) {
+            return Err(format!("{url} is not an http or https URL"));
+        }
+        Ok(RemoteFile {
+            url: url.to_string(),
+            len: None,
+            pos: 0,
+            buf: Vec::new(),
+            start: 0,
+            chunk: MIN_CHUNK,
+            requests: 0,
+            fetched: 0,
+        })
+    }
+
+    /// The length of the file, fetching a byte to learn it if no request has yet
+    pub fn file_len(&mut self) -> io::Result<u64> {
+        if self.len.is_none() {
+            self.fetch(0, 1)?;
+        }
+        self.len
+            .ok_or_else(|| invalid(format!("{} does not give its length", self.url)))
+    }
+
+    /// How many requests have been made
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    /// How many bytes of the file have been fetched
+    pub fn fetched(&self) -> u64 {
+        self.fetched
+    }
+
+    /// fetch `len` bytes from `start`, fewer at the end of the file
+    fn fetch(&mut self, start: u64, len: u64) -> io::Result<Vec<u8>> {
+        let range = format!("bytes={start}-{}", start + len - 1);
+        let mut url = self.url.clone();
+        for _ in 0..MAX_REDIRECTS {
+            self.requests += 1;
+            let range = [("Range", range.as_str())];
+            let (head, mut body) = request("GET", &url, &range, Upload::None)?;
+            let mut data = Vec::new();
+            match head.status {
+                206 => {
+                    self.len = head.get("content-range").and_then(total_len).or(self.len);
+                    (&mut body.reader).take(len).read_to_end(&mut data)?;
+                }
+                200 => {
+                    self.len = head.get("content-length").and_then(|l| l.parse().ok());
+                    io::copy(&mut (&mut body.reader).take(start), &mut io::sink())?;
+                    (&mut body.reader).take(len).read_to_end(&mut data)?;
+                }
+                416 => {
+                    self.len = head.get("content-range").and_then(total_len).or(self.len);
+                }
+                301 | 302 | 303 | 307 | 308 => {
+                    let location = head
+                        .get("location")
+                        .ok_or_else(|| invalid(format!("redirect from {url} has no location")))?;
+                    url = match location.strip_prefix('/') {
+                        Some(path) => format!("{}/{path}", origin(&url)),
+                        None => location.to_string(),
+                    };
+                    continue;
+                }
+                status => return Err(invalid(format!("HTTP {status} from {url}"))),
+            }
+            self.fetched += data.len() as u64;
+            return Ok(data);
+        }
+        Err(invalid(format!("too many redirects from {}", self.url)))
+    }
+}
+
+impl Read for RemoteFile {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let end = self.start + self.buf.len() as u64;
+        if out.is_empty() || self.len.is_some_and(|len| self.pos >= len) {
+            return Ok(0);
+        }
+        if !(self.start..end).contains(&self.pos) {
+            self.chunk = if self.pos == end && self.requests > 0 {
+                (self.chunk * 2).min(MAX_CHUNK)
+            } else {
+                MIN_CHUNK
+            };
+            self.buf = self.fetch(self.pos, self.chunk)?;
+            self.start = self.pos;
+        }
+        let at = (self.pos - self.start) as usize;
+        let n = out.len().min(self.buf.len().saturating_sub(at));
+        out[..n].copy_from_slice(&self.buf[at..at + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for RemoteFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(by) => self.pos.checked_add_signed(by),
+            SeekFrom::End(by) => self.file_len()?.checked_add_signed(by),
+        };
+        self.pos = pos
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// serve `data` to range requests, the first one through a redirect
+    fn serve(data: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split_whitespace().nth(1).unwrap().to_string();
+                let mut range = None;
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                    if let Some(r) = line.trim_end().strip_prefix("Range: bytes=") {
+                        let (a, b) = r.split_once('-').unwrap();
+                        range = Some((a.parse::<usize>().unwrap(), b.parse::<usize>().unwrap()));
+                    }
+                }
+                if path == "/model.gguf" {
+                    let to = format!("http://{address}/files/model.gguf");
+                    write!(stream, "HTTP/1.0 302 Found\r\nLocation: {to}\r\n\r\n").unwrap();
+                    continue;
+                }
+                let (a, b) = range.unwrap();
+                let b = b.min(data.len() - 1);
+                write!(
+                    stream,
+                    "HTTP/1.0 206 Partial Content\r\nContent-Range: bytes {a}-{b}/{}\r\n\r\n",
+                    data.len()
+                )
+                .unwrap();
+                stream.write_all(&data[a..=b]).unwrap();
+            }
+        });
+        format!("http://{address}/model.gguf")
+    }
+
+    #[test]
+    fn reads_ranges() {
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+        let mut file = RemoteFile::open(&serve(data.clone())).unwrap();
+        let mut head = [0; 10];
+        file.read_exact(&mut head).unwrap();
+        assert_eq!(head[..], data[..10]);
+        assert_eq!(file.file_len().unwrap(), 1_000_000);
+        assert_eq!(file.fetched(), MIN_CHUNK);
+
+        file.seek(SeekFrom::End(-5)).unwrap();
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, data[data.len() - 5..]);
+        assert_eq!(file.requests(), 4);
+
+        for url in [
+            "http://localhost/a b",
+            "http://localhost/a\r\nHost: elsewhere",
+            "file:///etc/passwd",
+        ] {
+            let Err(e) =
+                RemoteFile::open(url).and_then(|mut f| f.file_len().map_err(|e| e.to_string()))
+            else {
+                panic!("{url:?} was requested");
+            };
+            assert!(!e.is_empty());
+        }
+        assert_eq!(encode_path("/modèle.gguf"), "/mod%C3%A8le.gguf");
+    }
+}
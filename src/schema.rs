@@ -0,0 +1,146 @@
+//! # JSON Schema
+//!
+//! The structure of the JSON this crate serializes and the `gguf` tool prints, so tools in
+//! other languages can validate it and generate types from it: a parsed [`GGUFFile`], a
+//! [`GGUFHeader`], the header as `gguf dump --json` prints it, the `gguf tensors --json` listing
+//! and a [`ValidationReport`]. Each form is under `$defs`; the document as a whole accepts any
+//! of them.
+//!
+//! [`GGUFFile`]: crate::GGUFFile
+//! [`GGUFHeader`]: crate::GGUFHeader
+//! [`ValidationReport`]: crate::validate::ValidationReport
+
+/// The JSON Schema, draft 2020-12, that the JSON forms of this version of the crate conform to
+pub const SCHEMA: &str = include_str!("schema.json");
+
+/// The JSON Schema of the crate's JSON output, the same as [`SCHEMA`]
+pub fn schema() -> &'static str {
+    SCHEMA
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "json")]
+    use serde_json::Value;
+
+    /// Whether `value` conforms to `schema`, for the keywords the schema uses
+    #[cfg(feature = "json")]
+    fn conforms(root: &Value, schema: &Value, value: &Value) -> bool {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference
+                .strip_prefix("#/$defs/")
+                .expect("a local reference");
+            assert!(
+                root["$defs"].get(name).is_some(),
+                "{reference} is undefined"
+            );
+            return conforms(root, &root["$defs"][name], value);
+        }
+        if let Some(options) = schema["anyOf"].as_array() {
+            return options.iter().any(|s| conforms(root, s, value));
+        }
+        if let Some(options) = schema["enum"].as_array() {
+            return options.contains(value);
+        }
+        if let Some(constant) = schema.get("const") {
+            return constant == value;
+        }
+        let types: Vec<&str> = match &schema["type"] {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let kind = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(n) if n.is_u64() || n.is_i64() => "integer",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        let typed = types.is_empty()
+            || types.contains(&kind)
+            || (kind == "integer" && types.contains(&"number"));
+        if !typed {
+            return false;
+        }
+        if let (Some(min), Some(n)) = (schema["minimum"].as_f64(), value.as_f64()) {
+            if n < min {
+                return false;
+            }
+        }
+        match value {
+            Value::Array(items) => {
+                schema["maxItems"]
+                    .as_u64()
+                    .is_none_or(|max| items.len() as u64 <= max)
+                    && items
+                        .iter()
+                        .all(|item| schema.get("items").is_none_or(|s| conforms(root, s, item)))
+            }
+            Value::Object(fields) => {
+                let properties = &schema["properties"];
+                let required = schema["required"].as_array().cloned().unwrap_or_default();
+                required
+                    .iter()
+                    .all(|key| fields.contains_key(key.as_str().unwrap_or_default()))
+                    && fields.iter().all(|(key, field)| match properties.get(key) {
+                        Some(s) => conforms(root, s, field),
+                        None => schema["additionalProperties"] != false,
+                    })
+            }
+            _ => true,
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn outputs_conform_to_the_schema() {
+        use crate::test_util::{file, header, tensor};
+        use crate::{GGMLType, GGUFMetadataArrayValue, GGUFMetadataValue, GGUfMetadataValueType};
+        let schema: Value = serde_json::from_str(super::schema()).unwrap();
+        let tokens = (0..5)
+            .map(|i| GGUFMetadataValue::String(i.to_string()))
+            .collect();
+        let file = file(
+            header(&[
+                ("general.alignment", GGUFMetadataValue::Uint32(32)),
+                ("a.eps", GGUFMetadataValue::Float32(1e-5)),
+                (
+                    "tokenizer.ggml.tokens",
+                    GGUFMetadataValue::Array(GGUFMetadataArrayValue::new(
+                        GGUfMetadataValueType::String,
+                        tokens,
+                    )),
+                ),
+            ]),
+            vec![tensor("output.weight", &[32, 2], GGMLType::Q8_0, 0)],
+        );
+        let check = |def: &str, value: Value| {
+            let def = serde_json::json!({ "$ref": format!("#/$defs/{def}") });
+            assert!(conforms(&schema, &def, &value), "{value}");
+            assert!(conforms(&schema, &schema, &value));
+        };
+        check("file", serde_json::to_value(&file).unwrap());
+        check("header", serde_json::to_value(&file.header).unwrap());
+        check(
+            "dump",
+            serde_json::json!({"version": 3, "tensor_count": 0, "metadata": [
+                {"key": "tokenizer.ggml.merges", "type": "Array", "value": ["a b", "c d"]},
+            ]}),
+        );
+        let report = crate::validate(&[0; 4]);
+        assert!(!report.findings.is_empty());
+        check("validation_report", serde_json::to_value(&report).unwrap());
+        check(
+            "tensor_listing",
+            serde_json::json!({"tensors": [{
+                "name": "output.weight", "shape": [32, 2], "type": "Q8_0", "bytes": null, "offset": 0,
+            }]}),
+        );
+        let mut wrong = serde_json::to_value(&file.header).unwrap();
+        wrong["version"] = Value::from("3");
+        assert!(!conforms(&schema, &schema, &wrong));
+    }
+}
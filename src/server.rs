@@ -0,0 +1,556 @@
+//! # HTTP service
+//!
+//! A small JSON service over the library for checking models without writing glue: `POST
+//! /inspect` returns the metadata and tensor infos of a file, `POST /validate` its
+//! [`ValidationReport`] and `POST /diff` what differs between two files, in the shapes the
+//! `--json` output of `gguf dump`, `verify` and `diff` takes. `GET /health` answers `ok`.
+//!
+//! A file comes as the request body, as a `multipart/form-data` part (`a` and `b` for a diff),
+//! or as a URL in a JSON body, `{"url": ...}` or `{"a": ..., "b": ...}`, read with range
+//! requests so only its header is fetched, once `ServerOptions::allow_urls` turns that on with the
+//! `remote` feature. A
+//! `gguf strip` stub stands in for a large upload. The server runs on `std::net`, a fixed pool
+//! of threads taking the connections, one request each.
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::diff::MetadataDiff;
+#[cfg(feature = "remote")]
+use crate::remote::RemoteFile;
+#[cfg(feature = "remote")]
+use crate::validate::validate_reader;
+use crate::validate::{validate, ValidationReport};
+use crate::{GGUFFile, GGUFMetadata, GGUFTensorInfo, ParseOptions};
+
+/// Limits of a [`Server`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerOptions {
+    /// The largest request body accepted, answered with 413 beyond it.
+    pub max_body: u64,
+    /// Whether files may be given by URL, which lets clients make the server fetch from
+    /// anywhere it can reach, internal hosts included; off by default.
+    #[cfg(feature = "remote")]
+    pub allow_urls: bool,
+    /// The threads answering requests; connections beyond them wait to be accepted.
+    pub workers: usize,
+    /// How long a read or write of a connection may stall before it is dropped.
+    pub timeout: Duration,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            max_body: 256 << 20,
+            #[cfg(feature = "remote")]
+            allow_urls: false,
+            workers: 8,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The longest request or header line accepted
+const MAX_LINE: u64 = 8 << 10;
+/// The most header lines accepted
+const MAX_HEADERS: usize = 100;
+
+/// A file of a request
+enum Source {
+    Bytes(Vec<u8>),
+    #[cfg(feature = "remote")]
+    Url(String),
+}
+
+/// A failed request: the status and the message of its `{"error": ...}` body
+struct Failure(u16, String);
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure(422, message)
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Read a line of at most [`MAX_LINE`] bytes into `line`, its length, 0 at the end
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> Result<usize, Failure> {
+    let read = reader
+        .take(MAX_LINE)
+        .read_line(line)
+        .map_err(|_| Failure(400, "unreadable request".to_string()))?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Err(Failure(
+            431,
+            format!("request lines are limited to {MAX_LINE} bytes"),
+        ));
+    }
+    Ok(read)
+}
+
+/// Read a request, its body by `Content-Length`, answering `Expect: 100-continue` on `interim`
+fn read_request(
+    reader: &mut impl BufRead,
+    interim: &mut impl Write,
+    max_body: u64,
+) -> Result<Request, Failure> {
+    let bad = |message: &str| Failure(400, message.to_string());
+    let mut line = String::new();
+    read_line(reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(bad("malformed request line"));
+    };
+    let (method, path) = (method.to_string(), target.split('?').next().unwrap_or("/"));
+    let path = path.to_string();
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        if read_line(reader, &mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(Failure(
+                431,
+                format!("requests are limited to {MAX_HEADERS} headers"),
+            ));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+    if headers.contains_key("transfer-encoding") {
+        return Err(Failure(
+            411,
+            "send the body with a Content-Length".to_string(),
+        ));
+    }
+    let len: u64 = match headers.get("content-length") {
+        Some(len) => len.parse().map_err(|_| bad("malformed Content-Length"))?,
+        None => 0,
+    };
+    if len > max_body {
+        return Err(Failure(
+            413,
+            format!(
+                "bodies are limited to {max_body} bytes; upload a `gguf strip` stub or give a URL"
+            ),
+        ));
+    }
+    if headers
+        .get("expect")
+        .is_some_and(|e| e.eq_ignore_ascii_case("100-continue"))
+    {
+        interim
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .map_err(|_| bad("the connection closed"))?;
+    }
+    // Grown as the bytes arrive, so a Content-Length the client never sends costs nothing
+    let mut body = Vec::new();
+    let mut chunk = [0; 64 << 10];
+    while (body.len() as u64) < len {
+        let want = chunk.len().min((len - body.len() as u64) as usize);
+        match reader.read(&mut chunk[..want]) {
+            Ok(0) | Err(_) => return Err(bad("the body ended early")),
+            Ok(read) => body.extend_from_slice(&chunk[..read]),
+        }
+    }
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+/// `needle` in `haystack` from `from` on
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    let first = *needle.first()?;
+    let mut at = from;
+    while at + needle.len() <= haystack.len() {
+        let next = at + haystack[at..].iter().position(|&b| b == first)?;
+        if haystack[next..].starts_with(needle) {
+            return Some(next);
+        }
+        at = next + 1;
+    }
+    None
+}
+
+/// The named parts of a `multipart/form-data` body
+fn multipart(body: &[u8], boundary: &str) -> Result<HashMap<String, Vec<u8>>, Failure> {
+    let bad = || Failure(400, "malformed multipart body".to_string());
+    let delimiter = format!("--{boundary}");
+    let mut parts = HashMap::new();
+    let mut at = find(body, delimiter.as_bytes(), 0).ok_or_else(bad)? + delimiter.len();
+    while !body[at..].starts_with(b"--") {
+        let head_end = find(body, b"\r\n\r\n", at).ok_or_else(bad)?;
+        let head = String::from_utf8_lossy(&body[at..head_end]);
+        let name = head
+            .split(';')
+            .find_map(|param| param.trim().strip_prefix("name="))
+            .map(|name| name.trim_matches('"').to_string())
+            .unwrap_or_default();
+        let end = find(body, format!("\r\n{delimiter}").as_bytes(), head_end).ok_or_else(bad)?;
+        parts.insert(name, body[head_end + 4..end].to_vec());
+        at = end + 2 + delimiter.len();
+    }
+    Ok(parts)
+}
+
+/// The files of a request, by name: `file` for a body that is one
+fn sources(request: Request, options: &ServerOptions) -> Result<HashMap<String, Source>, Failure> {
+    let content_type = request
+        .headers
+        .get("content-type")
+        .map(|t| t.to_ascii_lowercase())
+        .unwrap_or_default();
+    if content_type.starts_with("application/json") {
+        return urls(&request.body, options);
+    }
+    if content_type.starts_with("multipart/form-data") {
+        let boundary = request.headers["content-type"]
+            .split(';')
+            .find_map(|param| param.trim().strip_prefix("boundary="))
+            .map(|b| b.trim_matches('"').to_string())
+            .ok_or_else(|| Failure(400, "the multipart body has no boundary".to_string()))?;
+        let parts = multipart(&request.body, &boundary)?;
+        return Ok(parts
+            .into_iter()
+            .map(|(name, data)| (name, Source::Bytes(data)))
+            .collect());
+    }
+    Ok(HashMap::from([(
+        "file".to_string(),
+        Source::Bytes(request.body),
+    )]))
+}
+
+/// The files of a JSON body of URLs
+#[cfg(feature = "remote")]
+fn urls(body: &[u8], options: &ServerOptions) -> Result<HashMap<String, Source>, Failure> {
+    if !options.allow_urls {
+        return Err(Failure(403, "this server reads uploads only".to_string()));
+    }
+    let doc: Value =
+        serde_json::from_slice(body).map_err(|e| Failure(400, format!("malformed JSON: {e}")))?;
+    let urls = doc.as_object().into_iter().flatten();
+    Ok(urls
+        .filter_map(|(name, url)| Some((name.clone(), url.as_str()?.to_string())))
+        .map(|(name, url)| (name.replace("url", "file"), Source::Url(url)))
+        .collect())
+}
+
+#[cfg(not(feature = "remote"))]
+fn urls(_: &[u8], _: &ServerOptions) -> Result<HashMap<String, Source>, Failure> {
+    Err(Failure(403, "this server reads uploads only".to_string()))
+}
+
+fn take(sources: &mut HashMap<String, Source>, name: &str) -> Result<Source, Failure> {
+    sources
+        .remove(name)
+        .ok_or_else(|| Failure(400, format!("the request has no file {name}")))
+}
+
+fn open(source: &Source) -> Result<(GGUFFile, u64), String> {
+    let options = ParseOptions::lenient();
+    match source {
+        Source::Bytes(data) => GGUFFile::read_from(&mut Cursor::new(data), &options),
+        #[cfg(feature = "remote")]
+        Source::Url(url) => GGUFFile::read_from(&mut RemoteFile::open(url)?, &options),
+    }
+    .map_err(|e| e.to_string())
+}
+
+fn tensor_json(t: &GGUFTensorInfo) -> Value {
+    json!({
+        "name": t.name,
+        "shape": t.dimensions,
+        "type": t.tensor_type,
+        "offset": t.offset,
+        "bytes": t.size_bytes(),
+    })
+}
+
+fn entry_json(m: &GGUFMetadata) -> Value {
+    json!({"key": m.key, "type": m.value_type, "value": m.value})
+}
+
+fn inspect(source: &Source) -> Result<Value, Failure> {
+    let (file, data_start) = open(source)?;
+    Ok(json!({
+        "version": file.header.version,
+        "tensor_count": file.tensors.len(),
+        "data_start": data_start,
+        "metadata": file.header.metadata.iter().map(entry_json).collect::<Vec<_>>(),
+        "tensors": file.tensors.iter().map(tensor_json).collect::<Vec<_>>(),
+    }))
+}
+
+fn check(source: &Source) -> Result<ValidationReport, Failure> {
+    Ok(match source {
+        Source::Bytes(data) => validate(data),
+        #[cfg(feature = "remote")]
+        Source::Url(url) => validate_reader(RemoteFile::open(url)?)?,
+    })
+}
+
+/// Entries only in `b`, only in `a` and in both but different, by `name`
+fn changes<T>(
+    a: &[T],
+    b: &[T],
+    name: impl Fn(&T) -> &str,
+    same: impl Fn(&T, &T) -> bool,
+    to_json: impl Fn(&T) -> Value,
+) -> Value {
+    let in_a: HashMap<&str, &T> = a.iter().map(|x| (name(x), x)).collect();
+    let in_b: HashMap<&str, &T> = b.iter().map(|x| (name(x), x)).collect();
+    let added: Vec<Value> = b
+        .iter()
+        .filter(|x| !in_a.contains_key(name(x)))
+        .map(&to_json)
+        .collect();
+    let removed: Vec<Value> = a
+        .iter()
+        .filter(|x| !in_b.contains_key(name(x)))
+        .map(&to_json)
+        .collect();
+    let changed: Vec<Value> = b
+        .iter()
+        .filter_map(|new| {
+            let old = in_a.get(name(new))?;
+            (!same(old, new))
+                .then(|| json!({"name": name(new), "old": to_json(old), "new": to_json(new)}))
+        })
+        .collect();
+    json!({"added": added, "removed": removed, "changed": changed})
+}
+
+fn metadata_json(diff: &MetadataDiff) -> Value {
+    json!({
+        "added": diff.added.iter().map(entry_json).collect::<Vec<_>>(),
+        "removed": diff.removed.iter().map(entry_json).collect::<Vec<_>>(),
+        "changed": diff.changed.iter().map(|(old, new)| {
+            json!({"name": new.key, "old": entry_json(old), "new": entry_json(new)})
+        }).collect::<Vec<_>>(),
+    })
+}
+
+fn diff(a: &Source, b: &Source) -> Result<Value, Failure> {
+    let ((a, _), (b, _)) = (open(a)?, open(b)?);
+    Ok(json!({
+        "version": (a.header.version != b.header.version)
+            .then_some([a.header.version, b.header.version]),
+        "metadata": metadata_json(&MetadataDiff::between(&a.header, &b.header)),
+        "tensors": changes(
+            &a.tensors,
+            &b.tensors,
+            |t| &t.name,
+            |x, y| x.dimensions == y.dimensions && x.tensor_type == y.tensor_type,
+            tensor_json,
+        ),
+    }))
+}
+
+/// Answer one request
+fn respond(request: Request, options: &ServerOptions) -> Result<Value, Failure> {
+    let route = (request.method.as_str(), request.path.as_str());
+    match route {
+        ("GET", "/health") => return Ok(json!({"status": "ok"})),
+        ("POST", "/inspect" | "/validate" | "/diff") => {}
+        (_, "/health" | "/inspect" | "/validate" | "/diff") => {
+            return Err(Failure(
+                405,
+                format!("{} is not allowed here", request.method),
+            ))
+        }
+        (_, path) => return Err(Failure(404, format!("no endpoint {path}"))),
+    }
+    let path = request.path.clone();
+    let mut sources = sources(request, options)?;
+    match path.as_str() {
+        "/inspect" => inspect(&take(&mut sources, "file")?),
+        "/validate" => {
+            Ok(serde_json::to_value(check(&take(&mut sources, "file")?)?).unwrap_or_default())
+        }
+        _ => diff(&take(&mut sources, "a")?, &take(&mut sources, "b")?),
+    }
+}
+
+fn handle(mut stream: TcpStream, options: &ServerOptions) -> io::Result<()> {
+    stream.set_read_timeout(Some(options.timeout))?;
+    stream.set_write_timeout(Some(options.timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (status, body) = match read_request(&mut reader, &mut stream, options.max_body)
+        .and_then(|request| respond(request, options))
+    {
+        Ok(body) => (200, body),
+        Err(Failure(status, message)) => (status, json!({ "error": message })),
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Unprocessable Entity",
+    };
+    let body = serde_json::to_vec(&body).unwrap_or_default();
+    write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    stream.flush()
+}
+
+/// A bound service, answering requests once [`Server::serve`] runs
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+    options: ServerOptions,
+}
+
+impl Server {
+    /// Listen on `address`, e.g. `127.0.0.1:8080`, or port 0 for any free one
+    pub fn bind(address: impl ToSocketAddrs, options: ServerOptions) -> Result<Self, String> {
+        let listener = TcpListener::bind(address).map_err(|e| e.to_string())?;
+        Ok(Server { listener, options })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, String> {
+        self.listener.local_addr().map_err(|e| e.to_string())
+    }
+
+    /// Answer requests on [`ServerOptions::workers`] threads, logging to stderr the
+    /// connections that could not be accepted and going on
+    pub fn serve(self) -> Result<(), String> {
+        let options = Arc::new(self.options);
+        let workers = options.workers.max(1);
+        // Holding a connection per worker, so accepting waits while every worker is busy
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(workers);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..workers {
+            let (receiver, options) = (receiver.clone(), options.clone());
+            std::thread::spawn(move || loop {
+                let stream = match receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => return,
+                };
+                match stream {
+                    Ok(stream) => {
+                        let _ = handle(stream, &options);
+                    }
+                    Err(_) => return,
+                }
+            });
+        }
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => sender
+                    .send(stream)
+                    .map_err(|_| "the workers stopped".to_string())?,
+                Err(e) => {
+                    eprintln!("gguf server: accepting a connection failed: {e}");
+                    // Out of file descriptors, say; give the workers time to close some
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header};
+    use crate::writer::write_header;
+    use crate::GGUFMetadataValue;
+
+    fn post(address: SocketAddr, path: &str, content_type: &str, body: &[u8]) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "POST {path} HTTP/1.1\r\nHost: test\r\nContent-Type: {content_type}\r\n\
+             Content-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn stub(name: &str) -> Vec<u8> {
+        let file = file(
+            header(&[("general.name", GGUFMetadataValue::String(name.to_string()))]),
+            Vec::new(),
+        );
+        let mut buf = Vec::new();
+        write_header(&mut buf, &file).unwrap();
+        buf
+    }
+
+    #[test]
+    fn serves_endpoints() {
+        let server = Server::bind("127.0.0.1:0", ServerOptions::default()).unwrap();
+        let address = server.local_addr().unwrap();
+        std::thread::spawn(move || server.serve());
+
+        let octets = "application/octet-stream";
+        let (status, doc) = post(address, "/inspect", octets, &stub("tiny"));
+        assert_eq!(status, 200);
+        assert_eq!(doc["metadata"][0]["value"], "tiny");
+        let (status, doc) = post(address, "/validate", octets, b"junk");
+        assert_eq!((status, &doc["valid"]), (200, &json!(false)));
+
+        let mut body = Vec::new();
+        for (name, data) in [("a", stub("tiny")), ("b", stub("tiny-chat"))] {
+            body.extend(
+                format!(
+                    "--xyz\r\nContent-Disposition: form-data; name=\"{name}\"; \
+                 filename=\"{name}.gguf\"\r\n\r\n"
+                )
+                .bytes(),
+            );
+            body.extend(data);
+            body.extend(b"\r\n");
+        }
+        body.extend(b"--xyz--\r\n");
+        let (status, doc) = post(address, "/diff", "multipart/form-data; boundary=xyz", &body);
+        assert_eq!(status, 200);
+        assert_eq!(doc["metadata"]["changed"][0]["new"]["value"], "tiny-chat");
+
+        let (status, doc) = post(address, "/inspect", octets, b"junk");
+        assert_eq!(status, 422);
+        assert!(doc["error"].is_string());
+        assert_eq!(post(address, "/nothing", octets, b"").0, 404);
+
+        let url = br#"{"url": "http://10.0.0.1/model.gguf"}"#;
+        assert_eq!(post(address, "/inspect", "application/json", url).0, 403);
+        let long = format!(
+            "GET /health HTTP/1.1\r\nX: {}\r\n\r\n",
+            "x".repeat(MAX_LINE as usize)
+        );
+        let read = read_request(&mut long.as_bytes(), &mut io::sink(), 0);
+        assert!(matches!(read, Err(Failure(431, _))));
+    }
+}
@@ -0,0 +1,113 @@
+//! # Detached signatures
+//!
+//! A signature covers a canonical digest of the file: the SHA-256 of the header and tensor
+//! infos followed by the data of each tensor in tensor info order. Alignment padding and
+//! trailing bytes are left out, so re-padding a file does not invalidate its signature.
+//!
+//! Signatures are Ed25519 and stored in a one-line `.sig` text file, `ed25519:<hex>`. The
+//! crate only verifies them: its Ed25519 is hand-written and not constant-time, which matters
+//! for a secret key but not for checking a signature against a public one. Sign the
+//! [`canonical_digest`] with a vetted implementation, e.g.
+//! `openssl pkeyutl -sign -rawin -inkey key.pem -in digest.bin`, and hex-encode the result.
+//! The module needs the `experimental` feature besides `signing`.
+use std::fs::File;
+use std::path::Path;
+
+use crate::digest::{hex, Sha256};
+use crate::manifest::{hash_into, read_header};
+
+mod ed25519;
+
+/// A public key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey(pub [u8; 32]);
+
+impl VerifyingKey {
+    /// Parse a key written as 64 hex digits
+    pub fn from_hex(text: &str) -> Result<Self, String> {
+        Ok(VerifyingKey(parse_hex(text.trim())?))
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex(&self.0)
+    }
+}
+
+fn parse_hex<const N: usize>(text: &str) -> Result<[u8; N], String> {
+    if text.len() != N * 2 {
+        return Err(format!(
+            "expected {} hex digits, found {}",
+            N * 2,
+            text.len()
+        ));
+    }
+    let mut out = [0; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = text
+            .get(i * 2..i * 2 + 2)
+            .and_then(|h| u8::from_str_radix(h, 16).ok())
+            .ok_or_else(|| format!("invalid hex digits in {text}"))?;
+    }
+    Ok(out)
+}
+
+/// The digest a signature covers
+pub fn canonical_digest(file: impl AsRef<Path>) -> Result<[u8; 32], String> {
+    let mut file = File::open(file).map_err(|e| e.to_string())?;
+    let (gguf, header_len, data_start) = read_header(&mut file)?;
+    let mut hasher = Sha256::default();
+    hash_into(&mut hasher, &mut file, 0, Some(header_len))?;
+    for tensor in &gguf.tensors {
+        let size = tensor
+            .size_bytes()
+            .ok_or_else(|| format!("tensor {} has no whole number of blocks", tensor.name))?;
+        hash_into(
+            &mut hasher,
+            &mut file,
+            data_start + tensor.offset,
+            Some(size),
+        )?;
+    }
+    Ok(hasher.finish())
+}
+
+/// Check a file against the contents of its `.sig` file
+pub fn verify(file: impl AsRef<Path>, sig: &str, key: &VerifyingKey) -> Result<bool, String> {
+    let signature = sig
+        .trim()
+        .strip_prefix("ed25519:")
+        .ok_or_else(|| "not an ed25519 signature".to_string())?;
+    let signature = parse_hex::<64>(signature)?;
+    let digest = canonical_digest(file)?;
+    Ok(ed25519::verify(&key.0, &digest, &signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{header_start, temp_path, tensor_info};
+
+    #[test]
+    fn verify_file() {
+        let mut buf = header_start(3, 1, 0);
+        tensor_info(&mut buf, "w", &[4], 0, 0);
+        buf.resize(64, 0);
+        buf.extend([1; 16]);
+        let path = temp_path("gguf-sign.gguf");
+        std::fs::write(&path, &buf).unwrap();
+
+        let seed = [7; 32];
+        let public = VerifyingKey::from_hex(&hex(&ed25519::public_key(&seed))).unwrap();
+        let signature = ed25519::sign(&seed, &canonical_digest(&path).unwrap());
+        let sig = format!("ed25519:{}\n", hex(&signature));
+        assert!(verify(&path, &sig, &public).unwrap());
+
+        buf.extend([0; 32]);
+        std::fs::write(&path, &buf).unwrap();
+        assert!(verify(&path, &sig, &public).unwrap());
+        buf[70] = 2;
+        std::fs::write(&path, &buf).unwrap();
+        assert!(!verify(&path, &sig, &public).unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+}
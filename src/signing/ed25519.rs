@@ -0,0 +1,656 @@
+//! Ed25519 verification (RFC 8032) and the SHA-512 it is built on
+//!
+//! The arithmetic favours brevity over speed and is not constant-time. That leaks nothing when
+//! verifying, as every input is public, so the crate does only that; signing is here for the
+//! tests, which check it against the RFC's vectors.
+
+const K: [u64; 80] = [
+    0x428a2f98d728ae22,
+    0x7137449123ef65cd,
+    0xb5c0fbcfec4d3b2f,
+    0xe9b5dba58189dbbc,
+    0x3956c25bf348b538,
+    0x59f111f1b605d019,
+    0x923f82a4af194f9b,
+    0xab1c5ed5da6d8118,
+    0xd807aa98a3030242,
+    0x12835b0145706fbe,
+    0x243185be4ee4b28c,
+    0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f,
+    0x80deb1fe3b1696b1,
+    0x9bdc06a725c71235,
+    0xc19bf174cf692694,
+    0xe49b69c19ef14ad2,
+    0xefbe4786384f25e3,
+    0x0fc19dc68b8cd5b5,
+    0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275,
+    0x4a7484aa6ea6e483,
+    0x5cb0a9dcbd41fbd4,
+    0x76f988da831153b5,
+    0x983e5152ee66dfab,
+    0xa831c66d2db43210,
+    0xb00327c898fb213f,
+    0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2,
+    0xd5a79147930aa725,
+    0x06ca6351e003826f,
+    0x142929670a0e6e70,
+    0x27b70a8546d22ffc,
+    0x2e1b21385c26c926,
+    0x4d2c6dfc5ac42aed,
+    0x53380d139d95b3df,
+    0x650a73548baf63de,
+    0x766a0abb3c77b2a8,
+    0x81c2c92e47edaee6,
+    0x92722c851482353b,
+    0xa2bfe8a14cf10364,
+    0xa81a664bbc423001,
+    0xc24b8b70d0f89791,
+    0xc76c51a30654be30,
+    0xd192e819d6ef5218,
+    0xd69906245565a910,
+    0xf40e35855771202a,
+    0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8,
+    0x1e376c085141ab53,
+    0x2748774cdf8eeb99,
+    0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63,
+    0x4ed8aa4ae3418acb,
+    0x5b9cca4f7763e373,
+    0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc,
+    0x78a5636f43172f60,
+    0x84c87814a1f0ab72,
+    0x8cc702081a6439ec,
+    0x90befffa23631e28,
+    0xa4506cebde82bde9,
+    0xbef9a3f7b2c67915,
+    0xc67178f2e372532b,
+    0xca273eceea26619c,
+    0xd186b8c721c0c207,
+    0xeada7dd6cde0eb1e,
+    0xf57d4f7fee6ed178,
+    0x06f067aa72176fba,
+    0x0a637dc5a2c898a6,
+    0x113f9804bef90dae,
+    0x1b710b35131c471b,
+    0x28db77f523047d84,
+    0x32caab7b40c72493,
+    0x3c9ebe0a15c9bebc,
+    0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6,
+    0x597f299cfc657e2a,
+    0x5fcb6fab3ad6faec,
+    0x6c44198c4a475817,
+];
+
+/// SHA-512 of the concatenated parts
+pub(crate) fn sha512(parts: &[&[u8]]) -> [u8; 64] {
+    let mut state: [u64; 8] = [
+        0x6a09e667f3bcc908,
+        0xbb67ae8584caa73b,
+        0x3c6ef372fe94f82b,
+        0xa54ff53a5f1d36f1,
+        0x510e527fade682d1,
+        0x9b05688c2b3e6c1f,
+        0x1f83d9abfb41bd6b,
+        0x5be0cd19137e2179,
+    ];
+    let mut data: Vec<u8> = parts.concat();
+    let bits = (data.len() as u128) * 8;
+    data.push(0x80);
+    while data.len() % 128 != 112 {
+        data.push(0);
+    }
+    data.extend_from_slice(&bits.to_be_bytes());
+    for block in data.chunks_exact(128) {
+        let mut w = [0u64; 80];
+        for (i, word) in block.chunks_exact(8).enumerate() {
+            w[i] = u64::from_be_bytes(word.try_into().unwrap_or_default());
+        }
+        for i in 16..80 {
+            let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+            let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..80 {
+            let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+    let mut out = [0; 64];
+    for (chunk, word) in out.chunks_exact_mut(8).zip(state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// element of the field of integers modulo 2^255 - 19, in five 51-bit limbs
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_u64(v: u64) -> Fe {
+        Fe([v & MASK, v >> 51, 0, 0, 0])
+    }
+
+    /// decode 255 little-endian bits, ignoring the top bit
+    fn from_bytes(b: &[u8; 32]) -> Fe {
+        let load = |i: usize| u64::from_le_bytes(b[i..i + 8].try_into().unwrap_or_default());
+        Fe([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    /// the canonical little-endian encoding
+    fn to_bytes(self) -> [u8; 32] {
+        let mut l = self.carry().0;
+        // add 19 to find out whether the value is at least p, then subtract p by dropping bit 255
+        let mut q = (l[0] + 19) >> 51;
+        for limb in &l[1..] {
+            q = (limb + q) >> 51;
+        }
+        l[0] += 19 * q;
+        for i in 0..4 {
+            l[i + 1] += l[i] >> 51;
+            l[i] &= MASK;
+        }
+        l[4] &= MASK;
+        let mut out = [0; 32];
+        let (mut acc, mut bits, mut i) = (0u128, 0, 0);
+        for limb in l {
+            acc |= u128::from(limb) << bits;
+            bits += 51;
+            while bits >= 8 {
+                out[i] = acc as u8;
+                acc >>= 8;
+                bits -= 8;
+                i += 1;
+            }
+        }
+        out[31] = acc as u8;
+        out
+    }
+
+    /// bring every limb back to 51 bits, folding the overflow of the top limb times 19
+    fn carry(self) -> Fe {
+        let mut l = self.0;
+        let top = l[4] >> 51;
+        l[4] &= MASK;
+        l[0] += top * 19;
+        for i in 0..4 {
+            l[i + 1] += l[i] >> 51;
+            l[i] &= MASK;
+        }
+        Fe(l)
+    }
+
+    fn add(self, o: Fe) -> Fe {
+        let mut l = self.0;
+        for (a, b) in l.iter_mut().zip(o.0) {
+            *a += b;
+        }
+        Fe(l).carry()
+    }
+
+    fn sub(self, o: Fe) -> Fe {
+        // add 16p first so no limb underflows
+        const P16: [u64; 5] = [
+            36028797018963664,
+            36028797018963952,
+            36028797018963952,
+            36028797018963952,
+            36028797018963952,
+        ];
+        let mut l = self.0;
+        for i in 0..5 {
+            l[i] = l[i] + P16[i] - o.0[i];
+        }
+        Fe(l).carry()
+    }
+
+    fn neg(self) -> Fe {
+        Fe::ZERO.sub(self)
+    }
+
+    fn mul(self, o: Fe) -> Fe {
+        let m = |x: u64, y: u64| u128::from(x) * u128::from(y);
+        let [a0, a1, a2, a3, a4] = self.0;
+        let [b0, b1, b2, b3, b4] = o.0;
+        let (b1, b2, b3, b4, b1_19, b2_19, b3_19, b4_19) =
+            (b1, b2, b3, b4, b1 * 19, b2 * 19, b3 * 19, b4 * 19);
+        let c0 = m(a0, b0) + m(a4, b1_19) + m(a3, b2_19) + m(a2, b3_19) + m(a1, b4_19);
+        let mut c1 = m(a1, b0) + m(a0, b1) + m(a4, b2_19) + m(a3, b3_19) + m(a2, b4_19);
+        let mut c2 = m(a2, b0) + m(a1, b1) + m(a0, b2) + m(a4, b3_19) + m(a3, b4_19);
+        let mut c3 = m(a3, b0) + m(a2, b1) + m(a1, b2) + m(a0, b3) + m(a4, b4_19);
+        let mut c4 = m(a4, b0) + m(a3, b1) + m(a2, b2) + m(a1, b3) + m(a0, b4);
+        c1 += c0 >> 51;
+        c2 += c1 >> 51;
+        c3 += c2 >> 51;
+        c4 += c3 >> 51;
+        let mut l = [
+            c0 as u64 & MASK,
+            c1 as u64 & MASK,
+            c2 as u64 & MASK,
+            c3 as u64 & MASK,
+            c4 as u64 & MASK,
+        ];
+        l[0] += (c4 >> 51) as u64 * 19;
+        l[1] += l[0] >> 51;
+        l[0] &= MASK;
+        Fe(l)
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    /// raise to a little-endian exponent
+    fn pow(self, exp: &[u8; 32]) -> Fe {
+        let mut r = Fe::ONE;
+        for bit in (0..256).rev() {
+            r = r.square();
+            if exp[bit / 8] >> (bit % 8) & 1 == 1 {
+                r = r.mul(self);
+            }
+        }
+        r
+    }
+
+    fn invert(self) -> Fe {
+        // p - 2
+        let mut exp = [0xff; 32];
+        exp[0] = 0xeb;
+        exp[31] = 0x7f;
+        self.pow(&exp)
+    }
+
+    fn is_negative(self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    fn equals(self, o: Fe) -> bool {
+        self.to_bytes() == o.to_bytes()
+    }
+}
+
+/// -121665 / 121666
+fn curve_d() -> Fe {
+    Fe::from_u64(121665)
+        .neg()
+        .mul(Fe::from_u64(121666).invert())
+}
+
+/// a square root of -1, 2^((p - 1) / 4)
+fn sqrt_m1() -> Fe {
+    let mut exp = [0xff; 32];
+    exp[0] = 0xfb;
+    exp[31] = 0x1f;
+    Fe::from_u64(2).pow(&exp)
+}
+
+/// point of the curve in extended coordinates
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY: Point = Point {
+        x: Fe::ZERO,
+        y: Fe::ONE,
+        z: Fe::ONE,
+        t: Fe::ZERO,
+    };
+
+    fn base() -> Point {
+        let mut b = [0x66; 32];
+        b[0] = 0x58;
+        Point::decode(&b).unwrap_or(Point::IDENTITY)
+    }
+
+    fn add(self, o: Point) -> Point {
+        let d2 = curve_d().add(curve_d());
+        let a = self.y.sub(self.x).mul(o.y.sub(o.x));
+        let b = self.y.add(self.x).mul(o.y.add(o.x));
+        let c = self.t.mul(d2).mul(o.t);
+        let d = self.z.add(self.z).mul(o.z);
+        let (e, f, g, h) = (b.sub(a), d.sub(c), d.add(c), b.add(a));
+        Point {
+            x: e.mul(f),
+            y: g.mul(h),
+            z: f.mul(g),
+            t: e.mul(h),
+        }
+    }
+
+    /// multiply by a little-endian scalar
+    fn mul(self, scalar: &[u8; 32]) -> Point {
+        let mut r = Point::IDENTITY;
+        for bit in (0..256).rev() {
+            r = r.add(r);
+            if scalar[bit / 8] >> (bit % 8) & 1 == 1 {
+                r = r.add(self);
+            }
+        }
+        r
+    }
+
+    fn encode(self) -> [u8; 32] {
+        let zi = self.z.invert();
+        let (x, y) = (self.x.mul(zi), self.y.mul(zi));
+        let mut out = y.to_bytes();
+        out[31] |= u8::from(x.is_negative()) << 7;
+        out
+    }
+
+    fn decode(b: &[u8; 32]) -> Option<Point> {
+        let y = Fe::from_bytes(b);
+        let mut canonical = *b;
+        canonical[31] &= 0x7f;
+        if y.to_bytes() != canonical {
+            return None;
+        }
+        let sign = b[31] >> 7 == 1;
+        let y2 = y.square();
+        let u = y2.sub(Fe::ONE);
+        let v = curve_d().mul(y2).add(Fe::ONE);
+        // x = u v^3 (u v^7)^((p - 5) / 8)
+        let mut exp = [0xff; 32];
+        exp[0] = 0xfd;
+        exp[31] = 0x0f;
+        let v3 = v.square().mul(v);
+        let mut x = u.mul(v3).mul(u.mul(v3).mul(v.square().square()).pow(&exp));
+        let vx2 = v.mul(x.square());
+        if !vx2.equals(u) {
+            if !vx2.equals(u.neg()) {
+                return None;
+            }
+            x = x.mul(sqrt_m1());
+        }
+        if x.equals(Fe::ZERO) && sign {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = x.neg();
+        }
+        Some(Point {
+            x,
+            y,
+            z: Fe::ONE,
+            t: x.mul(y),
+        })
+    }
+}
+
+/// order of the base point, little-endian
+const L: [u64; 4] = [
+    0x5812631a5cf5d3ed,
+    0x14def9dea2f79cd6,
+    0,
+    0x1000000000000000,
+];
+
+/// reduce a little-endian number modulo L
+fn reduce(bytes: &[u8]) -> [u8; 32] {
+    let mut rem = [0u64; 5];
+    for bit in (0..bytes.len() * 8).rev() {
+        // rem = rem * 2 + bit
+        for i in (1..5).rev() {
+            rem[i] = rem[i] << 1 | rem[i - 1] >> 63;
+        }
+        rem[0] = rem[0] << 1 | u64::from(bytes[bit / 8] >> (bit % 8) & 1);
+        if !less_than_l(&rem) {
+            let mut borrow = 0;
+            for (i, limb) in rem.iter_mut().enumerate() {
+                let l = L.get(i).copied().unwrap_or(0);
+                let (v, b1) = limb.overflowing_sub(l);
+                let (v, b2) = v.overflowing_sub(borrow);
+                *limb = v;
+                borrow = u64::from(b1 || b2);
+            }
+        }
+    }
+    let mut out = [0; 32];
+    for (chunk, limb) in out.chunks_exact_mut(8).zip(rem) {
+        chunk.copy_from_slice(&limb.to_le_bytes());
+    }
+    out
+}
+
+fn less_than_l(n: &[u64; 5]) -> bool {
+    if n[4] != 0 {
+        return false;
+    }
+    for i in (0..4).rev() {
+        if n[i] != L[i] {
+            return n[i] < L[i];
+        }
+    }
+    false
+}
+
+/// (a * b + c) mod L
+#[cfg(test)]
+fn mul_add(a: &[u8; 32], b: &[u8; 32], c: &[u8; 32]) -> [u8; 32] {
+    let limbs = |s: &[u8; 32]| -> [u64; 4] {
+        std::array::from_fn(|i| {
+            u64::from_le_bytes(s[i * 8..i * 8 + 8].try_into().unwrap_or_default())
+        })
+    };
+    let (a, b, c) = (limbs(a), limbs(b), limbs(c));
+    let mut wide = [0u128; 9];
+    for i in 0..4 {
+        for j in 0..4 {
+            let p = u128::from(a[i]) * u128::from(b[j]);
+            wide[i + j] += p & u128::from(u64::MAX);
+            wide[i + j + 1] += p >> 64;
+        }
+        wide[i] += u128::from(c[i]);
+    }
+    let mut bytes = [0u8; 72];
+    let mut carry = 0u128;
+    for (i, w) in wide.iter().enumerate() {
+        let v = w + carry;
+        bytes[i * 8..i * 8 + 8].copy_from_slice(&(v as u64).to_le_bytes());
+        carry = v >> 64;
+    }
+    reduce(&bytes)
+}
+
+/// the clamped secret scalar and the prefix derived from a seed
+#[cfg(test)]
+fn expand(seed: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let h = sha512(&[seed]);
+    let mut scalar: [u8; 32] = h[..32].try_into().unwrap_or_default();
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    (scalar, h[32..].try_into().unwrap_or_default())
+}
+
+/// The public key of a 32-byte secret seed
+#[cfg(test)]
+pub(crate) fn public_key(seed: &[u8; 32]) -> [u8; 32] {
+    Point::base().mul(&expand(seed).0).encode()
+}
+
+/// Sign a message with a secret seed
+#[cfg(test)]
+pub(crate) fn sign(seed: &[u8; 32], message: &[u8]) -> [u8; 64] {
+    let (scalar, prefix) = expand(seed);
+    let public = Point::base().mul(&scalar).encode();
+    let r = reduce(&sha512(&[&prefix, message]));
+    let big_r = Point::base().mul(&r).encode();
+    let k = reduce(&sha512(&[&big_r, &public, message]));
+    let s = mul_add(&k, &scalar, &r);
+    let mut signature = [0; 64];
+    signature[..32].copy_from_slice(&big_r);
+    signature[32..].copy_from_slice(&s);
+    signature
+}
+
+/// Check a signature of a message against a public key
+pub(crate) fn verify(public: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    let Some(a) = Point::decode(public) else {
+        return false;
+    };
+    let big_r: [u8; 32] = signature[..32].try_into().unwrap_or_default();
+    let s: [u8; 32] = signature[32..].try_into().unwrap_or_default();
+    if reduce(&s) != s {
+        return false;
+    }
+    let k = reduce(&sha512(&[&big_r, public, message]));
+    // [s]B == R + [k]A
+    let left = Point::base().mul(&s).encode();
+    let right = Point::decode(&big_r).map(|r| r.add(a.mul(&k)).encode());
+    right == Some(left)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::hex;
+
+    fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+        std::array::from_fn(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap())
+    }
+
+    /// The message of TEST 1024 of RFC 8032, 1023 bytes
+    const MESSAGE_1024: &str = include_str!("rfc8032-test-1024.hex");
+
+    #[test]
+    fn rfc8032_vectors() {
+        let message_1024: Vec<u8> = MESSAGE_1024
+            .trim()
+            .as_bytes()
+            .chunks(2)
+            .map(|h| u8::from_str_radix(std::str::from_utf8(h).unwrap(), 16).unwrap())
+            .collect();
+        assert_eq!(message_1024.len(), 1023);
+        // TEST 1, 2, 3 and 1024: secret seed, public key, message, signature
+        let vectors: [(&str, &str, &[u8], &str); 4] = [
+            (
+                "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+                b"",
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+            ),
+            (
+                "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+                "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+                &[0x72],
+                "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+            ),
+            (
+                "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+                "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+                &[0xaf, 0x82],
+                "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+            ),
+            (
+                "f5e5767cf153319517630f226876b86c8160cc583bc013744c6bf255f5cc0ee5",
+                "278117fc144c72340f67d0f2316e8386ceffbf2b2428c9c51fef7c597f1d426e",
+                &message_1024,
+                "0aab4c900501b3e24d7cdf4663326a3a87df5e4843b2cbdb67cbf6e460fec350aa5371b1508f9f4528ecea23c436d94b5e8fcd4f681e30a6ac00a9704a188a03",
+            ),
+        ];
+        for (seed, public, message, signature) in vectors {
+            let (seed, public) = (bytes::<32>(seed), bytes::<32>(public));
+            assert_eq!(public_key(&seed), public);
+            assert_eq!(hex(&sign(&seed, message)), signature);
+            assert!(verify(&public, message, &bytes(signature)));
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_and_malleable_signatures() {
+        let seed = bytes::<32>("4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb");
+        let public = public_key(&seed);
+        let signature = sign(&seed, &[0x72]);
+        assert!(verify(&public, &[0x72], &signature));
+
+        assert!(!verify(&public, &[0x73], &signature));
+        let mut flipped_r = signature;
+        flipped_r[0] ^= 1;
+        assert!(!verify(&public, &[0x72], &flipped_r));
+
+        // S + L passes the group equation just as S does, so only the range check refuses it
+        let mut malleable = signature;
+        let mut carry = 0u128;
+        for (i, chunk) in malleable[32..].chunks_exact_mut(8).enumerate() {
+            let sum = u128::from(u64::from_le_bytes(chunk.try_into().unwrap()))
+                + u128::from(L[i])
+                + carry;
+            chunk.copy_from_slice(&(sum as u64).to_le_bytes());
+            carry = sum >> 64;
+        }
+        assert_eq!(carry, 0);
+        assert!(!verify(&public, &[0x72], &malleable));
+
+        // y = p + 1 encodes the identity, as y = 1 does; against that key [1]B == B + [k]A
+        // holds for every message
+        let mut non_canonical = [0xff; 32];
+        non_canonical[0] = 0xee;
+        non_canonical[31] = 0x7f;
+        assert!(Point::decode(&non_canonical).is_none());
+        let mut forged = [0; 64];
+        forged[..32].copy_from_slice(&Point::base().encode());
+        forged[32] = 1;
+        assert!(!verify(&non_canonical, b"anything", &forged));
+    }
+
+    #[test]
+    fn sha512_vectors() {
+        let million = vec![b'a'; 1_000_000];
+        // FIPS 180-2's examples, one, two and many blocks long, and the empty message
+        let vectors: [(&[u8], &str); 4] = [
+            (b"", "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e"),
+            (b"abc", "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"),
+            (b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu", "8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909"),
+            (&million, "e718483d0ce769644e2e42c7bc15b4638e1f98b13b2044285632a803afa973ebde0ff244877ea60a4cb0432ce577c31beb009c5c2c49aa2e4eadb217ad8cc09b"),
+        ];
+        for (message, digest) in vectors {
+            assert_eq!(hex(&sha512(&[message])), digest);
+        }
+        assert_eq!(sha512(&[b"ab", b"c"]), sha512(&[b"abc"]));
+    }
+}
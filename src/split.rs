@@ -0,0 +1,200 @@
+//! # Sharded files
+//!
+//! Splits a file into shards and merges them back, in the layout of llama.cpp's `gguf-split`:
+//! each shard is a GGUF file named `<prefix>-00001-of-00004.gguf` holding some of the tensors
+//! and the `split.no`, `split.count` and `split.tensors.count` keys. The first shard also holds
+//! all other metadata.
+use std::fs::File;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::keys::split::{
+    COUNT as SPLIT_COUNT, NO as SPLIT_NO, TENSORS_COUNT as SPLIT_TENSORS_COUNT,
+};
+use crate::manifest::read_header;
+use crate::writer::{copy_data, rewrite, write_file, WriteError};
+use crate::{GGUFFile, GGUFHeader, GGUFMetadata, GGUFMetadataValue};
+
+/// The path of shard `no`, counting from 0, of `count`
+pub fn split_path(prefix: &str, no: u16, count: u16) -> String {
+    format!("{prefix}-{:05}-of-{count:05}.gguf", no + 1)
+}
+
+/// The prefix of a shard path made by [`split_path`], `None` if it is not one
+pub fn split_prefix(path: &str) -> Option<(&str, u16, u16)> {
+    let stem = path.strip_suffix(".gguf")?;
+    let (rest, count) = stem.rsplit_once("-of-")?;
+    let (prefix, no) = rest.rsplit_once('-')?;
+    let (no, count) = (no.parse::<u16>().ok()?, count.parse::<u16>().ok()?);
+    (no >= 1).then_some((prefix, no - 1, count))
+}
+
+/// Group the tensors, in order, into shards whose data is at most `max_size` bytes
+///
+/// A tensor larger than `max_size` gets a shard of its own.
+pub fn plan(file: &GGUFFile, max_size: u64) -> Result<Vec<Range<usize>>, String> {
+    let alignment = file.alignment();
+    let mut shards = Vec::new();
+    let (mut start, mut size) = (0, 0);
+    for (i, tensor) in file.tensors.iter().enumerate() {
+        let bytes = tensor
+            .size_bytes()
+            .ok_or_else(|| format!("tensor {} has no whole number of blocks", tensor.name))?
+            .next_multiple_of(alignment);
+        if i > start && size + bytes > max_size {
+            shards.push(start..i);
+            (start, size) = (i, 0);
+        }
+        size += bytes;
+    }
+    if start < file.tensors.len() || shards.is_empty() {
+        shards.push(start..file.tensors.len());
+    }
+    if shards.len() > usize::from(u16::MAX) {
+        return Err(format!(
+            "{} shards are more than a split can have",
+            shards.len()
+        ));
+    }
+    Ok(shards)
+}
+
+/// Split `src` into shards of at most `max_size` bytes of tensor data, returning their paths
+pub fn split(
+    src: impl AsRef<Path>,
+    prefix: &str,
+    max_size: u64,
+) -> Result<Vec<PathBuf>, WriteError> {
+    let src = src.as_ref();
+    let (file, _, _) = read_header(&mut File::open(src)?)?;
+    let shards = plan(&file, max_size)?;
+    let count = shards.len() as u16;
+    let mut paths = Vec::new();
+    for (no, tensors) in shards.into_iter().enumerate() {
+        let mut metadata: Vec<GGUFMetadata> = match no {
+            0 => file
+                .header
+                .metadata
+                .iter()
+                .filter(|m| !m.key.starts_with("split."))
+                .cloned()
+                .collect(),
+            _ => file
+                .header
+                .metadata
+                .iter()
+                .filter(|m| m.key == "general.alignment")
+                .cloned()
+                .collect(),
+        };
+        metadata.extend([
+            GGUFMetadata::new(SPLIT_NO, GGUFMetadataValue::Uint16(no as u16)),
+            GGUFMetadata::new(SPLIT_COUNT, GGUFMetadataValue::Uint16(count)),
+            GGUFMetadata::new(
+                SPLIT_TENSORS_COUNT,
+                GGUFMetadataValue::Int32(file.tensors.len() as i32),
+            ),
+        ]);
+        let shard = GGUFFile {
+            header: GGUFHeader {
+                version: file.header.version,
+                tensor_count: tensors.len() as u64,
+                metadata,
+            },
+            tensors: file.tensors[tensors].to_vec(),
+        };
+        let path = PathBuf::from(split_path(prefix, no as u16, count));
+        rewrite(src, &path, &shard)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Merge the shards of a split, given the path of any of them, into one file
+pub fn merge(shard: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<(), WriteError> {
+    let shard = shard.as_ref();
+    let name = shard.to_string_lossy();
+    let (prefix, _, count) =
+        split_prefix(&name).ok_or_else(|| format!("{name} is not named like a shard"))?;
+    let mut inputs = Vec::new();
+    let mut merged: Option<GGUFFile> = None;
+    let mut sources = Vec::new();
+    for no in 0..count {
+        let path = split_path(prefix, no, count);
+        let mut input = File::open(&path).map_err(|e| format!("{path}: {e}"))?;
+        let (file, _, data_start) = read_header(&mut input)?;
+        let key = |key| file.header.get(key).and_then(GGUFMetadataValue::as_u64);
+        if key(SPLIT_NO) != Some(u64::from(no)) || key(SPLIT_COUNT) != Some(u64::from(count)) {
+            return Err(format!("{path} is not shard {} of {count}", no + 1).into());
+        }
+        for tensor in &file.tensors {
+            sources.push((inputs.len(), data_start + tensor.offset));
+        }
+        match &mut merged {
+            None => merged = Some(file),
+            Some(merged) => merged.tensors.extend(file.tensors),
+        }
+        inputs.push(input);
+    }
+    let mut merged = merged.ok_or("a split has at least one shard")?;
+    let expected = merged
+        .header
+        .get(SPLIT_TENSORS_COUNT)
+        .and_then(GGUFMetadataValue::as_u64);
+    if expected != Some(merged.tensors.len() as u64) {
+        return Err(format!(
+            "the shards hold {} tensors, the first one says {}",
+            merged.tensors.len(),
+            expected.map_or("nothing".to_string(), |n| n.to_string())
+        )
+        .into());
+    }
+    merged
+        .header
+        .metadata
+        .retain(|m| !m.key.starts_with("split."));
+    merged.header.tensor_count = merged.tensors.len() as u64;
+    let mut next = sources.into_iter();
+    write_file(dst, &merged, |tensor, out| {
+        let (input, start) = next.next().expect("one source per tensor");
+        copy_data(&mut inputs[input], start, tensor, out)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{entry, header_start, temp_path, tensor_info};
+
+    #[test]
+    fn split_and_merge_round_trip() {
+        let mut buf = header_start(3, 3, 1);
+        entry(&mut buf, "name", 8, &[1, 0, 0, 0, 0, 0, 0, 0, b'm']);
+        for (name, offset) in [("a", 0u64), ("b", 32), ("c", 64)] {
+            tensor_info(&mut buf, name, &[8], 0, offset);
+        }
+        buf.resize(buf.len().next_multiple_of(32), 0);
+        for byte in 1..=3 {
+            buf.extend([byte; 32]);
+        }
+
+        let dir = temp_path("gguf-split");
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("model.gguf");
+        std::fs::write(&src, &buf).unwrap();
+        let prefix = dir.join("model").to_string_lossy().into_owned();
+        let paths = split(&src, &prefix, 64).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert!(paths[1].ends_with("model-00002-of-00002.gguf"));
+        let second = GGUFFile::read(&std::fs::read(&paths[1]).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.tensors.len(), 1);
+        assert!(second.header.get("name").is_none());
+
+        let merged = dir.join("merged.gguf");
+        merge(&paths[1], &merged).unwrap();
+        assert_eq!(std::fs::read(&merged).unwrap(), buf);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+//! # Weight statistics
+//!
+//! Summarizes the values of tensors in one pass over their data, decoding a few blocks at a
+//! time so that no tensor is held in memory whole.
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::quant::dequantize;
+use crate::GGUFTensorInfo;
+
+/// Bytes of tensor data decoded at a time, rounded down to whole blocks
+const CHUNK: u64 = 1 << 20;
+
+/// Running statistics of a stream of values
+///
+/// The mean and variance are updated with Welford's method; NaNs and infinities are counted but
+/// left out of the other statistics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    /// Finite values seen.
+    pub count: u64,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f64,
+    /// Sum of squared differences from the mean.
+    m2: f64,
+    pub zeros: u64,
+    pub nans: u64,
+    pub infs: u64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            count: 0,
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+            zeros: 0,
+            nans: 0,
+            infs: 0,
+        }
+    }
+}
+
+impl Stats {
+    pub fn push(&mut self, value: f32) {
+        if value.is_nan() {
+            self.nans += 1;
+            return;
+        }
+        if value.is_infinite() {
+            self.infs += 1;
+            return;
+        }
+        self.count += 1;
+        self.zeros += u64::from(value == 0.0);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        let delta = f64::from(value) - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (f64::from(value) - self.mean);
+    }
+
+    /// Combine with the statistics of another stream
+    pub fn merge(&mut self, other: &Stats) {
+        let count = self.count + other.count;
+        if count > 0 {
+            let delta = other.mean - self.mean;
+            self.m2 +=
+                other.m2 + delta * delta * self.count as f64 * other.count as f64 / count as f64;
+            self.mean += delta * other.count as f64 / count as f64;
+        }
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.zeros += other.zeros;
+        self.nans += other.nans;
+        self.infs += other.infs;
+    }
+
+    /// The population standard deviation
+    pub fn std(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            (self.m2 / self.count as f64).sqrt()
+        }
+    }
+}
+
+/// The statistics of one tensor, reading its data from a file whose tensor data starts at
+/// `data_start`
+pub fn tensor_stats(
+    input: &mut (impl Read + Seek),
+    data_start: u64,
+    tensor: &GGUFTensorInfo,
+) -> Result<Stats, String> {
+    let tensor_type = tensor.tensor_type;
+    let (size, block_bytes) = match (tensor.size_bytes(), tensor_type.type_size()) {
+        (Some(size), Some(block_bytes)) => (size, block_bytes),
+        _ => {
+            return Err(format!(
+                "tensor {} has no whole number of blocks",
+                tensor.name
+            ))
+        }
+    };
+    input
+        .seek(SeekFrom::Start(data_start + tensor.offset))
+        .map_err(|e| e.to_string())?;
+    let mut stats = Stats::default();
+    let mut buf = Vec::new();
+    let mut left = size;
+    while left > 0 {
+        let n = left.min((CHUNK / block_bytes).max(1) * block_bytes);
+        buf.resize(n as usize, 0);
+        input
+            .read_exact(&mut buf)
+            .map_err(|e| format!("reading tensor {}: {e}", tensor.name))?;
+        for value in dequantize(tensor_type, &buf)? {
+            stats.push(value);
+        }
+        left -= n;
+    }
+    Ok(stats)
+}
+
+/// The statistics of one tensor of which `data` is the whole data, as [`TensorReader::read`]
+/// gives it
+///
+/// [`TensorReader::read`]: crate::batch::TensorReader::read
+pub fn data_stats(tensor: &GGUFTensorInfo, data: &[u8]) -> Result<Stats, String> {
+    let mut stats = Stats::default();
+    for value in dequantize(tensor.tensor_type, data)? {
+        stats.push(value);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn welford_matches_two_passes() {
+        let values: Vec<f32> = (0..1000)
+            .map(|i| ((i * 37) % 101) as f32 / 7.0 - 5.0)
+            .collect();
+        let mut all = Stats::default();
+        values.iter().for_each(|&v| all.push(v));
+        let mean = values.iter().map(|&v| f64::from(v)).sum::<f64>() / 1000.0;
+        let var = values
+            .iter()
+            .map(|&v| (f64::from(v) - mean).powi(2))
+            .sum::<f64>()
+            / 1000.0;
+        assert!((all.mean - mean).abs() < 1e-9);
+        assert!((all.std() - var.sqrt()).abs() < 1e-9);
+
+        let (mut a, mut b) = (Stats::default(), Stats::default());
+        values[..300].iter().for_each(|&v| a.push(v));
+        values[300..].iter().for_each(|&v| b.push(v));
+        a.merge(&b);
+        assert_eq!(a.count, all.count);
+        assert!((a.std() - all.std()).abs() < 1e-9);
+
+        let zeros = a.zeros;
+        [f32::NAN, f32::INFINITY, 0.0]
+            .iter()
+            .for_each(|&v| a.push(v));
+        assert_eq!((a.nans, a.infs, a.zeros, a.count), (1, 1, zeros + 1, 1001));
+    }
+}
@@ -0,0 +1,192 @@
+//! # Summaries
+//!
+//! [`GGUFFile::summary`] gathers what an application shows of a model at a glance, the facts of
+//! a model card, from the header alone.
+use crate::ggml::LlamaFileType;
+use crate::keys::{general, split, tokenizer};
+use crate::tokenizer::SpecialTokens;
+use crate::writer::header_bytes;
+use crate::GGUFFile;
+
+/// A special token of the vocabulary
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SpecialToken {
+    /// What the token is for, `bos`, `eos`, `eot`, ...
+    pub role: &'static str,
+    pub id: u32,
+    /// The text of the token, `None` if the vocabulary has no token `id`.
+    pub text: Option<String>,
+}
+
+/// Which shard of a split a file is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct Shard {
+    /// `split.no`, counting from 0.
+    pub no: u64,
+    /// `split.count`.
+    pub count: u64,
+    /// `split.tensors.count`, the tensors of all shards.
+    pub tensors: Option<u64>,
+}
+
+/// An overview of a model, as [`GGUFFile::summary`] gives it
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ModelSummary {
+    pub architecture: Option<String>,
+    /// `general.name`.
+    pub name: Option<String>,
+    /// The number of elements of all tensors, which for a split are those of this shard,
+    /// `u64::MAX` for shapes past counting.
+    pub parameters: u64,
+    /// The file type `general.file_type` declares, or else the one the tensors make.
+    pub file_type: Option<LlamaFileType>,
+    pub context_length: Option<u64>,
+    /// `{arch}.vocab_size`, or else the length of `tokenizer.ggml.tokens`.
+    pub vocab_size: Option<u64>,
+    pub special_tokens: Vec<SpecialToken>,
+    pub shard: Option<Shard>,
+    /// The bytes of the file: the header, then the data up to the end of the last tensor,
+    /// aligned; `u64::MAX` for offsets past the end of any file.
+    pub file_size: u64,
+}
+
+impl GGUFFile {
+    /// The architecture, name, parameter count, file type, context length, vocabulary, shard
+    /// and size of the file, in one call
+    pub fn summary(&self) -> ModelSummary {
+        let header = &self.header;
+        let text = |key: &str| header.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        let number = |key: &str| header.get(key).and_then(|v| v.as_u64());
+        let architecture = text(general::ARCHITECTURE);
+        let arch_number = |suffix: &str| {
+            let arch = architecture.as_deref()?;
+            number(&format!("{arch}{suffix}"))
+        };
+        let tokens = header.get(tokenizer::TOKENS).and_then(|v| v.as_array());
+        let special_tokens = SpecialTokens::from_header(header)
+            .unwrap_or_default()
+            .named()
+            .into_iter()
+            .filter_map(|(role, id)| {
+                let id = id?;
+                let text = tokens.and_then(|t| t.value.get_str(id as usize));
+                Some(SpecialToken {
+                    role,
+                    id,
+                    text: text.map(str::to_string),
+                })
+            })
+            .collect();
+        let shard = match (number(split::NO), number(split::COUNT)) {
+            (Some(no), Some(count)) => Some(Shard {
+                no,
+                count,
+                tensors: number(split::TENSORS_COUNT),
+            }),
+            _ => None,
+        };
+        // The offsets and shapes are the file's word, so the sums saturate rather than overflow
+        let alignment = self.alignment();
+        let align = |n: u64| n.checked_next_multiple_of(alignment).unwrap_or(u64::MAX);
+        let data_end = self
+            .tensors
+            .iter()
+            .map(|t| t.offset.saturating_add(t.size_bytes().unwrap_or_default()))
+            .max()
+            .unwrap_or_default();
+        let header_len = header_bytes(self).len() as u64;
+        ModelSummary {
+            name: text(general::NAME),
+            parameters: self
+                .tensors
+                .iter()
+                .fold(0, |n, t| n.saturating_add(t.element_count())),
+            file_type: header.file_type().or_else(|| self.quant_report().inferred),
+            context_length: arch_number(crate::keys::arch::CONTEXT_LENGTH),
+            vocab_size: arch_number(crate::keys::arch::VOCAB_SIZE)
+                .or_else(|| tokens.map(|t| t.len)),
+            special_tokens,
+            shard,
+            file_size: align(header_len).saturating_add(align(data_end)),
+            architecture,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ggml::LlamaFileType;
+    use crate::test_util::{file, header, temp_path, tensor};
+    use crate::writer::write_file;
+    use crate::{
+        GGMLType, GGUFFile, GGUFMetadataArrayValue, GGUFMetadataValue, GGUfMetadataValueType,
+    };
+
+    #[test]
+    fn summarizes_a_file() {
+        use GGUFMetadataValue::{String as Str, Uint16, Uint32};
+        let tokens = ["<s>", "</s>", "a", "b"]
+            .map(|t| Str(t.to_string()))
+            .to_vec();
+        let header = header(&[
+            ("general.architecture", Str("llama".into())),
+            ("general.name", Str("Tiny".into())),
+            ("general.file_type", Uint32(7)),
+            ("llama.context_length", Uint32(2048)),
+            (
+                "tokenizer.ggml.tokens",
+                GGUFMetadataValue::Array(GGUFMetadataArrayValue::new(
+                    GGUfMetadataValueType::String,
+                    tokens,
+                )),
+            ),
+            ("tokenizer.ggml.bos_token_id", Uint32(0)),
+            ("tokenizer.ggml.eos_token_id", Uint32(1)),
+            ("split.no", Uint16(0)),
+            ("split.count", Uint16(2)),
+        ]);
+        let file = file(
+            header,
+            ["token_embd.weight", "output.weight"]
+                .map(|name| tensor(name, &[32, 4], GGMLType::Q8_0, 0))
+                .to_vec(),
+        );
+        let path = temp_path("summary.gguf");
+        let data = vec![0u8; 4 * 34];
+        write_file(&path, &file, |_, out| Ok(out.write_all(&data)?)).unwrap();
+        let written = GGUFFile::read(&std::fs::read(&path).unwrap())
+            .unwrap()
+            .unwrap();
+        let summary = written.summary();
+        assert_eq!(summary.file_size, std::fs::metadata(&path).unwrap().len());
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(summary.architecture.as_deref(), Some("llama"));
+        assert_eq!(summary.parameters, 256);
+        assert_eq!(summary.file_type, Some(LlamaFileType::MOSTLY_Q8_0));
+        assert_eq!(summary.context_length, Some(2048));
+        assert_eq!(summary.vocab_size, Some(4));
+        let special: Vec<_> = summary
+            .special_tokens
+            .iter()
+            .map(|t| (t.role, t.text.as_deref()))
+            .collect();
+        assert_eq!(special, [("bos", Some("<s>")), ("eos", Some("</s>"))]);
+        assert_eq!(summary.shard.map(|s| (s.no, s.count)), Some((0, 2)));
+    }
+
+    #[test]
+    fn saturates_on_offsets_past_any_file() {
+        let file = file(
+            header(&[]),
+            [(u64::MAX - 1, u64::MAX), (0, 2)]
+                .map(|(offset, rows)| {
+                    tensor(&format!("t{rows}"), &[32, rows], GGMLType::Q8_0, offset)
+                })
+                .to_vec(),
+        );
+        let summary = file.summary();
+        assert_eq!(summary.file_size, u64::MAX);
+        assert_eq!(summary.parameters, u64::MAX);
+    }
+}
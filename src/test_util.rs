@@ -0,0 +1,113 @@
+//! What the tests build their files from: tensor infos, headers as values, headers written byte
+//! by byte, valid or not, and temporary paths of their own
+#[cfg(feature = "std")]
+use crate::GGMLType;
+use crate::{GGUFFile, GGUFHeader, GGUFMetadata, GGUFMetadataValue, GGUFTensorInfo};
+
+/// A tensor info of `dimensions` at `offset` of the tensor data
+#[cfg(feature = "std")]
+pub(crate) fn tensor(
+    name: &str,
+    dimensions: &[u64],
+    tensor_type: GGMLType,
+    offset: u64,
+) -> GGUFTensorInfo {
+    GGUFTensorInfo {
+        name: name.to_string(),
+        dimensions: dimensions.into(),
+        tensor_type,
+        offset,
+    }
+}
+
+/// A version 3 header of the given entries, counting no tensors
+pub(crate) fn header(entries: &[(&str, GGUFMetadataValue)]) -> GGUFHeader {
+    GGUFHeader {
+        version: 3,
+        tensor_count: 0,
+        metadata: entries
+            .iter()
+            .map(|(key, value)| GGUFMetadata::new(*key, value.clone()))
+            .collect(),
+    }
+}
+
+/// A file of `header` and `tensors`, the header counting them
+pub(crate) fn file(header: GGUFHeader, tensors: Vec<GGUFTensorInfo>) -> GGUFFile {
+    GGUFFile {
+        header: GGUFHeader {
+            tensor_count: tensors.len() as u64,
+            ..header
+        },
+        tensors,
+    }
+}
+
+/// The magic, version and counts a header starts with
+pub(crate) fn header_start(version: u32, tensor_count: u64, metadata_count: u64) -> Vec<u8> {
+    let mut buf = b"GGUF".to_vec();
+    buf.extend(version.to_le_bytes());
+    buf.extend(tensor_count.to_le_bytes());
+    buf.extend(metadata_count.to_le_bytes());
+    buf
+}
+
+/// A string, its length then its bytes
+pub(crate) fn string(out: &mut Vec<u8>, s: &[u8]) {
+    out.extend((s.len() as u64).to_le_bytes());
+    out.extend(s);
+}
+
+/// A metadata entry, `value` already encoded as `value_type`
+pub(crate) fn entry(out: &mut Vec<u8>, key: &str, value_type: u32, value: &[u8]) {
+    string(out, key.as_bytes());
+    out.extend(value_type.to_le_bytes());
+    out.extend(value);
+}
+
+/// A tensor info, `tensor_type` as the ggml type id
+pub(crate) fn tensor_info(
+    out: &mut Vec<u8>,
+    name: &str,
+    dimensions: &[u64],
+    tensor_type: u32,
+    offset: u64,
+) {
+    string(out, name.as_bytes());
+    out.extend((dimensions.len() as u32).to_le_bytes());
+    for dimension in dimensions {
+        out.extend(dimension.to_le_bytes());
+    }
+    out.extend(tensor_type.to_le_bytes());
+    out.extend(offset.to_le_bytes());
+}
+
+/// A version 3 header with the given entries and one 32-element f32 tensor per name, 128
+/// bytes apart
+#[cfg(feature = "std")]
+pub(crate) fn raw_header(entries: &[(&str, u32, Vec<u8>)], tensors: &[&str]) -> Vec<u8> {
+    let mut buf = header_start(3, tensors.len() as u64, entries.len() as u64);
+    for (key, value_type, value) in entries {
+        entry(&mut buf, key, *value_type, value);
+    }
+    for (i, name) in tensors.iter().enumerate() {
+        tensor_info(&mut buf, name, &[32], 0, i as u64 * 128);
+    }
+    buf
+}
+
+/// A path in the temporary directory no other test uses, `name` with the process id and a
+/// count of the paths given before it ahead of its extension
+#[cfg(feature = "std")]
+pub(crate) fn temp_path(name: &str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNT.fetch_add(1, Ordering::Relaxed);
+    let (stem, extension) = match name.split_once('.') {
+        Some((stem, extension)) => (stem, format!(".{extension}")),
+        None => (name, String::new()),
+    };
+    let unique = format!("{stem}-{}-{n}{extension}", std::process::id());
+    std::env::temp_dir().join(unique)
+}
@@ -0,0 +1,942 @@
+//! # Tokenizer metadata and detokenization
+//!
+//! Reads the `tokenizer.ggml.*` keys of a GGUF header into a [`Vocab`] and turns token ids back
+//! into text, and encodes text for the SentencePiece BPE, byte-level BPE and RWKV world vocabularies.
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+use crate::{
+    ArrayValues, GGUFFile, GGUFHeader, GGUFMetadata, GGUFMetadataArrayValue, GGUFMetadataValue,
+    GGUfMetadataValueType,
+};
+
+mod bpe;
+mod check;
+mod diff;
+#[cfg(feature = "json")]
+mod hf;
+mod pre;
+mod rwkv;
+mod spm;
+mod text;
+
+pub use check::SelfTestReport;
+pub use diff::{RenamedToken, RetypedToken, SpecialTokenChange, TokenEntry, VocabDiff};
+pub use pre::PreTokenizer;
+
+/// Token type as stored in `tokenizer.ggml.token_type`
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenType {
+    /// No type was recorded, treated like a normal token.
+    Undefined = 0,
+    /// A regular piece of text.
+    Normal = 1,
+    /// The unknown token.
+    Unknown = 2,
+    /// A control token such as `<s>` or `<|eot_id|>`.
+    Control = 3,
+    /// A token added on top of the base vocabulary.
+    UserDefined = 4,
+    /// A reserved slot that is never produced.
+    Unused = 5,
+    /// A byte-fallback token of the form `<0xNN>`.
+    Byte = 6,
+}
+
+impl TryFrom<i64> for TokenType {
+    type Error = String;
+
+    fn try_from(item: i64) -> Result<Self, Self::Error> {
+        Ok(match item {
+            0 => TokenType::Undefined,
+            1 => TokenType::Normal,
+            2 => TokenType::Unknown,
+            3 => TokenType::Control,
+            4 => TokenType::UserDefined,
+            5 => TokenType::Unused,
+            6 => TokenType::Byte,
+            _ => return Err(format!("invalid token type {}", item)),
+        })
+    }
+}
+
+/// Tokenizer model as named by `tokenizer.ggml.model`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TokenizerModel {
+    /// The file has no vocabulary.
+    #[default]
+    None,
+    /// SentencePiece BPE with byte fallback (`llama`).
+    Llama,
+    /// Byte-level BPE (`gpt2`).
+    Gpt2,
+    /// WordPiece (`bert`).
+    Bert,
+    /// SentencePiece unigram (`t5`).
+    T5,
+    /// RWKV world tokenizer (`rwkv`).
+    Rwkv,
+    /// Any other model name.
+    Other(String),
+}
+
+impl TokenizerModel {
+    /// Parse the value of `tokenizer.ggml.model`
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "no_vocab" | "none" => Self::None,
+            "llama" => Self::Llama,
+            "gpt2" => Self::Gpt2,
+            "bert" => Self::Bert,
+            "t5" => Self::T5,
+            "rwkv" => Self::Rwkv,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// The name stored in `tokenizer.ggml.model`
+    pub fn name(&self) -> &str {
+        match self {
+            Self::None => "no_vocab",
+            Self::Llama => "llama",
+            Self::Gpt2 => "gpt2",
+            Self::Bert => "bert",
+            Self::T5 => "t5",
+            Self::Rwkv => "rwkv",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+/// Ids of the special tokens declared in the header
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpecialTokens {
+    pub bos: Option<u32>,
+    pub eos: Option<u32>,
+    pub unk: Option<u32>,
+    pub sep: Option<u32>,
+    pub pad: Option<u32>,
+    pub cls: Option<u32>,
+    pub mask: Option<u32>,
+    /// End of turn, e.g. `<|eot_id|>`.
+    pub eot: Option<u32>,
+    /// End of message, e.g. Llama 3.1's `<|eom_id|>` ending a tool call.
+    pub eom: Option<u32>,
+    /// Additional stop tokens from `tokenizer.ggml.stop_token_ids`.
+    pub stop: Vec<u32>,
+}
+
+impl SpecialTokens {
+    /// Read the special token ids from the `tokenizer.ggml.*_token_id` keys of a header
+    pub fn from_header(header: &GGUFHeader) -> Result<Self, String> {
+        Ok(SpecialTokens {
+            bos: token_id(header, "tokenizer.ggml.bos_token_id"),
+            eos: token_id(header, "tokenizer.ggml.eos_token_id"),
+            unk: token_id(header, "tokenizer.ggml.unknown_token_id"),
+            sep: token_id(header, "tokenizer.ggml.seperator_token_id")
+                .or_else(|| token_id(header, "tokenizer.ggml.separator_token_id")),
+            pad: token_id(header, "tokenizer.ggml.padding_token_id"),
+            cls: token_id(header, "tokenizer.ggml.cls_token_id"),
+            mask: token_id(header, "tokenizer.ggml.mask_token_id"),
+            eot: token_id(header, "tokenizer.ggml.eot_token_id"),
+            eom: token_id(header, "tokenizer.ggml.eom_token_id"),
+            stop: number_array(header, "tokenizer.ggml.stop_token_ids", |v| {
+                v.as_u64().and_then(|id| u32::try_from(id).ok())
+            })?
+            .unwrap_or_default(),
+        })
+    }
+
+    /// The single ids paired with their names, e.g. `("eos", Some(2))`
+    pub fn named(&self) -> [(&'static str, Option<u32>); 9] {
+        [
+            ("bos", self.bos),
+            ("eos", self.eos),
+            ("unk", self.unk),
+            ("sep", self.sep),
+            ("pad", self.pad),
+            ("cls", self.cls),
+            ("mask", self.mask),
+            ("eot", self.eot),
+            ("eom", self.eom),
+        ]
+    }
+}
+
+/// How control tokens are rendered when detokenizing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecialTokenPolicy {
+    /// Leave them out of the text.
+    Drop,
+    /// Render their literal text, e.g. `<|eot_id|>`.
+    Literal,
+    /// Replace each one with the given text, substituting `{id}` with the token id.
+    Placeholder(String),
+}
+
+/// A token handled outside of the regular vocabulary
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct AddedToken {
+    /// The token text, `None` for a special id that points past the vocabulary.
+    pub content: Option<String>,
+    pub token_type: Option<TokenType>,
+    /// Whether the token is a control token rather than text.
+    pub special: bool,
+    /// Whether whitespace on the left is absorbed into the token.
+    pub lstrip: bool,
+    /// Whether whitespace on the right is absorbed into the token.
+    pub rstrip: bool,
+}
+
+/// Vocabulary of a GGUF model
+///
+/// Lookups by token text and merges use indexes built on first use, which
+/// [`tokens_mut`](Vocab::tokens_mut) and [`merges_mut`](Vocab::merges_mut) drop again.
+#[derive(Debug, Clone, Default)]
+pub struct Vocab {
+    pub model: TokenizerModel,
+    tokens: Vec<String>,
+    /// Per-token scores, empty if the file has none.
+    pub scores: Vec<f32>,
+    /// Per-token types, empty if the file has none.
+    pub token_types: Vec<TokenType>,
+    /// BPE merges as `"left right"` pairs, empty if the file has none.
+    merges: Vec<String>,
+    /// The pre-tokenizer name from `tokenizer.ggml.pre`, see [`Vocab::pre_tokenizer`].
+    pub pre: Option<String>,
+    pub special: SpecialTokens,
+    /// Whether the SentencePiece dummy prefix space is added (and removed again on decode).
+    pub add_space_prefix: bool,
+    ids: OnceLock<HashMap<String, u32>>,
+    trie: OnceLock<Trie>,
+    /// the trie over the bytes RWKV world tokens stand for
+    rwkv_trie: OnceLock<Trie>,
+    ranks: OnceLock<HashMap<(String, String), usize>>,
+}
+
+/// byte trie over the token texts for longest-match lookups
+#[derive(Debug, Clone, Default)]
+struct Trie {
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    /// children sorted by byte
+    children: Vec<(u8, u32)>,
+    token: Option<u32>,
+}
+
+impl Trie {
+    fn new<T: AsRef<[u8]>>(tokens: impl IntoIterator<Item = T>) -> Self {
+        let mut trie = Trie {
+            nodes: vec![TrieNode::default()],
+        };
+        for (id, token) in tokens.into_iter().enumerate() {
+            let mut node = 0;
+            for &b in token.as_ref() {
+                node = match trie.nodes[node].children.binary_search_by_key(&b, |c| c.0) {
+                    Ok(i) => trie.nodes[node].children[i].1 as usize,
+                    Err(i) => {
+                        let child = trie.nodes.len();
+                        trie.nodes.push(TrieNode::default());
+                        trie.nodes[node].children.insert(i, (b, child as u32));
+                        child
+                    }
+                };
+            }
+            trie.nodes[node].token.get_or_insert(id as u32);
+        }
+        trie
+    }
+
+    fn longest_prefix(&self, text: &[u8]) -> Option<(u32, usize)> {
+        let mut node = 0;
+        let mut best = None;
+        for (i, b) in text.iter().enumerate() {
+            let children = &self.nodes[node].children;
+            match children.binary_search_by_key(b, |c| c.0) {
+                Ok(c) => node = children[c].1 as usize,
+                Err(_) => break,
+            }
+            if let Some(id) = self.nodes[node].token {
+                best = Some((id, i + 1));
+            }
+        }
+        best
+    }
+}
+
+/// read a string array, `None` if the key is missing
+fn string_array(header: &GGUFHeader, key: &str) -> Result<Option<Vec<String>>, String> {
+    let Some(value) = header.get(key) else {
+        return Ok(None);
+    };
+    let array = value
+        .as_array()
+        .ok_or_else(|| format!("{key} is not an array"))?;
+    array
+        .value
+        .iter()
+        .map(|v| match v {
+            GGUFMetadataValue::String(s) => Ok(s),
+            _ => Err(format!("{key} contains a non-string value")),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// read a numeric array, `None` if the key is missing
+fn number_array<T>(
+    header: &GGUFHeader,
+    key: &str,
+    f: impl Fn(&GGUFMetadataValue) -> Option<T>,
+) -> Result<Option<Vec<T>>, String> {
+    let Some(value) = header.get(key) else {
+        return Ok(None);
+    };
+    let array = value
+        .as_array()
+        .ok_or_else(|| format!("{key} is not an array"))?;
+    array
+        .value
+        .iter()
+        .map(|v| f(&v).ok_or_else(|| format!("{key} contains a non-numeric value")))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// read an optional token id
+fn token_id(header: &GGUFHeader, key: &str) -> Option<u32> {
+    header
+        .get(key)
+        .and_then(GGUFMetadataValue::as_u64)
+        .and_then(|v| u32::try_from(v).ok())
+}
+
+impl Vocab {
+    /// Read the vocabulary from the `tokenizer.ggml.*` keys of a header
+    pub fn from_header(header: &GGUFHeader) -> Result<Vocab, String> {
+        let model = header
+            .get("tokenizer.ggml.model")
+            .and_then(GGUFMetadataValue::as_str)
+            .map(TokenizerModel::from_name)
+            .ok_or_else(|| "missing tokenizer.ggml.model".to_string())?;
+        let tokens = string_array(header, "tokenizer.ggml.tokens")?.unwrap_or_default();
+        let scores = number_array(header, "tokenizer.ggml.scores", |v| {
+            v.as_f64().map(|f| f as f32)
+        })?
+        .unwrap_or_default();
+        let token_types = number_array(header, "tokenizer.ggml.token_type", |v| v.as_i64())?
+            .unwrap_or_default()
+            .into_iter()
+            .map(TokenType::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        let merges = string_array(header, "tokenizer.ggml.merges")?.unwrap_or_default();
+        let pre = header
+            .get("tokenizer.ggml.pre")
+            .and_then(GGUFMetadataValue::as_str)
+            .map(str::to_string);
+        let special = SpecialTokens::from_header(header)?;
+        let add_space_prefix = header
+            .get("tokenizer.ggml.add_space_prefix")
+            .and_then(GGUFMetadataValue::as_bool)
+            .unwrap_or(model == TokenizerModel::Llama);
+        Ok(Vocab {
+            model,
+            tokens,
+            scores,
+            token_types,
+            merges,
+            pre,
+            special,
+            add_space_prefix,
+            ..Default::default()
+        })
+    }
+
+    /// The token texts, by id
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+
+    /// The token texts to change, dropping the indexes lookups built from them
+    pub fn tokens_mut(&mut self) -> &mut Vec<String> {
+        self.ids.take();
+        self.trie.take();
+        self.rwkv_trie.take();
+        &mut self.tokens
+    }
+
+    pub fn into_tokens(self) -> Vec<String> {
+        self.tokens
+    }
+
+    /// BPE merges as `"left right"` pairs, empty if the file has none
+    pub fn merges(&self) -> &[String] {
+        &self.merges
+    }
+
+    /// The merges to change, dropping the ranks built from them
+    pub fn merges_mut(&mut self) -> &mut Vec<String> {
+        self.ranks.take();
+        &mut self.merges
+    }
+
+    /// Number of tokens
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Whether the vocabulary has no tokens
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// The text of a token
+    pub fn token(&self, id: u32) -> Option<&str> {
+        self.tokens.get(id as usize).map(String::as_str)
+    }
+
+    /// The id of a token text, using a hash index built on first use
+    pub fn id(&self, token: &str) -> Option<u32> {
+        self.ids
+            .get_or_init(|| {
+                let mut ids = HashMap::with_capacity(self.tokens.len());
+                for (id, token) in self.tokens.iter().enumerate() {
+                    ids.entry(token.clone()).or_insert(id as u32);
+                }
+                ids
+            })
+            .get(token)
+            .copied()
+    }
+
+    /// The longest token that is a prefix of `bytes`, with its length in bytes
+    ///
+    /// Backed by a byte trie built on first use. RWKV world tokens are matched by the bytes they
+    /// stand for rather than their escaped text.
+    pub fn longest_prefix(&self, bytes: &[u8]) -> Option<(u32, usize)> {
+        let trie = match self.model {
+            TokenizerModel::Rwkv => self
+                .rwkv_trie
+                .get_or_init(|| Trie::new(self.tokens.iter().map(|t| rwkv::unescape_rwkv(t)))),
+            _ => self.trie.get_or_init(|| Trie::new(&self.tokens)),
+        };
+        trie.longest_prefix(bytes)
+    }
+
+    /// The type of a token, `Normal` if the file records none
+    pub fn token_type(&self, id: u32) -> TokenType {
+        self.token_types
+            .get(id as usize)
+            .copied()
+            .unwrap_or(TokenType::Normal)
+    }
+
+    /// Control, unknown and user-defined tokens, plus special ids outside the vocabulary
+    ///
+    /// GGUF does not store strip attributes; they are recovered from well-known conventions such
+    /// as `<mask>` absorbing the space before it.
+    pub fn added_tokens(&self) -> BTreeMap<u32, AddedToken> {
+        let mut added = BTreeMap::new();
+        for (id, token) in self.tokens.iter().enumerate() {
+            let token_type = self.token_type(id as u32);
+            let special = match token_type {
+                TokenType::Control | TokenType::Unknown => true,
+                TokenType::UserDefined => false,
+                _ => continue,
+            };
+            added.insert(
+                id as u32,
+                AddedToken {
+                    content: Some(token.clone()),
+                    token_type: Some(token_type),
+                    special,
+                    lstrip: token == "<mask>",
+                    rstrip: false,
+                },
+            );
+        }
+        let special = &self.special;
+        for id in special
+            .named()
+            .into_iter()
+            .filter_map(|(_, id)| id)
+            .chain(special.stop.iter().copied())
+        {
+            if id as usize >= self.tokens.len() {
+                added.entry(id).or_insert(AddedToken {
+                    content: None,
+                    token_type: None,
+                    special: true,
+                    lstrip: false,
+                    rstrip: false,
+                });
+            }
+        }
+        added
+    }
+
+    /// The raw bytes a token decodes to, with control tokens rendered per the policy
+    pub fn token_bytes(&self, id: u32, policy: &SpecialTokenPolicy) -> Vec<u8> {
+        let Some(text) = self.token(id) else {
+            return Vec::new();
+        };
+        let special = || match policy {
+            SpecialTokenPolicy::Drop => Vec::new(),
+            SpecialTokenPolicy::Literal => text.as_bytes().to_vec(),
+            SpecialTokenPolicy::Placeholder(placeholder) => {
+                placeholder.replace("{id}", &id.to_string()).into_bytes()
+            }
+        };
+        match self.token_type(id) {
+            TokenType::Control => special(),
+            TokenType::Unused => Vec::new(),
+            TokenType::Unknown => match self.model {
+                TokenizerModel::Llama | TokenizerModel::Bert | TokenizerModel::T5 => {
+                    "\u{2585}".as_bytes().to_vec()
+                }
+                _ => special(),
+            },
+            TokenType::Byte => match parse_byte_token(text) {
+                Some(b) => vec![b],
+                None => text.as_bytes().to_vec(),
+            },
+            TokenType::UserDefined => text.as_bytes().to_vec(),
+            TokenType::Normal | TokenType::Undefined => match self.model {
+                TokenizerModel::Gpt2 => text
+                    .chars()
+                    .map(|c| unicode_to_byte(c).unwrap_or(b'?'))
+                    .collect(),
+                TokenizerModel::Rwkv => rwkv::unescape_rwkv(text),
+                _ => text.replace('\u{2581}', " ").into_bytes(),
+            },
+        }
+    }
+
+    /// A decoder that turns ids into text incrementally
+    pub fn decoder(&self, policy: SpecialTokenPolicy) -> StreamDecoder<'_> {
+        StreamDecoder {
+            vocab: self,
+            policy,
+            pending: Vec::new(),
+            at_start: true,
+        }
+    }
+
+    /// Decode a sequence of ids into text, rendering control tokens per the policy
+    pub fn detokenize(&self, ids: &[u32], policy: SpecialTokenPolicy) -> String {
+        let mut decoder = self.decoder(policy);
+        let mut text = String::new();
+        for &id in ids {
+            text.push_str(&decoder.push(id));
+        }
+        text.push_str(&decoder.finish());
+        text
+    }
+
+    /// Ids that end generation: eos, eot, eom, the extra stop tokens, and control tokens known to
+    /// end a turn such as `<|im_end|>`, sorted and deduplicated
+    pub fn stop_token_ids(&self) -> Vec<u32> {
+        const END_OF_TURN: [&str; 8] = [
+            "<|eot_id|>",
+            "<|eom_id|>",
+            "<|im_end|>",
+            "<|end|>",
+            "<end_of_turn>",
+            "<|endoftext|>",
+            "<EOT>",
+            "<\u{ff5c}end\u{2581}of\u{2581}sentence\u{ff5c}>",
+        ];
+        let special = &self.special;
+        let mut ids: Vec<u32> = [special.eos, special.eot, special.eom]
+            .into_iter()
+            .flatten()
+            .chain(special.stop.iter().copied())
+            .chain(END_OF_TURN.iter().filter_map(|&text| {
+                self.id(text)
+                    .filter(|&id| self.token_type(id) == TokenType::Control)
+            }))
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// Encode text into token ids
+    ///
+    /// SentencePiece BPE (`llama`), byte-level BPE (`gpt2`) and RWKV world (`rwkv`) vocabularies
+    /// can be encoded.
+    pub fn encode(&self, text: &str) -> Result<Vec<u32>, String> {
+        match self.model {
+            TokenizerModel::Llama => self.encode_spm(text),
+            TokenizerModel::Gpt2 => self.encode_bpe(text),
+            TokenizerModel::Rwkv => self.encode_rwkv(text),
+            _ => Err(format!(
+                "encoding is not supported for the {} tokenizer",
+                self.model.name()
+            )),
+        }
+    }
+
+    /// Decode a sequence of ids into text, keeping control tokens literally or dropping them
+    pub fn decode(&self, ids: &[u32], keep_special: bool) -> String {
+        let policy = if keep_special {
+            SpecialTokenPolicy::Literal
+        } else {
+            SpecialTokenPolicy::Drop
+        };
+        self.detokenize(ids, policy)
+    }
+}
+
+/// Common interface of the tokenizers
+pub trait Tokenizer {
+    /// The vocabulary the tokenizer works on
+    fn vocab(&self) -> &Vocab;
+
+    /// Encode text into token ids
+    fn encode(&self, text: &str) -> Result<Vec<u32>, String>;
+
+    /// Decode token ids into text, keeping control tokens literally or dropping them
+    fn decode(&self, ids: &[u32], keep_special: bool) -> String {
+        self.vocab().decode(ids, keep_special)
+    }
+
+    /// Number of tokens the text encodes to, not counting bos or eos
+    fn count(&self, text: &str) -> Result<usize, String> {
+        self.encode(text).map(|ids| ids.len())
+    }
+
+    /// Number of tokens each of the texts encodes to
+    fn count_batch(&self, texts: &[&str]) -> Result<Vec<usize>, String> {
+        texts.iter().map(|text| self.count(text)).collect()
+    }
+
+    /// Check that token texts survive encoding, and that the vocabulary matches the file's
+    /// embedding tensor and special ids
+    fn self_test(&self, file: &GGUFFile) -> SelfTestReport {
+        check::self_test(self, file)
+    }
+}
+
+impl Tokenizer for Vocab {
+    fn vocab(&self) -> &Vocab {
+        self
+    }
+
+    fn encode(&self, text: &str) -> Result<Vec<u32>, String> {
+        Vocab::encode(self, text)
+    }
+}
+
+/// The `tokenizer.ggml.*` key-value set describing a tokenizer
+#[derive(Debug, Clone)]
+pub struct TokenizerMetadata {
+    pub vocab: Vocab,
+    pub add_bos_token: Option<bool>,
+    pub add_eos_token: Option<bool>,
+}
+
+impl TokenizerMetadata {
+    /// Read the tokenizer keys of a header
+    pub fn from_header(header: &GGUFHeader) -> Result<Self, String> {
+        Ok(TokenizerMetadata {
+            vocab: Vocab::from_header(header)?,
+            add_bos_token: header
+                .get("tokenizer.ggml.add_bos_token")
+                .and_then(GGUFMetadataValue::as_bool),
+            add_eos_token: header
+                .get("tokenizer.ggml.add_eos_token")
+                .and_then(GGUFMetadataValue::as_bool),
+        })
+    }
+
+    /// The metadata entries, ready to be written into a GGUF header
+    pub fn to_metadata(&self) -> Vec<GGUFMetadata> {
+        let vocab = &self.vocab;
+        let strings = |values: &[String]| {
+            GGUFMetadataValue::Array(GGUFMetadataArrayValue::packed(
+                GGUfMetadataValueType::String,
+                ArrayValues::String(values.iter().map(String::as_str).collect()),
+            ))
+        };
+        let mut metadata = vec![
+            GGUFMetadata::new(
+                "tokenizer.ggml.model",
+                GGUFMetadataValue::String(vocab.model.name().to_string()),
+            ),
+            GGUFMetadata::new("tokenizer.ggml.tokens", strings(&vocab.tokens)),
+        ];
+        if !vocab.scores.is_empty() {
+            metadata.push(GGUFMetadata::new(
+                "tokenizer.ggml.scores",
+                GGUFMetadataValue::Array(GGUFMetadataArrayValue::packed(
+                    GGUfMetadataValueType::Float32,
+                    ArrayValues::Float32(vocab.scores.clone()),
+                )),
+            ));
+        }
+        if !vocab.token_types.is_empty() {
+            metadata.push(GGUFMetadata::new(
+                "tokenizer.ggml.token_type",
+                GGUFMetadataValue::Array(GGUFMetadataArrayValue::packed(
+                    GGUfMetadataValueType::Int32,
+                    ArrayValues::Int32(vocab.token_types.iter().map(|&t| t as i32).collect()),
+                )),
+            ));
+        }
+        if !vocab.merges.is_empty() {
+            metadata.push(GGUFMetadata::new(
+                "tokenizer.ggml.merges",
+                strings(&vocab.merges),
+            ));
+        }
+        if let Some(pre) = &vocab.pre {
+            metadata.push(GGUFMetadata::new(
+                "tokenizer.ggml.pre",
+                GGUFMetadataValue::String(pre.clone()),
+            ));
+        }
+        let special = &vocab.special;
+        for (key, id) in [
+            ("tokenizer.ggml.bos_token_id", special.bos),
+            ("tokenizer.ggml.eos_token_id", special.eos),
+            ("tokenizer.ggml.unknown_token_id", special.unk),
+            ("tokenizer.ggml.seperator_token_id", special.sep),
+            ("tokenizer.ggml.padding_token_id", special.pad),
+            ("tokenizer.ggml.cls_token_id", special.cls),
+            ("tokenizer.ggml.mask_token_id", special.mask),
+            ("tokenizer.ggml.eot_token_id", special.eot),
+            ("tokenizer.ggml.eom_token_id", special.eom),
+        ] {
+            if let Some(id) = id {
+                metadata.push(GGUFMetadata::new(key, GGUFMetadataValue::Uint32(id)));
+            }
+        }
+        if !special.stop.is_empty() {
+            metadata.push(GGUFMetadata::new(
+                "tokenizer.ggml.stop_token_ids",
+                GGUFMetadataValue::Array(GGUFMetadataArrayValue::packed(
+                    GGUfMetadataValueType::Uint32,
+                    ArrayValues::Uint32(special.stop.clone()),
+                )),
+            ));
+        }
+        for (key, flag) in [
+            ("tokenizer.ggml.add_bos_token", self.add_bos_token),
+            ("tokenizer.ggml.add_eos_token", self.add_eos_token),
+        ] {
+            if let Some(flag) = flag {
+                metadata.push(GGUFMetadata::new(key, GGUFMetadataValue::Bool(flag)));
+            }
+        }
+        if vocab.model == TokenizerModel::Llama {
+            metadata.push(GGUFMetadata::new(
+                "tokenizer.ggml.add_space_prefix",
+                GGUFMetadataValue::Bool(vocab.add_space_prefix),
+            ));
+        }
+        metadata
+    }
+}
+
+/// Incremental decoder assembling UTF-8 sequences split across tokens
+pub struct StreamDecoder<'a> {
+    vocab: &'a Vocab,
+    policy: SpecialTokenPolicy,
+    pending: Vec<u8>,
+    at_start: bool,
+}
+
+impl StreamDecoder<'_> {
+    /// Feed one id, returning the text that became complete
+    pub fn push(&mut self, id: u32) -> String {
+        let mut bytes = self.vocab.token_bytes(id, &self.policy);
+        if self.at_start && !bytes.is_empty() && self.pending.is_empty() {
+            self.at_start = false;
+            if self.vocab.add_space_prefix && bytes[0] == b' ' {
+                bytes.remove(0);
+            }
+        }
+        self.pending.extend_from_slice(&bytes);
+        self.take_complete()
+    }
+
+    /// Flush what is left, replacing incomplete sequences with U+FFFD
+    pub fn finish(mut self) -> String {
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        text
+    }
+
+    /// take the longest prefix of pending bytes that cannot change anymore
+    fn take_complete(&mut self) -> String {
+        let mut text = String::new();
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(s) => {
+                    text.push_str(s);
+                    self.pending.clear();
+                    return text;
+                }
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    text.push_str(std::str::from_utf8(&self.pending[..valid]).unwrap());
+                    match e.error_len() {
+                        // an incomplete sequence at the end, wait for more bytes
+                        None => {
+                            self.pending.drain(..valid);
+                            return text;
+                        }
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            self.pending.drain(..valid + len);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// parse a byte-fallback token such as `<0x0A>`
+pub(crate) fn parse_byte_token(text: &str) -> Option<u8> {
+    let hex = text.strip_prefix("<0x")?.strip_suffix('>')?;
+    if hex.len() != 2 {
+        return None;
+    }
+    u8::from_str_radix(hex, 16).ok()
+}
+
+/// the byte a GPT-2 byte-level BPE character stands for
+pub(crate) fn unicode_to_byte(c: char) -> Option<u8> {
+    let c = c as u32;
+    match c {
+        0x21..=0x7e | 0xa1..=0xac | 0xae..=0xff => Some(c as u8),
+        256..=288 => Some((c - 256) as u8),
+        289..=322 => Some((c - 289 + 0x7f) as u8),
+        323 => Some(0xad),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vocab(model: TokenizerModel, tokens: &[(&str, TokenType)]) -> Vocab {
+        Vocab {
+            add_space_prefix: model == TokenizerModel::Llama,
+            model,
+            tokens: tokens.iter().map(|(t, _)| t.to_string()).collect(),
+            scores: Vec::new(),
+            token_types: tokens.iter().map(|(_, t)| *t).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decode_byte_fallback_across_tokens() {
+        let v = vocab(
+            TokenizerModel::Llama,
+            &[
+                ("<s>", TokenType::Control),
+                ("\u{2581}caf", TokenType::Normal),
+                ("<0xC3>", TokenType::Byte),
+                ("<0xA9>", TokenType::Byte),
+                ("<0x0A>", TokenType::Byte),
+            ],
+        );
+        assert_eq!(v.decode(&[0, 1, 2, 3, 4], false), "café\n");
+        assert_eq!(v.decode(&[0, 1, 2, 3], true), "<s> café");
+
+        assert_eq!(
+            v.detokenize(
+                &[0, 1],
+                SpecialTokenPolicy::Placeholder("[{id}]".to_string())
+            ),
+            "[0] caf"
+        );
+
+        let mut decoder = v.decoder(SpecialTokenPolicy::Drop);
+        assert_eq!(decoder.push(1), "caf");
+        assert_eq!(decoder.push(2), "");
+        assert_eq!(decoder.push(3), "é");
+        assert_eq!(decoder.push(2), "");
+        assert_eq!(decoder.finish(), "\u{fffd}");
+    }
+
+    #[test]
+    fn added_tokens_include_out_of_range_ids() {
+        let mut v = vocab(
+            TokenizerModel::Llama,
+            &[
+                ("<unk>", TokenType::Unknown),
+                ("a", TokenType::Normal),
+                ("<|tool|>", TokenType::UserDefined),
+            ],
+        );
+        v.special.eos = Some(7);
+        let added = v.added_tokens();
+        assert_eq!(added.keys().copied().collect::<Vec<_>>(), [0, 2, 7]);
+        assert!(added[&0].special);
+        assert!(!added[&2].special);
+        assert_eq!(added[&7].content, None);
+    }
+
+    #[test]
+    fn stop_tokens_include_end_of_turn_controls() {
+        let mut v = vocab(
+            TokenizerModel::Gpt2,
+            &[
+                ("<|begin_of_text|>", TokenType::Control),
+                ("<|end_of_text|>", TokenType::Control),
+                ("<|eom_id|>", TokenType::Control),
+                ("<|eot_id|>", TokenType::Control),
+                ("<|im_end|>", TokenType::Normal),
+            ],
+        );
+        v.special.eos = Some(1);
+        v.special.stop = vec![3];
+        assert_eq!(v.stop_token_ids(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn lookup_by_text_and_prefix() {
+        let mut v = vocab(
+            TokenizerModel::Rwkv,
+            &[
+                ("a", TokenType::Normal),
+                ("ab", TokenType::Normal),
+                ("abc", TokenType::Normal),
+                ("b", TokenType::Normal),
+            ],
+        );
+        assert_eq!(v.id("abc"), Some(2));
+        assert_eq!(v.id("x"), None);
+        assert_eq!(v.longest_prefix(b"abd"), Some((1, 2)));
+        assert_eq!(v.longest_prefix(b"xyz"), None);
+
+        // the indexes follow the tokens once they change
+        v.tokens_mut().push("xy".to_string());
+        assert_eq!(v.id("xy"), Some(4));
+        assert_eq!(v.longest_prefix(b"xyz"), Some((4, 2)));
+        v.model = TokenizerModel::Gpt2;
+        assert_eq!(v.longest_prefix(b"xyz"), Some((4, 2)));
+    }
+
+    #[test]
+    fn decode_byte_level_bpe() {
+        let v = vocab(
+            TokenizerModel::Gpt2,
+            &[
+                ("Hello", TokenType::Normal),
+                ("Ġworld", TokenType::Normal),
+                ("Ċ", TokenType::Normal),
+            ],
+        );
+        assert_eq!(v.decode(&[0, 1, 2], false), "Hello world\n");
+    }
+}
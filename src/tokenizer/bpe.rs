@@ -0,0 +1,126 @@
+//! Byte-level BPE encoding
+use std::collections::HashMap;
+
+use super::{PreTokenizer, Vocab};
+
+impl Vocab {
+    /// The pre-tokenizer regime named by `tokenizer.ggml.pre`
+    pub fn pre_tokenizer(&self) -> PreTokenizer {
+        self.pre
+            .as_deref()
+            .map(PreTokenizer::from_name)
+            .unwrap_or_default()
+    }
+
+    /// rank of each merge pair, built on first use
+    fn merge_ranks(&self) -> &HashMap<(String, String), usize> {
+        self.ranks.get_or_init(|| {
+            self.merges
+                .iter()
+                .enumerate()
+                .filter_map(|(rank, merge)| {
+                    let (left, right) = merge.split_once(' ')?;
+                    Some(((left.to_string(), right.to_string()), rank))
+                })
+                .collect()
+        })
+    }
+
+    /// Encode text with byte-level BPE, splitting it by the pre-tokenizer first
+    ///
+    /// Control token texts in the input are encoded as plain text.
+    pub(crate) fn encode_bpe(&self, text: &str) -> Result<Vec<u32>, String> {
+        let pre = self.pre_tokenizer();
+        let mut ids = Vec::new();
+        for word in pre.split(text)? {
+            let word: String = word.bytes().map(byte_to_unicode).collect();
+            // llama 3 uses whole-word tokens without applying the merges
+            if pre == PreTokenizer::Llama3 {
+                if let Some(id) = self.id(&word) {
+                    ids.push(id);
+                    continue;
+                }
+            }
+            for symbol in self.merge_word(&word) {
+                match self.id(&symbol) {
+                    Some(id) => ids.push(id),
+                    None => {
+                        for c in symbol.chars() {
+                            let id = self.id(c.encode_utf8(&mut [0; 4])).ok_or_else(|| {
+                                format!(
+                                    "no token for byte {:#04x}",
+                                    super::unicode_to_byte(c).unwrap_or(0)
+                                )
+                            })?;
+                            ids.push(id);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// apply the merges to a word, lowest rank first
+    fn merge_word(&self, word: &str) -> Vec<String> {
+        let ranks = self.merge_ranks();
+        let mut symbols: Vec<String> = word.chars().map(String::from).collect();
+        loop {
+            let best = symbols
+                .windows(2)
+                .enumerate()
+                .filter_map(|(i, pair)| {
+                    ranks
+                        .get(&(pair[0].clone(), pair[1].clone()))
+                        .map(|&rank| (rank, i))
+                })
+                .min();
+            let Some((_, i)) = best else {
+                return symbols;
+            };
+            let right = symbols.remove(i + 1);
+            symbols[i].push_str(&right);
+        }
+    }
+}
+
+/// the character GPT-2 byte-level BPE uses for a byte
+pub(crate) fn byte_to_unicode(b: u8) -> char {
+    let c = match b {
+        0x21..=0x7e | 0xa1..=0xac | 0xae..=0xff => b as u32,
+        0x00..=0x20 => 256 + b as u32,
+        0x7f..=0xa0 => 289 + (b - 0x7f) as u32,
+        0xad => 323,
+    };
+    char::from_u32(c).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{Tokenizer, TokenizerModel};
+
+    #[test]
+    fn encode_with_merges() {
+        let tokens = [
+            "H", "e", "l", "o", "Ġ", "w", "r", "d", "He", "ll", "Hell", "Hello", "Ġw",
+        ];
+        let vocab = Vocab {
+            model: TokenizerModel::Gpt2,
+            tokens: tokens.map(String::from).to_vec(),
+            merges: ["H e", "l l", "He ll", "Hell o", "Ġ w"]
+                .map(String::from)
+                .to_vec(),
+            pre: Some("gpt-2".to_string()),
+            ..Default::default()
+        };
+        let ids = vocab.encode_bpe("Hello world").unwrap();
+        assert_eq!(ids, [11, 12, 3, 6, 2, 7]);
+        assert_eq!(vocab.decode(&ids, false), "Hello world");
+        assert_eq!(
+            vocab.count_batch(&["Hello", "Hello world"]).unwrap(),
+            [1, 6]
+        );
+        assert!(vocab.encode_bpe("x").is_err());
+    }
+}
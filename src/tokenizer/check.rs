@@ -0,0 +1,133 @@
+//! Self-consistency checks of a tokenizer
+use super::{SpecialTokenPolicy, TokenType, Tokenizer};
+use crate::GGUFFile;
+
+/// Outcome of [`Tokenizer::self_test`]
+#[derive(serde::Serialize, Debug, Clone, Default, PartialEq)]
+pub struct SelfTestReport {
+    /// Number of tokens whose text was round-tripped.
+    pub checked: usize,
+    /// Ids whose text changes when encoded and decoded again.
+    pub round_trip_failures: Vec<u32>,
+    /// Problems with the vocabulary size, the special ids or the encoder.
+    pub problems: Vec<String>,
+}
+
+impl SelfTestReport {
+    /// Whether every check passed
+    pub fn is_ok(&self) -> bool {
+        self.round_trip_failures.is_empty() && self.problems.is_empty()
+    }
+}
+
+pub(super) fn self_test<T: Tokenizer + ?Sized>(tokenizer: &T, file: &GGUFFile) -> SelfTestReport {
+    let vocab = tokenizer.vocab();
+    let mut report = SelfTestReport::default();
+
+    // ggml stores the embedding as [n_embd, n_vocab]
+    if let Some(embd) = file.tensors.iter().find(|t| t.name == "token_embd.weight") {
+        match embd.dimensions.get(1) {
+            Some(&rows) if rows != vocab.len() as u64 => report.problems.push(format!(
+                "vocabulary has {} tokens but token_embd.weight has {rows} rows",
+                vocab.len()
+            )),
+            Some(_) => {}
+            None => report
+                .problems
+                .push("token_embd.weight has no vocabulary dimension".to_string()),
+        }
+    }
+
+    for (name, id) in vocab.special.named() {
+        if let Some(id) = id.filter(|&id| id as usize >= vocab.len()) {
+            report.problems.push(format!(
+                "{name} token id {id} is out of range for {} tokens",
+                vocab.len()
+            ));
+        }
+    }
+
+    if let Err(e) = tokenizer.encode("") {
+        report.problems.push(format!("round trip skipped: {e}"));
+        return report;
+    }
+    for id in 0..vocab.len() as u32 {
+        if !matches!(
+            vocab.token_type(id),
+            TokenType::Normal | TokenType::Undefined | TokenType::UserDefined
+        ) {
+            continue;
+        }
+        // tokens standing for partial UTF-8 sequences have no text of their own
+        if String::from_utf8(vocab.token_bytes(id, &SpecialTokenPolicy::Drop)).is_err() {
+            continue;
+        }
+        let text = tokenizer.decode(&[id], false);
+        if text.is_empty() {
+            continue;
+        }
+        report.checked += 1;
+        let round_trip = tokenizer
+            .encode(&text)
+            .map(|ids| tokenizer.decode(&ids, false));
+        if round_trip.as_deref() != Ok(text.as_str()) {
+            report.round_trip_failures.push(id);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, tensor};
+    use crate::tokenizer::{SpecialTokens, TokenizerModel, Vocab};
+    use crate::GGMLType;
+
+    #[test]
+    fn self_test_llama_vocab() {
+        let tokens = [
+            "<unk>",
+            "<s>",
+            "</s>",
+            "<0x0A>",
+            "<0xC3>",
+            "<0xA9>",
+            "\u{2581}",
+            "\u{2581}a",
+            "b",
+            "\u{2581}ab",
+        ];
+        let mut token_types = vec![TokenType::Normal; tokens.len()];
+        token_types[..3].copy_from_slice(&[
+            TokenType::Unknown,
+            TokenType::Control,
+            TokenType::Control,
+        ]);
+        token_types[3..6].fill(TokenType::Byte);
+        let vocab = Vocab {
+            model: TokenizerModel::Llama,
+            tokens: tokens.map(String::from).to_vec(),
+            scores: (0..tokens.len()).map(|i| i as f32).collect(),
+            token_types,
+            special: SpecialTokens {
+                bos: Some(1),
+                eos: Some(12),
+                unk: Some(0),
+                ..Default::default()
+            },
+            add_space_prefix: true,
+            ..Default::default()
+        };
+        assert_eq!(vocab.encode("ab é\n").unwrap(), [9, 6, 4, 5, 3]);
+
+        let file = file(
+            header(&[]),
+            vec![tensor("token_embd.weight", &[8, 16], GGMLType::F32, 0)],
+        );
+        let report = vocab.self_test(&file);
+        assert_eq!(report.checked, 3);
+        assert!(report.round_trip_failures.is_empty());
+        assert_eq!(report.problems.len(), 2);
+    }
+}
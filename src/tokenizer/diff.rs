@@ -0,0 +1,144 @@
+//! Comparison of two vocabularies
+use super::{TokenType, Vocab};
+
+/// A token present in only one of the vocabularies
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct TokenEntry {
+    pub id: u32,
+    pub token: String,
+}
+
+/// A token id whose text or type differs
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct RenamedToken {
+    pub id: u32,
+    pub before: String,
+    pub after: String,
+}
+
+/// A token id whose type differs while its text is unchanged
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct RetypedToken {
+    pub id: u32,
+    pub token: String,
+    pub before: TokenType,
+    pub after: TokenType,
+}
+
+/// A special token whose id differs
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct SpecialTokenChange {
+    /// Which special token, e.g. `eos`.
+    pub name: &'static str,
+    pub before: Option<u32>,
+    pub after: Option<u32>,
+}
+
+/// Differences between a base vocabulary and another one
+#[derive(serde::Serialize, Debug, Clone, Default, PartialEq)]
+pub struct VocabDiff {
+    /// Ids only present in the other vocabulary.
+    pub added: Vec<TokenEntry>,
+    /// Ids only present in the base vocabulary.
+    pub removed: Vec<TokenEntry>,
+    pub renamed: Vec<RenamedToken>,
+    pub retyped: Vec<RetypedToken>,
+    pub special: Vec<SpecialTokenChange>,
+    pub model_changed: bool,
+}
+
+impl VocabDiff {
+    /// Whether the vocabularies are identical
+    pub fn is_empty(&self) -> bool {
+        *self == VocabDiff::default()
+    }
+}
+
+impl Vocab {
+    /// Compare against another vocabulary, e.g. a fine-tune's against its base model's
+    pub fn diff(&self, other: &Vocab) -> VocabDiff {
+        let mut diff = VocabDiff {
+            model_changed: self.model != other.model,
+            ..Default::default()
+        };
+        for id in 0..self.len().max(other.len()) as u32 {
+            match (self.token(id), other.token(id)) {
+                (Some(before), Some(after)) if before != after => diff.renamed.push(RenamedToken {
+                    id,
+                    before: before.to_string(),
+                    after: after.to_string(),
+                }),
+                (Some(token), Some(_)) => {
+                    let (before, after) = (self.token_type(id), other.token_type(id));
+                    if before != after {
+                        diff.retyped.push(RetypedToken {
+                            id,
+                            token: token.to_string(),
+                            before,
+                            after,
+                        });
+                    }
+                }
+                (Some(token), None) => diff.removed.push(TokenEntry {
+                    id,
+                    token: token.to_string(),
+                }),
+                (None, Some(token)) => diff.added.push(TokenEntry {
+                    id,
+                    token: token.to_string(),
+                }),
+                (None, None) => {}
+            }
+        }
+        for ((name, before), (_, after)) in
+            self.special.named().into_iter().zip(other.special.named())
+        {
+            if before != after {
+                diff.special.push(SpecialTokenChange {
+                    name,
+                    before,
+                    after,
+                });
+            }
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::SpecialTokens;
+
+    #[test]
+    fn diff_extended_vocab() {
+        let base = Vocab {
+            tokens: ["<s>", "a", "b"].map(String::from).to_vec(),
+            special: SpecialTokens {
+                eos: Some(0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let tuned = Vocab {
+            tokens: ["<s>", "a", "c", "<|im_end|>"].map(String::from).to_vec(),
+            special: SpecialTokens {
+                eos: Some(3),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let diff = base.diff(&tuned);
+        assert_eq!(
+            diff.added,
+            [TokenEntry {
+                id: 3,
+                token: "<|im_end|>".to_string()
+            }]
+        );
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.renamed[0].after, "c");
+        assert_eq!(diff.special[0].after, Some(3));
+        assert!(base.diff(&base).is_empty());
+    }
+}
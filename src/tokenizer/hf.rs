@@ -0,0 +1,468 @@
+//! Conversion between GGUF vocabularies and Hugging Face `tokenizer.json`
+use super::{SpecialTokens, TokenType, TokenizerMetadata, TokenizerModel, Vocab};
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+impl Vocab {
+    /// Reconstruct a Hugging Face `tokenizer.json` document from the vocabulary
+    pub fn to_hf_tokenizer_json(&self) -> Result<Value, String> {
+        let unk = self.special.unk.and_then(|id| self.token(id));
+        let (normalizer, pre_tokenizer, decoder, model) = match self.model {
+            TokenizerModel::Llama => (
+                json!({
+                    "type": "Sequence",
+                    "normalizers": [
+                        {"type": "Prepend", "prepend": "\u{2581}"},
+                        {"type": "Replace", "pattern": {"String": " "}, "content": "\u{2581}"},
+                    ]
+                }),
+                Value::Null,
+                json!({
+                    "type": "Sequence",
+                    "decoders": [
+                        {"type": "Replace", "pattern": {"String": "\u{2581}"}, "content": " "},
+                        {"type": "ByteFallback"},
+                        {"type": "Fuse"},
+                        {"type": "Strip", "content": " ", "start": 1, "stop": 0},
+                    ]
+                }),
+                json!({
+                    "type": "BPE",
+                    "dropout": null,
+                    "unk_token": unk,
+                    "continuing_subword_prefix": null,
+                    "end_of_word_suffix": null,
+                    "fuse_unk": true,
+                    "byte_fallback": true,
+                    "vocab": self.hf_vocab(|t| t.to_string()),
+                    "merges": self.hf_merges(),
+                }),
+            ),
+            TokenizerModel::Gpt2 => (
+                Value::Null,
+                json!({
+                    "type": "ByteLevel",
+                    "add_prefix_space": false,
+                    "trim_offsets": true,
+                    "use_regex": true,
+                }),
+                json!({
+                    "type": "ByteLevel",
+                    "add_prefix_space": true,
+                    "trim_offsets": true,
+                    "use_regex": true,
+                }),
+                json!({
+                    "type": "BPE",
+                    "dropout": null,
+                    "unk_token": unk,
+                    "continuing_subword_prefix": null,
+                    "end_of_word_suffix": null,
+                    "fuse_unk": false,
+                    "byte_fallback": false,
+                    "vocab": self.hf_vocab(|t| t.to_string()),
+                    "merges": self.merges,
+                }),
+            ),
+            TokenizerModel::Bert => (
+                json!({
+                    "type": "BertNormalizer",
+                    "clean_text": true,
+                    "handle_chinese_chars": true,
+                    "strip_accents": null,
+                    "lowercase": true,
+                }),
+                json!({"type": "BertPreTokenizer"}),
+                json!({"type": "WordPiece", "prefix": "##", "cleanup": true}),
+                json!({
+                    "type": "WordPiece",
+                    "unk_token": unk,
+                    "continuing_subword_prefix": "##",
+                    "max_input_chars_per_word": 100,
+                    // GGUF marks word starts with U+2581, tokenizers marks continuations with ##
+                    "vocab": self.hf_vocab(|t| match t.strip_prefix('\u{2581}') {
+                        Some(word) => word.to_string(),
+                        None => format!("##{t}"),
+                    }),
+                }),
+            ),
+            TokenizerModel::T5 => (
+                Value::Null,
+                json!({"type": "Metaspace", "replacement": "\u{2581}", "prepend_scheme": "always", "split": true}),
+                json!({"type": "Metaspace", "replacement": "\u{2581}", "prepend_scheme": "always", "split": true}),
+                json!({
+                    "type": "Unigram",
+                    "unk_id": self.special.unk,
+                    "byte_fallback": false,
+                    "vocab": self
+                        .tokens
+                        .iter()
+                        .enumerate()
+                        .map(|(id, t)| json!([t, self.scores.get(id).copied().unwrap_or(0.0)]))
+                        .collect::<Vec<_>>(),
+                }),
+            ),
+            ref other => {
+                return Err(format!(
+                    "cannot export {} tokenizer as tokenizer.json",
+                    other.name()
+                ))
+            }
+        };
+        Ok(json!({
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": self.hf_added_tokens(),
+            "normalizer": normalizer,
+            "pre_tokenizer": pre_tokenizer,
+            "post_processor": null,
+            "decoder": decoder,
+            "model": model,
+        }))
+    }
+
+    /// Write the reconstructed `tokenizer.json` to a file
+    pub fn export_hf_tokenizer_json(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let doc = self.to_hf_tokenizer_json()?;
+        let text = serde_json::to_string_pretty(&doc).map_err(|e| e.to_string())?;
+        std::fs::write(path, text).map_err(|e| e.to_string())
+    }
+
+    /// token to id map, special tokens keep their literal text
+    fn hf_vocab(&self, rename: impl Fn(&str) -> String) -> Map<String, Value> {
+        let mut vocab = Map::new();
+        for (id, token) in self.tokens.iter().enumerate() {
+            let name = match self.token_type(id as u32) {
+                TokenType::Normal | TokenType::Undefined => rename(token),
+                _ => token.clone(),
+            };
+            vocab.entry(name).or_insert_with(|| json!(id));
+        }
+        vocab
+    }
+
+    /// tokens that tokenizers handles outside of the model
+    fn hf_added_tokens(&self) -> Vec<Value> {
+        self.added_tokens()
+            .into_iter()
+            .filter_map(|(id, token)| {
+                Some(json!({
+                    "id": id,
+                    "content": token.content?,
+                    "single_word": false,
+                    "lstrip": token.lstrip,
+                    "rstrip": token.rstrip,
+                    "normalized": !token.special,
+                    "special": token.special,
+                }))
+            })
+            .collect()
+    }
+
+    /// the stored merges, or merges recovered from scores for SentencePiece files
+    fn hf_merges(&self) -> Vec<String> {
+        if !self.merges.is_empty() {
+            return self.merges.clone();
+        }
+        let score = |id: usize| self.scores.get(id).copied().unwrap_or(0.0);
+        let mut merges = Vec::new();
+        for (id, token) in self.tokens.iter().enumerate() {
+            if self.token_type(id as u32) != TokenType::Normal {
+                continue;
+            }
+            for (split, _) in token.char_indices().skip(1) {
+                let (left, right) = token.split_at(split);
+                if let (Some(l), Some(r)) = (self.id(left), self.id(right)) {
+                    merges.push((score(id), id, l as usize, r as usize));
+                }
+            }
+        }
+        merges.sort_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then((a.1, a.2, a.3).cmp(&(b.1, b.2, b.3)))
+        });
+        merges
+            .into_iter()
+            .map(|(_, _, l, r)| format!("{} {}", self.tokens[l], self.tokens[r]))
+            .collect()
+    }
+}
+
+impl TokenizerMetadata {
+    /// Build the tokenizer keys from a Hugging Face `tokenizer.json` file
+    pub fn from_hf_json(path: impl AsRef<Path>) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let doc: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        Self::from_hf_json_value(&doc)
+    }
+
+    /// Build the tokenizer keys from a parsed `tokenizer.json` document
+    pub fn from_hf_json_value(doc: &Value) -> Result<Self, String> {
+        let model = &doc["model"];
+        let added: Vec<(u32, &str, bool)> = doc["added_tokens"]
+            .as_array()
+            .map(|tokens| {
+                tokens
+                    .iter()
+                    .filter_map(|t| {
+                        let id = u32::try_from(t["id"].as_u64()?).ok()?;
+                        Some((
+                            id,
+                            t["content"].as_str()?,
+                            t["special"].as_bool() == Some(true),
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // collect (token, score) pairs indexed by id
+        let mut entries: Vec<Option<(String, f32)>> = Vec::new();
+        let mut set = |id: usize, token: String, score: f32| {
+            if entries.len() <= id {
+                entries.resize(id + 1, None);
+            }
+            entries[id] = Some((token, score));
+        };
+        let kind = model["type"].as_str().unwrap_or("BPE");
+        let byte_fallback = model["byte_fallback"].as_bool() == Some(true);
+        let tokenizer_model = match kind {
+            "BPE" if byte_fallback => TokenizerModel::Llama,
+            "BPE" => TokenizerModel::Gpt2,
+            "WordPiece" => TokenizerModel::Bert,
+            "Unigram" => TokenizerModel::T5,
+            other => return Err(format!("unsupported tokenizer.json model type {other}")),
+        };
+        match kind {
+            "Unigram" => {
+                let vocab = model["vocab"]
+                    .as_array()
+                    .ok_or("tokenizer.json model has no vocab")?;
+                for (id, entry) in vocab.iter().enumerate() {
+                    let token = entry[0].as_str().ok_or("invalid unigram vocab entry")?;
+                    let score = entry[1].as_f64().unwrap_or(0.0) as f32;
+                    set(id, token.to_string(), score);
+                }
+            }
+            _ => {
+                let vocab = model["vocab"]
+                    .as_object()
+                    .ok_or("tokenizer.json model has no vocab")?;
+                let prefix = model["continuing_subword_prefix"].as_str().unwrap_or("##");
+                for (token, id) in vocab {
+                    let id = id.as_u64().ok_or("invalid vocab id")? as usize;
+                    let token = match tokenizer_model {
+                        TokenizerModel::Bert => match token.strip_prefix(prefix) {
+                            Some(piece) => piece.to_string(),
+                            None if added.iter().any(|a| a.1 == token) => token.clone(),
+                            None => format!("\u{2581}{token}"),
+                        },
+                        _ => token.clone(),
+                    };
+                    set(id, token, -(id as f32));
+                }
+            }
+        }
+        for &(id, content, _) in &added {
+            set(id as usize, content.to_string(), 0.0);
+        }
+
+        let unk_token = match kind {
+            "Unigram" => model["unk_id"]
+                .as_u64()
+                .and_then(|id| entries.get(id as usize)?.as_ref())
+                .map(|(t, _)| t.clone()),
+            _ => model["unk_token"].as_str().map(str::to_string),
+        };
+        let mut tokens = Vec::with_capacity(entries.len());
+        let mut scores = Vec::with_capacity(entries.len());
+        let mut token_types = Vec::with_capacity(entries.len());
+        for (id, entry) in entries.into_iter().enumerate() {
+            let (token, score) = entry.unwrap_or_else(|| (format!("[PAD{id}]"), 0.0));
+            let token_type = match added.iter().find(|a| a.0 as usize == id) {
+                _ if unk_token.as_deref() == Some(token.as_str()) => TokenType::Unknown,
+                Some((_, _, true)) => TokenType::Control,
+                Some((_, _, false)) => TokenType::UserDefined,
+                None if token.starts_with("[PAD") && token.ends_with(']') => TokenType::Unused,
+                None if byte_fallback && super::parse_byte_token(&token).is_some() => {
+                    TokenType::Byte
+                }
+                None => TokenType::Normal,
+            };
+            tokens.push(token);
+            scores.push(score);
+            token_types.push(token_type);
+        }
+
+        let merges = model["merges"]
+            .as_array()
+            .map(|merges| {
+                merges
+                    .iter()
+                    .filter_map(|m| match m {
+                        Value::String(s) => Some(s.clone()),
+                        Value::Array(pair) => Some(format!(
+                            "{} {}",
+                            pair.first()?.as_str()?,
+                            pair.get(1)?.as_str()?
+                        )),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let id_of = |name: &str| tokens.iter().position(|t| t == name).map(|id| id as u32);
+        let find = |names: &[&str]| names.iter().find_map(|n| id_of(n));
+        let (template_bos, template_eos) = template_special_tokens(&doc["post_processor"]);
+        let mut special = SpecialTokens {
+            bos: template_bos.as_deref().and_then(id_of).or_else(|| {
+                find(&[
+                    "<s>",
+                    "<|begin_of_text|>",
+                    "<bos>",
+                    "[CLS]",
+                    "<|startoftext|>",
+                ])
+            }),
+            eos: template_eos
+                .as_deref()
+                .and_then(id_of)
+                .or_else(|| find(&["</s>", "<|end_of_text|>", "<eos>", "<|endoftext|>", "[SEP]"])),
+            unk: unk_token.as_deref().and_then(id_of),
+            sep: None,
+            pad: find(&["<pad>", "[PAD]", "<|pad|>"]),
+            cls: None,
+            mask: find(&["<mask>", "[MASK]"]),
+            eot: find(&["<|eot_id|>", "<|im_end|>", "<end_of_turn>"]),
+            eom: id_of("<|eom_id|>"),
+            stop: Vec::new(),
+        };
+        if tokenizer_model == TokenizerModel::Bert {
+            special.sep = id_of("[SEP]");
+            special.cls = id_of("[CLS]");
+        }
+        let normalizer = doc["normalizer"].to_string();
+        let add_space_prefix = tokenizer_model == TokenizerModel::Llama
+            && (normalizer.contains("\"Prepend\"")
+                || doc["pre_tokenizer"]["type"] == "Metaspace"
+                    && doc["pre_tokenizer"]["prepend_scheme"] != "never");
+        Ok(TokenizerMetadata {
+            vocab: Vocab {
+                model: tokenizer_model,
+                tokens,
+                scores,
+                token_types,
+                merges,
+                special,
+                add_space_prefix,
+                ..Default::default()
+            },
+            add_bos_token: Some(template_bos.is_some()),
+            add_eos_token: Some(template_eos.is_some()),
+        })
+    }
+}
+
+/// the special tokens a TemplateProcessing post-processor puts before and after a sequence
+fn template_special_tokens(processor: &Value) -> (Option<String>, Option<String>) {
+    if let Some(processors) = processor["processors"].as_array() {
+        return processors
+            .iter()
+            .map(template_special_tokens)
+            .find(|(bos, eos)| bos.is_some() || eos.is_some())
+            .unwrap_or_default();
+    }
+    if processor["type"] == "BertProcessing" || processor["type"] == "RobertaProcessing" {
+        return (
+            processor["cls"][0].as_str().map(str::to_string),
+            processor["sep"][0].as_str().map(str::to_string),
+        );
+    }
+    let Some(single) = processor["single"].as_array() else {
+        return (None, None);
+    };
+    let mut seen_sequence = false;
+    let (mut bos, mut eos) = (None, None);
+    for piece in single {
+        if piece.get("Sequence").is_some() {
+            seen_sequence = true;
+        } else if let Some(id) = piece["SpecialToken"]["id"].as_str() {
+            if !seen_sequence && bos.is_none() {
+                bos = Some(id.to_string());
+            } else if seen_sequence && eos.is_none() {
+                eos = Some(id.to_string());
+            }
+        }
+    }
+    (bos, eos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::SpecialTokens;
+
+    #[test]
+    fn export_recovers_sentencepiece_merges() {
+        let vocab = Vocab {
+            model: TokenizerModel::Llama,
+            tokens: ["<unk>", "a", "b", "ab", "\u{2581}ab"]
+                .map(String::from)
+                .to_vec(),
+            scores: vec![0.0, -1.0, -2.0, -0.5, -0.1],
+            token_types: vec![
+                TokenType::Unknown,
+                TokenType::Normal,
+                TokenType::Normal,
+                TokenType::Normal,
+                TokenType::Normal,
+            ],
+            merges: Vec::new(),
+            special: SpecialTokens {
+                unk: Some(0),
+                ..Default::default()
+            },
+            add_space_prefix: true,
+            ..Default::default()
+        };
+        let doc = vocab.to_hf_tokenizer_json().unwrap();
+        assert_eq!(doc["model"]["type"], "BPE");
+        assert_eq!(doc["model"]["unk_token"], "<unk>");
+        assert_eq!(doc["model"]["vocab"]["ab"], 3);
+        assert_eq!(doc["model"]["merges"], json!(["a b"]));
+        assert_eq!(doc["added_tokens"][0]["content"], "<unk>");
+    }
+
+    #[test]
+    fn import_byte_level_bpe() {
+        let doc = json!({
+            "added_tokens": [{"id": 3, "content": "<|endoftext|>", "special": true}],
+            "post_processor": {
+                "type": "TemplateProcessing",
+                "single": [{"Sequence": {"id": "A", "type_id": 0}}, {"SpecialToken": {"id": "<|endoftext|>", "type_id": 0}}],
+            },
+            "model": {
+                "type": "BPE",
+                "vocab": {"a": 0, "b": 1, "ab": 2, "<|endoftext|>": 3},
+                "merges": [["a", "b"]],
+            }
+        });
+        let tokenizer = TokenizerMetadata::from_hf_json_value(&doc).unwrap();
+        let vocab = &tokenizer.vocab;
+        assert_eq!(vocab.model, TokenizerModel::Gpt2);
+        assert_eq!(vocab.tokens, ["a", "b", "ab", "<|endoftext|>"]);
+        assert_eq!(vocab.token_type(3), TokenType::Control);
+        assert_eq!(vocab.merges, ["a b"]);
+        assert_eq!(vocab.special.eos, Some(3));
+        assert_eq!(tokenizer.add_eos_token, Some(true));
+
+        let metadata = tokenizer.to_metadata();
+        assert_eq!(metadata[0].key, "tokenizer.ggml.model");
+        assert!(metadata
+            .iter()
+            .any(|m| m.key == "tokenizer.ggml.eos_token_id"
+                && m.value == crate::GGUFMetadataValue::Uint32(3)));
+    }
+}
@@ -0,0 +1,426 @@
+//! Pre-tokenizer regimes named by `tokenizer.ggml.pre`
+//!
+//! BPE encoders split text into words with a regex before merging, and the regex differs between
+//! model families. The regexes used by llama.cpp are implemented here as hand-written matchers;
+//! letters and digits are classified by the Unicode `Alphabetic` and `Numeric` properties.
+use std::ops::Range;
+
+/// Pre-tokenizer regime as named by `tokenizer.ggml.pre`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PreTokenizer {
+    /// The fallback splitting, used when `pre` is missing or `default`.
+    #[default]
+    Default,
+    /// Llama 3 (`llama-bpe`), also used by dbrx, smaug and chatglm.
+    Llama3,
+    /// DeepSeek LLM (`deepseek-llm`).
+    DeepseekLlm,
+    /// DeepSeek Coder (`deepseek-coder`).
+    DeepseekCoder,
+    /// Falcon (`falcon`).
+    Falcon,
+    /// GPT-2 (`gpt-2`), also used by mpt, olmo, jais and phi-2.
+    Gpt2,
+    /// GPT-2 with single digits (`starcoder`), also used by refact, command-r and smollm.
+    Starcoder,
+    /// Qwen2 (`qwen2`), like Llama 3 with single digits.
+    Qwen2,
+    /// Any other name.
+    Other(String),
+}
+
+impl PreTokenizer {
+    /// Parse the value of `tokenizer.ggml.pre`
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "default" => Self::Default,
+            "llama3" | "llama-v3" | "llama-bpe" | "falcon3" | "dbrx" | "smaug-bpe"
+            | "chatglm-bpe" => Self::Llama3,
+            "deepseek-llm" => Self::DeepseekLlm,
+            "deepseek-coder" => Self::DeepseekCoder,
+            "falcon" => Self::Falcon,
+            "gpt-2" | "phi-2" | "mpt" | "olmo" | "jais" | "roberta-bpe" | "gigachat"
+            | "jina-es" | "jina-de" | "jina-v1-en" | "jina-v2-es" | "jina-v2-de"
+            | "jina-v2-code" => Self::Gpt2,
+            "starcoder" | "refact" | "command-r" | "smollm" | "codeshell" | "exaone"
+            | "minerva-7b" => Self::Starcoder,
+            "qwen2" | "stablelm2" | "deepseek-r1-qwen" | "megrez" => Self::Qwen2,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// The canonical name of the regime
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Default => "default",
+            Self::Llama3 => "llama-bpe",
+            Self::DeepseekLlm => "deepseek-llm",
+            Self::DeepseekCoder => "deepseek-coder",
+            Self::Falcon => "falcon",
+            Self::Gpt2 => "gpt-2",
+            Self::Starcoder => "starcoder",
+            Self::Qwen2 => "qwen2",
+            Self::Other(name) => name,
+        }
+    }
+
+    /// Split text into the words the BPE merges are applied to
+    pub fn split<'t>(&self, text: &'t str) -> Result<Vec<&'t str>, String> {
+        let patterns: &[&[Rule]] = match self {
+            Self::Default => &[
+                &[run(None, Class::Punct("$+<=>^~|"), 1, MANY, None)],
+                GPT2,
+                &[run(None, Class::Digit, 1, MANY, None)],
+                THREE_DIGITS,
+            ],
+            Self::Llama3 => &[LLAMA3],
+            Self::DeepseekLlm => &[
+                NEWLINE,
+                &[run(
+                    Some(Class::Whitespace),
+                    Class::CasedLetter,
+                    1,
+                    MANY,
+                    None,
+                )],
+                &[run(
+                    Some(Class::Whitespace),
+                    Class::DeepseekSymbol,
+                    1,
+                    MANY,
+                    None,
+                )],
+                &[Rule::FinalWhitespace],
+                CJK,
+                &[run(None, Class::Digit, 1, MANY, None)],
+            ],
+            Self::DeepseekCoder => &[
+                NEWLINE,
+                &[run(Some(Class::Whitespace), Class::Letter, 1, MANY, None)],
+                &[run(
+                    Some(Class::Whitespace),
+                    Class::Punct(""),
+                    1,
+                    MANY,
+                    None,
+                )],
+                CJK,
+                SINGLE_DIGIT,
+            ],
+            Self::Falcon => &[
+                &[run(None, Class::Punct("$+<=>^~|`"), 1, MANY, None)],
+                GPT2,
+                THREE_DIGITS,
+            ],
+            Self::Gpt2 => &[GPT2],
+            Self::Starcoder => &[SINGLE_DIGIT, GPT2],
+            Self::Qwen2 => &[QWEN2],
+            Self::Other(name) => return Err(format!("unsupported pre-tokenizer {name}")),
+        };
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let mut pieces = vec![Range {
+            start: 0,
+            end: chars.len(),
+        }];
+        for rules in patterns {
+            let mut split = Vec::with_capacity(pieces.len());
+            for piece in pieces {
+                split_piece(&chars[piece.clone()], rules, piece.start, &mut split);
+            }
+            pieces = split;
+        }
+        let offset = |i: usize| chars.get(i).map_or(text.len(), |c| c.0);
+        Ok(pieces
+            .into_iter()
+            .map(|p| &text[offset(p.start)..offset(p.end)])
+            .collect())
+    }
+}
+
+const MANY: usize = usize::MAX;
+
+/// `'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)`
+const GPT2: &[Rule] = &[
+    Rule::Contraction { ignore_case: false },
+    run(Some(Class::Space), Class::Letter, 1, MANY, None),
+    run(Some(Class::Space), Class::Digit, 1, MANY, None),
+    run(Some(Class::Space), Class::Other, 1, MANY, None),
+    Rule::TrailingWhitespace,
+];
+
+/// `(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+`
+const LLAMA3: &[Rule] = &[
+    Rule::Contraction { ignore_case: true },
+    run(Some(Class::NotNewline), Class::Letter, 1, MANY, None),
+    run(None, Class::Digit, 1, 3, None),
+    run(
+        Some(Class::Space),
+        Class::Other,
+        1,
+        MANY,
+        Some(Class::Newline),
+    ),
+    Rule::LineBreaks,
+    Rule::TrailingWhitespace,
+    run(None, Class::Whitespace, 1, MANY, None),
+];
+
+/// like [`LLAMA3`] with `\p{N}` instead of `\p{N}{1,3}`
+const QWEN2: &[Rule] = &[
+    Rule::Contraction { ignore_case: true },
+    run(Some(Class::NotNewline), Class::Letter, 1, MANY, None),
+    run(None, Class::Digit, 1, 1, None),
+    run(
+        Some(Class::Space),
+        Class::Other,
+        1,
+        MANY,
+        Some(Class::Newline),
+    ),
+    Rule::LineBreaks,
+    Rule::TrailingWhitespace,
+    run(None, Class::Whitespace, 1, MANY, None),
+];
+
+const NEWLINE: &[Rule] = &[run(None, Class::Newline, 1, 1, None)];
+const SINGLE_DIGIT: &[Rule] = &[run(None, Class::Digit, 1, 1, None)];
+const THREE_DIGITS: &[Rule] = &[run(None, Class::AsciiDigit, 3, 3, None)];
+const CJK: &[Rule] = &[run(None, Class::Cjk, 1, MANY, None)];
+
+/// A set of characters
+#[derive(Debug, Clone, Copy)]
+enum Class {
+    /// ` `
+    Space,
+    /// `\s`
+    Whitespace,
+    /// `[\r\n]`
+    Newline,
+    /// `\p{L}`
+    Letter,
+    /// letters with case, approximating DeepSeek's explicit letter ranges
+    CasedLetter,
+    /// `\p{N}`
+    Digit,
+    /// `[0-9]`
+    AsciiDigit,
+    /// `[^\s\p{L}\p{N}]`
+    Other,
+    /// `[^\r\n\p{L}\p{N}]`
+    NotNewline,
+    /// `\p{P}` plus the given characters
+    Punct(&'static str),
+    /// `[!-/:-~！-／：-～‘-‟　-。]`
+    DeepseekSymbol,
+    /// `[一-龥ࠀ-一가-퟿]`
+    Cjk,
+}
+
+impl Class {
+    fn contains(self, c: char) -> bool {
+        match self {
+            Class::Space => c == ' ',
+            Class::Whitespace => c.is_whitespace(),
+            Class::Newline => c == '\r' || c == '\n',
+            Class::Letter => c.is_alphabetic(),
+            Class::CasedLetter => c.is_lowercase() || c.is_uppercase(),
+            Class::Digit => c.is_numeric(),
+            Class::AsciiDigit => c.is_ascii_digit(),
+            Class::Other => !(c.is_whitespace() || c.is_alphabetic() || c.is_numeric()),
+            Class::NotNewline => !(c == '\r' || c == '\n' || c.is_alphabetic() || c.is_numeric()),
+            Class::Punct(extra) => is_punctuation(c) || extra.contains(c),
+            Class::DeepseekSymbol => matches!(c,
+                '!'..='/' | ':'..='~' | '！'..='／' | '：'..='～' | '‘'..='‟' | '　'..='。'),
+            Class::Cjk => {
+                matches!(c, '\u{0800}'..='\u{9fa5}' | '\u{ac00}'..='\u{d7ff}')
+            }
+        }
+    }
+}
+
+/// approximation of `\p{P}`: ASCII punctuation and the common Unicode punctuation blocks
+fn is_punctuation(c: char) -> bool {
+    if c.is_ascii() {
+        return c.is_ascii_punctuation() && !"$+<=>^`|~".contains(c);
+    }
+    matches!(c,
+        '¡' | '§' | '«' | '¶' | '·' | '»' | '¿'
+        | '\u{2010}'..='\u{2027}'
+        | '\u{2030}'..='\u{2043}'
+        | '\u{2045}'..='\u{2051}'
+        | '\u{2053}'..='\u{205e}'
+        | '\u{3001}'..='\u{3003}'
+        | '\u{3008}'..='\u{3011}'
+        | '\u{3014}'..='\u{301f}'
+        | '\u{ff01}'..='\u{ff03}'
+        | '\u{ff05}'..='\u{ff0a}'
+        | '\u{ff0c}'..='\u{ff0f}'
+        | '\u{ff1a}' | '\u{ff1b}' | '\u{ff1f}' | '\u{ff20}')
+}
+
+/// One alternative of a split regex
+#[derive(Debug, Clone, Copy)]
+enum Rule {
+    /// `'s|'t|'re|'ve|'m|'ll|'d`
+    Contraction { ignore_case: bool },
+    /// `prefix? class{min,max} suffix*`
+    Run {
+        prefix: Option<Class>,
+        class: Class,
+        min: usize,
+        max: usize,
+        suffix: Option<Class>,
+    },
+    /// `\s*[\r\n]+`
+    LineBreaks,
+    /// `\s+(?!\S)`
+    TrailingWhitespace,
+    /// `\s+$`
+    FinalWhitespace,
+}
+
+const fn run(
+    prefix: Option<Class>,
+    class: Class,
+    min: usize,
+    max: usize,
+    suffix: Option<Class>,
+) -> Rule {
+    Rule::Run {
+        prefix,
+        class,
+        min,
+        max,
+        suffix,
+    }
+}
+
+impl Rule {
+    /// length in chars of the match at the start of `text`, 0 if there is none
+    fn matches(self, text: &[char]) -> usize {
+        let count = |from: usize, class: Class, max: usize| {
+            text[from..]
+                .iter()
+                .take(max)
+                .take_while(|&&c| class.contains(c))
+                .count()
+        };
+        match self {
+            Rule::Contraction { ignore_case } => {
+                if text.first() != Some(&'\'') {
+                    return 0;
+                }
+                let next = |i: usize| {
+                    text.get(i).map(|&c| {
+                        if ignore_case {
+                            c.to_ascii_lowercase()
+                        } else {
+                            c
+                        }
+                    })
+                };
+                match (next(1), next(2)) {
+                    (Some('s' | 't' | 'm' | 'd'), _) => 2,
+                    (Some('r' | 'v'), Some('e')) | (Some('l'), Some('l')) => 3,
+                    _ => 0,
+                }
+            }
+            Rule::Run {
+                prefix,
+                class,
+                min,
+                max,
+                suffix,
+            } => {
+                let mut start = match (prefix, text.first()) {
+                    (Some(p), Some(&c)) if p.contains(c) => 1,
+                    _ => 0,
+                };
+                let mut len = count(start, class, max);
+                if len < min && start == 1 {
+                    // the prefix character may belong to the run itself
+                    start = 0;
+                    len = count(0, class, max);
+                }
+                if len < min.max(1) {
+                    return 0;
+                }
+                let end = start + len;
+                match suffix {
+                    Some(suffix) => end + count(end, suffix, MANY),
+                    None => end,
+                }
+            }
+            Rule::LineBreaks => {
+                let spaces = count(0, Class::Whitespace, MANY);
+                text[..spaces]
+                    .iter()
+                    .rposition(|&c| Class::Newline.contains(c))
+                    .map_or(0, |i| i + 1)
+            }
+            Rule::TrailingWhitespace => {
+                let spaces = count(0, Class::Whitespace, MANY);
+                if spaces == text.len() {
+                    spaces
+                } else {
+                    // give back the space in front of the next word
+                    spaces.saturating_sub(1)
+                }
+            }
+            Rule::FinalWhitespace => {
+                let spaces = count(0, Class::Whitespace, MANY);
+                if spaces == text.len() {
+                    spaces
+                } else {
+                    0
+                }
+            }
+        }
+    }
+}
+
+/// split a piece into matches and the stretches between them, which are kept as pieces too
+fn split_piece(text: &[(usize, char)], rules: &[Rule], offset: usize, out: &mut Vec<Range<usize>>) {
+    let chars: Vec<char> = text.iter().map(|c| c.1).collect();
+    let (mut pos, mut gap) = (0, 0);
+    while pos < chars.len() {
+        match rules
+            .iter()
+            .map(|r| r.matches(&chars[pos..]))
+            .find(|&n| n > 0)
+        {
+            Some(n) => {
+                if gap < pos {
+                    out.push(offset + gap..offset + pos);
+                }
+                out.push(offset + pos..offset + pos + n);
+                pos += n;
+                gap = pos;
+            }
+            None => pos += 1,
+        }
+    }
+    if gap < chars.len() {
+        out.push(offset + gap..offset + chars.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_regimes() {
+        let text = "Hello world's  12345\n\n  ok!";
+        assert_eq!(
+            PreTokenizer::Gpt2.split(text).unwrap(),
+            ["Hello", " world", "'s", " ", " 12345", "\n\n ", " ok", "!"]
+        );
+        assert_eq!(
+            PreTokenizer::Llama3.split(text).unwrap(),
+            ["Hello", " world", "'s", " ", " ", "123", "45", "\n\n", " ", " ok", "!"]
+        );
+        assert_eq!(PreTokenizer::Qwen2.split("ab12").unwrap(), ["ab", "1", "2"]);
+        assert!(PreTokenizer::from_name("mystery").split(text).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! RWKV "world" tokenizer
+//!
+//! The world vocabulary stores each token as the escaped body of a Python bytes literal, e.g.
+//! `\n` or `\xe4\xb8`, and encodes by greedily taking the longest token matching the input bytes.
+use super::Vocab;
+
+/// the bytes an escaped world token stands for
+pub(crate) fn unescape_rwkv(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        let Some((&escape, tail)) = rest.split_first() else {
+            bytes.push(b'\\');
+            break;
+        };
+        rest = tail;
+        match escape {
+            b't' => bytes.push(b'\t'),
+            b'n' => bytes.push(b'\n'),
+            b'r' => bytes.push(b'\r'),
+            b'x' => {
+                let hex = rest.get(..2).and_then(|h| std::str::from_utf8(h).ok());
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(value) => {
+                        bytes.push(value);
+                        rest = &rest[2..];
+                    }
+                    None => bytes.extend_from_slice(b"\\x"),
+                }
+            }
+            other => bytes.push(other),
+        }
+    }
+    bytes
+}
+
+impl Vocab {
+    /// Encode text by repeatedly taking the longest matching token
+    pub(crate) fn encode_rwkv(&self, text: &str) -> Result<Vec<u32>, String> {
+        let mut ids = Vec::new();
+        let mut rest = text.as_bytes();
+        while !rest.is_empty() {
+            match self.longest_prefix(rest) {
+                Some((id, len)) => {
+                    ids.push(id);
+                    rest = &rest[len..];
+                }
+                None => {
+                    let unk = self
+                        .special
+                        .unk
+                        .ok_or_else(|| format!("no token for byte {:#04x}", rest[0]))?;
+                    ids.push(unk);
+                    rest = &rest[1..];
+                }
+            }
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{TokenType, TokenizerModel};
+
+    #[test]
+    fn encode_world_tokens() {
+        let tokens = ["<s>", "\\n", "\\xe4\\xb8\\x80", "a", "ab", "\\\\", "\\'"];
+        let mut token_types = vec![TokenType::Normal; tokens.len()];
+        token_types[0] = TokenType::Control;
+        let vocab = Vocab {
+            model: TokenizerModel::Rwkv,
+            tokens: tokens.map(String::from).to_vec(),
+            token_types,
+            ..Default::default()
+        };
+        let ids = vocab.encode("ab\u{4e00}\n\\a'").unwrap();
+        assert_eq!(ids, [4, 2, 1, 5, 3, 6]);
+        assert_eq!(vocab.decode(&ids, false), "ab\u{4e00}\n\\a'");
+        assert!(vocab.encode("z").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! SentencePiece BPE encoding
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use super::Vocab;
+
+/// a run of the input, linked to its neighbours
+#[derive(Clone, Copy)]
+struct Symbol {
+    start: usize,
+    len: usize,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// a candidate merge of two adjacent symbols
+struct Bigram {
+    score: f32,
+    left: usize,
+    right: usize,
+    len: usize,
+}
+
+impl PartialEq for Bigram {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Bigram {}
+
+impl PartialOrd for Bigram {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Bigram {
+    /// highest score first, leftmost on ties
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(other.left.cmp(&self.left))
+    }
+}
+
+impl Vocab {
+    /// Encode text by merging the highest scoring pairs, falling back to byte tokens
+    pub(crate) fn encode_spm(&self, text: &str) -> Result<Vec<u32>, String> {
+        let mut text = text.replace(' ', "\u{2581}");
+        if self.add_space_prefix && !text.is_empty() {
+            text.insert(0, '\u{2581}');
+        }
+        let mut symbols: Vec<Symbol> = text
+            .char_indices()
+            .enumerate()
+            .map(|(i, (start, c))| Symbol {
+                start,
+                len: c.len_utf8(),
+                prev: i.checked_sub(1),
+                next: Some(i + 1),
+            })
+            .collect();
+        if let Some(last) = symbols.last_mut() {
+            last.next = None;
+        }
+
+        let mut queue = BinaryHeap::new();
+        let candidate = |symbols: &[Symbol], left: usize, right: usize| {
+            let (l, r) = (symbols[left], symbols[right]);
+            let len = l.len + r.len;
+            let id = self.id(&text[l.start..l.start + len])?;
+            Some(Bigram {
+                score: self.scores.get(id as usize).copied().unwrap_or(0.0),
+                left,
+                right,
+                len,
+            })
+        };
+        for i in 1..symbols.len() {
+            queue.extend(candidate(&symbols, i - 1, i));
+        }
+        while let Some(bigram) = queue.pop() {
+            let (left, right) = (symbols[bigram.left], symbols[bigram.right]);
+            // skip merges made stale by an earlier one
+            if left.len == 0 || right.len == 0 || left.len + right.len != bigram.len {
+                continue;
+            }
+            symbols[bigram.left].len = bigram.len;
+            symbols[bigram.left].next = right.next;
+            symbols[bigram.right].len = 0;
+            if let Some(next) = right.next {
+                symbols[next].prev = Some(bigram.left);
+                queue.extend(candidate(&symbols, bigram.left, next));
+            }
+            if let Some(prev) = left.prev {
+                queue.extend(candidate(&symbols, prev, bigram.left));
+            }
+        }
+
+        let mut ids = Vec::new();
+        let mut next = if symbols.is_empty() { None } else { Some(0) };
+        while let Some(i) = next {
+            let symbol = symbols[i];
+            let piece = &text[symbol.start..symbol.start + symbol.len];
+            match self.id(piece) {
+                Some(id) => ids.push(id),
+                None => {
+                    for b in piece.bytes() {
+                        let id = self
+                            .id(&format!("<0x{b:02X}>"))
+                            .or(self.special.unk)
+                            .ok_or_else(|| format!("no token for byte {b:#04x}"))?;
+                        ids.push(id);
+                    }
+                }
+            }
+            next = symbol.next;
+        }
+        Ok(ids)
+    }
+}
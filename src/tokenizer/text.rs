@@ -0,0 +1,71 @@
+//! Plain-text `vocab.txt` and `merges.txt` exports
+use std::path::Path;
+
+use super::Vocab;
+
+/// escape the characters that would break the line format
+fn escape(token: &str) -> String {
+    token
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+impl Vocab {
+    /// The token list, one token per line in id order, followed by a tab and its score if the
+    /// file has scores
+    ///
+    /// Newlines, carriage returns and tabs inside tokens are written as `\n`, `\r` and `\t`.
+    pub fn to_vocab_txt(&self) -> String {
+        let mut text = String::new();
+        for (id, token) in self.tokens.iter().enumerate() {
+            text.push_str(&escape(token));
+            if let Some(score) = self.scores.get(id) {
+                text.push('\t');
+                text.push_str(&score.to_string());
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// The merge list in rank order, under the `#version` line of the Hugging Face format
+    pub fn to_merges_txt(&self) -> String {
+        let mut text = "#version: 0.2\n".to_string();
+        for merge in &self.merges {
+            text.push_str(merge);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Write [`Vocab::to_vocab_txt`] to a file
+    pub fn export_vocab_txt(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        std::fs::write(path, self.to_vocab_txt()).map_err(|e| e.to_string())
+    }
+
+    /// Write [`Vocab::to_merges_txt`] to a file, failing if the vocabulary has no merges
+    pub fn export_merges_txt(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        if self.merges.is_empty() {
+            return Err("vocabulary has no merges".to_string());
+        }
+        std::fs::write(path, self.to_merges_txt()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_exports() {
+        let vocab = Vocab {
+            tokens: ["a", "b", "ab", "\n"].map(String::from).to_vec(),
+            scores: vec![0.0, -1.0, -1.5, -2.0],
+            merges: vec!["a b".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(vocab.to_vocab_txt(), "a\t0\nb\t-1\nab\t-1.5\n\\n\t-2\n");
+        assert_eq!(vocab.to_merges_txt(), "#version: 0.2\na b\n");
+    }
+}
@@ -0,0 +1,132 @@
+//! # Tracing
+//!
+//! With the `tracing` feature the crate times the phases of its work, reading and parsing a
+//! header, loading a file and each of its tensors, validating, and hands each [`Span`] to the
+//! [`Subscriber`] set with [`set_subscriber`] as it closes. A service forwards them to the
+//! telemetry it already has, a `tracing` subscriber or its logs:
+//!
+//! ```
+//! gguf::trace::set_subscriber(|span: &gguf::trace::Span| {
+//!     eprintln!("{:indent$}{} {} {:?}", "", span.name, span.detail, span.elapsed,
+//!         indent = span.depth * 2);
+//! })
+//! .unwrap();
+//! ```
+//!
+//! Until a subscriber is set, a span costs the check of a static.
+use std::cell::Cell;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// A phase of the crate's work, closed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span<'a> {
+    /// What was timed: `read_header`, `parse`, `load`, `tensor` or `validate`.
+    pub name: &'static str,
+    /// What it was timed on, such as the name of a tensor; empty for nothing in particular.
+    pub detail: &'a str,
+    pub elapsed: Duration,
+    /// The spans still open around it on its thread, 0 for an outermost one.
+    pub depth: usize,
+}
+
+/// Where the spans go, on the thread that closed them
+pub trait Subscriber: Send + Sync {
+    fn on_close(&self, span: &Span<'_>);
+}
+
+impl<F: Fn(&Span<'_>) + Send + Sync> Subscriber for F {
+    fn on_close(&self, span: &Span<'_>) {
+        self(span)
+    }
+}
+
+static SUBSCRIBER: OnceLock<Box<dyn Subscriber>> = OnceLock::new();
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Send the spans of the whole process to `subscriber`, which can be set once
+pub fn set_subscriber(subscriber: impl Subscriber + 'static) -> Result<(), String> {
+    SUBSCRIBER
+        .set(Box::new(subscriber))
+        .map_err(|_| "a subscriber is already set".to_string())
+}
+
+/// An open span, closed when dropped
+pub(crate) struct Guard {
+    open: Option<(&'static str, String, Instant)>,
+}
+
+/// Open a span, `detail` only formatted if there is a subscriber
+pub(crate) fn span(name: &'static str, detail: impl FnOnce() -> String) -> Guard {
+    let open = SUBSCRIBER.get().map(|_| {
+        DEPTH.with(|d| d.set(d.get() + 1));
+        (name, detail(), Instant::now())
+    });
+    Guard { open }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let (Some((name, detail, start)), Some(subscriber)) = (self.open.take(), SUBSCRIBER.get())
+        else {
+            return;
+        };
+        let depth = DEPTH.with(|d| {
+            d.set(d.get() - 1);
+            d.get()
+        });
+        subscriber.on_close(&Span {
+            name,
+            detail: &detail,
+            elapsed: start.elapsed(),
+            depth,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::Mutex;
+    use std::thread::{self, ThreadId};
+
+    use super::*;
+    use crate::test_util::{file, header};
+    use crate::writer::write_header;
+    use crate::{GGUFFile, ParseOptions};
+
+    #[test]
+    fn spans_nest_and_reach_the_subscriber() {
+        static SPANS: Mutex<Vec<(ThreadId, &str, String, usize)>> = Mutex::new(Vec::new());
+        set_subscriber(|span: &Span| {
+            let id = thread::current().id();
+            let span = (id, span.name, span.detail.to_string(), span.depth);
+            SPANS.lock().unwrap().push(span);
+        })
+        .unwrap();
+        assert!(set_subscriber(|_: &Span| {}).is_err());
+
+        let file = file(header(&[]), Vec::new());
+        let mut buf = Vec::new();
+        write_header(&mut buf, &file).unwrap();
+        GGUFFile::read_from(&mut Cursor::new(&buf), &ParseOptions::default()).unwrap();
+
+        // tests on other threads parse too
+        let id = thread::current().id();
+        let spans: Vec<_> = SPANS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|s| s.0 == id)
+            .map(|s| (s.1, s.2.clone(), s.3))
+            .collect();
+        let len = format!("{} bytes", buf.len());
+        assert_eq!(
+            spans,
+            [("parse", len, 1), ("read_header", String::new(), 0)]
+        );
+    }
+}
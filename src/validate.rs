@@ -0,0 +1,1161 @@
+//! # Spec conformance checks
+//!
+//! [`validate`] walks the raw bytes of a file instead of going through the parser, so it can
+//! keep going past problems the parser rejects and report each of them with its offset.
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::{GGMLType, GGUfMetadataValueType, ParseOptions};
+
+mod naming;
+mod profile;
+mod scan;
+mod schema;
+
+pub use profile::{arch_profile, ArchProfile};
+pub use scan::scan;
+pub use schema::{check_schema, key_schema, KeySchema};
+
+/// How serious a finding is
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A problem found in a file
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Stable identifier of the check, e.g. `duplicate-key`.
+    pub code: &'static str,
+    pub message: String,
+    /// Byte offset in the file the finding refers to.
+    pub offset: Option<u64>,
+    /// Metadata key the finding is about.
+    pub key: Option<String>,
+    /// Tensor the finding is about.
+    pub tensor: Option<String>,
+}
+
+impl Finding {
+    pub(crate) fn with_key(&mut self, key: &str) -> &mut Self {
+        self.key = Some(key.to_string());
+        self
+    }
+
+    pub(crate) fn with_tensor(&mut self, name: &str) -> &mut Self {
+        self.tensor = Some(name.to_string());
+        self
+    }
+}
+
+/// Outcome of [`validate`]
+///
+/// Serializes as `{"format": 1, "valid": .., "max_severity": .., "findings": [..]}`, each
+/// finding having `severity`, `code`, `message`, `offset`, `key` and `tensor`, absent values
+/// being `null`. The format number changes whenever that structure does, so stored reports
+/// can be compared across versions of this crate; new codes may appear without a change.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub findings: Vec<Finding>,
+}
+
+/// Version of the serialized structure of [`ValidationReport`]
+pub const REPORT_FORMAT: u32 = 1;
+
+impl serde::Serialize for ValidationReport {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut s = serializer.serialize_struct("ValidationReport", 4)?;
+        s.serialize_field("format", &REPORT_FORMAT)?;
+        s.serialize_field("valid", &self.is_valid())?;
+        s.serialize_field("max_severity", &self.max_severity())?;
+        s.serialize_field("findings", &self.findings)?;
+        s.end()
+    }
+}
+
+impl ValidationReport {
+    /// Whether there is no error-level finding
+    pub fn is_valid(&self) -> bool {
+        self.max_severity() < Some(Severity::Error)
+    }
+
+    /// The most serious severity found
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.findings.iter().map(|f| f.severity).max()
+    }
+
+    /// The findings with the given severity or worse
+    pub fn at_least(&self, severity: Severity) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(move |f| f.severity >= severity)
+    }
+
+    /// The report as pretty-printed JSON
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        code: &'static str,
+        offset: usize,
+        message: String,
+    ) -> &mut Finding {
+        self.add(severity, code, Some(offset as u64), message)
+    }
+
+    /// a finding about the file as a whole rather than a position in it
+    pub(crate) fn note(
+        &mut self,
+        severity: Severity,
+        code: &'static str,
+        message: String,
+    ) -> &mut Finding {
+        self.add(severity, code, None, message)
+    }
+
+    fn add(
+        &mut self,
+        severity: Severity,
+        code: &'static str,
+        offset: Option<u64>,
+        message: String,
+    ) -> &mut Finding {
+        self.findings.push(Finding {
+            severity,
+            code,
+            message,
+            offset,
+            key: None,
+            tensor: None,
+        });
+        self.findings.last_mut().unwrap()
+    }
+}
+
+/// Known `general.*` keys and the type the spec gives them
+const GENERAL_KEYS: &[(&str, GGUfMetadataValueType)] = &[
+    ("general.architecture", GGUfMetadataValueType::String),
+    (
+        "general.quantization_version",
+        GGUfMetadataValueType::Uint32,
+    ),
+    ("general.alignment", GGUfMetadataValueType::Uint32),
+    ("general.name", GGUfMetadataValueType::String),
+    ("general.file_type", GGUfMetadataValueType::Uint32),
+];
+
+/// Longest tensor name ggml accepts, in bytes
+const MAX_TENSOR_NAME: usize = 64;
+/// Deepest nesting of arrays accepted; the spec allows arrays of arrays but no key uses more
+const MAX_ARRAY_DEPTH: usize = 2;
+/// Suffixes of per-layer keys, whose arrays hold one integer per block
+const PER_LAYER_KEYS: &[&str] = &[
+    ".attention.head_count",
+    ".attention.head_count_kv",
+    ".feed_forward_length",
+];
+/// Tokenizer arrays that hold one item per token
+const PER_TOKEN_KEYS: &[&str] = &[
+    "tokenizer.ggml.tokens",
+    "tokenizer.ggml.scores",
+    "tokenizer.ggml.token_type",
+];
+/// Tensors with one row per token
+const VOCAB_TENSORS: &[&str] = &["token_embd.weight", "output.weight"];
+/// Most dimensions a ggml tensor can have
+const MAX_DIMS: u32 = 4;
+/// Most bytes [`validate_file`] reads
+const MAX_HEADER: usize = 1 << 30;
+
+/// cursor over the raw bytes, `None` meaning the input ended
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    /// whether sizes are 64 bits wide, as in version 2 onwards
+    wide: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: u64) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(usize::try_from(len).ok()?)?;
+        let bytes = self.buf.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn size(&mut self) -> Option<u64> {
+        if self.wide {
+            self.u64()
+        } else {
+            self.u32().map(u64::from)
+        }
+    }
+
+    /// a string, reporting invalid UTF-8 and returning it lossily decoded
+    fn string(&mut self, report: &mut ValidationReport, what: &str) -> Option<String> {
+        let offset = self.pos;
+        let len = self.size()?;
+        let bytes = self.bytes(len)?;
+        match std::str::from_utf8(bytes) {
+            Ok(s) => Some(s.to_string()),
+            Err(e) => {
+                report.push(
+                    Severity::Error,
+                    "utf8",
+                    offset,
+                    format!("{what} is not valid UTF-8: {e}"),
+                );
+                Some(String::from_utf8_lossy(bytes).into_owned())
+            }
+        }
+    }
+}
+
+/// what is remembered of a value for the cross-checks
+pub(crate) enum Value {
+    Uint(u64),
+    Str(String),
+    /// an array with its item type and length
+    Array(GGUfMetadataValueType, u64),
+    Other,
+}
+
+/// a metadata entry as seen by the walker
+pub(crate) struct Entry {
+    pub(crate) value_type: GGUfMetadataValueType,
+    pub(crate) value: Value,
+}
+
+/// size of a fixed-size value type
+fn fixed_size(value_type: GGUfMetadataValueType) -> Option<u64> {
+    use GGUfMetadataValueType::*;
+    Some(match value_type {
+        Uint8 | Int8 | Bool => 1,
+        Uint16 | Int16 => 2,
+        Uint32 | Int32 | Float32 => 4,
+        Uint64 | Int64 | Float64 => 8,
+        String | Array => return None,
+    })
+}
+
+struct Walker<'a> {
+    reader: Reader<'a>,
+    report: ValidationReport,
+    /// set when a problem stops the walk before the input ends
+    stopped: bool,
+    /// length of the whole file, which `reader` may only hold the start of
+    file_len: u64,
+}
+
+impl Walker<'_> {
+    /// read a value type, `None` after reporting an unknown one since nothing after it can be
+    /// located
+    fn value_type(&mut self) -> Option<GGUfMetadataValueType> {
+        let offset = self.reader.pos;
+        let raw = self.reader.u32()?;
+        match GGUfMetadataValueType::try_from(raw) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                self.report.push(Severity::Error, "value-type", offset, e);
+                self.stopped = true;
+                None
+            }
+        }
+    }
+
+    /// read a value, `index` being its position in the innermost array holding it and `depth`
+    /// the number of arrays it is nested in
+    fn value(
+        &mut self,
+        value_type: GGUfMetadataValueType,
+        key: &str,
+        index: Option<u64>,
+        depth: usize,
+    ) -> Option<Value> {
+        let offset = self.reader.pos;
+        match value_type {
+            GGUfMetadataValueType::Bool => {
+                let b = self.reader.bytes(1)?[0];
+                if b > 1 {
+                    self.report
+                        .push(
+                            Severity::Error,
+                            "bool",
+                            offset,
+                            format!("{key} holds the bool value {b}, expected 0 or 1"),
+                        )
+                        .with_key(key);
+                }
+                Some(Value::Other)
+            }
+            GGUfMetadataValueType::Uint32 => self.reader.u32().map(|v| Value::Uint(v.into())),
+            GGUfMetadataValueType::Uint64 => self.reader.u64().map(Value::Uint),
+            GGUfMetadataValueType::String => {
+                let len = self.reader.size()?;
+                let bytes = self.reader.bytes(len)?;
+                Some(Value::Str(check_text(
+                    &mut self.report,
+                    bytes,
+                    offset,
+                    key,
+                    index,
+                )))
+            }
+            GGUfMetadataValueType::Array => {
+                if depth >= MAX_ARRAY_DEPTH {
+                    self.report
+                        .push(
+                            Severity::Error,
+                            "array-depth",
+                            offset,
+                            format!("{key} nests arrays more than {MAX_ARRAY_DEPTH} deep"),
+                        )
+                        .with_key(key);
+                    self.stopped = true;
+                    return None;
+                }
+                let item_type = self.value_type()?;
+                let len = self.reader.size()?;
+                match fixed_size(item_type) {
+                    Some(size) if item_type != GGUfMetadataValueType::Bool => {
+                        self.reader.bytes(len.checked_mul(size)?)?;
+                    }
+                    _ => {
+                        for i in 0..len {
+                            self.value(item_type, key, Some(i), depth + 1)?;
+                        }
+                    }
+                }
+                Some(Value::Array(item_type, len))
+            }
+            other => {
+                self.reader.bytes(fixed_size(other)?)?;
+                Some(Value::Other)
+            }
+        }
+    }
+
+    fn walk(&mut self) -> Option<()> {
+        let magic = self.reader.bytes(4)?;
+        if magic != b"GGUF" {
+            self.report.push(
+                Severity::Error,
+                "magic",
+                0,
+                format!("expected the magic GGUF, found {magic:02x?}"),
+            );
+            return Some(());
+        }
+        let version = self.reader.u32()?;
+        match version {
+            1 => {
+                self.report.push(
+                    Severity::Warning,
+                    "version",
+                    4,
+                    "version 1 is obsolete and uses 32-bit sizes".to_string(),
+                );
+                self.reader.wide = false;
+            }
+            2 | 3 => {}
+            _ => {
+                self.report.push(
+                    Severity::Error,
+                    "version",
+                    4,
+                    format!("unknown version {version}, checking the rest as version 3"),
+                );
+            }
+        }
+        let tensor_count = self.reader.size()?;
+        let kv_count = self.reader.size()?;
+        let remaining = self.file_len - self.reader.pos as u64;
+        if kv_count > remaining || tensor_count > remaining {
+            self.report.push(
+                Severity::Error,
+                "counts",
+                8,
+                format!(
+                    "{kv_count} metadata entries and {tensor_count} tensors cannot fit in the \
+                     {remaining} remaining bytes"
+                ),
+            );
+            return Some(());
+        }
+
+        let mut keys = HashMap::new();
+        for _ in 0..kv_count {
+            let offset = self.reader.pos;
+            let key = self.reader.string(&mut self.report, "metadata key")?;
+            check_key_name(&mut self.report, &key, offset);
+            let value_type = self.value_type()?;
+            let value = self.value(value_type, &key, None, 0)?;
+            if keys
+                .insert(key.clone(), Entry { value_type, value })
+                .is_some()
+            {
+                self.report
+                    .push(
+                        Severity::Error,
+                        "duplicate-key",
+                        offset,
+                        format!("{key} appears more than once"),
+                    )
+                    .with_key(&key);
+            }
+        }
+        check_arrays(&mut self.report, &keys);
+        for (key, expected) in GENERAL_KEYS {
+            if let Some(entry) = keys.get(*key) {
+                if entry.value_type != *expected {
+                    self.report
+                        .note(
+                            Severity::Error,
+                            "key-type",
+                            format!("{key} is {:?}, expected {expected:?}", entry.value_type),
+                        )
+                        .with_key(key);
+                }
+            }
+        }
+        let mut naming = None;
+        match keys.get("general.architecture").map(|e| &e.value) {
+            Some(Value::Str(arch)) => {
+                profile::check(&mut self.report, arch, &keys);
+                if arch_profile(arch).is_some() {
+                    let count = |key: &str| match keys.get(&format!("{arch}.{key}")) {
+                        Some(Entry {
+                            value: Value::Uint(count),
+                            ..
+                        }) => Some(*count),
+                        _ => None,
+                    };
+                    naming = Some(naming::TensorNames::new(
+                        count("block_count"),
+                        count("decoder_block_count"),
+                    ));
+                }
+            }
+            Some(_) => {}
+            None => {
+                self.report
+                    .note(
+                        Severity::Warning,
+                        "missing-architecture",
+                        "general.architecture is missing".to_string(),
+                    )
+                    .with_key("general.architecture");
+            }
+        }
+        let alignment = match keys.get("general.alignment").map(|e| &e.value) {
+            Some(&Value::Uint(alignment)) => alignment,
+            _ => 32,
+        };
+        if !alignment.is_power_of_two() {
+            self.report
+                .note(
+                    Severity::Error,
+                    "alignment",
+                    format!("general.alignment {alignment} is not a power of two"),
+                )
+                .with_key("general.alignment");
+        }
+
+        let n_vocab = match keys.get("tokenizer.ggml.tokens").map(|e| &e.value) {
+            Some(Value::Array(_, len)) => Some(*len),
+            _ => None,
+        };
+        let mut names = HashSet::new();
+        let mut extents = Vec::new();
+        for _ in 0..tensor_count {
+            let offset = self.reader.pos;
+            let name = self.reader.string(&mut self.report, "tensor name")?;
+            if name.len() > MAX_TENSOR_NAME {
+                self.report
+                    .push(
+                        Severity::Warning,
+                        "tensor-name",
+                        offset,
+                        format!("tensor name {name} is longer than {MAX_TENSOR_NAME} bytes"),
+                    )
+                    .with_tensor(&name);
+            }
+            if let Some(naming) = &mut naming {
+                naming.check(&mut self.report, &name, offset);
+            }
+            if !names.insert(name.clone()) {
+                self.report
+                    .push(
+                        Severity::Error,
+                        "duplicate-tensor",
+                        offset,
+                        format!("tensor {name} appears more than once"),
+                    )
+                    .with_tensor(&name);
+            }
+            let n_dims = self.reader.u32()?;
+            if n_dims > MAX_DIMS {
+                self.report
+                    .push(
+                        Severity::Error,
+                        "dimensions",
+                        offset,
+                        format!(
+                            "tensor {name} has {n_dims} dimensions, at most {MAX_DIMS} allowed"
+                        ),
+                    )
+                    .with_tensor(&name);
+            }
+            let mut dims = crate::Dimensions::new();
+            for _ in 0..n_dims {
+                dims.push(self.reader.u64()?);
+            }
+            if dims.contains(&0) {
+                self.report
+                    .push(
+                        Severity::Warning,
+                        "dimensions",
+                        offset,
+                        format!("tensor {name} has an empty dimension"),
+                    )
+                    .with_tensor(&name);
+            }
+            let raw_type = self.reader.u32()?;
+            let data_offset = self.reader.u64()?;
+            let tensor_type = match GGMLType::try_from(raw_type) {
+                Ok(GGMLType::Count) | Err(_) => {
+                    self.report
+                        .push(
+                            Severity::Error,
+                            "tensor-type",
+                            offset,
+                            format!("tensor {name} has the unknown type {raw_type}"),
+                        )
+                        .with_tensor(&name);
+                    continue;
+                }
+                Ok(t) => t,
+            };
+            if alignment.is_power_of_two() && !data_offset.is_multiple_of(alignment) {
+                self.report
+                    .push(
+                        Severity::Error,
+                        "alignment",
+                        offset,
+                        format!(
+                            "tensor {name} data offset {data_offset} is not aligned to {alignment}"
+                        ),
+                    )
+                    .with_tensor(&name);
+            }
+            if let (Some(n_vocab), Some(&rows)) = (n_vocab, dims.get(1)) {
+                if VOCAB_TENSORS.contains(&name.as_str()) && rows != n_vocab {
+                    self.report
+                        .push(
+                            Severity::Error,
+                            "vocab-size",
+                            offset,
+                            format!(
+                            "tensor {name} has {rows} rows but the tokenizer has {n_vocab} tokens"
+                        ),
+                        )
+                        .with_tensor(&name);
+                }
+            }
+            let info = crate::GGUFTensorInfo {
+                name,
+                dimensions: dims,
+                tensor_type,
+                offset: data_offset,
+            };
+            match info.size_bytes() {
+                Some(size) => {
+                    extents.push((data_offset, data_offset.saturating_add(size), info.name))
+                }
+                None => {
+                    self.report
+                        .push(
+                            Severity::Error,
+                            "shape",
+                            offset,
+                            format!(
+                                "tensor {} of shape {:?} does not fill whole {:?} blocks",
+                                info.name, info.dimensions, info.tensor_type
+                            ),
+                        )
+                        .with_tensor(&info.name);
+                }
+            }
+        }
+
+        if let Some(naming) = naming {
+            naming.finish(&mut self.report);
+        }
+        let header_end = self.reader.pos;
+        extents.sort();
+        if self.file_len > header_end as u64 && alignment.is_power_of_two() {
+            self.check_data_len(header_end as u64, alignment, &extents);
+        }
+        for pair in extents.windows(2) {
+            let ((_, end, a), (start, _, b)) = (&pair[0], &pair[1]);
+            if start < end {
+                self.report
+                    .note(
+                        Severity::Error,
+                        "overlap",
+                        format!("the data of tensors {a} and {b} overlap"),
+                    )
+                    .with_tensor(a);
+            }
+        }
+        Some(())
+    }
+}
+
+impl Walker<'_> {
+    /// compare the end of the last tensor with the end of the input, unless only the header
+    /// was given
+    fn check_data_len(&mut self, header_end: u64, alignment: u64, extents: &[(u64, u64, String)]) {
+        let data_start = header_end.next_multiple_of(alignment);
+        let data_len = self.file_len.saturating_sub(data_start);
+        let Some((_, end, name)) = extents.iter().max_by_key(|(_, end, _)| *end) else {
+            return;
+        };
+        if data_len < *end {
+            self.report
+                .note(
+                    Severity::Error,
+                    "truncated-data",
+                    format!(
+                        "the file ends {} bytes before the data of tensor {name} does",
+                        end - data_len
+                    ),
+                )
+                .with_tensor(name);
+        } else if data_len > end.next_multiple_of(alignment) {
+            self.report.push(
+                Severity::Warning,
+                "trailing-data",
+                (data_start + end) as usize,
+                format!(
+                    "{} bytes follow the data of the last tensor",
+                    data_len - end
+                ),
+            );
+        }
+    }
+}
+
+/// decode a string value, reporting invalid UTF-8, NULs, control characters and replacement
+/// characters left by an earlier lossy conversion
+fn check_text(
+    report: &mut ValidationReport,
+    bytes: &[u8],
+    offset: usize,
+    key: &str,
+    index: Option<u64>,
+) -> String {
+    let path = || match index {
+        Some(i) => format!("{key}[{i}]"),
+        None => key.to_string(),
+    };
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => {
+            if text.contains('\u{fffd}') {
+                report
+                    .push(
+                        Severity::Info,
+                        "replacement-character",
+                        offset,
+                        format!(
+                            "{} contains U+FFFD, a sign of an earlier lossy conversion",
+                            path()
+                        ),
+                    )
+                    .with_key(key);
+            }
+            text.to_string()
+        }
+        Err(e) => {
+            report
+                .push(
+                    Severity::Error,
+                    "utf8",
+                    offset,
+                    format!("{} is not valid UTF-8: {e}", path()),
+                )
+                .with_key(key);
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+    };
+    if bytes.contains(&0) {
+        report
+            .push(
+                Severity::Warning,
+                "nul",
+                offset,
+                format!("{} contains a NUL byte", path()),
+            )
+            .with_key(key);
+    } else if let Some(c) = text
+        .chars()
+        .find(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+    {
+        report
+            .push(
+                Severity::Warning,
+                "control-character",
+                offset,
+                format!(
+                    "{} contains the control character U+{:04X}",
+                    path(),
+                    c as u32
+                ),
+            )
+            .with_key(key);
+    }
+    text
+}
+
+/// per-layer arrays must hold integers and per-token arrays must agree in length
+fn check_arrays(report: &mut ValidationReport, keys: &HashMap<String, Entry>) {
+    use GGUfMetadataValueType::*;
+    let mut sorted: Vec<_> = keys.iter().collect();
+    sorted.sort_by_key(|(key, _)| *key);
+    for (key, entry) in sorted {
+        if let Value::Array(item_type, _) = entry.value {
+            let integer = matches!(item_type, Uint8 | Int8 | Uint16 | Int16 | Uint32 | Int32);
+            if PER_LAYER_KEYS.iter().any(|s| key.ends_with(s)) && !integer {
+                report
+                    .note(
+                        Severity::Error,
+                        "array-type",
+                        format!("{key} holds {item_type:?} items, expected integers"),
+                    )
+                    .with_key(key);
+            }
+        }
+    }
+    let lengths: Vec<(&str, u64)> = PER_TOKEN_KEYS
+        .iter()
+        .filter_map(|k| match keys.get(*k).map(|e| &e.value) {
+            Some(Value::Array(_, len)) => Some((*k, *len)),
+            _ => None,
+        })
+        .collect();
+    if let Some((first, len)) = lengths.first() {
+        for (key, other) in &lengths[1..] {
+            if other != len {
+                report
+                    .note(
+                        Severity::Error,
+                        "array-length",
+                        format!("{key} has {other} items but {first} has {len}"),
+                    )
+                    .with_key(key);
+            }
+        }
+    }
+}
+
+/// keys are lower_snake_case segments joined by dots
+fn check_key_name(report: &mut ValidationReport, key: &str, offset: usize) {
+    let valid_segment = |s: &str| {
+        !s.is_empty()
+            && s.bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+    };
+    if key.is_empty() {
+        report.push(
+            Severity::Error,
+            "key-naming",
+            offset,
+            "empty metadata key".to_string(),
+        );
+    } else if !key.split('.').all(valid_segment) {
+        report
+            .push(
+                Severity::Warning,
+                "key-naming",
+                offset,
+                format!("{key} is not made of dot-separated lower_snake_case segments"),
+            )
+            .with_key(key);
+    }
+}
+
+/// Check a file, or at least its header and tensor infos, against the GGUF spec
+pub fn validate(buf: &[u8]) -> ValidationReport {
+    validate_prefix(buf, buf.len() as u64)
+}
+
+/// [`validate`] the start of a file `file_len` bytes long
+fn validate_prefix(buf: &[u8], file_len: u64) -> ValidationReport {
+    span!("validate", "{} bytes", buf.len());
+    let mut walker = Walker {
+        reader: Reader {
+            buf,
+            pos: 0,
+            wide: true,
+        },
+        report: ValidationReport::default(),
+        stopped: false,
+        file_len,
+    };
+    if walker.walk().is_none() && !walker.stopped {
+        let offset = walker.reader.pos;
+        walker.report.push(
+            Severity::Error,
+            "truncated",
+            offset,
+            "the input ends inside the header".to_string(),
+        );
+    }
+    walker.report
+}
+
+/// [`validate`] a file, reading only its header and tensor infos
+///
+/// The tensor data is not read, only checked to fit the length of the file. A header the
+/// parser rejects is read whole, up to 1 GiB, so the checks can go past the problem.
+pub fn validate_file(path: impl AsRef<Path>) -> Result<ValidationReport, String> {
+    validate_reader(File::open(path).map_err(|e| e.to_string())?)
+}
+
+/// [`validate_file`] from any reader, such as a remote file or a packaged one
+pub fn validate_reader(mut file: impl Read + Seek) -> Result<ValidationReport, String> {
+    let file_len = file.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+    file.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+    let mut buf = Vec::new();
+    let mut chunk = vec![0; 1 << 16];
+    let mut parses = true;
+    while buf.len() < MAX_HEADER {
+        let n = file.read(&mut chunk).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if parses {
+            match crate::GGUFFile::read_with_len(&buf, &ParseOptions::lenient()) {
+                Ok(Some(_)) => break,
+                Ok(None) => {}
+                Err(_) => parses = false,
+            }
+        }
+        chunk.resize(buf.len().clamp(1 << 16, 1 << 26), 0);
+    }
+    Ok(validate_prefix(&buf, file_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{entry, header_start, raw_header, string, temp_path, tensor_info};
+
+    #[test]
+    fn architecture_profile_keys() {
+        let mut llama = Vec::new();
+        string(&mut llama, b"llama");
+        let mut gpt2 = Vec::new();
+        string(&mut gpt2, b"gpt2");
+        let mut tokens = 8u32.to_le_bytes().to_vec();
+        tokens.extend_from_slice(&0u64.to_le_bytes());
+        let u32_value = 4096u32.to_le_bytes().to_vec();
+        let buf = raw_header(
+            &[
+                ("general.architecture", 8, llama),
+                ("llama.context_length", 10, 4096u64.to_le_bytes().to_vec()),
+                ("llama.embedding_length", 4, u32_value.clone()),
+                ("llama.feed_forward_length", 4, u32_value.clone()),
+                ("llama.attention.head_count", 4, u32_value),
+                (
+                    "llama.attention.layer_norm_rms_epsilon",
+                    6,
+                    1e-5f32.to_le_bytes().to_vec(),
+                ),
+                ("tokenizer.ggml.model", 8, gpt2),
+                ("tokenizer.ggml.tokens", 9, tokens),
+            ],
+            &[],
+        );
+        let messages: Vec<_> = validate(&buf)
+            .findings
+            .into_iter()
+            .map(|f| (f.code, f.message))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (
+                    "key-type",
+                    "llama.context_length is Uint64, expected one of [Uint32]".to_string()
+                ),
+                ("missing-key", "llama.block_count is missing".to_string()),
+                (
+                    "missing-key",
+                    "tokenizer.ggml.merges is missing".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_reports_each_problem() {
+        let mut buf = header_start(3, 2, 3);
+        entry(
+            &mut buf,
+            "general.name",
+            8,
+            &[&4u64.to_le_bytes()[..], b"caf\xe9"].concat(),
+        );
+        entry(&mut buf, "Tokenizer.flag", 7, &[2]);
+        entry(
+            &mut buf,
+            "general.name",
+            8,
+            &[&1u64.to_le_bytes()[..], b"x"].concat(),
+        );
+        for (name, offset) in [("a", 0u64), ("b", 48)] {
+            tensor_info(&mut buf, name, &[64], 0, offset);
+        }
+
+        let report = validate(&buf);
+        let codes: Vec<_> = report.findings.iter().map(|f| f.code).collect();
+        assert_eq!(
+            codes,
+            [
+                "utf8",
+                "key-naming",
+                "bool",
+                "duplicate-key",
+                "missing-architecture",
+                "alignment",
+                "overlap"
+            ]
+        );
+        assert!(!report.is_valid());
+        assert_eq!(validate(&buf[..30]).findings[0].code, "truncated");
+    }
+
+    /// an array payload of `len` items of `item_type` taking `size` bytes each
+    fn array(item_type: u32, len: u64, size: usize) -> Vec<u8> {
+        let mut out = item_type.to_le_bytes().to_vec();
+        out.extend_from_slice(&len.to_le_bytes());
+        out.resize(out.len() + len as usize * size, 0);
+        out
+    }
+
+    #[test]
+    fn array_shapes_and_lengths() {
+        let mut nested = Vec::new();
+        for _ in 0..3 {
+            nested.extend_from_slice(&9u32.to_le_bytes());
+            nested.extend_from_slice(&1u64.to_le_bytes());
+        }
+        let codes = |kvs: &[(&str, u32, Vec<u8>)]| -> Vec<&'static str> {
+            validate(&raw_header(kvs, &[]))
+                .findings
+                .into_iter()
+                .map(|f| f.code)
+                .collect()
+        };
+        assert_eq!(codes(&[("x.nested", 9, nested)]), ["array-depth"]);
+        assert_eq!(
+            codes(&[
+                ("llama.feed_forward_length", 9, array(6, 2, 4)),
+                ("tokenizer.ggml.scores", 9, array(6, 2, 4)),
+                ("tokenizer.ggml.token_type", 9, array(5, 3, 4)),
+            ]),
+            ["array-type", "array-length", "missing-architecture"]
+        );
+    }
+
+    #[test]
+    fn string_values_are_clean_text() {
+        let mut tokens = 8u32.to_le_bytes().to_vec();
+        tokens.extend_from_slice(&3u64.to_le_bytes());
+        for t in [&b"a\n"[..], b"b\x1b", b"\xff"] {
+            string(&mut tokens, t);
+        }
+        let mut name = Vec::new();
+        string(&mut name, "x\0\u{fffd}".as_bytes());
+        let findings: Vec<_> = validate(&raw_header(
+            &[
+                ("general.name", 8, name),
+                ("tokenizer.ggml.tokens", 9, tokens),
+            ],
+            &[],
+        ))
+        .findings
+        .into_iter()
+        .map(|f| f.message)
+        .collect();
+        assert_eq!(
+            findings[..4],
+            [
+                "general.name contains U+FFFD, a sign of an earlier lossy conversion",
+                "general.name contains a NUL byte",
+                "tokenizer.ggml.tokens[1] contains the control character U+001B",
+                "tokenizer.ggml.tokens[2] is not valid UTF-8: invalid utf-8 sequence of 1 bytes from index 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn embedding_rows_match_the_vocab() {
+        let mut tokens = 8u32.to_le_bytes().to_vec();
+        tokens.extend_from_slice(&3u64.to_le_bytes());
+        for t in [b"a", b"b", b"c"] {
+            string(&mut tokens, t);
+        }
+        let mut buf = raw_header(&[("tokenizer.ggml.tokens", 9, tokens)], &[]);
+        buf[8..16].copy_from_slice(&1u64.to_le_bytes());
+        tensor_info(&mut buf, "token_embd.weight", &[32, 4], 0, 0);
+        let codes: Vec<_> = validate(&buf)
+            .findings
+            .into_iter()
+            .map(|f| f.code)
+            .collect();
+        assert_eq!(codes, ["missing-architecture", "vocab-size"]);
+    }
+
+    #[test]
+    fn data_length_matches_the_tensors() {
+        let mut buf = raw_header(&[], &["a", "b"]);
+        buf.resize(buf.len().next_multiple_of(32), 0);
+        let codes = |len: usize| -> Vec<&'static str> {
+            let mut file = buf.clone();
+            file.resize(buf.len() + len, 0);
+            validate(&file)
+                .findings
+                .into_iter()
+                .map(|f| f.code)
+                .filter(|c| *c != "missing-architecture")
+                .collect()
+        };
+        assert_eq!(codes(0), ["truncated-data"]);
+        assert_eq!(codes(200), ["truncated-data"]);
+        assert!(codes(256).is_empty());
+        assert_eq!(codes(320), ["trailing-data"]);
+
+        let mut file = buf.clone();
+        file.resize(buf.len() + 200, 0);
+        let path = temp_path("gguf-validate.gguf");
+        std::fs::write(&path, &file).unwrap();
+        assert_eq!(validate_file(&path), Ok(validate(&file)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn tensor_names_follow_the_scheme() {
+        let mut arch = Vec::new();
+        string(&mut arch, b"mamba");
+        let buf = raw_header(
+            &[
+                ("general.architecture", 8, arch),
+                ("mamba.block_count", 4, 3u32.to_le_bytes().to_vec()),
+            ],
+            &[
+                "token_embd.weight",
+                "blk.0.ssm_a",
+                "blk.3.ssm_in.weight",
+                "blk.01.ssm_out.weight",
+                "blk.0.attn_qq.weight",
+            ],
+        );
+        let findings: Vec<_> = validate(&buf)
+            .findings
+            .into_iter()
+            .filter(|f| !matches!(f.code, "missing-key"))
+            .map(|f| f.code)
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                "block-index",
+                "block-index",
+                "tensor-naming",
+                "missing-block"
+            ]
+        );
+    }
+
+    #[test]
+    fn encoder_and_decoder_blocks_are_counted_apart() {
+        let mut arch = Vec::new();
+        string(&mut arch, b"whisper");
+        let two = 2u32.to_le_bytes().to_vec();
+        let buf = raw_header(
+            &[
+                ("general.architecture", 8, arch),
+                ("whisper.block_count", 4, two.clone()),
+                ("whisper.decoder_block_count", 4, two),
+            ],
+            &[
+                "enc.conv1.weight",
+                "enc.blk.0.attn_q.weight",
+                "enc.blk.1.attn_q.weight",
+                "dec.blk.0.cross_attn_q.weight",
+                "dec.blk.2.attn_q.weight",
+                "dec.output_norm.weight",
+            ],
+        );
+        let findings: Vec<_> = validate(&buf)
+            .findings
+            .into_iter()
+            .filter(|f| !matches!(f.code, "missing-key"))
+            .map(|f| f.message)
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                "tensor dec.blk.2.attn_q.weight is in block 2 but there are only 2 decoder blocks",
+                "1 of 2 decoder blocks have no tensors, starting with block 1"
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn report_serializes_to_json() {
+        let report = validate(&raw_header(
+            &[("general.alignment", 4, vec![3, 0, 0, 0])],
+            &["a", "a"],
+        ));
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["format"], 1);
+        assert_eq!(json["valid"], false);
+        assert_eq!(json["max_severity"], "error");
+        let finding = |code: &str| {
+            json["findings"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|f| f["code"] == code)
+                .unwrap()
+        };
+        let alignment = finding("alignment");
+        assert_eq!(alignment["key"], "general.alignment");
+        assert_eq!(alignment["offset"], serde_json::Value::Null);
+        let duplicate = finding("duplicate-tensor");
+        assert_eq!(duplicate["tensor"], "a");
+        assert!(duplicate["offset"].is_u64());
+    }
+}
@@ -0,0 +1,310 @@
+//! Tensor naming convention checks
+use std::collections::BTreeSet;
+
+use super::{Severity, ValidationReport};
+
+/// Tensors outside the blocks, without the `.weight`/`.bias` suffix
+const GLOBAL_TENSORS: &[&str] = &[
+    "token_embd",
+    "token_embd_norm",
+    "token_types",
+    "position_embd",
+    "output_norm",
+    "output",
+    "rope_freqs",
+    "rope_factors_long",
+    "rope_factors_short",
+    "cls",
+    "cls.output",
+    "enc.conv1",
+    "enc.conv2",
+    "enc.position_embd",
+    "enc.output_norm",
+    "dec.output_norm",
+];
+
+/// Tensors of `blk.N`, without the `.weight`/`.bias` suffix
+const BLOCK_TENSORS: &[&str] = &[
+    "attn_norm",
+    "attn_norm_2",
+    "attn_q",
+    "attn_k",
+    "attn_v",
+    "attn_qkv",
+    "attn_output",
+    "attn_o",
+    "attn_rel_b",
+    "attn_q_norm",
+    "attn_k_norm",
+    "attn_output_norm",
+    "attn_post_norm",
+    "attn_rot_embd",
+    "attn_sinks",
+    "attn_q_a",
+    "attn_q_b",
+    "attn_q_a_norm",
+    "attn_kv_a_mqa",
+    "attn_kv_a_norm",
+    "attn_kv_b",
+    "attn_k_b",
+    "attn_v_b",
+    "cross_attn_norm",
+    "cross_attn_q",
+    "cross_attn_k",
+    "cross_attn_v",
+    "cross_attn_o",
+    "cross_attn_rel_b",
+    "ffn_norm",
+    "ffn_gate",
+    "ffn_up",
+    "ffn_down",
+    "ffn_act",
+    "ffn_gate_inp",
+    "ffn_gate_exps",
+    "ffn_up_exps",
+    "ffn_down_exps",
+    "ffn_gate_shexp",
+    "ffn_up_shexp",
+    "ffn_down_shexp",
+    "ffn_gate_inp_shexp",
+    "ffn_norm_exps",
+    "exp_probs_b",
+    "post_attention_norm",
+    "post_ffw_norm",
+    "layer_output_norm",
+    "ssm_in",
+    "ssm_conv1d",
+    "ssm_x",
+    "ssm_dt",
+    "ssm_a",
+    "ssm_d",
+    "ssm_out",
+    "ssm_norm",
+    "time_mix_w1",
+    "time_mix_w2",
+    "time_mix_lerp_x",
+    "time_mix_lerp_w",
+    "time_mix_lerp_k",
+    "time_mix_lerp_v",
+    "time_mix_lerp_r",
+    "time_mix_lerp_g",
+    "time_mix_lerp_fused",
+    "time_mix_first",
+    "time_mix_decay",
+    "time_mix_decay_w1",
+    "time_mix_decay_w2",
+    "time_mix_key",
+    "time_mix_value",
+    "time_mix_receptance",
+    "time_mix_gate",
+    "time_mix_ln",
+    "time_mix_output",
+    "channel_mix_lerp_k",
+    "channel_mix_lerp_r",
+    "channel_mix_key",
+    "channel_mix_receptance",
+    "channel_mix_value",
+];
+
+/// Checks tensor names one by one, then whether any block is missing
+///
+/// The blocks of an encoder-decoder are `enc.blk.N` and `dec.blk.N`, each stack counted apart.
+pub(super) struct TensorNames {
+    block_count: Option<u64>,
+    decoder_block_count: Option<u64>,
+    blocks: BTreeSet<u64>,
+    decoder_blocks: BTreeSet<u64>,
+}
+
+impl TensorNames {
+    pub(super) fn new(block_count: Option<u64>, decoder_block_count: Option<u64>) -> Self {
+        TensorNames {
+            block_count,
+            decoder_block_count,
+            blocks: BTreeSet::new(),
+            decoder_blocks: BTreeSet::new(),
+        }
+    }
+
+    pub(super) fn check(&mut self, report: &mut ValidationReport, name: &str, offset: usize) {
+        let base = name
+            .strip_suffix(".weight")
+            .or_else(|| name.strip_suffix(".bias"))
+            .unwrap_or(name);
+        let (decoder, block) = match base.strip_prefix("dec.blk.") {
+            Some(rest) => (true, Some(rest)),
+            None => (
+                false,
+                base.strip_prefix("enc.blk.")
+                    .or_else(|| base.strip_prefix("blk.")),
+            ),
+        };
+        let (count, blocks, stack) = match decoder {
+            true => (
+                self.decoder_block_count,
+                &mut self.decoder_blocks,
+                "decoder blocks",
+            ),
+            false => (self.block_count, &mut self.blocks, "blocks"),
+        };
+        let known = match block {
+            Some(rest) => {
+                let (index, rest) = rest.split_once('.').unwrap_or((rest, ""));
+                match index.parse::<u64>() {
+                    Ok(i) if i.to_string() == index => {
+                        if count.is_some_and(|count| i >= count) {
+                            report
+                                .push(
+                                    Severity::Error,
+                                    "block-index",
+                                    offset,
+                                    format!(
+                                    "tensor {name} is in block {i} but there are only {} {stack}",
+                                    count.unwrap_or_default()
+                                ),
+                                )
+                                .with_tensor(name);
+                        }
+                        blocks.insert(i);
+                    }
+                    _ => {
+                        report
+                            .push(
+                                Severity::Error,
+                                "block-index",
+                                offset,
+                                format!("tensor {name} has the malformed block index {index:?}"),
+                            )
+                            .with_tensor(name);
+                    }
+                }
+                BLOCK_TENSORS.contains(&rest)
+            }
+            None => name != base && GLOBAL_TENSORS.contains(&base),
+        };
+        if !known {
+            report
+                .push(
+                    Severity::Warning,
+                    "tensor-naming",
+                    offset,
+                    format!("tensor {name} does not follow the standard naming scheme"),
+                )
+                .with_tensor(name);
+        }
+    }
+
+    pub(super) fn finish(self, report: &mut ValidationReport) {
+        let stacks = [
+            (self.block_count, &self.blocks, "blocks"),
+            (
+                self.decoder_block_count,
+                &self.decoder_blocks,
+                "decoder blocks",
+            ),
+        ];
+        for (count, blocks, stack) in stacks {
+            let Some(count) = count else {
+                continue;
+            };
+            let missing: Vec<u64> = (0..count).filter(|i| !blocks.contains(i)).collect();
+            if let Some(first) = missing.first() {
+                report.note(
+                    Severity::Warning,
+                    "missing-block",
+                    format!(
+                        "{} of {count} {stack} have no tensors, starting with block {first}",
+                        missing.len()
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the codes and tensors of the findings for `names`, with `blocks` blocks
+    fn findings(blocks: u64, names: &[&str]) -> Vec<(&'static str, Option<String>)> {
+        let mut report = ValidationReport::default();
+        let mut checker = TensorNames::new(Some(blocks), None);
+        for (i, name) in names.iter().enumerate() {
+            checker.check(&mut report, name, i * 64);
+        }
+        checker.finish(&mut report);
+        report
+            .findings
+            .into_iter()
+            .map(|f| (f.code, f.tensor))
+            .collect()
+    }
+
+    fn llama(blocks: u64) -> Vec<String> {
+        let mut names = vec!["token_embd.weight".to_string()];
+        for i in 0..blocks {
+            for tensor in [
+                "attn_norm",
+                "attn_q",
+                "attn_k",
+                "attn_v",
+                "attn_output",
+                "ffn_norm",
+                "ffn_gate",
+                "ffn_up",
+                "ffn_down",
+            ] {
+                names.push(format!("blk.{i}.{tensor}.weight"));
+            }
+        }
+        names.extend([
+            "output_norm.weight".to_string(),
+            "output.weight".to_string(),
+        ]);
+        names
+    }
+
+    #[test]
+    fn llama_layout_passes() {
+        let names = llama(2);
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        assert_eq!(findings(2, &names), []);
+    }
+
+    #[test]
+    fn block_index_past_the_count() {
+        let names = llama(2);
+        let mut names: Vec<&str> = names.iter().map(String::as_str).collect();
+        names.push("blk.2.attn_q.weight");
+        assert_eq!(
+            findings(2, &names),
+            [("block-index", Some("blk.2.attn_q.weight".to_string()))]
+        );
+        assert_eq!(
+            findings(3, &names[..names.len() - 1]),
+            [("missing-block", None)]
+        );
+        assert_eq!(
+            findings(2, &["blk.01.attn_q.weight", "blk.1.attn_q.weight"]),
+            [
+                ("block-index", Some("blk.01.attn_q.weight".to_string())),
+                ("missing-block", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_suffix() {
+        assert_eq!(
+            findings(
+                1,
+                &["blk.0.attn_qq.weight", "output.scale", "token_embd.weight"]
+            ),
+            [
+                ("tensor-naming", Some("blk.0.attn_qq.weight".to_string())),
+                ("tensor-naming", Some("output.scale".to_string())),
+            ]
+        );
+    }
+}
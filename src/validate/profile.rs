@@ -0,0 +1,357 @@
+//! Keys each architecture needs
+use std::collections::HashMap;
+
+use super::{Entry, Severity, ValidationReport, Value};
+use crate::GGUfMetadataValueType::{self, Array, Float32, Uint32};
+
+/// The keys an architecture needs, relative to its `<arch>.` prefix
+#[derive(Debug)]
+pub struct ArchProfile {
+    pub architecture: &'static str,
+    /// Each key with the types it may have; arrays hold per-layer values.
+    pub required: &'static [(&'static str, &'static [GGUfMetadataValueType])],
+    /// Whether the model reads text, so the `tokenizer.ggml.*` keys must be present.
+    pub needs_tokenizer: bool,
+}
+
+const COUNT: &[GGUfMetadataValueType] = &[Uint32];
+const PER_LAYER: &[GGUfMetadataValueType] = &[Uint32, Array];
+const EPSILON: &[GGUfMetadataValueType] = &[Float32];
+
+const RMS_NORM: &[(&str, &[GGUfMetadataValueType])] = &[
+    ("context_length", COUNT),
+    ("embedding_length", COUNT),
+    ("block_count", COUNT),
+    ("feed_forward_length", PER_LAYER),
+    ("attention.head_count", PER_LAYER),
+    ("attention.layer_norm_rms_epsilon", EPSILON),
+];
+
+const LAYER_NORM: &[(&str, &[GGUfMetadataValueType])] = &[
+    ("context_length", COUNT),
+    ("embedding_length", COUNT),
+    ("block_count", COUNT),
+    ("feed_forward_length", PER_LAYER),
+    ("attention.head_count", PER_LAYER),
+    ("attention.layer_norm_epsilon", EPSILON),
+];
+
+const MAMBA: &[(&str, &[GGUfMetadataValueType])] = &[
+    ("context_length", COUNT),
+    ("embedding_length", COUNT),
+    ("block_count", COUNT),
+    ("ssm.conv_kernel", COUNT),
+    ("ssm.inner_size", COUNT),
+    ("ssm.state_size", COUNT),
+    ("ssm.time_step_rank", COUNT),
+    ("attention.layer_norm_rms_epsilon", EPSILON),
+];
+
+const RWKV: &[(&str, &[GGUfMetadataValueType])] = &[
+    ("context_length", COUNT),
+    ("embedding_length", COUNT),
+    ("block_count", COUNT),
+    ("feed_forward_length", PER_LAYER),
+    ("wkv.head_size", COUNT),
+    ("attention.layer_norm_epsilon", EPSILON),
+];
+
+/// Encoder-decoders, whose `block_count` counts the encoder blocks
+const T5: &[(&str, &[GGUfMetadataValueType])] = &[
+    ("context_length", COUNT),
+    ("embedding_length", COUNT),
+    ("block_count", COUNT),
+    ("decoder_block_count", COUNT),
+    ("feed_forward_length", PER_LAYER),
+    ("attention.head_count", PER_LAYER),
+    ("attention.layer_norm_rms_epsilon", EPSILON),
+];
+
+const WHISPER: &[(&str, &[GGUfMetadataValueType])] = &[
+    ("context_length", COUNT),
+    ("embedding_length", COUNT),
+    ("block_count", COUNT),
+    ("decoder_block_count", COUNT),
+    ("attention.head_count", PER_LAYER),
+    ("audio.mel_bins", COUNT),
+    ("audio.context_length", COUNT),
+];
+
+const fn text(
+    architecture: &'static str,
+    required: &'static [(&'static str, &'static [GGUfMetadataValueType])],
+) -> ArchProfile {
+    ArchProfile {
+        architecture,
+        required,
+        needs_tokenizer: true,
+    }
+}
+
+const PROFILES: &[ArchProfile] = &[
+    text("llama", RMS_NORM),
+    text("qwen2", RMS_NORM),
+    text("qwen2moe", RMS_NORM),
+    text("qwen3", RMS_NORM),
+    text("qwen3moe", RMS_NORM),
+    text("gemma", RMS_NORM),
+    text("gemma2", RMS_NORM),
+    text("gemma3", RMS_NORM),
+    text("phi3", RMS_NORM),
+    text("deepseek2", RMS_NORM),
+    text("command-r", LAYER_NORM),
+    text("starcoder", LAYER_NORM),
+    text("starcoder2", LAYER_NORM),
+    text("stablelm", LAYER_NORM),
+    text("falcon", LAYER_NORM),
+    text("gpt2", LAYER_NORM),
+    text("phi2", LAYER_NORM),
+    text("mpt", LAYER_NORM),
+    text("bert", LAYER_NORM),
+    text("nomic-bert", LAYER_NORM),
+    text("mamba", MAMBA),
+    text("rwkv6", RWKV),
+    text("t5", T5),
+    text("whisper", WHISPER),
+];
+
+/// The profile of a `general.architecture` value, `None` if it is not known
+pub fn arch_profile(architecture: &str) -> Option<&'static ArchProfile> {
+    PROFILES.iter().find(|p| p.architecture == architecture)
+}
+
+/// report a key that is missing or has none of the allowed types
+fn check_key(
+    report: &mut ValidationReport,
+    keys: &HashMap<String, Entry>,
+    key: &str,
+    allowed: &[GGUfMetadataValueType],
+    missing: Severity,
+) {
+    let finding = match keys.get(key) {
+        None => report.note(missing, "missing-key", format!("{key} is missing")),
+        Some(entry) if !allowed.contains(&entry.value_type) => report.note(
+            Severity::Error,
+            "key-type",
+            format!(
+                "{key} is {:?}, expected one of {allowed:?}",
+                entry.value_type
+            ),
+        ),
+        Some(_) => return,
+    };
+    finding.with_key(key);
+}
+
+/// report an array key whose items do not have the expected type
+fn check_array(
+    report: &mut ValidationReport,
+    keys: &HashMap<String, Entry>,
+    key: &str,
+    item_type: GGUfMetadataValueType,
+    missing: Severity,
+) {
+    check_key(report, keys, key, &[Array], missing);
+    if let Some(Value::Array(actual, _)) = keys.get(key).map(|e| &e.value) {
+        if *actual != item_type {
+            report
+                .note(
+                    Severity::Error,
+                    "key-type",
+                    format!("{key} holds {actual:?} items, expected {item_type:?}"),
+                )
+                .with_key(key);
+        }
+    }
+}
+
+/// check the keys required by the architecture and its tokenizer
+pub(super) fn check(
+    report: &mut ValidationReport,
+    architecture: &str,
+    keys: &HashMap<String, Entry>,
+) {
+    let Some(profile) = arch_profile(architecture) else {
+        report.note(
+            Severity::Info,
+            "unknown-architecture",
+            format!("no key profile for architecture {architecture}"),
+        );
+        return;
+    };
+    for (key, allowed) in profile.required {
+        check_key(
+            report,
+            keys,
+            &format!("{architecture}.{key}"),
+            allowed,
+            Severity::Error,
+        );
+    }
+    if !profile.needs_tokenizer {
+        return;
+    }
+    let model = match keys.get("tokenizer.ggml.model").map(|e| &e.value) {
+        Some(Value::Str(model)) => model.as_str(),
+        _ => {
+            check_key(
+                report,
+                keys,
+                "tokenizer.ggml.model",
+                &[GGUfMetadataValueType::String],
+                Severity::Error,
+            );
+            return;
+        }
+    };
+    if model == "no_vocab" || model == "none" {
+        return;
+    }
+    let string = GGUfMetadataValueType::String;
+    check_array(
+        report,
+        keys,
+        "tokenizer.ggml.tokens",
+        string,
+        Severity::Error,
+    );
+    match model {
+        "gpt2" => check_array(
+            report,
+            keys,
+            "tokenizer.ggml.merges",
+            string,
+            Severity::Error,
+        ),
+        "llama" => {
+            check_array(
+                report,
+                keys,
+                "tokenizer.ggml.scores",
+                Float32,
+                Severity::Warning,
+            );
+            check_array(
+                report,
+                keys,
+                "tokenizer.ggml.token_type",
+                GGUfMetadataValueType::Int32,
+                Severity::Warning,
+            );
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use GGUfMetadataValueType::Int32;
+
+    /// the keys of a llama model with a llama tokenizer
+    fn llama() -> HashMap<String, Entry> {
+        let uint = |v| Entry {
+            value_type: Uint32,
+            value: Value::Uint(v),
+        };
+        let array = |item_type, len| Entry {
+            value_type: Array,
+            value: Value::Array(item_type, len),
+        };
+        let float = Entry {
+            value_type: Float32,
+            value: Value::Other,
+        };
+        let model = Entry {
+            value_type: GGUfMetadataValueType::String,
+            value: Value::Str("llama".to_string()),
+        };
+        [
+            ("llama.context_length", uint(4096)),
+            ("llama.embedding_length", uint(4096)),
+            ("llama.block_count", uint(32)),
+            ("llama.feed_forward_length", uint(11008)),
+            ("llama.attention.head_count", array(Int32, 32)),
+            ("llama.attention.layer_norm_rms_epsilon", float),
+            ("tokenizer.ggml.model", model),
+            (
+                "tokenizer.ggml.tokens",
+                array(GGUfMetadataValueType::String, 3),
+            ),
+            ("tokenizer.ggml.scores", array(Float32, 3)),
+            ("tokenizer.ggml.token_type", array(Int32, 3)),
+        ]
+        .into_iter()
+        .map(|(key, entry)| (key.to_string(), entry))
+        .collect()
+    }
+
+    fn findings(keys: &HashMap<String, Entry>) -> Vec<(Severity, &'static str, String)> {
+        let mut report = ValidationReport::default();
+        check(&mut report, "llama", keys);
+        report
+            .findings
+            .into_iter()
+            .map(|f| (f.severity, f.code, f.key.unwrap_or_default()))
+            .collect()
+    }
+
+    #[test]
+    fn complete_profile_passes() {
+        assert_eq!(findings(&llama()), []);
+        let mut report = ValidationReport::default();
+        check(&mut report, "unheard-of", &llama());
+        assert_eq!(report.findings[0].code, "unknown-architecture");
+        assert!(report.is_valid());
+    }
+
+    #[test]
+    fn missing_key() {
+        let mut keys = llama();
+        keys.remove("llama.block_count");
+        keys.remove("tokenizer.ggml.scores");
+        assert_eq!(
+            findings(&keys),
+            [
+                (
+                    Severity::Error,
+                    "missing-key",
+                    "llama.block_count".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "missing-key",
+                    "tokenizer.ggml.scores".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn key_of_the_wrong_type() {
+        let mut keys = llama();
+        keys.get_mut("llama.context_length").unwrap().value_type = GGUfMetadataValueType::Uint64;
+        keys.insert(
+            "tokenizer.ggml.tokens".to_string(),
+            Entry {
+                value_type: Array,
+                value: Value::Array(Int32, 3),
+            },
+        );
+        assert_eq!(
+            findings(&keys),
+            [
+                (
+                    Severity::Error,
+                    "key-type",
+                    "llama.context_length".to_string()
+                ),
+                (
+                    Severity::Error,
+                    "key-type",
+                    "tokenizer.ggml.tokens".to_string()
+                ),
+            ]
+        );
+    }
+}
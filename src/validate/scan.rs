@@ -0,0 +1,332 @@
+//! Security scan of untrusted files
+//!
+//! [`scan`] looks for what a hostile file would do rather than for spec deviations: values
+//! big enough to exhaust a loader, arrays nested deep enough to overflow a recursive parser,
+//! keys that hide characters, payloads smuggled into metadata and tensors that point outside
+//! the file.
+use super::{fixed_size, Reader, Severity, ValidationReport};
+use crate::{GGMLType, GGUFTensorInfo, GGUfMetadataValueType};
+
+/// Longest string value not flagged, in bytes
+const MAX_STRING: u64 = 1 << 20;
+/// Most array items not flagged
+const MAX_ARRAY: u64 = 1 << 24;
+/// Largest metadata section not flagged, in bytes
+const MAX_METADATA: u64 = 256 << 20;
+/// Deepest array nesting followed; no known key nests arrays at all
+const MAX_NESTING: usize = 4;
+
+/// Leading bytes of executables and archives
+const BINARY_MAGIC: &[(&[u8], &str)] = &[
+    (b"\x7fELF", "an ELF executable"),
+    (b"MZ\x90\x00", "a Windows executable"),
+    (b"\xcf\xfa\xed\xfe", "a Mach-O executable"),
+    (b"\xca\xfe\xba\xbe", "a Mach-O universal binary"),
+    (b"PK\x03\x04", "a zip archive"),
+    (b"\x1f\x8b\x08", "a gzip stream"),
+    (b"#!/", "a script"),
+];
+
+/// Fragments typical of shell, Python and browser scripts, matched case-insensitively
+const SCRIPT_PATTERNS: &[&str] = &[
+    "<script",
+    "powershell",
+    "/bin/sh",
+    "/bin/bash",
+    "subprocess",
+    "os.system",
+    "__import__",
+    "eval(",
+    "exec(",
+    "curl http",
+    "wget http",
+    "base64 -d",
+];
+
+struct Scanner<'a> {
+    reader: Reader<'a>,
+    report: ValidationReport,
+    /// set when something stops the scan before the input ends
+    stopped: bool,
+}
+
+impl Scanner<'_> {
+    fn value_type(&mut self) -> Option<GGUfMetadataValueType> {
+        let offset = self.reader.pos;
+        match GGUfMetadataValueType::try_from(self.reader.u32()?) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                self.report.push(Severity::Error, "value-type", offset, e);
+                self.stopped = true;
+                None
+            }
+        }
+    }
+
+    /// look at a string or byte payload for signs of embedded executables or scripts
+    fn payload(&mut self, bytes: &[u8], key: &str, offset: usize) {
+        if let Some((_, what)) = BINARY_MAGIC.iter().find(|(m, _)| bytes.starts_with(m)) {
+            self.report
+                .push(
+                    Severity::Error,
+                    "embedded-binary",
+                    offset,
+                    format!("{key} holds what looks like {what}"),
+                )
+                .with_key(key);
+            return;
+        }
+        let text = String::from_utf8_lossy(bytes).to_lowercase();
+        if let Some(pattern) = SCRIPT_PATTERNS.iter().find(|p| text.contains(*p)) {
+            self.report
+                .push(
+                    Severity::Warning,
+                    "embedded-script",
+                    offset,
+                    format!("{key} contains the script fragment {pattern:?}"),
+                )
+                .with_key(key);
+        }
+    }
+
+    fn value(&mut self, value_type: GGUfMetadataValueType, key: &str, depth: usize) -> Option<()> {
+        let offset = self.reader.pos;
+        match value_type {
+            GGUfMetadataValueType::String => {
+                let len = self.reader.size()?;
+                if len > MAX_STRING {
+                    self.report
+                        .push(
+                            Severity::Warning,
+                            "large-value",
+                            offset,
+                            format!("{key} holds a string of {len} bytes"),
+                        )
+                        .with_key(key);
+                }
+                let bytes = self.reader.bytes(len)?;
+                self.payload(bytes, key, offset);
+            }
+            GGUfMetadataValueType::Array => {
+                if depth >= MAX_NESTING {
+                    self.report
+                        .push(
+                            Severity::Error,
+                            "nesting",
+                            offset,
+                            format!("{key} nests arrays more than {MAX_NESTING} deep"),
+                        )
+                        .with_key(key);
+                    self.stopped = true;
+                    return None;
+                }
+                let item_type = self.value_type()?;
+                let len = self.reader.size()?;
+                if len > MAX_ARRAY {
+                    self.report
+                        .push(
+                            Severity::Warning,
+                            "large-value",
+                            offset,
+                            format!("{key} holds an array of {len} items"),
+                        )
+                        .with_key(key);
+                }
+                match fixed_size(item_type) {
+                    Some(size) => {
+                        let bytes = self.reader.bytes(len.checked_mul(size)?)?;
+                        if size == 1 {
+                            self.payload(bytes, key, offset);
+                        }
+                    }
+                    None => {
+                        for _ in 0..len {
+                            self.value(item_type, key, depth + 1)?;
+                        }
+                    }
+                }
+            }
+            other => {
+                self.reader.bytes(fixed_size(other)?)?;
+            }
+        }
+        Some(())
+    }
+
+    fn scan(&mut self) -> Option<()> {
+        if self.reader.bytes(4)? != b"GGUF" {
+            self.report
+                .push(Severity::Error, "magic", 0, "not a GGUF file".to_string());
+            self.stopped = true;
+            return None;
+        }
+        if self.reader.u32()? == 1 {
+            self.reader.wide = false;
+        }
+        let tensor_count = self.reader.size()?;
+        let kv_count = self.reader.size()?;
+        let remaining = (self.reader.buf.len() - self.reader.pos) as u64;
+        if kv_count > remaining || tensor_count > remaining {
+            self.report.push(
+                Severity::Error,
+                "counts",
+                8,
+                format!(
+                    "{kv_count} metadata entries and {tensor_count} tensors cannot fit in the \
+                     file"
+                ),
+            );
+            self.stopped = true;
+            return None;
+        }
+
+        let mut alignment = 32;
+        for _ in 0..kv_count {
+            let offset = self.reader.pos;
+            let len = self.reader.size()?;
+            let key = String::from_utf8_lossy(self.reader.bytes(len)?).into_owned();
+            if key.chars().any(|c| c.is_control() || !c.is_ascii()) {
+                self.report
+                    .push(
+                        Severity::Warning,
+                        "nonprintable-key",
+                        offset,
+                        format!("the key {key:?} has non-printable or non-ASCII characters"),
+                    )
+                    .with_key(&key);
+            }
+            let value_type = self.value_type()?;
+            if key == "general.alignment" && value_type == GGUfMetadataValueType::Uint32 {
+                let value = self.reader.u32()?;
+                if value.is_power_of_two() {
+                    alignment = u64::from(value);
+                }
+            } else {
+                self.value(value_type, &key, 0)?;
+            }
+        }
+        let metadata_size = self.reader.pos as u64;
+        if metadata_size > MAX_METADATA {
+            self.report.note(
+                Severity::Warning,
+                "large-value",
+                format!("the metadata takes {metadata_size} bytes"),
+            );
+        }
+
+        let mut tensors = Vec::new();
+        for _ in 0..tensor_count {
+            let offset = self.reader.pos;
+            let len = self.reader.size()?;
+            let name = String::from_utf8_lossy(self.reader.bytes(len)?).into_owned();
+            let n_dims = self.reader.u32()?;
+            let mut dimensions = crate::Dimensions::new();
+            for _ in 0..n_dims {
+                dimensions.push(self.reader.u64()?);
+            }
+            let tensor_type = GGMLType::try_from(self.reader.u32()?);
+            let data_offset = self.reader.u64()?;
+            if let Ok(tensor_type) = tensor_type {
+                let info = GGUFTensorInfo {
+                    name,
+                    dimensions,
+                    tensor_type,
+                    offset: data_offset,
+                };
+                tensors.push((offset, info));
+            }
+        }
+
+        let data_start = (self.reader.pos as u64).next_multiple_of(alignment);
+        let data_len = (self.reader.buf.len() as u64).saturating_sub(data_start);
+        for (offset, info) in tensors {
+            let end = info
+                .size_bytes()
+                .and_then(|size| info.offset.checked_add(size));
+            if end.is_none_or(|end| end > data_len) {
+                self.report
+                    .push(
+                        Severity::Error,
+                        "extent",
+                        offset,
+                        format!(
+                            "tensor {} at data offset {} extends past the {data_len} bytes of data",
+                            info.name, info.offset
+                        ),
+                    )
+                    .with_tensor(&info.name);
+            }
+        }
+        Some(())
+    }
+}
+
+/// Look for signs of a hostile file in the complete contents of a file
+///
+/// Unlike [`validate`](super::validate), which checks conformance, this flags what a loader
+/// should be wary of even in a file the spec allows.
+pub fn scan(buf: &[u8]) -> ValidationReport {
+    let mut scanner = Scanner {
+        reader: Reader {
+            buf,
+            pos: 0,
+            wide: true,
+        },
+        report: ValidationReport::default(),
+        stopped: false,
+    };
+    if scanner.scan().is_none() && !scanner.stopped {
+        let offset = scanner.reader.pos;
+        scanner.report.push(
+            Severity::Error,
+            "truncated",
+            offset,
+            "the file ends inside the header".to_string(),
+        );
+    }
+    scanner.report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{raw_header, string};
+
+    #[test]
+    fn scan_flags_hostile_content() {
+        let mut script = Vec::new();
+        string(&mut script, b"{{ x }} import os; os.system('rm -rf /')");
+        let mut elf = 0u32.to_le_bytes().to_vec();
+        elf.extend_from_slice(&8u64.to_le_bytes());
+        elf.extend_from_slice(b"\x7fELF\x02\x01\x01\x00");
+        let mut nested = Vec::new();
+        for _ in 0..6 {
+            nested.extend_from_slice(&9u32.to_le_bytes());
+            nested.extend_from_slice(&1u64.to_le_bytes());
+        }
+        let buf = raw_header(
+            &[
+                ("tokenizer.chat_template", 8, script),
+                ("general.na\u{7}me", 9, elf),
+                ("x", 9, nested),
+            ],
+            &["token_embd.weight"],
+        );
+        let report = scan(&buf);
+        let codes: Vec<_> = report.findings.iter().map(|f| f.code).collect();
+        assert_eq!(
+            codes,
+            [
+                "embedded-script",
+                "nonprintable-key",
+                "embedded-binary",
+                "nesting"
+            ]
+        );
+
+        let mut ok = raw_header(&[], &["token_embd.weight"]);
+        assert_eq!(scan(&ok).findings[0].code, "extent");
+        ok.resize(ok.len().next_multiple_of(32) + 128, 0);
+        assert!(scan(&ok).findings.is_empty());
+    }
+}
@@ -0,0 +1,329 @@
+//! Value types of the standardized keys
+use super::{Severity, ValidationReport};
+use crate::GGUfMetadataValueType::{self, Array, Bool, Float32, Int32, Uint32, Uint64, Uint8};
+use crate::{GGUFHeader, GGUFMetadataValue};
+
+/// The types a standardized key may have
+#[derive(Debug)]
+pub struct KeySchema {
+    /// The key, with `{arch}` standing for `general.architecture`.
+    pub key: &'static str,
+    /// Standard types.
+    pub types: &'static [GGUfMetadataValueType],
+    /// Types older converters wrote, which loaders may still reject.
+    pub drift: &'static [GGUfMetadataValueType],
+    /// Accepted item types when the value is an array.
+    pub items: &'static [GGUfMetadataValueType],
+}
+
+const fn key(
+    key: &'static str,
+    types: &'static [GGUfMetadataValueType],
+    items: &'static [GGUfMetadataValueType],
+) -> KeySchema {
+    KeySchema {
+        key,
+        types,
+        drift: &[],
+        items,
+    }
+}
+
+/// a count some converters wrote as 64 bits
+const fn count(key: &'static str) -> KeySchema {
+    KeySchema {
+        key,
+        types: U32,
+        drift: &[Uint64],
+        items: NONE,
+    }
+}
+
+const U32: &[GGUfMetadataValueType] = &[Uint32];
+const PER_LAYER: &[GGUfMetadataValueType] = &[Uint32, Array];
+const F32: &[GGUfMetadataValueType] = &[Float32];
+const STR: &[GGUfMetadataValueType] = &[GGUfMetadataValueType::String];
+const BOOL: &[GGUfMetadataValueType] = &[Bool];
+const ARRAY: &[GGUfMetadataValueType] = &[Array];
+const NONE: &[GGUfMetadataValueType] = &[];
+const INTS: &[GGUfMetadataValueType] = &[Uint32, Int32];
+
+const SCHEMA: &[KeySchema] = &[
+    key("general.architecture", STR, NONE),
+    key("general.quantization_version", U32, NONE),
+    key("general.alignment", U32, NONE),
+    key("general.file_type", U32, NONE),
+    key("general.name", STR, NONE),
+    key("general.basename", STR, NONE),
+    key("general.finetune", STR, NONE),
+    key("general.author", STR, NONE),
+    key("general.organization", STR, NONE),
+    key("general.version", STR, NONE),
+    key("general.description", STR, NONE),
+    key("general.license", STR, NONE),
+    key("general.url", STR, NONE),
+    key("general.size_label", STR, NONE),
+    key("general.tags", ARRAY, STR),
+    key("general.languages", ARRAY, STR),
+    key("general.datasets", ARRAY, STR),
+    key("{arch}.vocab_size", U32, NONE),
+    count("{arch}.context_length"),
+    count("{arch}.embedding_length"),
+    count("{arch}.block_count"),
+    key("{arch}.feed_forward_length", PER_LAYER, INTS),
+    key("{arch}.expert_count", U32, NONE),
+    key("{arch}.expert_used_count", U32, NONE),
+    key("{arch}.attention.head_count", PER_LAYER, INTS),
+    key("{arch}.attention.head_count_kv", PER_LAYER, INTS),
+    key("{arch}.attention.key_length", U32, NONE),
+    key("{arch}.attention.value_length", U32, NONE),
+    key("{arch}.attention.layer_norm_epsilon", F32, NONE),
+    key("{arch}.attention.layer_norm_rms_epsilon", F32, NONE),
+    key("{arch}.attention.sliding_window", U32, NONE),
+    key("{arch}.rope.dimension_count", U32, NONE),
+    key("{arch}.rope.freq_base", F32, NONE),
+    key("{arch}.rope.scaling.type", STR, NONE),
+    key("{arch}.rope.scaling.factor", F32, NONE),
+    key("{arch}.rope.scaling.original_context_length", U32, NONE),
+    key("{arch}.pooling_type", U32, NONE),
+    key("{arch}.attention.causal", BOOL, NONE),
+    key("{arch}.classifier.output_labels", ARRAY, STR),
+    key("{arch}.ssm.conv_kernel", U32, NONE),
+    key("{arch}.ssm.state_size", U32, NONE),
+    key("{arch}.ssm.inner_size", U32, NONE),
+    key("{arch}.ssm.time_step_rank", U32, NONE),
+    key("{arch}.ssm.group_count", U32, NONE),
+    key("{arch}.wkv.head_size", U32, NONE),
+    key("{arch}.time_mix_extra_dim", U32, NONE),
+    key("{arch}.time_decay_extra_dim", U32, NONE),
+    key("{arch}.rescale_every_n_layers", U32, NONE),
+    key("{arch}.token_shift_count", U32, NONE),
+    count("{arch}.decoder_block_count"),
+    key("{arch}.decoder_start_token_id", U32, NONE),
+    key("whisper.audio.mel_bins", U32, NONE),
+    key("whisper.audio.context_length", U32, NONE),
+    key("tokenizer.ggml.model", STR, NONE),
+    key("tokenizer.ggml.pre", STR, NONE),
+    key("tokenizer.ggml.tokens", ARRAY, STR),
+    key("tokenizer.ggml.scores", ARRAY, F32),
+    key("tokenizer.ggml.token_type", ARRAY, &[Int32]),
+    key("tokenizer.ggml.merges", ARRAY, STR),
+    key("tokenizer.ggml.added_tokens", ARRAY, STR),
+    key("tokenizer.ggml.bos_token_id", U32, NONE),
+    key("tokenizer.ggml.eos_token_id", U32, NONE),
+    key("tokenizer.ggml.unknown_token_id", U32, NONE),
+    key("tokenizer.ggml.seperator_token_id", U32, NONE),
+    key("tokenizer.ggml.separator_token_id", U32, NONE),
+    key("tokenizer.ggml.padding_token_id", U32, NONE),
+    key("tokenizer.ggml.cls_token_id", U32, NONE),
+    key("tokenizer.ggml.mask_token_id", U32, NONE),
+    key("tokenizer.ggml.eot_token_id", U32, NONE),
+    key("tokenizer.ggml.eom_token_id", U32, NONE),
+    key("tokenizer.ggml.prefix_token_id", U32, NONE),
+    key("tokenizer.ggml.suffix_token_id", U32, NONE),
+    key("tokenizer.ggml.middle_token_id", U32, NONE),
+    key("tokenizer.ggml.fim_pre_token_id", U32, NONE),
+    key("tokenizer.ggml.fim_suf_token_id", U32, NONE),
+    key("tokenizer.ggml.fim_mid_token_id", U32, NONE),
+    key("tokenizer.ggml.fim_pad_token_id", U32, NONE),
+    key("tokenizer.ggml.fim_rep_token_id", U32, NONE),
+    key("tokenizer.ggml.fim_sep_token_id", U32, NONE),
+    key("tokenizer.ggml.stop_token_ids", ARRAY, INTS),
+    key("tokenizer.ggml.token_type_count", U32, NONE),
+    key("tokenizer.ggml.add_bos_token", BOOL, NONE),
+    key("tokenizer.ggml.add_eos_token", BOOL, NONE),
+    key("tokenizer.ggml.add_space_prefix", BOOL, NONE),
+    key("tokenizer.ggml.add_sep_token", BOOL, NONE),
+    key("tokenizer.ggml.remove_extra_whitespaces", BOOL, NONE),
+    key("tokenizer.ggml.precompiled_charsmap", ARRAY, &[Uint8]),
+    key("tokenizer.chat_template", STR, NONE),
+    key("tokenizer.huggingface.json", STR, NONE),
+    key("tokenizer.rwkv.world", STR, NONE),
+];
+
+/// Namespaces whose keys are not all registered, e.g. the many per-architecture
+/// hyperparameters, with `{arch}` standing for `general.architecture`
+const OPEN_NAMESPACES: &[&str] = &[
+    "{arch}.",
+    "general.source.",
+    "general.base_model.",
+    "general.dataset.",
+    "tokenizer.chat_template.",
+    "split.",
+    "quantize.",
+    "adapter.",
+    "imatrix.",
+];
+
+/// Whether a key is registered or sits in a namespace that is not fully registered
+fn is_known(key: &str, architecture: Option<&str>) -> bool {
+    key_schema(key, architecture).is_some()
+        || OPEN_NAMESPACES
+            .iter()
+            .any(|ns| match ns.strip_prefix("{arch}") {
+                Some(rest) => architecture
+                    .and_then(|arch| key.strip_prefix(arch))
+                    .is_some_and(|k| k.starts_with(rest)),
+                None => key.starts_with(ns),
+            })
+}
+
+/// edit distance between two keys
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (prev + usize::from(ca != *cb))
+                .min(row[j] + 1)
+                .min(row[j + 1] + 1);
+            prev = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+/// The registered key closest to a misspelled one, if any is close enough to be a typo
+fn suggestion(key: &str, architecture: Option<&str>) -> Option<String> {
+    SCHEMA
+        .iter()
+        .filter_map(|s| match s.key.strip_prefix("{arch}") {
+            Some(rest) => architecture.map(|arch| format!("{arch}{rest}")),
+            None => Some(s.key.to_string()),
+        })
+        .map(|candidate| (distance(key, &candidate), candidate))
+        .filter(|(d, _)| *d <= 3)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The schema of a key, `architecture` resolving the `{arch}.` keys
+pub fn key_schema(key: &str, architecture: Option<&str>) -> Option<&'static KeySchema> {
+    SCHEMA.iter().find(|s| match s.key.strip_prefix("{arch}.") {
+        Some(rest) => {
+            architecture
+                .and_then(|arch| key.strip_prefix(arch))
+                .and_then(|k| k.strip_prefix('.'))
+                == Some(rest)
+        }
+        None => s.key == key,
+    })
+}
+
+/// Check parsed metadata against the key registry: the types of the standardized keys, and
+/// keys that are neither registered nor in a namespace left open to converters
+pub fn check_schema(header: &GGUFHeader) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let architecture = header.get("general.architecture").and_then(|v| v.as_str());
+    for entry in &header.metadata {
+        let key = &entry.key;
+        if !is_known(key, architecture) {
+            let hint = suggestion(key, architecture)
+                .map(|s| format!(", did you mean {s}?"))
+                .unwrap_or_default();
+            report
+                .note(
+                    Severity::Warning,
+                    "unknown-key",
+                    format!("{key} is not a registered key{hint}"),
+                )
+                .with_key(key);
+        }
+        let Some(schema) = key_schema(key, architecture) else {
+            continue;
+        };
+        if schema.drift.contains(&entry.value_type) {
+            report
+                .note(
+                    Severity::Warning,
+                    "type-drift",
+                    format!(
+                        "{key} is {:?}, the standard type is {:?}",
+                        entry.value_type, schema.types[0]
+                    ),
+                )
+                .with_key(key);
+        } else if !schema.types.contains(&entry.value_type) {
+            report
+                .note(
+                    Severity::Error,
+                    "key-type",
+                    format!(
+                        "{key} is {:?}, expected one of {:?}",
+                        entry.value_type, schema.types
+                    ),
+                )
+                .with_key(key);
+        }
+        if let GGUFMetadataValue::Array(array) = &entry.value {
+            if schema.types.contains(&Array) && !schema.items.contains(&array.value_type) {
+                report
+                    .note(
+                        Severity::Error,
+                        "item-type",
+                        format!(
+                            "{key} holds {:?} items, expected one of {:?}",
+                            array.value_type, schema.items
+                        ),
+                    )
+                    .with_key(key);
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::header;
+    use crate::GGUFMetadataArrayValue;
+
+    #[test]
+    fn schema_flags_type_drift() {
+        let header = header(&[
+            (
+                "general.architecture",
+                GGUFMetadataValue::String("llama".into()),
+            ),
+            ("llama.context_length", GGUFMetadataValue::Uint64(4096)),
+            ("llama.rope.freq_base", GGUFMetadataValue::Float64(1e4)),
+            (
+                "llama.attention.head_count",
+                GGUFMetadataValue::Array(GGUFMetadataArrayValue::new(
+                    GGUfMetadataValueType::Float32,
+                    vec![GGUFMetadataValue::Float32(32.0)],
+                )),
+            ),
+            (
+                "llama.rope.scaling.yarn_log_multiplier",
+                GGUFMetadataValue::Float32(0.1),
+            ),
+            ("qwen2.rope.freq_base", GGUFMetadataValue::Float64(1e4)),
+            ("tokenizer.ggml.token", GGUFMetadataValue::Bool(true)),
+        ]);
+        let findings: Vec<_> = check_schema(&header)
+            .findings
+            .into_iter()
+            .map(|f| (f.code, f.message))
+            .collect();
+        let codes: Vec<_> = findings.iter().map(|(c, _)| *c).collect();
+        assert_eq!(
+            codes,
+            [
+                "type-drift",
+                "key-type",
+                "item-type",
+                "unknown-key",
+                "unknown-key"
+            ]
+        );
+        assert_eq!(
+            findings[4].1,
+            "tokenizer.ggml.token is not a registered key, did you mean tokenizer.ggml.tokens?"
+        );
+    }
+}
@@ -0,0 +1,356 @@
+//! # Visiting a header as it is read
+//!
+//! [`visit`] reads the header and tensor infos in one pass over any reader and hands each part
+//! to a [`GGUFVisitor`] the moment it is read, keeping nothing itself: an indexer that wants
+//! the keys and tensor names, or a converter writing the metadata out in another format, builds
+//! only the structures it needs rather than a [`GGUFFile`](crate::GGUFFile) first. An array
+//! comes as its start, each element and its end, and [`GGUFVisitor::on_array_start`] can skip
+//! the elements of one, such as the vocabulary, without them being decoded.
+//!
+//! The options are honoured as [`GGUFFile::read_from`](crate::GGUFFile::read_from) honours
+//! them, but for the duplicate key handling: the visitor sees every entry as it is stored.
+use std::io::{self, BufReader, Read};
+
+use crate::keys::general::ALIGNMENT;
+use crate::parser::decode;
+use crate::{
+    Dimensions, GGMLType, GGUFMetadataValue, GGUFTensorInfo, GGUfMetadataValueType, ParseError,
+    ParseOptions,
+};
+
+/// What a consumer of [`visit`] is told of a header, in the order of the file
+///
+/// Every method does nothing unless implemented.
+#[allow(unused_variables)]
+pub trait GGUFVisitor {
+    /// The version and counts at the start of the file
+    fn on_header(&mut self, version: u32, tensor_count: u64, metadata_count: u64) {}
+
+    /// The key and type of a metadata entry, before its value
+    fn on_metadata_key(&mut self, key: &str, value_type: GGUfMetadataValueType) {}
+
+    /// The value of an entry that is not an array
+    fn on_metadata_value(&mut self, value: &GGUFMetadataValue) {}
+
+    /// The start of an array of `len` elements of `elem_type`, an entry's value or an element
+    /// of another array; `false` skips its elements, which are then not handed over and, for
+    /// strings, not even decoded
+    fn on_array_start(&mut self, elem_type: GGUfMetadataValueType, len: u64) -> bool {
+        true
+    }
+
+    /// An element of the array started last, other than an array, whose start and elements
+    /// come instead
+    fn on_array_element(&mut self, index: u64, value: &GGUFMetadataValue) {}
+
+    /// The end of the array started last, skipped or not
+    fn on_array_end(&mut self) {}
+
+    /// A tensor info
+    fn on_tensor_info(&mut self, tensor: &GGUFTensorInfo) {}
+}
+
+/// Read the header and tensor infos at the start of `reader`, telling `visitor` of each part,
+/// and give the offset of the tensor data
+pub fn visit(
+    reader: impl Read,
+    options: &ParseOptions,
+    visitor: &mut impl GGUFVisitor,
+) -> Result<u64, ParseError> {
+    let mut walk = Walk {
+        input: BufReader::with_capacity(1 << 16, reader),
+        pos: 0,
+        options,
+    };
+    if walk.bytes::<4>()? != *b"GGUF" {
+        return Err(ParseError::Invalid("not a GGUF file".to_string()));
+    }
+    let version = walk.u32()?;
+    if !options.accepts_version(version) {
+        return Err(ParseError::UnsupportedVersion { version });
+    }
+    let tensor_count = walk.u64()?;
+    let metadata_count = walk.u64()?;
+    visitor.on_header(version, tensor_count, metadata_count);
+
+    let mut alignment = 32;
+    for _ in 0..metadata_count {
+        let key = walk.string()?;
+        let at = walk.pos;
+        let Ok(value_type) = GGUfMetadataValueType::try_from(walk.u32()?) else {
+            if options.truncate_at_unknown_type {
+                // nothing after the value can be found, the tensor infos least of all
+                return Ok(at.next_multiple_of(alignment));
+            }
+            return Err(walk.invalid(at, "unknown metadata value type"));
+        };
+        visitor.on_metadata_key(&key, value_type);
+        if value_type == GGUfMetadataValueType::Array {
+            walk.array(visitor, 0)?;
+            continue;
+        }
+        let value = walk.value(value_type)?;
+        if key == ALIGNMENT {
+            alignment = value.as_u64().filter(|a| a.is_power_of_two()).unwrap_or(32);
+        }
+        visitor.on_metadata_value(&value);
+    }
+
+    for _ in 0..tensor_count {
+        let name = walk.string()?;
+        let mut dimensions = Dimensions::new();
+        for _ in 0..walk.u32()? {
+            dimensions.push(walk.u64()?);
+        }
+        let at = walk.pos;
+        let tensor_type =
+            GGMLType::try_from(walk.u32()?).map_err(|_| walk.invalid(at, "unknown tensor type"))?;
+        let tensor = GGUFTensorInfo {
+            name,
+            dimensions,
+            tensor_type,
+            offset: walk.u64()?,
+        };
+        visitor.on_tensor_info(&tensor);
+    }
+    Ok(walk.pos.next_multiple_of(alignment))
+}
+
+/// A reader of little-endian fields, counting the bytes read
+struct Walk<'a, R> {
+    input: BufReader<R>,
+    pos: u64,
+    options: &'a ParseOptions,
+}
+
+impl<R: Read> Walk<'_, R> {
+    /// Count `n` more bytes read, failing once the header runs past its limit
+    fn advance(&mut self, n: u64) -> Result<(), ParseError> {
+        self.pos += n;
+        match self.options.max_header_bytes {
+            Some(limit) if self.pos > limit => Err(ParseError::HeaderTooLarge { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    fn invalid(&self, at: u64, what: &str) -> ParseError {
+        ParseError::Invalid(format!("{what} at byte {at}"))
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], ParseError> {
+        let mut bytes = [0; N];
+        self.input.read_exact(&mut bytes).map_err(ended)?;
+        self.advance(N as u64)?;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, ParseError> {
+        self.bytes().map(u64::from_le_bytes)
+    }
+
+    /// The bytes of a string, grown as they are read so a bogus length allocates no more than
+    /// the input holds
+    fn string_bytes(&mut self) -> Result<Vec<u8>, ParseError> {
+        let len = self.u64()?;
+        if let Some(limit) = self.options.max_header_bytes.filter(|&l| len > l) {
+            return Err(ParseError::HeaderTooLarge { limit });
+        }
+        let mut data = Vec::new();
+        let read = (&mut self.input)
+            .take(len)
+            .read_to_end(&mut data)
+            .map_err(ended)?;
+        if (read as u64) < len {
+            return Err(ended(io::ErrorKind::UnexpectedEof.into()));
+        }
+        self.advance(len)?;
+        Ok(data)
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        let at = self.pos;
+        let data = self.string_bytes()?;
+        match decode(&data, self.options) {
+            Some(s) => Ok(s.into_owned()),
+            None => Err(self.invalid(at, "invalid UTF-8")),
+        }
+    }
+
+    /// A value that is not an array
+    fn value(
+        &mut self,
+        value_type: GGUfMetadataValueType,
+    ) -> Result<GGUFMetadataValue, ParseError> {
+        use GGUFMetadataValue as V;
+        use GGUfMetadataValueType as T;
+        Ok(match value_type {
+            T::Uint8 => V::Uint8(u8::from_le_bytes(self.bytes()?)),
+            T::Int8 => V::Int8(i8::from_le_bytes(self.bytes()?)),
+            T::Uint16 => V::Uint16(u16::from_le_bytes(self.bytes()?)),
+            T::Int16 => V::Int16(i16::from_le_bytes(self.bytes()?)),
+            T::Uint32 => V::Uint32(self.u32()?),
+            T::Int32 => V::Int32(i32::from_le_bytes(self.bytes()?)),
+            T::Float32 => V::Float32(f32::from_le_bytes(self.bytes()?)),
+            T::Uint64 => V::Uint64(self.u64()?),
+            T::Int64 => V::Int64(i64::from_le_bytes(self.bytes()?)),
+            T::Float64 => V::Float64(f64::from_le_bytes(self.bytes()?)),
+            T::Bool => {
+                let at = self.pos;
+                match self.bytes::<1>()? {
+                    [0] => V::Bool(false),
+                    [1] => V::Bool(true),
+                    _ if self.options.lenient_bools => V::Bool(true),
+                    _ => return Err(self.invalid(at, "invalid bool value")),
+                }
+            }
+            T::String => V::String(self.string()?),
+            T::Array => unreachable!("arrays are walked by `array`"),
+        })
+    }
+
+    /// An array, `depth` counting the arrays it is nested in
+    fn array(&mut self, visitor: &mut impl GGUFVisitor, depth: usize) -> Result<(), ParseError> {
+        if let Some(limit) = self.options.max_array_depth.filter(|&max| depth >= max) {
+            return Err(ParseError::ArrayTooDeep { limit });
+        }
+        let at = self.pos;
+        let elem_type = GGUfMetadataValueType::try_from(self.u32()?)
+            .map_err(|_| self.invalid(at, "unknown array element type"))?;
+        let len = self.u64()?;
+        if let Some(limit) = self.options.max_array_len.filter(|&max| len > max) {
+            return Err(ParseError::ArrayTooLarge { limit });
+        }
+        let wanted = visitor.on_array_start(elem_type, len);
+        for index in 0..len {
+            match elem_type {
+                GGUfMetadataValueType::Array if wanted => self.array(visitor, depth + 1)?,
+                GGUfMetadataValueType::Array => self.array(&mut Skip, depth + 1)?,
+                GGUfMetadataValueType::String if !wanted => drop(self.string_bytes()?),
+                _ => {
+                    let value = self.value(elem_type)?;
+                    if wanted {
+                        visitor.on_array_element(index, &value);
+                    }
+                }
+            }
+        }
+        visitor.on_array_end();
+        Ok(())
+    }
+}
+
+/// The visitor of a skipped array's nested arrays
+struct Skip;
+
+impl GGUFVisitor for Skip {
+    fn on_array_start(&mut self, _: GGUfMetadataValueType, _: u64) -> bool {
+        false
+    }
+}
+
+/// The error of a read that failed, most often because the input ended
+fn ended(e: io::Error) -> ParseError {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => {
+            ParseError::Invalid("the file ends inside the header".to_string())
+        }
+        _ => ParseError::Io(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, tensor};
+    use crate::writer::header_bytes;
+    use crate::{ArrayValues, GGUFMetadataArrayValue};
+
+    /// Keys, array lengths and tensor names, skipping the tokens
+    #[derive(Default)]
+    struct Index {
+        keys: Vec<String>,
+        elements: Vec<(u64, GGUFMetadataValue)>,
+        arrays: Vec<u64>,
+        tensors: Vec<String>,
+        skip_next: bool,
+    }
+
+    impl GGUFVisitor for Index {
+        fn on_metadata_key(&mut self, key: &str, _: GGUfMetadataValueType) {
+            self.keys.push(key.to_string());
+            self.skip_next = key == "tokenizer.ggml.tokens";
+        }
+
+        fn on_array_start(&mut self, _: GGUfMetadataValueType, len: u64) -> bool {
+            self.arrays.push(len);
+            !std::mem::take(&mut self.skip_next)
+        }
+
+        fn on_array_element(&mut self, index: u64, value: &GGUFMetadataValue) {
+            self.elements.push((index, value.clone()));
+        }
+
+        fn on_tensor_info(&mut self, tensor: &GGUFTensorInfo) {
+            self.tensors.push(tensor.name.clone());
+        }
+    }
+
+    #[test]
+    fn visits_each_part_once() {
+        let array = |value_type, values| {
+            GGUFMetadataValue::Array(GGUFMetadataArrayValue::packed(value_type, values))
+        };
+        let tokens: Vec<String> = (0..1000).map(|i| format!("tok{i}")).collect();
+        let file = file(
+            header(&[
+                ("general.alignment", GGUFMetadataValue::Uint32(64)),
+                (
+                    "tokenizer.ggml.tokens",
+                    array(GGUfMetadataValueType::String, tokens.into()),
+                ),
+                (
+                    "stop",
+                    array(GGUfMetadataValueType::Int32, ArrayValues::Int32(vec![7, 8])),
+                ),
+            ]),
+            vec![tensor("output.weight", &[4, 4], GGMLType::F32, 0)],
+        );
+        let bytes = header_bytes(&file);
+        let mut index = Index::default();
+        let data_start = visit(&bytes[..], &ParseOptions::default(), &mut index).unwrap();
+        assert_eq!(data_start, (bytes.len() as u64).next_multiple_of(64));
+        assert_eq!(
+            index.keys,
+            ["general.alignment", "tokenizer.ggml.tokens", "stop"]
+        );
+        assert_eq!(index.arrays, [1000, 2]);
+        assert_eq!(
+            index.elements,
+            [
+                (0, GGUFMetadataValue::Int32(7)),
+                (1, GGUFMetadataValue::Int32(8))
+            ]
+        );
+        assert_eq!(index.tensors, ["output.weight"]);
+
+        let error = visit(
+            &bytes[..bytes.len() - 1],
+            &ParseOptions::default(),
+            &mut Skip,
+        );
+        assert_eq!(
+            error.unwrap_err(),
+            ParseError::Invalid("the file ends inside the header".to_string())
+        );
+        let limited = ParseOptions {
+            max_array_len: Some(100),
+            ..ParseOptions::default()
+        };
+        let error = visit(&bytes[..], &limited, &mut Skip).unwrap_err();
+        assert_eq!(error, ParseError::ArrayTooLarge { limit: 100 });
+    }
+}
@@ -0,0 +1,98 @@
+//! # WebAssembly exports
+//!
+//! Plain exports for `wasm32-unknown-unknown` builds, so a page can show the metadata of a
+//! GGUF file without uploading it, through nothing but `WebAssembly.instantiate`:
+//!
+//! ```sh
+//! cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+//! ```
+//!
+//! `wasm/gguf.js` wraps them to take a `Uint8Array` or read a `Blob` slice by slice. By hand,
+//! copy the start of the file into memory from [`gguf_wasm_alloc`], call [`gguf_wasm_inspect`]
+//! and read the JSON it returns:
+//!
+//! - `{"status": "ok", "file": {"header": .., "tensors": [..]}}` once the bytes hold the whole
+//!   header and tensor infos, in the form described by [`crate::schema`];
+//! - `{"status": "incomplete"}` when they end inside them, to retry with more of the file;
+//! - `{"status": "error", "message": ".."}` when they are not a GGUF header.
+use serde_json::{json, Value};
+
+use crate::{GGUFFile, ParseOptions};
+
+/// The result of inspecting the start of a file, as [`gguf_wasm_inspect`] returns it
+pub fn inspect(bytes: &[u8]) -> Value {
+    match GGUFFile::read_with(bytes, &ParseOptions::default()) {
+        Ok(Some(file)) => json!({"status": "ok", "file": file}),
+        Ok(None) => json!({"status": "incomplete"}),
+        Err(e) => json!({"status": "error", "message": e.to_string()}),
+    }
+}
+
+/// Allocate `len` bytes of memory for the caller to fill
+#[no_mangle]
+pub extern "C" fn gguf_wasm_alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// Free memory from [`gguf_wasm_alloc`] or [`gguf_wasm_inspect`]
+///
+/// # Safety
+///
+/// `ptr` must come from one of them with the same `len`, and be freed once.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_wasm_free(ptr: *mut u8, len: usize) {
+    // SAFETY: the memory is a Vec of capacity `len` handed out by this module
+    drop(unsafe { Vec::from_raw_parts(ptr, 0, len) });
+}
+
+/// Inspect the `len` bytes at `ptr`, returning a buffer holding the length of the JSON result
+/// as a little-endian u32 followed by the JSON, to free with its total length
+///
+/// # Safety
+///
+/// `ptr` must point at `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gguf_wasm_inspect(ptr: *const u8, len: usize) -> *mut u8 {
+    // SAFETY: the caller passes `len` readable bytes
+    let bytes = unsafe { std::slice::from_raw_parts(ptr, len) };
+    let json = inspect(bytes).to_string();
+    let mut out = Vec::with_capacity(4 + json.len());
+    out.extend((json.len() as u32).to_le_bytes());
+    out.extend(json.as_bytes());
+    // the capacity is exactly the length, which the caller frees with
+    let ptr = out.as_mut_ptr();
+    std::mem::forget(out);
+    ptr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{entry, header_start, string};
+
+    #[test]
+    fn inspect_prefixes() {
+        let mut buf = header_start(3, 0, 1);
+        let mut llama = Vec::new();
+        string(&mut llama, b"llama");
+        entry(&mut buf, "general.architecture", 8, &llama);
+
+        assert_eq!(inspect(&buf[..10])["status"], "incomplete");
+        assert_eq!(inspect(b"junk and more junk")["status"], "error");
+        unsafe {
+            let ptr = gguf_wasm_alloc(buf.len());
+            ptr.copy_from_nonoverlapping(buf.as_ptr(), buf.len());
+            let out = gguf_wasm_inspect(ptr, buf.len());
+            gguf_wasm_free(ptr, buf.len());
+            let len = u32::from_le_bytes(*out.cast::<[u8; 4]>()) as usize;
+            let json: Value =
+                serde_json::from_slice(std::slice::from_raw_parts(out.add(4), len)).unwrap();
+            gguf_wasm_free(out, 4 + len);
+            assert_eq!(json["status"], "ok");
+            assert_eq!(json["file"]["header"]["metadata"][0]["value"], "llama");
+        }
+    }
+}
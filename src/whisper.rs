@@ -0,0 +1,84 @@
+//! # Whisper
+//!
+//! Whisper is an encoder-decoder: the encoder reads a mel spectrogram of 30 seconds of audio,
+//! and the decoder writes its transcript as tokens, attending to the encoder's output. Its
+//! files keep the two stacks apart, the encoder's tensors under `enc.` and the decoder's under
+//! `dec.`, as T5's do. [`Whisper::of`] reads the `whisper.*` hyperparameters of a header.
+use crate::keys::whisper;
+use crate::GGUFHeader;
+
+/// The hyperparameters of a Whisper model
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct Whisper {
+    /// The frequency bins of the spectrogram, 80, or 128 from large-v3 on.
+    pub mel_bins: Option<u64>,
+    /// The positions of the encoder, 1500 for 30 seconds.
+    pub audio_context_length: Option<u64>,
+    /// The tokens the decoder holds, 448.
+    pub text_context_length: Option<u64>,
+    /// The width of both stacks.
+    pub embedding_length: Option<u64>,
+    pub encoder_block_count: Option<u64>,
+    pub decoder_block_count: Option<u64>,
+    pub head_count: Option<u64>,
+    pub vocab_size: Option<u64>,
+}
+
+impl Whisper {
+    /// The hyperparameters of `header`, `None` if it is not a Whisper file
+    pub fn of(header: &GGUFHeader) -> Option<Self> {
+        let arch = header.get(crate::keys::general::ARCHITECTURE)?.as_str()?;
+        if arch != "whisper" {
+            return None;
+        }
+        let int = |key: &str| header.get(key).and_then(|v| v.as_u64());
+        Some(Whisper {
+            mel_bins: int(whisper::AUDIO_MEL_BINS),
+            audio_context_length: int(whisper::AUDIO_CONTEXT_LENGTH),
+            text_context_length: int(whisper::CONTEXT_LENGTH),
+            embedding_length: int(whisper::EMBEDDING_LENGTH),
+            encoder_block_count: int(whisper::BLOCK_COUNT),
+            decoder_block_count: int(whisper::DECODER_BLOCK_COUNT),
+            head_count: int(whisper::ATTENTION_HEAD_COUNT),
+            vocab_size: int(whisper::VOCAB_SIZE),
+        })
+    }
+
+    /// The seconds of audio the encoder takes at once, a frame being 10 ms and the encoder's
+    /// convolutions halving the frames
+    pub fn window_seconds(&self) -> Option<f64> {
+        Some(self.audio_context_length? as f64 * 2.0 / 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::header;
+    use crate::GGUFMetadataValue;
+
+    #[test]
+    fn reads_the_hyperparameters() {
+        use GGUFMetadataValue::{String as Str, Uint32};
+        let mut header = header(&[
+            ("general.architecture", Str("whisper".into())),
+            ("whisper.audio.mel_bins", Uint32(128)),
+            ("whisper.audio.context_length", Uint32(1500)),
+            ("whisper.context_length", Uint32(448)),
+            ("whisper.block_count", Uint32(32)),
+            ("whisper.decoder_block_count", Uint32(4)),
+        ]);
+        let whisper = Whisper::of(&header).unwrap();
+        assert_eq!(whisper.mel_bins, Some(128));
+        assert_eq!(whisper.text_context_length, Some(448));
+        assert_eq!(
+            (whisper.encoder_block_count, whisper.decoder_block_count),
+            (Some(32), Some(4))
+        );
+        assert_eq!(whisper.window_seconds(), Some(30.0));
+        assert_eq!(whisper.head_count, None);
+
+        header.set("general.architecture", Str("llama".into()));
+        assert_eq!(Whisper::of(&header), None);
+    }
+}
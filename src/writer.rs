@@ -0,0 +1,466 @@
+//! # Writing GGUF files
+//!
+//! Files are written in the version 3 layout: the header, the tensor infos, zero padding up to
+//! the alignment, then the data of each tensor in tensor info order, each starting on an
+//! alignment boundary and followed by zero padding.
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+#[cfg(feature = "std")]
+use crate::manifest::read_header;
+#[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+use crate::mmap::MmapMut;
+#[cfg(not(feature = "std"))]
+use crate::prelude::*;
+use crate::{ArrayValues, GGUFFile, GGUFMetadataValue};
+#[cfg(feature = "std")]
+use crate::{GGUFTensorInfo, ParseError};
+
+/// Why a file could not be written
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum WriteError {
+    /// Reading the source or writing the output failed.
+    Io(io::Error),
+    /// The source does not parse.
+    Parse(ParseError),
+    /// The file cannot be written as asked, such as with tensor data of the wrong length.
+    Invalid(String),
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WriteError::Io(e) => e.fmt(f),
+            WriteError::Parse(e) => e.fmt(f),
+            WriteError::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for WriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WriteError::Io(e) => Some(e),
+            WriteError::Parse(e) => Some(e),
+            WriteError::Invalid(_) => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for WriteError {
+    fn from(e: io::Error) -> Self {
+        WriteError::Io(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<ParseError> for WriteError {
+    fn from(e: ParseError) -> Self {
+        WriteError::Parse(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<String> for WriteError {
+    fn from(message: String) -> Self {
+        WriteError::Invalid(message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&str> for WriteError {
+    fn from(message: &str) -> Self {
+        WriteError::Invalid(message.to_string())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<WriteError> for String {
+    fn from(e: WriteError) -> String {
+        e.to_string()
+    }
+}
+
+fn encode_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend((s.len() as u64).to_le_bytes());
+    buf.extend(s.as_bytes());
+}
+
+/// Append a value without its type tag to `buf`
+pub fn encode_value(buf: &mut Vec<u8>, value: &GGUFMetadataValue) {
+    match value {
+        GGUFMetadataValue::Uint8(v) => buf.extend(v.to_le_bytes()),
+        GGUFMetadataValue::Int8(v) => buf.extend(v.to_le_bytes()),
+        GGUFMetadataValue::Uint16(v) => buf.extend(v.to_le_bytes()),
+        GGUFMetadataValue::Int16(v) => buf.extend(v.to_le_bytes()),
+        GGUFMetadataValue::Uint32(v) => buf.extend(v.to_le_bytes()),
+        GGUFMetadataValue::Int32(v) => buf.extend(v.to_le_bytes()),
+        GGUFMetadataValue::Float32(v) => buf.extend(v.to_le_bytes()),
+        GGUFMetadataValue::Uint64(v) => buf.extend(v.to_le_bytes()),
+        GGUFMetadataValue::Int64(v) => buf.extend(v.to_le_bytes()),
+        GGUFMetadataValue::Float64(v) => buf.extend(v.to_le_bytes()),
+        GGUFMetadataValue::Bool(v) => buf.push(u8::from(*v)),
+        GGUFMetadataValue::String(v) => encode_string(buf, v),
+        GGUFMetadataValue::Array(array) => {
+            buf.extend((array.value_type as u32).to_le_bytes());
+            buf.extend((array.value.len() as u64).to_le_bytes());
+            match &array.value {
+                ArrayValues::String(strings) => strings.iter().for_each(|s| encode_string(buf, s)),
+                ArrayValues::Raw(raw) => buf.extend(raw.as_bytes()),
+                values => values.iter().for_each(|v| encode_value(buf, &v)),
+            }
+        }
+    }
+}
+
+/// Write a value without its type tag
+#[cfg(feature = "std")]
+pub fn write_value(out: &mut impl Write, value: &GGUFMetadataValue) -> io::Result<()> {
+    let mut buf = Vec::new();
+    encode_value(&mut buf, value);
+    out.write_all(&buf)
+}
+
+/// The header and tensor infos
+///
+/// The tensor and metadata counts are taken from the vectors rather than the header, and
+/// files older than version 2 are written as version 3.
+pub fn header_bytes(file: &GGUFFile) -> Vec<u8> {
+    let mut buf = b"GGUF".to_vec();
+    let version = match file.header.version {
+        2 | 3 => file.header.version,
+        _ => 3,
+    };
+    buf.extend(version.to_le_bytes());
+    buf.extend((file.tensors.len() as u64).to_le_bytes());
+    buf.extend((file.header.metadata.len() as u64).to_le_bytes());
+    for entry in &file.header.metadata {
+        encode_string(&mut buf, &entry.key);
+        buf.extend((entry.value_type as u32).to_le_bytes());
+        encode_value(&mut buf, &entry.value);
+    }
+    for tensor in &file.tensors {
+        encode_string(&mut buf, &tensor.name);
+        buf.extend((tensor.dimensions.len() as u32).to_le_bytes());
+        for dim in &tensor.dimensions {
+            buf.extend(dim.to_le_bytes());
+        }
+        buf.extend((tensor.tensor_type as u32).to_le_bytes());
+        buf.extend(tensor.offset.to_le_bytes());
+    }
+    buf
+}
+
+/// Write the header and tensor infos, returning the number of bytes written; see
+/// [`header_bytes`]
+#[cfg(feature = "std")]
+pub fn write_header(out: &mut impl Write, file: &GGUFFile) -> io::Result<u64> {
+    let buf = header_bytes(file);
+    out.write_all(&buf)?;
+    Ok(buf.len() as u64)
+}
+
+/// Give each tensor the first aligned offset after the one before it, in tensor info order,
+/// returning the length of the data section
+pub fn assign_offsets(file: &mut GGUFFile) -> Result<u64, String> {
+    let alignment = file.alignment();
+    let mut end = 0u64;
+    for tensor in &mut file.tensors {
+        let size = tensor
+            .size_bytes()
+            .ok_or_else(|| format!("tensor {} has no whole number of blocks", tensor.name))?;
+        tensor.offset = end.next_multiple_of(alignment);
+        end = tensor.offset + size;
+    }
+    Ok(end.next_multiple_of(alignment))
+}
+
+#[cfg(feature = "std")]
+fn pad(out: &mut impl Write, written: u64, alignment: u64) -> io::Result<u64> {
+    let padding = written.next_multiple_of(alignment) - written;
+    io::copy(&mut io::repeat(0).take(padding), out)?;
+    Ok(written + padding)
+}
+
+/// Write `file` to `dst` with freshly assigned offsets, `data` writing the data of each tensor
+/// in turn
+///
+/// The output goes to a temporary file next to `dst` that replaces it once complete, so `dst`
+/// may be one of the inputs.
+#[cfg(feature = "std")]
+pub fn write_file(
+    dst: impl AsRef<Path>,
+    file: &GGUFFile,
+    mut data: impl FnMut(&GGUFTensorInfo, &mut dyn Write) -> Result<(), WriteError>,
+) -> Result<(), WriteError> {
+    let dst = dst.as_ref();
+    let mut file = file.clone();
+    assign_offsets(&mut file)?;
+    let alignment = file.alignment();
+
+    let mut partial = dst.as_os_str().to_owned();
+    partial.push(".partial");
+    let mut write = || -> Result<(), WriteError> {
+        let mut out = BufWriter::new(File::create(&partial)?);
+        let header_len = write_header(&mut out, &file)?;
+        let data_start = pad(&mut out, header_len, alignment)?;
+        for tensor in &file.tensors {
+            let size = tensor.size_bytes().unwrap_or_default();
+            data(tensor, &mut out)?;
+            let end = out.stream_position()?;
+            if end != data_start + tensor.offset + size {
+                return Err(WriteError::Invalid(format!(
+                    "wrote {} bytes for tensor {}, expected {size}",
+                    end - data_start - tensor.offset,
+                    tensor.name
+                )));
+            }
+            pad(&mut out, end, alignment)?;
+        }
+        Ok(out.flush()?)
+    };
+    match write() {
+        Ok(()) => Ok(std::fs::rename(&partial, dst)?),
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+/// How many bytes of tensor data [`write_file_mapped`] writes before handing them to the disk,
+/// so that the pages waiting to be written do not pile up in memory
+#[cfg(all(feature = "std", unix, target_pointer_width = "64"))]
+const FLUSH_EVERY: usize = 256 << 20;
+
+/// [`write_file`] through a writable mapping of the output, which is given its full length
+/// before any tensor is written
+///
+/// `data` writes into the pages of the file itself rather than through a buffer and a `write`
+/// for each piece of it, and the pages are handed to the disk every 256 MiB, which is much
+/// faster for outputs of many gigabytes. On Linux the blocks of the file are allocated first,
+/// so a full disk is an error here rather than a crash halfway through. Writing more than the
+/// size of a tensor fails. Off 64-bit unix this is [`write_file`].
+#[cfg(feature = "std")]
+pub fn write_file_mapped(
+    dst: impl AsRef<Path>,
+    file: &GGUFFile,
+    mut data: impl FnMut(&GGUFTensorInfo, &mut dyn Write) -> Result<(), WriteError>,
+) -> Result<(), WriteError> {
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    return write_file(dst, file, data);
+
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    {
+        let dst = dst.as_ref();
+        let mut file = file.clone();
+        let data_len = assign_offsets(&mut file)?;
+        let header = header_bytes(&file);
+        let data_start = (header.len() as u64).next_multiple_of(file.alignment());
+
+        let mut partial = dst.as_os_str().to_owned();
+        partial.push(".partial");
+        let mut write = || -> Result<(), WriteError> {
+            let out = File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&partial)?;
+            let mut map = MmapMut::create(&out, data_start + data_len)?;
+            map.as_mut_slice()[..header.len()].copy_from_slice(&header);
+            let mut flushed = 0;
+            for tensor in &file.tensors {
+                let size = tensor.size_bytes().unwrap_or_default() as usize;
+                let start = (data_start + tensor.offset) as usize;
+                let mut rest = &mut map.as_mut_slice()[start..start + size];
+                data(tensor, &mut rest)?;
+                if !rest.is_empty() {
+                    return Err(WriteError::Invalid(format!(
+                        "wrote {} bytes for tensor {}, expected {size}",
+                        size - rest.len(),
+                        tensor.name
+                    )));
+                }
+                if start + size - flushed >= FLUSH_EVERY {
+                    map.flush_async(flushed..start + size);
+                    flushed = start + size;
+                }
+            }
+            Ok(())
+        };
+        match write() {
+            Ok(()) => Ok(std::fs::rename(&partial, dst)?),
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Write `file` to `dst` with freshly assigned offsets, copying the data of each tensor from
+/// the tensor of the same name in `src`
+///
+/// As with [`write_file`], `dst` may be `src`.
+#[cfg(feature = "std")]
+pub fn rewrite(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    file: &GGUFFile,
+) -> Result<(), WriteError> {
+    let mut input = File::open(src)?;
+    let (source, _, source_start) = read_header(&mut input)?;
+    write_file_mapped(dst, file, |tensor, out| {
+        let from = source
+            .tensors
+            .iter()
+            .find(|t| t.name == tensor.name)
+            .ok_or_else(|| {
+                WriteError::Invalid(format!("the source has no tensor {}", tensor.name))
+            })?;
+        copy_data(&mut input, source_start + from.offset, tensor, out)
+    })
+}
+
+/// copy the data of `tensor` from `start` in `input`
+#[cfg(feature = "std")]
+pub(crate) fn copy_data(
+    input: &mut File,
+    start: u64,
+    tensor: &GGUFTensorInfo,
+    out: &mut dyn Write,
+) -> Result<(), WriteError> {
+    let size = tensor.size_bytes().unwrap_or_default();
+    input.seek(SeekFrom::Start(start))?;
+    let copied = io::copy(&mut input.take(size), out)?;
+    if copied < size {
+        return Err(WriteError::Invalid(format!(
+            "the source ends {} bytes before the data of tensor {} does",
+            size - copied,
+            tensor.name
+        )));
+    }
+    Ok(())
+}
+
+/// Replace the header of a file in place, leaving the tensor data where it is
+///
+/// This only works when the tensor infos are unchanged and the new header pads to the same
+/// length as the old one. Otherwise the file is left untouched and `false` returned; use
+/// [`rewrite`] instead.
+#[cfg(feature = "std")]
+pub fn write_header_in_place(path: impl AsRef<Path>, file: &GGUFFile) -> Result<bool, WriteError> {
+    let mut out = File::options().read(true).write(true).open(path)?;
+    let (old, _, data_start) = read_header(&mut out)?;
+    let mut header = Vec::new();
+    write_header(&mut header, file)?;
+    let fits = (header.len() as u64).next_multiple_of(file.alignment()) == data_start;
+    if old.tensors != file.tensors || old.alignment() != file.alignment() || !fits {
+        return Ok(false);
+    }
+    header.resize(data_start as usize, 0);
+    out.seek(SeekFrom::Start(0))?;
+    out.write_all(&header)?;
+    Ok(true)
+}
+
+/// Rewrite a file that parses but breaks the layout rules, with misaligned or unordered tensor
+/// data or non-zero padding, into one that follows them, keeping all metadata and tensor data
+#[cfg(feature = "std")]
+pub fn repair(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<(), WriteError> {
+    let (file, _, _) = read_header(&mut File::open(&src)?)?;
+    rewrite(src, dst, &file)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::test_util::{file, header, header_start, temp_path, tensor_info};
+
+    #[test]
+    fn repair_realigns_tensor_data() {
+        let mut buf = header_start(3, 2, 0);
+        for (name, offset) in [("a", 40u64), ("b", 0)] {
+            tensor_info(&mut buf, name, &[4], 0, offset);
+        }
+        buf.resize(buf.len().next_multiple_of(32), 0xff);
+        buf.extend([2; 16]);
+        buf.extend([0xff; 24]);
+        buf.extend([1; 16]);
+
+        let dir = temp_path("gguf-repair");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (broken, fixed) = (dir.join("broken.gguf"), dir.join("fixed.gguf"));
+        std::fs::write(&broken, &buf).unwrap();
+        let layout = |buf: &[u8]| {
+            crate::validate(buf)
+                .findings
+                .into_iter()
+                .map(|f| f.code)
+                .filter(|c| ["alignment", "overlap", "trailing-data"].contains(c))
+                .count()
+        };
+        assert_eq!(layout(&buf), 1);
+
+        repair(&broken, &fixed).unwrap();
+        let out = std::fs::read(&fixed).unwrap();
+        assert_eq!(layout(&out), 0);
+        let file = GGUFFile::read(&out).unwrap().unwrap();
+        let offsets: Vec<_> = file.tensors.iter().map(|t| t.offset).collect();
+        assert_eq!(offsets, [0, 32]);
+        let data = &out[out.len() - 64..];
+        assert_eq!(data, [[1; 16], [0; 16], [2; 16], [0; 16]].concat());
+        assert!(out[..out.len() - 64].ends_with(&[0; 8]));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mapped_writes_match_buffered() {
+        use crate::{smallvec, GGMLType};
+        let tensors = (0..3)
+            .map(|i| GGUFTensorInfo {
+                name: format!("t{i}"),
+                dimensions: smallvec![3 + i],
+                tensor_type: GGMLType::F32,
+                offset: 0,
+            })
+            .collect();
+        let file = file(header(&[]), tensors);
+        let dir = temp_path("gguf-mapped");
+        std::fs::create_dir_all(&dir).unwrap();
+        let fill = |tensor: &GGUFTensorInfo, out: &mut dyn Write| {
+            let size = tensor.size_bytes().unwrap() as usize;
+            Ok(out.write_all(&vec![tensor.name.as_bytes()[1]; size])?)
+        };
+        let (buffered, mapped) = (dir.join("buffered.gguf"), dir.join("mapped.gguf"));
+        write_file(&buffered, &file, fill).unwrap();
+        write_file_mapped(&mapped, &file, fill).unwrap();
+        assert_eq!(
+            std::fs::read(&buffered).unwrap(),
+            std::fs::read(&mapped).unwrap()
+        );
+
+        let short = write_file_mapped(dir.join("short.gguf"), &file, |_, out| {
+            Ok(out.write_all(&[0; 4])?)
+        });
+        assert_eq!(
+            short.unwrap_err().to_string(),
+            "wrote 4 bytes for tensor t0, expected 12"
+        );
+        let long = write_file_mapped(dir.join("long.gguf"), &file, |_, out| {
+            Ok(out.write_all(&[0; 64])?)
+        });
+        assert!(long.is_err());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,15 @@
+// swift-tools-version:5.7
+// Swift bindings over the C API; build libgguf.a for each target first, e.g.
+// `cargo rustc --lib --release --features capi --crate-type staticlib --target aarch64-apple-ios`,
+// and add its directory to the linker search path of the app.
+import PackageDescription
+
+let package = Package(
+    name: "GGUF",
+    products: [.library(name: "GGUF", targets: ["GGUF"])],
+    targets: [
+        .systemLibrary(name: "CGGUF", path: "Sources/CGGUF"),
+        .target(name: "GGUF", dependencies: ["CGGUF"]),
+        .testTarget(name: "GGUFTests", dependencies: ["GGUF"]),
+    ]
+)
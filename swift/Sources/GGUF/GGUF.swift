@@ -0,0 +1,154 @@
+// Read and validate GGUF files from Swift through the C API of the gguf crate, so apps can
+// check a downloaded model and show its metadata before loading it.
+import CGGUF
+
+/// A failure reported by the library
+public struct GGUFError: Error, CustomStringConvertible {
+    public let description: String
+
+    static func last() -> GGUFError {
+        GGUFError(description: gguf_last_error().map { String(cString: $0) } ?? "unknown error")
+    }
+}
+
+/// A metadata value; arrays are read item by item with `GGUFFile.item(_:at:)`
+public enum MetadataValue: Equatable {
+    case uint(UInt64)
+    case int(Int64)
+    case float(Double)
+    case bool(Bool)
+    case string(String)
+    /// An array of `count` items of GGUF value type `itemType`
+    case array(itemType: UInt32, count: UInt64)
+
+    init(_ value: GgufValue, nulTerminated: Bool = true) {
+        switch value.value_type {
+        case 0, 2, 4, 10: self = .uint(value.uint_value)
+        case 1, 3, 5, 11: self = .int(value.int_value)
+        case 6, 12: self = .float(value.float_value)
+        case 7: self = .bool(value.uint_value != 0)
+        case 8:
+            guard let chars = value.string_value else {
+                self = .string("")
+                return
+            }
+            if nulTerminated {
+                self = .string(String(cString: chars))
+            } else {
+                let bytes = UnsafeRawBufferPointer(start: chars, count: Int(value.len))
+                self = .string(String(decoding: bytes, as: UTF8.self))
+            }
+        default: self = .array(itemType: value.item_type, count: value.len)
+        }
+    }
+}
+
+/// A tensor info; `dimensions` are innermost first, as ggml orders them
+public struct TensorInfo: Equatable {
+    public let name: String
+    public let dimensions: [UInt64]
+    /// The ggml type, 0 for F32 to 39 for MXFP4
+    public let type: UInt32
+    /// The offset of the data from `GGUFFile.dataOffset`
+    public let offset: UInt64
+    public let size: UInt64
+}
+
+/// The header and tensor infos of an opened file
+public final class GGUFFile {
+    private let handle: OpaquePointer
+
+    public init(path: String) throws {
+        guard let handle = gguf_open(path) else { throw GGUFError.last() }
+        self.handle = handle
+    }
+
+    deinit {
+        gguf_free(handle)
+    }
+
+    /// The metadata keys, in file order
+    public var keys: [String] {
+        (0..<gguf_metadata_count(handle)).compactMap { index in
+            gguf_metadata_key(handle, index).map { String(cString: $0) }
+        }
+    }
+
+    /// The value at `key`, if there is one
+    public subscript(key: String) -> MetadataValue? {
+        var value = GgufValue()
+        return gguf_get_metadata(handle, key, &value) ? MetadataValue(value) : nil
+    }
+
+    /// Item `index` of the array at `key`, if there is one
+    public func item(_ key: String, at index: UInt64) -> MetadataValue? {
+        var value = GgufValue()
+        guard gguf_get_array_item(handle, key, index, &value) else { return nil }
+        return MetadataValue(value, nulTerminated: false)
+    }
+
+    /// The offset in the file where the tensor data starts
+    public var dataOffset: UInt64 {
+        gguf_data_offset(handle)
+    }
+
+    public var tensors: [TensorInfo] {
+        get throws {
+            try (0..<gguf_tensor_count(handle)).map { index in
+                var info = GgufTensorInfo()
+                guard gguf_tensor_info(handle, index, &info) else { throw GGUFError.last() }
+                let dims = withUnsafeBytes(of: info.dims) { Array($0.bindMemory(to: UInt64.self)) }
+                return TensorInfo(
+                    name: String(cString: info.name),
+                    dimensions: Array(dims.prefix(Int(info.n_dims))),
+                    type: info.tensor_type,
+                    offset: info.offset,
+                    size: info.size
+                )
+            }
+        }
+    }
+}
+
+public enum Severity: UInt32, Comparable {
+    case info, warning, error
+
+    public static func < (lhs: Severity, rhs: Severity) -> Bool {
+        lhs.rawValue < rhs.rawValue
+    }
+}
+
+/// A problem found in a file
+public struct Finding: Equatable {
+    public let severity: Severity
+    /// The stable identifier of the check, e.g. `duplicate-key`
+    public let code: String
+    public let message: String
+    public let offset: UInt64?
+    public let key: String?
+    public let tensor: String?
+}
+
+/// The findings of checking a file against the spec
+public struct ValidationReport {
+    public let isValid: Bool
+    public let findings: [Finding]
+
+    public init(path: String) throws {
+        guard let report = gguf_validate(path) else { throw GGUFError.last() }
+        defer { gguf_report_free(report) }
+        isValid = gguf_report_valid(report)
+        findings = (0..<gguf_report_count(report)).compactMap { index in
+            var finding = GgufFinding()
+            guard gguf_report_finding(report, index, &finding) else { return nil }
+            return Finding(
+                severity: Severity(rawValue: finding.severity) ?? .error,
+                code: String(cString: finding.code),
+                message: String(cString: finding.message),
+                offset: finding.offset < 0 ? nil : UInt64(finding.offset),
+                key: finding.key.map { String(cString: $0) },
+                tensor: finding.tensor.map { String(cString: $0) }
+            )
+        }
+    }
+}
@@ -0,0 +1,55 @@
+// Smoke test against a library built with `cargo rustc --lib --features capi --crate-type staticlib`:
+// `swift test -Xlinker -L../target/debug`
+import Foundation
+import XCTest
+
+@testable import GGUF
+
+final class GGUFTests: XCTestCase {
+    /// A string, a number and an array of strings, and one F32 tensor of 2x3
+    private func tinyFile() throws -> URL {
+        var data = Data("GGUF".utf8)
+        func u32(_ values: UInt32...) { values.forEach { withUnsafeBytes(of: $0.littleEndian) { data.append(contentsOf: $0) } } }
+        func u64(_ values: UInt64...) { values.forEach { withUnsafeBytes(of: $0.littleEndian) { data.append(contentsOf: $0) } } }
+        func string(_ s: String) {
+            u64(UInt64(s.utf8.count))
+            data.append(contentsOf: Array(s.utf8))
+        }
+        u32(3)
+        u64(1, 3)
+        string("general.architecture"); u32(8); string("llama")
+        string("llama.context_length"); u32(4, 4096)
+        string("tokenizer.ggml.tokens"); u32(9, 8); u64(3)
+        ["<s>", "</s>", "héllo"].forEach(string)
+        string("output.weight"); u32(2); u64(3, 2); u32(0); u64(0)
+        data.append(Data(count: (32 - data.count % 32) % 32))
+        [Float(0), 1, 2, 3, 4, 5].forEach { u32($0.bitPattern) }
+        let url = FileManager.default.temporaryDirectory.appendingPathComponent("tiny-\(UUID()).gguf")
+        try data.write(to: url)
+        addTeardownBlock { try? FileManager.default.removeItem(at: url) }
+        return url
+    }
+
+    func testReadsMetadataAndTensors() throws {
+        let url = try tinyFile()
+        let file = try GGUFFile(path: url.path)
+        XCTAssertEqual(file.keys, ["general.architecture", "llama.context_length", "tokenizer.ggml.tokens"])
+        XCTAssertEqual(file["general.architecture"], .string("llama"))
+        XCTAssertEqual(file["llama.context_length"], .uint(4096))
+        XCTAssertEqual(file["tokenizer.ggml.tokens"], .array(itemType: 8, count: 3))
+        XCTAssertEqual(file.item("tokenizer.ggml.tokens", at: 2), .string("héllo"))
+        XCTAssertNil(file.item("tokenizer.ggml.tokens", at: 3))
+        let tensors = try file.tensors
+        XCTAssertEqual(tensors, [TensorInfo(name: "output.weight", dimensions: [3, 2], type: 0, offset: 0, size: 24)])
+        let size = try FileManager.default.attributesOfItem(atPath: url.path)[.size] as! UInt64
+        XCTAssertEqual(file.dataOffset, size - 24)
+    }
+
+    func testValidates() throws {
+        let report = try ValidationReport(path: try tinyFile().path)
+        XCTAssertFalse(report.isValid)
+        XCTAssertTrue(report.findings.contains { $0.code == "missing-key" && $0.key == "llama.block_count" })
+        XCTAssertTrue(report.findings.contains { $0.code == "vocab-size" && $0.tensor == "output.weight" })
+        XCTAssertThrowsError(try GGUFFile(path: "missing.gguf"))
+    }
+}
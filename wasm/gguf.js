@@ -0,0 +1,56 @@
+// Inspect GGUF files in the browser with the wasm build of the gguf crate, without uploading them:
+//
+//   cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+//
+//   import { load } from "./gguf.js";
+//   const gguf = await load(fetch("gguf.wasm"));
+//   const { header, tensors } = await gguf.inspectBlob(input.files[0]);
+
+const decoder = new TextDecoder();
+
+/** Instantiate the module from a `Response`, a promise of one, or its bytes */
+export async function load(source) {
+  const response = await source;
+  const { instance } =
+    response instanceof Response
+      ? await WebAssembly.instantiateStreaming(response, {})
+      : await WebAssembly.instantiate(response, {});
+  return new GGUF(instance.exports);
+}
+
+export class GGUF {
+  constructor(exports) {
+    this.exports = exports;
+  }
+
+  /**
+   * Inspect the start of a file, returning `{status: "ok", file}`, `{status: "incomplete"}`
+   * when it ends inside the header or tensor infos, or `{status: "error", message}`
+   */
+  inspect(bytes) {
+    const { memory, gguf_wasm_alloc, gguf_wasm_free, gguf_wasm_inspect } = this.exports;
+    const ptr = gguf_wasm_alloc(bytes.length);
+    new Uint8Array(memory.buffer, ptr, bytes.length).set(bytes);
+    const out = gguf_wasm_inspect(ptr, bytes.length);
+    gguf_wasm_free(ptr, bytes.length);
+    // memory may have grown, so the views are taken after the call
+    const len = new DataView(memory.buffer).getUint32(out, true);
+    const json = decoder.decode(new Uint8Array(memory.buffer, out + 4, len));
+    gguf_wasm_free(out, 4 + len);
+    return JSON.parse(json);
+  }
+
+  /**
+   * The header and tensor infos of a `Blob` or `File`, reading slices that double from
+   * `initial` bytes until they hold them, so a large model is never read whole
+   */
+  async inspectBlob(blob, initial = 1 << 20) {
+    for (let size = initial; ; size *= 2) {
+      const slice = new Uint8Array(await blob.slice(0, size).arrayBuffer());
+      const result = this.inspect(slice);
+      if (result.status === "ok") return result.file;
+      if (result.status === "error") throw new Error(result.message);
+      if (size >= blob.size) throw new Error("the file ends inside its header");
+    }
+  }
+}